@@ -2,6 +2,8 @@ use std::sync::{Arc, Mutex};
 use mlua::{UserData, UserDataMethods, MetaMethod, Value, LuaSerdeExt};
 
 use crate::inference::provider::{InferenceMessage, ProviderClient};
+use crate::kernel::compaction;
+use crate::persistence::state::StateStore;
 use std::collections::HashMap;
 
 /// Inner state shareable between Rust and Lua
@@ -14,6 +16,8 @@ pub struct ContextState {
     pub token_count: u32,
     pub token_limit: u32,
     pub thinking_budget: u32,
+    /// Index of the first message protected by `kernel.min_retained_turns`
+    pub retained_start: usize,
 }
 
 /// UserData wrapper for Context validation and mutation
@@ -21,6 +25,8 @@ pub struct ContextState {
 pub struct ContextWrapper {
     pub state: Arc<Mutex<ContextState>>,
     pub clients: HashMap<String, ProviderClient>,
+    /// Store and session whose `tool_executions` feed `summarize`'s tool digest
+    pub tool_log: Option<(StateStore, String)>,
 }
 
 impl ContextWrapper {
@@ -34,6 +40,7 @@ impl ContextWrapper {
         thinking_budget: u32,
         clients: HashMap<String, ProviderClient>,
     ) -> Self {
+        let messages_len = messages.len();
         Self {
             state: Arc::new(Mutex::new(ContextState {
                 model,
@@ -43,11 +50,25 @@ impl ContextWrapper {
                 token_count,
                 token_limit,
                 thinking_budget,
+                retained_start: messages_len,
             })),
             clients,
+            tool_log: None,
         }
     }
 
+    /// Mark messages from `index` on as protected from trimming/compaction.
+    pub fn with_retained_start(self, index: usize) -> Self {
+        self.state.lock().unwrap().retained_start = index;
+        self
+    }
+
+    /// Let `summarize` add a digest of the session's logged tool calls.
+    pub fn with_tool_log(mut self, store: StateStore, session_id: String) -> Self {
+        self.tool_log = Some((store, session_id));
+        self
+    }
+
     /// Retrieve the inner state (cloning the data out)
     pub fn get_state(&self) -> ContextState {
         self.state.lock().unwrap().clone()
@@ -77,6 +98,13 @@ impl UserData for ContextWrapper {
             Ok(state.token_limit)
         });
 
+        // 1-based index of the first message that must be kept verbatim
+        // (kernel.min_retained_turns); #messages + 1 when nothing is protected
+        methods.add_method("get_retained_start", |_, this, ()| {
+            let state = this.state.lock().unwrap();
+            Ok(state.retained_start + 1)
+        });
+
         methods.add_method("get_system_prompt", |_, this, ()| {
             let state = this.state.lock().unwrap();
             Ok(state.system_prompt.clone())
@@ -210,6 +238,7 @@ impl UserData for ContextWrapper {
         methods.add_method("summarize", |lua, this: &ContextWrapper, args: Value| {
              let clients = this.clients.clone();
              let state_arc = this.state.clone();
+             let tool_log = this.tool_log.clone();
              
              let messages_opt: Option<Vec<InferenceMessage>> = if args.is_nil() {
                  None
@@ -233,14 +262,26 @@ impl UserData for ContextWrapper {
                              .ok_or_else(|| format!("Provider '{}' not initialized", provider_name))
                      };
 
-                     match get_client() {
+                     let summary = match get_client() {
                          Ok(client) => {
                              let system_prompt = "Summarize the following conversation concisely.";
                              client.completion(&model, system_prompt, &messages).await
                                  .map_err(|e| format!("Completion failed: {}", e))
                          },
                          Err(e) => Err(e),
-                     }
+                     }?;
+
+                     // Tool calls come from the execution log, not the model's retelling
+                     let digest = match tool_log {
+                         Some((store, session_id)) => compaction::tool_digest_for(&store, &session_id, &messages)
+                             .await
+                             .unwrap_or_else(|e| {
+                                 tracing::warn!(error = %e, "Failed to build tool digest for summary");
+                                 Vec::new()
+                             }),
+                         None => Vec::new(),
+                     };
+                     Ok::<_, String>(compaction::structured_summary(&summary, &digest))
                  })
              });
              
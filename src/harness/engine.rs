@@ -9,6 +9,8 @@ use mlua::{Function, Lua, LuaSerdeExt, MultiValue, Table, Value};
 use std::path::Path;
 
 use crate::harness::globals::{self, HarnessAppData};
+use crate::harness::orchestrator::{ActiveOrchestration, Orchestration};
+use crate::harness::tools::{HarnessToolDef, RegisteredTools, ToolHandlers};
 use crate::harness::verdict::{Verdict, compose_verdicts};
 
 /// The harness engine manages script loading and hook evaluation.
@@ -121,11 +123,19 @@ impl HarnessEngine {
 
         let known_hooks = [
             "on_tool_call",
+            "on_tool_result",
             "on_token_usage",
             "on_agent_start",
             "on_agent_end",
+            "on_session_end",
             "on_before_inference",
+            "on_before_request",
+            "should_continue",
             "on_task_submit",
+            "on_orchestrate",
+            "on_budget_warning",
+            "on_assistant_message",
+            "on_memory_capture",
         ];
 
         for hook in known_hooks {
@@ -154,6 +164,128 @@ impl HarnessEngine {
         Ok(compose_verdicts(&verdicts))
     }
 
+    /// Call `on_tool_call` across all loaded scripts. Each script sees the
+    /// arguments as rewritten by the scripts before it; the result is
+    /// `Modify` with the final arguments when any script rewrote them and
+    /// nothing rejected or escalated the call.
+    pub fn evaluate_tool_call(&self, name: &str, id: &str, args: &serde_json::Value) -> Result<Verdict> {
+        let payload = serde_json::json!({ "name": name, "id": id, "args": args });
+        self.evaluate_chained("on_tool_call", payload, "args", "a table", serde_json::Value::is_object)
+    }
+
+    /// Call `on_tool_result` across all loaded scripts, chaining rewritten
+    /// output like [`evaluate_tool_call`](Self::evaluate_tool_call) chains
+    /// arguments. The result is `Modify` with the final output (a string)
+    /// when any script changed it and nothing rejected it.
+    pub fn evaluate_tool_result(&self, name: &str, id: &str, args: &serde_json::Value, output: &str, is_error: bool) -> Result<Verdict> {
+        let payload = serde_json::json!({ "name": name, "id": id, "args": args, "output": output, "is_error": is_error });
+        self.evaluate_chained("on_tool_result", payload, "output", "a string", serde_json::Value::is_string)
+    }
+
+    /// Call `hook_name` across all loaded scripts, handing each the payload
+    /// with `field` replaced by the last `Modify` so far. Rewrites that fail
+    /// `valid` are an error.
+    fn evaluate_chained(
+        &self,
+        hook_name: &str,
+        mut payload: serde_json::Value,
+        field: &str,
+        expected: &str,
+        valid: fn(&serde_json::Value) -> bool,
+    ) -> Result<Verdict> {
+        let Ok(modules) = self.lua.globals().get::<Table>("__harness_modules") else {
+            return Ok(Verdict::Allow);
+        };
+        let original = payload[field].clone();
+        let mut verdicts = Vec::new();
+        for script_name in &self.scripts {
+            let Ok(func) = modules.get::<Table>(script_name.as_str()).and_then(|m| m.get::<Function>(hook_name)) else {
+                continue;
+            };
+            let lua_payload = self.lua.to_value(&payload)
+                .map_err(|e| anyhow::anyhow!("Failed to convert payload to Lua: {}", e))?;
+            let result = func.call::<MultiValue>(lua_payload)
+                .map_err(|e| anyhow::anyhow!("Harness '{}' hook '{}' failed: {}", script_name, hook_name, e))?;
+            match parse_verdict(&self.lua, result)? {
+                Verdict::Modify(value) if valid(&value) => payload[field] = value,
+                Verdict::Modify(other) => {
+                    return Err(anyhow::anyhow!(
+                        "Harness '{}' hook '{}' rewrote {} to something other than {}: {}",
+                        script_name, hook_name, field, expected, other
+                    ));
+                }
+                verdict => verdicts.push(verdict),
+            }
+        }
+        if payload[field] != original {
+            verdicts.push(Verdict::Modify(payload[field].take()));
+        }
+        Ok(compose_verdicts(&verdicts))
+    }
+
+    /// Take the pending `bedrock.abort_tool` request, if a script made one.
+    pub fn take_tool_abort(&self) -> Option<String> {
+        self.lua.app_data_mut::<globals::ToolAbort>().and_then(|mut abort| abort.0.take())
+    }
+
+    /// Update the usage `bedrock.get_usage()` reports for `session_id`.
+    /// Returns the session's soft limit when this update crosses it (once
+    /// per crossing), so the caller can fire `on_budget_warning`.
+    pub fn update_usage(&self, session_id: &str, input_tokens: u64, output_tokens: u64, cost_usd: f64) -> Option<u64> {
+        let mut usages = self.lua.app_data_mut::<globals::UsageBySession>()?;
+        let default_soft_limit = usages.default_soft_limit;
+        let usage = usages.sessions.entry(session_id.to_string()).or_default();
+        usage.input_tokens = input_tokens;
+        usage.output_tokens = output_tokens;
+        usage.cost_usd = cost_usd;
+        let limit = usage.soft_limit.or(default_soft_limit)?;
+        if usage.total() < limit {
+            usage.warned = false;
+            return None;
+        }
+        if usage.warned {
+            return None;
+        }
+        usage.warned = true;
+        Some(limit)
+    }
+
+    /// Whether any loaded script defines `hook_name`.
+    pub fn has_hook(&self, hook_name: &str) -> bool {
+        let Ok(modules) = self.lua.globals().get::<Table>("__harness_modules") else {
+            return false;
+        };
+        self.scripts.iter().any(|name| {
+            modules
+                .get::<Table>(name.as_str())
+                .map(|m| matches!(m.get::<Value>(hook_name), Ok(Value::Function(_))))
+                .unwrap_or(false)
+        })
+    }
+
+    /// Tools the scripts registered with `bedrock.register_tool`.
+    pub fn tools(&self) -> Vec<HarnessToolDef> {
+        self.lua.app_data_ref::<RegisteredTools>().map(|t| t.0.clone()).unwrap_or_default()
+    }
+
+    /// Make this VM's tool handlers the ones `handlers` runs.
+    pub fn install_tools(&self, handlers: &ToolHandlers) {
+        *handlers.write().unwrap_or_else(|e| e.into_inner()) =
+            self.tools().into_iter().map(|tool| (tool.name, (self.lua.clone(), tool.handler))).collect();
+    }
+
+    /// Install the state that orchestration primitives (`bedrock.call_model`, ...) act on.
+    pub fn begin_orchestration(&self, orchestration: Orchestration) {
+        if let Some(mut slot) = self.lua.app_data_mut::<ActiveOrchestration>() {
+            slot.0 = Some(orchestration);
+        }
+    }
+
+    /// Remove and return the active orchestration state.
+    pub fn end_orchestration(&self) -> Option<Orchestration> {
+        self.lua.app_data_mut::<ActiveOrchestration>().and_then(|mut slot| slot.0.take())
+    }
+
     /// Load and execute a Lua script string (for testing/verification).
     pub fn load_script_str(&mut self, script: &str) -> Result<()> {
         self.lua
@@ -269,11 +401,18 @@ impl HarnessEngine {
 ///   return REJECT, "reason"   → Verdict::Reject(reason)
 ///   return ESCALATE, "reason" → Verdict::Escalate(reason)
 ///   return MODIFY, {new_data} → Verdict::Modify(json_data)
+///   return {new_data}         → Verdict::Modify(json_data)
+///   return "new text"         → Verdict::Modify(json_string)
 fn parse_verdict(lua: &Lua, values: MultiValue) -> Result<Verdict> {
     let mut iter = values.into_iter();
 
     let verdict_code = match iter.next() {
         Some(Value::Integer(n)) => n,
+        Some(value @ (Value::Table(_) | Value::String(_))) => {
+            return lua.from_value::<serde_json::Value>(value)
+                .map(Verdict::Modify)
+                .map_err(|e| anyhow::anyhow!("Failed to convert returned value to JSON: {}", e));
+        }
         Some(Value::Nil) | None => return Ok(Verdict::Allow), // No return = ALLOW
         other => {
             return Err(anyhow::anyhow!(
@@ -323,7 +462,11 @@ mod tests {
             state_store: None,
             clients: std::collections::HashMap::new(),
             embedding_provider: None,
-            queue: std::sync::Arc::new(tokio::sync::Mutex::new(Some(std::sync::Arc::new(tokio::sync::Mutex::new(std::collections::VecDeque::new()))))),
+            queue: std::sync::Arc::new(tokio::sync::Mutex::new(Some((std::sync::Arc::new(tokio::sync::Mutex::new(std::collections::VecDeque::new())), Default::default())))),
+            active_session: Default::default(),
+            tools: Default::default(),
+            safe_mode: None,
+            mcp_servers: Default::default(),
             config: std::sync::Arc::new(crate::kernel::config::BedrockConfig::default()),
         }
     }
@@ -617,4 +760,109 @@ mod tests {
              _ => panic!("Expected Modify verdict, got {:?}", verdict),
         }
     }
+
+    #[test]
+    fn test_engine_tool_call_rewrites_chain() {
+        let dir = TempDir::new().unwrap();
+        // Scripts load in name order; the second sees the first's rewrite
+        std::fs::write(
+            dir.path().join("a_sandbox.lua"),
+            r#"
+            function on_tool_call(call)
+                if call.name == "shell_exec" then
+                    return { command = "cd sandbox && " .. call.args.command }
+                end
+                return allow()
+            end
+            "#,
+        ).unwrap();
+        std::fs::write(
+            dir.path().join("b_guard.lua"),
+            r#"
+            function on_tool_call(call)
+                if call.name == "shell_exec" and not call.args.command:find("^cd sandbox") then
+                    return reject("unsandboxed shell")
+                end
+                if call.name == "write_file" then
+                    return MODIFY, "not a table"
+                end
+            end
+            "#,
+        ).unwrap();
+
+        let mut engine = HarnessEngine::new(test_app_data()).unwrap();
+        engine.load_dir(dir.path()).unwrap();
+
+        let verdict = engine.evaluate_tool_call("shell_exec", "call_1", &serde_json::json!({ "command": "ls" })).unwrap();
+        assert_eq!(verdict, Verdict::Modify(serde_json::json!({ "command": "cd sandbox && ls" })));
+        let verdict = engine.evaluate_tool_call("read_file", "call_2", &serde_json::json!({ "path": "a.txt" })).unwrap();
+        assert_eq!(verdict, Verdict::Allow);
+        let err = engine.evaluate_tool_call("write_file", "call_3", &serde_json::json!({})).unwrap_err();
+        assert!(err.to_string().contains("other than a table"));
+    }
+
+    #[test]
+    fn test_engine_abort_tool() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("abort.lua"),
+            r#"
+            function on_tool_call(call)
+                if call.args.path == "secrets.txt" then
+                    bedrock.abort_tool("policy: secrets are off limits")
+                end
+                return ALLOW
+            end
+            "#,
+        ).unwrap();
+
+        let mut engine = HarnessEngine::new(test_app_data()).unwrap();
+        engine.load_dir(dir.path()).unwrap();
+
+        let verdict = engine.evaluate("on_tool_call", serde_json::json!({"name": "read_file", "args": {"path": "ok.txt"}})).unwrap();
+        assert_eq!(verdict, Verdict::Allow);
+        assert_eq!(engine.take_tool_abort(), None);
+
+        // The declarative verdict is still ALLOW; the abort is reported separately
+        let verdict = engine.evaluate("on_tool_call", serde_json::json!({"name": "read_file", "args": {"path": "secrets.txt"}})).unwrap();
+        assert_eq!(verdict, Verdict::Allow);
+        assert_eq!(engine.take_tool_abort().as_deref(), Some("policy: secrets are off limits"));
+        // Consumed once taken
+        assert_eq!(engine.take_tool_abort(), None);
+    }
+
+    #[test]
+    fn test_soft_limit_warns_once_per_crossing() {
+        let app_data = test_app_data();
+        let active_session = app_data.active_session.clone();
+        let mut engine = HarnessEngine::new(app_data).unwrap();
+        // No limit set: never warns
+        assert_eq!(engine.update_usage("s1", 500, 500, 0.0), None);
+
+        // Set at load time: the default for every session
+        engine.load_script_str("bedrock.set_soft_limit(100)").unwrap();
+        assert_eq!(engine.update_usage("s1", 50, 10, 0.0), None);
+        assert_eq!(engine.update_usage("s1", 80, 30, 0.01), Some(100));
+        assert_eq!(engine.update_usage("s1", 90, 40, 0.02), None);
+        *active_session.write().unwrap() = Some("s1".to_string());
+        engine.load_script_str(r#"
+            local usage = bedrock.get_usage()
+            assert(usage.input == 90 and usage.output == 40 and usage.total == 130)
+            assert(usage.soft_limit == 100)
+        "#).unwrap();
+
+        // Another session has its own usage and warns on its own crossing
+        assert_eq!(engine.update_usage("s2", 10, 10, 0.0), None);
+        assert_eq!(engine.update_usage("s2", 90, 20, 0.01), Some(100));
+
+        // Raising the active session's limit re-arms its warning only
+        engine.load_script_str("bedrock.set_soft_limit(200)").unwrap();
+        assert_eq!(engine.update_usage("s1", 150, 60, 0.03), Some(200));
+        assert_eq!(engine.update_usage("s2", 150, 60, 0.03), None);
+        *active_session.write().unwrap() = Some("s2".to_string());
+        engine.load_script_str(r#"
+            local usage = bedrock.get_usage()
+            assert(usage.total == 210 and usage.soft_limit == 100)
+        "#).unwrap();
+    }
 }
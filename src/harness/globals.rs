@@ -8,16 +8,25 @@ use std::path::{Path, PathBuf};
 use tokio::sync::Mutex;
 use glob::glob;
 
+use crate::harness::orchestrator;
 use crate::persistence::state::StateStore;
 use crate::inference::provider::{
     ProviderClient
 };
 use crate::inference::embeddings::EmbeddingProvider;
+use crate::tools::registry::ToolDescriptor;
+use crate::tools::safe_mode::SharedSafeMode;
+use crate::kernel::session::InjectedTasks;
 use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 
 pub type SessionQueue = Arc<Mutex<VecDeque<String>>>;
-pub type ActiveSessionQueue = Arc<Mutex<Option<SessionQueue>>>;
+/// Queue of the session being run, with its marks for tasks the harness adds.
+pub type ActiveSessionQueue = Arc<Mutex<Option<(SessionQueue, InjectedTasks)>>>;
+/// Id of the session being run, for harness APIs scoped to it.
+pub type ActiveSessionId = Arc<std::sync::RwLock<Option<String>>>;
+/// The kernel's current tool set, refreshed whenever tools are registered.
+pub type ToolCatalog = Arc<std::sync::RwLock<Vec<ToolDescriptor>>>;
 
 /// Shared state passed to async Lua callbacks via app data.
 pub struct HarnessAppData {
@@ -27,9 +36,55 @@ pub struct HarnessAppData {
     pub clients: HashMap<String, ProviderClient>,
     pub embedding_provider: Option<Arc<dyn EmbeddingProvider>>,
     pub queue: ActiveSessionQueue,
+    pub active_session: ActiveSessionId,
+    pub tools: ToolCatalog,
+    /// Safe-mode state (`bedrock.unlock_tool`), when safe mode is on
+    pub safe_mode: Option<SharedSafeMode>,
+    /// Connected MCP servers, for `bedrock.mcp_resources()`
+    pub mcp_servers: crate::tools::mcp::McpServers,
     pub config: Arc<crate::kernel::config::BedrockConfig>, // Full type path to avoid cycle if needed
 }
 
+/// Abort requested by `bedrock.abort_tool(reason)`, consumed by the kernel
+/// when it evaluates the current (or next pending) tool call.
+#[derive(Debug, Default)]
+pub struct ToolAbort(pub Option<String>);
+
+/// Token usage of one session, kept current by the kernel after each turn,
+/// plus the soft limit a script set while that session was active.
+#[derive(Debug, Default)]
+pub struct SessionUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+    pub soft_limit: Option<u64>,
+    /// `on_budget_warning` already fired for the current crossing
+    pub warned: bool,
+}
+
+impl SessionUsage {
+    pub fn total(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+}
+
+/// [`SessionUsage`] of every session this harness has run, by session id.
+/// `bedrock.get_usage()` and `bedrock.set_soft_limit` act on the active
+/// session; a limit set while no session is active (e.g. at load time) is
+/// the default for sessions that haven't set their own.
+#[derive(Debug, Default)]
+pub struct UsageBySession {
+    pub sessions: HashMap<String, SessionUsage>,
+    pub default_soft_limit: Option<u64>,
+}
+
+impl UsageBySession {
+    /// The soft limit that applies to `usage`
+    pub fn soft_limit(&self, usage: &SessionUsage) -> Option<u64> {
+        usage.soft_limit.or(self.default_soft_limit)
+    }
+}
+
 /// Register all Bedrock-SL globals into the Lua VM.
 pub fn register_globals(lua: &Lua, app_data: HarnessAppData) -> LuaResult<()> {
     register_verdict_constants(lua)?;
@@ -44,54 +99,38 @@ pub fn register_globals(lua: &Lua, app_data: HarnessAppData) -> LuaResult<()> {
 
     // Store app data for later access
     lua.set_app_data(app_data);
+    lua.set_app_data(ToolAbort::default());
+    lua.set_app_data(UsageBySession::default());
+    lua.set_app_data(crate::harness::tools::RegisteredTools::default());
 
     Ok(())
 }
 
-/// Register ALLOW, REJECT, ESCALATE as integer constants.
+/// Register ALLOW, REJECT, ESCALATE, MODIFY as integer constants, plus
+/// `allow()`, `reject(reason)` and `escalate(reason)` helpers returning them.
 ///
 /// Lua convention:
 ///   return ALLOW           -- proceed
 ///   return REJECT, "reason" -- block
 ///   return ESCALATE, "reason" -- ask human
+///   return reject("reason")  -- same as REJECT, "reason"
 fn register_verdict_constants(lua: &Lua) -> LuaResult<()> {
     let globals = lua.globals();
     globals.set("ALLOW", 1)?;
     globals.set("REJECT", 2)?;
     globals.set("ESCALATE", 3)?;
     globals.set("MODIFY", 4)?;
+    globals.set("allow", lua.create_function(|_, ()| Ok(1))?)?;
+    globals.set("reject", lua.create_function(|_, reason: Option<String>| Ok((2, reason.unwrap_or_default())))?)?;
+    globals.set("escalate", lua.create_function(|_, reason: Option<String>| Ok((3, reason.unwrap_or_default())))?)?;
     Ok(())
 }
 
 /// Resolve a path relative to a root, ensuring it stays within the root.
-/// Returns None if the path escapes the root.
+/// Returns None if the path escapes the root (the same check file tools get,
+/// see [`crate::tools::is_safe_path`]).
 fn resolve_safe_path(root: &Path, path_str: &str) -> Option<PathBuf> {
-    let path = Path::new(path_str);
-    let resolved = if path.is_absolute() {
-        path.to_path_buf()
-    } else {
-        root.join(path)
-    };
-
-    // Canonicalize both, checking that resolved starts with root
-    // For non-existent paths, check the parent
-    let canonical_root = root.canonicalize().ok()?;
-
-    if let Ok(canonical) = resolved.canonicalize() {
-        if canonical.starts_with(&canonical_root) {
-            return Some(canonical);
-        }
-    } else {
-        // File doesn't exist yet — check parent directory
-        if let Some(parent) = resolved.parent() {
-            if let Ok(canonical_parent) = parent.canonicalize() {
-                if canonical_parent.starts_with(&canonical_root) {
-                    return Some(canonical_parent.join(resolved.file_name()?));
-                }
-            }
-        }
-    }
-    None
+    crate::tools::is_safe_path(root, Path::new(path_str)).ok()
 }
 
 /// Register `fs` table: read, write, exists, list, is_safe_path
@@ -296,7 +335,7 @@ fn register_session_module(lua: &Lua, app_data: &HarnessAppData) -> LuaResult<()
                         })
                     });
                     match result {
-                        Ok(sessions) => Ok(sessions),
+                        Ok(sessions) => Ok(sessions.into_iter().map(|s| s.id).collect()),
                         Err(e) => Err(mlua::Error::runtime(format!("Failed to list sessions: {}", e))),
                     }
                 }
@@ -323,11 +362,17 @@ fn register_session_module(lua: &Lua, app_data: &HarnessAppData) -> LuaResult<()
                             let tbl = lua.create_table()?;
                             for (i, row) in rows.into_iter().enumerate() {
                                 let msg_tbl = lua.create_table()?;
+                                let content_json = row.content_json();
                                 msg_tbl.set("role", row.role)?;
-                                // content is JSON string. Decode it.
-                                let content_json: serde_json::Value = serde_json::from_str(&row.content)
-                                    .map_err(|e| mlua::Error::runtime(format!("Failed to parse message content: {}", e)))?;
-                                msg_tbl.set("content", lua.to_value(&content_json)?)?;
+                                msg_tbl.set("partial", row.partial)?;
+                                // content is JSON string. Decode it, falling back to the raw string for a malformed row.
+                                match content_json {
+                                    Some(content_json) => msg_tbl.set("content", lua.to_value(&content_json)?)?,
+                                    None => {
+                                        msg_tbl.set("content", row.content)?;
+                                        msg_tbl.set("malformed", true)?;
+                                    }
+                                }
                                 tbl.set(i + 1, msg_tbl)?;
                             }
                             Ok(Value::Table(tbl))
@@ -348,8 +393,9 @@ fn register_session_module(lua: &Lua, app_data: &HarnessAppData) -> LuaResult<()
             tokio::task::block_in_place(|| {
                 tokio::runtime::Handle::current().block_on(async {
                     let queue_opt = active_queue.lock().await;
-                    if let Some(queue) = &*queue_opt {
+                    if let Some((queue, injected)) = &*queue_opt {
                         let mut q = queue.lock().await;
+                        injected.mark(&command);
                         q.push_back(command);
                     } else {
                         eprintln!("[harness] WARN: session.queue called without active session");
@@ -368,9 +414,10 @@ fn register_session_module(lua: &Lua, app_data: &HarnessAppData) -> LuaResult<()
             tokio::task::block_in_place(|| {
                 tokio::runtime::Handle::current().block_on(async {
                     let queue_opt = active_queue.lock().await;
-                     if let Some(queue) = &*queue_opt {
+                     if let Some((queue, injected)) = &*queue_opt {
                         let mut q = queue.lock().await;
                         for cmd in commands {
+                             injected.mark(&cmd);
                              q.push_back(cmd);
                         }
                      }
@@ -388,8 +435,9 @@ fn register_session_module(lua: &Lua, app_data: &HarnessAppData) -> LuaResult<()
             tokio::task::block_in_place(|| {
                 tokio::runtime::Handle::current().block_on(async {
                      let queue_opt = active_queue.lock().await;
-                     if let Some(queue) = &*queue_opt {
+                     if let Some((queue, injected)) = &*queue_opt {
                          let mut q = queue.lock().await;
+                         injected.mark(&command);
                          q.push_front(command);
                      }
                 })
@@ -453,6 +501,113 @@ fn register_bedrock_module(lua: &Lua, app_data: &HarnessAppData) -> LuaResult<()
         Ok(modules.get::<Value>(name)?)
     })?)?;
 
+    // bedrock.register_tool({ name, description, parameters?, effect?, handler }) -> offer a Lua tool to the model
+    bedrock_table.set("register_tool", lua.create_function(crate::harness::tools::register_tool)?)?;
+
+    // bedrock.abort_tool(reason) -> skip the current/pending tool call with an error result
+    bedrock_table.set("abort_tool", lua.create_function(|lua, reason: Option<String>| {
+        let reason = reason.unwrap_or_else(|| "aborted by harness".to_string());
+        match lua.app_data_mut::<ToolAbort>() {
+            Some(mut abort) => {
+                abort.0 = Some(reason);
+                Ok(())
+            }
+            None => Err(mlua::Error::runtime("abort_tool unavailable: harness not initialized")),
+        }
+    })?)?;
+
+    // bedrock.get_usage() -> { input, output, total, cost_usd, soft_limit }
+    {
+        let active_session = app_data.active_session.clone();
+        bedrock_table.set("get_usage", lua.create_function(move |lua, ()| {
+            let usages = lua.app_data_ref::<UsageBySession>()
+                .ok_or_else(|| mlua::Error::runtime("get_usage unavailable: harness not initialized"))?;
+            let session_id = active_session.read().unwrap_or_else(|e| e.into_inner()).clone();
+            let idle = SessionUsage::default();
+            let usage = session_id.and_then(|id| usages.sessions.get(&id)).unwrap_or(&idle);
+            lua.to_value(&serde_json::json!({
+                "input": usage.input_tokens,
+                "output": usage.output_tokens,
+                "total": usage.total(),
+                "cost_usd": usage.cost_usd,
+                "soft_limit": usages.soft_limit(usage),
+            }))
+        })?)?;
+    }
+
+    // bedrock.list_tools() -> [{ name, description, parameters, effect, source, locked }]
+    {
+        let tools = app_data.tools.clone();
+        bedrock_table.set("list_tools", lua.create_function(move |lua, ()| {
+            let tools = tools.read().unwrap_or_else(|e| e.into_inner());
+            lua.to_value(&*tools)
+        })?)?;
+    }
+
+    // bedrock.mcp_resources() -> [{ server, uri, name, description, mime_type }]
+    {
+        let servers = app_data.mcp_servers.clone();
+        bedrock_table.set("mcp_resources", lua.create_function(move |lua, ()| {
+            let servers = servers.read().unwrap_or_else(|e| e.into_inner()).clone();
+            let resources = tokio::task::block_in_place(|| {
+                tokio::runtime::Handle::current().block_on(crate::tools::mcp_resources::list_resources(&servers))
+            });
+            lua.to_value(&resources)
+        })?)?;
+    }
+
+    // bedrock.read_mcp_resource(uri, server?) -> string; errors if it can't be read
+    {
+        let servers = app_data.mcp_servers.clone();
+        bedrock_table.set("read_mcp_resource", lua.create_function(move |_, (uri, server): (String, Option<String>)| {
+            let servers = servers.read().unwrap_or_else(|e| e.into_inner()).clone();
+            tokio::task::block_in_place(|| {
+                tokio::runtime::Handle::current().block_on(crate::tools::mcp_resources::read_resource(&servers, &uri, server.as_deref()))
+            })
+            .map_err(|e| mlua::Error::runtime(format!("read_mcp_resource: {:#}", e)))
+        })?)?;
+    }
+
+    // bedrock.unlock_tool(name) -> true if newly unlocked; false if already unlocked or not in safe mode
+    {
+        let tools = app_data.tools.clone();
+        let safe_mode = app_data.safe_mode.clone();
+        bedrock_table.set("unlock_tool", lua.create_function(move |_, name: String| {
+            let Some(safe_mode) = &safe_mode else {
+                return Ok(false);
+            };
+            let unlocked = safe_mode.write().unwrap_or_else(|e| e.into_inner()).unlock(&name);
+            for tool in tools.write().unwrap_or_else(|e| e.into_inner()).iter_mut().filter(|t| t.name == name) {
+                tool.locked = false;
+            }
+            Ok(unlocked)
+        })?)?;
+    }
+
+    // bedrock.set_soft_limit(tokens | nil) -> on_budget_warning fires once the
+    // active session's total tokens reach it; with no active session, sets the default
+    {
+        let active_session = app_data.active_session.clone();
+        bedrock_table.set("set_soft_limit", lua.create_function(move |lua, limit: Option<u64>| {
+            let mut usages = lua.app_data_mut::<UsageBySession>()
+                .ok_or_else(|| mlua::Error::runtime("set_soft_limit unavailable: harness not initialized"))?;
+            match active_session.read().unwrap_or_else(|e| e.into_inner()).clone() {
+                Some(session_id) => {
+                    let usage = usages.sessions.entry(session_id).or_default();
+                    usage.soft_limit = limit;
+                    usage.warned = false;
+                }
+                None => {
+                    usages.default_soft_limit = limit;
+                    for usage in usages.sessions.values_mut().filter(|u| u.soft_limit.is_none()) {
+                        usage.warned = false;
+                    }
+                }
+            }
+            Ok(())
+        })?)?;
+    }
+
     // bedrock.complete(prompt, options) -> string | nil
     {
         let clients = app_data.clients.clone();
@@ -591,6 +746,8 @@ fn register_bedrock_module(lua: &Lua, app_data: &HarnessAppData) -> LuaResult<()
                          let tbl = lua.create_table()?;
                          for (i, row) in rows.into_iter().enumerate() {
                              let row_tbl = lua.create_table()?;
+                             row_tbl.set("id", row.id)?;
+                             row_tbl.set("session_id", row.session_id)?;
                              row_tbl.set("content", row.content)?;
                              row_tbl.set("score", row.score)?;
                              // Parse metadata if needed, for now just raw string or ignore
@@ -607,10 +764,109 @@ fn register_bedrock_module(lua: &Lua, app_data: &HarnessAppData) -> LuaResult<()
         bedrock_table.set("memory", memory_table)?;
     }
 
+    // bedrock.plan sub-module
+    {
+        let plan_table = lua.create_table()?;
+
+        // bedrock.plan.get(session_id) -> { {title=..., status=...}, ... }
+        {
+            let store = app_data.state_store.clone();
+            plan_table.set("get", lua.create_function(move |lua, session_id: String| {
+                let store = store.clone().ok_or_else(|| mlua::Error::runtime("No state store available"))?;
+                let steps = tokio::task::block_in_place(|| {
+                    tokio::runtime::Handle::current().block_on(store.get_plan(&session_id))
+                }).map_err(|e| mlua::Error::runtime(e.to_string()))?;
+                lua.to_value(&steps)
+            })?)?;
+        }
+
+        // bedrock.plan.update(session_id, steps) -- replaces the plan and logs plan_updated
+        {
+            let store = app_data.state_store.clone();
+            plan_table.set("update", lua.create_function(move |lua, (session_id, steps): (String, Value)| {
+                let store = store.clone().ok_or_else(|| mlua::Error::runtime("No state store available"))?;
+                let steps: Vec<crate::persistence::state::PlanStep> = lua.from_value(steps)?;
+                tokio::task::block_in_place(|| {
+                    tokio::runtime::Handle::current().block_on(async {
+                        store.set_plan(&session_id, &steps).await?;
+                        let event = crate::kernel::event::KernelEvent::PlanUpdated { steps };
+                        store.insert_event(&session_id, event.event_type(), &serde_json::to_value(&event)?).await
+                    })
+                }).map_err(|e| mlua::Error::runtime(e.to_string()))
+            })?)?;
+        }
+
+        bedrock_table.set("plan", plan_table)?;
+    }
+
+    // bedrock.records_put(collection, id, record, { global = bool }?) -- store a record in the current session (or globally)
+    {
+        let store = app_data.state_store.clone();
+        let active_session = app_data.active_session.clone();
+        bedrock_table.set("records_put", lua.create_function(move |lua, (collection, id, record, opts): (String, String, Value, Option<Table>)| {
+            let store = store.clone().ok_or_else(|| mlua::Error::runtime("No state store available"))?;
+            let session_id = record_scope(&active_session, opts.as_ref(), "records_put")?;
+            let record: serde_json::Value = lua.from_value(record)?;
+            tokio::task::block_in_place(|| {
+                tokio::runtime::Handle::current().block_on(store.records_put(&collection, &id, session_id.as_deref(), &record))
+            }).map_err(|e| mlua::Error::runtime(e.to_string()))
+        })?)?;
+    }
+
+    // bedrock.records_query(collection, filter?, { global = bool, limit = n }?) -> { {id=..., data=..., ...}, ... }
+    {
+        let store = app_data.state_store.clone();
+        let active_session = app_data.active_session.clone();
+        bedrock_table.set("records_query", lua.create_function(move |lua, (collection, filter, opts): (String, Option<Value>, Option<Table>)| {
+            let store = store.clone().ok_or_else(|| mlua::Error::runtime("No state store available"))?;
+            let session_id = record_scope(&active_session, opts.as_ref(), "records_query")?;
+            let limit = match &opts {
+                Some(opts) => opts.get::<Option<usize>>("limit")?.unwrap_or(DEFAULT_RECORDS_LIMIT),
+                None => DEFAULT_RECORDS_LIMIT,
+            }
+            .min(MAX_RECORDS_LIMIT);
+            // An empty Lua table deserializes as an array
+            let filter = match filter {
+                Some(Value::Table(t)) if !t.is_empty() => match lua.from_value::<serde_json::Value>(Value::Table(t))? {
+                    serde_json::Value::Object(map) => map,
+                    _ => return Err(mlua::Error::runtime("records_query filter must be a table of path = value")),
+                },
+                _ => serde_json::Map::new(),
+            };
+            let records = tokio::task::block_in_place(|| {
+                tokio::runtime::Handle::current().block_on(store.records_query(&collection, session_id.as_deref(), &filter, limit))
+            }).map_err(|e| mlua::Error::runtime(e.to_string()))?;
+            lua.to_value(&records)
+        })?)?;
+    }
+
+    // bedrock.call_model / run_tool / append_message / get_messages (orchestrator mode)
+    orchestrator::register(lua, &bedrock_table)?;
+
     lua.globals().set("bedrock", bedrock_table)?;
     Ok(())
 }
 
+/// Records returned by `bedrock.records_query` without a `limit`.
+const DEFAULT_RECORDS_LIMIT: usize = 100;
+
+/// Most records one `bedrock.records_query` call returns.
+const MAX_RECORDS_LIMIT: usize = 1_000;
+
+/// Session a `bedrock.records_*` call is scoped to: the active session, or
+/// `None` with `{ global = true }`.
+fn record_scope(active_session: &ActiveSessionId, opts: Option<&Table>, function: &str) -> LuaResult<Option<String>> {
+    if let Some(opts) = opts {
+        if opts.get::<Option<bool>>("global")?.unwrap_or(false) {
+            return Ok(None);
+        }
+    }
+    match &*active_session.read().unwrap_or_else(|e| e.into_inner()) {
+        Some(id) => Ok(Some(id.clone())),
+        None => Err(mlua::Error::runtime(format!("{} needs an active session; pass {{ global = true }} for a global record", function))),
+    }
+}
+
 /// Register `bedrock.agent` table: spawn
 fn register_agent_module(lua: &Lua, app_data: &HarnessAppData) -> LuaResult<()> {
     let agent_table = lua.create_table()?;
@@ -702,12 +958,19 @@ fn register_agent_module(lua: &Lua, app_data: &HarnessAppData) -> LuaResult<()>
     Ok(())
 }
 
-/// Register `log(msg)` global function
+/// Register `log(msg)` global function, and route `print(...)` to stderr
+/// with it: stdout carries the model's response, or the protocol under
+/// `bedrock mcp-serve`.
 fn register_log_function(lua: &Lua) -> LuaResult<()> {
     lua.globals().set("log", lua.create_function(|_lua, msg: String| {
         eprintln!("[harness] {}", msg);
         Ok(())
     })?)?;
+    lua.globals().set("print", lua.create_function(|_lua, values: mlua::MultiValue| {
+        let parts = values.iter().map(|v| v.to_string()).collect::<LuaResult<Vec<_>>>()?;
+        eprintln!("[harness] {}", parts.join("\t"));
+        Ok(())
+    })?)?;
     Ok(())
 }
 
@@ -723,19 +986,38 @@ mod tests {
             state_store: None,
             clients: HashMap::new(),
             embedding_provider: None,
-            queue: Arc::new(Mutex::new(Some(Arc::new(Mutex::new(VecDeque::new()))))),
+            queue: Arc::new(Mutex::new(Some((Arc::new(Mutex::new(VecDeque::new())), Default::default())))),
+            active_session: Default::default(),
+            tools: Default::default(),
+            safe_mode: None,
+            mcp_servers: Default::default(),
             config: Arc::new(crate::kernel::config::BedrockConfig {
                 agent: crate::kernel::config::AgentConfig {
                     system_prompt: "test".to_string(),
                     model: "test".to_string(),
                     provider: "openai".to_string(),
                     thinking: None,
+                    inject_plan: true,
+                    prompt_template: Default::default(),
+                    postprocessors: Vec::new(),
+                    fallbacks: Vec::new(),
                 },
                 kernel: crate::kernel::config::KernelConfig::default(),
                 persistence: crate::kernel::config::PersistenceConfig::default(),
                 harness: crate::kernel::config::HarnessConfig::default(),
                 providers: crate::kernel::config::ProvidersConfig::default(),
                 embeddings: None,
+                tools: Default::default(),
+                repl: Default::default(),
+                memory: Default::default(),
+                maintenance: Default::default(),
+                credentials: Default::default(),
+                mcp: Default::default(),
+                inference: Default::default(),
+                compaction: Default::default(),
+                limits: Default::default(),
+                server: Default::default(),
+                pricing: Default::default(),
             }),
         }
     }
@@ -748,6 +1030,9 @@ mod tests {
         assert_eq!(globals.get::<i32>("ALLOW").unwrap(), 1);
         assert_eq!(globals.get::<i32>("REJECT").unwrap(), 2);
         assert_eq!(globals.get::<i32>("ESCALATE").unwrap(), 3);
+        let (code, reason): (i32, String) = lua.load(r#"return reject("no")"#).eval().unwrap();
+        assert_eq!((code, reason.as_str()), (2, "no"));
+        assert_eq!(lua.load("return allow()").eval::<i32>().unwrap(), 1);
     }
 
     #[test]
@@ -817,6 +1102,94 @@ mod tests {
         assert_eq!(count, 2);
     }
 
+    #[test]
+    fn test_list_tools_reflects_catalog() {
+        let dir = TempDir::new().unwrap();
+        let lua = Lua::new();
+        let app_data = create_test_app_data(dir.path());
+        let catalog = app_data.tools.clone();
+        register_globals(&lua, app_data).unwrap();
+
+        let count: i32 = lua.load("return #bedrock.list_tools()").eval().unwrap();
+        assert_eq!(count, 0);
+
+        // Tools registered later (e.g. by an MCP server) show up on the next call
+        *catalog.write().unwrap() = crate::tools::builtins::create_default_registry().descriptors();
+        let summary: String = lua.load(r#"
+            for _, tool in ipairs(bedrock.list_tools()) do
+                if tool.name == "shell_exec" then
+                    return tool.effect .. " " .. tool.source .. " " .. tool.parameters.required[1]
+                end
+            end
+        "#).eval().unwrap();
+        assert_eq!(summary, "execute builtin command");
+    }
+
+    #[test]
+    fn test_unlock_tool() {
+        let dir = TempDir::new().unwrap();
+        let lua = Lua::new();
+        let mut app_data = create_test_app_data(dir.path());
+        let safe_mode = crate::tools::safe_mode::SafeMode::new(Vec::new()).shared();
+        let mut registry = crate::tools::builtins::create_default_registry();
+        registry.set_safe_mode(safe_mode.clone());
+        *app_data.tools.write().unwrap() = registry.descriptors();
+        app_data.safe_mode = Some(safe_mode.clone());
+        register_globals(&lua, app_data).unwrap();
+
+        let locked = |name: &str| lua.load(format!(
+            "for _, t in ipairs(bedrock.list_tools()) do if t.name == '{}' then return t.locked end end", name
+        )).eval::<bool>().unwrap();
+        assert!(locked("shell_exec"));
+        assert!(lua.load("return bedrock.unlock_tool('shell_exec')").eval::<bool>().unwrap());
+        assert!(!lua.load("return bedrock.unlock_tool('shell_exec')").eval::<bool>().unwrap());
+        assert!(!locked("shell_exec"));
+        assert!(!registry.is_locked("shell_exec"));
+        assert_eq!(safe_mode.write().unwrap().take_unlocked(), vec!["shell_exec"]);
+
+        // Outside safe mode there is nothing to unlock
+        let lua = Lua::new();
+        register_globals(&lua, create_test_app_data(dir.path())).unwrap();
+        assert!(!lua.load("return bedrock.unlock_tool('shell_exec')").eval::<bool>().unwrap());
+    }
+
+    // block_in_place needs the multi-threaded runtime
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_records_put_and_query() {
+        let dir = TempDir::new().unwrap();
+        let lua = Lua::new();
+        let mut app_data = create_test_app_data(dir.path());
+        app_data.state_store = Some(StateStore::open_memory().await.unwrap());
+        let active_session = app_data.active_session.clone();
+        register_globals(&lua, app_data).unwrap();
+
+        // Session-scoped calls need a running session
+        let err = lua.load("bedrock.records_put('tasks', 't1', { title = 'x' })").exec().unwrap_err();
+        assert!(err.to_string().contains("global = true"));
+
+        *active_session.write().unwrap() = Some("s1".to_string());
+        lua.load(r#"
+            bedrock.records_put("tasks", "t1", { title = "write docs", status = "open", owner = { name = "ann" } })
+            bedrock.records_put("tasks", "t2", { title = "fix bug", status = "done", owner = { name = "ann" } })
+            bedrock.records_put("tasks", "t3", { title = "review", status = "open", owner = { name = "bob" } })
+            bedrock.records_put("tasks", "shared", { title = "release" }, { global = true })
+        "#).exec().unwrap();
+
+        let ids = |script: &str| lua.load(format!(
+            "local ids = {{}} for _, r in ipairs({}) do table.insert(ids, r.id) end return table.concat(ids, ',')", script
+        )).eval::<String>().unwrap();
+        assert_eq!(ids("bedrock.records_query('tasks')"), "t1,t2,t3");
+        assert_eq!(ids("bedrock.records_query('tasks', { status = 'open', ['owner.name'] = 'ann' })"), "t1");
+        assert_eq!(ids("bedrock.records_query('tasks', {}, { limit = 2 })"), "t1,t2");
+        assert_eq!(ids("bedrock.records_query('tasks', nil, { global = true })"), "shared");
+        let title: String = lua.load("return bedrock.records_query('tasks', { status = 'done' })[1].data.title").eval().unwrap();
+        assert_eq!(title, "fix bug");
+
+        // Another session sees only global records
+        *active_session.write().unwrap() = Some("s2".to_string());
+        assert_eq!(ids("bedrock.records_query('tasks')"), "");
+    }
+
     #[test]
     fn test_json_encode_decode() {
         let lua = Lua::new();
@@ -826,19 +1199,38 @@ mod tests {
             state_store: None,
             clients: HashMap::new(),
             embedding_provider: None,
-            queue: Arc::new(Mutex::new(Some(Arc::new(Mutex::new(VecDeque::new()))))),
+            queue: Arc::new(Mutex::new(Some((Arc::new(Mutex::new(VecDeque::new())), Default::default())))),
+            active_session: Default::default(),
+            tools: Default::default(),
+            safe_mode: None,
+            mcp_servers: Default::default(),
             config: Arc::new(crate::kernel::config::BedrockConfig {
                 agent: crate::kernel::config::AgentConfig {
                     system_prompt: "test".to_string(),
                     model: "test".to_string(),
                     provider: "openai".to_string(),
                     thinking: None,
+                    inject_plan: true,
+                    prompt_template: Default::default(),
+                    postprocessors: Vec::new(),
+                    fallbacks: Vec::new(),
                 },
                 kernel: crate::kernel::config::KernelConfig::default(),
                 persistence: crate::kernel::config::PersistenceConfig::default(),
                 harness: crate::kernel::config::HarnessConfig::default(),
                 providers: crate::kernel::config::ProvidersConfig::default(),
                 embeddings: None,
+                tools: Default::default(),
+                repl: Default::default(),
+                memory: Default::default(),
+                maintenance: Default::default(),
+                credentials: Default::default(),
+                mcp: Default::default(),
+                inference: Default::default(),
+                compaction: Default::default(),
+                limits: Default::default(),
+                server: Default::default(),
+                pricing: Default::default(),
             }),
         };
         register_globals(&lua, app_data).unwrap();
@@ -863,25 +1255,45 @@ mod tests {
             state_store: None,
             clients: HashMap::new(),
             embedding_provider: None,
-            queue: Arc::new(Mutex::new(Some(Arc::new(Mutex::new(VecDeque::new()))))),
+            queue: Arc::new(Mutex::new(Some((Arc::new(Mutex::new(VecDeque::new())), Default::default())))),
+            active_session: Default::default(),
+            tools: Default::default(),
+            safe_mode: None,
+            mcp_servers: Default::default(),
             config: Arc::new(crate::kernel::config::BedrockConfig {
                 agent: crate::kernel::config::AgentConfig {
                     system_prompt: "test".to_string(),
                     model: "test".to_string(),
                     provider: "openai".to_string(),
                     thinking: None,
+                    inject_plan: true,
+                    prompt_template: Default::default(),
+                    postprocessors: Vec::new(),
+                    fallbacks: Vec::new(),
                 },
                 kernel: crate::kernel::config::KernelConfig::default(),
                 persistence: crate::kernel::config::PersistenceConfig::default(),
                 harness: crate::kernel::config::HarnessConfig::default(),
                 providers: crate::kernel::config::ProvidersConfig::default(),
                 embeddings: None,
+                tools: Default::default(),
+                repl: Default::default(),
+                memory: Default::default(),
+                maintenance: Default::default(),
+                credentials: Default::default(),
+                mcp: Default::default(),
+                inference: Default::default(),
+                compaction: Default::default(),
+                limits: Default::default(),
+                server: Default::default(),
+                pricing: Default::default(),
             }),
         };
         register_globals(&lua, app_data).unwrap();
 
         // Just verify it doesn't panic
         lua.load("log('test message from harness')").exec().unwrap();
+        lua.load("print('print from harness', 1, nil, {})").exec().unwrap();
     }
 
     #[test]
@@ -893,19 +1305,38 @@ mod tests {
             state_store: None,
             clients: HashMap::new(),
             embedding_provider: None,
-            queue: Arc::new(Mutex::new(Some(Arc::new(Mutex::new(VecDeque::new()))))),
+            queue: Arc::new(Mutex::new(Some((Arc::new(Mutex::new(VecDeque::new())), Default::default())))),
+            active_session: Default::default(),
+            tools: Default::default(),
+            safe_mode: None,
+            mcp_servers: Default::default(),
             config: Arc::new(crate::kernel::config::BedrockConfig {
                 agent: crate::kernel::config::AgentConfig {
                     system_prompt: "test".to_string(),
                     model: "test".to_string(),
                     provider: "openai".to_string(),
                     thinking: None,
+                    inject_plan: true,
+                    prompt_template: Default::default(),
+                    postprocessors: Vec::new(),
+                    fallbacks: Vec::new(),
                 },
                 kernel: crate::kernel::config::KernelConfig::default(),
                 persistence: crate::kernel::config::PersistenceConfig::default(),
                 harness: crate::kernel::config::HarnessConfig::default(),
                 providers: crate::kernel::config::ProvidersConfig::default(),
                 embeddings: None,
+                tools: Default::default(),
+                repl: Default::default(),
+                memory: Default::default(),
+                maintenance: Default::default(),
+                credentials: Default::default(),
+                mcp: Default::default(),
+                inference: Default::default(),
+                compaction: Default::default(),
+                limits: Default::default(),
+                server: Default::default(),
+                pricing: Default::default(),
             }),
         };
         register_globals(&lua, app_data).unwrap();
@@ -2,3 +2,6 @@ pub mod engine;
 pub mod globals;
 pub mod verdict;
 pub mod context;
+pub mod orchestrator;
+pub mod request;
+pub mod tools;
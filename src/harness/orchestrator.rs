@@ -0,0 +1,298 @@
+//! Orchestrator mode: the harness drives the agent loop.
+//!
+//! With `kernel.mode = "orchestrator"` the kernel skips its built-in turn loop
+//! and calls the `on_orchestrate` hook once per queued prompt. For the duration
+//! of that call an [`Orchestration`] is installed in the Lua VM's app data and
+//! the following primitives operate on it:
+//!
+//! - `bedrock.call_model(messages?, options?)` — one inference call
+//! - `bedrock.run_tool(name, args, id?)` — execute a registered tool
+//! - `bedrock.append_message(message)` — append to (and persist) the history
+//! - `bedrock.get_messages()` — the current history
+//!
+//! The kernel still provides provider clients, tools and persistence; turn
+//! structure, tool gating and stopping conditions are up to the script. The
+//! kernel's own run limits still apply: `call_model` counts against
+//! `kernel.max_turns` and is refused once the cost budget would be crossed,
+//! raising a Lua error that ends the orchestration.
+
+use futures::StreamExt;
+use mlua::{Lua, LuaSerdeExt, Result as LuaResult, Table, Value};
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::inference::pricing::{self, ModelPricing};
+use crate::inference::provider::{
+    InferenceContent, InferenceMessage, InferenceOptions, InferenceRole, ProviderClient,
+};
+use crate::kernel::event::KernelEvent;
+use crate::kernel::estimate_request_cost;
+use crate::kernel::session::StopReason;
+use crate::persistence::state::StateStore;
+use crate::tools::registry::ToolRegistry;
+use crate::tools::ToolContext;
+
+/// State the orchestration primitives act on while `on_orchestrate` runs.
+pub struct Orchestration {
+    pub session_id: String,
+    pub turn_index: u32,
+    pub history: Vec<InferenceMessage>,
+    pub model: String,
+    pub provider: String,
+    pub system_prompt: String,
+    pub clients: HashMap<String, ProviderClient>,
+    /// Snapshot of the kernel's tools
+    pub tools: ToolRegistry,
+    pub tool_ctx: ToolContext,
+    pub state: Option<StateStore>,
+    pub limits: OrchestrationLimits,
+    /// One entry per `bedrock.call_model` request
+    pub usage: Vec<ModelUsage>,
+    /// Estimated session spend in USD, including the calls made so far
+    pub spent_usd: f64,
+    /// Tools run so far via `bedrock.run_tool`
+    pub tool_calls: u32,
+    /// Set when `call_model` was refused by one of the `limits`
+    pub stop_reason: Option<StopReason>,
+    /// Estimated cost of the request refused for the cost budget
+    pub refused_estimate_usd: Option<f64>,
+}
+
+/// Run limits applied to `bedrock.call_model`, mirroring the built-in loop.
+#[derive(Debug, Clone)]
+pub struct OrchestrationLimits {
+    /// Model calls allowed for the prompt (`kernel.max_turns`)
+    pub max_model_calls: u32,
+    /// Session spend cap in USD (`limits.max_cost_usd` / `kernel.cost_budget_usd`)
+    pub cost_budget_usd: Option<f64>,
+    /// Pricing overrides from `[pricing]`
+    pub pricing: HashMap<String, ModelPricing>,
+}
+
+impl Default for OrchestrationLimits {
+    fn default() -> Self {
+        Self { max_model_calls: u32::MAX, cost_budget_usd: None, pricing: HashMap::new() }
+    }
+}
+
+/// Token usage of one `bedrock.call_model` request.
+#[derive(Debug, Clone)]
+pub struct ModelUsage {
+    pub provider: String,
+    pub model: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl Orchestration {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        session_id: String,
+        turn_index: u32,
+        history: Vec<InferenceMessage>,
+        model: String,
+        provider: String,
+        system_prompt: String,
+        clients: HashMap<String, ProviderClient>,
+        tools: ToolRegistry,
+        tool_ctx: ToolContext,
+        state: Option<StateStore>,
+    ) -> Self {
+        Self {
+            session_id,
+            turn_index,
+            history,
+            model,
+            provider,
+            system_prompt,
+            clients,
+            tools,
+            tool_ctx,
+            state,
+            limits: OrchestrationLimits::default(),
+            usage: Vec::new(),
+            spent_usd: 0.0,
+            tool_calls: 0,
+            stop_reason: None,
+            refused_estimate_usd: None,
+        }
+    }
+
+    /// Apply `limits`, counting `spent_usd` already spent by the session.
+    pub fn with_limits(mut self, limits: OrchestrationLimits, spent_usd: f64) -> Self {
+        self.limits = limits;
+        self.spent_usd = spent_usd;
+        self
+    }
+
+    /// Refuse the next model call if it would exceed the run limits.
+    fn check_limits(&mut self, provider: &str, model: &str, system_prompt: &str, messages: &[InferenceMessage], tools: &[serde_json::Value]) -> LuaResult<()> {
+        if self.usage.len() as u32 >= self.limits.max_model_calls {
+            self.stop_reason = Some(StopReason::MaxTurns);
+            return Err(mlua::Error::runtime(format!(
+                "call_model refused: kernel.max_turns ({}) reached",
+                self.limits.max_model_calls
+            )));
+        }
+        let Some(budget) = self.limits.cost_budget_usd else { return Ok(()) };
+        let pricing = pricing::lookup(&self.limits.pricing, provider, model);
+        let estimate = estimate_request_cost(pricing, system_prompt, messages, tools, &InferenceOptions::default());
+        match estimate.or((self.spent_usd > budget).then_some(0.0)) {
+            Some(estimate) if self.spent_usd + estimate > budget => {
+                self.stop_reason = Some(StopReason::CostBudgetExceeded);
+                self.refused_estimate_usd = Some(estimate);
+                Err(mlua::Error::runtime(format!(
+                    "call_model refused: the request (~${:.4}) would exceed the ${:.4} cost budget",
+                    estimate, budget
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// App-data slot holding the active orchestration (if any).
+#[derive(Default)]
+pub struct ActiveOrchestration(pub Option<Orchestration>);
+
+fn not_active() -> mlua::Error {
+    mlua::Error::runtime("orchestration primitives are only available inside on_orchestrate (kernel.mode = \"orchestrator\")")
+}
+
+/// Run `f` against the active orchestration.
+fn with_active<T>(lua: &Lua, f: impl FnOnce(&mut Orchestration) -> LuaResult<T>) -> LuaResult<T> {
+    let mut slot = lua.app_data_mut::<ActiveOrchestration>().ok_or_else(not_active)?;
+    let orch = slot.0.as_mut().ok_or_else(not_active)?;
+    f(orch)
+}
+
+fn block_on<F: std::future::Future>(fut: F) -> F::Output {
+    tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(fut))
+}
+
+fn role_name(message: &InferenceMessage) -> &'static str {
+    let all_results = !message.content.is_empty()
+        && message.content.iter().all(|c| matches!(c, InferenceContent::ToolResult { .. }));
+    match message.role {
+        InferenceRole::Assistant => "assistant",
+        _ if all_results => "tool_result",
+        _ => "user",
+    }
+}
+
+/// Register the orchestration primitives on the `bedrock` table.
+pub fn register(lua: &Lua, bedrock_table: &Table) -> LuaResult<()> {
+    lua.set_app_data(ActiveOrchestration::default());
+
+    // bedrock.get_messages() -> { message, ... }
+    bedrock_table.set("get_messages", lua.create_function(|lua, ()| {
+        let history = with_active(lua, |o| Ok(o.history.clone()))?;
+        lua.to_value(&history)
+    })?)?;
+
+    // bedrock.append_message(message) -- same shape as ctx:get_messages() entries
+    bedrock_table.set("append_message", lua.create_function(|lua, val: Value| {
+        let message: InferenceMessage = lua.from_value(val)?;
+        let (store, session_id, turn_index) = with_active(lua, |o| {
+            o.history.push(message.clone());
+            Ok((o.state.clone(), o.session_id.clone(), o.turn_index))
+        })?;
+        if let Some(store) = store {
+            let content = serde_json::to_value(&message.content).map_err(mlua::Error::external)?;
+            block_on(store.insert_message(&session_id, turn_index, role_name(&message), &content, None, None))
+                .map_err(|e| mlua::Error::runtime(format!("Failed to persist message: {}", e)))?;
+        }
+        Ok(())
+    })?)?;
+
+    // bedrock.call_model(messages?, options?) -> { text, tool_calls, input_tokens, output_tokens, stop_reason }
+    bedrock_table.set("call_model", lua.create_function(|lua, (messages, options): (Value, Option<Table>)| {
+        let messages: Option<Vec<InferenceMessage>> = match messages {
+            Value::Nil => None,
+            v => Some(lua.from_value(v)?),
+        };
+        let (client, provider, model, system_prompt, messages, tools) = with_active(lua, |o| {
+            let mut provider = o.provider.clone();
+            let mut model = o.model.clone();
+            let mut system_prompt = o.system_prompt.clone();
+            let mut with_tools = true;
+            if let Some(opts) = &options {
+                if let Some(p) = opts.get::<Option<String>>("provider")? { provider = p; }
+                if let Some(m) = opts.get::<Option<String>>("model")? { model = m; }
+                if let Some(s) = opts.get::<Option<String>>("system_prompt")? { system_prompt = s; }
+                if let Some(t) = opts.get::<Option<bool>>("tools")? { with_tools = t; }
+            }
+            let client = o.clients.get(&provider).cloned()
+                .ok_or_else(|| mlua::Error::runtime(format!("Provider '{}' not initialized", provider)))?;
+            let tools = if with_tools { o.tools.tool_definitions() } else { Vec::new() };
+            let messages = messages.unwrap_or_else(|| o.history.clone());
+            o.check_limits(&provider, &model, &system_prompt, &messages, &tools)?;
+            Ok((client, provider, model, system_prompt, messages, tools))
+        })?;
+
+        let result = block_on(async {
+            let mut stream = client.stream(&model, &system_prompt, &messages, &tools, &InferenceOptions::default()).await?;
+            let mut text = String::new();
+            let mut tool_calls = Vec::new();
+            let (mut input_tokens, mut output_tokens) = (0, 0);
+            let mut stop_reason = None;
+            while let Some(event) = stream.next().await {
+                match event? {
+                    KernelEvent::MessageDelta { content_delta } => text.push_str(&content_delta),
+                    KernelEvent::ToolCall { id, name, args } => {
+                        tool_calls.push(serde_json::json!({ "id": id, "name": name, "args": args }));
+                    }
+                    KernelEvent::MessageEnd { input_tokens: i, output_tokens: o, stop_reason: reason, .. } => {
+                        input_tokens += i;
+                        output_tokens += o;
+                        stop_reason = reason;
+                    }
+                    _ => {}
+                }
+            }
+            anyhow::Ok((text, tool_calls, input_tokens, output_tokens, stop_reason))
+        });
+        let (text, tool_calls, input_tokens, output_tokens, stop_reason) =
+            result.map_err(|e| mlua::Error::runtime(format!("call_model failed: {}", e)))?;
+
+        with_active(lua, |o| {
+            let cost = pricing::lookup(&o.limits.pricing, &provider, &model).map(|p| p.cost(input_tokens, output_tokens));
+            o.spent_usd += cost.unwrap_or(0.0);
+            o.usage.push(ModelUsage { provider, model, input_tokens, output_tokens });
+            Ok(())
+        })?;
+        lua.to_value(&serde_json::json!({
+            "text": text,
+            "tool_calls": tool_calls,
+            "input_tokens": input_tokens,
+            "output_tokens": output_tokens,
+            "stop_reason": stop_reason,
+        }))
+    })?)?;
+
+    // bedrock.run_tool(name, args, id?) -> { content, is_error }
+    bedrock_table.set("run_tool", lua.create_function(|lua, (name, args, id): (String, Value, Option<String>)| {
+        let args: serde_json::Value = match args {
+            Value::Nil => serde_json::json!({}),
+            v => lua.from_value(v)?,
+        };
+        let (tools, tool_ctx, store, session_id, turn_index, id) = with_active(lua, |o| {
+            let id = id.unwrap_or_else(|| format!("orch_{}_{}", o.turn_index, o.tool_calls));
+            o.tool_calls += 1;
+            Ok((o.tools.clone(), o.tool_ctx.clone(), o.state.clone(), o.session_id.clone(), o.turn_index, id))
+        })?;
+
+        let start = Instant::now();
+        let (content, is_error) = match block_on(tools.execute(&name, args.clone(), &tool_ctx)) {
+            Ok(output) => (output.content, false),
+            Err(e) => (format!("Tool error: {}", e), true),
+        };
+        if let Some(store) = store {
+            let duration_ms = start.elapsed().as_millis() as u64;
+            let _ = block_on(store.insert_tool_execution(&session_id, turn_index, &id, &name, &args, Some(&content), is_error, Some(duration_ms), "orchestrated"));
+        }
+        lua.to_value(&serde_json::json!({ "id": id, "content": content, "is_error": is_error }))
+    })?)?;
+
+    Ok(())
+}
@@ -0,0 +1,136 @@
+//! The `on_before_request` hook's view of an outgoing provider request.
+//!
+//! The hook runs right before every provider call with the exact messages
+//! about to be sent (after `on_before_inference`, trimming and continuation
+//! stitching); a retry of the same request resends its result. Edits only
+//! change what this request sends; the session history (and, through a
+//! snapshot, the stored session) is left alone unless the script calls
+//! `req:persist()`.
+
+use std::sync::{Arc, Mutex};
+
+use mlua::{LuaSerdeExt, MetaMethod, UserData, UserDataMethods, Value};
+
+use crate::inference::provider::InferenceMessage;
+
+/// The request as edited by the hook.
+#[derive(Clone, Debug)]
+pub struct RequestState {
+    pub messages: Vec<InferenceMessage>,
+    /// The messages were changed by the hook
+    pub modified: bool,
+    /// The hook asked for the changes to replace the session history
+    pub persist: bool,
+}
+
+/// UserData handed to `on_before_request(req)`.
+#[derive(Clone)]
+pub struct RequestWrapper {
+    pub state: Arc<Mutex<RequestState>>,
+    model: String,
+    provider: String,
+    turn_index: u32,
+}
+
+impl RequestWrapper {
+    pub fn new(messages: Vec<InferenceMessage>, model: String, provider: String, turn_index: u32) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(RequestState { messages, modified: false, persist: false })),
+            model,
+            provider,
+            turn_index,
+        }
+    }
+
+    /// Retrieve the edited request (cloning the data out)
+    pub fn get_state(&self) -> RequestState {
+        self.state.lock().unwrap().clone()
+    }
+
+    fn edit(&self, f: impl FnOnce(&mut Vec<InferenceMessage>)) {
+        let mut state = self.state.lock().unwrap();
+        f(&mut state.messages);
+        state.modified = true;
+    }
+}
+
+impl UserData for RequestWrapper {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        // A copy; edit through the methods below
+        methods.add_method("get_messages", |lua, this, ()| {
+            lua.to_value(&this.state.lock().unwrap().messages).map_err(mlua::Error::external)
+        });
+
+        methods.add_method("append", |lua, this, val: Value| {
+            let msg: InferenceMessage = lua.from_value(val).map_err(mlua::Error::external)?;
+            this.edit(|messages| messages.push(msg));
+            Ok(())
+        });
+
+        methods.add_method("prepend", |lua, this, val: Value| {
+            let msg: InferenceMessage = lua.from_value(val).map_err(mlua::Error::external)?;
+            this.edit(|messages| messages.insert(0, msg));
+            Ok(())
+        });
+
+        methods.add_method("set_messages", |lua, this, val: Value| {
+            let msgs: Vec<InferenceMessage> = lua.from_value(val).map_err(mlua::Error::external)?;
+            this.edit(|messages| *messages = msgs);
+            Ok(())
+        });
+
+        // Keep the edits in the session history for later turns too
+        methods.add_method("persist", |_, this, ()| {
+            this.state.lock().unwrap().persist = true;
+            Ok(())
+        });
+
+        methods.add_meta_method(MetaMethod::Index, |lua, this, key: String| {
+            match key.as_str() {
+                "messages" => lua.to_value(&this.state.lock().unwrap().messages).map_err(mlua::Error::external),
+                "model" => Ok(Value::String(lua.create_string(&this.model)?)),
+                "provider" => Ok(Value::String(lua.create_string(&this.provider)?)),
+                "turn_index" => Ok(Value::Integer(this.turn_index as i64)),
+                _ => Ok(Value::Nil),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inference::provider::{InferenceContent, InferenceRole};
+    use mlua::Lua;
+
+    fn text(role: InferenceRole, text: &str) -> InferenceMessage {
+        InferenceMessage { role, content: vec![InferenceContent::Text { text: text.to_string() }], tool_call_id: None }
+    }
+
+    #[test]
+    fn test_request_edits() {
+        let lua = Lua::new();
+        let req = RequestWrapper::new(vec![text(InferenceRole::User, "hi")], "m".to_string(), "p".to_string(), 3);
+        lua.globals().set("req", req.clone()).unwrap();
+
+        // Reading doesn't count as a change
+        let count: i64 = lua.load("return #req.messages + req.turn_index").eval().unwrap();
+        assert_eq!(count, 4);
+        assert!(!req.get_state().modified);
+
+        lua.load(r#"
+            local reminder = req.messages[1]
+            reminder.content[1].text = "Reply in French."
+            req:prepend(reminder)
+            req.messages[1].content[1].text = "ignored: a copy"
+        "#).exec().unwrap();
+        let state = req.get_state();
+        assert!(state.modified && !state.persist);
+        assert_eq!(state.messages.len(), 2);
+        assert!(matches!(&state.messages[0].content[0], InferenceContent::Text { text } if text == "Reply in French."));
+
+        lua.load("req:set_messages({}); req:persist()").exec().unwrap();
+        let state = req.get_state();
+        assert!(state.messages.is_empty() && state.persist);
+    }
+}
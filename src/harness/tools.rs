@@ -0,0 +1,289 @@
+//! Tools defined by harness scripts (`bedrock.register_tool`).
+//!
+//! A script registers a tool while it loads:
+//!
+//! ```lua
+//! bedrock.register_tool({
+//!     name = "word_count",
+//!     description = "Count the words in a text",
+//!     parameters = { type = "object", properties = { text = { type = "string" } }, required = { "text" } },
+//!     effect = "read_only",
+//!     handler = function(args, ctx)
+//!         local _, count = string.gsub(args.text, "%S+", "")
+//!         return tostring(count)
+//!     end,
+//! })
+//! ```
+//!
+//! Once the scripts have loaded, the kernel registers each one as a
+//! [`ToolSource::Harness`](crate::tools::registry::ToolSource) tool, offered
+//! to the model like any other. A call runs the handler in the harness VM
+//! with the arguments and `ctx` (`session_id`, `workspace_root`). A string
+//! result is the tool's output and other values are encoded as JSON; a Lua
+//! error fails the call with its message. A handler still running when the
+//! call's timeout runs out is stopped by a VM interrupt.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use async_trait::async_trait;
+use mlua::{Function, Lua, LuaSerdeExt, Result as LuaResult, Table, Value, VmState};
+use serde_json::json;
+
+use crate::tools::{Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
+
+/// A tool as registered by a script.
+#[derive(Clone)]
+pub struct HarnessToolDef {
+    pub name: String,
+    pub description: String,
+    /// JSON Schema for the tool's parameters
+    pub parameters: serde_json::Value,
+    pub effect: ToolEffect,
+    pub handler: Function,
+}
+
+/// Tools registered so far in a harness VM (Lua app data).
+#[derive(Default)]
+pub struct RegisteredTools(pub Vec<HarnessToolDef>);
+
+/// Handlers of the current harness's tools by name, with the VM they run in.
+///
+/// Shared between the kernel's [`HarnessTool`]s and harness reloads, so a
+/// reloaded script's handler takes over without re-registering the tool.
+pub type ToolHandlers = Arc<RwLock<HashMap<String, (Lua, Function)>>>;
+
+/// Deadlines of the tool handlers running in a VM, innermost last (Lua app
+/// data). Handlers nest when one calls another through `bedrock.run_tool`.
+#[derive(Default)]
+struct HandlerDeadlines(Vec<Instant>);
+
+/// Run `handler` with `args`, stopping it with an error once `deadline`
+/// passes. Runs inline, so the handler blocks the calling thread until then.
+fn call_with_deadline(lua: &Lua, handler: &Function, args: (Value, Value), deadline: Option<Instant>) -> LuaResult<Value> {
+    let Some(deadline) = deadline else {
+        return handler.call(args);
+    };
+    if lua.app_data_ref::<HandlerDeadlines>().is_none() {
+        lua.set_app_data(HandlerDeadlines::default());
+        lua.set_interrupt(|lua| {
+            let expired = lua.app_data_ref::<HandlerDeadlines>().is_some_and(|d| d.0.iter().any(|at| Instant::now() >= *at));
+            if expired {
+                return Err(mlua::Error::runtime("stopped at the tool call's timeout"));
+            }
+            Ok(VmState::Continue)
+        });
+    }
+    if let Some(mut deadlines) = lua.app_data_mut::<HandlerDeadlines>() {
+        deadlines.0.push(deadline);
+    }
+    let result = handler.call(args);
+    if let Some(mut deadlines) = lua.app_data_mut::<HandlerDeadlines>() {
+        deadlines.0.pop();
+    }
+    result
+}
+
+/// `bedrock.register_tool(def)`: validate `def` and add it to [`RegisteredTools`].
+pub fn register_tool(lua: &Lua, def: Table) -> LuaResult<()> {
+    let name: String = def.get("name").map_err(|_| mlua::Error::runtime("register_tool: 'name' must be a string"))?;
+    let valid_name = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid_name {
+        return Err(mlua::Error::runtime(format!(
+            "register_tool: invalid name '{}' (use 1-64 letters, digits, '_' or '-')",
+            name
+        )));
+    }
+    let description: String = def
+        .get("description")
+        .map_err(|_| mlua::Error::runtime(format!("register_tool '{}': 'description' must be a string", name)))?;
+    let handler: Function = def
+        .get("handler")
+        .map_err(|_| mlua::Error::runtime(format!("register_tool '{}': 'handler' must be a function", name)))?;
+    let parameters = match def.get::<Value>("parameters")? {
+        Value::Nil => json!({ "type": "object", "properties": {} }),
+        value => lua.from_value::<serde_json::Value>(value)?,
+    };
+    if !parameters.is_object() {
+        return Err(mlua::Error::runtime(format!("register_tool '{}': 'parameters' must be a JSON Schema table", name)));
+    }
+    let effect = match def.get::<Option<String>>("effect")? {
+        None => ToolEffect::Unknown,
+        Some(effect) => serde_json::from_value(json!(effect)).map_err(|_| {
+            mlua::Error::runtime(format!(
+                "register_tool '{}': unknown effect '{}' (read_only, write, execute or unknown)",
+                name, effect
+            ))
+        })?,
+    };
+
+    let mut registered = lua
+        .app_data_mut::<RegisteredTools>()
+        .ok_or_else(|| mlua::Error::runtime("register_tool unavailable: harness not initialized"))?;
+    if registered.0.iter().any(|tool| tool.name == name) {
+        return Err(mlua::Error::runtime(format!("register_tool: '{}' is already registered", name)));
+    }
+    registered.0.push(HarnessToolDef { name, description, parameters, effect, handler });
+    Ok(())
+}
+
+/// A harness-defined tool, as the kernel registers it.
+pub struct HarnessTool {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+    effect: ToolEffect,
+    handlers: ToolHandlers,
+}
+
+impl HarnessTool {
+    pub fn new(def: &HarnessToolDef, handlers: ToolHandlers) -> Self {
+        Self {
+            name: def.name.clone(),
+            description: def.description.clone(),
+            parameters: def.parameters.clone(),
+            effect: def.effect,
+            handlers,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for HarnessTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn parameters_schema(&self) -> serde_json::Value {
+        self.parameters.clone()
+    }
+
+    fn effect(&self) -> ToolEffect {
+        self.effect
+    }
+
+    #[tracing::instrument(skip(self, params, ctx), fields(tool = %self.name))]
+    async fn execute(&self, params: serde_json::Value, ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
+        let handler = self.handlers.read().unwrap_or_else(|e| e.into_inner()).get(&self.name).cloned();
+        let Some((lua, handler)) = handler else {
+            return Err(ToolError::ExecutionError(format!("Harness tool '{}' is no longer defined", self.name)));
+        };
+        // Run inline rather than on a blocking thread: `bedrock.run_tool` calls
+        // tools while its script holds the VM, and the VM lock is only reentrant
+        // on the same thread
+        let failed = |e: mlua::Error| ToolError::ExecutionError(format!("Harness tool '{}' failed: {}", self.name, e));
+        let args = lua.to_value(&params).map_err(failed)?;
+        let lua_ctx = lua
+            .to_value(&json!({
+                "session_id": ctx.session_id,
+                "workspace_root": ctx.workspace_root.to_string_lossy(),
+            }))
+            .map_err(failed)?;
+        let content = match call_with_deadline(&lua, &handler, (args, lua_ctx), ctx.deadline).map_err(failed)? {
+            Value::Nil => String::new(),
+            Value::String(s) => s.to_str().map_err(failed)?.to_string(),
+            other => lua.from_value::<serde_json::Value>(other).map_err(failed)?.to_string(),
+        };
+        Ok(ToolOutput::new(content))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(script: &str) -> (Lua, Vec<HarnessToolDef>) {
+        let lua = Lua::new();
+        lua.set_app_data(RegisteredTools::default());
+        let bedrock = lua.create_table().unwrap();
+        bedrock.set("register_tool", lua.create_function(register_tool).unwrap()).unwrap();
+        lua.globals().set("bedrock", bedrock).unwrap();
+        lua.load(script).exec().unwrap();
+        let tools = lua.app_data_ref::<RegisteredTools>().unwrap().0.clone();
+        (lua, tools)
+    }
+
+    fn harness_tool(lua: &Lua, def: &HarnessToolDef) -> HarnessTool {
+        let handlers: ToolHandlers = Default::default();
+        handlers.write().unwrap().insert(def.name.clone(), (lua.clone(), def.handler.clone()));
+        HarnessTool::new(def, handlers)
+    }
+
+    #[tokio::test]
+    async fn test_harness_tool() {
+        let (lua, tools) = load(r#"
+            bedrock.register_tool({
+                name = "greet",
+                description = "Greet someone",
+                parameters = { type = "object", properties = { who = { type = "string" } } },
+                effect = "read_only",
+                handler = function(args, ctx) return "hello " .. args.who .. " from " .. ctx.session_id end,
+            })
+            bedrock.register_tool({
+                name = "stats",
+                description = "Numbers",
+                handler = function(args) return { count = 2 } end,
+            })
+            bedrock.register_tool({
+                name = "fail",
+                description = "Always fails",
+                handler = function() error("no luck") end,
+            })
+        "#);
+        assert_eq!(tools.len(), 3);
+        assert_eq!(tools[0].effect, ToolEffect::ReadOnly);
+        assert_eq!(tools[1].parameters, json!({ "type": "object", "properties": {} }));
+
+        let ctx = ToolContext { session_id: "s1".to_string(), ..Default::default() };
+        let greet = harness_tool(&lua, &tools[0]);
+        assert_eq!(greet.execute(json!({ "who": "ada" }), &ctx).await.unwrap().content, "hello ada from s1");
+        let stats = harness_tool(&lua, &tools[1]);
+        assert_eq!(stats.execute(json!({}), &ctx).await.unwrap().content, r#"{"count":2}"#);
+        let err = harness_tool(&lua, &tools[2]).execute(json!({}), &ctx).await.unwrap_err().to_string();
+        assert!(err.contains("Harness tool 'fail' failed") && err.contains("no luck"), "{}", err);
+
+        // A handler dropped by a reload fails the call
+        let orphan = HarnessTool::new(&tools[0], Default::default());
+        assert!(orphan.execute(json!({}), &ctx).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_harness_tool_stops_at_deadline() {
+        let (lua, tools) = load(r#"
+            bedrock.register_tool({
+                name = "spin",
+                description = "Never returns",
+                handler = function() while true do end end,
+            })
+        "#);
+        let spin = harness_tool(&lua, &tools[0]);
+        let ctx = ToolContext { deadline: Some(Instant::now() + std::time::Duration::from_millis(100)), ..Default::default() };
+        let err = spin.execute(json!({}), &ctx).await.unwrap_err().to_string();
+        assert!(err.contains("stopped at the tool call's timeout"), "{}", err);
+
+        // The VM is usable afterwards, with or without a deadline
+        assert_eq!(lua.load("return 1 + 1").eval::<i32>().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_register_tool_validation() {
+        let lua = Lua::new();
+        lua.set_app_data(RegisteredTools::default());
+        lua.globals().set("register_tool", lua.create_function(register_tool).unwrap()).unwrap();
+        let error = |script: &str| lua.load(script).exec().unwrap_err().to_string();
+
+        assert!(error(r#"register_tool({ name = "bad name", description = "x", handler = print })"#).contains("invalid name"));
+        assert!(error(r#"register_tool({ name = "t", handler = print })"#).contains("'description' must be a string"));
+        assert!(error(r#"register_tool({ name = "t", description = "x" })"#).contains("'handler' must be a function"));
+        assert!(error(r#"register_tool({ name = "t", description = "x", effect = "delete", handler = print })"#).contains("unknown effect"));
+        lua.load(r#"register_tool({ name = "t", description = "x", handler = print })"#).exec().unwrap();
+        assert!(error(r#"register_tool({ name = "t", description = "x", handler = print })"#).contains("already registered"));
+    }
+}
@@ -0,0 +1,216 @@
+//! Pluggable provider authentication.
+//!
+//! An [`AuthScheme`] produces the credential a provider client authenticates
+//! with. [`ProviderAuth`] caches that credential, refreshes it shortly before
+//! it expires, and rebuilds the underlying SDK client whenever it changes —
+//! the SDK clients take their key at construction time.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::inference::provider::InferenceProvider;
+use crate::kernel::config::AuthConfig;
+
+/// Refresh credentials this long before they expire.
+const REFRESH_MARGIN: Duration = Duration::from_secs(30);
+
+/// A credential plus its expiry (`None` = never expires).
+#[derive(Debug, Clone)]
+pub struct Credential {
+    pub secret: String,
+    pub expires_at: Option<Instant>,
+}
+
+impl Credential {
+    fn is_fresh(&self) -> bool {
+        match self.expires_at {
+            Some(at) => Instant::now() + REFRESH_MARGIN < at,
+            None => true,
+        }
+    }
+}
+
+/// A way of obtaining provider credentials.
+#[async_trait]
+pub trait AuthScheme: Send + Sync {
+    /// Fetch a new credential. Called on first use and whenever the cached one expires.
+    async fn fetch(&self) -> Result<Credential>;
+}
+
+/// Static key read from an environment variable.
+pub struct EnvAuth {
+    pub var: String,
+}
+
+#[async_trait]
+impl AuthScheme for EnvAuth {
+    async fn fetch(&self) -> Result<Credential> {
+        let secret = std::env::var(&self.var)
+            .with_context(|| format!("Environment variable '{}' not set", self.var))?;
+        Ok(Credential { secret, expires_at: None })
+    }
+}
+
+/// Short-lived token printed to stdout by an external command
+/// (e.g. `gcloud auth print-access-token`, an OAuth helper script).
+pub struct CommandAuth {
+    pub command: String,
+    pub args: Vec<String>,
+    pub ttl: Duration,
+}
+
+#[async_trait]
+impl AuthScheme for CommandAuth {
+    async fn fetch(&self) -> Result<Credential> {
+        let output = tokio::process::Command::new(&self.command)
+            .args(&self.args)
+            .output()
+            .await
+            .with_context(|| format!("Failed to run auth command: {}", self.command))?;
+        anyhow::ensure!(
+            output.status.success(),
+            "Auth command '{}' failed: {}",
+            self.command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        let secret = String::from_utf8(output.stdout)
+            .context("Auth command printed non-UTF-8 output")?
+            .trim()
+            .to_string();
+        anyhow::ensure!(!secret.is_empty(), "Auth command '{}' printed no token", self.command);
+        Ok(Credential { secret, expires_at: Some(Instant::now() + self.ttl) })
+    }
+}
+
+/// Build the scheme selected by a provider's `auth` config.
+pub fn scheme_from_config(config: &AuthConfig) -> Arc<dyn AuthScheme> {
+    match config {
+        AuthConfig::Env { var } => Arc::new(EnvAuth { var: var.clone() }),
+        AuthConfig::Command { command, args, ttl_secs } => Arc::new(CommandAuth {
+            command: command.clone(),
+            args: args.clone(),
+            ttl: Duration::from_secs(*ttl_secs),
+        }),
+    }
+}
+
+type ClientFactory = Box<dyn Fn(&str) -> Result<Arc<dyn InferenceProvider>> + Send + Sync>;
+
+/// Caches a credential and the SDK client built from it.
+pub struct ProviderAuth {
+    scheme: Arc<dyn AuthScheme>,
+    factory: ClientFactory,
+    state: Mutex<Option<(Credential, Arc<dyn InferenceProvider>)>>,
+}
+
+impl ProviderAuth {
+    /// `factory` builds an SDK client from a credential secret.
+    pub fn new(
+        scheme: Arc<dyn AuthScheme>,
+        factory: impl Fn(&str) -> Result<Arc<dyn InferenceProvider>> + Send + Sync + 'static,
+    ) -> Self {
+        Self { scheme, factory: Box::new(factory), state: Mutex::new(None) }
+    }
+
+    /// Return a client with a fresh credential, refreshing and rebuilding if needed.
+    pub async fn provider(&self) -> Result<Arc<dyn InferenceProvider>> {
+        let mut state = self.state.lock().await;
+        if let Some((cred, provider)) = state.as_ref() {
+            if cred.is_fresh() {
+                return Ok(provider.clone());
+            }
+        }
+
+        let cred = self.scheme.fetch().await.context("Failed to obtain provider credentials")?;
+        let provider = match state.as_ref() {
+            // Unchanged secret (e.g. refreshed static key): keep the existing client
+            Some((old, provider)) if old.secret == cred.secret => provider.clone(),
+            _ => (self.factory)(&cred.secret)?,
+        };
+        *state = Some((cred, provider.clone()));
+        Ok(provider)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inference::provider::{InferenceRequest, InferenceStream, RequestOptions, SdkError};
+    use futures::future::BoxFuture;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingScheme {
+        fetches: AtomicUsize,
+        ttl: Option<Duration>,
+    }
+
+    #[async_trait]
+    impl AuthScheme for CountingScheme {
+        async fn fetch(&self) -> Result<Credential> {
+            let n = self.fetches.fetch_add(1, Ordering::SeqCst);
+            Ok(Credential {
+                secret: format!("token-{}", n),
+                expires_at: self.ttl.map(|t| Instant::now() + t),
+            })
+        }
+    }
+
+    struct NullProvider;
+
+    impl InferenceProvider for NullProvider {
+        fn stream<'a>(&'a self, _request: InferenceRequest, _options: Option<RequestOptions>) -> BoxFuture<'a, std::result::Result<InferenceStream, SdkError>> {
+            Box::pin(async { Ok(Box::pin(futures::stream::empty()) as InferenceStream) })
+        }
+    }
+
+    fn counting_auth(ttl: Option<Duration>) -> (Arc<CountingScheme>, Arc<AtomicUsize>, ProviderAuth) {
+        let scheme = Arc::new(CountingScheme { fetches: AtomicUsize::new(0), ttl });
+        let builds = Arc::new(AtomicUsize::new(0));
+        let builds_clone = builds.clone();
+        let auth = ProviderAuth::new(scheme.clone(), move |_secret| {
+            builds_clone.fetch_add(1, Ordering::SeqCst);
+            Ok(Arc::new(NullProvider) as Arc<dyn InferenceProvider>)
+        });
+        (scheme, builds, auth)
+    }
+
+    #[tokio::test]
+    async fn test_credential_cached_until_expiry() {
+        let (scheme, builds, auth) = counting_auth(Some(Duration::from_secs(3600)));
+        auth.provider().await.unwrap();
+        auth.provider().await.unwrap();
+        assert_eq!(scheme.fetches.load(Ordering::SeqCst), 1);
+        assert_eq!(builds.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_expiring_credential_refreshed_and_client_rebuilt() {
+        // A TTL inside the refresh margin is always considered stale
+        let (scheme, builds, auth) = counting_auth(Some(Duration::from_secs(1)));
+        auth.provider().await.unwrap();
+        auth.provider().await.unwrap();
+        assert_eq!(scheme.fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(builds.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_command_auth_trims_output() {
+        let scheme = CommandAuth {
+            command: "echo".to_string(),
+            args: vec!["  abc123  ".to_string()],
+            ttl: Duration::from_secs(60),
+        };
+        let cred = scheme.fetch().await.unwrap();
+        assert_eq!(cred.secret, "abc123");
+        assert!(cred.expires_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_env_auth_missing_var() {
+        let scheme = EnvAuth { var: "BEDROCK_TEST_AUTH_UNSET_VAR".to_string() };
+        assert!(scheme.fetch().await.is_err());
+    }
+}
@@ -0,0 +1,237 @@
+//! Provider keys kept outside the environment and `bedrock.toml`.
+//!
+//! With `[credentials] store = "file"`, a provider with no `auth` table whose
+//! `api_key_env` variable is unset reads its key from a TOML file
+//! (`~/.bedrock/credentials.toml` by default), one table per provider name:
+//!
+//! ```toml
+//! [anthropic]
+//! api_key = "sk-ant-..."
+//! ```
+//!
+//! On Unix the file must not be readable or writable by group or others.
+//! With `store = "keychain"` (macOS only) the key is the password of the
+//! generic keychain item with service `bedrock` and the provider name as
+//! account, e.g. `security add-generic-password -s bedrock -a anthropic -w`.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::inference::auth::{scheme_from_config, AuthScheme, Credential};
+use crate::kernel::config::{AuthConfig, CredentialStore, CredentialsConfig, ProviderConfig};
+
+/// Keychain service name bedrock's items are stored under.
+pub const KEYCHAIN_SERVICE: &str = "bedrock";
+
+/// Where a provider's credential comes from, as reported by `validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CredentialSource {
+    /// The provider's `auth` table
+    Auth(AuthConfig),
+    /// The `api_key_env` variable
+    Env(String),
+    /// The credentials file
+    File(PathBuf),
+    /// The macOS keychain
+    Keychain,
+    /// Nowhere; requests will fail
+    Missing,
+}
+
+impl std::fmt::Display for CredentialSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CredentialSource::Auth(AuthConfig::Env { var }) => write!(f, "auth env {}", var),
+            CredentialSource::Auth(AuthConfig::Command { command, .. }) => write!(f, "auth command {}", command),
+            CredentialSource::Env(var) => write!(f, "env {}", var),
+            CredentialSource::File(path) => write!(f, "credentials file {}", path.display()),
+            CredentialSource::Keychain => write!(f, "keychain (service {})", KEYCHAIN_SERVICE),
+            CredentialSource::Missing => write!(f, "none found"),
+        }
+    }
+}
+
+/// Key for `provider` in the credentials file at `path`.
+pub struct FileAuth {
+    pub path: PathBuf,
+    pub provider: String,
+}
+
+#[async_trait]
+impl AuthScheme for FileAuth {
+    async fn fetch(&self) -> Result<Credential> {
+        let secret = read_key(&self.path, &self.provider)?
+            .with_context(|| format!("No api_key for '{}' in {}", self.provider, self.path.display()))?;
+        Ok(Credential { secret, expires_at: None })
+    }
+}
+
+/// Key for `provider` in the macOS keychain.
+pub struct KeychainAuth {
+    pub provider: String,
+}
+
+#[async_trait]
+impl AuthScheme for KeychainAuth {
+    async fn fetch(&self) -> Result<Credential> {
+        anyhow::ensure!(cfg!(target_os = "macos"), "Keychain credentials are only supported on macOS");
+        let output = tokio::process::Command::new("security")
+            .args(["find-generic-password", "-s", KEYCHAIN_SERVICE, "-a", &self.provider, "-w"])
+            .output()
+            .await
+            .context("Failed to run the `security` keychain tool")?;
+        anyhow::ensure!(
+            output.status.success(),
+            "No keychain item for service '{}', account '{}'",
+            KEYCHAIN_SERVICE,
+            self.provider
+        );
+        let secret = String::from_utf8(output.stdout).context("Keychain item is not UTF-8")?.trim().to_string();
+        anyhow::ensure!(!secret.is_empty(), "Keychain item for '{}' is empty", self.provider);
+        Ok(Credential { secret, expires_at: None })
+    }
+}
+
+/// The credentials file path with a leading `~/` expanded.
+pub fn file_path(config: &CredentialsConfig) -> PathBuf {
+    match (config.path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(&config.path),
+    }
+}
+
+/// `provider`'s `api_key` from the credentials file, or `None` if the file or
+/// the entry doesn't exist. Fails if the file is readable by others.
+pub fn read_key(path: &Path, provider: &str) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    check_permissions(path)?;
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let table: toml::Table = toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(table
+        .get(provider)
+        .and_then(|entry| entry.get("api_key"))
+        .and_then(|key| key.as_str())
+        .map(str::to_string))
+}
+
+/// Refuse a credentials file that group or others can access.
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(path)?.permissions().mode();
+    anyhow::ensure!(
+        mode & 0o077 == 0,
+        "{} is accessible by other users (mode {:o}); run `chmod 600 {}`",
+        path.display(),
+        mode & 0o777,
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// The scheme a provider authenticates with, if it needs one: its `auth`
+/// table, or else the credential store when `api_key_env` is unset. `None`
+/// means the plain `api_key_env` key is used.
+pub fn scheme_for(name: &str, provider: &ProviderConfig, credentials: &CredentialsConfig) -> Option<Arc<dyn AuthScheme>> {
+    if let Some(auth) = &provider.auth {
+        return Some(scheme_from_config(auth));
+    }
+    if env_key(provider).is_some() {
+        return None;
+    }
+    match credentials.store {
+        CredentialStore::None => None,
+        CredentialStore::File => Some(Arc::new(FileAuth { path: file_path(credentials), provider: name.to_string() })),
+        CredentialStore::Keychain => Some(Arc::new(KeychainAuth { provider: name.to_string() })),
+    }
+}
+
+/// Where `name`'s credential would come from. Looks the key up in the
+/// credential store but doesn't run `auth` commands.
+pub async fn source_for(name: &str, provider: &ProviderConfig, credentials: &CredentialsConfig) -> Result<CredentialSource> {
+    if let Some(auth) = &provider.auth {
+        return Ok(CredentialSource::Auth(auth.clone()));
+    }
+    if let Some(var) = env_key(provider) {
+        return Ok(CredentialSource::Env(var.to_string()));
+    }
+    Ok(match credentials.store {
+        CredentialStore::None => CredentialSource::Missing,
+        CredentialStore::File => {
+            let path = file_path(credentials);
+            match read_key(&path, name)? {
+                Some(_) => CredentialSource::File(path),
+                None => CredentialSource::Missing,
+            }
+        }
+        CredentialStore::Keychain => match (KeychainAuth { provider: name.to_string() }).fetch().await {
+            Ok(_) => CredentialSource::Keychain,
+            Err(_) => CredentialSource::Missing,
+        },
+    })
+}
+
+/// The `api_key_env` variable, if it is set and non-empty.
+fn env_key(provider: &ProviderConfig) -> Option<&str> {
+    provider
+        .api_key_env
+        .as_deref()
+        .filter(|var| std::env::var(var).is_ok_and(|v| !v.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(api_key_env: Option<&str>) -> ProviderConfig {
+        ProviderConfig {
+            kind: "openai".to_string(),
+            api_key_env: api_key_env.map(str::to_string),
+            base_url: None,
+            auth: None,
+            tool_results: Default::default(),
+            extra_body: None,
+            strip_thinking: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_credentials_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.toml");
+        std::fs::write(&path, "[work]\napi_key = \"sk-file\"\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+            let err = read_key(&path, "work").unwrap_err();
+            assert!(err.to_string().contains("chmod 600"));
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        assert_eq!(read_key(&path, "work").unwrap().as_deref(), Some("sk-file"));
+        assert_eq!(read_key(&path, "other").unwrap(), None);
+        assert_eq!(read_key(&dir.path().join("missing.toml"), "work").unwrap(), None);
+
+        let credentials = CredentialsConfig { store: CredentialStore::File, path: path.to_string_lossy().into_owned() };
+        // Only consulted when the env key is absent
+        let unset = provider(Some("BEDROCK_TEST_CREDENTIALS_UNSET"));
+        let secret = scheme_for("work", &unset, &credentials).unwrap().fetch().await.unwrap().secret;
+        assert_eq!(secret, "sk-file");
+        assert_eq!(source_for("work", &unset, &credentials).await.unwrap(), CredentialSource::File(path.clone()));
+        assert_eq!(source_for("other", &unset, &credentials).await.unwrap(), CredentialSource::Missing);
+
+        let set = provider(Some("PATH"));
+        assert!(scheme_for("work", &set, &credentials).is_none());
+        assert_eq!(source_for("work", &set, &credentials).await.unwrap(), CredentialSource::Env("PATH".to_string()));
+        assert!(scheme_for("work", &unset, &CredentialsConfig::default()).is_none());
+    }
+}
@@ -19,6 +19,14 @@ pub trait EmbeddingProvider: Send + Sync {
     /// Generate an embedding for the given text.
     async fn embed(&self, text: &str) -> Result<Embedding>;
 
+    /// Vector dimension produced by this provider.
+    ///
+    /// The default embeds a probe string; providers that know their
+    /// dimension up front should override this.
+    async fn dimensions(&self) -> Result<usize> {
+        Ok(self.embed("dimension probe").await?.vector.len())
+    }
+
     /// Generate embeddings for a batch of texts.
     async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Embedding>> {
         let mut embeddings = Vec::new();
@@ -76,6 +84,14 @@ impl EmbeddingProvider for OpenAIEmbeddingProvider {
             anyhow::bail!("No embedding data returned from OpenAI")
         }
     }
+
+    async fn dimensions(&self) -> Result<usize> {
+        match self.model.as_str() {
+            "text-embedding-3-small" | "text-embedding-ada-002" => Ok(1536),
+            "text-embedding-3-large" => Ok(3072),
+            _ => Ok(self.embed("dimension probe").await?.vector.len()),
+        }
+    }
 }
 
 /// No-op provider for testing or disabled embeddings.
@@ -90,6 +106,10 @@ impl EmbeddingProvider for NoOpEmbeddingProvider {
             model: "noop".to_string(),
         })
     }
+
+    async fn dimensions(&self) -> Result<usize> {
+        Ok(1536)
+    }
 }
 
 pub fn create_embedding_provider(config: &EmbeddingConfig) -> Box<dyn EmbeddingProvider> {
@@ -1,2 +1,6 @@
+pub mod auth;
+pub mod credentials;
 pub mod provider;
 pub mod embeddings;
+pub mod ollama;
+pub mod pricing;
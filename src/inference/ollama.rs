@@ -0,0 +1,281 @@
+//! Native Ollama backend (`type = "ollama"`), for fully local runs.
+//!
+//! Talks to the daemon's `/api/chat` endpoint, which streams one JSON object
+//! per line. Needs no API key; `base_url` defaults to the local daemon. Tool
+//! calls are passed through for models that support them, and Ollama's
+//! `prompt_eval_count` / `eval_count` become the input and output token
+//! counts of `MessageEnd`.
+
+use futures::future::BoxFuture;
+use futures::stream::{self, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::inference::provider::{
+    InferenceContent, InferenceEvent, InferenceProvider, InferenceRequest, InferenceRole, InferenceStream,
+    RequestOptions, SdkError,
+};
+
+/// Where a local Ollama daemon listens by default.
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+
+pub struct OllamaProvider {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl OllamaProvider {
+    /// A provider for the daemon at `base_url` (default [`DEFAULT_OLLAMA_URL`]).
+    pub fn new(base_url: Option<&str>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.unwrap_or(DEFAULT_OLLAMA_URL).trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl InferenceProvider for OllamaProvider {
+    fn stream<'a>(&'a self, request: InferenceRequest, _options: Option<RequestOptions>) -> BoxFuture<'a, Result<InferenceStream, SdkError>> {
+        Box::pin(async move {
+            let model = request.model.clone();
+            let response = self
+                .client
+                .post(format!("{}/api/chat", self.base_url))
+                .json(&chat_body(&request))
+                .send()
+                .await
+                .map_err(|e| SdkError::Api(format!("Ollama request failed: {}", e)))?;
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                return Err(SdkError::Api(format!("Ollama returned {}: {}", status, error_message(&body))));
+            }
+
+            let start = stream::iter([Ok(InferenceEvent::MessageStart {
+                role: "assistant".to_string(),
+                model,
+                provider_id: "ollama".to_string(),
+            })]);
+            let bytes = response.bytes_stream();
+            let lines = stream::unfold((bytes, Vec::new(), false), |(mut bytes, mut buf, done)| async move {
+                if done {
+                    return None;
+                }
+                loop {
+                    if let Some(pos) = buf.iter().position(|&b| b == b'\n') {
+                        let line: Vec<u8> = buf.drain(..=pos).collect();
+                        return Some((Ok(line), (bytes, buf, false)));
+                    }
+                    match bytes.next().await {
+                        Some(Ok(chunk)) => buf.extend_from_slice(&chunk),
+                        Some(Err(e)) => return Some((Err(SdkError::Api(format!("Ollama stream failed: {}", e))), (bytes, buf, true))),
+                        // A final line without a trailing newline
+                        None if !buf.is_empty() => return Some((Ok(std::mem::take(&mut buf)), (bytes, buf, true))),
+                        None => return None,
+                    }
+                }
+            });
+            let events = lines
+                .filter_map(|line| async move {
+                    match line {
+                        Ok(line) => {
+                            let line = String::from_utf8_lossy(&line);
+                            (!line.trim().is_empty()).then(|| chunk_events(line.trim()))
+                        }
+                        Err(e) => Some(vec![Err(e)]),
+                    }
+                })
+                .flat_map(stream::iter);
+            Ok(Box::pin(start.chain(events)) as InferenceStream)
+        })
+    }
+}
+
+/// The `/api/chat` request body for `request`.
+fn chat_body(request: &InferenceRequest) -> Value {
+    let mut messages = Vec::new();
+    if let Some(system) = request.system.as_deref().filter(|s| !s.is_empty()) {
+        messages.push(json!({ "role": "system", "content": system }));
+    }
+    // Ollama identifies tool results by tool name rather than call id
+    let mut tool_names: HashMap<&str, &str> = HashMap::new();
+    for message in &request.messages {
+        let mut text = String::new();
+        let mut tool_calls = Vec::new();
+        for content in &message.content {
+            match content {
+                InferenceContent::Text { text: t } => text.push_str(t),
+                InferenceContent::ToolUse { id, name, input } => {
+                    tool_names.insert(id, name);
+                    tool_calls.push(json!({ "function": { "name": name, "arguments": input } }));
+                }
+                InferenceContent::ToolResult { tool_use_id, content, .. } => {
+                    let mut result = json!({ "role": "tool", "content": content });
+                    if let Some(name) = tool_names.get(tool_use_id.as_str()) {
+                        result["tool_name"] = json!(name);
+                    }
+                    messages.push(result);
+                }
+                InferenceContent::Thinking { .. } => {}
+            }
+        }
+        if text.is_empty() && tool_calls.is_empty() {
+            continue;
+        }
+        let role = match message.role {
+            InferenceRole::Assistant => "assistant",
+            InferenceRole::System => "system",
+            InferenceRole::User | InferenceRole::Tool => "user",
+        };
+        let mut out = json!({ "role": role, "content": text });
+        if !tool_calls.is_empty() {
+            out["tool_calls"] = Value::Array(tool_calls);
+        }
+        messages.push(out);
+    }
+
+    let mut body = json!({ "model": request.model, "messages": messages, "stream": true });
+    if let Some(tools) = request.tools.as_ref().filter(|t| !t.is_empty()) {
+        let tools: Vec<Value> = tools
+            .iter()
+            .map(|t| json!({ "type": "function", "function": { "name": t.name, "description": t.description, "parameters": t.input_schema } }))
+            .collect();
+        body["tools"] = Value::Array(tools);
+    }
+    let mut options = serde_json::Map::new();
+    if let Some(temperature) = request.temperature {
+        options.insert("temperature".to_string(), json!(temperature));
+    }
+    if let Some(max_tokens) = request.max_tokens {
+        options.insert("num_predict".to_string(), json!(max_tokens));
+    }
+    if !options.is_empty() {
+        body["options"] = Value::Object(options);
+    }
+    if request.thinking_budget.is_some() {
+        body["think"] = json!(true);
+    }
+    if let Some(extra) = &request.extra_body {
+        for (key, value) in extra {
+            body[key] = value.clone();
+        }
+    }
+    body
+}
+
+/// Events for one streamed line of an `/api/chat` response.
+fn chunk_events(line: &str) -> Vec<Result<InferenceEvent, SdkError>> {
+    let chunk: Value = match serde_json::from_str(line) {
+        Ok(chunk) => chunk,
+        Err(e) => return vec![Err(SdkError::Api(format!("Malformed Ollama response line: {}", e)))],
+    };
+    if let Some(error) = chunk.get("error").and_then(|e| e.as_str()) {
+        return vec![Err(SdkError::Api(format!("Ollama error: {}", error)))];
+    }
+    let mut events = Vec::new();
+    let message = &chunk["message"];
+    if let Some(thinking) = message["thinking"].as_str().filter(|t| !t.is_empty()) {
+        events.push(Ok(InferenceEvent::ThinkingDelta { content: thinking.to_string() }));
+    }
+    if let Some(content) = message["content"].as_str().filter(|c| !c.is_empty()) {
+        events.push(Ok(InferenceEvent::MessageDelta { content: content.to_string() }));
+    }
+    for call in message["tool_calls"].as_array().into_iter().flatten() {
+        let id = call["id"]
+            .as_str()
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("call_{}", &uuid::Uuid::new_v4().simple().to_string()[..12]));
+        events.push(Ok(InferenceEvent::ToolCall {
+            id,
+            name: call["function"]["name"].as_str().unwrap_or_default().to_string(),
+            args: call["function"]["arguments"].clone(),
+        }));
+    }
+    if chunk["done"].as_bool() == Some(true) {
+        events.push(Ok(InferenceEvent::MessageEnd {
+            input_tokens: chunk["prompt_eval_count"].as_u64().unwrap_or(0) as u32,
+            output_tokens: chunk["eval_count"].as_u64().unwrap_or(0) as u32,
+            stop_reason: chunk["done_reason"].as_str().map(str::to_string),
+        }));
+    }
+    events
+}
+
+/// The `error` field of an Ollama error body, or the body itself.
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| v["error"].as_str().map(str::to_string))
+        .unwrap_or_else(|| body.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inference::provider::{InferenceMessage, Tool};
+
+    #[test]
+    fn test_chat_body() {
+        let request = InferenceRequest {
+            model: "llama3.1".to_string(),
+            system: Some("Be brief".to_string()),
+            messages: vec![
+                InferenceMessage {
+                    role: InferenceRole::User,
+                    content: vec![InferenceContent::Text { text: "read a.txt".to_string() }],
+                    tool_call_id: None,
+                },
+                InferenceMessage {
+                    role: InferenceRole::Assistant,
+                    content: vec![InferenceContent::ToolUse {
+                        id: "call_1".to_string(),
+                        name: "read_file".to_string(),
+                        input: json!({"path": "a.txt"}),
+                    }],
+                    tool_call_id: None,
+                },
+                InferenceMessage {
+                    role: InferenceRole::User,
+                    content: vec![InferenceContent::ToolResult { tool_use_id: "call_1".to_string(), content: "hi".to_string(), is_error: false }],
+                    tool_call_id: None,
+                },
+            ],
+            tools: Some(vec![Tool { name: "read_file".to_string(), description: "Read".to_string(), input_schema: json!({"type": "object"}) }]),
+            max_tokens: Some(256),
+            ..Default::default()
+        };
+        let body = chat_body(&request);
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[2]["tool_calls"][0]["function"]["arguments"]["path"], "a.txt");
+        assert_eq!(messages[3], json!({"role": "tool", "content": "hi", "tool_name": "read_file"}));
+        assert_eq!(body["tools"][0]["function"]["parameters"]["type"], "object");
+        assert_eq!(body["options"]["num_predict"], 256);
+        assert_eq!(body["stream"], true);
+    }
+
+    #[test]
+    fn test_chunk_events() {
+        let events = chunk_events(r#"{"message":{"role":"assistant","content":"Hel"},"done":false}"#);
+        assert!(matches!(&events[..], [Ok(InferenceEvent::MessageDelta { content })] if content == "Hel"));
+
+        let line = r#"{"message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"read_file","arguments":{"path":"a.txt"}}}]},"done":false}"#;
+        match &chunk_events(line)[..] {
+            [Ok(InferenceEvent::ToolCall { id, name, args })] => {
+                assert!(id.starts_with("call_"));
+                assert_eq!(name, "read_file");
+                assert_eq!(args["path"], "a.txt");
+            }
+            other => panic!("expected a tool call, got {:?}", other),
+        }
+
+        let end = chunk_events(r#"{"message":{"role":"assistant","content":""},"done":true,"done_reason":"stop","prompt_eval_count":42,"eval_count":7}"#);
+        assert!(matches!(
+            &end[..],
+            [Ok(InferenceEvent::MessageEnd { input_tokens: 42, output_tokens: 7, stop_reason: Some(r) })] if r == "stop"
+        ));
+
+        assert!(matches!(&chunk_events(r#"{"error":"model not found"}"#)[..], [Err(SdkError::Api(m))] if m.contains("model not found")));
+    }
+}
@@ -0,0 +1,102 @@
+//! Built-in per-model pricing used for cost accounting.
+//!
+//! Prices are USD per million tokens. Models are matched by prefix so dated
+//! snapshots (`claude-sonnet-4-20250514`) resolve to their family entry; the
+//! longest matching prefix wins. The `[pricing]` config table overrides or
+//! extends these entries, keyed by `model` or `provider/model` prefix.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// USD per million input/output tokens for a model family.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct ModelPricing {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+impl ModelPricing {
+    /// Cost in USD of a request with the given token counts.
+    pub fn cost(&self, input_tokens: u64, output_tokens: u64) -> f64 {
+        (input_tokens as f64 * self.input_per_mtok + output_tokens as f64 * self.output_per_mtok) / 1_000_000.0
+    }
+}
+
+const PRICING: &[(&str, ModelPricing)] = &[
+    ("claude-opus-4", ModelPricing { input_per_mtok: 15.0, output_per_mtok: 75.0 }),
+    ("claude-sonnet-4", ModelPricing { input_per_mtok: 3.0, output_per_mtok: 15.0 }),
+    ("claude-3-7-sonnet", ModelPricing { input_per_mtok: 3.0, output_per_mtok: 15.0 }),
+    ("claude-3-5-sonnet", ModelPricing { input_per_mtok: 3.0, output_per_mtok: 15.0 }),
+    ("claude-3-5-haiku", ModelPricing { input_per_mtok: 0.8, output_per_mtok: 4.0 }),
+    ("claude-3-haiku", ModelPricing { input_per_mtok: 0.25, output_per_mtok: 1.25 }),
+    ("gpt-4o", ModelPricing { input_per_mtok: 2.5, output_per_mtok: 10.0 }),
+    ("gpt-4o-mini", ModelPricing { input_per_mtok: 0.15, output_per_mtok: 0.6 }),
+    ("gpt-4.1", ModelPricing { input_per_mtok: 2.0, output_per_mtok: 8.0 }),
+    ("gpt-4.1-mini", ModelPricing { input_per_mtok: 0.4, output_per_mtok: 1.6 }),
+    ("gpt-4.1-nano", ModelPricing { input_per_mtok: 0.1, output_per_mtok: 0.4 }),
+    ("o3", ModelPricing { input_per_mtok: 2.0, output_per_mtok: 8.0 }),
+    ("o4-mini", ModelPricing { input_per_mtok: 1.1, output_per_mtok: 4.4 }),
+];
+
+/// Look up pricing for a model, or `None` if it isn't in the table.
+pub fn pricing_for(model: &str) -> Option<ModelPricing> {
+    longest_prefix(PRICING.iter().map(|(prefix, pricing)| (*prefix, pricing)), model)
+}
+
+/// Pricing for `model` served by `provider`: an override keyed by
+/// `provider/model` prefix, else one keyed by model prefix, else the
+/// built-in table.
+pub fn lookup(overrides: &HashMap<String, ModelPricing>, provider: &str, model: &str) -> Option<ModelPricing> {
+    let entries = || overrides.iter().map(|(key, pricing)| (key.as_str(), pricing));
+    longest_prefix(entries(), &format!("{}/{}", provider, model))
+        .or_else(|| longest_prefix(entries(), model))
+        .or_else(|| pricing_for(model))
+}
+
+fn longest_prefix<'a>(entries: impl Iterator<Item = (&'a str, &'a ModelPricing)>, name: &str) -> Option<ModelPricing> {
+    entries
+        .filter(|(prefix, _)| name.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, pricing)| *pricing)
+}
+
+/// Rough token estimate for text (~4 characters per token).
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.len() as u64).div_ceil(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_longest_prefix_wins() {
+        assert_eq!(pricing_for("gpt-4o-mini-2024-07-18").unwrap().input_per_mtok, 0.15);
+        assert_eq!(pricing_for("gpt-4o-2024-08-06").unwrap().input_per_mtok, 2.5);
+        assert!(pricing_for("mock-model").is_none());
+    }
+
+    #[test]
+    fn test_cost() {
+        let pricing = pricing_for("claude-sonnet-4-20250514").unwrap();
+        let cost = pricing.cost(1_000_000, 100_000);
+        assert!((cost - 4.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_lookup_overrides() {
+        let price = |input| ModelPricing { input_per_mtok: input, output_per_mtok: 1.0 };
+        let overrides = HashMap::from([
+            ("gpt-4o".to_string(), price(5.0)),
+            ("azure/gpt-4o".to_string(), price(6.0)),
+            ("llama3".to_string(), price(0.0)),
+        ]);
+        assert_eq!(lookup(&overrides, "openai", "gpt-4o-2024-08-06").unwrap().input_per_mtok, 5.0);
+        assert_eq!(lookup(&overrides, "azure", "gpt-4o").unwrap().input_per_mtok, 6.0);
+        // The built-in longer prefix isn't consulted once an override matches
+        assert_eq!(lookup(&overrides, "openai", "gpt-4o-mini").unwrap().input_per_mtok, 5.0);
+        assert_eq!(lookup(&overrides, "ollama", "llama3.1").unwrap().cost(1000, 0), 0.0);
+        assert_eq!(lookup(&overrides, "anthropic", "claude-3-haiku").unwrap().input_per_mtok, 0.25);
+        assert!(lookup(&overrides, "mock", "mock-model").is_none());
+    }
+}
@@ -3,7 +3,8 @@ use anyhow::{Context, Result};
 use futures::stream::{Stream, StreamExt};
 use std::pin::Pin;
 
-use crate::kernel::config::ProviderConfig;
+use crate::inference::auth::ProviderAuth;
+use crate::kernel::config::{ProviderConfig, ToolResultMode};
 use crate::kernel::event::KernelEvent;
 
 // Use standardized types from SDK
@@ -18,6 +19,7 @@ use futures::future::BoxFuture;
 pub enum ProviderKind {
     Anthropic,
     OpenAI,
+    Ollama,
     Mock,
 }
 
@@ -28,12 +30,48 @@ impl FromStr for ProviderKind {
         match s.to_lowercase().as_str() {
             "anthropic" => Ok(ProviderKind::Anthropic),
             "openai" => Ok(ProviderKind::OpenAI),
+            "ollama" => Ok(ProviderKind::Ollama),
             "mock" => Ok(ProviderKind::Mock),
             _ => anyhow::bail!("Unknown provider kind: {}", s),
         }
     }
 }
 
+/// Why a response ended, normalized across providers: OpenAI's `stop` and
+/// Anthropic's `end_turn` are both [`FinishReason::Stop`], and so on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinishReason {
+    Stop,
+    /// The output token limit cut the response short
+    Length,
+    ToolCalls,
+    ContentFilter,
+    /// A reason bedrock doesn't know
+    Other,
+}
+
+impl FinishReason {
+    pub fn parse(stop_reason: &str) -> Self {
+        match stop_reason.to_lowercase().as_str() {
+            "stop" | "end_turn" | "stop_sequence" | "pause_turn" => FinishReason::Stop,
+            "length" | "max_tokens" => FinishReason::Length,
+            "tool_calls" | "tool_use" | "function_call" => FinishReason::ToolCalls,
+            "content_filter" | "refusal" | "safety" | "recitation" => FinishReason::ContentFilter,
+            _ => FinishReason::Other,
+        }
+    }
+
+    /// Name stored in `messages.finish_reason`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FinishReason::Stop => "stop",
+            FinishReason::Length => "length",
+            FinishReason::ToolCalls => "tool_calls",
+            FinishReason::ContentFilter => "content_filter",
+            FinishReason::Other => "other",
+        }
+    }
+}
 
 /// Options for inference execution
 #[derive(Debug, Clone, Default)]
@@ -48,6 +86,14 @@ pub struct InferenceOptions {
 pub struct ProviderClient {
     pub kind: ProviderKind,
     pub provider: std::sync::Arc<dyn InferenceProvider>,
+    /// Refreshing credentials; when set, requests use the client it yields instead of `provider`
+    auth: Option<std::sync::Arc<ProviderAuth>>,
+    /// How tool results are encoded in requests
+    tool_result_mode: ToolResultMode,
+    /// Extra top-level fields merged into every request body
+    extra_body: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Whether reasoning blocks are left out of requests
+    strip_thinking: bool,
 }
 
 impl ProviderClient {
@@ -55,7 +101,40 @@ impl ProviderClient {
         kind: ProviderKind,
         provider: std::sync::Arc<dyn InferenceProvider>,
     ) -> Self {
-        Self { kind, provider }
+        Self { kind, provider, auth: None, tool_result_mode: ToolResultMode::Native, extra_body: None, strip_thinking: true }
+    }
+
+    /// Set how tool results are encoded for this provider.
+    pub fn with_tool_result_mode(mut self, mode: ToolResultMode) -> Self {
+        self.tool_result_mode = mode;
+        self
+    }
+
+    /// Merge these fields verbatim into every request body, overriding
+    /// anything bedrock sets under the same key.
+    pub fn with_extra_body(mut self, extra_body: Option<serde_json::Map<String, serde_json::Value>>) -> Self {
+        self.extra_body = extra_body;
+        self
+    }
+
+    /// Set whether reasoning blocks in the history are sent back to the provider.
+    pub fn with_strip_thinking(mut self, strip: bool) -> Self {
+        self.strip_thinking = strip;
+        self
+    }
+
+    /// Create a client whose credentials come from a pluggable auth scheme.
+    pub async fn with_auth(kind: ProviderKind, auth: ProviderAuth) -> Result<Self> {
+        let provider = auth.provider().await?;
+        Ok(Self { kind, provider, auth: Some(std::sync::Arc::new(auth)), tool_result_mode: ToolResultMode::Native, extra_body: None, strip_thinking: true })
+    }
+
+    /// The SDK client to use for the next request, refreshing credentials if needed.
+    async fn current_provider(&self) -> Result<std::sync::Arc<dyn InferenceProvider>> {
+        match &self.auth {
+            Some(auth) => auth.provider().await,
+            None => Ok(self.provider.clone()),
+        }
     }
 
     /// Run a non-streaming completion (aggregates the stream).
@@ -65,13 +144,25 @@ impl ProviderClient {
         system_prompt: &str,
         messages: &[InferenceMessage],
     ) -> Result<String> {
+        Ok(self.completion_with_usage(model, system_prompt, messages).await?.0)
+    }
+
+    /// Run a non-streaming completion, returning the text with the input and
+    /// output token counts.
+    pub async fn completion_with_usage(
+        &self,
+        model: &str,
+        system_prompt: &str,
+        messages: &[InferenceMessage],
+    ) -> Result<(String, u64, u64)> {
         let req = self.build_request(model, system_prompt, messages, &[], &InferenceOptions::default());
-        let result = self.provider.complete(req, None).await?;
-        Ok(result.content.iter().filter_map(|c| match c {
+        let result = self.current_provider().await?.complete(req, None).await?;
+        let text = result.content.iter().filter_map(|c| match c {
             InferenceContent::Text { text } => Some(text.as_str()),
             // We could include thinking here if desired, but typically completion() returns just the answer.
             _ => None,
-        }).collect::<Vec<_>>().join(""))
+        }).collect::<Vec<_>>().join("");
+        Ok((text, result.usage.input_tokens as u64, result.usage.output_tokens as u64))
     }
 
     /// Run a streaming completion.
@@ -84,13 +175,14 @@ impl ProviderClient {
         options: &InferenceOptions,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<KernelEvent>> + Send>>> {
         let req = self.build_request(model, system_prompt, messages, tools, options);
-        let sdk_stream = self.provider.stream(req, None).await?;
+        let sdk_stream = self.current_provider().await?.stream(req, None).await
+            .map_err(|e| provider_error(e.to_string()))?;
 
         // Map SDK InferenceEvents to Bedrock KernelEvents
         let kernel_stream = sdk_stream.map(|res| {
             match res {
                 Ok(event) => map_sdk_event(event),
-                Err(e) => Err(anyhow::anyhow!("Provider error: {}", e)),
+                Err(e) => Err(provider_error(e.to_string())),
             }
         });
 
@@ -113,18 +205,206 @@ impl ProviderClient {
              })
         }).collect();
 
+        let mut messages = match self.tool_result_mode {
+            ToolResultMode::Native => messages.to_vec(),
+            ToolResultMode::Text => fold_tool_results_as_text(messages),
+        };
+        if self.strip_thinking {
+            messages = strip_thinking(messages);
+        }
+
         InferenceRequest::builder()
             .model(model)
-            .messages(messages.to_vec())
+            .messages(messages)
             .system(system_prompt)
+            // Omit the parameter entirely: some providers reject `tools: []`
             .maybe_tools(if sdk_tools.is_empty() { None } else { Some(sdk_tools) })
             .maybe_temperature(options.temperature)
             .maybe_max_tokens(options.max_tokens)
             .maybe_thinking_budget(options.thinking_budget)
+            .maybe_extra_body(self.extra_body.clone())
             .build()
     }
 }
 
+// ─── Role Mapping ────────────────────────────────────────────────
+
+/// Remove reasoning blocks, dropping messages that held nothing else.
+pub fn strip_thinking(messages: Vec<InferenceMessage>) -> Vec<InferenceMessage> {
+    messages
+        .into_iter()
+        .filter_map(|mut msg| {
+            if !msg.content.iter().any(|c| matches!(c, InferenceContent::Thinking { .. })) {
+                return Some(msg);
+            }
+            msg.content.retain(|c| !matches!(c, InferenceContent::Thinking { .. }));
+            (!msg.content.is_empty()).then_some(msg)
+        })
+        .collect()
+}
+
+/// Rewrite tool results as labeled text blocks in user messages, for providers
+/// without a native tool-result role. Tool outputs are wrapped as:
+///
+/// ```text
+/// [tool_result id=<tool_use_id>]
+/// <content>
+/// [/tool_result]
+/// ```
+///
+/// with `error=true` added to the header for failed calls.
+pub fn fold_tool_results_as_text(messages: &[InferenceMessage]) -> Vec<InferenceMessage> {
+    messages.iter().map(|msg| {
+        let has_results = msg.content.iter().any(|c| matches!(c, InferenceContent::ToolResult { .. }));
+        if !has_results && msg.role != InferenceRole::Tool {
+            return msg.clone();
+        }
+        let content = msg.content.iter().map(|c| match c {
+            InferenceContent::ToolResult { tool_use_id, content, is_error } => {
+                let error = if *is_error { " error=true" } else { "" };
+                InferenceContent::Text {
+                    text: format!("[tool_result id={}{}]\n{}\n[/tool_result]", tool_use_id, error, content),
+                }
+            }
+            other => other.clone(),
+        }).collect();
+        InferenceMessage { role: InferenceRole::User, content, tool_call_id: None }
+    }).collect()
+}
+
+// ─── Error Mapping ───────────────────────────────────────────────
+
+/// Provider failures the kernel can recover from.
+#[derive(Debug, thiserror::Error)]
+pub enum ProviderError {
+    /// The request exceeded the model's context window
+    #[error("Provider rejected the request as too long for the context window: {0}")]
+    ContextOverflow(String),
+    /// A transient failure (rate limit, server error, timeout) worth retrying elsewhere
+    #[error("Provider error: {0}")]
+    Retryable(String),
+}
+
+/// HTTP status and provider error code carried by a provider error.
+///
+/// The SDKs only surface error text, so both are parsed out of it: the status
+/// from a leading `429 Too Many Requests`, `HTTP 503` or `returned 500`, the
+/// code from the JSON error body (`error.code`, `error.type` or Gemini's
+/// `error.status`) or from a prefix like `overloaded_error: Overloaded`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorDetails {
+    pub status: Option<u16>,
+    pub code: Option<String>,
+}
+
+impl ErrorDetails {
+    pub fn parse(message: &str) -> Self {
+        Self { status: parse_status(message), code: parse_error_code(message) }
+    }
+}
+
+/// Words a status code follows in provider and SDK error messages.
+const STATUS_PREFIXES: &[&str] = &["http", "status", "code", "returned", "error"];
+
+fn parse_status(message: &str) -> Option<u16> {
+    let words: Vec<&str> = message.split(|c: char| !c.is_ascii_alphanumeric()).filter(|w| !w.is_empty()).collect();
+    words.iter().enumerate().find_map(|(i, word)| {
+        let status = word.parse::<u16>().ok().filter(|s| word.len() == 3 && (400..600).contains(s))?;
+        let follows_prefix = i.checked_sub(1).is_none_or(|prev| STATUS_PREFIXES.contains(&words[prev].to_lowercase().as_str()));
+        follows_prefix.then_some(status)
+    })
+}
+
+fn parse_error_code(message: &str) -> Option<String> {
+    let body = message.find('{').zip(message.rfind('}'))
+        .and_then(|(start, end)| serde_json::from_str::<serde_json::Value>(message.get(start..=end)?).ok());
+    let from_body = body.and_then(|body| {
+        let error = body.get("error")?;
+        ["code", "type", "status"].iter().find_map(|key| error.get(*key)?.as_str().map(str::to_string))
+    });
+    from_body.or_else(|| {
+        message.split(':').map(str::trim).find(|part| {
+            part.contains('_') && part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        }).map(str::to_string)
+    })
+}
+
+/// Provider error codes for a request over the context window.
+const CONTEXT_OVERFLOW_CODES: &[&str] = &[
+    "context_length_exceeded",
+    "request_too_large",
+    "string_above_max_length",
+    "model_max_prompt_tokens_exceeded",
+    "tokens_limit_reached",
+];
+
+/// Whether a provider error reports a context-length overflow: HTTP 413, a
+/// known error code, or (where providers reuse a generic code such as
+/// Anthropic's `invalid_request_error`) the wording of the message.
+pub fn is_context_overflow(message: &str) -> bool {
+    let details = ErrorDetails::parse(message);
+    if details.status == Some(413) || details.code.as_deref().is_some_and(|code| CONTEXT_OVERFLOW_CODES.contains(&code)) {
+        return true;
+    }
+    let message = message.to_lowercase();
+    [
+        "context length",
+        "context window",
+        "prompt is too long",
+        "input is too long",
+        "maximum context",
+        "exceeds the maximum number of tokens",
+        "too many tokens",
+        "request too large",
+        "payload too large",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+/// HTTP statuses of transient failures: timeouts, rate limits, and
+/// overloaded or failing servers (529 is Anthropic's "overloaded").
+const RETRYABLE_STATUSES: &[u16] = &[408, 429, 500, 502, 503, 504, 529];
+
+/// Provider error codes of transient failures.
+const RETRYABLE_CODES: &[&str] = &[
+    "rate_limit_error",
+    "rate_limit_exceeded",
+    "overloaded_error",
+    "api_error",
+    "server_error",
+    "RESOURCE_EXHAUSTED",
+    "UNAVAILABLE",
+];
+
+/// Whether a provider error reports a transient failure. An error with an
+/// HTTP status is retryable if the status is; otherwise a known error code
+/// decides, and an error with neither is retryable if its wording reports a
+/// timeout or a connection that could not be made.
+pub fn is_retryable(message: &str) -> bool {
+    let details = ErrorDetails::parse(message);
+    if let Some(status) = details.status {
+        return RETRYABLE_STATUSES.contains(&status);
+    }
+    if details.code.as_deref().is_some_and(|code| RETRYABLE_CODES.contains(&code)) {
+        return true;
+    }
+    let message = message.to_lowercase();
+    ["timed out", "timeout", "connection refused", "connection reset", "error sending request"]
+        .iter()
+        .any(|pattern| message.contains(pattern))
+}
+
+fn provider_error(message: String) -> anyhow::Error {
+    if is_context_overflow(&message) {
+        ProviderError::ContextOverflow(message).into()
+    } else if is_retryable(&message) {
+        ProviderError::Retryable(message).into()
+    } else {
+        anyhow::anyhow!("Provider error: {}", message)
+    }
+}
+
 // ─── Event Mapping ───────────────────────────────────────────────
 
 fn map_sdk_event(event: InferenceEvent) -> Result<KernelEvent> {
@@ -133,7 +413,9 @@ fn map_sdk_event(event: InferenceEvent) -> Result<KernelEvent> {
         InferenceEvent::MessageDelta { content } => Ok(KernelEvent::MessageDelta { content_delta: content }),
         InferenceEvent::ThinkingDelta { content } => Ok(KernelEvent::ThinkingDelta { thinking: content }),
         InferenceEvent::ToolCall { id, name, args } => Ok(KernelEvent::ToolCall { id, name, args }),
-        InferenceEvent::MessageEnd { input_tokens, output_tokens, .. } => Ok(KernelEvent::MessageEnd { role: "assistant".to_string(), input_tokens: input_tokens as u64, output_tokens: output_tokens as u64 }),
+        InferenceEvent::MessageEnd { input_tokens, output_tokens, stop_reason } => Ok(KernelEvent::MessageEnd { role: "assistant".to_string(), input_tokens: input_tokens as u64, output_tokens: output_tokens as u64, stop_reason }),
+        InferenceEvent::Error { message } if is_context_overflow(&message) => Err(ProviderError::ContextOverflow(message).into()),
+        InferenceEvent::Error { message } if is_retryable(&message) => Err(ProviderError::Retryable(message).into()),
         InferenceEvent::Error { message } => Err(anyhow::anyhow!("Provider stream error: {}", message)),
         _ => Err(anyhow::anyhow!("Unknown inference event type")),
     }
@@ -144,7 +426,11 @@ fn map_sdk_event(event: InferenceEvent) -> Result<KernelEvent> {
 pub fn create_anthropic_client(provider_config: &ProviderConfig) -> Result<std::sync::Arc<dyn InferenceProvider>> {
      let env_var = provider_config.api_key_env.as_ref().context("API key environment variable not configured")?;
      let api_key = std::env::var(env_var).context("Missing API Key")?;
-     let mut config = anthropic_sdk::ClientConfig::new(api_key)?;
+     create_anthropic_client_with_key(provider_config, &api_key)
+}
+
+pub fn create_anthropic_client_with_key(provider_config: &ProviderConfig, api_key: &str) -> Result<std::sync::Arc<dyn InferenceProvider>> {
+     let mut config = anthropic_sdk::ClientConfig::new(api_key.to_string())?;
      if let Some(url) = &provider_config.base_url { config = config.with_base_url(url); }
      
      let client = anthropic_sdk::Client::from_config(config)?;
@@ -152,10 +438,21 @@ pub fn create_anthropic_client(provider_config: &ProviderConfig) -> Result<std::
 }
 
 
+/// Sent to OpenAI-compatible servers configured without a key; local servers
+/// (vLLM, llama.cpp, LiteLLM without auth) accept any bearer token.
+const NO_API_KEY: &str = "no-key";
+
 pub fn create_openai_client(provider_config: &ProviderConfig) -> Result<std::sync::Arc<dyn InferenceProvider>> {
+     if provider_config.api_key_env.is_none() && provider_config.base_url.is_some() {
+          return create_openai_client_with_key(provider_config, NO_API_KEY);
+     }
      let env_var = provider_config.api_key_env.as_ref().context("API key environment variable not configured")?;
      let api_key = std::env::var(env_var).context("Missing API Key")?;
-     let mut config = openai_sdk::ClientConfig::new(api_key)?;
+     create_openai_client_with_key(provider_config, &api_key)
+}
+
+pub fn create_openai_client_with_key(provider_config: &ProviderConfig, api_key: &str) -> Result<std::sync::Arc<dyn InferenceProvider>> {
+     let mut config = openai_sdk::ClientConfig::new(api_key.to_string())?;
      if let Some(url) = &provider_config.base_url { config = config.with_base_url(url); }
      
      let client = openai_sdk::Client::from_config(config)?;
@@ -191,3 +488,209 @@ impl InferenceProvider for MockProvider {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finish_reason_parse() {
+        assert_eq!(FinishReason::parse("stop"), FinishReason::Stop);
+        assert_eq!(FinishReason::parse("end_turn"), FinishReason::Stop);
+        assert_eq!(FinishReason::parse("max_tokens"), FinishReason::Length);
+        assert_eq!(FinishReason::parse("length"), FinishReason::Length);
+        assert_eq!(FinishReason::parse("tool_use"), FinishReason::ToolCalls);
+        assert_eq!(FinishReason::parse("SAFETY"), FinishReason::ContentFilter);
+        assert_eq!(FinishReason::parse("something_new"), FinishReason::Other);
+        assert_eq!(FinishReason::parse("end_turn").as_str(), "stop");
+    }
+
+    fn history() -> Vec<InferenceMessage> {
+        vec![
+            InferenceMessage {
+                role: InferenceRole::User,
+                content: vec![InferenceContent::Text { text: "read it".to_string() }],
+                tool_call_id: None,
+            },
+            InferenceMessage {
+                role: InferenceRole::Assistant,
+                content: vec![InferenceContent::ToolUse {
+                    id: "call_1".to_string(),
+                    name: "read_file".to_string(),
+                    input: serde_json::json!({"path": "a.txt"}),
+                }],
+                tool_call_id: None,
+            },
+            InferenceMessage {
+                role: InferenceRole::User,
+                content: vec![
+                    InferenceContent::ToolResult { tool_use_id: "call_1".to_string(), content: "hello".to_string(), is_error: false },
+                    InferenceContent::ToolResult { tool_use_id: "call_2".to_string(), content: "boom".to_string(), is_error: true },
+                ],
+                tool_call_id: None,
+            },
+        ]
+    }
+
+    fn mock_client() -> ProviderClient {
+        ProviderClient::new(ProviderKind::Mock, create_mock_client(&ProviderConfig {
+            kind: "mock".to_string(),
+            api_key_env: None,
+            base_url: None,
+            auth: None,
+            tool_results: ToolResultMode::Native,
+            extra_body: None,
+            strip_thinking: true,
+        }))
+    }
+
+    #[test]
+    fn test_native_mode_passes_tool_results_through() {
+        let client = mock_client();
+        let req = client.build_request("m", "sys", &history(), &[], &InferenceOptions::default());
+        assert!(matches!(req.messages[2].content[0], InferenceContent::ToolResult { .. }));
+    }
+
+    #[test]
+    fn test_empty_tool_list_omits_tools_parameter() {
+        let client = mock_client();
+        let req = client.build_request("m", "sys", &history(), &[], &InferenceOptions::default());
+        assert!(req.tools.is_none());
+
+        let tools = [serde_json::json!({"name": "read_file", "description": "Read"})];
+        let req = client.build_request("m", "sys", &history(), &tools, &InferenceOptions::default());
+        assert_eq!(req.tools.map(|t| t.len()), Some(1));
+    }
+
+    #[test]
+    fn test_text_mode_folds_tool_results_into_user_text() {
+        let folded = fold_tool_results_as_text(&history());
+        assert_eq!(folded.len(), 3);
+
+        // Messages without tool results are untouched
+        assert!(matches!(folded[1].content[0], InferenceContent::ToolUse { .. }));
+
+        let results = &folded[2];
+        assert_eq!(results.role, InferenceRole::User);
+        match (&results.content[0], &results.content[1]) {
+            (InferenceContent::Text { text: ok }, InferenceContent::Text { text: err }) => {
+                assert_eq!(ok, "[tool_result id=call_1]\nhello\n[/tool_result]");
+                assert!(err.starts_with("[tool_result id=call_2 error=true]"));
+            }
+            other => panic!("Expected text blocks, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_extra_body_passed_through() {
+        let req = mock_client().build_request("m", "sys", &history(), &[], &InferenceOptions::default());
+        assert!(req.extra_body.is_none());
+
+        let body = serde_json::json!({ "reasoning_effort": "high", "provider": { "order": ["a", "b"] } });
+        let client = mock_client().with_extra_body(body.as_object().cloned());
+        let req = client.build_request("m", "sys", &history(), &[], &InferenceOptions::default());
+        assert_eq!(req.extra_body.unwrap()["provider"]["order"][1], "b");
+    }
+
+    #[test]
+    fn test_openai_compatible_without_key() {
+        let mut config = ProviderConfig {
+            kind: "openai".to_string(),
+            api_key_env: None,
+            base_url: None,
+            auth: None,
+            tool_results: Default::default(),
+            extra_body: None,
+            strip_thinking: true,
+        };
+        assert!(create_openai_client(&config).is_err());
+        config.base_url = Some("http://localhost:8000/v1".to_string());
+        assert!(create_openai_client(&config).is_ok());
+    }
+
+    #[test]
+    fn test_error_details() {
+        let details = ErrorDetails::parse(r#"api error: 400 Bad Request: {"error":{"message":"too long","type":"invalid_request_error","code":"context_length_exceeded"}}"#);
+        assert_eq!(details, ErrorDetails { status: Some(400), code: Some("context_length_exceeded".to_string()) });
+        let details = ErrorDetails::parse(r#"{"error":{"code":400,"message":"bad","status":"INVALID_ARGUMENT"}}"#);
+        assert_eq!(details.code.as_deref(), Some("INVALID_ARGUMENT"));
+        assert_eq!(ErrorDetails::parse("overloaded_error: Overloaded").code.as_deref(), Some("overloaded_error"));
+        assert_eq!(ErrorDetails::parse("Ollama returned 503 Service Unavailable").status, Some(503));
+        // Numbers that aren't a status
+        assert_eq!(ErrorDetails::parse("prompt is too long: 210000 tokens > 200000 maximum"), ErrorDetails::default());
+        assert_eq!(ErrorDetails::parse("invalid request id 413 for model x").status, None);
+    }
+
+    #[test]
+    fn test_context_overflow_errors() {
+        for message in [
+            "413 Payload Too Large",
+            r#"400 {"error":{"type":"invalid_request_error","code":"context_length_exceeded","message":"..."}}"#,
+            r#"{"type":"error","error":{"type":"request_too_large","message":"Request exceeds the maximum size"}}"#,
+            "invalid_request_error: prompt is too long: 210000 tokens > 200000 maximum",
+            "The input token count (1200000) exceeds the maximum number of tokens allowed (1048576).",
+        ] {
+            assert!(is_context_overflow(message), "{}", message);
+        }
+        for message in ["invalid_request_error: max_tokens must be positive", "request 413abc failed", "tool call 1413 failed"] {
+            assert!(!is_context_overflow(message), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_retryable_errors() {
+        for message in [
+            "429 Too Many Requests",
+            "HTTP 503",
+            "api error: 408 Request Timeout",
+            "rate_limit_error",
+            "overloaded_error: Overloaded",
+            r#"{"error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+            r#"{"error":{"message":"quota","status":"RESOURCE_EXHAUSTED"}}"#,
+            "request timed out",
+            "connection refused",
+        ] {
+            let err = provider_error(message.to_string());
+            assert!(matches!(err.downcast_ref::<ProviderError>(), Some(ProviderError::Retryable(_))), "{}", message);
+        }
+        for message in [
+            "401 Unauthorized",
+            "invalid model",
+            "max_tokens: 512 exceeds limit",
+            // The status decides over the wording
+            "400 Bad Request: tool timed out",
+            // A number that isn't a status
+            "invalid request id 503 for model x",
+        ] {
+            assert!(provider_error(message.to_string()).downcast_ref::<ProviderError>().is_none(), "{}", message);
+        }
+        // Overflow wins: it is recovered by trimming, not by switching providers
+        let err = provider_error("413 request too large".to_string());
+        assert!(matches!(err.downcast_ref::<ProviderError>(), Some(ProviderError::ContextOverflow(_))));
+    }
+
+    #[test]
+    fn test_strip_thinking() {
+        let mut messages = history();
+        messages[1].content.insert(0, InferenceContent::Thinking { content: "read it first".to_string() });
+        messages.push(InferenceMessage {
+            role: InferenceRole::Assistant,
+            content: vec![InferenceContent::Thinking { content: "nothing else".to_string() }],
+            tool_call_id: None,
+        });
+        let req = mock_client().build_request("m", "sys", &messages, &[], &InferenceOptions::default());
+        assert_eq!(req.messages.len(), messages.len() - 1);
+        assert_eq!(req.messages[1].content, history()[1].content);
+
+        let client = mock_client().with_strip_thinking(false);
+        let req = client.build_request("m", "sys", &messages, &[], &InferenceOptions::default());
+        assert_eq!(req.messages, messages);
+    }
+
+    #[test]
+    fn test_text_mode_applied_by_client() {
+        let client = mock_client().with_tool_result_mode(ToolResultMode::Text);
+        let req = client.build_request("m", "sys", &history(), &[], &InferenceOptions::default());
+        assert!(req.messages.iter().flat_map(|m| m.content.iter())
+            .all(|c| !matches!(c, InferenceContent::ToolResult { .. })));
+    }
+}
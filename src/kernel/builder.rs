@@ -4,8 +4,12 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 
 use crate::kernel::{Kernel, BedrockConfig};
+use crate::kernel::verbosity::Verbosity;
 use crate::tools::registry::ToolRegistry;
-use crate::tools::builtins::create_default_registry;
+use crate::tools::resource::ResourceLimiter;
+use crate::tools::safe_mode::SafeMode;
+use crate::tools::approval::ApprovalHandler;
+use crate::tools::builtins::create_registry;
 use crate::persistence::state::StateStore;
 use crate::inference::embeddings::EmbeddingProvider;
 
@@ -13,6 +17,11 @@ use crate::inference::embeddings::EmbeddingProvider;
 pub struct RuntimeBuilder {
     config: BedrockConfig,
     json: bool,
+    quiet: bool,
+    interactive: bool,
+    pin_agent: bool,
+    approval_handler: Option<ApprovalHandler>,
+    verbosity: Verbosity,
     tool_registry: ToolRegistry,
     state: Option<StateStore>,
     embedding_provider: Option<Arc<dyn EmbeddingProvider>>,
@@ -21,10 +30,16 @@ pub struct RuntimeBuilder {
 impl RuntimeBuilder {
     /// Create a new builder with the given configuration.
     pub fn new(config: BedrockConfig) -> Self {
+        let tool_registry = create_registry(&config.tools);
         Self {
             config,
             json: false,
-            tool_registry: create_default_registry(),
+            quiet: false,
+            interactive: true,
+            pin_agent: false,
+            approval_handler: None,
+            verbosity: Verbosity::default(),
+            tool_registry,
             state: None,
             embedding_provider: None,
         }
@@ -36,6 +51,41 @@ impl RuntimeBuilder {
         self
     }
 
+    /// Don't echo streamed responses to stdout, e.g. when serving many
+    /// sessions from one process.
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Whether a person is at the terminal to approve escalated tool calls
+    /// (default true). When false, escalations are denied without asking.
+    pub fn interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    /// Keep the configured `agent.model` and `agent.provider` when resuming a
+    /// session whose snapshot used others, e.g. because they were given with
+    /// `--model` or `--provider` (default false).
+    pub fn pin_agent(mut self, pin: bool) -> Self {
+        self.pin_agent = pin;
+        self
+    }
+
+    /// Decide approvals (escalations and `tools.requires_approval` matches)
+    /// with `handler` instead of prompting on stdin.
+    pub fn approval_handler(mut self, handler: ApprovalHandler) -> Self {
+        self.approval_handler = Some(handler);
+        self
+    }
+
+    /// Set which diagnostic categories print to stderr.
+    pub fn verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
     /// Set a custom state store.
     pub fn with_state_store(mut self, state: StateStore) -> Self {
         self.state = Some(state);
@@ -49,18 +99,48 @@ impl RuntimeBuilder {
     }
 
     /// Build the Kernel.
-    pub fn build(self) -> Result<Kernel> {
+    pub fn build(mut self) -> Result<Kernel> {
+        if !self.config.tools.enabled {
+            self.tool_registry = ToolRegistry::new();
+        }
+        if self.config.tools.safe {
+            self.tool_registry.set_safe_mode(SafeMode::new(self.config.tools.allow.clone()).shared());
+        }
+        self.tool_registry.set_precedence(self.config.tools.precedence.clone());
+        self.tool_registry.set_schema_conflicts(
+            self.config.tools.on_schema_conflict,
+            self.config.tools.schema_overrides.clone(),
+        );
+        self.tool_registry.set_aliases(&self.config.tools.aliases)?;
+        let resources = ResourceLimiter::new(self.config.tools.resource_limits.clone());
+        let tool_slots = self.config.kernel.max_parallel_tool_calls.map(|n| tokio::sync::Semaphore::new(n as usize));
+        let tool_catalog = Arc::new(std::sync::RwLock::new(self.tool_registry.descriptors()));
         Ok(Kernel {
             config: Arc::new(self.config),
             json: self.json,
+            quiet: self.quiet,
+            interactive: self.interactive,
+            pin_agent: self.pin_agent,
+            approval_handler: self.approval_handler,
+            verbosity: self.verbosity,
+            renderer: std::sync::Mutex::new(super::render::EventRenderer::new(self.json, self.quiet, self.verbosity)),
             tool_registry: self.tool_registry,
             state: self.state,
             harness: Arc::new(Mutex::new(None)),
+            harness_tools: Default::default(),
             check_watcher: None,
             clients: HashMap::new(),
             embedding_provider: self.embedding_provider,
             active_queue: std::sync::Arc::new(tokio::sync::Mutex::new(None)),
-            mcp_clients: Vec::new(),
+            active_session: Default::default(),
+            mcp_servers: Default::default(),
+            event_bus: tokio::sync::broadcast::channel(super::EVENT_BUS_CAPACITY).0,
+            interrupt: Default::default(),
+            resources,
+            tool_slots,
+            tool_catalog,
+            activity: Default::default(),
+            maintenance: None,
         })
     }
 }
@@ -0,0 +1,101 @@
+//! Splitting oversized prompts into queueable parts.
+//!
+//! Used by `bedrock run --chunk` when a prompt exceeds
+//! `kernel.max_prompt_tokens`. Parts break at line boundaries where possible
+//! and repeat a little of the previous part so nothing is cut mid-thought.
+
+use crate::inference::pricing;
+
+/// Share of each part repeated at the start of the next one.
+const OVERLAP_DIVISOR: usize = 10;
+
+/// Characters reserved for the "[Part i of n]" header.
+const HEADER_RESERVE: usize = 64;
+
+/// Split `text` into labeled parts that each fit within `max_tokens`.
+/// Text that already fits is returned as a single, unlabeled part.
+pub fn chunk_prompt(text: &str, max_tokens: u32) -> Vec<String> {
+    if pricing::estimate_tokens(text) <= max_tokens as u64 {
+        return vec![text.to_string()];
+    }
+    // estimate_tokens counts 4 bytes per token
+    let budget = (max_tokens as usize * 4).saturating_sub(HEADER_RESERVE).max(1);
+    let overlap = budget / OVERLAP_DIVISOR;
+
+    let mut parts = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        let mut end = floor_char_boundary(text, (start + budget).min(text.len()));
+        if end < text.len() {
+            // Prefer ending on a line break in the back half of the window
+            if let Some(nl) = text[start..end].rfind('\n').filter(|&i| i >= budget / 2) {
+                end = start + nl + 1;
+            }
+        }
+        if end <= start {
+            end = start + text[start..].chars().next().map_or(1, char::len_utf8);
+        }
+        parts.push(&text[start..end]);
+        if end == text.len() {
+            break;
+        }
+        start = floor_char_boundary(text, end.saturating_sub(overlap)).max(start + 1);
+        start = ceil_char_boundary(text, start);
+    }
+
+    let n = parts.len();
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(i, part)| format!("[Part {} of {}]\n{}", i + 1, n, part))
+        .collect()
+}
+
+fn floor_char_boundary(s: &str, mut i: usize) -> usize {
+    while !s.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+fn ceil_char_boundary(s: &str, mut i: usize) -> usize {
+    while i < s.len() && !s.is_char_boundary(i) {
+        i += 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_prompt_is_unchanged() {
+        assert_eq!(chunk_prompt("hello", 100), vec!["hello".to_string()]);
+    }
+
+    #[test]
+    fn test_chunks_fit_and_overlap() {
+        let text: String = (0..400).map(|i| format!("line {}\n", i)).collect();
+        let parts = chunk_prompt(&text, 200);
+        assert!(parts.len() > 1);
+        for (i, part) in parts.iter().enumerate() {
+            assert!(pricing::estimate_tokens(part) <= 200, "part {} too large", i);
+            assert!(part.starts_with(&format!("[Part {} of {}]\n", i + 1, parts.len())));
+        }
+        // Consecutive parts share some text, and the last line survives
+        let body = |p: &str| p.split_once('\n').unwrap().1.to_string();
+        let first = body(&parts[0]);
+        let tail = first.lines().last().unwrap();
+        assert!(body(&parts[1]).contains(tail));
+        assert!(parts.last().unwrap().ends_with("line 399\n"));
+    }
+
+    #[test]
+    fn test_multibyte_text_splits_on_char_boundaries() {
+        let text = "é".repeat(1000);
+        let parts = chunk_prompt(&text, 100);
+        assert!(parts.len() > 1);
+        assert!(parts.iter().all(|p| p.contains('é')));
+    }
+}
@@ -0,0 +1,232 @@
+//! Structured summaries for history compaction.
+//!
+//! A plain summary of compacted messages tends to lose which tools ran and
+//! how they went. `ctx:summarize()` and the kernel's automatic compaction
+//! (`[compaction]`) therefore append a digest of the tool calls in the
+//! summarized range, built from the `tool_executions` log rather than from the
+//! message text, so the agent keeps an accurate record of the actions it
+//! already took.
+
+use anyhow::Result;
+
+use crate::inference::pricing;
+use crate::inference::provider::{InferenceContent, InferenceMessage};
+use crate::kernel::{retention, snapshot, verbosity};
+use crate::persistence::state::{StateStore, ToolExecutionRow};
+
+/// System prompt for the model that writes compaction summaries.
+pub const SUMMARY_PROMPT: &str = "Summarize the following conversation concisely. Keep the user's goals, decisions made, \
+facts learned and work still outstanding; the summary replaces these messages in the agent's context.";
+
+/// Most digest lines kept; older calls are counted instead of listed.
+const MAX_DIGEST_LINES: usize = 50;
+
+/// Arguments that best identify what a call did, in order of preference.
+const KEY_ARGS: &[&str] = &["path", "command", "url", "query", "pattern", "name"];
+
+/// Ids of the tool calls made in `messages`, in order.
+pub fn tool_call_ids(messages: &[InferenceMessage]) -> Vec<String> {
+    messages
+        .iter()
+        .flat_map(|m| &m.content)
+        .filter_map(|c| match c {
+            InferenceContent::ToolUse { id, .. } => Some(id.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The messages to send to the summary model. A cut can leave a tool call
+/// whose result falls after it, which providers reject, so unpaired tool
+/// blocks are dropped; the digest still covers those calls.
+pub fn summary_input(messages: &[InferenceMessage]) -> Vec<InferenceMessage> {
+    let mut messages = messages.to_vec();
+    snapshot::drop_unpaired_tool_blocks(&mut messages);
+    messages
+}
+
+/// One line per execution, e.g. `shell_exec(cargo test) -> error: 3 failed`.
+pub fn tool_digest(executions: &[ToolExecutionRow]) -> Vec<String> {
+    let skipped = executions.len().saturating_sub(MAX_DIGEST_LINES);
+    let mut lines: Vec<String> = executions[skipped..].iter().map(digest_line).collect();
+    if skipped > 0 {
+        lines.insert(0, format!("({} earlier tool calls omitted)", skipped));
+    }
+    lines
+}
+
+fn digest_line(exec: &ToolExecutionRow) -> String {
+    let args: serde_json::Value = serde_json::from_str(&exec.args).unwrap_or_default();
+    let target = KEY_ARGS
+        .iter()
+        .find_map(|key| args.get(key).and_then(|v| v.as_str()))
+        .map(|s| verbosity::preview(s, 80))
+        .unwrap_or_default();
+    let status = if exec.is_error { "error" } else { "ok" };
+    // Verdicts other than plain execution (rejected, denied, cached...) are worth keeping
+    let verdict = match exec.verdict.as_str() {
+        "allow" | "modify" => String::new(),
+        other => format!(" [{}]", other),
+    };
+    // The last line of output usually carries the outcome ("test result: 3 failed")
+    let outcome = exec
+        .output
+        .as_deref()
+        .and_then(|out| out.lines().rev().find(|l| !l.trim().is_empty()))
+        .map(|l| format!(": {}", verbosity::preview(l.trim(), 100)))
+        .unwrap_or_default();
+    format!("{}({}) -> {}{}{}", exec.tool_name, target, status, verdict, outcome)
+}
+
+/// Digest of the logged executions of the tool calls in `messages`.
+pub async fn tool_digest_for(store: &StateStore, session_id: &str, messages: &[InferenceMessage]) -> Result<Vec<String>> {
+    let ids = tool_call_ids(messages);
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let executions: Vec<ToolExecutionRow> = store
+        .get_tool_executions(session_id)
+        .await?
+        .into_iter()
+        .filter(|e| ids.contains(&e.tool_call_id))
+        .collect();
+    Ok(tool_digest(&executions))
+}
+
+/// Estimated prompt tokens of `history` sent under `system_prompt`.
+pub fn estimate_prompt_tokens(system_prompt: &str, history: &[InferenceMessage]) -> u64 {
+    pricing::estimate_tokens(system_prompt) + pricing::estimate_tokens(&serde_json::to_string(history).unwrap_or_default())
+}
+
+/// Index of the first message kept verbatim when compacting down to the last
+/// `keep_turns` turns; 0 when there is nothing older to summarize.
+pub fn compaction_cut(history: &[InferenceMessage], keep_turns: u32) -> usize {
+    retention::retained_tail_start(history, keep_turns.max(1))
+}
+
+/// Replace `history[..cut]` with `summary`, placed at the start of the first
+/// kept message so roles keep alternating. Returns the number of messages removed.
+pub fn apply_summary(history: &mut Vec<InferenceMessage>, cut: usize, summary: &str) -> usize {
+    history.drain(..cut);
+    if let Some(first) = history.first_mut() {
+        first.content.insert(0, InferenceContent::Text {
+            text: format!("[Summary of the earlier conversation]\n{}", summary.trim()),
+        });
+    }
+    cut
+}
+
+/// Combine the model's conversational summary with the tool digest.
+pub fn structured_summary(summary: &str, digest: &[String]) -> String {
+    if digest.is_empty() {
+        return summary.to_string();
+    }
+    let actions: Vec<String> = digest.iter().map(|line| format!("- {}", line)).collect();
+    format!("## Summary\n{}\n\n## Tool calls\n{}", summary.trim(), actions.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inference::provider::InferenceRole;
+    use serde_json::json;
+
+    fn exec(tool: &str, args: serde_json::Value, output: &str, is_error: bool, verdict: &str) -> ToolExecutionRow {
+        ToolExecutionRow {
+            id: 0,
+            session_id: "s".to_string(),
+            turn_index: 0,
+            tool_call_id: "t".to_string(),
+            tool_name: tool.to_string(),
+            args: args.to_string(),
+            output: Some(output.to_string()),
+            is_error,
+            duration_ms: None,
+            verdict: verdict.to_string(),
+            created_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_tool_digest_lines() {
+        let digest = tool_digest(&[
+            exec("shell_exec", json!({"command": "cargo test"}), "running 6 tests\ntest result: FAILED. 3 passed; 3 failed\n\n", true, "allow"),
+            exec("write_file", json!({"path": "src/lib.rs", "content": "..."}), "Wrote 120 bytes", false, "modify"),
+            exec("shell_exec", json!({"command": "rm -rf /"}), "[HARNESS REJECTED] nope", true, "reject"),
+        ]);
+        assert_eq!(digest, vec![
+            "shell_exec(cargo test) -> error: test result: FAILED. 3 passed; 3 failed",
+            "write_file(src/lib.rs) -> ok: Wrote 120 bytes",
+            "shell_exec(rm -rf /) -> error [reject]: [HARNESS REJECTED] nope",
+        ]);
+
+        let many: Vec<_> = (0..60).map(|_| exec("read_file", json!({"path": "a"}), "x", false, "allow")).collect();
+        let digest = tool_digest(&many);
+        assert_eq!(digest.len(), MAX_DIGEST_LINES + 1);
+        assert_eq!(digest[0], "(10 earlier tool calls omitted)");
+    }
+
+    #[test]
+    fn test_apply_summary() {
+        let text = |role, text: &str| InferenceMessage { role, content: vec![InferenceContent::Text { text: text.to_string() }], tool_call_id: None };
+        let mut history = vec![
+            text(InferenceRole::User, "q1"),
+            text(InferenceRole::Assistant, "a1"),
+            text(InferenceRole::User, "q2"),
+            text(InferenceRole::Assistant, "a2"),
+            text(InferenceRole::User, "q3"),
+        ];
+        let cut = compaction_cut(&history, 2);
+        assert_eq!(cut, 2);
+        assert_eq!(compaction_cut(&history, 3), 0);
+
+        let before = estimate_prompt_tokens("sys", &history);
+        assert_eq!(apply_summary(&mut history, cut, "Asked q1.\n"), 2);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].role, InferenceRole::User);
+        assert_eq!(history[0].content[0], InferenceContent::Text { text: "[Summary of the earlier conversation]\nAsked q1.".to_string() });
+        assert_eq!(history[0].content[1], InferenceContent::Text { text: "q2".to_string() });
+        assert!(estimate_prompt_tokens("sys", &history) < before + 20);
+    }
+
+    #[test]
+    fn test_summary_input_drops_unanswered_calls() {
+        let call = |id: &str| InferenceContent::ToolUse { id: id.to_string(), name: "read_file".to_string(), input: json!({}) };
+        let messages = vec![
+            InferenceMessage { role: InferenceRole::Assistant, content: vec![call("a")], tool_call_id: None },
+            InferenceMessage {
+                role: InferenceRole::User,
+                content: vec![InferenceContent::ToolResult { tool_use_id: "a".to_string(), content: "ok".to_string(), is_error: false }],
+                tool_call_id: None,
+            },
+            // Answered after the cut
+            InferenceMessage { role: InferenceRole::Assistant, content: vec![InferenceContent::Text { text: "next".to_string() }, call("b")], tool_call_id: None },
+        ];
+        let input = summary_input(&messages);
+        assert_eq!(input.len(), 3);
+        assert_eq!(input[2].content, vec![InferenceContent::Text { text: "next".to_string() }]);
+        assert_eq!(tool_call_ids(&input), vec!["a"]);
+    }
+
+    #[tokio::test]
+    async fn test_digest_covers_only_summarized_calls() {
+        let store = StateStore::open_memory().await.unwrap();
+        for (id, path) in [("t1", "a.txt"), ("t2", "b.txt")] {
+            store.insert_tool_execution("s", 0, id, "read_file", &json!({"path": path}), Some("contents"), false, Some(1), "allow").await.unwrap();
+        }
+        let messages = vec![
+            InferenceMessage { role: InferenceRole::User, content: vec![InferenceContent::Text { text: "read a".to_string() }], tool_call_id: None },
+            InferenceMessage {
+                role: InferenceRole::Assistant,
+                content: vec![InferenceContent::ToolUse { id: "t1".to_string(), name: "read_file".to_string(), input: json!({"path": "a.txt"}) }],
+                tool_call_id: None,
+            },
+        ];
+        let digest = tool_digest_for(&store, "s", &messages).await.unwrap();
+        assert_eq!(digest, vec!["read_file(a.txt) -> ok: contents"]);
+
+        let summary = structured_summary("The user asked for a.txt.\n", &digest);
+        assert_eq!(summary, "## Summary\nThe user asked for a.txt.\n\n## Tool calls\n- read_file(a.txt) -> ok: contents");
+        assert_eq!(structured_summary("Just chat.", &[]), "Just chat.");
+    }
+}
@@ -2,6 +2,42 @@ use anyhow::{Context, Result};
 use serde::Deserialize;
 use std::path::{Path, PathBuf};
 
+use crate::inference::pricing::ModelPricing;
+use crate::tools::builtins::{FetchConfig, ShellPolicy};
+use crate::tools::network::NetworkPolicy;
+use crate::tools::registry::{SchemaConflictPolicy, ToolSource};
+
+
+/// One problem found by [`BedrockConfig::validate_all`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{field}: {message}")]
+pub struct ConfigError {
+    /// Dotted path of the offending setting, e.g. `kernel.max_turns`
+    pub field: String,
+    pub message: String,
+}
+
+/// Every problem found in a configuration, as returned by
+/// [`BedrockConfig::validate`]; downcast an `anyhow::Error` to get the list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl std::fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0.as_slice() {
+            [single] => write!(f, "{}", single),
+            errors => {
+                write!(f, "{} configuration problems:", errors.len())?;
+                for e in errors {
+                    write!(f, "\n  - {}", e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
 
 /// Top-level Bedrock configuration, parsed from `bedrock.toml`.
 #[derive(Debug, Clone, Deserialize)]
@@ -17,6 +53,30 @@ pub struct BedrockConfig {
     pub providers: ProvidersConfig,
     #[serde(default)]
     pub embeddings: Option<EmbeddingConfig>,
+    #[serde(default)]
+    pub tools: ToolsConfig,
+    #[serde(default)]
+    pub repl: ReplConfig,
+    #[serde(default)]
+    pub memory: MemoryConfig,
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    #[serde(default)]
+    pub credentials: CredentialsConfig,
+    #[serde(default)]
+    pub mcp: McpConfig,
+    #[serde(default)]
+    pub inference: InferenceConfig,
+    #[serde(default)]
+    pub compaction: CompactionConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub server: ServerConfig,
+    /// Per-model prices overriding or extending the built-in table, keyed by
+    /// model prefix (`gpt-4o`) or provider and model prefix (`azure/gpt-4o`)
+    #[serde(default)]
+    pub pricing: std::collections::HashMap<String, ModelPricing>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -38,6 +98,59 @@ pub struct AgentConfig {
     /// Extended thinking configuration
     #[serde(default)]
     pub thinking: Option<ThinkingConfig>,
+    /// Append the session's plan (see `update_plan`) to the system prompt each turn
+    #[serde(default = "default_inject_plan")]
+    pub inject_plan: bool,
+    /// How `system_prompt` is rendered each turn
+    #[serde(default)]
+    pub prompt_template: PromptTemplate,
+    /// Cleanup steps applied, in order, to each assistant message before it is
+    /// persisted and added to history
+    #[serde(default)]
+    pub postprocessors: Vec<Postprocessor>,
+    /// Providers tried in order when a request fails with a retryable error
+    /// (rate limit, 5xx, timeout); each turn starts again from `provider`
+    #[serde(default)]
+    pub fallbacks: Vec<FallbackTarget>,
+}
+
+/// One entry of the `agent.fallbacks` chain.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FallbackTarget {
+    /// Name of a `[providers]` entry
+    pub provider: String,
+    /// Model to request from it; defaults to the model of the failed request
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// One step of the assistant message postprocessor chain (see `kernel::postprocess`).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Postprocessor {
+    /// Drop markdown code fence lines (```` ```lang ````), keeping their contents
+    StripCodeFences,
+    /// Remove trailing whitespace from every line and the end of the message
+    TrimTrailingWhitespace,
+    /// Replace credential-looking tokens with `[REDACTED]`
+    RedactSecrets,
+    /// Keep at most this many characters
+    Truncate(usize),
+    /// Require a JSON document, extracting it from surrounding prose or fences
+    EnforceJson,
+    /// Run the harness `on_assistant_message` hook at this point in the chain
+    Harness,
+}
+
+/// Template engine for the system prompt (see `kernel::template`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptTemplate {
+    /// `{{ path }}` placeholders only; unresolved ones are left as written
+    #[default]
+    Simple,
+    /// minijinja templates with conditionals, loops and filters
+    Jinja,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -57,6 +170,106 @@ pub struct KernelConfig {
     /// Heartbeat interval in seconds
     #[serde(default = "default_heartbeat_interval")]
     pub heartbeat_interval_secs: u32,
+    /// Maximum tool calls executed per turn; extra calls are dropped (unlimited if unset)
+    #[serde(default)]
+    pub max_tool_calls_per_turn: Option<u32>,
+    /// Tool calls of a turn executing at once; the rest wait for a free slot
+    /// and results keep call order (unlimited if unset, 1 runs them in turn)
+    #[serde(default)]
+    pub max_parallel_tool_calls: Option<u32>,
+    /// Time-to-live for cross-session tool result cache entries, in seconds
+    #[serde(default = "default_tool_cache_ttl")]
+    pub tool_cache_ttl_secs: u64,
+    /// Times a turn is re-run when its outcome is unusable (empty or malformed tool calls)
+    #[serde(default)]
+    pub max_turn_retries: u32,
+    /// Replace provider tool call ids with stable `call_<turn>_<n>` ids (for golden tests)
+    #[serde(default)]
+    pub deterministic_tool_ids: bool,
+    /// Hard cap on estimated session spend in USD; the run aborts when crossed
+    /// (unlimited if unset). `limits.max_cost_usd` takes precedence.
+    #[serde(default)]
+    pub cost_budget_usd: Option<f64>,
+    /// Who drives the agent loop
+    #[serde(default)]
+    pub mode: LoopMode,
+    /// Last N turns that trimming/compaction must leave verbatim (0 = no floor)
+    #[serde(default)]
+    pub min_retained_turns: u32,
+    /// Model context window in tokens, reported to the harness as
+    /// `ctx:get_token_limit()` and checked against the retention floor
+    #[serde(default = "default_context_window_tokens")]
+    pub context_window_tokens: u32,
+    /// Drop older history and retry once when the provider rejects a request as too long
+    #[serde(default = "default_trim_on_context_overflow")]
+    pub trim_on_context_overflow: bool,
+    /// Follow-up requests made when a response is cut off at the output token limit (0 = never)
+    #[serde(default)]
+    pub max_continuations: u32,
+    /// Largest prompt accepted by `run`, in estimated tokens (unlimited if unset)
+    #[serde(default)]
+    pub max_prompt_tokens: Option<u32>,
+    /// Halt the run once more than this many tool executions in a row have
+    /// failed; a successful call resets the count (unlimited if unset)
+    #[serde(default)]
+    pub max_consecutive_tool_errors: Option<u32>,
+    /// Answer a tool call identical to the one before it with that call's
+    /// result instead of running it again; the repeat counts as a failed call
+    #[serde(default)]
+    pub dedup_tool_calls: bool,
+    /// What `Kernel::run` does once the prompt queue drains
+    #[serde(default)]
+    pub on_queue_empty: QueueEmptyPolicy,
+    /// Start tool calls as soon as they arrive in the stream instead of after
+    /// the whole response
+    #[serde(default)]
+    pub eager_tool_dispatch: EagerDispatch,
+}
+
+/// Which tool calls start running while the response is still streaming.
+///
+/// Calls past `max_tool_calls_per_turn`, repeats caught by
+/// `dedup_tool_calls`, calls with malformed arguments, calls matching
+/// `tools.requires_approval` and calls the harness escalates or rejects
+/// always wait for the end of the response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EagerDispatch {
+    /// Run tools after the response ends
+    #[default]
+    Off,
+    /// Start read-only tools early; others wait for the end of the response
+    ReadOnly,
+    /// Start every allowed tool early. A call started by a response that is
+    /// then interrupted or retried (`max_turn_retries`) has already run.
+    All,
+}
+
+/// What a run does when its prompt queue is empty.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueEmptyPolicy {
+    /// End the run
+    #[default]
+    Stop,
+    /// Wait for another task to be queued (by the harness or the embedding
+    /// program) until the session is interrupted
+    AwaitMore,
+    /// Ask the harness `should_continue` hook, which may queue more tasks;
+    /// otherwise end the run
+    CompletionCheck,
+}
+
+/// Who drives the agent loop.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoopMode {
+    /// The kernel's built-in turn loop (inference, tool dispatch, repeat)
+    #[default]
+    Builtin,
+    /// The harness drives the loop from `on_orchestrate` using `bedrock.call_model`,
+    /// `bedrock.run_tool` and `bedrock.append_message`
+    Orchestrator,
 }
 
 impl Default for KernelConfig {
@@ -65,21 +278,61 @@ impl Default for KernelConfig {
             workspace_root: default_workspace_root(),
             max_turns: default_max_turns(),
             heartbeat_interval_secs: default_heartbeat_interval(),
+            max_tool_calls_per_turn: None,
+            max_parallel_tool_calls: None,
+            tool_cache_ttl_secs: default_tool_cache_ttl(),
+            max_turn_retries: 0,
+            deterministic_tool_ids: false,
+            cost_budget_usd: None,
+            mode: LoopMode::Builtin,
+            min_retained_turns: 0,
+            context_window_tokens: default_context_window_tokens(),
+            trim_on_context_overflow: default_trim_on_context_overflow(),
+            max_continuations: 0,
+            max_prompt_tokens: None,
+            max_consecutive_tool_errors: None,
+            dedup_tool_calls: false,
+            on_queue_empty: QueueEmptyPolicy::Stop,
+            eager_tool_dispatch: EagerDispatch::Off,
         }
     }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct PersistenceConfig {
-    /// Path to the libSQL database file
+    /// Path to the libSQL database file; may use `{date}`, `{workspace_name}` and `~/`
     #[serde(default = "default_database_path")]
     pub database_path: String,
+    /// Store raw provider responses (redacted) for debugging; inflates storage
+    #[serde(default)]
+    pub store_raw_responses: bool,
+    /// How often an in-progress streamed assistant message is saved, in ms (0 disables)
+    #[serde(default = "default_partial_message_interval")]
+    pub partial_message_interval_ms: u64,
+    /// Store event payloads and tool arguments as indented JSON (dev aid; larger rows)
+    #[serde(default)]
+    pub pretty_payloads: bool,
+    /// Snapshot the live session every N turns so resuming skips full replay (0 disables)
+    #[serde(default)]
+    pub snapshot_interval_turns: u32,
+    /// Truncate tool args stored in the execution log past this many bytes (None stores them in full)
+    #[serde(default)]
+    pub max_logged_arg_size: Option<usize>,
+    /// Keep the full value of truncated tool args in the artifacts table
+    #[serde(default)]
+    pub spill_logged_args: bool,
 }
 
 impl Default for PersistenceConfig {
     fn default() -> Self {
         Self {
             database_path: default_database_path(),
+            store_raw_responses: false,
+            partial_message_interval_ms: default_partial_message_interval(),
+            pretty_payloads: false,
+            snapshot_interval_turns: 0,
+            max_logged_arg_size: None,
+            spill_logged_args: false,
         }
     }
 }
@@ -104,151 +357,1104 @@ impl Default for HarnessConfig {
     }
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct ToolsConfig {
+    /// Register no tools at all (pure chat); the provider gets no tools parameter
+    #[serde(default = "default_tools_enabled")]
+    pub enabled: bool,
+    /// Which source wins when tools share a name, highest first
+    #[serde(default = "default_tool_precedence")]
+    pub precedence: Vec<ToolSource>,
+    /// Prefix MCP tool names with their server name (`<server>__<tool>`).
+    /// Without it, only tools whose name a server listed earlier already uses
+    /// are prefixed
+    #[serde(default)]
+    pub namespace_mcp: bool,
+    /// Offer tools under other names, as `alias = "tool"`; the tool is then
+    /// listed and called only by its alias
+    #[serde(default)]
+    pub aliases: std::collections::HashMap<String, String>,
+    /// What to do when same-named tools from different sources have different
+    /// parameter schemas: `error` (default) refuses the later tool, `precedence`
+    /// resolves it like any other clash
+    #[serde(default)]
+    pub on_schema_conflict: SchemaConflictPolicy,
+    /// Tool names resolved by precedence even when their schemas differ
+    #[serde(default)]
+    pub schema_overrides: Vec<String>,
+    /// Concurrent executions allowed per tool resource key (1 for keys not listed)
+    #[serde(default)]
+    pub resource_limits: std::collections::HashMap<String, usize>,
+    /// Safe mode: only read-only tools run; others fail with `PermissionDenied`
+    /// until listed in `allow` or unlocked by a harness (`bedrock.unlock_tool`)
+    #[serde(default)]
+    pub safe: bool,
+    /// Tools unlocked up front in safe mode
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Milliseconds a tool call may run before it is cancelled (default 0, no
+    /// limit). A limit also applies to `delegate`, whose call lasts a whole
+    /// sub-agent run, unless `[tools.timeouts]` exempts it with 0.
+    #[serde(default = "default_tool_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Per-tool `timeout_ms` overrides, by the tool's own name (not an alias)
+    #[serde(default)]
+    pub timeouts: std::collections::HashMap<String, u64>,
+    /// Calls that wait for the user's approval, as `tool` or `tool(arg-pattern)`
+    /// rules naming the tool's own name, not an alias (see [`crate::tools::approval`])
+    #[serde(default)]
+    pub requires_approval: Vec<String>,
+    /// What `shell_exec` may run and how (`[tools.shell]`)
+    #[serde(default)]
+    pub shell: ShellPolicy,
+    /// Hosts network tools such as `fetch_url` may reach (`[tools.network]`)
+    #[serde(default)]
+    pub network: NetworkPolicy,
+    /// Limits and secret headers for `fetch_url` (`[tools.fetch]`)
+    #[serde(default)]
+    pub fetch: FetchConfig,
+    /// Directories outside `workspace_root` that file tools may also use
+    /// (absolute, `~/`, or relative to `workspace_root`)
+    #[serde(default)]
+    pub allowed_paths: Vec<String>,
+}
+
+impl ToolsConfig {
+    /// How long `tool` may run, or `None` when it has no limit.
+    pub fn timeout_for(&self, tool: &str) -> Option<std::time::Duration> {
+        let ms = self.timeouts.get(tool).copied().unwrap_or(self.timeout_ms);
+        (ms > 0).then(|| std::time::Duration::from_millis(ms))
+    }
+
+    /// `allowed_paths` with `~/` expanded.
+    pub fn allowed_dirs(&self) -> Vec<PathBuf> {
+        self.allowed_paths
+            .iter()
+            .map(|dir| match (dir.strip_prefix("~/"), std::env::var_os("HOME")) {
+                (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+                _ => PathBuf::from(dir),
+            })
+            .collect()
+    }
+}
+
+impl Default for ToolsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_tools_enabled(),
+            precedence: default_tool_precedence(),
+            namespace_mcp: false,
+            aliases: Default::default(),
+            on_schema_conflict: SchemaConflictPolicy::default(),
+            schema_overrides: Vec::new(),
+            resource_limits: Default::default(),
+            safe: false,
+            allow: Vec::new(),
+            timeout_ms: default_tool_timeout_ms(),
+            timeouts: Default::default(),
+            requires_approval: Vec::new(),
+            shell: ShellPolicy::default(),
+            network: NetworkPolicy::default(),
+            fetch: FetchConfig::default(),
+            allowed_paths: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReplConfig {
+    /// End the REPL session after this many minutes without input (0 disables)
+    #[serde(default)]
+    pub idle_timeout: u64,
+}
+
+impl ReplConfig {
+    /// The idle timeout as a duration, or `None` when disabled.
+    pub fn idle_timeout(&self) -> Option<std::time::Duration> {
+        (self.idle_timeout > 0).then(|| std::time::Duration::from_secs(self.idle_timeout * 60))
+    }
+}
+
+/// Automatic memory capture from tool results.
+///
+/// A successful result is stored as a memory when its tool is in
+/// `capture_tools` (or the list is empty) and it is at least
+/// `capture_min_chars` long. A harness `on_memory_capture` hook can override
+/// that decision per result by returning `MODIFY, { worth_remembering = ... }`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MemoryConfig {
+    /// Store qualifying tool results as memories
+    #[serde(default)]
+    pub capture_tool_results: bool,
+    /// Tools whose results are captured (all tools if empty)
+    #[serde(default)]
+    pub capture_tools: Vec<String>,
+    /// Shortest result captured, in characters
+    #[serde(default = "default_capture_min_chars")]
+    pub capture_min_chars: usize,
+    /// Longer results are cut to this many characters before embedding
+    #[serde(default = "default_capture_max_chars")]
+    pub capture_max_chars: usize,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            capture_tool_results: false,
+            capture_tools: Vec::new(),
+            capture_min_chars: default_capture_min_chars(),
+            capture_max_chars: default_capture_max_chars(),
+        }
+    }
+}
+
+impl MemoryConfig {
+    /// Whether the default policy captures a successful `tool` result.
+    pub fn captures(&self, tool: &str, content: &str) -> bool {
+        self.capture_tool_results
+            && (self.capture_tools.is_empty() || self.capture_tools.iter().any(|t| t == tool))
+            && content.trim().chars().count() >= self.capture_min_chars
+    }
+}
+
+/// Background storage maintenance, run off the hot path while the kernel is
+/// idle (see [`crate::kernel::maintenance`]).
+#[derive(Debug, Clone, Deserialize)]
+pub struct MaintenanceConfig {
+    /// Spawn the maintenance task
+    #[serde(default)]
+    pub enabled: bool,
+    /// Seconds between maintenance passes
+    #[serde(default = "default_maintenance_interval")]
+    pub interval_secs: u64,
+    /// A pass only runs once no run has been active for this many seconds
+    #[serde(default = "default_maintenance_idle")]
+    pub idle_secs: u64,
+    /// Jobs each pass runs, in order
+    #[serde(default = "default_maintenance_jobs")]
+    pub jobs: Vec<MaintenanceJob>,
+    /// Rows deleted per transaction
+    #[serde(default = "default_maintenance_batch_size")]
+    pub batch_size: usize,
+    /// `prune_logs` deletes events and raw responses older than this (unset
+    /// keeps them all)
+    #[serde(default)]
+    pub log_max_age_days: Option<u32>,
+    /// `memory_decay` deletes memories older than this (unset keeps them all)
+    #[serde(default)]
+    pub memory_max_age_days: Option<u32>,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: default_maintenance_interval(),
+            idle_secs: default_maintenance_idle(),
+            jobs: default_maintenance_jobs(),
+            batch_size: default_maintenance_batch_size(),
+            log_max_age_days: None,
+            memory_max_age_days: None,
+        }
+    }
+}
+
+/// A background maintenance job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceJob {
+    /// Delete expired harness KV entries and tool cache rows
+    ExpireKv,
+    /// Delete events and raw responses older than `log_max_age_days`
+    PruneLogs,
+    /// Delete memories older than `memory_max_age_days`
+    MemoryDecay,
+}
+
+impl std::fmt::Display for MaintenanceJob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MaintenanceJob::ExpireKv => write!(f, "expire_kv"),
+            MaintenanceJob::PruneLogs => write!(f, "prune_logs"),
+            MaintenanceJob::MemoryDecay => write!(f, "memory_decay"),
+        }
+    }
+}
+
 pub type ProvidersConfig = std::collections::HashMap<String, ProviderConfig>;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ProviderConfig {
-    /// The type of provider ("anthropic", "openai", "mock")
+    /// The type of provider ("anthropic", "openai", "ollama", "mock")
     #[serde(rename = "type")]
     pub kind: String,
     /// Environment variable name containing the API key
     pub api_key_env: Option<String>,
     /// Optional base URL override (for proxies)
     pub base_url: Option<String>,
+    /// Authentication scheme; defaults to the static key in `api_key_env`
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+    /// How tool results are sent to the provider
+    #[serde(default)]
+    pub tool_results: ToolResultMode,
+    /// Provider-specific parameters merged verbatim into every request body
+    /// (e.g. `reasoning_effort`, `logit_bias`); must be a table
+    #[serde(default)]
+    pub extra_body: Option<serde_json::Value>,
+    /// Drop reasoning blocks from the history sent back to the provider; they
+    /// are still streamed, persisted and kept in the session
+    #[serde(default = "default_strip_thinking")]
+    pub strip_thinking: bool,
 }
 
-// ─── Defaults ────────────────────────────────────────────────────
+/// Top-level request body fields bedrock sets itself.
+const REQUEST_BODY_FIELDS: &[&str] = &["model", "messages", "system", "tools", "temperature", "max_tokens", "thinking", "stream"];
 
-fn default_system_prompt() -> String {
-    "You are a helpful coding assistant.".to_string()
+impl ProviderConfig {
+    /// `extra_body` keys that replace a field bedrock sets, sorted.
+    pub fn extra_body_collisions(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = match &self.extra_body {
+            Some(serde_json::Value::Object(body)) => body.keys().map(String::as_str).filter(|k| REQUEST_BODY_FIELDS.contains(k)).collect(),
+            _ => Vec::new(),
+        };
+        keys.sort();
+        keys
+    }
 }
 
-fn default_workspace_root() -> String {
-    ".".to_string()
+/// How tool results are represented in requests to a provider.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolResultMode {
+    /// Native tool-result content blocks
+    #[default]
+    Native,
+    /// Labeled text blocks inside a user message, for providers without a tool-result role
+    Text,
 }
 
-fn default_max_turns() -> u32 {
-    50
+/// How a provider obtains its credentials.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthConfig {
+    /// Static key read from an environment variable
+    Env { var: String },
+    /// Token printed to stdout by a command, cached and refreshed after `ttl_secs`
+    Command {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default = "default_auth_ttl")]
+        ttl_secs: u64,
+    },
 }
 
-fn default_heartbeat_interval() -> u32 {
-    30
+/// Where provider keys are looked up when a provider has no `auth` table
+/// and its `api_key_env` variable is unset (see [`crate::inference::credentials`]).
+#[derive(Debug, Clone, Deserialize)]
+pub struct CredentialsConfig {
+    #[serde(default)]
+    pub store: CredentialStore,
+    /// Credentials file used by `store = "file"`
+    #[serde(default = "default_credentials_path")]
+    pub path: String,
 }
 
-fn default_database_path() -> String {
-    ".bedrock/state.db".to_string()
+impl Default for CredentialsConfig {
+    fn default() -> Self {
+        Self { store: CredentialStore::None, path: default_credentials_path() }
+    }
 }
 
-fn default_harness_directory() -> String {
-    ".bedrock/harnesses".to_string()
+/// A store of provider keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialStore {
+    /// Only `api_key_env` and `auth`
+    #[default]
+    None,
+    /// A TOML file readable only by its owner
+    File,
+    /// The macOS keychain
+    Keychain,
 }
 
-fn default_harness_fs_root() -> String {
-    ".".to_string()
+/// MCP servers connected when the kernel starts.
+#[derive(Debug, Clone, Deserialize)]
+pub struct McpConfig {
+    /// Servers launched by `init_clients`, in precedence order
+    #[serde(default)]
+    pub servers: Vec<McpServerConfig>,
+    /// Most servers connected at once, counting ones a harness spawns later
+    /// (unset for no limit)
+    #[serde(default)]
+    pub max_servers: Option<usize>,
+    /// Servers started and queried for tools in parallel
+    #[serde(default = "default_mcp_startup_concurrency")]
+    pub startup_concurrency: usize,
+    /// A server that hasn't listed its tools by then is skipped
+    #[serde(default = "default_mcp_startup_timeout")]
+    pub startup_timeout_secs: u64,
+    /// Start servers when one of their tools is first called rather than at
+    /// startup, once their tool list is cached (overridable per server)
+    #[serde(default)]
+    pub lazy: bool,
+    /// Seconds between health checks of running servers (0 to disable)
+    #[serde(default = "default_mcp_health_check_interval")]
+    pub health_check_interval_secs: u64,
+    /// Failed restarts in a row after which a crashed server stays down
+    #[serde(default = "default_mcp_max_restarts")]
+    pub max_restarts: u32,
+    /// Wait before the first restart, doubled for each failure after it
+    #[serde(default = "default_mcp_restart_backoff")]
+    pub restart_backoff_ms: u64,
 }
 
-// ─── Loading ─────────────────────────────────────────────────────
-
-impl BedrockConfig {
-    /// Load configuration from a TOML file.
-    pub fn from_file(path: &Path) -> Result<Self> {
-        let contents = std::fs::read_to_string(path)
-            .with_context(|| format!("Could not read config file: {}", path.display()))?;
-        Self::from_str(&contents)
+impl McpConfig {
+    /// When the servers' clients may be restarted.
+    pub fn restart_policy(&self) -> crate::tools::mcp::RestartPolicy {
+        crate::tools::mcp::RestartPolicy {
+            max_restarts: self.max_restarts,
+            base_delay: std::time::Duration::from_millis(self.restart_backoff_ms),
+            startup_timeout: std::time::Duration::from_secs(self.startup_timeout_secs),
+        }
     }
+}
 
-    /// Parse configuration from a TOML string.
-    pub fn from_str(toml_str: &str) -> Result<Self> {
-        let config: BedrockConfig = toml::from_str(toml_str)
-            .with_context(|| "Failed to parse bedrock.toml")?;
-        config.validate()?;
-        Ok(config)
+impl Default for McpConfig {
+    fn default() -> Self {
+        Self {
+            servers: Vec::new(),
+            max_servers: None,
+            startup_concurrency: default_mcp_startup_concurrency(),
+            startup_timeout_secs: default_mcp_startup_timeout(),
+            lazy: false,
+            health_check_interval_secs: default_mcp_health_check_interval(),
+            max_restarts: default_mcp_max_restarts(),
+            restart_backoff_ms: default_mcp_restart_backoff(),
+        }
     }
+}
 
-    /// Validate semantic invariants that serde can't enforce.
-    pub fn validate(&self) -> Result<()> {
-        anyhow::ensure!(
-            !self.agent.model.trim().is_empty(),
-            "agent.model must not be empty"
-        );
-        if !self.providers.contains_key(&self.agent.provider) {
-            anyhow::bail!(
-                "Provider '{}' configured in [agent] but not found in [providers]",
-                self.agent.provider
-            );
+/// Hard per-session usage caps, checked after every response. Crossing a
+/// token cap stops the run with `StopReason::LimitExceeded`; the cost cap
+/// behaves like `kernel.cost_budget_usd`, which it replaces.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LimitsConfig {
+    #[serde(default)]
+    pub max_input_tokens: Option<u64>,
+    #[serde(default)]
+    pub max_output_tokens: Option<u64>,
+    #[serde(default)]
+    pub max_cost_usd: Option<f64>,
+    /// Fractions of each limit at which a `budget_warning` event is emitted
+    #[serde(default = "default_limit_warn_at")]
+    pub warn_at: Vec<f64>,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_input_tokens: None,
+            max_output_tokens: None,
+            max_cost_usd: None,
+            warn_at: default_limit_warn_at(),
         }
-        anyhow::ensure!(
-            self.kernel.max_turns > 0,
-            "kernel.max_turns must be greater than 0"
-        );
-        anyhow::ensure!(
-            self.kernel.heartbeat_interval_secs > 0,
-            "kernel.heartbeat_interval_secs must be greater than 0"
-        );
-        Ok(())
     }
+}
 
-    /// Resolve the workspace root path relative to a base directory.
-    pub fn resolve_workspace_root(&self, base: &Path) -> PathBuf {
-        let root = Path::new(&self.kernel.workspace_root);
-        if root.is_absolute() {
-            root.to_path_buf()
-        } else {
-            base.join(root)
+/// `bedrock serve`. Every request must carry `Authorization: Bearer <token>`,
+/// with the token read from the `token_env` environment variable; the server
+/// won't start without one.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerConfig {
+    #[serde(default = "default_server_token_env")]
+    pub token_env: String,
+    /// End a session with no queued prompt that has emitted no event for this
+    /// long (0 keeps sessions until shutdown)
+    #[serde(default = "default_server_idle_timeout")]
+    pub idle_timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            token_env: default_server_token_env(),
+            idle_timeout_secs: default_server_idle_timeout(),
         }
     }
 }
 
-impl Default for AgentConfig {
+/// Automatic history compaction. Once a request's estimated prompt crosses
+/// `threshold_tokens`, the turns before the last `keep_turns` are summarized
+/// by a (typically cheaper) model and replaced by that summary in the session
+/// history. The `messages` table keeps every original message.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompactionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Estimated prompt size, in tokens, that triggers a compaction
+    #[serde(default = "default_compaction_threshold")]
+    pub threshold_tokens: u64,
+    /// Most recent turns kept verbatim (never fewer than `kernel.min_retained_turns`)
+    #[serde(default = "default_compaction_keep_turns")]
+    pub keep_turns: u32,
+    /// Provider that writes the summary (default: the turn's provider)
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// Model that writes the summary (default: the turn's model)
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+impl Default for CompactionConfig {
     fn default() -> Self {
         Self {
-            system_prompt: default_system_prompt(),
-            model: "test-model".to_string(),
-            provider: "mock".to_string(),
-            thinking: None,
+            enabled: false,
+            threshold_tokens: default_compaction_threshold(),
+            keep_turns: default_compaction_keep_turns(),
+            provider: None,
+            model: None,
         }
     }
 }
 
-impl Default for BedrockConfig {
+/// How requests to providers are sent.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct InferenceConfig {
+    #[serde(default)]
+    pub retry: RetryConfig,
+}
+
+/// Exponential backoff for requests that fail with a retryable error (rate
+/// limit, 5xx, timeout). Retries stay on the same provider; `agent.fallbacks`
+/// takes over once they are used up.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RetryConfig {
+    /// Attempts per provider, counting the first; 1 disables retrying
+    #[serde(default = "default_retry_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for each one after it
+    #[serde(default = "default_retry_base_delay")]
+    pub base_delay_ms: u64,
+    /// Cap on any single delay
+    #[serde(default = "default_retry_max_delay")]
+    pub max_delay_ms: u64,
+    /// Wait a random 50-100% of each delay, so clients that failed together
+    /// don't retry together
+    #[serde(default = "default_retry_jitter")]
+    pub jitter: bool,
+}
+
+impl Default for RetryConfig {
     fn default() -> Self {
         Self {
-            agent: AgentConfig::default(),
-            kernel: KernelConfig::default(),
-            persistence: PersistenceConfig::default(),
-            harness: HarnessConfig::default(),
-            providers: ProvidersConfig::default(),
-            embeddings: None,
+            max_attempts: default_retry_max_attempts(),
+            base_delay_ms: default_retry_base_delay(),
+            max_delay_ms: default_retry_max_delay(),
+            jitter: default_retry_jitter(),
         }
     }
 }
 
-// ─── Tests ───────────────────────────────────────────────────────
+impl RetryConfig {
+    /// How long to wait before retry number `retry` (1-based).
+    pub fn delay(&self, retry: u32) -> std::time::Duration {
+        let exp = self.base_delay_ms.saturating_mul(1u64 << retry.saturating_sub(1).min(32));
+        let mut ms = exp.min(self.max_delay_ms);
+        if self.jitter {
+            use std::hash::{BuildHasher, Hasher};
+            let random = std::collections::hash_map::RandomState::new().build_hasher().finish();
+            ms = ms / 2 + random % (ms / 2 + 1);
+        }
+        std::time::Duration::from_millis(ms)
+    }
+}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// An MCP server, started over stdio (`command`) or reached over HTTP (`url`).
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct McpServerConfig {
+    /// Short name used in logs and to namespace tools; defaults to the
+    /// command name or the URL's host
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Command of a local server, spoken to over stdio
+    #[serde(default)]
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// URL of a remote server, instead of `command`
+    #[serde(default)]
+    pub url: Option<String>,
+    /// `http` (streamable HTTP, the default for a `url`) or `sse` (HTTP+SSE)
+    #[serde(default)]
+    pub transport: Option<McpTransportKind>,
+    /// Headers sent with every request to a remote server, e.g. `Authorization`
+    #[serde(default)]
+    pub headers: std::collections::HashMap<String, String>,
+    /// Overrides `mcp.lazy` for this server
+    #[serde(default)]
+    pub lazy: Option<bool>,
+}
 
-    #[test]
-    fn test_parse_full_config() {
-        let toml = r#"
-[agent]
-system_prompt = "You are a helpful coding assistant."
-model = "claude-sonnet-4-20250514"
-provider = "anthropic"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum McpTransportKind {
+    Stdio,
+    Http,
+    Sse,
+}
 
-[agent.thinking]
-enabled = false
+impl McpServerConfig {
+    /// How to reach the server.
+    pub fn to_transport(&self) -> crate::tools::mcp::McpTransport {
+        match &self.url {
+            Some(url) => crate::tools::mcp::McpTransport::Http {
+                url: url.clone(),
+                headers: self.headers.clone(),
+                sse: self.transport == Some(McpTransportKind::Sse),
+            },
+            None => crate::tools::mcp::McpTransport::Stdio { command: self.command.clone(), args: self.args.clone() },
+        }
+    }
 
-[kernel]
-workspace_root = "."
-max_turns = 50
-heartbeat_interval_secs = 30
+    /// The configured name, or one derived from the command or URL.
+    pub fn server_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.to_transport().default_name())
+    }
+}
 
-[persistence]
-database_path = ".bedrock/state.db"
+// ─── Defaults ────────────────────────────────────────────────────
 
-[harness]
+fn default_system_prompt() -> String {
+    "You are a helpful coding assistant.".to_string()
+}
+
+fn default_inject_plan() -> bool {
+    true
+}
+
+fn default_workspace_root() -> String {
+    ".".to_string()
+}
+
+fn default_max_turns() -> u32 {
+    50
+}
+
+fn default_heartbeat_interval() -> u32 {
+    30
+}
+
+fn default_tool_cache_ttl() -> u64 {
+    86_400
+}
+
+fn default_tools_enabled() -> bool {
+    true
+}
+
+fn default_tool_timeout_ms() -> u64 {
+    0
+}
+
+fn default_capture_min_chars() -> usize {
+    80
+}
+
+fn default_capture_max_chars() -> usize {
+    4_000
+}
+
+fn default_context_window_tokens() -> u32 {
+    128_000
+}
+
+fn default_trim_on_context_overflow() -> bool {
+    true
+}
+
+fn default_strip_thinking() -> bool {
+    true
+}
+
+fn default_tool_precedence() -> Vec<ToolSource> {
+    crate::tools::registry::DEFAULT_PRECEDENCE.to_vec()
+}
+
+fn default_partial_message_interval() -> u64 {
+    500
+}
+
+fn default_credentials_path() -> String {
+    "~/.bedrock/credentials.toml".to_string()
+}
+
+fn default_maintenance_interval() -> u64 {
+    300
+}
+
+fn default_maintenance_idle() -> u64 {
+    30
+}
+
+fn default_maintenance_jobs() -> Vec<MaintenanceJob> {
+    vec![MaintenanceJob::ExpireKv, MaintenanceJob::PruneLogs, MaintenanceJob::MemoryDecay]
+}
+
+fn default_maintenance_batch_size() -> usize {
+    500
+}
+
+fn default_mcp_startup_concurrency() -> usize {
+    4
+}
+
+fn default_mcp_startup_timeout() -> u64 {
+    30
+}
+
+fn default_mcp_health_check_interval() -> u64 {
+    30
+}
+
+fn default_mcp_max_restarts() -> u32 {
+    5
+}
+
+fn default_mcp_restart_backoff() -> u64 {
+    1000
+}
+
+fn default_limit_warn_at() -> Vec<f64> {
+    vec![0.8]
+}
+
+fn default_server_token_env() -> String {
+    "BEDROCK_SERVER_TOKEN".to_string()
+}
+
+fn default_server_idle_timeout() -> u64 {
+    1800
+}
+
+fn default_compaction_threshold() -> u64 {
+    96_000
+}
+
+fn default_compaction_keep_turns() -> u32 {
+    4
+}
+
+fn default_retry_max_attempts() -> u32 {
+    3
+}
+
+fn default_retry_base_delay() -> u64 {
+    1_000
+}
+
+fn default_retry_max_delay() -> u64 {
+    30_000
+}
+
+fn default_retry_jitter() -> bool {
+    true
+}
+
+fn default_auth_ttl() -> u64 {
+    3_000
+}
+
+fn default_database_path() -> String {
+    ".bedrock/state.db".to_string()
+}
+
+fn default_harness_directory() -> String {
+    ".bedrock/harnesses".to_string()
+}
+
+fn default_harness_fs_root() -> String {
+    ".".to_string()
+}
+
+// ─── Loading ─────────────────────────────────────────────────────
+
+/// Environment fallback for `agent.model`.
+pub const MODEL_ENV: &str = "BEDROCK_MODEL";
+/// Environment fallback for `agent.provider`.
+pub const PROVIDER_ENV: &str = "BEDROCK_PROVIDER";
+
+/// Where an effective agent setting came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingSource {
+    Cli,
+    Env,
+    Config,
+}
+
+impl SettingSource {
+    /// Human-readable origin, e.g. "BEDROCK_MODEL" or "--model".
+    pub fn describe(self, env_var: &str, flag: &str) -> String {
+        match self {
+            SettingSource::Cli => flag.to_string(),
+            SettingSource::Env => env_var.to_string(),
+            SettingSource::Config => "config file".to_string(),
+        }
+    }
+}
+
+/// Origins of the resolved `agent.model` and `agent.provider`.
+#[derive(Debug, Clone, Copy)]
+pub struct AgentSources {
+    pub model: SettingSource,
+    pub provider: SettingSource,
+}
+
+impl AgentSources {
+    /// Whether the model or provider was given on the command line or in
+    /// the environment rather than taken from the config file.
+    pub fn overridden(&self) -> bool {
+        self.model != SettingSource::Config || self.provider != SettingSource::Config
+    }
+}
+
+impl BedrockConfig {
+    /// Load configuration from a TOML file.
+    ///
+    /// `${VAR}` references in string values are expanded from the
+    /// environment; comments are left alone.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read config file: {}", path.display()))?;
+        let mut value: toml::Value = toml::from_str(&contents)
+            .with_context(|| "Failed to parse bedrock.toml")?;
+        crate::kernel::env::expand_env_in_toml(&mut value)
+            .with_context(|| format!("Failed to expand config file: {}", path.display()))?;
+        let config: BedrockConfig = value.try_into()
+            .with_context(|| "Failed to parse bedrock.toml")?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parse configuration from a TOML string.
+    pub fn from_str(toml_str: &str) -> Result<Self> {
+        let config: BedrockConfig = toml::from_str(toml_str)
+            .with_context(|| "Failed to parse bedrock.toml")?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Validate semantic invariants that serde can't enforce, failing with a
+    /// [`ConfigErrors`] that lists every problem found.
+    pub fn validate(&self) -> Result<()> {
+        self.validate_all().map_err(|errors| ConfigErrors(errors).into())
+    }
+
+    /// Check every semantic invariant and return all the problems found, so
+    /// they can be fixed in one pass.
+    pub fn validate_all(&self) -> std::result::Result<(), Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        let mut check = |ok: bool, field: &str, message: String| {
+            if !ok {
+                errors.push(ConfigError { field: field.to_string(), message });
+            }
+        };
+        check(!self.agent.model.trim().is_empty(), "agent.model", "must not be empty".to_string());
+        check(
+            self.providers.contains_key(&self.agent.provider),
+            "agent.provider",
+            format!("Provider '{}' configured in [agent] but not found in [providers]", self.agent.provider),
+        );
+        for (i, fallback) in self.agent.fallbacks.iter().enumerate() {
+            check(
+                self.providers.contains_key(&fallback.provider),
+                &format!("agent.fallbacks[{}].provider", i),
+                format!("Provider '{}' not found in [providers]", fallback.provider),
+            );
+        }
+        check(self.kernel.max_turns > 0, "kernel.max_turns", "must be greater than 0".to_string());
+        check(self.kernel.heartbeat_interval_secs > 0, "kernel.heartbeat_interval_secs", "must be greater than 0".to_string());
+        for (i, source) in self.tools.precedence.iter().enumerate() {
+            check(
+                !self.tools.precedence[..i].contains(source),
+                "tools.precedence",
+                format!("lists '{}' more than once", source),
+            );
+        }
+        let mut zero_limits: Vec<&String> = self.tools.resource_limits.iter().filter(|(_, limit)| **limit == 0).map(|(key, _)| key).collect();
+        zero_limits.sort();
+        for key in zero_limits {
+            check(false, &format!("tools.resource_limits.{}", key), "must be greater than 0".to_string());
+        }
+        check(
+            self.tools.allow.is_empty() || self.tools.safe,
+            "tools.allow",
+            "only applies in safe mode (tools.safe = true)".to_string(),
+        );
+        check(self.kernel.max_tool_calls_per_turn != Some(0), "kernel.max_tool_calls_per_turn", "must be greater than 0".to_string());
+        for rule in &self.tools.requires_approval {
+            if let Err(e) = crate::tools::approval::ApprovalRule::parse(rule) {
+                check(false, "tools.requires_approval", e.to_string());
+            }
+        }
+        let mut aliases: Vec<(&String, &String)> = self.tools.aliases.iter().collect();
+        aliases.sort();
+        for (i, (alias, name)) in aliases.iter().enumerate() {
+            let key = format!("tools.aliases.{}", alias);
+            check(!self.tools.aliases.contains_key(*name), &key, format!("'{}' is itself an alias", name));
+            check(
+                !aliases[..i].iter().any(|(_, other)| other == name),
+                &key,
+                format!("'{}' already has an alias", name),
+            );
+        }
+        check(self.tools.shell.max_output_bytes > 0, "tools.shell.max_output_bytes", "must be greater than 0".to_string());
+        check(self.tools.fetch.max_response_bytes > 0, "tools.fetch.max_response_bytes", "must be greater than 0".to_string());
+        check(self.tools.fetch.timeout_secs > 0, "tools.fetch.timeout_secs", "must be greater than 0".to_string());
+        check(self.kernel.max_parallel_tool_calls != Some(0), "kernel.max_parallel_tool_calls", "must be greater than 0".to_string());
+        check(self.kernel.max_prompt_tokens != Some(0), "kernel.max_prompt_tokens", "must be greater than 0".to_string());
+        check(self.kernel.context_window_tokens > 0, "kernel.context_window_tokens", "must be greater than 0".to_string());
+        check(self.memory.capture_max_chars > 0, "memory.capture_max_chars", "must be greater than 0".to_string());
+        check(self.maintenance.interval_secs > 0, "maintenance.interval_secs", "must be greater than 0".to_string());
+        check(
+            self.credentials.store != CredentialStore::Keychain || cfg!(target_os = "macos"),
+            "credentials.store",
+            "keychain is only supported on macOS".to_string(),
+        );
+        check(self.maintenance.batch_size > 0, "maintenance.batch_size", "must be greater than 0".to_string());
+        check(self.limits.max_input_tokens != Some(0), "limits.max_input_tokens", "must be greater than 0".to_string());
+        check(self.limits.max_output_tokens != Some(0), "limits.max_output_tokens", "must be greater than 0".to_string());
+        if let Some(max) = self.limits.max_cost_usd {
+            check(max > 0.0, "limits.max_cost_usd", "must be greater than 0".to_string());
+        }
+        check(
+            self.limits.warn_at.iter().all(|f| *f > 0.0 && *f <= 1.0),
+            "limits.warn_at",
+            "fractions must be in (0, 1]".to_string(),
+        );
+        check(self.compaction.threshold_tokens > 0, "compaction.threshold_tokens", "must be greater than 0".to_string());
+        check(self.compaction.keep_turns > 0, "compaction.keep_turns", "must be greater than 0".to_string());
+        if let Some(provider) = &self.compaction.provider {
+            check(
+                self.providers.contains_key(provider),
+                "compaction.provider",
+                format!("Provider '{}' not found in [providers]", provider),
+            );
+        }
+        check(self.inference.retry.max_attempts > 0, "inference.retry.max_attempts", "must be greater than 0".to_string());
+        check(
+            self.inference.retry.base_delay_ms <= self.inference.retry.max_delay_ms,
+            "inference.retry.max_delay_ms",
+            "must not be less than base_delay_ms".to_string(),
+        );
+        check(self.mcp.startup_concurrency > 0, "mcp.startup_concurrency", "must be greater than 0".to_string());
+        check(self.mcp.startup_timeout_secs > 0, "mcp.startup_timeout_secs", "must be greater than 0".to_string());
+        check(self.mcp.restart_backoff_ms > 0, "mcp.restart_backoff_ms", "must be greater than 0".to_string());
+        for (i, server) in self.mcp.servers.iter().enumerate() {
+            let field = format!("mcp.servers[{}]", i);
+            match &server.url {
+                Some(url) => {
+                    let scheme = reqwest::Url::parse(url).map(|url| url.scheme().to_string());
+                    check(
+                        matches!(scheme.as_deref(), Ok("http" | "https")),
+                        &format!("{}.url", field),
+                        format!("'{}' is not an http(s) URL", url),
+                    );
+                    check(server.command.is_empty(), &field, "set either command or url, not both".to_string());
+                    check(
+                        server.transport != Some(McpTransportKind::Stdio),
+                        &format!("{}.transport", field),
+                        "stdio needs a command, not a url".to_string(),
+                    );
+                }
+                None => {
+                    check(!server.command.is_empty(), &field, "needs a command or a url".to_string());
+                    check(
+                        matches!(server.transport, None | Some(McpTransportKind::Stdio)),
+                        &format!("{}.transport", field),
+                        "http and sse need a url".to_string(),
+                    );
+                    check(server.headers.is_empty(), &format!("{}.headers", field), "only apply to a url".to_string());
+                }
+            }
+        }
+        check(
+            self.kernel.max_consecutive_tool_errors != Some(0),
+            "kernel.max_consecutive_tool_errors",
+            "must be greater than 0".to_string(),
+        );
+        let mut providers: Vec<_> = self.providers.iter().collect();
+        providers.sort_by_key(|(name, _)| *name);
+        for (name, provider) in providers {
+            check(
+                provider.extra_body.as_ref().is_none_or(|body| body.is_object()),
+                &format!("providers.{}.extra_body", name),
+                "must be a table".to_string(),
+            );
+        }
+        if let Err(e) = crate::kernel::template::check(self.agent.prompt_template, &self.agent.system_prompt) {
+            check(false, "agent.system_prompt", e.to_string());
+        }
+        if let Err(e) = crate::persistence::path::check_template(&self.persistence.database_path) {
+            check(false, "persistence.database_path", e.to_string());
+        }
+        check(
+            !self.agent.postprocessors.contains(&Postprocessor::Truncate(0)),
+            "agent.postprocessors",
+            "truncate must keep at least 1 character".to_string(),
+        );
+        let mut pricing: Vec<_> = self.pricing.iter().collect();
+        pricing.sort_by_key(|(key, _)| *key);
+        for (key, price) in pricing {
+            check(
+                [price.input_per_mtok, price.output_per_mtok].iter().all(|p| p.is_finite() && *p >= 0.0),
+                &format!("pricing.{}", key),
+                "prices must be non-negative".to_string(),
+            );
+        }
+        if let Some(budget) = self.kernel.cost_budget_usd {
+            check(budget > 0.0, "kernel.cost_budget_usd", "must be greater than 0".to_string());
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The session spend cap in USD: `limits.max_cost_usd`, else `kernel.cost_budget_usd`.
+    pub fn cost_budget_usd(&self) -> Option<f64> {
+        self.limits.max_cost_usd.or(self.kernel.cost_budget_usd)
+    }
+
+    /// The database to open: `persistence.database_path` with its
+    /// placeholders (`{date}`, `{workspace_name}`, `~/`) expanded as of now.
+    pub fn database_path(&self) -> String {
+        crate::persistence::path::expand(
+            &self.persistence.database_path,
+            &self.kernel.workspace_root,
+            std::time::SystemTime::now(),
+        )
+    }
+
+    /// Apply model/provider overrides and re-validate. Precedence is CLI flag,
+    /// then `BEDROCK_MODEL` / `BEDROCK_PROVIDER`, then the config file; empty
+    /// environment values are ignored.
+    pub fn resolve_agent(&mut self, model: Option<String>, provider: Option<String>) -> Result<AgentSources> {
+        let env = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        self.resolve_agent_from(model, provider, env(MODEL_ENV), env(PROVIDER_ENV))
+    }
+
+    fn resolve_agent_from(
+        &mut self,
+        cli_model: Option<String>,
+        cli_provider: Option<String>,
+        env_model: Option<String>,
+        env_provider: Option<String>,
+    ) -> Result<AgentSources> {
+        let pick = |cli: Option<String>, env: Option<String>, target: &mut String| match (cli, env) {
+            (Some(v), _) => {
+                *target = v;
+                SettingSource::Cli
+            }
+            (None, Some(v)) => {
+                *target = v;
+                SettingSource::Env
+            }
+            (None, None) => SettingSource::Config,
+        };
+        let sources = AgentSources {
+            model: pick(cli_model, env_model, &mut self.agent.model),
+            provider: pick(cli_provider, env_provider, &mut self.agent.provider),
+        };
+        self.validate().with_context(|| {
+            format!(
+                "Invalid configuration for model '{}' (from {}) and provider '{}' (from {})",
+                self.agent.model, sources.model.describe(MODEL_ENV, "--model"),
+                self.agent.provider, sources.provider.describe(PROVIDER_ENV, "--provider"),
+            )
+        })?;
+        Ok(sources)
+    }
+
+    /// Resolve the workspace root path relative to a base directory.
+    pub fn resolve_workspace_root(&self, base: &Path) -> PathBuf {
+        let root = Path::new(&self.kernel.workspace_root);
+        if root.is_absolute() {
+            root.to_path_buf()
+        } else {
+            base.join(root)
+        }
+    }
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            system_prompt: default_system_prompt(),
+            model: "test-model".to_string(),
+            provider: "mock".to_string(),
+            thinking: None,
+            inject_plan: default_inject_plan(),
+            prompt_template: PromptTemplate::default(),
+            postprocessors: Vec::new(),
+            fallbacks: Vec::new(),
+        }
+    }
+}
+
+impl Default for BedrockConfig {
+    fn default() -> Self {
+        Self {
+            agent: AgentConfig::default(),
+            kernel: KernelConfig::default(),
+            persistence: PersistenceConfig::default(),
+            harness: HarnessConfig::default(),
+            providers: ProvidersConfig::default(),
+            embeddings: None,
+            tools: ToolsConfig::default(),
+            repl: ReplConfig::default(),
+            memory: MemoryConfig::default(),
+            maintenance: MaintenanceConfig::default(),
+            credentials: CredentialsConfig::default(),
+            mcp: McpConfig::default(),
+            inference: InferenceConfig::default(),
+            compaction: CompactionConfig::default(),
+            limits: LimitsConfig::default(),
+            server: ServerConfig::default(),
+            pricing: Default::default(),
+        }
+    }
+}
+
+// ─── Tests ───────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_full_config() {
+        let toml = r#"
+[agent]
+system_prompt = "You are a helpful coding assistant."
+model = "claude-sonnet-4-20250514"
+provider = "anthropic"
+
+[agent.thinking]
+enabled = false
+
+[kernel]
+workspace_root = "."
+max_turns = 50
+heartbeat_interval_secs = 30
+
+[persistence]
+database_path = ".bedrock/state.db"
+
+[harness]
 directory = ".bedrock/harnesses"
 
 [providers.anthropic]
@@ -374,15 +1580,553 @@ provider = "google"
     }
 
     #[test]
-    fn test_validate_zero_max_turns() {
+    fn test_validate_zero_max_tool_calls_per_turn() {
         let toml = r#"
 [agent]
 model = "gpt-4o"
 provider = "openai"
 
+[providers.openai]
+type = "openai"
+
 [kernel]
-max_turns = 0
+max_tool_calls_per_turn = 0
 "#;
         assert!(BedrockConfig::from_str(toml).is_err());
+        let err = BedrockConfig::from_str(&toml.replace("max_tool_calls_per_turn", "max_parallel_tool_calls")).unwrap_err();
+        assert!(format!("{:#}", err).contains("kernel.max_parallel_tool_calls"));
+    }
+
+    #[test]
+    fn test_provider_extra_body() {
+        let toml = r#"
+[agent]
+model = "gpt-4o"
+provider = "openai"
+
+[providers.openai]
+type = "openai"
+extra_body = { reasoning_effort = "high", max_tokens = 10, provider = { order = ["a"] } }
+"#;
+        let config = BedrockConfig::from_str(toml).unwrap();
+        let openai = &config.providers["openai"];
+        assert_eq!(openai.extra_body.as_ref().unwrap()["provider"]["order"][0], "a");
+        assert_eq!(openai.extra_body_collisions(), vec!["max_tokens"]);
+
+        let mut config = config;
+        config.providers.get_mut("openai").unwrap().extra_body = Some(serde_json::json!("high"));
+        let errors = config.validate_all().unwrap_err();
+        assert_eq!(errors[0].field, "providers.openai.extra_body");
+    }
+
+    #[test]
+    fn test_agent_fallbacks() {
+        let toml = r#"
+[agent]
+model = "claude-sonnet-4-20250514"
+provider = "anthropic"
+
+[[agent.fallbacks]]
+provider = "openai"
+model = "gpt-4o"
+
+[[agent.fallbacks]]
+provider = "local"
+
+[providers.anthropic]
+type = "anthropic"
+
+[providers.openai]
+type = "openai"
+"#;
+        let errors = toml::from_str::<BedrockConfig>(toml).unwrap().validate_all().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "agent.fallbacks[1].provider");
+
+        let config = BedrockConfig::from_str(&format!("{}\n[providers.local]\ntype = \"ollama\"\n", toml)).unwrap();
+        assert_eq!(config.agent.fallbacks[0].model.as_deref(), Some("gpt-4o"));
+        assert_eq!(config.agent.fallbacks[1], FallbackTarget { provider: "local".to_string(), model: None });
+    }
+
+    #[test]
+    fn test_limits_config() {
+        let toml = r#"
+[agent]
+model = "gpt-4o"
+provider = "openai"
+
+[providers.openai]
+type = "openai"
+
+[kernel]
+cost_budget_usd = 5.0
+
+[limits]
+max_input_tokens = 1000000
+warn_at = [0.5, 0.9]
+"#;
+        let config = BedrockConfig::from_str(toml).unwrap();
+        assert_eq!(config.limits.max_input_tokens, Some(1_000_000));
+        assert_eq!(config.limits.warn_at, vec![0.5, 0.9]);
+        assert_eq!(config.cost_budget_usd(), Some(5.0));
+        let config = BedrockConfig::from_str(&format!("{}max_cost_usd = 2.0\n", toml)).unwrap();
+        assert_eq!(config.cost_budget_usd(), Some(2.0));
+
+        assert!(BedrockConfig::from_str(&toml.replace("[0.5, 0.9]", "[1.5]")).is_err());
+        assert!(BedrockConfig::from_str(&toml.replace("1000000", "0")).is_err());
+    }
+
+    #[test]
+    fn test_pricing_config() {
+        let toml = r#"
+[agent]
+model = "llama3.1"
+provider = "ollama"
+
+[providers.ollama]
+type = "ollama"
+
+[pricing."gpt-4o"]
+input_per_mtok = 2.0
+output_per_mtok = 8.0
+
+[pricing."ollama/llama3"]
+input_per_mtok = 0.0
+output_per_mtok = 0.0
+"#;
+        let config = BedrockConfig::from_str(toml).unwrap();
+        assert_eq!(config.pricing.len(), 2);
+        assert_eq!(config.pricing["gpt-4o"].output_per_mtok, 8.0);
+
+        let err = BedrockConfig::from_str(&toml.replace("= 8.0", "= -1.0")).unwrap_err();
+        assert!(err.to_string().contains("pricing.gpt-4o"), "{}", err);
+    }
+
+    #[test]
+    fn test_retry_config() {
+        let toml = r#"
+[agent]
+model = "gpt-4o"
+provider = "openai"
+
+[providers.openai]
+type = "openai"
+
+[inference.retry]
+base_delay_ms = 100
+max_delay_ms = 500
+jitter = false
+"#;
+        let retry = BedrockConfig::from_str(toml).unwrap().inference.retry;
+        assert_eq!(retry.max_attempts, 3);
+        let delays: Vec<u64> = (1..=5).map(|n| retry.delay(n).as_millis() as u64).collect();
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+
+        let jittered = RetryConfig { jitter: true, ..retry };
+        for _ in 0..20 {
+            let ms = jittered.delay(3).as_millis();
+            assert!((200..=400).contains(&ms), "{}", ms);
+        }
+        assert!(BedrockConfig::from_str(&toml.replace("max_delay_ms = 500", "max_delay_ms = 50")).is_err());
+        assert!(BedrockConfig::from_str(&format!("{}max_attempts = 0\n", toml)).is_err());
+    }
+
+    #[test]
+    fn test_validate_zero_max_consecutive_tool_errors() {
+        let toml = r#"
+[agent]
+model = "gpt-4o"
+provider = "openai"
+
+[providers.openai]
+type = "openai"
+
+[kernel]
+max_consecutive_tool_errors = 0
+"#;
+        assert!(BedrockConfig::from_str(toml).is_err());
+        assert_eq!(BedrockConfig::from_str(&toml.replace("= 0", "= 3")).unwrap().kernel.max_consecutive_tool_errors, Some(3));
+    }
+
+    #[test]
+    fn test_validate_zero_max_turns() {
+        let toml = r#"
+[agent]
+model = "gpt-4o"
+provider = "openai"
+
+[kernel]
+max_turns = 0
+"#;
+        assert!(BedrockConfig::from_str(toml).is_err());
+    }
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let toml = r#"
+[agent]
+model = "gpt-4o"
+provider = "openai"
+
+[kernel]
+max_turns = 0
+max_tool_calls_per_turn = 0
+
+[persistence]
+database_path = "{dat}.db"
+"#;
+        let config: BedrockConfig = toml::from_str(toml).unwrap();
+        let errors = config.validate_all().unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["agent.provider", "kernel.max_turns", "kernel.max_tool_calls_per_turn", "persistence.database_path"]);
+        assert_eq!(errors[1].to_string(), "kernel.max_turns: must be greater than 0");
+
+        // The anyhow form keeps the list and names each problem
+        let err = BedrockConfig::from_str(toml).unwrap_err();
+        let listed = err.downcast_ref::<ConfigErrors>().unwrap();
+        assert_eq!(listed.0, errors);
+        assert!(err.to_string().starts_with("4 configuration problems:\n  - agent.provider: "), "{}", err);
+    }
+
+    #[test]
+    fn test_memory_capture_policy() {
+        let toml = r#"
+[agent]
+model = "m"
+provider = "mock"
+
+[memory]
+capture_tool_results = true
+capture_tools = ["shell_exec"]
+capture_min_chars = 10
+"#;
+        let config: BedrockConfig = toml::from_str(toml).unwrap();
+        let memory = &config.memory;
+        assert!(memory.captures("shell_exec", "error[E0308]: mismatched types"));
+        assert!(!memory.captures("shell_exec", "   ok    "));
+        assert!(!memory.captures("read_file", "error[E0308]: mismatched types"));
+        assert_eq!(memory.capture_max_chars, 4_000);
+
+        // Off by default
+        assert!(!MemoryConfig::default().captures("shell_exec", &"x".repeat(1_000)));
+    }
+
+    #[test]
+    fn test_parse_provider_auth_command() {
+        let toml = r#"
+[agent]
+model = "gemini-pro"
+provider = "gateway"
+
+[providers.gateway]
+type = "openai"
+base_url = "https://gateway.example.com/v1"
+
+[providers.gateway.auth]
+type = "command"
+command = "gcloud"
+args = ["auth", "print-access-token"]
+"#;
+        let config = BedrockConfig::from_str(toml).unwrap();
+        let auth = config.providers["gateway"].auth.clone().unwrap();
+        assert_eq!(
+            auth,
+            AuthConfig::Command {
+                command: "gcloud".to_string(),
+                args: vec!["auth".to_string(), "print-access-token".to_string()],
+                ttl_secs: 3_000,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_provider_tool_results_mode() {
+        let toml = r#"
+[agent]
+model = "local-model"
+provider = "local"
+
+[providers.local]
+type = "openai"
+tool_results = "text"
+
+[providers.openai]
+type = "openai"
+"#;
+        let config = BedrockConfig::from_str(toml).unwrap();
+        assert_eq!(config.providers["local"].tool_results, ToolResultMode::Text);
+        assert_eq!(config.providers["openai"].tool_results, ToolResultMode::Native);
+    }
+
+    #[test]
+    fn test_parse_tools_config() {
+        let toml = r#"
+[agent]
+model = "gpt-4o"
+provider = "openai"
+
+[providers.openai]
+type = "openai"
+
+[tools]
+precedence = ["mcp", "builtin"]
+allowed_paths = ["/opt/shared", "~/notes"]
+namespace_mcp = true
+enabled = false
+on_schema_conflict = "precedence"
+schema_overrides = ["read_file"]
+
+[tools.resource_limits]
+github-api = 2
+
+[tools.aliases]
+search = "brave__web_search"
+
+[tools.timeouts]
+shell_exec = 0
+fetch = 5000
+
+[tools.shell]
+deny = ["rm", "git push*"]
+env_deny = ["*_API_KEY"]
+dry_run = true
+
+[tools.network]
+allow_domains = ["github.com"]
+
+[tools.fetch.headers."api.github.com"]
+Authorization = "Bearer abc"
+"#;
+        let approval = toml.replace("enabled = false", "enabled = false\nrequires_approval = [\"shell_exec(git push*)\"]");
+        assert_eq!(BedrockConfig::from_str(&approval).unwrap().tools.requires_approval, vec!["shell_exec(git push*)".to_string()]);
+        let err = BedrockConfig::from_str(&approval.replace("push*)", "push*")).unwrap_err();
+        assert!(format!("{:#}", err).contains("tools.requires_approval"));
+        let config = BedrockConfig::from_str(toml).unwrap();
+        assert_eq!(config.tools.precedence, vec![ToolSource::Mcp, ToolSource::Builtin]);
+        assert!(config.tools.namespace_mcp);
+        assert!(!config.tools.enabled);
+        assert!(ToolsConfig::default().enabled);
+        assert_eq!(config.tools.on_schema_conflict, SchemaConflictPolicy::Precedence);
+        assert_eq!(config.tools.shell.deny, vec!["rm".to_string(), "git push*".to_string()]);
+        assert!(config.tools.shell.dry_run && !config.tools.shell.pin_cwd);
+        assert_eq!(config.tools.shell.max_output_bytes, 100_000);
+        assert_eq!(config.tools.network.allow_domains, vec!["github.com".to_string()]);
+        assert_eq!(config.tools.fetch.headers["api.github.com"]["Authorization"], "Bearer abc");
+        assert_eq!(config.tools.fetch.timeout_secs, 30);
+        let dirs = config.tools.allowed_dirs();
+        assert_eq!(dirs[0], PathBuf::from("/opt/shared"));
+        assert!(dirs[1].ends_with("notes") && !dirs[1].starts_with("~"));
+        assert_eq!(config.tools.schema_overrides, vec!["read_file".to_string()]);
+        assert_eq!(ToolsConfig::default().on_schema_conflict, SchemaConflictPolicy::Error);
+        assert_eq!(config.tools.resource_limits.get("github-api"), Some(&2));
+        assert!(BedrockConfig::from_str(&toml.replace("github-api = 2", "github-api = 0")).is_err());
+        assert_eq!(config.tools.timeout_for("read_file"), None);
+        assert_eq!(config.tools.timeout_for("fetch"), Some(std::time::Duration::from_millis(5000)));
+        let timed = BedrockConfig::from_str(&toml.replace("enabled = false", "enabled = false\ntimeout_ms = 600000")).unwrap();
+        assert_eq!(timed.tools.timeout_for("read_file"), Some(std::time::Duration::from_secs(600)));
+        assert_eq!(timed.tools.timeout_for("shell_exec"), None);
+
+        assert_eq!(config.tools.aliases["search"], "brave__web_search");
+        let twice = toml.replace("search = \"brave__web_search\"", "search = \"brave__web_search\"\nfind = \"brave__web_search\"");
+        assert!(format!("{:#}", BedrockConfig::from_str(&twice).unwrap_err()).contains("already has an alias"));
+        let chained = toml.replace("search = \"brave__web_search\"", "search = \"brave__web_search\"\nfind = \"search\"");
+        assert!(format!("{:#}", BedrockConfig::from_str(&chained).unwrap_err()).contains("is itself an alias"));
+
+        let dup = toml.replace(r#"["mcp", "builtin"]"#, r#"["mcp", "mcp"]"#);
+        assert!(BedrockConfig::from_str(&dup).is_err());
+    }
+
+    #[test]
+    fn test_validate_cost_budget() {
+        let toml = r#"
+[agent]
+model = "gpt-4o"
+provider = "openai"
+
+[providers.openai]
+type = "openai"
+
+[kernel]
+cost_budget_usd = 2.5
+"#;
+        let config = BedrockConfig::from_str(toml).unwrap();
+        assert_eq!(config.kernel.cost_budget_usd, Some(2.5));
+        assert!(BedrockConfig::from_str(&toml.replace("2.5", "0.0")).is_err());
+    }
+
+    #[test]
+    fn test_loop_mode() {
+        let toml = r#"
+[agent]
+model = "gpt-4o"
+provider = "openai"
+
+[providers.openai]
+type = "openai"
+"#;
+        let config = BedrockConfig::from_str(toml).unwrap();
+        assert_eq!(config.kernel.mode, LoopMode::Builtin);
+
+        let config = BedrockConfig::from_str(&format!("{}\n[kernel]\nmode = \"orchestrator\"\n", toml)).unwrap();
+        assert_eq!(config.kernel.mode, LoopMode::Orchestrator);
+    }
+
+    #[test]
+    fn test_parse_postprocessors() {
+        let toml = r#"
+[agent]
+model = "gpt-4o"
+provider = "openai"
+postprocessors = ["strip_code_fences", "redact_secrets", { truncate = 2000 }, "harness", "enforce_json"]
+
+[providers.openai]
+type = "openai"
+"#;
+        let config = BedrockConfig::from_str(toml).unwrap();
+        assert_eq!(config.agent.postprocessors, vec![
+            Postprocessor::StripCodeFences,
+            Postprocessor::RedactSecrets,
+            Postprocessor::Truncate(2000),
+            Postprocessor::Harness,
+            Postprocessor::EnforceJson,
+        ]);
+        assert!(BedrockConfig::from_str(&toml.replace("2000", "0")).is_err());
+        assert!(BedrockConfig::from_str(&toml.replace("\"harness\"", "\"shout\"")).is_err());
+    }
+
+    #[test]
+    fn test_database_path_placeholders() {
+        let toml = r#"
+[agent]
+model = "gpt-4o"
+provider = "openai"
+
+[kernel]
+workspace_root = "/srv/shop"
+
+[persistence]
+database_path = "/var/lib/bedrock/{workspace_name}-{date}.db"
+
+[providers.openai]
+type = "openai"
+"#;
+        let config = BedrockConfig::from_str(toml).unwrap();
+        let path = config.database_path();
+        assert!(path.starts_with("/var/lib/bedrock/shop-20"), "{}", path);
+        assert!(path.ends_with(".db") && !path.contains('{'));
+        assert!(BedrockConfig::from_str(&toml.replace("{date}", "{day}")).is_err());
+    }
+
+    #[test]
+    fn test_repl_idle_timeout() {
+        let toml = r#"
+[agent]
+model = "gpt-4o"
+provider = "openai"
+
+[providers.openai]
+type = "openai"
+"#;
+        let config = BedrockConfig::from_str(toml).unwrap();
+        assert_eq!(config.repl.idle_timeout(), None);
+
+        let config = BedrockConfig::from_str(&format!("{}\n[repl]\nidle_timeout = 30\n", toml)).unwrap();
+        assert_eq!(config.repl.idle_timeout(), Some(std::time::Duration::from_secs(30 * 60)));
+    }
+
+    #[test]
+    fn test_trim_on_context_overflow_default() {
+        let toml = r#"
+[agent]
+model = "gpt-4o"
+provider = "openai"
+
+[providers.openai]
+type = "openai"
+"#;
+        let config = BedrockConfig::from_str(toml).unwrap();
+        assert!(config.kernel.trim_on_context_overflow);
+
+        let config = BedrockConfig::from_str(&format!("{}\n[kernel]\ntrim_on_context_overflow = false\n", toml)).unwrap();
+        assert!(!config.kernel.trim_on_context_overflow);
+    }
+
+    #[test]
+    fn test_mcp_servers() {
+        let toml = r#"
+[agent]
+model = "gpt-4o"
+provider = "openai"
+
+[providers.openai]
+type = "openai"
+
+[mcp]
+max_servers = 2
+startup_timeout_secs = 5
+lazy = true
+max_restarts = 3
+
+[[mcp.servers]]
+name = "fs"
+command = "npx"
+args = ["-y", "@modelcontextprotocol/server-filesystem", "."]
+lazy = false
+
+[[mcp.servers]]
+command = "/opt/servers/github-mcp"
+
+[[mcp.servers]]
+url = "https://mcp.example.com/sse"
+transport = "sse"
+headers = { Authorization = "Bearer token" }
+"#;
+        let config = BedrockConfig::from_str(toml).unwrap();
+        assert_eq!(config.mcp.servers.len(), 3);
+        assert_eq!(config.mcp.servers[2].server_name(), "mcp.example.com");
+        assert!(matches!(config.mcp.servers[2].to_transport(), crate::tools::mcp::McpTransport::Http { sse: true, .. }));
+        assert_eq!(config.mcp.servers[1].server_name(), "github-mcp");
+        assert_eq!(config.mcp.servers[0].name.as_deref(), Some("fs"));
+        assert!(config.mcp.servers[1].args.is_empty());
+        assert_eq!((config.mcp.max_servers, config.mcp.startup_concurrency, config.mcp.startup_timeout_secs), (Some(2), 4, 5));
+        assert!(config.mcp.lazy);
+        assert_eq!((config.mcp.servers[0].lazy, config.mcp.servers[1].lazy), (Some(false), None));
+        assert_eq!((config.mcp.health_check_interval_secs, config.mcp.max_restarts, config.mcp.restart_backoff_ms), (30, 3, 1000));
+        assert_eq!(config.mcp.restart_policy().startup_timeout, std::time::Duration::from_secs(5));
+
+        let err = BedrockConfig::from_str(&toml.replace("startup_timeout_secs = 5", "startup_concurrency = 0")).unwrap_err();
+        assert!(format!("{:#}", err).contains("mcp.startup_concurrency"));
+        let err = BedrockConfig::from_str(&toml.replace("https://mcp", "ftp://mcp").replace("/opt/servers/github-mcp\"", "x\"\ntransport = \"http\"")).unwrap_err();
+        let err = format!("{:#}", err);
+        assert!(err.contains("mcp.servers[2].url") && err.contains("mcp.servers[1].transport"), "{}", err);
+    }
+
+    #[test]
+    fn test_agent_resolution_precedence() {
+        let toml = r#"
+[agent]
+model = "gpt-4o"
+provider = "openai"
+
+[providers.openai]
+type = "openai"
+
+[providers.anthropic]
+type = "anthropic"
+"#;
+        let mut config = BedrockConfig::from_str(toml).unwrap();
+        let sources = config.resolve_agent_from(None, None, None, None).unwrap();
+        assert_eq!((sources.model, sources.provider), (SettingSource::Config, SettingSource::Config));
+        assert_eq!(config.agent.model, "gpt-4o");
+
+        let sources = config
+            .resolve_agent_from(Some("cli-model".into()), None, Some("env-model".into()), Some("anthropic".into()))
+            .unwrap();
+        assert_eq!((sources.model, sources.provider), (SettingSource::Cli, SettingSource::Env));
+        assert_eq!(config.agent.model, "cli-model");
+        assert_eq!(config.agent.provider, "anthropic");
+
+        let err = config.resolve_agent_from(None, None, None, Some("nope".into())).unwrap_err();
+        assert!(format!("{:#}", err).contains("provider 'nope' (from BEDROCK_PROVIDER)"));
     }
 }
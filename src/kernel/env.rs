@@ -0,0 +1,165 @@
+//! Environment handling for configuration: `.env` file loading and `${VAR}`
+//! expansion in `bedrock.toml`.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Parse `KEY=VALUE` lines from a `.env` file.
+///
+/// Blank lines and `#` comments are skipped, an optional `export ` prefix is
+/// allowed, and values wrapped in matching single or double quotes are unquoted.
+pub fn parse_env_file(contents: &str) -> Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .with_context(|| format!("line {}: expected KEY=VALUE", i + 1))?;
+        let key = key.trim();
+        anyhow::ensure!(
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+            "line {}: invalid variable name '{}'",
+            i + 1,
+            key
+        );
+        vars.push((key.to_string(), unquote(value.trim()).to_string()));
+    }
+    Ok(vars)
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return &value[1..value.len() - 1];
+        }
+    }
+    value
+}
+
+/// Load a `.env` file into the process environment. Returns how many variables were set.
+///
+/// Variables already present in the environment are kept unless `override_existing` is set.
+pub fn load_env_file(path: &Path, override_existing: bool) -> Result<usize> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Could not read env file: {}", path.display()))?;
+    let vars = parse_env_file(&contents)
+        .with_context(|| format!("Failed to parse env file: {}", path.display()))?;
+
+    let mut set = 0;
+    for (key, value) in vars {
+        if override_existing || std::env::var_os(&key).is_none() {
+            std::env::set_var(&key, value);
+            set += 1;
+        }
+    }
+    Ok(set)
+}
+
+/// Replace `${VAR}` references with values from the environment.
+///
+/// Referencing an unset variable is an error so misconfiguration surfaces at load time.
+pub fn expand_env_vars(input: &str) -> Result<String> {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find('}')
+            .with_context(|| "Unterminated '${' in config")?;
+        let name = &after[..end];
+        let value = std::env::var(name)
+            .with_context(|| format!("Environment variable '{}' referenced in config is not set", name))?;
+        out.push_str(&value);
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Expand `${VAR}` references in every string of a parsed TOML document.
+///
+/// Expanding after parsing leaves comments alone and lets values contain
+/// quotes or backslashes without breaking the TOML around them.
+pub fn expand_env_in_toml(value: &mut toml::Value) -> Result<()> {
+    match value {
+        toml::Value::String(s) => *s = expand_env_vars(s)?,
+        toml::Value::Array(items) => items.iter_mut().try_for_each(expand_env_in_toml)?,
+        toml::Value::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                expand_env_in_toml(item).with_context(|| format!("in '{}'", key))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env_file() {
+        let vars = parse_env_file(
+            "# comment\n\nFOO=bar\nexport QUOTED=\"a b\"\nSINGLE='x=y'\nEMPTY=\n",
+        )
+        .unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                ("FOO".to_string(), "bar".to_string()),
+                ("QUOTED".to_string(), "a b".to_string()),
+                ("SINGLE".to_string(), "x=y".to_string()),
+                ("EMPTY".to_string(), String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_env_file_rejects_malformed_line() {
+        let err = parse_env_file("FOO=bar\nnot a pair\n").unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn test_load_env_file_respects_existing_unless_override() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        std::fs::write(&path, "BEDROCK_TEST_ENVFILE_KEEP=from_file\nBEDROCK_TEST_ENVFILE_NEW=new\n").unwrap();
+        std::env::set_var("BEDROCK_TEST_ENVFILE_KEEP", "real");
+
+        assert_eq!(load_env_file(&path, false).unwrap(), 1);
+        assert_eq!(std::env::var("BEDROCK_TEST_ENVFILE_KEEP").unwrap(), "real");
+        assert_eq!(std::env::var("BEDROCK_TEST_ENVFILE_NEW").unwrap(), "new");
+
+        load_env_file(&path, true).unwrap();
+        assert_eq!(std::env::var("BEDROCK_TEST_ENVFILE_KEEP").unwrap(), "from_file");
+    }
+
+    #[test]
+    fn test_expand_env_vars() {
+        std::env::set_var("BEDROCK_TEST_EXPAND_DIR", "/data");
+        assert_eq!(
+            expand_env_vars("path = \"${BEDROCK_TEST_EXPAND_DIR}/state.db\"").unwrap(),
+            "path = \"/data/state.db\""
+        );
+        assert!(expand_env_vars("${BEDROCK_TEST_EXPAND_UNSET}").is_err());
+        assert!(expand_env_vars("${UNTERMINATED").is_err());
+    }
+
+    #[test]
+    fn test_expand_env_in_toml_skips_comments_and_keeps_quotes() {
+        std::env::set_var("BEDROCK_TEST_EXPAND_QUOTED", r#"say "hi" \o/"#);
+        let mut value: toml::Value = toml::from_str(
+            "# ${BEDROCK_TEST_EXPAND_UNSET} is only mentioned here\ngreeting = \"${BEDROCK_TEST_EXPAND_QUOTED}\"\n",
+        )
+        .unwrap();
+        expand_env_in_toml(&mut value).unwrap();
+        assert_eq!(value["greeting"].as_str(), Some(r#"say "hi" \o/"#));
+    }
+}
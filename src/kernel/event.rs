@@ -1,4 +1,7 @@
-use serde::Serialize;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::persistence::state::EventRow;
 
 /// Every action in Bedrock produces a typed `KernelEvent`.
 ///
@@ -6,12 +9,19 @@ use serde::Serialize;
 /// 1. **Typed** — Each event has a specific variant
 /// 2. **Persisted** — Written to libSQL for auditability (Phase 3)
 /// 3. **Harness-gated** — Certain events pass through harness hooks before execution (Phase 4)
-#[derive(Debug, Clone, Serialize)]
+///
+/// Every variant round-trips through JSON unchanged (non-finite floats
+/// aside, which JSON can't represent), so stored events can be read back
+/// with [`KernelEvent::from_row`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum KernelEvent {
     /// Agent session begins
     AgentStart {
         session_id: String,
+        /// Session that delegated this one, for sub-agents
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        parent_session: Option<String>,
     },
 
     /// Agent session completes
@@ -19,6 +29,9 @@ pub enum KernelEvent {
         message_count: u32,
         total_input_tokens: u64,
         total_output_tokens: u64,
+        /// Estimated spend of the session; unpriced models count as zero
+        #[serde(default)]
+        total_cost_usd: f64,
     },
 
     /// New LLM call begins
@@ -32,6 +45,89 @@ pub enum KernelEvent {
         has_tool_calls: bool,
     },
 
+    /// Turn outcome was unusable and the turn is being re-run from the same history
+    TurnRetried {
+        turn_index: u32,
+        attempt: u32,
+        reason: String,
+    },
+
+    /// Turn retries were exhausted; the last outcome is used as-is
+    TurnFailed {
+        turn_index: u32,
+        reason: String,
+    },
+
+    /// The turn was interrupted by the user; the run stops but the session stays usable
+    TurnInterrupted {
+        turn_index: u32,
+    },
+
+    /// The host aborted the run (see `InterruptHandle::abort`); emitted once the
+    /// interrupted turn has been saved
+    Aborted {
+        turn_index: u32,
+    },
+
+    /// Older history was dropped after the provider reported a context overflow
+    ContextTrimmed {
+        turn_index: u32,
+        removed_messages: u32,
+        remaining_messages: u32,
+    },
+
+    /// A harness hook's changes were dropped because they broke a kernel
+    /// guarantee (e.g. the `kernel.min_retained_turns` tail)
+    HarnessEditDiscarded {
+        turn_index: u32,
+        hook: String,
+        reason: String,
+    },
+
+    /// Older turns were replaced by a summary to keep the prompt under
+    /// `compaction.threshold_tokens`; the `messages` table is left untouched
+    HistoryCompacted {
+        turn_index: u32,
+        removed_messages: u32,
+        tokens_before: u64,
+        tokens_after: u64,
+        model: String,
+        summary: String,
+    },
+
+    /// A request failed with a retryable provider error and is being retried
+    /// on the next entry of `agent.fallbacks`
+    ProviderFailover {
+        turn_index: u32,
+        from_provider: String,
+        from_model: String,
+        to_provider: String,
+        to_model: String,
+        error: String,
+    },
+
+    /// A response stopped at the output token limit
+    ResponseTruncated {
+        turn_index: u32,
+        stop_reason: String,
+        /// Continuations already requested this turn
+        continuations: u32,
+        /// Whether the kernel is asking the model to continue
+        continuing: bool,
+    },
+
+    /// One `agent.postprocessors` step ran on the assistant message
+    MessagePostprocessed {
+        turn_index: u32,
+        step: String,
+        changed: bool,
+        chars_before: usize,
+        chars_after: usize,
+        /// Why the step left the message unchanged, if it failed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+
     /// Streaming message begins
     MessageStart {
         role: String,
@@ -48,11 +144,21 @@ pub enum KernelEvent {
         thinking: String,
     },
 
+    /// The model's complete reasoning for a turn, emitted once the response
+    /// has finished streaming
+    Reasoning {
+        turn_index: u32,
+        content: String,
+    },
+
     /// Complete message assembled
     MessageEnd {
         role: String,
         input_tokens: u64,
         output_tokens: u64,
+        /// Provider finish reason (e.g. "end_turn", "tool_use", "length")
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stop_reason: Option<String>,
     },
 
     /// LLM requests a tool execution
@@ -69,6 +175,106 @@ pub enum KernelEvent {
         is_error: bool,
     },
 
+    /// More than `kernel.max_consecutive_tool_errors` tool calls failed in a
+    /// row; the run was halted
+    ToolErrorStorm {
+        consecutive_errors: u32,
+        limit: u32,
+        /// Output of the most recent failed call
+        last_error: String,
+    },
+
+    /// A tool call repeated the previous call exactly and was answered with
+    /// that call's result instead of running (`kernel.dedup_tool_calls`)
+    ToolCallDeduplicated {
+        tool_call_id: String,
+        tool: String,
+        repeat_of: String,
+    },
+
+    /// A sub-agent started by `delegate` finished; its events are stored
+    /// under `child_session`
+    SubagentFinished {
+        child_session: String,
+        goal: String,
+        turns: u32,
+        stop_reason: Option<crate::kernel::session::StopReason>,
+    },
+
+    /// A tool result was stored as an artifact; the model saw only the handle
+    ArtifactStored {
+        tool_call_id: String,
+        artifact: crate::tools::ArtifactRef,
+    },
+
+    /// A harness `on_before_request` hook changed the messages sent to the
+    /// provider; `persisted` if it also replaced the session history
+    RequestRewritten {
+        turn_index: u32,
+        messages_before: usize,
+        messages_sent: usize,
+        persisted: bool,
+    },
+
+    /// A call was refused because its tool is locked in safe mode
+    ToolLocked {
+        tool_call_id: String,
+        tool: String,
+    },
+
+    /// A harness unlocked a tool in safe mode (`bedrock.unlock_tool`)
+    ToolUnlocked {
+        tool: String,
+    },
+
+    /// A tool result was stored as a memory (`memory.capture_tool_results`)
+    MemoryCaptured {
+        tool_call_id: String,
+        tool: String,
+        chars: usize,
+    },
+
+    /// The model or provider for subsequent turns was changed (REPL `/model`, `/provider`)
+    ModelSwitched {
+        previous_provider: String,
+        previous_model: String,
+        provider: String,
+        model: String,
+    },
+
+    /// Sources cited by this turn's tool outputs
+    SourcesCollected {
+        turn_index: u32,
+        sources: Vec<crate::tools::CitedSource>,
+    },
+
+    /// The session's plan was replaced (via `update_plan` or `bedrock.plan.update`)
+    PlanUpdated {
+        steps: Vec<crate::persistence::state::PlanStep>,
+    },
+
+    /// Session usage reached the harness's soft limit, or crossed a
+    /// `limits.warn_at` fraction of one of its `[limits]`
+    BudgetWarning {
+        /// "soft_limit" for the harness's soft limit, otherwise the `[limits]`
+        /// key: "max_input_tokens", "max_output_tokens" or "max_cost_usd"
+        #[serde(default = "soft_limit_name")]
+        limit: String,
+        #[serde(alias = "total_tokens")]
+        used: f64,
+        #[serde(alias = "soft_limit")]
+        max: f64,
+        /// The `warn_at` fraction crossed; 1.0 for the soft limit
+        #[serde(default = "full_fraction")]
+        fraction: f64,
+    },
+
+    /// Tool calls beyond `max_tool_calls_per_turn` were dropped
+    ToolCallsTruncated {
+        requested: u32,
+        executed: u32,
+    },
+
     /// Tool execution begins (for logging/timing)
     ToolExecStart {
         id: String,
@@ -88,6 +294,23 @@ pub enum KernelEvent {
         cost_usd: f64,
     },
 
+    /// Session tokens went over a `[limits]` cap; the run was stopped
+    LimitExceeded {
+        limit: String,
+        used: u64,
+        max: u64,
+    },
+
+    /// Session spend crossed `limits.max_cost_usd` (or `kernel.cost_budget_usd`); the run was aborted
+    CostBudgetExceeded {
+        /// Estimated spend so far in USD
+        spent_usd: f64,
+        budget_usd: f64,
+        /// Set when the run stopped because the next request's estimated cost
+        /// would have crossed the budget, before it was sent
+        next_request_estimate_usd: Option<f64>,
+    },
+
     /// Harness engine rejected an action
     HarnessRejection {
         /// Which event type was rejected (e.g., "tool_call")
@@ -105,28 +328,84 @@ impl KernelEvent {
             KernelEvent::AgentEnd { .. } => "agent_end",
             KernelEvent::TurnStart { .. } => "turn_start",
             KernelEvent::TurnEnd { .. } => "turn_end",
+            KernelEvent::TurnRetried { .. } => "turn_retried",
+            KernelEvent::TurnFailed { .. } => "turn_failed",
+            KernelEvent::TurnInterrupted { .. } => "turn_interrupted",
+            KernelEvent::Aborted { .. } => "aborted",
+            KernelEvent::ContextTrimmed { .. } => "context_trimmed",
+            KernelEvent::HarnessEditDiscarded { .. } => "harness_edit_discarded",
+            KernelEvent::ProviderFailover { .. } => "provider_failover",
+            KernelEvent::HistoryCompacted { .. } => "history_compacted",
+            KernelEvent::ResponseTruncated { .. } => "response_truncated",
+            KernelEvent::MessagePostprocessed { .. } => "message_postprocessed",
             KernelEvent::MessageStart { .. } => "message_start",
             KernelEvent::MessageDelta { .. } => "message_delta",
             KernelEvent::ThinkingDelta { .. } => "thinking_delta",
+            KernelEvent::Reasoning { .. } => "reasoning",
             KernelEvent::MessageEnd { .. } => "message_end",
             KernelEvent::ToolCall { .. } => "tool_call",
             KernelEvent::ToolResult { .. } => "tool_result",
+            KernelEvent::ToolErrorStorm { .. } => "tool_error_storm",
+            KernelEvent::ToolCallDeduplicated { .. } => "tool_call_deduplicated",
+            KernelEvent::SubagentFinished { .. } => "subagent_finished",
+            KernelEvent::ArtifactStored { .. } => "artifact_stored",
+            KernelEvent::MemoryCaptured { .. } => "memory_captured",
+            KernelEvent::RequestRewritten { .. } => "request_rewritten",
+            KernelEvent::ToolLocked { .. } => "tool_locked",
+            KernelEvent::ToolUnlocked { .. } => "tool_unlocked",
+            KernelEvent::ModelSwitched { .. } => "model_switched",
+            KernelEvent::SourcesCollected { .. } => "sources_collected",
+            KernelEvent::PlanUpdated { .. } => "plan_updated",
+            KernelEvent::BudgetWarning { .. } => "budget_warning",
+            KernelEvent::ToolCallsTruncated { .. } => "tool_calls_truncated",
             KernelEvent::ToolExecStart { .. } => "tool_exec_start",
             KernelEvent::ToolExecEnd { .. } => "tool_exec_end",
             KernelEvent::TokenUsage { .. } => "token_usage",
+            KernelEvent::CostBudgetExceeded { .. } => "cost_budget_exceeded",
+            KernelEvent::LimitExceeded { .. } => "limit_exceeded",
             KernelEvent::HarnessRejection { .. } => "harness_rejection",
         }
     }
+
+    /// Rebuild the event stored in an `events` row. Fails if the payload is
+    /// malformed or doesn't match the row's `event_type`.
+    pub fn from_row(row: &EventRow) -> Result<Self> {
+        let event: KernelEvent = serde_json::from_str(&row.payload)
+            .with_context(|| format!("Malformed payload in event {} ({})", row.id, row.event_type))?;
+        anyhow::ensure!(
+            event.event_type() == row.event_type,
+            "Event {} is stored as '{}' but its payload is '{}'",
+            row.id,
+            row.event_type,
+            event.event_type()
+        );
+        Ok(event)
+    }
+}
+
+fn soft_limit_name() -> String {
+    "soft_limit".to_string()
+}
+
+fn full_fraction() -> f64 {
+    1.0
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_budget_warning_reads_soft_limit_payload() {
+        let event: KernelEvent = serde_json::from_str(r#"{"type":"budget_warning","total_tokens":120,"soft_limit":100}"#).unwrap();
+        assert_eq!(event, KernelEvent::BudgetWarning { limit: "soft_limit".to_string(), used: 120.0, max: 100.0, fraction: 1.0 });
+    }
+
     #[test]
     fn test_event_serialization() {
         let event = KernelEvent::AgentStart {
             session_id: "test-123".to_string(),
+            parent_session: None,
         };
         let json = serde_json::to_string(&event).unwrap();
         assert!(json.contains("\"type\":\"agent_start\""));
@@ -137,7 +416,8 @@ mod tests {
     fn test_event_type_names() {
         assert_eq!(
             KernelEvent::AgentStart {
-                session_id: "x".into()
+                session_id: "x".into(),
+                parent_session: None,
             }
             .event_type(),
             "agent_start"
@@ -164,4 +444,22 @@ mod tests {
         assert!(json.contains("\"name\":\"read_file\""));
         assert!(json.contains("\"path\":\"main.rs\""));
     }
+
+    #[test]
+    fn test_event_from_row() {
+        let event = KernelEvent::TurnEnd { turn_index: 2, has_tool_calls: true };
+        let mut row = EventRow {
+            id: 7,
+            session_id: "s".to_string(),
+            event_type: "turn_end".to_string(),
+            payload: serde_json::to_string(&event).unwrap(),
+            created_at: String::new(),
+        };
+        assert_eq!(KernelEvent::from_row(&row).unwrap(), event);
+
+        row.event_type = "turn_start".to_string();
+        assert!(KernelEvent::from_row(&row).unwrap_err().to_string().contains("stored as 'turn_start'"));
+        row.payload = "{\"type\":\"turn_end\"".to_string();
+        assert!(KernelEvent::from_row(&row).is_err());
+    }
 }
@@ -0,0 +1,115 @@
+//! Cooperative interruption of a running turn.
+//!
+//! An [`InterruptHandle`] is shared between the kernel and whoever drives it
+//! (e.g. the REPL's Ctrl-C handler). Interrupting stops the current turn at the
+//! next checkpoint — mid-stream or while tools run — and ends the run with
+//! `StopReason::Interrupted`, leaving the session usable for the next prompt.
+//!
+//! Aborting is the same stop requested by a host embedding the kernel (e.g. a
+//! client went away): the run ends with `StopReason::Aborted`, an `aborted`
+//! event, and a session snapshot when snapshots are enabled.
+//!
+//! The handle is reset when a run ends, so a request made before a run
+//! starts stops that run at its first checkpoint.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Clonable handle used to interrupt the kernel's current turn.
+#[derive(Debug, Clone)]
+pub struct InterruptHandle {
+    tx: Arc<watch::Sender<bool>>,
+    aborted: Arc<AtomicBool>,
+}
+
+impl Default for InterruptHandle {
+    fn default() -> Self {
+        Self { tx: Arc::new(watch::channel(false).0), aborted: Default::default() }
+    }
+}
+
+impl InterruptHandle {
+    /// Request that the current turn stop.
+    pub fn interrupt(&self) {
+        self.tx.send_replace(true);
+    }
+
+    /// Cancel the current run on behalf of the host: interrupt it and report
+    /// it as aborted rather than interrupted.
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+        self.interrupt();
+    }
+
+    /// Whether the pending interrupt is an abort.
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    /// Whether an interrupt is pending.
+    pub fn is_interrupted(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// Reset the handle.
+    pub(crate) fn clear(&self) {
+        self.aborted.store(false, Ordering::SeqCst);
+        self.tx.send_replace(false);
+    }
+
+    /// Reset the handle when the returned guard is dropped, i.e. when the
+    /// run holding it ends, however it ends.
+    pub(crate) fn clear_on_drop(&self) -> ClearOnDrop {
+        ClearOnDrop(self.clone())
+    }
+
+    /// Resolve once an interrupt is requested.
+    pub(crate) async fn interrupted(&self) {
+        let mut rx = self.tx.subscribe();
+        // The sender lives as long as `self`, so this only returns once set
+        let _ = rx.wait_for(|interrupted| *interrupted).await;
+    }
+}
+
+/// Clears an [`InterruptHandle`] on drop; see [`InterruptHandle::clear_on_drop`].
+pub(crate) struct ClearOnDrop(InterruptHandle);
+
+impl Drop for ClearOnDrop {
+    fn drop(&mut self) {
+        self.0.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_interrupt_wakes_waiter() {
+        let handle = InterruptHandle::default();
+        let waiter = {
+            let handle = handle.clone();
+            tokio::spawn(async move { handle.interrupted().await })
+        };
+        assert!(!handle.is_interrupted());
+        handle.interrupt();
+        tokio::time::timeout(std::time::Duration::from_secs(1), waiter).await.unwrap().unwrap();
+        assert!(handle.is_interrupted());
+
+        handle.clear();
+        assert!(!handle.is_interrupted());
+    }
+
+    #[test]
+    fn test_abort_interrupts() {
+        let handle = InterruptHandle::default();
+        handle.abort();
+        assert!(handle.is_interrupted() && handle.is_aborted());
+
+        handle.clear();
+        assert!(!handle.is_aborted());
+        handle.interrupt();
+        assert!(!handle.is_aborted());
+    }
+}
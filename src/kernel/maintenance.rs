@@ -0,0 +1,221 @@
+//! Background storage maintenance.
+//!
+//! With `[maintenance] enabled = true`, [`Kernel::start_maintenance`] spawns a
+//! task that wakes every `interval_secs` and, once no run has been active for
+//! `idle_secs`, works through the configured jobs: sweeping expired KV and
+//! cache rows and, once a maximum age is configured, pruning old events and
+//! raw responses and decaying old memories. Jobs delete `batch_size` rows per immediate transaction and the
+//! pass gives up as soon as a run starts, so a live turn waits on at most one
+//! small batch for the write lock. Turns themselves never prune.
+//!
+//! [`Kernel::start_maintenance`]: crate::kernel::Kernel::start_maintenance
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::Serialize;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::kernel::config::{MaintenanceConfig, MaintenanceJob};
+use crate::persistence::state::StateStore;
+
+/// Whether the kernel is busy, so maintenance only runs while it is idle.
+#[derive(Debug)]
+pub struct Activity {
+    running: AtomicUsize,
+    last_active: Mutex<Instant>,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self { running: AtomicUsize::new(0), last_active: Mutex::new(Instant::now()) }
+    }
+}
+
+impl Activity {
+    /// Mark a run as active until the returned guard is dropped.
+    pub fn begin(self: &Arc<Self>) -> ActivityGuard {
+        self.running.fetch_add(1, Ordering::SeqCst);
+        ActivityGuard(self.clone())
+    }
+
+    /// Whether no run is active and none ended within `idle`.
+    pub fn is_idle(&self, idle: Duration) -> bool {
+        self.running.load(Ordering::SeqCst) == 0 && self.last_active.lock().unwrap().elapsed() >= idle
+    }
+}
+
+/// Keeps a run marked active; see [`Activity::begin`].
+pub struct ActivityGuard(Arc<Activity>);
+
+impl Drop for ActivityGuard {
+    fn drop(&mut self) {
+        *self.0.last_active.lock().unwrap() = Instant::now();
+        self.0.running.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Rows deleted by one maintenance pass.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct MaintenanceReport {
+    /// Expired harness KV entries and tool cache rows
+    pub expired: u64,
+    /// Events and raw responses older than `log_max_age_days`
+    pub pruned: u64,
+    /// Memories older than `memory_max_age_days`
+    pub decayed: u64,
+    /// The pass stopped early because a run started or the task was cancelled
+    pub interrupted: bool,
+}
+
+impl MaintenanceReport {
+    fn count(&mut self, job: MaintenanceJob) -> &mut u64 {
+        match job {
+            MaintenanceJob::ExpireKv => &mut self.expired,
+            MaintenanceJob::PruneLogs => &mut self.pruned,
+            MaintenanceJob::MemoryDecay => &mut self.decayed,
+        }
+    }
+}
+
+/// Run each configured job until it has nothing left to delete, checking
+/// `keep_going` before every batch.
+pub async fn run_pass(store: &StateStore, config: &MaintenanceConfig, keep_going: impl Fn() -> bool) -> Result<MaintenanceReport> {
+    let mut report = MaintenanceReport::default();
+    let batch = config.batch_size;
+    for &job in &config.jobs {
+        loop {
+            if !keep_going() {
+                report.interrupted = true;
+                return Ok(report);
+            }
+            let deleted = match job {
+                MaintenanceJob::ExpireKv => store.delete_expired(batch).await?,
+                MaintenanceJob::PruneLogs => match config.log_max_age_days {
+                    Some(days) => store.delete_log_rows_older_than(days, batch).await?,
+                    None => 0,
+                },
+                MaintenanceJob::MemoryDecay => match config.memory_max_age_days {
+                    Some(days) => store.delete_memories_older_than(days, batch).await?,
+                    None => 0,
+                },
+            };
+            if deleted == 0 {
+                break;
+            }
+            *report.count(job) += deleted;
+            // Let queued writers in between batches
+            tokio::task::yield_now().await;
+        }
+    }
+    Ok(report)
+}
+
+/// The running maintenance task. Dropping the handle cancels it after the
+/// batch in progress, if any.
+pub struct MaintenanceHandle {
+    cancel: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
+
+impl MaintenanceHandle {
+    /// Cancel the task and wait for it to finish its current batch.
+    pub async fn stop(self) {
+        self.cancel.send_replace(true);
+        let _ = self.task.await;
+    }
+}
+
+/// Spawn the maintenance task.
+pub fn spawn(store: StateStore, config: MaintenanceConfig, activity: Arc<Activity>) -> MaintenanceHandle {
+    let (cancel, mut cancelled) = watch::channel(false);
+    let task = tokio::spawn(async move {
+        let interval = Duration::from_secs(config.interval_secs);
+        let idle = Duration::from_secs(config.idle_secs);
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                // Cancelled, or the handle was dropped
+                _ = cancelled.changed() => break,
+            }
+            if !activity.is_idle(idle) {
+                continue;
+            }
+            let keep_going = || !*cancelled.borrow() && activity.is_idle(idle);
+            match run_pass(&store, &config, keep_going).await {
+                Ok(report) if report.expired + report.pruned + report.decayed > 0 => {
+                    info!(
+                        expired = report.expired,
+                        pruned = report.pruned,
+                        decayed = report.decayed,
+                        interrupted = report.interrupted,
+                        "Maintenance pass finished"
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => warn!(error = %e, "Maintenance pass failed"),
+            }
+        }
+    });
+    MaintenanceHandle { cancel, task }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_activity_idle() {
+        let activity = Arc::new(Activity::default());
+        assert!(activity.is_idle(Duration::ZERO));
+        let guard = activity.begin();
+        assert!(!activity.is_idle(Duration::ZERO));
+        drop(guard);
+        assert!(activity.is_idle(Duration::ZERO));
+        // A run that just ended still counts as recent activity
+        assert!(!activity.is_idle(Duration::from_secs(60)));
+    }
+
+    #[tokio::test]
+    async fn test_run_pass() {
+        let store = StateStore::open_memory().await.unwrap();
+        for i in 0..5 {
+            store.insert_event("s", "test", &serde_json::json!({ "n": i })).await.unwrap();
+        }
+        store.insert_memory("s", "old", &[1.0], &serde_json::json!({})).await.unwrap();
+        store.insert_memory("s", "new", &[1.0], &serde_json::json!({})).await.unwrap();
+        let conn = store.get_connection().unwrap();
+        conn.execute("UPDATE events SET created_at = datetime('now', '-40 days') WHERE id <= 3", ()).await.unwrap();
+        conn.execute("UPDATE memories SET created_at = datetime('now', '-10 days') WHERE content = 'old'", ()).await.unwrap();
+        conn.execute("INSERT INTO harness_kv (key, value, expires_at) VALUES ('gone', 'v', datetime('now', '-1 minute'))", ()).await.unwrap();
+        conn.execute("INSERT INTO harness_kv (key, value, expires_at) VALUES ('kept', 'v', NULL)", ()).await.unwrap();
+        conn.execute("INSERT INTO tool_cache (tool_name, args_hash, output, expires_at) VALUES ('t', 'h', 'o', datetime('now', '-1 minute'))", ()).await.unwrap();
+
+        let mut config = MaintenanceConfig { batch_size: 2, ..Default::default() };
+        // Log pruning and memory decay are off until an age is configured
+        let report = run_pass(&store, &config, || true).await.unwrap();
+        assert_eq!(report, MaintenanceReport { expired: 2, pruned: 0, decayed: 0, interrupted: false });
+        assert_eq!(store.get_events("s").await.unwrap().len(), 5);
+        assert_eq!(store.kv_get("kept").await.unwrap().as_deref(), Some("v"));
+
+        config.log_max_age_days = Some(30);
+        let report = run_pass(&store, &config, || true).await.unwrap();
+        assert_eq!(report, MaintenanceReport { expired: 0, pruned: 3, decayed: 0, interrupted: false });
+        assert_eq!(store.get_events("s").await.unwrap().len(), 2);
+        assert_eq!(store.kv_get("kept").await.unwrap().as_deref(), Some("v"));
+
+        config.memory_max_age_days = Some(7);
+        config.jobs = vec![MaintenanceJob::MemoryDecay];
+        let report = run_pass(&store, &config, || true).await.unwrap();
+        assert_eq!(report.decayed, 1);
+        assert_eq!(store.memory_stats(None).await.unwrap().total, 1);
+
+        // A pass stops before its first batch once activity resumes
+        let report = run_pass(&store, &config, || false).await.unwrap();
+        assert!(report.interrupted);
+    }
+}
@@ -1,14 +1,33 @@
 pub mod config;
+pub mod env;
 pub mod event;
+pub mod postprocess;
+pub mod interrupt;
+pub mod retention;
 pub mod builder;
+pub mod chunk;
+pub mod compaction;
+pub mod maintenance;
+pub mod regression;
+pub mod render;
 pub mod session;
+pub mod snapshot;
+pub mod template;
+pub mod verbosity;
 
 use anyhow::{Context, Result};
 use builder::RuntimeBuilder;
-use session::SessionState;
-use config::BedrockConfig;
+use session::{LastToolCall, SessionState, StopReason};
+use snapshot::{SessionSnapshot, SNAPSHOT_VERSION};
+use template::PromptContext;
+use config::{BedrockConfig, EagerDispatch, LoopMode, QueueEmptyPolicy};
 use event::KernelEvent;
+use interrupt::InterruptHandle;
+use render::EventRenderer;
+use verbosity::Verbosity;
 use futures::StreamExt;
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
 use std::io::{self, BufRead, Write};
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -16,21 +35,28 @@ use std::time::Instant;
 use tokio::sync::{Mutex, mpsc};
 use tracing::{info, warn, error, debug, instrument};
 use futures::future::join_all;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::harness::engine::HarnessEngine;
 use crate::harness::globals::HarnessAppData;
 use crate::harness::context::ContextWrapper;
+use crate::harness::orchestrator::{Orchestration, OrchestrationLimits};
+use crate::harness::request::{RequestState, RequestWrapper};
 use crate::harness::verdict::Verdict;
+use crate::inference::auth::ProviderAuth;
+use crate::inference::pricing;
 use crate::inference::provider::{
-    self, InferenceContent, InferenceMessage, InferenceRole, ProviderClient, ProviderKind,
+    self, FinishReason, InferenceContent, InferenceMessage, InferenceRole, ProviderClient, ProviderError, ProviderKind,
 };
-use crate::persistence::state::StateStore;
-use crate::tools::ToolContext;
-use crate::tools::registry::ToolRegistry;
-use crate::tools::mcp::McpToolProxy;
-use mcp_sdk::client::McpClient;
-use mcp_sdk::transport::StdioTransport;
+use crate::persistence::state::{PlanStep, StateStore};
+use crate::tools::{Artifact, CitedSource, Source, Tool, ToolContext, ToolEffect, ToolError};
+use crate::tools::builtins::{event_filter, render_plan, render_slice, summarize_events};
+use crate::tools::registry::{ToolRegistry, ToolSource};
+use crate::tools::resource::ResourceLimiter;
+use crate::tools::approval::{self, ApprovalDecision, ApprovalRequest};
+use crate::tools::mcp::{McpServerHandle, McpServers, McpToolProxy, McpTransport};
+use crate::harness::tools::{HarnessTool, ToolHandlers};
+use mcp_sdk::types::ToolDefinition;
 use crate::inference::embeddings::EmbeddingProvider;
 use notify::{RecommendedWatcher, Event};
 
@@ -42,19 +68,66 @@ use notify::{RecommendedWatcher, Event};
 pub struct Kernel {
     pub(crate) config: Arc<BedrockConfig>,
     pub(crate) json: bool,
+    /// Don't echo streamed responses to stdout
+    pub(crate) quiet: bool,
+    /// Ask on stdin before running escalated tool calls; deny them otherwise
+    pub(crate) interactive: bool,
+    /// Resuming a session keeps the configured agent (see `RuntimeBuilder::pin_agent`)
+    pub(crate) pin_agent: bool,
+    /// Decides approvals in place of the stdin prompt
+    pub(crate) approval_handler: Option<crate::tools::approval::ApprovalHandler>,
+    /// Diagnostic output categories printed to stderr
+    pub(crate) verbosity: Verbosity,
+    /// Prints events as they are emitted, tracking tool names and token totals
+    pub(crate) renderer: std::sync::Mutex<EventRenderer>,
     pub(crate) tool_registry: ToolRegistry,
     pub(crate) state: Option<StateStore>,
     /// Thread-safe harness engine for hot-reloading
     pub(crate) harness: Arc<Mutex<Option<HarnessEngine>>>,
+    /// Handlers of the harness's `bedrock.register_tool` tools, swapped on reload
+    pub(crate) harness_tools: ToolHandlers,
     /// Watcher handle to keep it alive
     pub(crate) check_watcher: Option<RecommendedWatcher>,
     pub(crate) clients: HashMap<String, ProviderClient>,
     pub(crate) embedding_provider: Option<Arc<dyn EmbeddingProvider>>,
     /// Active session queue for harness interaction
     pub(crate) active_queue: crate::harness::globals::ActiveSessionQueue,
-    pub(crate) mcp_clients: Vec<Arc<McpClient<mcp_sdk::transport::StdioTransport>>>,
+    /// The session being run, for harness `bedrock.records_*`
+    pub(crate) active_session: crate::harness::globals::ActiveSessionId,
+    /// MCP servers whose tools are registered, health-checked in the background
+    pub(crate) mcp_servers: McpServers,
+    /// Every session's events with its id, for [`Kernel::subscribe`]
+    pub(crate) event_bus: tokio::sync::broadcast::Sender<(String, KernelEvent)>,
+    /// Stops the running turn when triggered (e.g. Ctrl-C in the REPL)
+    pub(crate) interrupt: InterruptHandle,
+    /// Per-resource-key concurrency limits for tool execution
+    pub(crate) resources: ResourceLimiter,
+    /// Permits for `kernel.max_parallel_tool_calls`; unlimited if `None`
+    pub(crate) tool_slots: Option<tokio::sync::Semaphore>,
+    /// Snapshot of the registry for `bedrock.list_tools()`
+    pub(crate) tool_catalog: crate::harness::globals::ToolCatalog,
+    /// Whether a run is in progress, for idle-only background maintenance
+    pub(crate) activity: Arc<maintenance::Activity>,
+    /// Background maintenance task, if started
+    pub(crate) maintenance: Option<maintenance::MaintenanceHandle>,
 }
 
+/// Follow-up prompt sent when a response is cut off at the output token limit.
+const CONTINUE_PROMPT: &str = "Your previous response was cut off at the output token limit. Continue exactly where it stopped, without repeating anything.";
+
+/// Recent memories exposed to the system prompt template as `memories`.
+const PROMPT_MEMORY_LIMIT: usize = 5;
+
+/// Events a [`Kernel::subscribe`] receiver may fall behind by before it
+/// misses some (`RecvError::Lagged`).
+pub const EVENT_BUS_CAPACITY: usize = 1024;
+
+/// How often a run waiting under `QueueEmptyPolicy::AwaitMore` checks for new tasks.
+const QUEUE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Longest [`Kernel::flush_events`] waits for queued events to be written.
+pub const EVENT_FLUSH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
 /// A pending tool call collected during streaming.
 #[derive(Debug, Clone)]
 struct PendingToolCall {
@@ -63,6 +136,17 @@ struct PendingToolCall {
     args: serde_json::Value,
 }
 
+/// The outcome of running one tool call.
+struct ToolExecution {
+    tc: PendingToolCall,
+    content: String,
+    is_error: bool,
+    metadata: serde_json::Value,
+    sources: Vec<Source>,
+    duration_ms: u64,
+    verdict: String,
+}
+
 impl Kernel {
     /// Create a new Kernel with the given configuration.
     /// Create a new builder for Kernel.
@@ -74,25 +158,96 @@ impl Kernel {
     /// DEPRECATED: Use `Kernel::builder(config).build()` instead.
     #[deprecated(since = "0.9.0", note = "Use Kernel::builder() instead")]
     pub fn new(config: BedrockConfig, json: bool) -> Self {
+        let resources = ResourceLimiter::new(config.tools.resource_limits.clone());
+        let tool_slots = config.kernel.max_parallel_tool_calls.map(|n| tokio::sync::Semaphore::new(n as usize));
+        let tool_registry = crate::tools::builtins::create_registry(&config.tools);
+        let tool_catalog = Arc::new(std::sync::RwLock::new(tool_registry.descriptors()));
         Self {
             config: Arc::new(config),
             json,
-            tool_registry: crate::tools::builtins::create_default_registry(),
+            quiet: false,
+            interactive: true,
+            pin_agent: false,
+            approval_handler: None,
+            verbosity: Verbosity::default(),
+            renderer: std::sync::Mutex::new(EventRenderer::new(json, false, Verbosity::default())),
+            tool_registry,
             state: None,
             harness: Arc::new(Mutex::new(None)),
+            harness_tools: Default::default(),
             check_watcher: None,
             clients: HashMap::new(),
             embedding_provider: None,
             active_queue: Arc::new(Mutex::new(None)),
-            mcp_clients: Vec::new(),
+            active_session: Default::default(),
+            mcp_servers: Default::default(),
+            event_bus: tokio::sync::broadcast::channel(EVENT_BUS_CAPACITY).0,
+            interrupt: InterruptHandle::default(),
+            resources,
+            tool_slots,
+            tool_catalog,
+            activity: Default::default(),
+            maintenance: None,
         }
     }
 
+    /// Describe the active tools, including whether safe mode has them locked.
+    pub fn tools(&self) -> Vec<crate::tools::registry::ToolDescriptor> {
+        self.tool_registry.descriptors()
+    }
+
+    /// The active configuration.
+    pub fn config(&self) -> &BedrockConfig {
+        &self.config
+    }
+
+    /// Handle for interrupting the current turn from another task, or for
+    /// aborting the run from a host embedding the kernel.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Receive the events of every session this kernel runs, with the
+    /// session's id, as they are emitted: turn starts, message and thinking
+    /// deltas, tool calls and their results, and so on, as in the NDJSON of
+    /// `--json`.
+    ///
+    /// Events are published before they are persisted, whether or not a
+    /// state store is configured, so a subscriber sees them live. A
+    /// receiver that falls more than [`EVENT_BUS_CAPACITY`] events behind
+    /// gets `RecvError::Lagged` and skips ahead; dropping it unsubscribes.
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<(String, KernelEvent)> {
+        self.event_bus.subscribe()
+    }
+
     /// Create a new session.
     pub fn create_session(&self) -> SessionState {
         let mut session = SessionState::new();
-        // Spawn background persistence if state is available
-        if let Some(ref store) = self.state {
+        self.spawn_event_task(&mut session);
+        session
+    }
+
+    /// Wait, up to [`EVENT_FLUSH_TIMEOUT`], until every event `session` has
+    /// emitted so far is persisted. Unlike
+    /// [`SessionState::flush_events`], the session stays usable afterwards.
+    pub async fn flush_events(&self, session: &mut SessionState) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        // Dropping the old sender lets the old task finish once it has drained
+        drop(std::mem::replace(&mut session.event_tx, tx));
+        session.event_rx = Some(Arc::new(Mutex::new(Some(rx))));
+        let previous = match session.event_task.take() {
+            Some(task) => task.lock().await.take(),
+            None => None,
+        };
+        self.spawn_event_task(session);
+        if let Some(handle) = previous {
+            let _ = tokio::time::timeout(EVENT_FLUSH_TIMEOUT, handle).await;
+        }
+    }
+
+    fn spawn_event_task(&self, session: &mut SessionState) {
+        // Spawn the background task that persists events if state is available
+        {
              let rx_opt = session.event_rx.take(); // take the rx from session
              if let Some(rx_mutex) = rx_opt {
                  // extract rx from mutex? It's Arc<Mutex<Option<Rx>>>.
@@ -103,7 +258,7 @@ impl Kernel {
                  // Wait, SessionState definition: 
                  // pub event_rx: Option<Arc<Mutex<Option<mpsc::UnboundedReceiver<(String, KernelEvent)>>>>>,
                  // We can take the Arc, then lock and take the Option.
-                 let store_clone = store.clone();
+                 let store_clone = self.state.clone();
                  // We need to spawn a task.
                  // But we can't block here.
                  // We can spawn a task that locks and runs.
@@ -113,9 +268,12 @@ impl Kernel {
                      if let Some(mut rx) = rx_guard.take() {
                          drop(rx_guard); // release lock
                          while let Some((session_id, event)) = rx.recv().await {
+                             let Some(store) = &store_clone else {
+                                 continue;
+                             };
                              let event_type = event.event_type().to_string();
                              let payload = serde_json::to_value(&event).unwrap_or_default();
-                             if let Err(e) = store_clone.insert_event(&session_id, &event_type, &payload).await {
+                             if let Err(e) = store.insert_event(&session_id, &event_type, &payload).await {
                                  warn!(error = %e, "Background persistence error");
                              }
                          }
@@ -124,7 +282,6 @@ impl Kernel {
                  session.event_task = Some(Arc::new(Mutex::new(Some(handle))));
              }
         }
-        session
     }
 
     /// Add a provider client manually (e.g. for testing).
@@ -132,10 +289,10 @@ impl Kernel {
         self.clients.insert(name, client);
     }
 
-    /// Initialize all configured provider clients. Call before `init_harness()` and `run()`.
-    pub fn init_clients(&mut self) -> Result<()> {
+    /// Initialize all configured provider clients and MCP servers. Call before `init_harness()` and `run()`.
+    pub async fn init_clients(&mut self) -> Result<()> {
         for (name, config) in &self.config.providers {
-            let client = self.create_client(name, config)?;
+            let client = self.create_client(name, config).await?;
             self.clients.insert(name.clone(), client);
         }
         
@@ -190,15 +347,21 @@ impl Kernel {
         
         self.embedding_provider = Some(Arc::from(embedding_provider));
 
+        self.init_mcp_servers().await;
+
         Ok(())
     }
 
     /// Initialize the state store. Call before `run()`.
     pub async fn init_state(&mut self) -> Result<()> {
-        let db_path = &self.config.persistence.database_path;
+        let db_path = &self.config.database_path();
         let store = StateStore::open(db_path).await.with_context(|| {
             format!("Failed to initialize state store at '{}'", db_path)
-        })?;
+        })?.with_pretty_payloads(self.config.persistence.pretty_payloads)
+        .with_max_logged_arg_size(
+            self.config.persistence.max_logged_arg_size,
+            self.config.persistence.spill_logged_args,
+        );
         info!(db_path = %db_path, "State store initialized");
         self.state = Some(store.clone());
 
@@ -209,6 +372,25 @@ impl Kernel {
     }
 
 
+    /// Import a session's messages and memories from another state database.
+    /// Call after `init_state()` and `init_clients()`.
+    pub async fn import_session(&self, source: &StateStore, session_id: Option<&str>, reembed: bool) -> Result<crate::persistence::import::ImportReport> {
+        let (target, embedder) = self.memory_handles()?;
+        crate::persistence::import::import_session(source, target, session_id, embedder.as_ref(), reembed).await
+    }
+
+    /// Re-embed memories excluded from search (e.g. imported with a different dimension).
+    pub async fn reindex_memories(&self) -> Result<usize> {
+        let (store, embedder) = self.memory_handles()?;
+        crate::persistence::import::reindex_memories(store, embedder.as_ref()).await
+    }
+
+    fn memory_handles(&self) -> Result<(&StateStore, &Arc<dyn EmbeddingProvider>)> {
+        let store = self.state.as_ref().context("State store not initialized")?;
+        let embedder = self.embedding_provider.as_ref().context("Embedding provider not initialized")?;
+        Ok((store, embedder))
+    }
+
     /// Initialize the harness engine. Call after `init_state()` and before `run()`.
     #[instrument(skip(self), fields(directory = %self.config.harness.directory))]
     pub async fn init_harness(&mut self) -> Result<()> {
@@ -229,6 +411,10 @@ impl Kernel {
             clients: self.clients.clone(),
             embedding_provider: self.embedding_provider.clone(),
             queue: self.active_queue.clone(),
+            active_session: self.active_session.clone(),
+            tools: self.tool_catalog.clone(),
+            safe_mode: self.tool_registry.safe_mode().cloned(),
+            mcp_servers: self.mcp_servers.clone(),
             config: self.config.clone(),
         };
 
@@ -247,6 +433,7 @@ impl Kernel {
         } else {
             warn!(directory = %harness_dir.display(), "No harness scripts found");
         }
+        self.register_harness_tools(&engine)?;
 
         {
             let mut h = self.harness.lock().await;
@@ -255,6 +442,332 @@ impl Kernel {
         Ok(())
     }
 
+    /// Replace the harness tools in the registry with those `engine`'s
+    /// scripts registered. All are checked first, so a conflict leaves the
+    /// registry and the running handlers as they were.
+    fn register_harness_tools(&mut self, engine: &HarnessEngine) -> Result<()> {
+        let tools = engine.tools();
+        if !self.config.tools.enabled {
+            if !tools.is_empty() {
+                warn!(count = tools.len(), "Tools are disabled; not registering harness tools");
+            }
+            return Ok(());
+        }
+        let mut registry = self.tool_registry.clone();
+        for tool in registry.descriptors().into_iter().filter(|d| d.source == ToolSource::Harness) {
+            registry.remove(&tool.name);
+        }
+        let mut shadowed = Vec::new();
+        for def in &tools {
+            let tool = HarnessTool::new(def, self.harness_tools.clone());
+            if let Some(clash) = registry.register_from(ToolSource::Harness, Box::new(tool))
+                .with_context(|| format!("Failed to register harness tool '{}'", def.name))? {
+                shadowed.push(format!("{} ({} shadows {})", clash.name, clash.winner, clash.loser));
+            }
+        }
+        if !shadowed.is_empty() {
+            warn!(shadowed = %shadowed.join(", "), "Harness tools clash with existing tools");
+        }
+        engine.install_tools(&self.harness_tools);
+        self.tool_registry = registry;
+        self.refresh_tool_catalog();
+        if !tools.is_empty() {
+            info!(count = tools.len(), "Harness tools registered");
+        }
+        Ok(())
+    }
+
+    /// Store a tool's artifact and return the handle text shown in its place.
+    /// Without a state store only the preview survives.
+    async fn store_artifact(
+        &self,
+        session_id: &str,
+        tool_call_id: &str,
+        artifact: Artifact,
+        event_tx: &tokio::sync::mpsc::UnboundedSender<(String, KernelEvent)>,
+    ) -> String {
+        let Some(ref store) = self.state else {
+            return format!("[artifact not stored: no state store]\npreview: {}", artifact.preview());
+        };
+        match store.insert_artifact(session_id, tool_call_id, &artifact).await {
+            Ok(handle) => {
+                let text = handle.to_string();
+                self.publish_event(event_tx, session_id, KernelEvent::ArtifactStored {
+                    tool_call_id: tool_call_id.to_string(),
+                    artifact: handle,
+                });
+                text
+            }
+            Err(e) => {
+                warn!(error = %e, "Failed to store artifact");
+                format!("[artifact not stored: {}]\npreview: {}", e, artifact.preview())
+            }
+        }
+    }
+
+    /// Run the harness `on_before_request` hook on the messages about to be
+    /// sent. Returns the edited request if the hook changed it, after
+    /// emitting `RequestRewritten`. Retries of the same request reuse the
+    /// result instead of running the hook again.
+    async fn before_request(
+        &self,
+        session: &SessionState,
+        messages: &[InferenceMessage],
+        model: &str,
+        provider: &str,
+    ) -> Option<RequestState> {
+        let harness = self.harness.lock().await;
+        let engine = harness.as_ref().filter(|engine| engine.has_hook("on_before_request"))?;
+        let request = RequestWrapper::new(messages.to_vec(), model.to_string(), provider.to_string(), session.turn_index);
+        if let Err(e) = engine.evaluate_userdata("on_before_request", request.clone()) {
+            warn!(error = %e, "Harness on_before_request error");
+        }
+        let state = request.get_state();
+        if !state.modified {
+            return None;
+        }
+        debug!(before = messages.len(), after = state.messages.len(), persisted = state.persist, "Request rewritten by harness");
+        self.persist_event(session, &KernelEvent::RequestRewritten {
+            turn_index: session.turn_index,
+            messages_before: messages.len(),
+            messages_sent: state.messages.len(),
+            persisted: state.persist,
+        });
+        Some(state)
+    }
+
+    /// Emit `ToolUnlocked` for tools a harness unlocked (`bedrock.unlock_tool`)
+    /// since the last call. A no-op outside safe mode.
+    fn report_unlocked_tools(&self, session: &SessionState) {
+        let Some(safe_mode) = self.tool_registry.safe_mode() else {
+            return;
+        };
+        let unlocked = safe_mode.write().unwrap_or_else(|e| e.into_inner()).take_unlocked();
+        for tool in unlocked {
+            info!(tool = %tool, "Tool unlocked by harness");
+            self.persist_event(session, &KernelEvent::ToolUnlocked { tool });
+        }
+    }
+
+    /// Store a successful tool result as a memory when the capture policy
+    /// (or the harness `on_memory_capture` hook) says it is worth remembering.
+    /// The tool name and arguments go in the memory's metadata for provenance.
+    async fn capture_tool_result(&self, session: &SessionState, tc: &PendingToolCall, content: &str) {
+        let mut text = content.to_string();
+        let mut capture = self.config.memory.captures(&tc.name, content);
+        let verdict = {
+            let harness = self.harness.lock().await;
+            match &*harness {
+                Some(engine) if engine.has_hook("on_memory_capture") => Some(engine.evaluate("on_memory_capture", serde_json::json!({
+                    "tool": tc.name,
+                    "args": tc.args,
+                    "tool_call_id": tc.id,
+                    "content": content,
+                }))),
+                _ => None,
+            }
+        };
+        match verdict {
+            Some(Ok(Verdict::Modify(decision))) => {
+                if let Some(worth) = decision.get("worth_remembering").and_then(|v| v.as_bool()) {
+                    capture = worth;
+                }
+                if let Some(summary) = decision.get("content").and_then(|v| v.as_str()) {
+                    text = summary.to_string();
+                }
+            }
+            Some(Ok(Verdict::Reject(_))) => capture = false,
+            Some(Err(e)) => warn!(error = %e, "Failed to evaluate on_memory_capture"),
+            _ => {}
+        }
+        if !capture || text.trim().is_empty() {
+            return;
+        }
+
+        let Ok((store, embedder)) = self.memory_handles() else {
+            debug!(tool = %tc.name, "Skipping tool result capture: memory not initialized");
+            return;
+        };
+        let text: String = text.chars().take(self.config.memory.capture_max_chars).collect();
+        let metadata = serde_json::json!({
+            "source": "tool_result",
+            "tool": tc.name,
+            "args": tc.args,
+            "tool_call_id": tc.id,
+            "turn_index": session.turn_index,
+        });
+        let stored = match embedder.embed(&text).await {
+            Ok(embedding) => store.insert_memory(&session.id, &text, &embedding.vector, &metadata).await,
+            Err(e) => Err(e),
+        };
+        match stored {
+            Ok(()) => self.persist_event(session, &KernelEvent::MemoryCaptured {
+                tool_call_id: tc.id.clone(),
+                tool: tc.name.clone(),
+                chars: text.chars().count(),
+            }),
+            Err(e) => warn!(tool = %tc.name, error = %e, "Failed to capture tool result as memory"),
+        }
+    }
+
+    /// Run a `fetch_artifact` call: a slice of the artifact, or a note that
+    /// it was saved to the requested file.
+    async fn fetch_artifact(&self, session_id: &str, request: &serde_json::Value) -> Result<String> {
+        let store = self.state.as_ref().context("No state store")?;
+        let id = request.get("id").and_then(|v| v.as_str()).unwrap_or_default();
+        let artifact = store.get_artifact(session_id, id).await?.with_context(|| format!("No artifact with id '{}'", id))?;
+        if let Some(path) = request.get("save_to").and_then(|v| v.as_str()) {
+            if let Some(parent) = std::path::Path::new(path).parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(path, &artifact.data).await.with_context(|| format!("Failed to write {}", path))?;
+            return Ok(format!("Saved artifact {} ({} bytes) to {}", artifact.id, artifact.size, path));
+        }
+        let offset = request.get("offset").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+        let length = request.get("length").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+        Ok(render_slice(&artifact, offset, length))
+    }
+
+    /// Run a `delegate` call: a sub-agent with its own session, limited to the
+    /// requested tools, turn limit and budget. Its usage counts toward the
+    /// parent session, and the answer is its final assistant message.
+    ///
+    /// Boxed because the child's `run` awaits this same future type.
+    fn delegate<'a>(
+        &'a self,
+        parent: &'a mut SessionState,
+        request: &'a serde_json::Value,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String>> + Send + 'a>> {
+        Box::pin(async move {
+            let goal = request.get("goal").and_then(|v| v.as_str()).context("delegate needs a goal")?;
+            let mut config = (*self.config).clone();
+            if let Some(max_turns) = request.get("max_turns").and_then(|v| v.as_u64()) {
+                config.kernel.max_turns = config.kernel.max_turns.min(max_turns as u32);
+            }
+            // The sub-agent gets what it asks for, but no more than the parent has left
+            let remaining = self.config.cost_budget_usd().map(|budget| budget - parent.total_cost_usd);
+            if remaining.is_some_and(|left| left <= 0.0) {
+                anyhow::bail!("The session's cost budget is spent; nothing left to delegate with");
+            }
+            let requested = request.get("cost_budget_usd").and_then(|v| v.as_f64());
+            if let Some(budget) = match (requested, remaining) {
+                (Some(requested), Some(left)) => Some(requested.min(left)),
+                (requested, left) => requested.or(left),
+            } {
+                config.limits.max_cost_usd = Some(budget);
+            }
+            let mut registry = match request.get("tools").and_then(|v| v.as_array()) {
+                Some(names) => {
+                    let names: Vec<String> = names.iter().filter_map(|n| n.as_str().map(str::to_string)).collect();
+                    self.tool_registry.subset(&names)?
+                }
+                None => self.tool_registry.clone(),
+            };
+            // One level of delegation; a sub-agent can't spawn its own
+            registry.remove("delegate");
+
+            let mut child = Kernel::builder(config)
+                .json_mode(self.json)
+                .verbosity(self.verbosity)
+                .with_tool_registry(registry)
+                .build()?;
+            child.clients = self.clients.clone();
+            child.state = self.state.clone();
+            child.embedding_provider = self.embedding_provider.clone();
+            child.init_harness().await?;
+
+            let mut session = child.create_session();
+            session.parent_session = Some(parent.id.clone());
+            info!(parent = %parent.id, child = %session.id, goal, "Delegating to sub-agent");
+            child.run_with_policy(&mut session, Some(goal.to_string()), QueueEmptyPolicy::Stop).await?;
+            child.end_session(&mut session).await?;
+
+            parent.total_input_tokens += session.total_input_tokens;
+            parent.total_output_tokens += session.total_output_tokens;
+            parent.total_cost_usd += session.total_cost_usd;
+            self.persist_event(parent, &KernelEvent::SubagentFinished {
+                child_session: session.id.clone(),
+                goal: goal.to_string(),
+                turns: session.turn_index,
+                stop_reason: session.stop_reason,
+            });
+
+            let answer = session.final_answer().unwrap_or_default();
+            let stopped = match session.stop_reason {
+                Some(StopReason::Completed) | None => String::new(),
+                Some(reason) => format!(", stopped: {:?}", reason),
+            };
+            let child_id = session.id.clone();
+            session.flush_events(std::time::Duration::from_secs(5)).await;
+            Ok(format!("[sub-agent {}{}]\n{}", child_id, stopped, answer))
+        })
+    }
+
+    /// Publish the current registry to `bedrock.list_tools()`.
+    fn refresh_tool_catalog(&self) {
+        *self.tool_catalog.write().unwrap_or_else(|e| e.into_inner()) = self.tool_registry.descriptors();
+    }
+
+    /// Describe what is wrong with the effective tool registry, if anything.
+    ///
+    /// An empty registry is allowed (requests are then sent without a tools
+    /// parameter). With `tools.enabled = false` that is the point, and only a
+    /// harness defining tool hooks, which can then never fire, is reported.
+    /// Otherwise the builtins are always registered, so an empty registry
+    /// means a custom one (`with_tool_registry`) left them out.
+    pub async fn tool_registry_problem(&self) -> Option<String> {
+        if !self.tool_registry.is_empty() {
+            return None;
+        }
+        let harness = self.harness.lock().await;
+        let hooks: Vec<&str> = ["on_tool_call", "on_tool_result"]
+            .into_iter()
+            .filter(|hook| harness.as_ref().is_some_and(|engine| engine.has_hook(hook)))
+            .collect();
+        if !self.config.tools.enabled {
+            // Chat-only by choice; only a harness expecting tools is worth a warning
+            return (!hooks.is_empty()).then(|| {
+                format!("Tools are disabled, but the harness defines {}; those hooks will never fire", hooks.join(" and "))
+            });
+        }
+        if hooks.is_empty() {
+            Some("No tools are registered; the model will be called without tools".to_string())
+        } else {
+            Some(format!(
+                "No tools are registered, but the harness defines {}; those hooks will never fire",
+                hooks.join(" and ")
+            ))
+        }
+    }
+
+    /// Switch the model and/or provider used for subsequent turns, keeping the
+    /// session's history. The new settings are validated first and the change
+    /// is recorded as a `ModelSwitched` event; on error nothing changes.
+    pub fn switch_agent(&mut self, session: &SessionState, model: Option<String>, provider: Option<String>) -> Result<()> {
+        let mut config = (*self.config).clone();
+        if let Some(model) = model {
+            config.agent.model = model;
+        }
+        if let Some(provider) = provider {
+            config.agent.provider = provider;
+        }
+        config.validate()?;
+        anyhow::ensure!(
+            self.clients.contains_key(&config.agent.provider),
+            "Provider '{}' has no initialized client",
+            config.agent.provider
+        );
+        info!(provider = %config.agent.provider, model = %config.agent.model, "Switching model");
+        self.persist_event(session, &KernelEvent::ModelSwitched {
+            previous_provider: self.config.agent.provider.clone(),
+            previous_model: self.config.agent.model.clone(),
+            provider: config.agent.provider.clone(),
+            model: config.agent.model.clone(),
+        });
+        self.config = Arc::new(config);
+        Ok(())
+    }
+
     /// Reload the harness from disk (atomic swap).
     #[instrument(skip(self))]
     pub async fn reload_harness(&mut self) -> Result<()> {
@@ -262,6 +775,7 @@ impl Kernel {
         self.init_harness().await
     }
 
+    #[allow(clippy::too_many_arguments)]
     #[instrument(skip_all)]
     pub async fn reload_harness_static(
         harness: Arc<Mutex<Option<HarnessEngine>>>,
@@ -270,6 +784,11 @@ impl Kernel {
         state: Option<StateStore>,
         embedding_provider: Option<Arc<dyn EmbeddingProvider>>,
         active_queue: crate::harness::globals::ActiveSessionQueue,
+        active_session: crate::harness::globals::ActiveSessionId,
+        tools: crate::harness::globals::ToolCatalog,
+        safe_mode: Option<crate::tools::safe_mode::SharedSafeMode>,
+        tool_handlers: ToolHandlers,
+        mcp_servers: McpServers,
     ) -> Result<()> {
         let harness_dir = PathBuf::from(&config.harness.directory);
         let fs_root = if config.harness.fs_root == "." {
@@ -285,6 +804,10 @@ impl Kernel {
             clients,
             embedding_provider,
             queue: active_queue,
+            active_session,
+            tools,
+            safe_mode,
+            mcp_servers,
             config: config,
         };
 
@@ -293,6 +816,15 @@ impl Kernel {
                 match engine.load_dir(&harness_dir) {
                     Ok(_) => {
                         let script_count = engine.loaded_scripts().len();
+                        // Handlers are swapped in place; the registry only changes on a full reload
+                        let names = || -> std::collections::BTreeSet<String> {
+                            tool_handlers.read().unwrap_or_else(|e| e.into_inner()).keys().cloned().collect()
+                        };
+                        let before = names();
+                        engine.install_tools(&tool_handlers);
+                        if names() != before {
+                            warn!("Harness tools were added or removed; restart or reload the harness to update the tool list");
+                        }
                         let mut h = harness.lock().await;
                         *h = Some(engine);
                         info!(count = script_count, "Harness reloaded successfully");
@@ -305,6 +837,34 @@ impl Kernel {
         Ok(())
     }
 
+    /// Spawn the background maintenance task if `maintenance.enabled` is set
+    /// and a state store is open. It is cancelled when the kernel is dropped.
+    pub fn start_maintenance(&mut self) {
+        if !self.config.maintenance.enabled || self.maintenance.is_some() {
+            return;
+        }
+        let Some(ref store) = self.state else {
+            warn!("Maintenance is enabled but there is no state store; not starting it");
+            return;
+        };
+        info!(interval_secs = self.config.maintenance.interval_secs, "Starting background maintenance");
+        self.maintenance = Some(maintenance::spawn(store.clone(), self.config.maintenance.clone(), self.activity.clone()));
+    }
+
+    /// Cancel the background maintenance task, waiting for a batch in
+    /// progress to commit.
+    pub async fn stop_maintenance(&mut self) {
+        if let Some(handle) = self.maintenance.take() {
+            handle.stop().await;
+        }
+    }
+
+    /// Run one maintenance pass now, whether or not the kernel is idle.
+    pub async fn run_maintenance(&self) -> Result<maintenance::MaintenanceReport> {
+        let store = self.state.as_ref().context("Maintenance requires persistence (call init_state first)")?;
+        maintenance::run_pass(store, &self.config.maintenance, || true).await
+    }
+
     /// Start watching the harness directory for changes (background thread).
     #[instrument(skip(self))]
     pub fn start_watcher(&mut self) -> Result<()> {
@@ -317,6 +877,11 @@ impl Kernel {
         let state_clone = self.state.clone();
         let embedding_clone = self.embedding_provider.clone();
         let queue_clone = self.active_queue.clone();
+        let session_clone = self.active_session.clone();
+        let tools_clone = self.tool_catalog.clone();
+        let safe_mode_clone = self.tool_registry.safe_mode().cloned();
+        let handlers_clone = self.harness_tools.clone();
+        let mcp_clone = self.mcp_servers.clone();
         let harness_dir = PathBuf::from(&config_clone.harness.directory);
 
         if !harness_dir.exists() {
@@ -342,9 +907,14 @@ impl Kernel {
                 let s = state_clone.clone();
                 let e = embedding_clone.clone();
                 let q = queue_clone.clone();
+                let a = session_clone.clone();
+                let t = tools_clone.clone();
+                let sm = safe_mode_clone.clone();
+                let th = handlers_clone.clone();
+                let ms = mcp_clone.clone();
                 
                 tokio::spawn(async move {
-                    if let Err(err) = Self::reload_harness_static(h, c, cl, s, e, q).await {
+                    if let Err(err) = Self::reload_harness_static(h, c, cl, s, e, q, a, t, sm, th, ms).await {
                         error!(error = %err, "Harness hot-reload failed");
                     }
                 });
@@ -383,20 +953,33 @@ impl Kernel {
 
     /// Run the agent loop with the given prompt.
     ///
-    /// Run the agent with multiple turns.
-    /// Run the agent with multiple turns.
-    #[instrument(skip(self, session), fields(session_id = %session.id))]
+    /// Queued tasks run in order; once the queue drains, `kernel.on_queue_empty`
+    /// decides whether the run ends.
     pub async fn run(&mut self, session: &mut SessionState, prompt: Option<String>) -> Result<()> {
+        let policy = self.config.kernel.on_queue_empty;
+        self.run_with_policy(session, prompt, policy).await
+    }
+
+    /// [`run`](Self::run) with an explicit [`QueueEmptyPolicy`], e.g. `Stop`
+    /// for a REPL that waits for the next prompt itself.
+    #[instrument(skip(self, session, prompt), fields(session_id = %session.id))]
+    pub async fn run_with_policy(&mut self, session: &mut SessionState, prompt: Option<String>, on_empty: QueueEmptyPolicy) -> Result<()> {
+        // An interrupt requested before the run stops it; one left over after it doesn't carry on
+        let _clear_interrupt = self.interrupt.clear_on_drop();
         // Set active queue for harness
         {
             let mut aq = self.active_queue.lock().await;
-            *aq = Some(session.queue.clone());
+            *aq = Some((session.queue.clone(), session.injected_tasks.clone()));
         }
+        *self.active_session.write().unwrap() = Some(session.id.clone());
 
         if let Some(p) = prompt {
+            self.check_prompt_size(&p)?;
             session.queue.lock().await.push_back(p);
         }
 
+        session.stop_reason = None;
+        let _active = self.activity.begin();
         let session_id = session.id.clone();
         
         // Resume session if history is not empty? 
@@ -405,6 +988,7 @@ impl Kernel {
             info!(session_id = %session_id, "Starting new agent session");
             self.persist_event(session, &KernelEvent::AgentStart {
                 session_id: session_id.clone(),
+                parent_session: session.parent_session.clone(),
             });
 
             {
@@ -415,21 +999,46 @@ impl Kernel {
                     }
                 }
             }
+
+            if let Some(problem) = self.tool_registry_problem().await {
+                warn!("{}", problem);
+                if !self.json {
+                    eprintln!("warning: {}", problem);
+                }
+            }
+        }
+
+        // Unnamed sessions are named after the first prompt they see
+        if session.name.is_none() {
+            let first_task = session.queue.lock().await.front().cloned();
+            if let Some(task) = first_task {
+                if let Err(e) = self.auto_name_session(session, &task).await {
+                    warn!(error = %e, "Failed to name session");
+                }
+            }
         }
 
         loop {
             // Pop next task
             {
-                let mut q = session.queue.lock().await;
-                if q.is_empty() {
-                    debug!("Queue empty, ending run");
+                let next = session.queue.lock().await.pop_front();
+                let Some(task) = next else {
+                    if self.await_queued_task(session, on_empty).await {
+                        continue;
+                    }
                     break;
-                }
-                let task = q.pop_front().unwrap();
-                drop(q);
-                
+                };
+
                 info!(task = %task, "Running task");
-                self.run_task(session, &task).await?;
+                match self.config.kernel.mode {
+                    LoopMode::Builtin => self.run_task(session, &task).await?,
+                    LoopMode::Orchestrator => self.run_orchestrated(session, &task).await?,
+                }
+            }
+
+            if matches!(session.stop_reason, Some(StopReason::CostBudgetExceeded | StopReason::LimitExceeded | StopReason::HarnessRejected | StopReason::Interrupted | StopReason::ToolErrorStorm)) {
+                warn!(reason = ?session.stop_reason, "Run aborted");
+                break;
             }
             
             // ─── Harness Hook: on_task_complete ─────────────────────
@@ -457,6 +1066,7 @@ impl Kernel {
                                 let mut q = session.queue.lock().await;
                                 for task in new_tasks {
                                     if let Some(t) = task.as_str() {
+                                        session.injected_tasks.mark(t);
                                         q.push_back(t.to_string());
                                     }
                                 }
@@ -467,6 +1077,7 @@ impl Kernel {
                     },
                     Ok(Verdict::Reject(reason)) => {
                         warn!(reason = %reason, "Session ended with REJECTION from harness");
+                        session.stop_reason = Some(StopReason::HarnessRejected);
                         break;
                     },
                     Ok(_) => {},
@@ -479,25 +1090,143 @@ impl Kernel {
             if recheck {
                 continue;
             }
-            break;
+        }
+
+        if session.stop_reason == Some(StopReason::Interrupted) && self.interrupt.is_aborted() {
+            info!(session_id = %session_id, "Run aborted by host");
+            session.stop_reason = Some(StopReason::Aborted);
+            self.persist_event(session, &KernelEvent::Aborted { turn_index: session.turn_index });
+            if self.config.persistence.snapshot_interval_turns > 0 {
+                self.save_snapshot(session).await;
+            }
+        }
+
+        if session.stop_reason.is_none() {
+            session.stop_reason = Some(StopReason::Completed);
         }
         
         Ok(())
     }
 
+    /// Give the session a name, usable in place of its id by commands that
+    /// take a session. Fails if the name is invalid or already taken.
+    pub async fn name_session(&self, session: &mut SessionState, name: &str) -> Result<()> {
+        session::validate_session_name(name)?;
+        if let Some(ref store) = self.state {
+            store.set_session_name(&session.id, name).await?;
+        }
+        session.name = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Name an unnamed session after its first prompt, with a numeric
+    /// suffix if another session already has that name.
+    async fn auto_name_session(&self, session: &mut SessionState, prompt: &str) -> Result<()> {
+        let slug = session::session_slug(prompt);
+        let name = match self.state {
+            Some(ref store) => store.unused_session_name(&slug).await?,
+            None => slug,
+        };
+        info!(name = %name, "Session named");
+        self.name_session(session, &name).await
+    }
+
+    /// Apply `policy` to an empty queue. Returns true once there is another
+    /// task to run; false ends the run.
+    async fn await_queued_task(&self, session: &mut SessionState, policy: QueueEmptyPolicy) -> bool {
+        match policy {
+            QueueEmptyPolicy::Stop => {
+                debug!("Queue empty, ending run");
+                false
+            }
+            QueueEmptyPolicy::AwaitMore => {
+                debug!("Queue empty, waiting for more tasks");
+                loop {
+                    tokio::select! {
+                        _ = self.interrupt.interrupted() => {
+                            info!("Interrupted while waiting for tasks; ending run");
+                            session.stop_reason = Some(StopReason::Interrupted);
+                            return false;
+                        }
+                        _ = tokio::time::sleep(QUEUE_POLL_INTERVAL) => {}
+                    }
+                    if !session.queue.lock().await.is_empty() {
+                        return true;
+                    }
+                }
+            }
+            QueueEmptyPolicy::CompletionCheck => {
+                let verdict = {
+                    let harness = self.harness.lock().await;
+                    match harness.as_ref().filter(|engine| engine.has_hook("should_continue")) {
+                        Some(engine) => engine.evaluate("should_continue", serde_json::json!({
+                            "session_id": session.id,
+                            "turn_count": session.turn_index,
+                            "final_answer": session.final_answer(),
+                        })),
+                        None => return false,
+                    }
+                };
+                let tasks: Vec<String> = match verdict {
+                    Ok(Verdict::Modify(serde_json::Value::String(task))) => vec![task],
+                    Ok(Verdict::Modify(serde_json::Value::Array(tasks))) => {
+                        tasks.into_iter().filter_map(|t| t.as_str().map(str::to_string)).collect()
+                    }
+                    Ok(_) => Vec::new(),
+                    Err(e) => {
+                        warn!(error = %e, "Harness should_continue error");
+                        Vec::new()
+                    }
+                };
+                if tasks.is_empty() {
+                    debug!("Harness should_continue queued nothing, ending run");
+                    return false;
+                }
+                info!(count = tasks.len(), "Harness should_continue queued more tasks");
+                session.queue.lock().await.extend(tasks);
+                true
+            }
+        }
+    }
+
     /// End the session and emit AgentEnd event.
+    ///
+    /// Runs the harness `on_session_end` hook with the final session stats.
+    /// Callers should invoke this even when `run()` failed (after setting
+    /// `session.stop_reason`); hook errors are logged and never propagated.
     pub async fn end_session(&mut self, session: &mut SessionState) -> Result<()> {
          self.persist_event(session, &KernelEvent::AgentEnd {
             message_count: session.turn_index,
             total_input_tokens: session.total_input_tokens,
             total_output_tokens: session.total_output_tokens,
+            total_cost_usd: session.total_cost_usd,
          });
+
+         // ─── Harness Hook: on_session_end ───────────────────────────
+         {
+             let harness = self.harness.lock().await;
+             if let Some(ref engine) = *harness {
+                 let payload = serde_json::json!({
+                     "session_id": session.id,
+                     "turns": session.turn_index,
+                     "input_tokens": session.total_input_tokens,
+                     "output_tokens": session.total_output_tokens,
+                     "total_tokens": session.total_input_tokens + session.total_output_tokens,
+                     "cost_usd": session.total_cost_usd,
+                     "stop_reason": session.stop_reason,
+                 });
+                 if let Err(e) = engine.evaluate("on_session_end", payload) {
+                     warn!(error = %e, "Harness on_session_end failed");
+                 }
+             }
+         }
          
          // Clear active queue
          {
              let mut aq = self.active_queue.lock().await;
              *aq = None;
          }
+         *self.active_session.write().unwrap() = None;
          
          Ok(())
     }
@@ -507,6 +1236,29 @@ impl Kernel {
         let mut q = session.queue.lock().await;
         q.push_back(prompt);
     }
+
+    /// Add text messages to the session's history ahead of its next prompt,
+    /// keeping their roles, e.g. the leading messages of an MCP prompt.
+    pub async fn add_messages(&self, session: &mut SessionState, messages: Vec<(InferenceRole, String)>) {
+        for (role, text) in messages {
+            if let Some(ref store) = self.state {
+                let role_name = if role == InferenceRole::Assistant { "assistant" } else { "user" };
+                let _ = store.insert_message(
+                    &session.id,
+                    session.turn_index,
+                    role_name,
+                    &serde_json::json!([{"type": "text", "text": text}]),
+                    None,
+                    None,
+                ).await;
+            }
+            session.history.push(InferenceMessage {
+                role,
+                content: vec![InferenceContent::Text { text }],
+                tool_call_id: None,
+            });
+        }
+    }
     
     /// Execute a single task (one specific prompt) within the persistent session.
     #[instrument(skip(self, session, prompt), fields(task = %prompt))]
@@ -520,18 +1272,20 @@ impl Kernel {
             tool_call_id: None,
         });
 
-        let tool_ctx = ToolContext {
-            workspace_root: std::path::PathBuf::from(&self.config.kernel.workspace_root),
-            session_id: session_id.clone(),
-        };
+        let tool_ctx = self.tool_context(&session_id);
 
-        // Persist user message
+        // Persist user message, marking tasks the user didn't send
         if let Some(ref store) = self.state {
+            let part = match session.injected_tasks.take(prompt) {
+                true => serde_json::json!({"type": "text", "text": prompt, "injected": true}),
+                false => serde_json::json!({"type": "text", "text": prompt}),
+            };
             let _ = store.insert_message(
                 &session_id,
                 session.turn_index,
                 "user",
-                &serde_json::json!([{"type": "text", "text": prompt}]),
+                &serde_json::json!([part]),
+                None,
                 None,
             ).await;
         }
@@ -542,14 +1296,20 @@ impl Kernel {
         loop {
             if task_turn_count >= max_task_turns {
                 error!(max_turns = max_task_turns, "Max turns reached for this task");
+                session.stop_reason = Some(StopReason::MaxTurns);
+                break;
+            }
+            if self.interrupt.is_interrupted() {
+                session.stop_reason = Some(StopReason::Interrupted);
                 break;
             }
 
             let completed_turn = self.execute_turn(session, &tool_ctx).await?;
 
-            self.evaluate_token_usage(session.total_input_tokens, session.total_output_tokens).await;
+            self.evaluate_token_usage(session).await;
             session.turn_index += 1;
             task_turn_count += 1;
+            self.snapshot_if_due(session).await;
 
             if !completed_turn {
                 break;
@@ -558,74 +1318,450 @@ impl Kernel {
         Ok(())
     }
 
-    /// Execute a single turn of the agent loop. Returns true if loop should continue.
-    async fn execute_turn(&mut self, session: &mut SessionState, tool_ctx: &ToolContext) -> Result<bool> {
-        let session_id = session.id.clone();
+    /// Reject a prompt larger than `kernel.max_prompt_tokens` before anything runs.
+    pub fn check_prompt_size(&self, prompt: &str) -> Result<()> {
+        if let Some(limit) = self.config.kernel.max_prompt_tokens {
+            let tokens = pricing::estimate_tokens(prompt);
+            anyhow::ensure!(
+                tokens <= limit as u64,
+                "Prompt is ~{} tokens, over the kernel.max_prompt_tokens limit of {}. Split the input (e.g. `bedrock run --from-stdin --chunk`) or raise the limit.",
+                tokens, limit
+            );
+        }
+        Ok(())
+    }
 
-        // Turn-local configuration
-        let mut model = self.config.agent.model.clone();
-        let mut provider_name = self.config.agent.provider.clone();
-        let mut system_prompt = self.config.agent.system_prompt.clone();
+    /// Run the `agent.postprocessors` chain on a finished assistant message,
+    /// emitting a `MessagePostprocessed` event per step.
+    async fn postprocess_message(&self, session: &SessionState, mut text: String) -> String {
+        for step in &self.config.agent.postprocessors {
+            let name = postprocess::step_name(step);
+            let result = match step {
+                config::Postprocessor::Harness => self.harness_postprocess(session, &text).await,
+                _ => postprocess::apply(step, &text),
+            };
+            let chars_before = text.chars().count();
+            let mut changed = false;
+            let error = match result {
+                Ok(processed) => {
+                    changed = processed != text;
+                    text = processed;
+                    None
+                }
+                Err(e) => {
+                    warn!(step = name, error = %e, "Postprocessor left the assistant message unchanged");
+                    Some(e)
+                }
+            };
+            let chars_after = text.chars().count();
+            debug!(step = name, changed, chars_before, chars_after, "Postprocessed assistant message");
+            self.persist_event(session, &KernelEvent::MessagePostprocessed {
+                turn_index: session.turn_index,
+                step: name.to_string(),
+                changed,
+                chars_before,
+                chars_after,
+                error,
+            });
+        }
+        text
+    }
 
-        self.persist_event(session, &KernelEvent::TurnStart { turn_index: session.turn_index });
+    /// The `harness` postprocessor: `on_assistant_message` may return
+    /// `MODIFY` with the replacement text (or `{ text = ... }`).
+    async fn harness_postprocess(&self, session: &SessionState, text: &str) -> std::result::Result<String, String> {
+        let harness = self.harness.lock().await;
+        let Some(ref engine) = *harness else {
+            return Ok(text.to_string());
+        };
+        let payload = serde_json::json!({ "text": text, "turn_index": session.turn_index });
+        match engine.evaluate("on_assistant_message", payload) {
+            Ok(Verdict::Modify(serde_json::Value::String(s))) => Ok(s),
+            Ok(Verdict::Modify(value)) => value["text"]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| "on_assistant_message MODIFY needs a string or { text = ... }".to_string()),
+            Ok(_) => Ok(text.to_string()),
+            Err(e) => Err(format!("on_assistant_message failed: {}", e)),
+        }
+    }
 
-        // ─── Harness Hook: on_before_inference ───────────────────────
-        let mut thinking_budget = self.config.agent.thinking.as_ref()
-            .and_then(|t| if t.enabled { t.budget_tokens } else { None })
-            .unwrap_or(0);
+    /// Render `agent.system_prompt` for the session's next turn.
+    async fn render_system_prompt(&self, session: &SessionState) -> Result<String> {
+        let prompt = &self.config.agent.system_prompt;
+        if !template::has_markup(prompt) {
+            return Ok(prompt.clone());
+        }
 
-        {
-            let harness = self.harness.lock().await;
-            if let Some(ref engine) = *harness {
-                let ctx = ContextWrapper::new(
-                    model.clone(),
-                    provider_name.clone(),
-                    system_prompt.clone(),
-                    session.history.clone(),
-                    0, 128_000,
-                    thinking_budget,
-                    self.clients.clone(),
-                );
-                
-                match engine.evaluate_userdata("on_before_inference", ctx.clone()) {
-                    Ok(verdict) => {
-                         if verdict.is_rejected() {
-                             warn!(reason = %verdict.reason().unwrap_or(""), "Turn rejected by harness");
-                             return Ok(false);
-                         }
-                    }
-                    Err(e) => {
-                         warn!(error = %e, "Harness on_before_inference error");
-                    }
+        let mut tools: Vec<template::ToolInfo> = self.tool_registry
+            .tool_definitions()
+            .iter()
+            .map(|def| template::ToolInfo {
+                name: def["name"].as_str().unwrap_or_default().to_string(),
+                description: def["description"].as_str().unwrap_or_default().to_string(),
+            })
+            .collect();
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+
+        // Memories cost a query, so they are only loaded when the prompt uses them
+        let mut memories = Vec::new();
+        if let (true, Some(store)) = (prompt.contains("memories"), &self.state) {
+            match store.get_memories(Some(&session.id)).await {
+                Ok(stored) => {
+                    let skip = stored.len().saturating_sub(PROMPT_MEMORY_LIMIT);
+                    memories = stored.into_iter().skip(skip).map(|m| m.content).collect();
                 }
-
-                let state = ctx.get_state();
-                session.history = state.messages;
-                system_prompt = state.system_prompt;
-                model = state.model;
-                provider_name = state.provider;
-                thinking_budget = state.thinking_budget;
+                Err(e) => warn!(error = %e, "Failed to load memories for the system prompt"),
             }
         }
 
-        if !self.clients.contains_key(&provider_name) {
-             if let Some(config) = self.config.providers.get(&provider_name) {
-                 debug!(provider = %provider_name, "Lazily initializing provider");
-                 match self.create_client(&provider_name, config) {
-                     Ok(client) => { self.clients.insert(provider_name.clone(), client); },
-                     Err(e) => {
-                         error!(provider = %provider_name, error = %e, "Failed to initialize provider");
-                         anyhow::bail!("Failed to initialize provider '{}': {}", provider_name, e);
-                     }
-                 }
-             } else {
-                 anyhow::bail!("Provider '{}' not found in configuration", provider_name);
-             }
-        }
+        let ctx = PromptContext {
+            session: template::SessionInfo { id: session.id.clone(), turn_index: session.turn_index },
+            model: self.config.agent.model.clone(),
+            provider: self.config.agent.provider.clone(),
+            tools,
+            memories,
+            usage: template::UsageInfo {
+                input_tokens: session.total_input_tokens,
+                output_tokens: session.total_output_tokens,
+                total_tokens: session.total_input_tokens + session.total_output_tokens,
+                cost_usd: session.total_cost_usd,
+            },
+        };
+        template::render(self.config.agent.prompt_template, prompt, &ctx)
+    }
+
+    /// Write a session snapshot when `persistence.snapshot_interval_turns` is due.
+    async fn snapshot_if_due(&self, session: &SessionState) {
+        let interval = self.config.persistence.snapshot_interval_turns;
+        if interval > 0 && session.turn_index.is_multiple_of(interval) {
+            self.save_snapshot(session).await;
+        }
+    }
+
+    /// Write a session snapshot now; failures are logged, not returned.
+    async fn save_snapshot(&self, session: &SessionState) {
+        let Some(store) = &self.state else { return };
+        let snapshot = SessionSnapshot::capture(session, &self.config.agent.model, &self.config.agent.provider);
+        let result = async {
+            let last_message_id = store.last_message_id(&session.id).await?;
+            store.put_snapshot(&session.id, SNAPSHOT_VERSION, last_message_id, &serde_json::to_string(&snapshot)?).await
+        }.await;
+        match result {
+            Ok(()) => debug!(turn = session.turn_index, "Session snapshot saved"),
+            Err(e) => warn!(error = %e, "Failed to save session snapshot"),
+        }
+    }
+
+    /// Load a persisted session so it can continue. Starts from the latest
+    /// snapshot and replays only newer messages; without a usable snapshot
+    /// every message is replayed (token and cost totals then start at zero).
+    /// A snapshot also restores the model and provider the session was
+    /// using, unless the kernel was built with `pin_agent`.
+    pub async fn resume_session(&mut self, session_id: &str) -> Result<SessionState> {
+        let store = self.state.clone().context("Resuming a session requires persistence (call init_state first)")?;
+        let mut session = self.create_session();
+        let session_id = &store.resolve_session(session_id).await?;
+        session.id = session_id.clone();
+        session.name = store.session_name(session_id).await?;
+
+        let snapshot = match store.get_snapshot(session_id).await? {
+            Some(row) if row.version == SNAPSHOT_VERSION => match serde_json::from_str::<SessionSnapshot>(&row.state) {
+                Ok(snapshot) => Some((snapshot, row.last_message_id)),
+                Err(e) => {
+                    warn!(error = %e, "Unreadable session snapshot; replaying all messages");
+                    None
+                }
+            },
+            Some(row) => {
+                warn!(version = row.version, expected = SNAPSHOT_VERSION, "Incompatible session snapshot; replaying all messages");
+                None
+            }
+            None => None,
+        };
+
+        let after_id = match snapshot {
+            Some((snapshot, last_message_id)) => {
+                if self.pin_agent {
+                    debug!(provider = %snapshot.provider, model = %snapshot.model, "Keeping the configured model over the snapshot's");
+                } else if self.config.providers.contains_key(&snapshot.provider) {
+                    let config = Arc::make_mut(&mut self.config);
+                    config.agent.model = snapshot.model.clone();
+                    config.agent.provider = snapshot.provider.clone();
+                } else {
+                    warn!(provider = %snapshot.provider, "Snapshot provider is not configured; keeping the current model");
+                }
+                snapshot.restore(&mut session);
+                last_message_id
+            }
+            None => 0,
+        };
+
+        let rows = store.get_messages_after(session_id, after_id).await?;
+        anyhow::ensure!(after_id > 0 || !rows.is_empty(), "Session '{}' has no stored messages", session_id);
+        session.history.extend(snapshot::history_from_rows(&rows));
+        // Checked across the snapshot boundary: a call may be in the snapshot
+        // and its result in a later row
+        let dropped = snapshot::drop_unpaired_tool_blocks(&mut session.history);
+        if dropped > 0 {
+            warn!(session_id, dropped, "Dropped tool calls and results missing their other half");
+        }
+        if let Some(last) = rows.last() {
+            session.turn_index = session.turn_index.max(last.turn_index + 1);
+        }
+        info!(session_id, replayed = rows.len(), from_snapshot = after_id > 0, "Session resumed");
+        Ok(session)
+    }
+
+    /// Branch a stored session (name or id): copy its history from the turns
+    /// before `at_turn` into a new session, optionally named `name`, and
+    /// return the new id. Resuming the fork continues from `at_turn` with a
+    /// different prompt, leaving the original trace untouched.
+    ///
+    /// Only messages are copied. The original's snapshot describes turns the
+    /// fork doesn't have, so the fork is resumed by replaying its messages:
+    /// its token and cost totals start at zero, it runs on the configured
+    /// model rather than the one the original used, and its events and tool
+    /// executions (and so `sessions cost`) begin at `at_turn`.
+    ///
+    /// The name is checked before anything is copied, so an invalid or taken
+    /// name leaves no unnamed fork behind.
+    pub async fn fork_session(&self, from_id: &str, at_turn: u32, name: Option<&str>) -> Result<String> {
+        let store = self.state.as_ref().context("Forking a session requires persistence (call init_state first)")?;
+        let from_id = store.resolve_session(from_id).await?;
+        if let Some(name) = name {
+            session::validate_session_name(name)?;
+            if let Some(owner) = store.session_by_name(name).await? {
+                anyhow::bail!("Session name '{}' is already used by session {}", name, owner);
+            }
+        }
+        let fork_id = store.fork_session(&from_id, at_turn).await?;
+        if let Some(name) = name {
+            store.set_session_name(&fork_id, name).await?;
+        }
+        info!(from = %from_id, at_turn, fork = %fork_id, "Session forked");
+        Ok(fork_id)
+    }
+
+    /// Hand one task to the harness's `on_orchestrate` hook (orchestrator mode).
+    ///
+    /// The script drives inference and tool execution through the orchestration
+    /// primitives; the kernel only supplies clients, tools and persistence, then
+    /// folds the resulting history and token usage back into the session.
+    async fn run_orchestrated(&mut self, session: &mut SessionState, prompt: &str) -> Result<()> {
+        // No user message is stored for the task, so its mark isn't needed
+        session.injected_tasks.take(prompt);
+        let system_prompt = self.render_system_prompt(session).await?;
+        let orchestration = Orchestration::new(
+            session.id.clone(),
+            session.turn_index,
+            session.history.clone(),
+            self.config.agent.model.clone(),
+            self.config.agent.provider.clone(),
+            system_prompt,
+            self.clients.clone(),
+            self.tool_registry.clone(),
+            self.tool_context(&session.id),
+            self.state.clone(),
+        )
+        .with_limits(
+            OrchestrationLimits {
+                max_model_calls: self.config.kernel.max_turns,
+                cost_budget_usd: self.config.cost_budget_usd(),
+                pricing: self.config.pricing.clone(),
+            },
+            session.total_cost_usd,
+        );
+        let usage_before = (session.total_input_tokens, session.total_output_tokens, session.total_cost_usd);
+
+        self.persist_event(session, &KernelEvent::TurnStart { turn_index: session.turn_index });
+
+        let (verdict, orchestration) = {
+            let harness = self.harness.lock().await;
+            let engine = harness
+                .as_ref()
+                .filter(|engine| engine.has_hook("on_orchestrate"))
+                .context("kernel.mode = \"orchestrator\" requires a harness script defining on_orchestrate")?;
+            engine.begin_orchestration(orchestration);
+            let verdict = engine.evaluate("on_orchestrate", serde_json::json!({
+                "session_id": session.id,
+                "prompt": prompt,
+                "turn_index": session.turn_index,
+            }));
+            (verdict, engine.end_orchestration())
+        };
+
+        let mut has_tool_calls = false;
+        let mut refused = None;
+        if let Some(orchestration) = orchestration {
+            has_tool_calls = orchestration.tool_calls > 0;
+            session.history = orchestration.history;
+            for usage in &orchestration.usage {
+                self.record_usage(session, &usage.provider, &usage.model, usage.input_tokens, usage.output_tokens).await;
+            }
+            refused = orchestration.stop_reason.map(|reason| (reason, orchestration.refused_estimate_usd));
+        }
+        match refused {
+            Some((StopReason::MaxTurns, _)) => {
+                error!(max_turns = self.config.kernel.max_turns, "Max turns reached for this task");
+                session.stop_reason = Some(StopReason::MaxTurns);
+            }
+            Some((StopReason::CostBudgetExceeded, estimate)) => {
+                let budget = self.config.cost_budget_usd().unwrap_or_default();
+                error!(spent_usd = session.total_cost_usd, estimate_usd = ?estimate, budget_usd = budget, "Next request would exceed cost budget");
+                self.persist_event(session, &KernelEvent::CostBudgetExceeded {
+                    spent_usd: session.total_cost_usd,
+                    budget_usd: budget,
+                    next_request_estimate_usd: estimate,
+                });
+                session.stop_reason = Some(StopReason::CostBudgetExceeded);
+            }
+            _ => {
+                self.check_cost_budget(session);
+            }
+        }
+        self.check_usage_limits(session, usage_before);
+        self.persist_event(session, &KernelEvent::TurnEnd {
+            turn_index: session.turn_index,
+            has_tool_calls,
+        });
+        self.evaluate_token_usage(session).await;
+        session.turn_index += 1;
+
+        // A refused call_model surfaces as a script error; the stop reason says why
+        if refused.is_some() {
+            return Ok(());
+        }
+        match verdict? {
+            Verdict::Reject(reason) => {
+                warn!(reason = %reason, "Orchestration REJECTED by harness");
+                session.stop_reason = Some(StopReason::HarnessRejected);
+            }
+            Verdict::Escalate(reason) => warn!(reason = %reason, "Orchestration escalated by harness"),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Execute a single turn of the agent loop. Returns true if loop should continue.
+    async fn execute_turn(&mut self, session: &mut SessionState, tool_ctx: &ToolContext) -> Result<bool> {
+        let session_id = session.id.clone();
+
+        // Turn-local configuration
+        let mut model = self.config.agent.model.clone();
+        let mut provider_name = self.config.agent.provider.clone();
+        let mut system_prompt = self.render_system_prompt(session).await?;
+
+        self.persist_event(session, &KernelEvent::TurnStart { turn_index: session.turn_index });
+        let turn_start = Instant::now();
+
+        // Drop aborts requested in an earlier turn that had no tool call to apply to
+        if let Some(ref engine) = *self.harness.lock().await {
+            engine.take_tool_abort();
+        }
+
+        // Surface the session's plan so the model keeps working against it
+        if self.config.agent.inject_plan {
+            if let Some(ref store) = self.state {
+                match store.get_plan(&session_id).await {
+                    Ok(steps) if !steps.is_empty() => {
+                        system_prompt = format!("{}\n\n## Current plan\n{}", system_prompt, render_plan(&steps));
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!(error = %e, "Failed to load plan"),
+                }
+            }
+        }
+
+        // ─── Harness Hook: on_before_inference ───────────────────────
+        let mut thinking_budget = self.config.agent.thinking.as_ref()
+            .and_then(|t| if t.enabled { t.budget_tokens } else { None })
+            .unwrap_or(0);
 
-        let client = self.clients.get(&provider_name)
+        let min_retained_turns = self.config.kernel.min_retained_turns;
+        let context_window = self.config.kernel.context_window_tokens;
+        {
+            let harness = self.harness.lock().await;
+            if let Some(ref engine) = *harness {
+                let mut ctx = ContextWrapper::new(
+                    model.clone(),
+                    provider_name.clone(),
+                    system_prompt.clone(),
+                    session.history.clone(),
+                    0, context_window,
+                    thinking_budget,
+                    self.clients.clone(),
+                ).with_retained_start(retention::retained_tail_start(&session.history, min_retained_turns));
+                if let Some(ref store) = self.state {
+                    ctx = ctx.with_tool_log(store.clone(), session_id.clone());
+                }
+                
+                match engine.evaluate_userdata("on_before_inference", ctx.clone()) {
+                    Ok(verdict) => {
+                         if verdict.is_rejected() {
+                             warn!(reason = %verdict.reason().unwrap_or(""), "Turn rejected by harness");
+                             return Ok(false);
+                         }
+                    }
+                    Err(e) => {
+                         warn!(error = %e, "Harness on_before_inference error");
+                    }
+                }
+
+                let state = ctx.get_state();
+                // The retention floor wins over harness trimming/compaction
+                if retention::tail_preserved(&session.history, &state.messages, min_retained_turns) {
+                    session.history = state.messages;
+                } else {
+                    warn!(min_retained_turns, "on_before_inference altered the last retained turns; keeping the original messages");
+                    self.persist_event(session, &KernelEvent::HarnessEditDiscarded {
+                        turn_index: session.turn_index,
+                        hook: "on_before_inference".to_string(),
+                        reason: format!("the message edits changed the last {} turns (kernel.min_retained_turns)", min_retained_turns),
+                    });
+                }
+                system_prompt = state.system_prompt;
+                model = state.model;
+                provider_name = state.provider;
+                thinking_budget = state.thinking_budget;
+            }
+        }
+
+        if self.config.compaction.enabled {
+            self.compact_history(session, &system_prompt, &model, &provider_name).await;
+        }
+
+        if min_retained_turns > 0 {
+            let floor = retention::floor_tokens(&session.history, min_retained_turns, &system_prompt);
+            anyhow::ensure!(
+                floor <= context_window as u64,
+                "The last {} turns (~{} tokens) exceed the {}-token context window; lower kernel.min_retained_turns or raise kernel.context_window_tokens",
+                min_retained_turns, floor, context_window
+            );
+        }
+
+        if !self.clients.contains_key(&provider_name) {
+             if let Some(config) = self.config.providers.get(&provider_name) {
+                 debug!(provider = %provider_name, "Lazily initializing provider");
+                 match self.create_client(&provider_name, config).await {
+                     Ok(client) => { self.clients.insert(provider_name.clone(), client); },
+                     Err(e) => {
+                         error!(provider = %provider_name, error = %e, "Failed to initialize provider");
+                         anyhow::bail!("Failed to initialize provider '{}': {}", provider_name, e);
+                     }
+                 }
+             } else {
+                 anyhow::bail!("Provider '{}' not found in configuration", provider_name);
+             }
+        }
+
+        let mut client = self.clients.get(&provider_name)
             .ok_or_else(|| anyhow::anyhow!("Provider '{}' not initialized", provider_name))?
             .clone();
+        // Position in agent.fallbacks of the next provider to fail over to
+        let mut next_fallback = 0;
+        // Backoff retries spent on the current provider (inference.retry)
+        let mut provider_retries = 0;
 
         let tools = self.tool_registry.tool_definitions();
 
@@ -635,67 +1771,329 @@ impl Kernel {
             thinking_budget: Some(thinking_budget),
         };
 
-        let mut stream = client.stream(&model, &system_prompt, &session.history, &tools, &options).await?;
-        
+        let usage_before = (session.total_input_tokens, session.total_output_tokens, session.total_cost_usd);
         let mut response_text = String::with_capacity(4096);
+        let mut thinking_text = String::new();
         let mut pending_tool_calls: Vec<PendingToolCall> = Vec::new();
+        // The SDKs don't expose HTTP bodies, so the raw response is the
+        // unmodified provider event stream for this turn.
+        let store_raw = self.state.is_some() && self.config.persistence.store_raw_responses;
+        let redact_stream = self.config.agent.postprocessors.contains(&config::Postprocessor::RedactSecrets);
+        let mut raw_events: Vec<serde_json::Value> = Vec::new();
+        let max_turn_retries = self.config.kernel.max_turn_retries;
+        let mut attempt = 0;
+        let interrupt = self.interrupt.clone();
+        let mut interrupted = false;
+        let mut overflow_retried = false;
+        let max_continuations = self.config.kernel.max_continuations;
+        let mut continuations = 0;
+        // Text of earlier responses in this turn that were cut off at the output limit
+        let mut continued_text = String::new();
+        let mut continued_thinking = String::new();
+        let mut stop_reason: Option<String>;
+        // Tool calls started before the response ends (kernel.eager_tool_dispatch)
+        let eager_mode = self.config.kernel.eager_tool_dispatch;
+        let mut eager_running: FuturesUnordered<BoxFuture<'_, ToolExecution>> = FuturesUnordered::new();
+        let mut eager_done: Vec<ToolExecution> = Vec::new();
+        let mut eager_ids: HashSet<String> = HashSet::new();
+        // Verdicts already evaluated while streaming, so the harness sees each call once
+        let mut early_verdicts: HashMap<String, Verdict> = HashMap::new();
+        let mut eager_previous: Option<LastToolCall>;
+        // The `on_before_request` edit of the last request, reused while retries
+        // resend the same messages; keyed by (history length, continued text length)
+        let mut rewrite: Option<((usize, usize), Option<RequestState>)> = None;
+        // Periodically persist the in-progress message so a crash mid-stream doesn't lose it
+        let partial_interval = match (&self.state, self.config.persistence.partial_message_interval_ms) {
+            (Some(_), ms) if ms > 0 => Some(std::time::Duration::from_millis(ms)),
+            _ => None,
+        };
+
+        // Refuse to send a request whose estimated cost would cross the budget
+        if let Some(budget) = self.config.cost_budget_usd() {
+            match estimate_request_cost(self.pricing(&provider_name, &model), &system_prompt, &session.history, &tools, &options) {
+                Some(estimate) if session.total_cost_usd + estimate > budget => {
+                    error!(spent_usd = session.total_cost_usd, estimate_usd = estimate, budget_usd = budget, "Next request would exceed cost budget");
+                    self.persist_event(session, &KernelEvent::CostBudgetExceeded {
+                        spent_usd: session.total_cost_usd,
+                        budget_usd: budget,
+                        next_request_estimate_usd: Some(estimate),
+                    });
+                    session.stop_reason = Some(StopReason::CostBudgetExceeded);
+                    return Ok(false);
+                }
+                Some(_) => {}
+                None => warn!(model = %model, "No pricing for model; the cost limit cannot be enforced"),
+            }
+        }
 
-        while let Some(event_result) = stream.next().await {
-             let event = event_result?;
-             match &event {
-                KernelEvent::MessageDelta { content_delta } => {
-                    if !self.json {
-                        print!("{}", content_delta);
-                        io::stdout().flush().ok();
+        loop {
+            response_text.clear();
+            thinking_text.clear();
+            pending_tool_calls.clear();
+            raw_events.clear();
+            stop_reason = None;
+            if !eager_ids.is_empty() {
+                // Dropping the futures cancels calls still running; finished ones already took effect
+                warn!(turn = session.turn_index, started = eager_ids.len(), "Discarding tool calls started by a retried response");
+                eager_running = FuturesUnordered::new();
+                eager_done.clear();
+                eager_ids.clear();
+            }
+            early_verdicts.clear();
+            eager_previous = session.last_tool_call.clone();
+
+            let continuation =
+                (!continued_text.is_empty()).then(|| continuation_messages(&session.history, &continued_text));
+            let request_key = (session.history.len(), continued_text.len());
+            if rewrite.as_ref().is_none_or(|(key, _)| *key != request_key) {
+                let rewritten = self
+                    .before_request(session, continuation.as_deref().unwrap_or(&session.history), &model, &provider_name)
+                    .await;
+                let mut key = request_key;
+                if let Some(ref request) = rewritten {
+                    // A continuation's stitched messages aren't the history, so there is nothing to persist into
+                    if request.persist && continuation.is_none() {
+                        session.history = request.messages.clone();
+                        key.0 = session.history.len();
+                        // Resuming restores the history from the latest snapshot
+                        self.save_snapshot(session).await;
                     }
-                    self.persist_event(session, &event);
-                    response_text.push_str(content_delta);
                 }
-                KernelEvent::ThinkingDelta { thinking: _ } => {
-                    // We don't append thinking to response_text
-                    self.persist_event(session, &event);
+                rewrite = Some((key, rewritten));
+            }
+            let rewritten = rewrite.as_ref().and_then(|(_, rewritten)| rewritten.as_ref());
+            let messages = match (rewritten, &continuation) {
+                (Some(request), _) => &request.messages,
+                (None, Some(continuation)) => continuation,
+                (None, None) => &session.history,
+            };
+            let mut stream = match client.stream(&model, &system_prompt, messages, &tools, &options).await {
+                Ok(stream) => stream,
+                Err(e) if is_retryable(&e) => {
+                    self.retry_or_fail_over(session, e, &mut provider_retries, &mut next_fallback, &mut provider_name, &mut model, &mut client).await?;
+                    continue;
                 }
-                KernelEvent::MessageEnd { input_tokens, output_tokens, .. } => {
-                    session.total_input_tokens += *input_tokens as u64;
-                    session.total_output_tokens += *output_tokens as u64;
-                    self.persist_event(session, &event);
+                Err(e) => {
+                    self.recover_from_overflow(session, e, &mut overflow_retried)?;
+                    continue;
                 }
-                KernelEvent::ToolCall { id, name, args } => {
-                    self.persist_event(session, &event);
-                    pending_tool_calls.push(PendingToolCall {
-                        id: id.clone(), name: name.clone(), args: args.clone()
+            };
+            let mut last_partial_flush = Instant::now();
+            let mut retry_request = false;
+            let mut redactor = redact_stream.then(postprocess::StreamRedactor::default);
+
+            loop {
+                 let event_result = tokio::select! {
+                     next = stream.next() => match next {
+                         Some(event_result) => event_result,
+                         None => break,
+                     },
+                     Some(done) = eager_running.next(), if !eager_running.is_empty() => {
+                         eager_done.push(done);
+                         continue;
+                     }
+                     _ = interrupt.interrupted() => {
+                         interrupted = true;
+                         break;
+                     }
+                 };
+                 let event = match event_result {
+                     Ok(event) => event,
+                     // An overflow or transient failure reported before any output can still be retried
+                     Err(e) if response_text.is_empty() && pending_tool_calls.is_empty() => {
+                         if is_retryable(&e) {
+                             self.retry_or_fail_over(session, e, &mut provider_retries, &mut next_fallback, &mut provider_name, &mut model, &mut client).await?;
+                         } else {
+                             self.recover_from_overflow(session, e, &mut overflow_retried)?;
+                         }
+                         retry_request = true;
+                         break;
+                     }
+                     Err(e) => return Err(e),
+                 };
+                 if store_raw {
+                     raw_events.push(serde_json::to_value(&event).unwrap_or_default());
+                 }
+                 match &event {
+                    KernelEvent::MessageDelta { content_delta } => {
+                        match redactor.as_mut().map(|r| r.push(content_delta)) {
+                            Some(ready) if ready.is_empty() => {}
+                            Some(ready) => self.persist_event(session, &KernelEvent::MessageDelta { content_delta: ready }),
+                            None => self.persist_event(session, &event),
+                        }
+                        response_text.push_str(content_delta);
+                    }
+                    KernelEvent::ThinkingDelta { thinking } => {
+                        // Kept apart from response_text; it becomes its own content part
+                        self.persist_event(session, &event);
+                        thinking_text.push_str(thinking);
+                    }
+                    KernelEvent::MessageEnd { input_tokens, output_tokens, stop_reason: reason, .. } => {
+                        stop_reason = reason.clone();
+                        self.record_usage(session, &provider_name, &model, *input_tokens, *output_tokens).await;
+                        self.persist_event(session, &event);
+                    }
+                    KernelEvent::ToolCall { id, name, args } => {
+                        // Provider ids vary run to run; synthesize stable ones when asked so
+                        // history, events and tool_executions are comparable across runs.
+                        let id = if self.config.kernel.deterministic_tool_ids {
+                            format!("call_{}_{}", session.turn_index, pending_tool_calls.len())
+                        } else {
+                            id.clone()
+                        };
+                        self.persist_event(session, &KernelEvent::ToolCall {
+                            id: id.clone(), name: name.clone(), args: args.clone()
+                        });
+                        let tc = PendingToolCall { id, name: name.clone(), args: args.clone() };
+                        if eager_mode != EagerDispatch::Off && self.eager_eligible(&tc, pending_tool_calls.len(), eager_mode, &mut eager_previous) {
+                            let verdict = self.evaluate_tool_call(&tc.name, &tc.id, &tc.args).await;
+                            // A harness rewrite can still bring the call under an approval rule
+                            if matches!(verdict, Verdict::Allow | Verdict::Modify(_)) && self.approval_reason(&tc.name, &tc.args, &verdict).is_none() {
+                                debug!(tool = %tc.name, "Starting tool call before the response ends");
+                                eager_ids.insert(tc.id.clone());
+                                eager_running.push(Box::pin(self.execute_tool_call(
+                                    session_id.clone(), tc.clone(), verdict.clone(), tool_ctx.clone(), session.event_tx.clone(),
+                                )));
+                            }
+                            early_verdicts.insert(tc.id.clone(), verdict);
+                        }
+                        pending_tool_calls.push(tc);
+                    }
+                    _ => { self.persist_event(session, &event); }
+                 }
+
+                 if let (Some(interval), Some(store)) = (partial_interval, &self.state) {
+                     if last_partial_flush.elapsed() >= interval {
+                         let mut text = format!("{}{}", continued_text, response_text);
+                         if redact_stream {
+                             text = crate::persistence::redact::redact_text(&text);
+                         }
+                         let content = assistant_message_json(
+                             &format!("{}{}", continued_thinking, thinking_text),
+                             &text,
+                             &pending_tool_calls,
+                         );
+                         if let Err(e) = store.upsert_assistant_message(&session_id, session.turn_index, &content, true, None).await {
+                             warn!(error = %e, "Failed to persist partial assistant message");
+                         }
+                         last_partial_flush = Instant::now();
+                     }
+                 }
+            }
+
+            if retry_request {
+                continue;
+            }
+
+            if let Some(rest) = redactor.as_mut().map(postprocess::StreamRedactor::finish).filter(|r| !r.is_empty()) {
+                self.persist_event(session, &KernelEvent::MessageDelta { content_delta: rest });
+            }
+
+            if !thinking_text.is_empty() {
+                self.persist_event(session, &KernelEvent::Reasoning {
+                    turn_index: session.turn_index,
+                    content: thinking_text.clone(),
+                });
+            }
+
+            let truncated = !interrupted && hit_output_limit(stop_reason.as_deref());
+            // Tool calls in a cut-off response may be incomplete, so only text is continued
+            let continue_response = truncated && pending_tool_calls.is_empty() && continuations < max_continuations;
+
+            if !self.quiet && !continue_response && !response_text.is_empty() && !response_text.ends_with('\n') { println!(); }
+
+            if let (true, Some(store)) = (store_raw, &self.state) {
+                let body = serde_json::json!({ "events": raw_events });
+                if let Err(e) = store.insert_raw_response(&session_id, session.turn_index, &provider_name, &model, &body).await {
+                    warn!(error = %e, "Failed to store raw provider response");
+                }
+            }
+
+            if interrupted {
+                // Keep what was streamed so far; tool calls from a cut-off response are dropped
+                warn!(turn = session.turn_index, "Turn interrupted during inference");
+                pending_tool_calls.clear();
+                eager_running = FuturesUnordered::new();
+                eager_done.clear();
+                eager_ids.clear();
+                self.persist_event(session, &KernelEvent::TurnInterrupted { turn_index: session.turn_index });
+                session.stop_reason = Some(StopReason::Interrupted);
+                break;
+            }
+
+            if truncated {
+                self.persist_event(session, &KernelEvent::ResponseTruncated {
+                    turn_index: session.turn_index,
+                    stop_reason: stop_reason.clone().unwrap_or_default(),
+                    continuations,
+                    continuing: continue_response,
+                });
+                if continue_response {
+                    continuations += 1;
+                    debug!(turn = session.turn_index, continuations, "Response hit the output limit; continuing");
+                    continued_text.push_str(&response_text);
+                    continued_thinking.push_str(&thinking_text);
+                    continue;
+                }
+                warn!(turn = session.turn_index, continuations, "Response truncated at the output token limit");
+            }
+
+            // Turn-level retry: re-run from the same history when the outcome is unusable.
+            // Request-level failures (transport, provider errors) are not handled here.
+            if max_turn_retries > 0 && continued_text.is_empty() {
+                if let Some(reason) = unusable_turn_outcome(&response_text, &pending_tool_calls) {
+                    if attempt < max_turn_retries {
+                        attempt += 1;
+                        warn!(turn = session.turn_index, attempt, reason = %reason, "Retrying turn");
+                        self.persist_event(session, &KernelEvent::TurnRetried {
+                            turn_index: session.turn_index, attempt, reason,
+                        });
+                        continue;
+                    }
+                    error!(turn = session.turn_index, reason = %reason, "Turn retries exhausted");
+                    self.persist_event(session, &KernelEvent::TurnFailed {
+                        turn_index: session.turn_index, reason,
                     });
                 }
-                _ => { self.persist_event(session, &event); }
-             }
+            }
+            break;
+        }
+        // Continued parts form one logical message
+        response_text.insert_str(0, &continued_text);
+        thinking_text.insert_str(0, &continued_thinking);
+        if !self.config.agent.postprocessors.is_empty() && !response_text.is_empty() {
+            response_text = self.postprocess_message(session, response_text).await;
         }
-
-        if !response_text.is_empty() && !response_text.ends_with('\n') { println!(); }
 
         let has_tool_calls = !pending_tool_calls.is_empty();
 
+        let budget_exceeded = self.check_cost_budget(session);
+        let limit_exceeded = self.check_usage_limits(session, usage_before);
+
+        if self.verbosity.timing {
+            eprintln!("[timing] turn {} inference: {}ms", session.turn_index, turn_start.elapsed().as_millis());
+        }
+
         self.persist_event(session, &KernelEvent::TurnEnd {
             turn_index: session.turn_index,
             has_tool_calls,
         });
 
+        // An interrupt before any output leaves nothing worth recording
+        if interrupted && response_text.is_empty() {
+            return Ok(false);
+        }
+
          if let Some(ref store) = self.state {
-            let content: Vec<serde_json::Value> = {
-                let mut parts = Vec::new();
-                if !response_text.is_empty() {
-                    parts.push(serde_json::json!({"type": "text", "text": response_text}));
-                }
-                for tc in &pending_tool_calls {
-                    parts.push(serde_json::json!({
-                        "type": "tool_use", "id": tc.id, "name": tc.name, "input": tc.args,
-                    }));
-                }
-                parts
-            };
-            let _ = store.insert_message(&session_id, session.turn_index, "assistant", &serde_json::Value::Array(content), None).await;
+            // Finalizes the partial row written while streaming, if any
+            let content = assistant_message_json(&thinking_text, &response_text, &pending_tool_calls);
+            let _ = store.upsert_assistant_message(&session_id, session.turn_index, &content, false, stop_reason.as_deref().map(|r| FinishReason::parse(r).as_str())).await;
         }
 
         let mut assistant_content: Vec<InferenceContent> = Vec::new();
+        if !thinking_text.is_empty() {
+            assistant_content.push(InferenceContent::Thinking { content: thinking_text.clone() });
+        }
         if !response_text.is_empty() {
             assistant_content.push(InferenceContent::Text { text: response_text.clone() });
         }
@@ -713,14 +2111,95 @@ impl Kernel {
         if !has_tool_calls {
             return Ok(false);
         }
+        // Keep the response as the partial result but run no more tools once
+        // over budget. Every call still gets a result so the history stays
+        // well-formed for the next prompt; calls started while streaming are
+        // left to finish and keep their output.
+        if budget_exceeded || limit_exceeded {
+            let (label, reason, verdict) = if budget_exceeded {
+                ("BUDGET EXCEEDED", "the session's cost budget is spent", "budget_exceeded")
+            } else {
+                ("LIMIT EXCEEDED", "the session's token limit is reached", "limit_exceeded")
+            };
+            let mut finished: Vec<ToolExecution> = eager_running.collect().await;
+            finished.append(&mut eager_done);
+            let mut tool_results = Vec::new();
+            for tc in &pending_tool_calls {
+                let (content, is_error, duration_ms, verdict) = match finished.iter().position(|done| done.tc.id == tc.id) {
+                    Some(i) => {
+                        let done = finished.swap_remove(i);
+                        (done.content, done.is_error, done.duration_ms, done.verdict)
+                    }
+                    None => (format!("[{}] Tool '{}' was not executed: {}", label, tc.name, reason), true, 0, verdict.to_string()),
+                };
+                if let Some(ref store) = self.state {
+                    let _ = store.insert_tool_execution(&session_id, session.turn_index, &tc.id, &tc.name, &tc.args, Some(&content), is_error, Some(duration_ms), &verdict).await;
+                }
+                tool_results.push(InferenceContent::ToolResult { tool_use_id: tc.id.clone(), content, is_error });
+            }
+            self.push_tool_results(session, tool_results).await;
+            return Ok(false);
+        }
 
         // Execute tools
-        // Phase 1: Evaluate verdicts
         let mut validated_calls = Vec::new();
         let mut tool_results: Vec<InferenceContent> = Vec::new();
 
-        for tc in &pending_tool_calls {
-            let verdict = self.evaluate_tool_call(&tc.name, &tc.id, &tc.args).await;
+        // Phase 0: Enforce the per-turn tool call cap
+        let executable_count = match self.config.kernel.max_tool_calls_per_turn {
+            Some(limit) => pending_tool_calls.len().min(limit as usize),
+            None => pending_tool_calls.len(),
+        };
+        if executable_count < pending_tool_calls.len() {
+            warn!(requested = pending_tool_calls.len(), executed = executable_count, "Tool calls truncated to per-turn limit");
+            self.persist_event(session, &KernelEvent::ToolCallsTruncated {
+                requested: pending_tool_calls.len() as u32,
+                executed: executable_count as u32,
+            });
+            for tc in &pending_tool_calls[executable_count..] {
+                let msg = format!(
+                    "[TOOL CALL LIMIT] Tool '{}' was not executed: only {} tool calls are allowed per turn. Be more selective and request the most important calls first.",
+                    tc.name, executable_count
+                );
+                if let Some(ref store) = self.state {
+                    let _ = store.insert_tool_execution(&session_id, session.turn_index, &tc.id, &tc.name, &tc.args, Some(&msg), true, Some(0), "truncated").await;
+                }
+                tool_results.push(InferenceContent::ToolResult { tool_use_id: tc.id.clone(), content: msg, is_error: true });
+            }
+        }
+
+        // Phase 0b: Calls that exactly repeat the one before them are answered
+        // from its result after execution instead of running again
+        let mut previous = session.last_tool_call.clone();
+        let mut repeats: Vec<(&PendingToolCall, String)> = Vec::new();
+        let mut to_evaluate = Vec::new();
+        for tc in &pending_tool_calls[..executable_count] {
+            if eager_ids.contains(&tc.id) {
+                previous = Some(LastToolCall { id: tc.id.clone(), name: tc.name.clone(), args: tc.args.clone(), content: String::new() });
+                continue;
+            }
+            if !self.config.kernel.dedup_tool_calls {
+                to_evaluate.push(tc);
+                continue;
+            }
+            match previous.as_ref().filter(|p| p.is_repeated_by(&tc.name, &tc.args)) {
+                // `previous` stays the call that ran, so a chain of repeats all point at it
+                Some(original) => repeats.push((tc, original.id.clone())),
+                None => {
+                    previous = Some(LastToolCall { id: tc.id.clone(), name: tc.name.clone(), args: tc.args.clone(), content: String::new() });
+                    to_evaluate.push(tc);
+                }
+            }
+        }
+
+        // Phase 1: Evaluate verdicts, asking the user about escalated calls
+        // and those matching `tools.requires_approval`
+        let mut approvals: HashMap<String, &str> = HashMap::new();
+        for tc in to_evaluate {
+            let verdict = match early_verdicts.remove(&tc.id) {
+                Some(verdict) => verdict,
+                None => self.evaluate_tool_call(&tc.name, &tc.id, &tc.args).await,
+            };
             match &verdict {
                 Verdict::Reject(reason) => {
                      warn!(tool = %tc.name, reason = %reason, "Tool REJECTED by harness");
@@ -733,170 +2212,377 @@ impl Kernel {
                      }
                      tool_results.push(InferenceContent::ToolResult { tool_use_id: tc.id.clone(), content: msg, is_error: true });
                 }
-                Verdict::Escalate(reason) => {
-                     warn!(tool = %tc.name, reason = %reason, "ESCALATION: Tool requires approval");
-                     eprint!("[bedrock] Allow? (y/n): ");
-                     io::stderr().flush().ok();
-                     let mut input = String::new();
-                     let approved = io::stdin().lock().read_line(&mut input).is_ok() && input.trim().eq_ignore_ascii_case("y");
-                     if !approved {
-                          warn!(tool = %tc.name, "Tool DENIED by user");
-                          let msg = format!("[ESCALATION DENIED] Tool '{}' denied: {}", tc.name, reason);
-                           self.persist_event(session, &KernelEvent::ToolExecStart { id: tc.id.clone(), name: tc.name.clone() });
-                           self.persist_event(session, &KernelEvent::ToolExecEnd { id: tc.id.clone(), success: false });
-                           if let Some(ref store) = self.state {
-                                let _ = store.insert_tool_execution(&session_id, session.turn_index, &tc.id, &tc.name, &tc.args, Some(&msg), true, Some(0), "escalate_denied").await;
-                           }
-                           tool_results.push(InferenceContent::ToolResult { tool_use_id: tc.id.clone(), content: msg, is_error: true });
-                     } else {
-                         info!(tool = %tc.name, "Tool APPROVED by user");
-                         validated_calls.push((tc, verdict));
-                     }
+                _ => {
+                    let Some(reason) = self.approval_reason(&tc.name, &tc.args, &verdict) else {
+                        validated_calls.push((tc, verdict));
+                        continue;
+                    };
+                    warn!(tool = %tc.name, reason = %reason, "ESCALATION: Tool requires approval");
+                    let (decision, recorded) = if session.approved_tools.contains(&tc.name) {
+                        (ApprovalDecision::Approve, "approved_session")
+                    } else {
+                        let decision = self.ask_approval(&tc.name, &tc.args, &verdict, &reason);
+                        (decision, decision.verdict())
+                    };
+                    if decision == ApprovalDecision::Deny {
+                        warn!(tool = %tc.name, "Tool DENIED by user");
+                        let msg = format!("[ESCALATION DENIED] Tool '{}' denied: {}", tc.name, reason);
+                        self.persist_event(session, &KernelEvent::ToolExecStart { id: tc.id.clone(), name: tc.name.clone() });
+                        self.persist_event(session, &KernelEvent::ToolExecEnd { id: tc.id.clone(), success: false });
+                        if let Some(ref store) = self.state {
+                            let _ = store.insert_tool_execution(&session_id, session.turn_index, &tc.id, &tc.name, &tc.args, Some(&msg), true, Some(0), recorded).await;
+                        }
+                        tool_results.push(InferenceContent::ToolResult { tool_use_id: tc.id.clone(), content: msg, is_error: true });
+                        continue;
+                    }
+                    info!(tool = %tc.name, verdict = recorded, "Tool APPROVED by user");
+                    if decision == ApprovalDecision::Always {
+                        session.approved_tools.insert(tc.name.clone());
+                    }
+                    approvals.insert(tc.id.clone(), recorded);
+                    // An approved escalation runs like an allowed call; a rewrite still applies
+                    let verdict = if verdict.is_escalated() { Verdict::Allow } else { verdict };
+                    validated_calls.push((tc, verdict));
+                }
+            }
+        }
+
+        self.report_unlocked_tools(session);
+
+        // Phase 2: Parallel Execution (at most `max_parallel_tool_calls` at a
+        // time), joined with any calls started while streaming
+        let running: Vec<PendingToolCall> = validated_calls.iter().map(|(tc, _)| (*tc).clone())
+            .chain(pending_tool_calls.iter().filter(|tc| eager_ids.contains(&tc.id) && !eager_done.iter().any(|done| done.tc.id == tc.id)).cloned())
+            .collect();
+        let event_tx = session.event_tx.clone();
+        let turn_index = session.turn_index;
+        let futures = validated_calls.into_iter().map(|(tc, verdict)| {
+            self.execute_tool_call(session_id.clone(), tc.clone(), verdict, tool_ctx.clone(), event_tx.clone())
+        });
+
+        let mut execution_results = tokio::select! {
+            (results, eager) = async { futures::join!(join_all(futures), eager_running.collect::<Vec<_>>()) } => {
+                results.into_iter().chain(eager).chain(std::mem::take(&mut eager_done)).collect::<Vec<_>>()
+            }
+            _ = interrupt.interrupted() => {
+                // In-flight tools are dropped; answer every call so the history stays well-formed
+                warn!(turn = turn_index, "Turn interrupted during tool execution");
+                for tc in &running {
+                    let msg = format!("[INTERRUPTED] Tool '{}' was cancelled by the user", tc.name);
+                    if let Some(ref store) = self.state {
+                        let _ = store.insert_tool_execution(&session_id, turn_index, &tc.id, &tc.name, &tc.args, Some(&msg), true, None, "interrupted").await;
+                    }
+                    tool_results.push(InferenceContent::ToolResult { tool_use_id: tc.id.clone(), content: msg, is_error: true });
+                }
+                self.persist_event(session, &KernelEvent::TurnInterrupted { turn_index });
+                session.stop_reason = Some(StopReason::Interrupted);
+                // Calls that finished while streaming still count
+                std::mem::take(&mut eager_done)
+            }
+        };
+        execution_results.sort_by_key(|done| pending_tool_calls.iter().position(|tc| tc.id == done.tc.id));
+        for done in &mut execution_results {
+            if let Some(recorded) = approvals.get(&done.tc.id) {
+                done.verdict = recorded.to_string();
+            }
+        }
+
+        // Aggregate provenance for the turn
+        let cited: Vec<CitedSource> = execution_results.iter()
+            .flat_map(|done| done.sources.iter().map(|source| CitedSource {
+                tool_call_id: done.tc.id.clone(),
+                tool_name: done.tc.name.clone(),
+                source: source.clone(),
+            }))
+            .collect();
+        if !cited.is_empty() {
+            if let Some(ref store) = self.state {
+                for c in &cited {
+                    let source = serde_json::to_value(&c.source).unwrap_or_default();
+                    if let Err(e) = store.insert_tool_source(&session_id, turn_index, &c.tool_call_id, &c.tool_name, c.source.kind(), &source).await {
+                        warn!(error = %e, "Failed to store tool source");
+                    }
+                }
+            }
+            self.persist_event(session, &KernelEvent::SourcesCollected { turn_index, sources: cited });
+        }
+
+        // Phase 3: Side Effects & Result Collection
+        for ToolExecution { tc, mut content, mut is_error, metadata, duration_ms, verdict, .. } in execution_results {
+            if !is_error {
+                self.apply_tool_action(session, &metadata, &mut content, &mut is_error).await;
+            }
+            // Record executions in call order (not completion order) so
+            // snapshots are stable, with the output the action substituted
+            if let Some(ref store) = self.state {
+                let _ = store.insert_tool_execution(&session_id, turn_index, &tc.id, &tc.name, &tc.args, Some(&content), is_error, Some(duration_ms), &verdict).await;
+            }
+            let (content, is_error) = self.evaluate_tool_result(&tc, content, is_error).await;
+            if !is_error && self.config.memory.capture_tool_results {
+                self.capture_tool_result(session, &tc, &content).await;
+            }
+            tool_results.push(InferenceContent::ToolResult { tool_use_id: tc.id.clone(), content, is_error });
+        }
+
+        for (tc, repeat_of) in repeats {
+            self.answer_repeated_call(session, tc, &repeat_of, &mut tool_results).await;
+        }
+        if let Some(mut last) = previous {
+            if session.last_tool_call.as_ref().is_none_or(|l| l.id != last.id) {
+                last.content = tool_result_content(&tool_results, &last.id).unwrap_or_default().to_string();
+            }
+            session.last_tool_call = Some(last);
+        }
+
+        self.push_tool_results(session, tool_results.clone()).await;
+
+         if session.stop_reason == Some(StopReason::Interrupted) {
+             return Ok(false);
+         }
+         Ok(!self.track_tool_errors(session, &tool_results))
+    }
+
+    /// Add a turn's tool results to the history as one user message and store it.
+    async fn push_tool_results(&self, session: &mut SessionState, tool_results: Vec<InferenceContent>) {
+        if let Some(ref store) = self.state {
+            let result_content: Vec<serde_json::Value> = tool_results.iter().map(|r| match r {
+                InferenceContent::ToolResult { tool_use_id, content, is_error } => {
+                    serde_json::json!({ "type": "tool_result", "tool_use_id": tool_use_id, "content": content, "is_error": is_error })
+                }
+                _ => serde_json::json!({})
+            }).collect();
+            let _ = store.insert_message(&session.id, session.turn_index, "tool_result", &serde_json::Value::Array(result_content), None, None).await;
+        }
+        session.history.push(InferenceMessage {
+            role: InferenceRole::User,
+            content: tool_results,
+            tool_call_id: None,
+        });
+    }
+
+    /// Carry out what a successful tool's `action` metadata asks of the
+    /// kernel: queue submitted tasks, save the plan, query events, fetch an
+    /// artifact, delegate, or connect an MCP server. The output may be
+    /// replaced with the action's result.
+    async fn apply_tool_action(&mut self, session: &mut SessionState, metadata: &serde_json::Value, content: &mut String, is_error: &mut bool) {
+        let Some(action) = metadata.get("action").and_then(|v| v.as_str()) else {
+            return;
+        };
+        if action == "submit_task" {
+             let verdict_result = {
+                let harness = self.harness.lock().await;
+                if let Some(engine) = &*harness {
+                    Some(engine.evaluate("on_task_submit", metadata.clone()))
+                } else { None }
+            };
+            
+            if let Some(result) = verdict_result {
+                match result {
+                    Ok(Verdict::Allow) => {
+                        if let Some(subtasks) = metadata.get("subtasks").and_then(|v| v.as_array()) {
+                            if let Some(clear) = metadata.get("clear_existing").and_then(|v| v.as_bool()) {
+                                if clear {
+                                    session.queue.lock().await.clear();
+                                    session.injected_tasks.clear();
+                                }
+                            }
+                            let mut q = session.queue.lock().await;
+                            for task in subtasks {
+                                if let Some(t) = task.as_str() {
+                                    session.injected_tasks.mark(t);
+                                    q.push_back(t.to_string());
+                                }
+                            }
+                            debug!("tasks queued from submit_task");
+                        }
+                    },
+                    Ok(Verdict::Modify(new_tasks_val)) => {
+                         if let Some(new_tasks) = new_tasks_val.as_array() {
+                              let mut q = session.queue.lock().await;
+                              if let Some(clear) = metadata.get("clear_existing").and_then(|v| v.as_bool()) {
+                                  if clear {
+                                      q.clear();
+                                      session.injected_tasks.clear();
+                                  }
+                              }
+                              for task in new_tasks {
+                                  if let Some(t) = task.as_str() {
+                                      session.injected_tasks.mark(t);
+                                      q.push_back(t.to_string());
+                                  }
+                              }
+                               debug!("tasks queued (MODIFIED by harness)");
+                          }
+                    },
+                    Ok(Verdict::Reject(reason)) => {
+                        *content = format!("Plan REJECTED by Harness: {}", reason);
+                    },
+                    Ok(Verdict::Escalate(reason)) => {
+                         *content = format!("Plan paused for approval: {}", reason);
+                    },
+                    Err(e) => { error!(error = %e, "Failed to evaluate on_task_submit"); }
+                }
+            } else {
+                if let Some(subtasks) = metadata.get("subtasks").and_then(|v| v.as_array()) {
+                     let mut q = session.queue.lock().await;
+                     if let Some(clear) = metadata.get("clear_existing").and_then(|v| v.as_bool()) {
+                        if clear {
+                            q.clear();
+                            session.injected_tasks.clear();
+                        }
+                     }
+                     for task in subtasks {
+                        if let Some(t) = task.as_str() {
+                            session.injected_tasks.mark(t);
+                            q.push_back(t.to_string());
+                        }
+                     }
+                }
+            }
+        } else if action == "update_plan" {
+            match serde_json::from_value::<Vec<PlanStep>>(metadata.get("steps").cloned().unwrap_or_default()) {
+                Ok(steps) => {
+                    if let Some(ref store) = self.state {
+                        if let Err(e) = store.set_plan(&session.id, &steps).await {
+                            *content = format!("Failed to save plan: {}", e);
+                            *is_error = true;
+                        }
+                    }
+                    if !*is_error {
+                        self.persist_event(session, &KernelEvent::PlanUpdated { steps });
+                    }
+                }
+                Err(e) => error!(error = %e, "Malformed update_plan metadata"),
+            }
+        } else if action == "query_events" {
+            if let Some(ref store) = self.state {
+                match store.query_events(&session.id, &event_filter(metadata)).await {
+                    Ok(events) => *content = summarize_events(&events),
+                    Err(e) => {
+                        *content = format!("Failed to query events: {}", e);
+                        *is_error = true;
+                    }
+                }
+            } else {
+                *is_error = true;
+            }
+        } else if action == "fetch_artifact" {
+            match self.fetch_artifact(&session.id, metadata).await {
+                Ok(text) => *content = text,
+                Err(e) => {
+                    *content = format!("Failed to fetch artifact: {}", e);
+                    *is_error = true;
                 }
-                Verdict::Allow | Verdict::Modify(_) => {
-                    validated_calls.push((tc, verdict));
+            }
+        } else if action == "delegate" {
+            match self.delegate(session, metadata).await {
+                Ok(answer) => *content = answer,
+                Err(e) => {
+                    *content = format!("Delegation failed: {}", e);
+                    *is_error = true;
                 }
             }
+        } else if action == "spawn_mcp" {
+              if let Some(cmd) = metadata.get("command").and_then(|v| v.as_str()) {
+                   let args: Vec<String> = metadata.get("args")
+                      .and_then(|v| v.as_array())
+                      .map(|arr| arr.iter().map(|v| v.as_str().unwrap_or_default().to_string()).collect())
+                      .unwrap_or_default();
+                   
+                   let server_name = metadata.get("name").and_then(|v| v.as_str());
+                   match self.spawn_mcp_server(cmd, &args, server_name).await {
+                       Ok(count) => {
+                           *content = format!("Successfully connected to MCP server. Loaded {} new tools.", count);
+                       },
+                       Err(e) => {
+                           *content = format!("Failed to connect to MCP server: {}", e);
+                           *is_error = true;
+                       }
+                   }
+              }
         }
+    }
 
-        // Phase 2: Parallel Execution
-        let kernel = &*self;
-        let event_tx = session.event_tx.clone();
-        let turn_index = session.turn_index;
-        let futures = validated_calls.into_iter().map(|(tc, verdict)| {
-            let session_id = session_id.clone();
-            let tool_ctx = tool_ctx.clone();
-            let event_tx = event_tx.clone();
-            async move {
-                let verdict_str = verdict.to_string();
-                let final_args = match verdict {
-                    Verdict::Modify(new_args) => {
-                         info!(tool = %tc.name, "Tool arguments MODIFIED by harness");
-                         new_args
-                    },
-                    _ => tc.args.clone()
-                };
-
-                let _ = event_tx.send((session_id.clone(), KernelEvent::ToolExecStart { id: tc.id.clone(), name: tc.name.clone() }));
-                let start = Instant::now();
-                let (content, is_error, metadata) = match kernel.tool_registry.execute(&tc.name, final_args, &tool_ctx).await {
-                    Ok(o) => (o.content, false, o.metadata),
-                    Err(e) => (format!("Tool error: {}", e), true, serde_json::Value::Null),
-                };
-                let duration_ms = start.elapsed().as_millis() as u64;
-                
-                let _ = event_tx.send((session_id.clone(), KernelEvent::ToolExecEnd { id: tc.id.clone(), success: !is_error }));
-
-                if let Some(ref store) = kernel.state {
-                     let _ = store.insert_tool_execution(&session_id, turn_index, &tc.id, &tc.name, &tc.args, Some(&content), is_error, Some(duration_ms), &verdict_str).await;
-                }
-                (tc, content, is_error, metadata)
-            }
+    /// Answer a call that repeats `repeat_of` with that call's result. The
+    /// answer is an error so loops of repeats trip
+    /// `kernel.max_consecutive_tool_errors`.
+    async fn answer_repeated_call(&self, session: &SessionState, tc: &PendingToolCall, repeat_of: &str, tool_results: &mut Vec<InferenceContent>) {
+        let prior = session.last_tool_call.as_ref()
+            .filter(|last| last.id == repeat_of)
+            .map(|last| last.content.as_str())
+            .or_else(|| tool_result_content(tool_results, repeat_of))
+            .unwrap_or_default();
+        warn!(tool = %tc.name, repeat_of = %repeat_of, "Identical tool call; returning the previous result");
+        let msg = format!(
+            "[IDENTICAL CALL] '{}' was called with exactly the same arguments as the previous call; returning its result instead of running it again. Consider a different approach.\n\n{}",
+            tc.name, prior
+        );
+        if let Some(ref store) = self.state {
+            let _ = store.insert_tool_execution(&session.id, session.turn_index, &tc.id, &tc.name, &tc.args, Some(&msg), true, Some(0), "deduplicated").await;
+        }
+        self.persist_event(session, &KernelEvent::ToolCallDeduplicated {
+            tool_call_id: tc.id.clone(),
+            tool: tc.name.clone(),
+            repeat_of: repeat_of.to_string(),
         });
+        tool_results.push(InferenceContent::ToolResult { tool_use_id: tc.id.clone(), content: msg, is_error: true });
+    }
 
-        let execution_results = join_all(futures).await;
-
-        // Phase 3: Side Effects & Result Collection
-        for (tc, mut content, mut is_error, metadata) in execution_results {
-            if !is_error {
-                if let Some(action) = metadata.get("action").and_then(|v| v.as_str()) {
-                    if action == "submit_task" {
-                         let verdict_result = {
-                            let harness = self.harness.lock().await;
-                            if let Some(engine) = &*harness {
-                                Some(engine.evaluate("on_task_submit", metadata.clone()))
-                            } else { None }
-                        };
-                        
-                        if let Some(result) = verdict_result {
-                            match result {
-                                Ok(Verdict::Allow) => {
-                                    if let Some(subtasks) = metadata.get("subtasks").and_then(|v| v.as_array()) {
-                                        if let Some(clear) = metadata.get("clear_existing").and_then(|v| v.as_bool()) {
-                                            if clear { session.queue.lock().await.clear(); }
-                                        }
-                                        let mut q = session.queue.lock().await;
-                                        for task in subtasks {
-                                            if let Some(t) = task.as_str() { q.push_back(t.to_string()); }
-                                        }
-                                        debug!("tasks queued from submit_task");
-                                    }
-                                },
-                                Ok(Verdict::Modify(new_tasks_val)) => {
-                                     if let Some(new_tasks) = new_tasks_val.as_array() {
-                                          let mut q = session.queue.lock().await;
-                                          if let Some(clear) = metadata.get("clear_existing").and_then(|v| v.as_bool()) {
-                                              if clear { q.clear(); }
-                                          }
-                                          for task in new_tasks {
-                                              if let Some(t) = task.as_str() { q.push_back(t.to_string()); }
-                                          }
-                                           debug!("tasks queued (MODIFIED by harness)");
-                                      }
-                                },
-                                Ok(Verdict::Reject(reason)) => {
-                                    content = format!("Plan REJECTED by Harness: {}", reason);
-                                },
-                                Ok(Verdict::Escalate(reason)) => {
-                                     content = format!("Plan paused for approval: {}", reason);
-                                },
-                                Err(e) => { error!(error = %e, "Failed to evaluate on_task_submit"); }
-                            }
-                        } else {
-                            if let Some(subtasks) = metadata.get("subtasks").and_then(|v| v.as_array()) {
-                                 let mut q = session.queue.lock().await;
-                                 if let Some(clear) = metadata.get("clear_existing").and_then(|v| v.as_bool()) {
-                                    if clear { q.clear(); }
-                                 }
-                                 for task in subtasks {
-                                    if let Some(t) = task.as_str() { q.push_back(t.to_string()); }
-                                 }
-                            }
-                        }
-                    } else if action == "spawn_mcp" {
-                          if let Some(cmd) = metadata.get("command").and_then(|v| v.as_str()) {
-                               let args: Vec<String> = metadata.get("args")
-                                  .and_then(|v| v.as_array())
-                                  .map(|arr| arr.iter().map(|v| v.as_str().unwrap_or_default().to_string()).collect())
-                                  .unwrap_or_default();
-                               
-                               match self.spawn_mcp_server(cmd, &args).await {
-                                   Ok(count) => {
-                                       content = format!("Successfully connected to MCP server. Loaded {} new tools.", count);
-                                   },
-                                   Err(e) => {
-                                       content = format!("Failed to connect to MCP server: {}", e);
-                                       is_error = true;
-                                   }
-                               }
-                          }
-                    }
+    /// Update the session's run of consecutive failed tool calls from this
+    /// turn's results (in call order). Returns true, after emitting
+    /// `ToolErrorStorm`, if it crossed `kernel.max_consecutive_tool_errors`.
+    fn track_tool_errors(&self, session: &mut SessionState, results: &[InferenceContent]) -> bool {
+        let mut last_error = None;
+        for result in results {
+            if let InferenceContent::ToolResult { content, is_error, .. } = result {
+                if *is_error {
+                    session.consecutive_tool_errors += 1;
+                    last_error = Some(content);
+                } else {
+                    session.consecutive_tool_errors = 0;
                 }
             }
-            tool_results.push(InferenceContent::ToolResult { tool_use_id: tc.id.clone(), content, is_error });
         }
-
-        session.history.push(InferenceMessage {
-            role: InferenceRole::User,
-            content: tool_results.clone(),
-            tool_call_id: None,
+        let Some(limit) = self.config.kernel.max_consecutive_tool_errors else {
+            return false;
+        };
+        if session.consecutive_tool_errors <= limit {
+            return false;
+        }
+        error!(consecutive_errors = session.consecutive_tool_errors, limit, "Too many consecutive tool errors; halting run");
+        self.persist_event(session, &KernelEvent::ToolErrorStorm {
+            consecutive_errors: session.consecutive_tool_errors,
+            limit,
+            last_error: last_error.cloned().unwrap_or_default(),
         });
-
-         if let Some(ref store) = self.state {
-             let result_content: Vec<serde_json::Value> = tool_results.iter().map(|r| match r {
-                 InferenceContent::ToolResult { tool_use_id, content, is_error } => {
-                     serde_json::json!({ "type": "tool_result", "tool_use_id": tool_use_id, "content": content, "is_error": is_error })
-                 }
-                 _ => serde_json::json!({})
-             }).collect();
-             let _ = store.insert_message(&session_id, session.turn_index, "tool_result", &serde_json::Value::Array(result_content), None).await;
-         }
-
-         Ok(true)
+        session.stop_reason = Some(StopReason::ToolErrorStorm);
+        true
     }
 
     /// Create the appropriate provider client from config.
-    fn create_client(&self, _name: &str, config: &crate::kernel::config::ProviderConfig) -> Result<ProviderClient> {
+    async fn create_client(&self, name: &str, config: &crate::kernel::config::ProviderConfig) -> Result<ProviderClient> {
+        for field in config.extra_body_collisions() {
+            warn!(provider = %name, field = %field, "providers.{}.extra_body overrides a request field bedrock sets", name);
+        }
+        let extra_body = config.extra_body.as_ref().and_then(|body| body.as_object().cloned());
+        Ok(self.create_base_client(name, config).await?.with_tool_result_mode(config.tool_results)
+            .with_extra_body(extra_body)
+            .with_strip_thinking(config.strip_thinking))
+    }
+
+    async fn create_base_client(&self, name: &str, config: &crate::kernel::config::ProviderConfig) -> Result<ProviderClient> {
+        // Pluggable auth or a credential store: the client is rebuilt from the scheme's credential as it refreshes
+        let scheme = crate::inference::credentials::scheme_for(name, config, &self.config.credentials);
+        if let (Some(scheme), "anthropic" | "openai") = (scheme, config.kind.as_str()) {
+            let provider_config = config.clone();
+            let (kind, auth) = if config.kind == "anthropic" {
+                (ProviderKind::Anthropic, ProviderAuth::new(scheme, move |key| provider::create_anthropic_client_with_key(&provider_config, key)))
+            } else {
+                (ProviderKind::OpenAI, ProviderAuth::new(scheme, move |key| provider::create_openai_client_with_key(&provider_config, key)))
+            };
+            return ProviderClient::with_auth(kind, auth).await;
+        }
+
         match config.kind.as_str() {
             "anthropic" => {
                 let client = provider::create_anthropic_client(config)?;
@@ -913,6 +2599,10 @@ impl Kernel {
                     client,
                 ))
             }
+            "ollama" => {
+                let client = crate::inference::ollama::OllamaProvider::new(config.base_url.as_deref());
+                Ok(ProviderClient::new(ProviderKind::Ollama, Arc::new(client)))
+            }
             "mock" => {
                 let client = provider::create_mock_client(config);
                  Ok(ProviderClient::new(
@@ -924,19 +2614,250 @@ impl Kernel {
         }
     }
 
+    /// Whether the `index`th call of a response may start before the response
+    /// ends. Tracks `previous` like the end-of-response dedup pass does.
+    /// Calls that need the user's approval wait for the response to end.
+    fn eager_eligible(&self, tc: &PendingToolCall, index: usize, mode: EagerDispatch, previous: &mut Option<LastToolCall>) -> bool {
+        if self.config.kernel.max_tool_calls_per_turn.is_some_and(|limit| index >= limit as usize) {
+            return false;
+        }
+        if self.config.kernel.dedup_tool_calls {
+            if previous.as_ref().is_some_and(|p| p.is_repeated_by(&tc.name, &tc.args)) {
+                return false;
+            }
+            *previous = Some(LastToolCall { id: tc.id.clone(), name: tc.name.clone(), args: tc.args.clone(), content: String::new() });
+        }
+        let tool = self.tool_registry.canonical_name(&tc.name);
+        if !tc.args.is_object() || approval::matching_rule(&self.config.tools.requires_approval, tool, &tc.args).is_some() {
+            return false;
+        }
+        match mode {
+            EagerDispatch::Off => false,
+            EagerDispatch::ReadOnly => self.tool_registry.get(&tc.name).is_some_and(|tool| tool.effect() == ToolEffect::ReadOnly),
+            EagerDispatch::All => true,
+        }
+    }
+
+    /// Run one allowed tool call: cache lookup, resource permit, execution and
+    /// the exec start/end events. Recording the result is left to the caller.
+    async fn execute_tool_call(
+        &self,
+        session_id: String,
+        tc: PendingToolCall,
+        verdict: Verdict,
+        mut tool_ctx: ToolContext,
+        event_tx: mpsc::UnboundedSender<(String, KernelEvent)>,
+    ) -> ToolExecution {
+        tool_ctx.original_args = Some(tc.args.clone());
+        let verdict_str = verdict.to_string();
+        let final_args = match verdict {
+            Verdict::Modify(new_args) => {
+                 info!(tool = %tc.name, "Tool arguments MODIFIED by harness");
+                 new_args
+            },
+            _ => tc.args.clone()
+        };
+
+        // Held until the call finishes; the semaphore is never closed
+        let _slot = match &self.tool_slots {
+            Some(slots) => slots.acquire().await.ok(),
+            None => None,
+        };
+        self.publish_event(&event_tx, &session_id, KernelEvent::ToolExecStart { id: tc.id.clone(), name: tc.name.clone() });
+        let start = Instant::now();
+
+        // Cross-session cache lookup for tools that opt in
+        let cache_key = match (&self.state, self.tool_registry.get(&tc.name)) {
+            (Some(_), Some(tool)) if tool.cacheable_globally() => Some(crate::tools::args_hash(&final_args)),
+            _ => None,
+        };
+        let cached = match (&self.state, &cache_key) {
+            (Some(store), Some(hash)) => store.tool_cache_get(&tc.name, hash).await.unwrap_or_else(|e| {
+                warn!(tool = %tc.name, error = %e, "Tool cache lookup failed");
+                None
+            }),
+            _ => None,
+        };
+
+        let (content, is_error, metadata, sources, verdict_str) = if let Some(output) = cached {
+            debug!(tool = %tc.name, "Global tool cache hit");
+            (output, false, serde_json::json!({ "cache": "global" }), Vec::new(), "global_cache_hit".to_string())
+        } else {
+            let resource_key = self.tool_registry.get(&tc.name).and_then(|tool| tool.resource_key());
+            let permit = self.resources.acquire(resource_key).await;
+            // Dropping an execution that outlives its timeout cancels it
+            let timeout = self.config.tools.timeout_for(self.tool_registry.canonical_name(&tc.name));
+            tool_ctx.deadline = timeout.map(|limit| Instant::now() + limit);
+            let execution = self.tool_registry.execute(&tc.name, final_args, &tool_ctx);
+            let result = match timeout {
+                Some(limit) => tokio::time::timeout(limit, execution).await.ok(),
+                None => Some(execution.await),
+            };
+            // A tool that stopped itself at the deadline timed out too
+            let result = result.filter(|r| r.is_ok() || tool_ctx.deadline.is_none_or(|deadline| Instant::now() < deadline));
+            // Artifact handles only resolve in the session that stored them
+            let cacheable = matches!(&result, Some(Ok(o)) if o.artifacts.is_empty());
+            let (content, is_error, metadata, sources, verdict_str) = match result {
+                Some(Ok(o)) => {
+                    let mut content = o.content;
+                    for artifact in o.artifacts {
+                        content.push_str("\n\n");
+                        content.push_str(&self.store_artifact(&session_id, &tc.id, artifact, &event_tx).await);
+                    }
+                    (content, false, o.metadata, o.sources, verdict_str)
+                }
+                Some(Err(e)) => {
+                    if matches!(e, ToolError::PermissionDenied(_)) && self.tool_registry.is_locked(&tc.name) {
+                        self.publish_event(&event_tx, &session_id, KernelEvent::ToolLocked { tool_call_id: tc.id.clone(), tool: tc.name.clone() });
+                    }
+                    (format!("Tool error: {}", e), true, serde_json::Value::Null, Vec::new(), verdict_str)
+                }
+                None => {
+                    let limit_ms = timeout.map_or(0, |t| t.as_millis() as u64);
+                    warn!(tool = %tc.name, timeout_ms = limit_ms, "Tool call timed out");
+                    let content = format!(
+                        "[TOOL TIMEOUT] Tool '{}' did not finish within {}ms and was cancelled. Retry with a smaller or faster request, or use another approach.",
+                        tc.name, limit_ms
+                    );
+                    (content, true, serde_json::json!({ "timeout_ms": limit_ms }), Vec::new(), "timeout".to_string())
+                }
+            };
+            drop(permit);
+            if let (Some(store), Some(hash), true) = (&self.state, &cache_key, cacheable) {
+                if let Err(e) = store.tool_cache_put(&tc.name, hash, &content, self.config.kernel.tool_cache_ttl_secs).await {
+                    warn!(tool = %tc.name, error = %e, "Failed to store tool result in cache");
+                }
+            }
+            (content, is_error, metadata, sources, verdict_str)
+        };
+        let duration_ms = start.elapsed().as_millis() as u64;
+        self.persist_event_internal(&event_tx, &session_id, &KernelEvent::ToolResult {
+            id: tc.id.clone(),
+            output: content.clone(),
+            is_error,
+        });
+        if self.verbosity.timing {
+            eprintln!("[timing] tool {}: {}ms", tc.name, duration_ms);
+        }
+
+        self.publish_event(&event_tx, &session_id, KernelEvent::ToolExecEnd { id: tc.id.clone(), success: !is_error });
+        ToolExecution { tc, content, is_error, metadata, sources, duration_ms, verdict: verdict_str }
+    }
+
+    /// What tools see of the kernel when they run for `session_id`.
+    fn tool_context(&self, session_id: &str) -> ToolContext {
+        ToolContext {
+            workspace_root: std::path::PathBuf::from(&self.config.kernel.workspace_root),
+            session_id: session_id.to_string(),
+            network: Arc::new(self.config.tools.network.clone()),
+            allowed_paths: Arc::new(self.config.tools.allowed_dirs()),
+            original_args: None,
+            deadline: None,
+        }
+    }
+
+    /// Run a single tool call outside of a turn, e.g. for a client of
+    /// `bedrock mcp-serve`. It passes the harness `on_tool_call` and
+    /// `on_tool_result` hooks like a model's call, its `action` metadata
+    /// (queued tasks, plan updates, delegation, ...) is carried out on
+    /// `session`, and it is recorded in `tool_executions` under `session`;
+    /// escalations are denied as there is no one to ask. Returns the output
+    /// and whether it is an error.
+    pub async fn call_tool(&mut self, session: &mut SessionState, name: &str, args: serde_json::Value) -> (String, bool) {
+        let tc = PendingToolCall {
+            id: format!("call_{}", &uuid::Uuid::new_v4().simple().to_string()[..12]),
+            name: name.to_string(),
+            args,
+        };
+        self.persist_event(session, &KernelEvent::ToolCall { id: tc.id.clone(), name: tc.name.clone(), args: tc.args.clone() });
+        let verdict = self.evaluate_tool_call(&tc.name, &tc.id, &tc.args).await;
+        let decision = match self.approval_reason(&tc.name, &tc.args, &verdict) {
+            Some(reason) if !verdict.is_rejected() => Some((self.ask_approval(&tc.name, &tc.args, &verdict, &reason), reason)),
+            _ => None,
+        };
+        let (content, is_error, duration_ms, verdict, executed) = match (verdict, decision) {
+            (Verdict::Reject(reason), _) => {
+                let verdict = Verdict::Reject(reason.clone()).to_string();
+                (format!("[HARNESS REJECTED] Tool '{}' blocked: {}", tc.name, reason), true, 0, verdict, false)
+            }
+            (_, Some((ApprovalDecision::Deny, reason))) => {
+                (format!("[ESCALATION DENIED] Tool '{}' requires approval: {}", tc.name, reason), true, 0, "escalate_denied".to_string(), false)
+            }
+            (verdict, decision) => {
+                let verdict = if verdict.is_escalated() { Verdict::Allow } else { verdict };
+                let tool_ctx = self.tool_context(&session.id);
+                let done = self.execute_tool_call(session.id.clone(), tc.clone(), verdict, tool_ctx, session.event_tx.clone()).await;
+                let recorded = decision.map_or(done.verdict, |(decision, _)| decision.verdict().to_string());
+                let (mut content, mut is_error) = (done.content, done.is_error);
+                if !is_error {
+                    self.apply_tool_action(session, &done.metadata, &mut content, &mut is_error).await;
+                }
+                (content, is_error, done.duration_ms, recorded, true)
+            }
+        };
+        if let Some(ref store) = self.state {
+            if let Err(e) = store
+                .insert_tool_execution(&session.id, session.turn_index, &tc.id, &tc.name, &tc.args, Some(&content), is_error, Some(duration_ms), &verdict)
+                .await
+            {
+                warn!(error = %e, "Failed to record tool execution");
+            }
+        }
+        if !executed {
+            return (content, is_error);
+        }
+        self.evaluate_tool_result(&tc, content, is_error).await
+    }
+
+    /// Why a call with harness verdict `verdict` needs the user's approval:
+    /// the harness escalated it or it matches `tools.requires_approval`.
+    /// Rules name tools by their own name, so an aliased call is matched
+    /// under the tool it calls.
+    fn approval_reason(&self, name: &str, args: &serde_json::Value, verdict: &Verdict) -> Option<String> {
+        if let Verdict::Escalate(reason) = verdict {
+            return Some(reason.clone());
+        }
+        let name = self.tool_registry.canonical_name(name);
+        let rule = approval::matching_rule(&self.config.tools.requires_approval, name, verdict_args(verdict, args))?;
+        Some(format!("matches requires_approval rule '{}'", rule))
+    }
+
+    /// Ask whether a call may run: through the approval handler if one is
+    /// set, else on stdin when interactive. Non-interactive kernels deny.
+    /// The model's arguments are shown, with the harness's rewrite of them
+    /// if `verdict` is a `MODIFY`.
+    fn ask_approval(&self, name: &str, args: &serde_json::Value, verdict: &Verdict, reason: &str) -> ApprovalDecision {
+        let modified_args = match verdict {
+            Verdict::Modify(rewritten) => Some(rewritten),
+            _ => None,
+        };
+        if let Some(handler) = &self.approval_handler {
+            return handler(&ApprovalRequest { tool: name, args, modified_args, reason });
+        }
+        if !self.interactive {
+            return ApprovalDecision::Deny;
+        }
+        eprintln!("[bedrock] Approval required for '{}': {}", name, reason);
+        eprintln!("[bedrock]   {}", verbosity::preview(&args.to_string(), 500));
+        if let Some(rewritten) = modified_args {
+            eprintln!("[bedrock]   runs as rewritten by the harness: {}", verbosity::preview(&rewritten.to_string(), 500));
+        }
+        eprint!("[bedrock] Allow? (y)es / (n)o / (a)lways for this session: ");
+        io::stderr().flush().ok();
+        let mut input = String::new();
+        match io::stdin().lock().read_line(&mut input) {
+            Ok(_) => ApprovalDecision::from_answer(&input),
+            Err(_) => ApprovalDecision::Deny,
+        }
+    }
+
     /// Evaluate harness `on_tool_call` hook.
     ///
     /// Returns the composed verdict. If no harness is loaded, returns `Allow`.
-
     async fn evaluate_tool_call(&self, name: &str, id: &str, args: &serde_json::Value) -> Verdict {
         let harness = self.harness.lock().await;
         if let Some(ref engine) = *harness {
-            let payload = serde_json::json!({
-                "name": name,
-                "id": id,
-                "args": args,
-            });
-            match engine.evaluate("on_tool_call", payload) {
+            let verdict = match engine.evaluate_tool_call(name, id, args) {
                 Ok(verdict) => {
                     if !verdict.is_allowed() {
                         info!(tool = %name, verdict = %verdict, "Harness verdict");
@@ -948,12 +2869,47 @@ impl Kernel {
                     warn!(error = %e, "Harness on_tool_call error");
                     Verdict::Allow
                 }
+            };
+            // An imperative bedrock.abort_tool() overrides the returned verdict
+            match engine.take_tool_abort() {
+                Some(reason) => {
+                    info!(tool = %name, reason = %reason, "Tool aborted by harness");
+                    Verdict::Reject(reason)
+                }
+                None => verdict,
             }
         } else {
             Verdict::Allow
         }
     }
 
+    /// Evaluate harness `on_tool_result` hook on an executed call's output.
+    ///
+    /// Returns the output the model sees: rewritten by `MODIFY`, or withheld
+    /// (as an error) on `REJECT`. `tool_executions` keeps the original.
+    async fn evaluate_tool_result(&self, tc: &PendingToolCall, content: String, is_error: bool) -> (String, bool) {
+        let harness = self.harness.lock().await;
+        let Some(ref engine) = *harness else {
+            return (content, is_error);
+        };
+        match engine.evaluate_tool_result(&tc.name, &tc.id, &tc.args, &content, is_error) {
+            Ok(Verdict::Modify(serde_json::Value::String(output))) => {
+                info!(tool = %tc.name, before = content.len(), after = output.len(), "Tool output rewritten by harness");
+                (output, is_error)
+            }
+            Ok(Verdict::Reject(reason)) => {
+                info!(tool = %tc.name, reason = %reason, "Tool output withheld by harness");
+                (format!("[HARNESS WITHHELD] Output of tool '{}' withheld: {}", tc.name, reason), true)
+            }
+            Ok(_) => (content, is_error),
+            Err(e) => {
+                // Non-fatal like on_tool_call: the output passes through unchanged
+                warn!(error = %e, "Harness on_tool_result error");
+                (content, is_error)
+            }
+        }
+    }
+
     /// Evaluate harness `on_token_usage` hook.
     ///
     /// This fires after each turn. If a harness rejects, it logs a warning.
@@ -961,7 +2917,8 @@ impl Kernel {
     /// logs but doesn't halt the loop (the harness can use `db.kv_set` to track state
     /// and reject tool calls instead).
 
-    async fn evaluate_token_usage(&self, input_tokens: u64, output_tokens: u64) {
+    async fn evaluate_token_usage(&self, session: &SessionState) {
+        let (input_tokens, output_tokens) = (session.total_input_tokens, session.total_output_tokens);
         let harness = self.harness.lock().await;
         if let Some(ref engine) = *harness {
             let payload = serde_json::json!({
@@ -979,52 +2936,569 @@ impl Kernel {
                     warn!(error = %e, "Harness on_token_usage error");
                 }
             }
+
+            // Soft limit set by the harness; [limits] stay the hard stop
+            if let Some(soft_limit) = engine.update_usage(&session.id, input_tokens, output_tokens, session.total_cost_usd) {
+                let total_tokens = input_tokens + output_tokens;
+                info!(total_tokens, soft_limit, "Soft token limit reached");
+                self.persist_event(session, &KernelEvent::BudgetWarning {
+                    limit: "soft_limit".to_string(),
+                    used: total_tokens as f64,
+                    max: soft_limit as f64,
+                    fraction: 1.0,
+                });
+                let payload = serde_json::json!({
+                    "input_tokens": input_tokens,
+                    "output_tokens": output_tokens,
+                    "total_tokens": total_tokens,
+                    "soft_limit": soft_limit,
+                    "cost_usd": session.total_cost_usd,
+                    "budget_usd": self.config.cost_budget_usd(),
+                });
+                if let Err(e) = engine.evaluate("on_budget_warning", payload) {
+                    warn!(error = %e, "Harness on_budget_warning error");
+                }
+            }
+        }
+    }
+
+    /// Handle a failed inference request. A context-overflow rejection is
+    /// answered (once per turn) by dropping older history so the caller can
+    /// retry; any other error, or a second overflow, is returned.
+    fn recover_from_overflow(&self, session: &mut SessionState, error: anyhow::Error, retried: &mut bool) -> Result<()> {
+        let message = match error.downcast_ref::<ProviderError>() {
+            Some(ProviderError::ContextOverflow(message)) if self.config.kernel.trim_on_context_overflow => message.clone(),
+            _ => return Err(error),
+        };
+        let min_retained_turns = self.config.kernel.min_retained_turns;
+        if *retried {
+            anyhow::bail!("Request still exceeds the provider's context window after auto-trimming: {}", message);
+        }
+        let removed = retention::trim_for_overflow(&mut session.history, min_retained_turns);
+        if removed == 0 {
+            anyhow::bail!(
+                "Request exceeds the provider's context window and no older history can be trimmed (kernel.min_retained_turns = {}): {}",
+                min_retained_turns, message
+            );
+        }
+        *retried = true;
+
+        warn!(turn = session.turn_index, removed, remaining = session.history.len(), "Context overflow; trimmed history and retrying");
+        self.persist_event(session, &KernelEvent::ContextTrimmed {
+            turn_index: session.turn_index,
+            removed_messages: removed as u32,
+            remaining_messages: session.history.len() as u32,
+        });
+        self.persist_event(session, &KernelEvent::TurnRetried {
+            turn_index: session.turn_index,
+            attempt: 1,
+            reason: "context overflow".to_string(),
+        });
+        Ok(())
+    }
+
+    /// Stop the run once the session's spend has crossed the cost budget.
+    /// Returns whether it was stopped.
+    fn check_cost_budget(&self, session: &mut SessionState) -> bool {
+        let Some(budget) = self.config.cost_budget_usd().filter(|budget| session.total_cost_usd > *budget) else {
+            return false;
+        };
+        error!(spent_usd = session.total_cost_usd, budget_usd = budget, "Cost budget exceeded; aborting run");
+        self.persist_event(session, &KernelEvent::CostBudgetExceeded {
+            spent_usd: session.total_cost_usd,
+            budget_usd: budget,
+            next_request_estimate_usd: None,
+        });
+        session.stop_reason = Some(StopReason::CostBudgetExceeded);
+        true
+    }
+
+    /// Emit `BudgetWarning` for each `limits.warn_at` fraction that this turn's
+    /// usage crossed, and stop the run once a `[limits]` token cap is exceeded.
+    /// `before` is the session's (input, output, cost) usage at the start of the
+    /// turn. Returns whether the run was stopped; the cost cap is enforced by
+    /// the cost budget check.
+    fn check_usage_limits(&self, session: &mut SessionState, before: (u64, u64, f64)) -> bool {
+        let limits = &self.config.limits;
+        let usage = [
+            ("max_input_tokens", before.0 as f64, session.total_input_tokens as f64, limits.max_input_tokens.map(|m| m as f64)),
+            ("max_output_tokens", before.1 as f64, session.total_output_tokens as f64, limits.max_output_tokens.map(|m| m as f64)),
+            ("max_cost_usd", before.2, session.total_cost_usd, self.config.cost_budget_usd()),
+        ];
+        for (limit, before, used, max) in usage {
+            let Some(max) = max else { continue };
+            for &fraction in &limits.warn_at {
+                let mark = max * fraction;
+                if before < mark && used >= mark {
+                    warn!(limit, used, max, fraction, "Session usage approaching limit");
+                    self.persist_event(session, &KernelEvent::BudgetWarning { limit: limit.to_string(), used, max, fraction });
+                }
+            }
+        }
+
+        let exceeded = [
+            ("max_input_tokens", session.total_input_tokens, limits.max_input_tokens),
+            ("max_output_tokens", session.total_output_tokens, limits.max_output_tokens),
+        ]
+        .into_iter()
+        .find_map(|(limit, used, max)| max.filter(|max| used > *max).map(|max| (limit, used, max)));
+        let Some((limit, used, max)) = exceeded else {
+            return false;
+        };
+        error!(limit, used, max, "Session token limit exceeded; stopping run");
+        self.persist_event(session, &KernelEvent::LimitExceeded { limit: limit.to_string(), used, max });
+        session.stop_reason = Some(StopReason::LimitExceeded);
+        true
+    }
+
+    /// Summarize older turns once the prompt is estimated above
+    /// `compaction.threshold_tokens` (see [`crate::kernel::config::CompactionConfig`]).
+    /// Failures are logged and leave the history as it was.
+    async fn compact_history(&mut self, session: &mut SessionState, system_prompt: &str, model: &str, provider_name: &str) {
+        let config = self.config.compaction.clone();
+        let tokens_before = compaction::estimate_prompt_tokens(system_prompt, &session.history);
+        if tokens_before <= config.threshold_tokens {
+            return;
+        }
+        let keep_turns = config.keep_turns.max(self.config.kernel.min_retained_turns);
+        let cut = compaction::compaction_cut(&session.history, keep_turns);
+        if cut == 0 {
+            debug!(tokens = tokens_before, keep_turns, "Prompt over the compaction threshold but no older turns to summarize");
+            return;
+        }
+
+        let summary_provider = config.provider.as_deref().unwrap_or(provider_name).to_string();
+        let summary_provider = summary_provider.as_str();
+        let summary_model = config.model.as_deref().unwrap_or(model);
+        if !self.clients.contains_key(summary_provider) {
+            let Some(provider_config) = self.config.providers.get(summary_provider) else {
+                warn!(provider = %summary_provider, "Compaction provider not found in configuration");
+                return;
+            };
+            match self.create_client(summary_provider, provider_config).await {
+                Ok(client) => { self.clients.insert(summary_provider.to_string(), client); }
+                Err(e) => {
+                    warn!(provider = %summary_provider, error = %e, "Failed to initialize compaction provider");
+                    return;
+                }
+            }
+        }
+        let input = compaction::summary_input(&session.history[..cut]);
+        let completion = self.clients[summary_provider].completion_with_usage(summary_model, compaction::SUMMARY_PROMPT, &input).await;
+        if let Ok((_, input_tokens, output_tokens)) = &completion {
+            self.record_usage(session, summary_provider, summary_model, *input_tokens, *output_tokens).await;
+        }
+        let summary = match completion {
+            Ok((summary, ..)) if !summary.trim().is_empty() => summary,
+            Ok(_) => {
+                warn!(model = %summary_model, "Compaction model returned an empty summary; history left as is");
+                return;
+            }
+            Err(e) => {
+                warn!(model = %summary_model, error = %e, "Compaction failed; history left as is");
+                return;
+            }
+        };
+        let digest = match &self.state {
+            Some(store) => compaction::tool_digest_for(store, &session.id, &session.history[..cut]).await.unwrap_or_else(|e| {
+                warn!(error = %e, "Failed to build tool digest for compaction");
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        let summary = compaction::structured_summary(&summary, &digest);
+
+        let removed = compaction::apply_summary(&mut session.history, cut, &summary);
+        let tokens_after = compaction::estimate_prompt_tokens(system_prompt, &session.history);
+        info!(turn = session.turn_index, removed, tokens_before, tokens_after, "Compacted history");
+        self.persist_event(session, &KernelEvent::HistoryCompacted {
+            turn_index: session.turn_index,
+            removed_messages: removed as u32,
+            tokens_before,
+            tokens_after,
+            model: summary_model.to_string(),
+            summary,
+        });
+    }
+
+    /// Handle a retryable provider error: back off and retry the same provider
+    /// until `inference.retry.max_attempts` is used up, then fail over.
+    #[allow(clippy::too_many_arguments)]
+    async fn retry_or_fail_over(
+        &self,
+        session: &SessionState,
+        error: anyhow::Error,
+        retries: &mut u32,
+        next_fallback: &mut usize,
+        provider_name: &mut String,
+        model: &mut String,
+        client: &mut ProviderClient,
+    ) -> Result<()> {
+        let policy = &self.config.inference.retry;
+        if *retries + 1 < policy.max_attempts {
+            *retries += 1;
+            let delay = policy.delay(*retries);
+            warn!(turn = session.turn_index, provider = %provider_name, attempt = *retries, delay_ms = delay.as_millis() as u64, error = %error, "Provider error; retrying");
+            self.persist_event(session, &KernelEvent::TurnRetried {
+                turn_index: session.turn_index,
+                attempt: *retries,
+                reason: format!("provider error: {}", error),
+            });
+            // An interrupt cuts the wait short; the next request attempt notices it
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = self.interrupt.interrupted() => {}
+            }
+            return Ok(());
+        }
+        self.fail_over(session, error, next_fallback, provider_name, model, client).await?;
+        *retries = 0;
+        Ok(())
+    }
+
+    /// Move the request to the next usable `agent.fallbacks` entry after a
+    /// retryable provider error, emitting `ProviderFailover`. Entries naming the
+    /// failed provider and model are skipped; once the chain is exhausted the
+    /// error is returned.
+    async fn fail_over(
+        &self,
+        session: &SessionState,
+        error: anyhow::Error,
+        next_fallback: &mut usize,
+        provider_name: &mut String,
+        model: &mut String,
+        client: &mut ProviderClient,
+    ) -> Result<()> {
+        while let Some(fallback) = self.config.agent.fallbacks.get(*next_fallback) {
+            *next_fallback += 1;
+            let to_model = fallback.model.clone().unwrap_or_else(|| model.clone());
+            if fallback.provider == *provider_name && to_model == *model {
+                continue;
+            }
+            // Not cached: tool calls in flight may still borrow the kernel
+            let to_client = match self.clients.get(&fallback.provider) {
+                Some(client) => client.clone(),
+                None => match self.config.providers.get(&fallback.provider) {
+                    Some(config) => match self.create_client(&fallback.provider, config).await {
+                        Ok(client) => client,
+                        Err(e) => {
+                            warn!(provider = %fallback.provider, error = %e, "Skipping fallback provider that failed to initialize");
+                            continue;
+                        }
+                    },
+                    None => continue,
+                },
+            };
+            warn!(turn = session.turn_index, from = %provider_name, to = %fallback.provider, error = %error, "Provider failed; failing over");
+            self.persist_event(session, &KernelEvent::ProviderFailover {
+                turn_index: session.turn_index,
+                from_provider: std::mem::replace(provider_name, fallback.provider.clone()),
+                from_model: std::mem::replace(model, to_model.clone()),
+                to_provider: fallback.provider.clone(),
+                to_model,
+                error: error.to_string(),
+            });
+            *client = to_client;
+            return Ok(());
         }
+        Err(error)
     }
 
-    /// Persist an event to the state store in the background.
+    /// Publish an event to subscribers and persist it to the state store in the background.
     #[instrument(skip(self, session, event), fields(event_type = %event.event_type()))]
     pub fn persist_event(&self, session: &SessionState, event: &KernelEvent) {
         self.persist_event_internal(&session.event_tx, &session.id, event);
     }
 
+    /// Pricing for `model` on `provider`, with the `[pricing]` overrides applied.
+    fn pricing(&self, provider: &str, model: &str) -> Option<pricing::ModelPricing> {
+        pricing::lookup(&self.config.pricing, provider, model)
+    }
+
+    /// Add one provider response's usage to the session totals and record it
+    /// in the `turn_costs` table. Unpriced models add tokens but no cost.
+    async fn record_usage(&self, session: &mut SessionState, provider: &str, model: &str, input_tokens: u64, output_tokens: u64) {
+        let cost = self.pricing(provider, model).map(|p| p.cost(input_tokens, output_tokens));
+        session.total_input_tokens += input_tokens;
+        session.total_output_tokens += output_tokens;
+        session.total_cost_usd += cost.unwrap_or(0.0);
+        if let Some(store) = &self.state {
+            if let Err(e) = store
+                .insert_turn_cost(&session.id, session.turn_index, provider, model, input_tokens, output_tokens, cost)
+                .await
+            {
+                warn!(error = %e, "Failed to record turn cost");
+            }
+        }
+    }
+
     /// Internal helper for persistence (used by parallel runners)
     fn persist_event_internal(&self, tx: &mpsc::UnboundedSender<(String, KernelEvent)>, session_id: &str, event: &KernelEvent) {
-        if self.json {
-            // In JSON mode, all events go to stdout as NDJSON
-            println!("{}", serde_json::to_string(event).unwrap_or_default());
+        self.renderer.lock().unwrap_or_else(|e| e.into_inner()).render(event);
+        self.publish_event(tx, session_id, event.clone());
+    }
+
+    /// Publish an event to subscribers, then queue it for persistence,
+    /// without rendering it.
+    fn publish_event(&self, tx: &mpsc::UnboundedSender<(String, KernelEvent)>, session_id: &str, event: KernelEvent) {
+        // Sending fails only when no one is subscribed
+        if self.event_bus.receiver_count() > 0 {
+            let _ = self.event_bus.send((session_id.to_string(), event.clone()));
         }
-        if let Err(e) = tx.send((session_id.to_string(), event.clone())) {
+        if let Err(e) = tx.send((session_id.to_string(), event)) {
             warn!(error = %e, "Failed to send event to background persistence task");
         }
     }
 
+    /// Start the servers in `[mcp] servers`, `mcp.startup_concurrency` at a
+    /// time. A server that fails, times out, or would exceed `mcp.max_servers`
+    /// is logged and skipped, and none of its tools are registered. A lazy
+    /// server whose tool list is cached from an earlier run is registered
+    /// from the cache and started when one of its tools is first called.
+    /// With `tools.enabled = false` no server is started.
+    /// Returns the number of servers connected or registered lazily.
+    pub async fn init_mcp_servers(&mut self) -> usize {
+        let config = self.config.mcp.clone();
+        if !self.config.tools.enabled {
+            if !config.servers.is_empty() {
+                warn!(count = config.servers.len(), "Tools are disabled; not starting MCP servers");
+            }
+            return 0;
+        }
+        let room = config.max_servers.map_or(usize::MAX, |max| max.saturating_sub(self.mcp_server_count()));
+        let (configured, over) = config.servers.split_at(config.servers.len().min(room));
+        for server in over {
+            warn!(server = %server.server_name(), max_servers = ?config.max_servers, "Skipping MCP server over mcp.max_servers");
+        }
+        if configured.is_empty() {
+            return 0;
+        }
+
+        let started = Instant::now();
+        let mut connected = 0;
+        let mut servers = Vec::with_capacity(configured.len());
+        for server in configured {
+            let server_arc = Arc::new(self.new_mcp_server(&server.server_name(), server.to_transport()));
+            let cached = match server.lazy.unwrap_or(config.lazy) {
+                true => server_arc.cached_tools().await,
+                false => None,
+            };
+            servers.push((server_arc, cached));
+        }
+
+        let eager: Vec<_> = servers.iter().filter(|(_, cached)| cached.is_none()).map(|(server, _)| server.clone()).collect();
+        let timeout = std::time::Duration::from_secs(config.startup_timeout_secs);
+        let mut started_tools = crate::tools::mcp::start_all(&eager, config.startup_concurrency, timeout, |server| server.start())
+            .await
+            .into_iter();
+        // Registered in configured order, lazy or not, so clashes are
+        // namespaced the same way whether or not tool lists were cached
+        for (server, cached) in servers {
+            let name = server.name().to_string();
+            let lazy = cached.is_some();
+            let tools = match cached {
+                Some(tools) => Ok(tools),
+                None => started_tools.next().expect("one start result per eager server"),
+            };
+            match tools.and_then(|tools| self.register_mcp_server(server, tools)) {
+                Ok(count) if lazy => {
+                    info!(server = %name, tools = count, "MCP server registered; starts on first use");
+                    connected += 1;
+                }
+                Ok(count) => {
+                    info!(server = %name, tools = count, "MCP server connected");
+                    connected += 1;
+                }
+                Err(e) => warn!(server = %name, error = %format!("{:#}", e), "Skipping MCP server"),
+            }
+        }
+        info!(connected, configured = config.servers.len(), elapsed_ms = started.elapsed().as_millis() as u64, "MCP servers started");
+        connected
+    }
+
+    /// The MCP servers whose tools are registered, in registration order.
+    pub fn mcp_servers(&self) -> Vec<Arc<McpServerHandle>> {
+        self.mcp_servers.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn mcp_server_count(&self) -> usize {
+        self.mcp_servers.read().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// A server supervised under `[mcp]`'s restart settings, caching its tool
+    /// list in the state store.
+    fn new_mcp_server(&self, name: &str, transport: McpTransport) -> McpServerHandle {
+        let server = McpServerHandle::new(name, transport, self.config.mcp.restart_policy());
+        match &self.state {
+            Some(state) => server.with_tool_cache(state.clone()),
+            None => server,
+        }
+    }
+
     /// Connect to an MCP server, initialize it, and register its tools.
     #[instrument(skip(self, args), fields(command = %command, args = ?args))]
-    async fn spawn_mcp_server(&mut self, command: &str, args: &[String]) -> Result<usize> {
-        let args_str: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    async fn spawn_mcp_server(&mut self, command: &str, args: &[String], server_name: Option<&str>) -> Result<usize> {
+        if let Some(max) = self.config.mcp.max_servers {
+            anyhow::ensure!(self.mcp_server_count() < max, "Already connected to {} MCP servers (mcp.max_servers)", max);
+        }
         info!("Connecting to MCP server");
+        let server_name = server_name
+            .map(str::to_string)
+            .unwrap_or_else(|| crate::tools::mcp::default_server_name(command));
+        let transport = McpTransport::Stdio { command: command.to_string(), args: args.to_vec() };
+        let server = Arc::new(self.new_mcp_server(&server_name, transport));
+        let tools = server.start().await?;
+        self.register_mcp_server(server, tools)
+    }
 
-        let transport = StdioTransport::new(command, &args_str)
-            .with_context(|| format!("Failed to spawn MCP process: {}", command))?;
-        
-        let client = McpClient::new(transport);
-        client.initialize().await.with_context(|| "Failed to initialize MCP client")?;
-        
-        let list_result = client.list_tools().await.with_context(|| "Failed to list MCP tools")?;
-        let count = list_result.tools.len();
-        
-        let client_arc = Arc::new(client);
-        self.mcp_clients.push(client_arc.clone());
+    /// Register the tools of an MCP server, and start health checks
+    /// (`mcp.health_check_interval_secs`) with the first one. The first
+    /// server also registers `read_resource`, unless tools are disabled.
+    ///
+    /// A tool whose name another MCP server already uses is registered as
+    /// `<server>__<tool>` instead, as with `tools.namespace_mcp`; at startup
+    /// the server listed first in `[mcp]` keeps the plain name.
+    fn register_mcp_server(&mut self, server: Arc<McpServerHandle>, tools: Vec<ToolDefinition>) -> Result<usize> {
+        let server_name = server.name().to_string();
+        let count = tools.len();
+        let mut proxies = Vec::with_capacity(count);
+        let mut renamed = Vec::new();
+        for tool_def in tools {
+            let mut proxy = McpToolProxy::new(server.clone(), tool_def);
+            if self.config.tools.namespace_mcp {
+                proxy = proxy.namespaced();
+            } else if let Some(other) = self.tool_registry.get(self.tool_registry.exposed_name(proxy.name())).and_then(|t| t.server()) {
+                renamed.push(format!("{} (also on {})", proxy.name(), other));
+                proxy = proxy.namespaced();
+            }
+            // Check every tool first so a conflict leaves none of the server's tools registered
+            self.tool_registry.check_schema_conflict(ToolSource::Mcp, &proxy)
+                .with_context(|| format!("Refusing tools from MCP server '{}'", server_name))?;
+            proxies.push(proxy);
+        }
+        let first = {
+            let mut servers = self.mcp_servers.write().unwrap_or_else(|e| e.into_inner());
+            servers.push(server);
+            servers.len() == 1
+        };
+        if self.config.tools.enabled && self.tool_registry.get("read_resource").is_none() {
+            let tool = crate::tools::mcp_resources::ReadResourceTool::new(self.mcp_servers.clone());
+            self.tool_registry.register_from(ToolSource::Builtin, Box::new(tool))?;
+        }
+        if first && self.config.mcp.health_check_interval_secs > 0 {
+            let interval = std::time::Duration::from_secs(self.config.mcp.health_check_interval_secs);
+            crate::tools::mcp::spawn_health_checks(Arc::downgrade(&self.mcp_servers), interval);
+        }
 
-        for tool_def in list_result.tools {
-            let proxy = McpToolProxy::new(client_arc.clone(), tool_def);
-            self.tool_registry.register(Box::new(proxy))
-                .with_context(|| "Failed to register MCP tool")?;
+        let mut shadowed = Vec::new();
+        for proxy in proxies {
+            if let Some(clash) = self.tool_registry.register_from(ToolSource::Mcp, Box::new(proxy))
+                .with_context(|| "Failed to register MCP tool")? {
+                shadowed.push(format!("{} ({} shadows {})", clash.name, clash.winner, clash.loser));
+            }
+        }
+        if !shadowed.is_empty() {
+            warn!(server = %server_name, shadowed = %shadowed.join(", "), "MCP tools clash with existing tools");
         }
+        if !renamed.is_empty() {
+            warn!(server = %server_name, tools = %renamed.join(", "), "MCP tools clash with another server's; registered as <server>__<tool>");
+        }
+        self.refresh_tool_catalog();
 
         info!(count = count, "MCP tools registered");
 
         Ok(count)
     }
 }
+
+/// Whether a provider stop reason means the output token limit cut the
+/// response short (OpenAI reports "length", Anthropic "max_tokens").
+fn hit_output_limit(stop_reason: Option<&str>) -> bool {
+    stop_reason.map(FinishReason::parse) == Some(FinishReason::Length)
+}
+
+/// The arguments a call runs with under `verdict`: a harness rewrite, or `args`.
+fn verdict_args<'a>(verdict: &'a Verdict, args: &'a serde_json::Value) -> &'a serde_json::Value {
+    match verdict {
+        Verdict::Modify(rewritten) => rewritten,
+        _ => args,
+    }
+}
+
+/// Content of the result answering tool call `id`, if there is one.
+fn tool_result_content<'a>(results: &'a [InferenceContent], id: &str) -> Option<&'a str> {
+    results.iter().find_map(|r| match r {
+        InferenceContent::ToolResult { tool_use_id, content, .. } if tool_use_id == id => Some(content.as_str()),
+        _ => None,
+    })
+}
+
+/// Request history for continuing a cut-off response: the partial answer so
+/// far followed by a prompt to pick up where it stopped.
+fn continuation_messages(history: &[InferenceMessage], partial: &str) -> Vec<InferenceMessage> {
+    let mut messages = history.to_vec();
+    messages.push(InferenceMessage {
+        role: InferenceRole::Assistant,
+        content: vec![InferenceContent::Text { text: partial.to_string() }],
+        tool_call_id: None,
+    });
+    messages.push(InferenceMessage {
+        role: InferenceRole::User,
+        content: vec![InferenceContent::Text { text: CONTINUE_PROMPT.to_string() }],
+        tool_call_id: None,
+    });
+    messages
+}
+
+/// Why a turn's outcome is unusable, if it is: no content at all, or tool
+/// calls whose arguments are not a JSON object.
+fn unusable_turn_outcome(response_text: &str, tool_calls: &[PendingToolCall]) -> Option<String> {
+    if response_text.trim().is_empty() && tool_calls.is_empty() {
+        return Some("empty response".to_string());
+    }
+    tool_calls.iter()
+        .find(|tc| tc.name.is_empty() || !tc.args.is_object())
+        .map(|tc| format!("malformed tool call '{}': arguments must be a JSON object", tc.name))
+}
+
+/// Response length assumed by the cost pre-check when no `max_tokens` is set.
+const DEFAULT_OUTPUT_ESTIMATE_TOKENS: u64 = 4096;
+
+/// Estimated USD cost of the next request: the prompt (system prompt, history
+/// and tool schemas) plus a full-length response. `None` if the model is unpriced.
+pub(crate) fn estimate_request_cost(
+    pricing: Option<pricing::ModelPricing>,
+    system_prompt: &str,
+    history: &[InferenceMessage],
+    tools: &[serde_json::Value],
+    options: &provider::InferenceOptions,
+) -> Option<f64> {
+    let pricing = pricing?;
+    let prompt = serde_json::to_string(history).unwrap_or_default()
+        + &serde_json::to_string(tools).unwrap_or_default();
+    let input_tokens = pricing::estimate_tokens(system_prompt) + pricing::estimate_tokens(&prompt);
+    let output_tokens = options.max_tokens.map(u64::from).unwrap_or(DEFAULT_OUTPUT_ESTIMATE_TOKENS);
+    Some(pricing.cost(input_tokens, output_tokens))
+}
+
+/// Whether `error` is a transient provider failure another provider could serve.
+fn is_retryable(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<ProviderError>(), Some(ProviderError::Retryable(_)))
+}
+
+/// Serialize an assistant message (text plus tool calls) for the `messages` table.
+fn assistant_message_json(thinking: &str, response_text: &str, tool_calls: &[PendingToolCall]) -> serde_json::Value {
+    let mut parts = Vec::new();
+    if !thinking.is_empty() {
+        parts.push(serde_json::json!({"type": "thinking", "content": thinking}));
+    }
+    if !response_text.is_empty() {
+        parts.push(serde_json::json!({"type": "text", "text": response_text}));
+    }
+    for tc in tool_calls {
+        parts.push(serde_json::json!({
+            "type": "tool_use", "id": tc.id, "name": tc.name, "input": tc.args,
+        }));
+    }
+    serde_json::Value::Array(parts)
+}
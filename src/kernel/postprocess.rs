@@ -0,0 +1,135 @@
+//! Built-in postprocessors for assistant messages.
+//!
+//! `agent.postprocessors` lists cleanup steps that run, in order, on each
+//! assistant message once it has finished streaming and before it is persisted
+//! and added to history. The kernel runs the chain and reports each step as a
+//! `MessagePostprocessed` event; the `harness` step is also handled there,
+//! since it calls into the harness engine. With `redact_secrets` in the chain,
+//! the streamed deltas pass through a [`StreamRedactor`] too, so secrets are
+//! neither printed nor stored in `message_delta` events.
+
+use crate::kernel::config::Postprocessor;
+use crate::persistence::redact::redact_text;
+
+/// Config name of a step, as written in `agent.postprocessors`.
+pub fn step_name(step: &Postprocessor) -> &'static str {
+    match step {
+        Postprocessor::StripCodeFences => "strip_code_fences",
+        Postprocessor::TrimTrailingWhitespace => "trim_trailing_whitespace",
+        Postprocessor::RedactSecrets => "redact_secrets",
+        Postprocessor::Truncate(_) => "truncate",
+        Postprocessor::EnforceJson => "enforce_json",
+        Postprocessor::Harness => "harness",
+    }
+}
+
+/// Apply a built-in step to `text`. An `Err` leaves the text unchanged and
+/// explains why the step could not be applied.
+pub fn apply(step: &Postprocessor, text: &str) -> Result<String, String> {
+    match step {
+        Postprocessor::StripCodeFences => Ok(strip_code_fences(text)),
+        Postprocessor::TrimTrailingWhitespace => {
+            let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+            Ok(lines.join("\n").trim_end().to_string())
+        }
+        Postprocessor::RedactSecrets => Ok(redact_text(text)),
+        Postprocessor::Truncate(max_chars) => Ok(text.chars().take(*max_chars).collect()),
+        Postprocessor::EnforceJson => extract_json(text).ok_or_else(|| "no valid JSON document in the message".to_string()),
+        Postprocessor::Harness => Err("the harness step is run by the kernel".to_string()),
+    }
+}
+
+/// Redacts streamed text before it is rendered or persisted. The trailing,
+/// possibly incomplete word of each delta is held back until the next one
+/// arrives, so a secret split across deltas is still seen whole.
+#[derive(Debug, Default)]
+pub struct StreamRedactor {
+    pending: String,
+}
+
+impl StreamRedactor {
+    /// Add a delta; returns the redacted text that can be emitted now.
+    pub fn push(&mut self, delta: &str) -> String {
+        self.pending.push_str(delta);
+        let Some((i, c)) = self.pending.char_indices().rev().find(|(_, c)| c.is_whitespace()) else {
+            return String::new();
+        };
+        let mut split = i + c.len_utf8();
+        // The token after `Bearer` is only redacted when seen with it
+        let ready = self.pending[..split].trim_end();
+        if ready.split_whitespace().next_back() == Some("Bearer") {
+            split = ready.len() - "Bearer".len();
+        }
+        let ready: String = self.pending.drain(..split).collect();
+        redact_text(&ready)
+    }
+
+    /// The redacted rest, once the stream has ended.
+    pub fn finish(&mut self) -> String {
+        redact_text(&std::mem::take(&mut self.pending))
+    }
+}
+
+fn strip_code_fences(text: &str) -> String {
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The message itself if it is JSON, otherwise the first JSON object or array
+/// embedded in it (e.g. inside a code fence or after a sentence of prose).
+fn extract_json(text: &str) -> Option<String> {
+    let trimmed = text.trim();
+    if serde_json::from_str::<serde_json::Value>(trimmed).is_ok() {
+        return Some(trimmed.to_string());
+    }
+    let unfenced = strip_code_fences(trimmed);
+    if serde_json::from_str::<serde_json::Value>(unfenced.trim()).is_ok() {
+        return Some(unfenced.trim().to_string());
+    }
+    for (open, close) in [('{', '}'), ('[', ']')] {
+        if let (Some(start), Some(end)) = (trimmed.find(open), trimmed.rfind(close)) {
+            let candidate = &trimmed[start..=end.max(start)];
+            if serde_json::from_str::<serde_json::Value>(candidate).is_ok() {
+                return Some(candidate.to_string());
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_steps() {
+        let fenced = "Here:\n```rust\nfn main() {}   \n```\n\n";
+        assert_eq!(apply(&Postprocessor::StripCodeFences, fenced).unwrap(), "Here:\nfn main() {}   \n");
+        assert_eq!(apply(&Postprocessor::TrimTrailingWhitespace, fenced).unwrap(), "Here:\n```rust\nfn main() {}\n```");
+        assert_eq!(apply(&Postprocessor::Truncate(3), "héllo").unwrap(), "hél");
+        assert_eq!(apply(&Postprocessor::RedactSecrets, "key sk-abcdef1234567890").unwrap(), "key [REDACTED]");
+        assert!(apply(&Postprocessor::Harness, "x").is_err());
+    }
+
+    #[test]
+    fn test_stream_redactor_sees_split_secrets() {
+        let mut redactor = StreamRedactor::default();
+        let mut out = String::new();
+        for delta in ["Use sk-abc", "def1234567890 or Bear", "er ", "abcdefgh12345", " now"] {
+            out.push_str(&redactor.push(delta));
+        }
+        out.push_str(&redactor.finish());
+        assert_eq!(out, "Use [REDACTED] or Bearer [REDACTED] now");
+    }
+
+    #[test]
+    fn test_enforce_json() {
+        let step = Postprocessor::EnforceJson;
+        assert_eq!(apply(&step, " {\"ok\": true}\n").unwrap(), "{\"ok\": true}");
+        assert_eq!(apply(&step, "```json\n[1, 2]\n```").unwrap(), "[1, 2]");
+        assert_eq!(apply(&step, "Result: {\"n\": 1}. Done.").unwrap(), "{\"n\": 1}");
+        assert!(apply(&step, "no json here").is_err());
+    }
+}
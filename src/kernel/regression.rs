@@ -0,0 +1,374 @@
+//! Replay-based regression tests, for `bedrock test` and
+//! `bedrock replay --deterministic`.
+//!
+//! A recording is the JSON transcript of a stored session, as written by
+//! `bedrock cat --format json`, or loaded straight from the store. Replaying
+//! it sends the session's prompts through the current kernel and harness,
+//! with the model scripted from the recorded assistant turns and every tool
+//! stubbed to return its recorded output. The tool calls the replay makes (name, arguments, harness verdict
+//! and error flag) and its final answer are then compared with the recording,
+//! so a harness or kernel change that alters behaviour shows up as a diff.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::inference::provider::{
+    InferenceEvent, InferenceProvider, InferenceRequest, InferenceStream, ProviderClient, ProviderKind,
+    RequestOptions, SdkError,
+};
+use crate::kernel::config::{BedrockConfig, QueueEmptyPolicy};
+use crate::kernel::verbosity::{self, Verbosity};
+use crate::kernel::Kernel;
+use crate::persistence::state::StateStore;
+use crate::persistence::transcript::{self, TranscriptEntry, TranscriptOptions};
+use crate::tools::builtins::create_default_registry;
+use crate::tools::registry::ToolRegistry;
+use crate::tools::{args_hash, Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
+
+/// Recorded output and error flag of each call, keyed by tool name and args hash.
+type RecordedOutputs = HashMap<String, VecDeque<(String, bool)>>;
+
+/// A tool call as compared between a recording and its replay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedCall {
+    pub name: String,
+    pub args: Value,
+    /// Harness verdict, when it was anything other than a plain allow
+    pub verdict: Option<String>,
+    pub is_error: bool,
+}
+
+impl fmt::Display for RecordedCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, verbosity::preview(&self.args.to_string(), 120))?;
+        if let Some(verdict) = &self.verdict {
+            write!(f, " [{}]", verdict)?;
+        }
+        if self.is_error {
+            write!(f, " (error)")?;
+        }
+        Ok(())
+    }
+}
+
+/// One recorded model response: its text and the tool calls it made.
+#[derive(Debug, Clone, Default)]
+struct RecordedTurn {
+    text: String,
+    /// (id, name, args)
+    calls: Vec<(String, String, Value)>,
+}
+
+/// A session loaded from its transcript, ready to replay or compare.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    /// Prompts the user sent, in order; the first starts the replay
+    pub prompts: Vec<String>,
+    /// Every user message in order, with whether the harness or kernel
+    /// queued it rather than the user
+    pub user_messages: Vec<(String, bool)>,
+    /// Tool calls in the order they were made
+    pub calls: Vec<RecordedCall>,
+    /// Text of the last model response
+    pub answer: String,
+    turns: Vec<RecordedTurn>,
+    outputs: RecordedOutputs,
+}
+
+impl Recording {
+    /// Parse a recording from `bedrock cat --format json` output (one entry per line).
+    pub fn parse(text: &str) -> Result<Self> {
+        let entries = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| serde_json::from_str(line).with_context(|| format!("Invalid transcript entry on line {}", i + 1)))
+            .collect::<Result<Vec<TranscriptEntry>>>()?;
+        Self::from_entries(&entries)
+    }
+
+    /// Load the recording of a stored session.
+    pub async fn load(store: &StateStore, session_id: &str) -> Result<Self> {
+        let entries = transcript::load(store, session_id, TranscriptOptions::default()).await?;
+        Self::from_entries(&entries)
+    }
+
+    pub fn from_entries(entries: &[TranscriptEntry]) -> Result<Self> {
+        let mut user_messages = Vec::new();
+        let mut turns: BTreeMap<u32, RecordedTurn> = BTreeMap::new();
+        let mut calls = Vec::new();
+        let mut outputs = RecordedOutputs::new();
+        for entry in entries {
+            match entry {
+                TranscriptEntry::User { text, injected, .. } => user_messages.push((text.clone(), *injected)),
+                TranscriptEntry::Assistant { turn_index, text } => {
+                    turns.entry(*turn_index).or_default().text.push_str(text);
+                }
+                TranscriptEntry::Thinking { .. } => {}
+                TranscriptEntry::ToolCall { turn_index, id, name, args, output, is_error, verdict, .. } => {
+                    let args = args.clone().with_context(|| {
+                        format!("Tool call '{}' was recorded without its arguments (drop --no-tool-args)", id)
+                    })?;
+                    outputs
+                        .entry(output_key(name, &args))
+                        .or_default()
+                        .push_back((output.clone().unwrap_or_default(), *is_error));
+                    turns.entry(*turn_index).or_default().calls.push((id.clone(), name.clone(), args.clone()));
+                    calls.push(RecordedCall { name: name.clone(), args, verdict: verdict.clone(), is_error: *is_error });
+                }
+                TranscriptEntry::Malformed { turn_index, .. } => {
+                    anyhow::bail!("Turn {} has a message that is not valid JSON and cannot be replayed", turn_index);
+                }
+            }
+        }
+        let prompts: Vec<String> = user_messages.iter().filter(|(_, injected)| !injected).map(|(text, _)| text.clone()).collect();
+        anyhow::ensure!(!prompts.is_empty(), "Recording has no user prompt");
+        let turns: Vec<RecordedTurn> = turns.into_values().collect();
+        Ok(Self {
+            prompts,
+            user_messages,
+            calls,
+            answer: turns.last().map(|t| t.text.clone()).unwrap_or_default(),
+            turns,
+            outputs,
+        })
+    }
+
+    /// Differences between this (expected) recording and `actual`, one line each.
+    pub fn diff(&self, actual: &Recording) -> Vec<String> {
+        let mut diffs = Vec::new();
+        let message = |(text, injected): &(String, bool)| {
+            let source = if *injected { "queued" } else { "prompt" };
+            format!("{} {:?}", source, verbosity::preview(text, 120))
+        };
+        for i in 0..self.user_messages.len().max(actual.user_messages.len()) {
+            match (self.user_messages.get(i), actual.user_messages.get(i)) {
+                (Some(e), Some(a)) if e == a => {}
+                (Some(e), Some(a)) => diffs.push(format!("user message {}: expected {}, got {}", i + 1, message(e), message(a))),
+                (Some(e), None) => diffs.push(format!("user message {}: expected {}, got none", i + 1, message(e))),
+                (None, Some(a)) => diffs.push(format!("user message {}: unexpected {}", i + 1, message(a))),
+                (None, None) => unreachable!(),
+            }
+        }
+        for i in 0..self.calls.len().max(actual.calls.len()) {
+            match (self.calls.get(i), actual.calls.get(i)) {
+                (Some(e), Some(a)) if e == a => {}
+                (Some(e), Some(a)) => diffs.push(format!("tool call {}: expected {}, got {}", i + 1, e, a)),
+                (Some(e), None) => diffs.push(format!("tool call {}: expected {}, got none", i + 1, e)),
+                (None, Some(a)) => diffs.push(format!("tool call {}: unexpected {}", i + 1, a)),
+                (None, None) => unreachable!(),
+            }
+        }
+        if self.answer != actual.answer {
+            diffs.push(format!(
+                "final answer: expected {:?}, got {:?}",
+                verbosity::preview(&self.answer, 200),
+                verbosity::preview(&actual.answer, 200),
+            ));
+        }
+        diffs
+    }
+}
+
+fn output_key(name: &str, args: &Value) -> String {
+    format!("{}:{}", name, args_hash(args))
+}
+
+/// Replay `recording` through the kernel and harness configured by `config`
+/// and return how the run differed from it (empty when it matched).
+///
+/// The replay uses an in-memory store, a scripted provider registered under
+/// `agent.provider`, and stub tools in place of the real ones, so only the
+/// harness scripts themselves can reach the workspace.
+pub async fn replay(config: &BedrockConfig, recording: &Recording) -> Result<Vec<String>> {
+    replay_with_output(config, recording, false, Verbosity::default()).await
+}
+
+/// [`replay`], printing the replayed run like a live one: NDJSON events with
+/// `json`, diagnostics per `verbosity`.
+///
+/// After each run the next prompt the user sent is sent, as in the recorded
+/// session. Tasks the harness or kernel queued are marked in the recording
+/// and not sent: they come from the replay, so a harness that stops queueing
+/// them shows up as a diff in the user messages.
+pub async fn replay_with_output(config: &BedrockConfig, recording: &Recording, json: bool, verbosity: Verbosity) -> Result<Vec<String>> {
+    let mut config = config.clone();
+    // Stable ids keep the replay's stored rows identical from run to run
+    config.kernel.deterministic_tool_ids = true;
+    // Nothing would ever queue the task an awaiting run waits for
+    if config.kernel.on_queue_empty == QueueEmptyPolicy::AwaitMore {
+        config.kernel.on_queue_empty = QueueEmptyPolicy::Stop;
+    }
+    let provider_name = config.agent.provider.clone();
+
+    let store = StateStore::open_memory().await?;
+    let mut kernel = Kernel::builder(config)
+        .with_state_store(store.clone())
+        .with_tool_registry(stub_registry(recording)?)
+        .json_mode(json)
+        .verbosity(verbosity)
+        .build()?;
+    let provider = ReplayProvider { turns: recording.turns.clone(), next: AtomicUsize::new(0) };
+    kernel.add_client(provider_name, ProviderClient::new(ProviderKind::Mock, Arc::new(provider)));
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    let mut run = kernel.run(&mut session, Some(recording.prompts[0].clone())).await;
+    for prompt in &recording.prompts[1..] {
+        if run.is_err() {
+            break;
+        }
+        run = kernel.run(&mut session, Some(prompt.clone())).await;
+    }
+    kernel.end_session(&mut session).await?;
+
+    let entries = transcript::load(&store, &session.id, TranscriptOptions::default()).await?;
+    let mut diffs = recording.diff(&Recording::from_entries(&entries)?);
+    if let Err(e) = run {
+        diffs.insert(0, format!("run failed: {:#}", e));
+    }
+    Ok(diffs)
+}
+
+/// A registry with a stub for every tool the recording calls. Stubs keep the
+/// built-in tool's description, schema and effect where there is one, so
+/// harnesses see the same tools they would in a live run.
+fn stub_registry(recording: &Recording) -> Result<ToolRegistry> {
+    let defaults = create_default_registry();
+    let outputs = Arc::new(Mutex::new(recording.outputs.clone()));
+    let mut registry = ToolRegistry::new();
+    let mut names: Vec<&str> = recording.calls.iter().map(|c| c.name.as_str()).collect();
+    names.sort_unstable();
+    names.dedup();
+    for name in names {
+        let builtin = defaults.get(name);
+        registry.register(Box::new(ReplayTool {
+            name: name.to_string(),
+            description: builtin.map_or_else(|| "Recorded tool".to_string(), |t| t.description().to_string()),
+            schema: builtin.map_or_else(|| json!({"type": "object"}), |t| t.parameters_schema()),
+            effect: builtin.map_or(ToolEffect::Unknown, |t| t.effect()),
+            outputs: outputs.clone(),
+        }))?;
+    }
+    Ok(registry)
+}
+
+/// A tool that answers each call with the output recorded for the same name
+/// and args, as the model sent them: a harness that modifies the args still
+/// gets the recorded output, and its verdict shows up in the diff instead.
+struct ReplayTool {
+    name: String,
+    description: String,
+    schema: Value,
+    effect: ToolEffect,
+    outputs: Arc<Mutex<RecordedOutputs>>,
+}
+
+#[async_trait]
+impl Tool for ReplayTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn parameters_schema(&self) -> Value {
+        self.schema.clone()
+    }
+
+    async fn execute(&self, params: Value, ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
+        let args = ctx.original_args.as_ref().unwrap_or(&params);
+        let recorded = self
+            .outputs
+            .lock()
+            .unwrap()
+            .get_mut(&output_key(&self.name, args))
+            .and_then(|queue| queue.pop_front());
+        match recorded {
+            Some((output, false)) => Ok(ToolOutput::new(output)),
+            Some((output, true)) => Err(ToolError::ExecutionError(output)),
+            None => Err(ToolError::ExecutionError("No recorded output for this call".to_string())),
+        }
+    }
+
+    fn effect(&self) -> ToolEffect {
+        self.effect
+    }
+}
+
+/// A provider that plays back the recorded model responses in order.
+struct ReplayProvider {
+    turns: Vec<RecordedTurn>,
+    next: AtomicUsize,
+}
+
+impl InferenceProvider for ReplayProvider {
+    fn stream<'a>(&'a self, _request: InferenceRequest, _options: Option<RequestOptions>) -> BoxFuture<'a, Result<InferenceStream, SdkError>> {
+        let index = self.next.fetch_add(1, Ordering::SeqCst);
+        let turn = self.turns.get(index).cloned();
+        Box::pin(async move {
+            let turn = turn.ok_or_else(|| {
+                SdkError::Api(format!("Recording has no response for model request {}", index + 1))
+            })?;
+            let mut events = vec![Ok(InferenceEvent::MessageStart {
+                role: "assistant".to_string(),
+                model: "replay".to_string(),
+                provider_id: "replay".to_string(),
+            })];
+            if !turn.text.is_empty() {
+                events.push(Ok(InferenceEvent::MessageDelta { content: turn.text }));
+            }
+            for (id, name, args) in turn.calls {
+                events.push(Ok(InferenceEvent::ToolCall { id, name, args }));
+            }
+            events.push(Ok(InferenceEvent::MessageEnd { input_tokens: 0, output_tokens: 0, stop_reason: None }));
+            Ok(Box::pin(futures::stream::iter(events)) as InferenceStream)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECORDING: &str = r#"
+{"type":"user","turn_index":0,"text":"Read the notes"}
+{"type":"tool_call","turn_index":0,"id":"call_0_0","name":"read_file","args":{"path":"notes.txt"},"output":"milk","is_error":false,"duration_ms":1}
+{"type":"assistant","turn_index":1,"text":"Buy milk."}
+"#;
+
+    #[test]
+    fn test_parse_recording() {
+        let recording = Recording::parse(RECORDING).unwrap();
+        assert_eq!(recording.prompts, vec!["Read the notes".to_string()]);
+        assert_eq!(recording.turns.len(), 2);
+        assert_eq!(recording.calls[0].name, "read_file");
+        assert_eq!(recording.answer, "Buy milk.");
+        assert!(recording.diff(&recording).is_empty());
+
+        let mut changed = recording.clone();
+        changed.calls[0].verdict = Some("REJECT: no".to_string());
+        changed.answer = "Done".to_string();
+        let diffs = recording.diff(&changed);
+        assert_eq!(diffs.len(), 2);
+        assert!(diffs[0].contains("[REJECT: no]"), "{}", diffs[0]);
+
+        // A task the harness queued isn't sent, but is compared
+        let queued = format!("{}{}\n", RECORDING, r#"{"type":"user","turn_index":2,"text":"Check again","injected":true}"#);
+        let queued = Recording::parse(&queued).unwrap();
+        assert_eq!(queued.prompts, recording.prompts);
+        let diffs = queued.diff(&recording);
+        assert_eq!(diffs, vec![r#"user message 2: expected queued "Check again", got none"#.to_string()]);
+
+        let no_args = RECORDING.replace(r#""args":{"path":"notes.txt"},"#, "");
+        assert!(Recording::parse(&no_args).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! Console and NDJSON rendering of kernel events.
+//!
+//! A live run prints each event through its kernel's [`EventRenderer`] as it
+//! is emitted, including the `-v` tool lines and token counts derived from
+//! them. `bedrock replay` feeds a stored session's events to
+//! [`EventRenderer::render_recorded`], which also prints the line break the
+//! run loop prints after a response, so a replay reads the way the run did.
+//!
+//! Events are stored with millisecond timestamps, so `--speed` keeps the
+//! pauses between them; sessions stored before that have whole seconds.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use anyhow::Result;
+
+use super::event::KernelEvent;
+use super::verbosity::{self, Verbosity};
+use crate::persistence::state::StateStore;
+
+/// Longest pause `bedrock replay --speed` makes between two events, so idle
+/// time (e.g. a user reading an approval prompt) doesn't stall the replay.
+pub const MAX_REPLAY_PAUSE: Duration = Duration::from_secs(5);
+
+/// Prints kernel events to the console: NDJSON on stdout in JSON mode,
+/// response text on stdout otherwise, diagnostics on stderr per [`Verbosity`].
+#[derive(Debug, Clone, Default)]
+pub struct EventRenderer {
+    json: bool,
+    quiet: bool,
+    verbosity: Verbosity,
+    /// Whether the response text printed last lacks a trailing newline
+    open_line: bool,
+    /// Tool name by call id, for `-v` result lines
+    tool_names: HashMap<String, String>,
+    turn_index: u32,
+    /// Tokens of the responses rendered so far, for `-v` token lines
+    total_input_tokens: u64,
+    total_output_tokens: u64,
+}
+
+impl EventRenderer {
+    pub fn new(json: bool, quiet: bool, verbosity: Verbosity) -> Self {
+        Self { json, quiet, verbosity, ..Default::default() }
+    }
+
+    /// Print `event` the way a live run does as it is emitted, with the
+    /// `-v` tool and token lines it brings.
+    pub fn render(&mut self, event: &KernelEvent) {
+        if self.json {
+            // In JSON mode, all events go to stdout as NDJSON
+            println!("{}", serde_json::to_string(event).unwrap_or_default());
+        }
+        if self.verbosity.events && !matches!(event, KernelEvent::MessageDelta { .. } | KernelEvent::ThinkingDelta { .. }) {
+            eprintln!("[event] {}", serde_json::to_string(event).unwrap_or_default());
+        }
+        match event {
+            KernelEvent::TurnStart { turn_index } => self.turn_index = *turn_index,
+            KernelEvent::MessageDelta { content_delta } if !self.json && !self.quiet => {
+                print!("{}", content_delta);
+                io::stdout().flush().ok();
+            }
+            KernelEvent::MessageEnd { input_tokens, output_tokens, .. } => {
+                self.total_input_tokens += input_tokens;
+                self.total_output_tokens += output_tokens;
+                if self.verbosity.tokens {
+                    eprintln!(
+                        "[tokens] turn {}: in={} out={} (session in={} out={})",
+                        self.turn_index, input_tokens, output_tokens,
+                        self.total_input_tokens, self.total_output_tokens
+                    );
+                }
+            }
+            KernelEvent::ToolCall { id, name, args } => {
+                self.tool_names.insert(id.clone(), name.clone());
+                if self.verbosity.tools {
+                    eprintln!("[tool] {} {}", name, verbosity::preview(&args.to_string(), 200));
+                }
+            }
+            KernelEvent::ToolResult { id, output, is_error } if self.verbosity.tools => {
+                let name = self.tool_names.get(id).map_or("?", String::as_str);
+                let status = if *is_error { "error" } else { "ok" };
+                eprintln!("[tool] {} -> {}: {}", name, status, verbosity::preview(output, 200));
+            }
+            KernelEvent::AgentEnd { total_input_tokens, total_output_tokens, total_cost_usd, .. } if self.verbosity.tokens => {
+                eprintln!(
+                    "[tokens] session total: in={} out={} cost=${:.4}",
+                    total_input_tokens, total_output_tokens, total_cost_usd
+                );
+            }
+            _ => {}
+        }
+    }
+
+    /// Print a recorded event the way [`render`](Self::render) did live,
+    /// plus the line break the run loop prints after a response. Tool
+    /// durations aren't recorded, so `timing` output is not reproduced.
+    pub fn render_recorded(&mut self, event: &KernelEvent) {
+        match event {
+            KernelEvent::MessageDelta { content_delta } if !content_delta.is_empty() => {
+                self.open_line = !content_delta.ends_with('\n');
+            }
+            KernelEvent::MessageEnd { .. } if std::mem::take(&mut self.open_line) && !self.json && !self.quiet => {
+                println!();
+            }
+            _ => {}
+        }
+        self.render(event);
+    }
+}
+
+/// Which part of a stored session `bedrock replay` shows, and how fast.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReplayOptions {
+    /// Playback speed relative to the recording: 1.0 is real time, 2.0 twice
+    /// as fast. `None` (or zero) prints everything without pausing.
+    pub speed: Option<f64>,
+    /// Skip the events before this turn starts
+    pub from_turn: Option<u32>,
+}
+
+/// Render the stored events of `session_id` in order. Returns how many
+/// events were rendered.
+pub async fn replay(store: &StateStore, session_id: &str, options: ReplayOptions, renderer: &mut EventRenderer) -> Result<usize> {
+    let rows = store.get_events(session_id).await?;
+    let speed = options.speed.filter(|s| *s > 0.0);
+    let mut started = options.from_turn.is_none_or(|turn| turn == 0);
+    let mut last_time: Option<f64> = None;
+    let mut rendered = 0;
+    for row in &rows {
+        let event = KernelEvent::from_row(row)?;
+        if let (false, Some(from_turn), KernelEvent::TurnStart { turn_index }) = (started, options.from_turn, &event) {
+            started = *turn_index >= from_turn;
+        }
+        if !started {
+            continue;
+        }
+        let time = parse_timestamp(&row.created_at);
+        if let (Some(speed), Some(last), Some(time)) = (speed, last_time, time) {
+            let gap = Duration::from_secs_f64((time - last).max(0.0) / speed);
+            tokio::time::sleep(gap.min(MAX_REPLAY_PAUSE)).await;
+        }
+        last_time = time.or(last_time);
+        renderer.render_recorded(&event);
+        rendered += 1;
+    }
+    Ok(rendered)
+}
+
+/// Seconds since the Unix epoch of a stored timestamp
+/// (`YYYY-MM-DD HH:MM:SS[.SSS]`, UTC).
+fn parse_timestamp(s: &str) -> Option<f64> {
+    let (date, time) = s.split_once(' ')?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let mut time = time.splitn(3, ':').map(|p| p.parse::<f64>());
+    let (hours, minutes, seconds) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+
+    // Days from civil date (Howard Hinnant's algorithm)
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let secs = days as f64 * 86_400.0 + hours * 3_600.0 + minutes * 60.0 + seconds;
+    (secs >= 0.0).then_some(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-01 00:00:00"), Some(0.0));
+        assert_eq!(parse_timestamp("2000-03-01 00:00:01"), Some(951_868_801.0));
+        assert_eq!(parse_timestamp("2026-01-01 12:30:05"), Some(1_767_270_605.0));
+        assert_eq!(parse_timestamp("2026-01-01 12:30:05.250"), Some(1_767_270_605.25));
+        assert_eq!(parse_timestamp("not a time"), None);
+    }
+
+    #[tokio::test]
+    async fn test_replay_from_turn() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let store = StateStore::open(tmp.path().join("test.db").to_str().unwrap()).await?;
+        let events = [
+            KernelEvent::TurnStart { turn_index: 0 },
+            KernelEvent::MessageDelta { content_delta: "first".to_string() },
+            KernelEvent::TurnStart { turn_index: 1 },
+            KernelEvent::MessageDelta { content_delta: "second".to_string() },
+            KernelEvent::TurnEnd { turn_index: 1, has_tool_calls: false },
+        ];
+        for event in &events {
+            store.insert_event("s1", event.event_type(), &serde_json::to_value(event)?).await?;
+        }
+
+        let mut renderer = EventRenderer::new(false, true, Verbosity::default());
+        assert_eq!(replay(&store, "s1", ReplayOptions::default(), &mut renderer).await?, 5);
+        let options = ReplayOptions { from_turn: Some(1), ..Default::default() };
+        assert_eq!(replay(&store, "s1", options, &mut renderer).await?, 3);
+        let options = ReplayOptions { from_turn: Some(2), ..Default::default() };
+        assert_eq!(replay(&store, "s1", options, &mut renderer).await?, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+//! Retention floor for context trimming and compaction.
+//!
+//! `kernel.min_retained_turns = K` guarantees that the last K full turns of the
+//! history reach the model verbatim. A turn starts at a user message that is not
+//! purely tool results and runs until the next such message, so a turn's tool
+//! calls and results are always kept together.
+
+use crate::inference::pricing;
+use crate::inference::provider::{InferenceContent, InferenceMessage, InferenceRole};
+
+fn starts_turn(message: &InferenceMessage) -> bool {
+    message.role == InferenceRole::User
+        && message.content.iter().any(|c| !matches!(c, InferenceContent::ToolResult { .. }))
+}
+
+/// Index of the first message in the protected tail (the last `min_turns`
+/// turns). Returns `history.len()` when nothing is protected.
+pub fn retained_tail_start(history: &[InferenceMessage], min_turns: u32) -> usize {
+    if min_turns == 0 {
+        return history.len();
+    }
+    let mut seen = 0;
+    for (i, message) in history.iter().enumerate().rev() {
+        if starts_turn(message) {
+            seen += 1;
+            if seen == min_turns {
+                return i;
+            }
+        }
+    }
+    // Fewer turns than the floor: everything is protected
+    0
+}
+
+/// Whether `edited` still ends with the protected tail of `original`.
+pub fn tail_preserved(original: &[InferenceMessage], edited: &[InferenceMessage], min_turns: u32) -> bool {
+    let tail = &original[retained_tail_start(original, min_turns)..];
+    // Compared via serde so no PartialEq is required on SDK message types
+    edited.len() >= tail.len()
+        && serde_json::to_value(&edited[edited.len() - tail.len()..]).ok() == serde_json::to_value(tail).ok()
+}
+
+/// Estimated tokens of the protected tail plus the system prompt.
+pub fn floor_tokens(history: &[InferenceMessage], min_turns: u32, system_prompt: &str) -> u64 {
+    let tail = &history[retained_tail_start(history, min_turns)..];
+    let tail_json = serde_json::to_string(tail).unwrap_or_default();
+    pricing::estimate_tokens(system_prompt) + pricing::estimate_tokens(&tail_json)
+}
+
+/// Drop the older half of the turns before the protected tail, for a retry
+/// after the provider reported a context overflow. The current turn is always
+/// kept. Returns the number of messages removed (0 when nothing can go).
+pub fn trim_for_overflow(history: &mut Vec<InferenceMessage>, min_turns: u32) -> usize {
+    let tail_start = retained_tail_start(history, min_turns.max(1));
+    let starts: Vec<usize> = (1..tail_start).filter(|&i| starts_turn(&history[i])).collect();
+    // Whole turns only, so tool calls stay next to their results
+    let cut = starts.get(starts.len() / 2).copied().unwrap_or(tail_start);
+    history.drain(..cut);
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(text: &str) -> InferenceMessage {
+        InferenceMessage { role: InferenceRole::User, content: vec![InferenceContent::Text { text: text.to_string() }], tool_call_id: None }
+    }
+
+    fn assistant(text: &str) -> InferenceMessage {
+        InferenceMessage { role: InferenceRole::Assistant, content: vec![InferenceContent::Text { text: text.to_string() }], tool_call_id: None }
+    }
+
+    fn tool_result(id: &str) -> InferenceMessage {
+        InferenceMessage {
+            role: InferenceRole::User,
+            content: vec![InferenceContent::ToolResult { tool_use_id: id.to_string(), content: "ok".to_string(), is_error: false }],
+            tool_call_id: None,
+        }
+    }
+
+    #[test]
+    fn test_retained_tail_start_counts_whole_turns() {
+        let history = vec![user("a"), assistant("1"), user("b"), assistant("call"), tool_result("t"), assistant("2")];
+        assert_eq!(retained_tail_start(&history, 0), 6);
+        // Tool results don't start a turn
+        assert_eq!(retained_tail_start(&history, 1), 2);
+        assert_eq!(retained_tail_start(&history, 2), 0);
+        assert_eq!(retained_tail_start(&history, 5), 0);
+    }
+
+    #[test]
+    fn test_tail_preserved() {
+        let history = vec![user("a"), assistant("1"), user("b"), assistant("2")];
+        let compacted = vec![user("summary of a"), user("b"), assistant("2")];
+        assert!(tail_preserved(&history, &compacted, 1));
+        assert!(!tail_preserved(&history, &compacted, 2));
+        assert!(!tail_preserved(&history, &[assistant("2")], 1));
+        assert!(tail_preserved(&history, &[], 0));
+    }
+
+    #[test]
+    fn test_trim_for_overflow_drops_older_turns() {
+        let mut history = vec![user("a"), assistant("1"), user("b"), assistant("call"), tool_result("t"), assistant("2"), user("c"), assistant("3"), user("d")];
+        // Three turns precede the current one; the older two go
+        assert_eq!(trim_for_overflow(&mut history, 0), 6);
+        assert!(tail_preserved(&[user("c"), assistant("3"), user("d")], &history, 2));
+        assert_eq!(trim_for_overflow(&mut history, 0), 2);
+        // Only the current turn is left
+        assert_eq!(trim_for_overflow(&mut history, 0), 0);
+        assert_eq!(history.len(), 1);
+
+        // The retention floor is honored
+        let mut history = vec![user("a"), assistant("1"), user("b"), assistant("2"), user("c")];
+        assert_eq!(trim_for_overflow(&mut history, 2), 2);
+        assert_eq!(trim_for_overflow(&mut history, 2), 0);
+        assert_eq!(history.len(), 3);
+    }
+}
@@ -4,18 +4,165 @@ use tokio::sync::{Mutex, mpsc};
 use tokio::task::JoinHandle;
 use mcp_sdk::client::McpClient;
 use mcp_sdk::transport::StdioTransport;
+use serde::{Deserialize, Serialize};
 
-use crate::inference::provider::InferenceMessage;
+use crate::inference::provider::{InferenceContent, InferenceMessage, InferenceRole};
 use crate::kernel::event::KernelEvent;
 
+/// Longest session name accepted or generated.
+pub const MAX_SESSION_NAME_LEN: usize = 48;
+
+/// Words of the first prompt used for a generated session name.
+const SLUG_WORDS: usize = 6;
+
+/// Longest generated name, leaving room for a `-N` suffix when it is taken.
+const MAX_SLUG_LEN: usize = MAX_SESSION_NAME_LEN - 8;
+
+/// Check a chosen session name: lowercase letters, digits and single
+/// hyphens, e.g. `fix-login-bug`. Names that parse as a session id are
+/// refused, since they would shadow that id wherever a name is accepted.
+pub fn validate_session_name(name: &str) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !name.is_empty() && name.len() <= MAX_SESSION_NAME_LEN,
+        "session name must be 1 to {} characters",
+        MAX_SESSION_NAME_LEN
+    );
+    anyhow::ensure!(
+        name.split('-').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())),
+        "session name '{}' may only contain lowercase letters, digits and single hyphens",
+        name
+    );
+    anyhow::ensure!(
+        uuid::Uuid::parse_str(name).is_err(),
+        "session name '{}' looks like a session id",
+        name
+    );
+    Ok(())
+}
+
+/// Session name generated from a prompt: its first few words, lowercased
+/// and hyphenated, or `session-<random>` if it has none.
+pub fn session_slug(prompt: &str) -> String {
+    let mut slug = String::new();
+    let words = prompt
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .take(SLUG_WORDS);
+    for word in words {
+        let word = word.to_ascii_lowercase();
+        if slug.len() + word.len() + 1 > MAX_SLUG_LEN {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word);
+    }
+    if slug.is_empty() {
+        slug = format!("session-{}", &uuid::Uuid::new_v4().simple().to_string()[..8]);
+    } else if uuid::Uuid::parse_str(&slug).is_ok() {
+        slug.insert_str(0, "session-");
+    }
+    slug
+}
+
+/// A tool call and the result it produced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LastToolCall {
+    pub id: String,
+    pub name: String,
+    pub args: serde_json::Value,
+    /// Result of the call that actually ran, without any repeat notice
+    pub content: String,
+}
+
+impl LastToolCall {
+    /// Whether a call to `name` with `args` repeats this one exactly.
+    pub fn is_repeated_by(&self, name: &str, args: &serde_json::Value) -> bool {
+        self.name == name && self.args == *args
+    }
+}
+
+/// Texts of queued tasks the harness or kernel added, as opposed to the
+/// user's prompts. The stored user message of each is marked `injected`
+/// when it runs, so a replay sends only the user's prompts.
+#[derive(Debug, Clone, Default)]
+pub struct InjectedTasks(Arc<std::sync::Mutex<Vec<String>>>);
+
+impl InjectedTasks {
+    /// Record that `task` was queued by the harness or kernel.
+    pub fn mark(&self, task: &str) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).push(task.to_string());
+    }
+
+    /// Whether `task` was queued by the harness or kernel, forgetting one mark.
+    pub fn take(&self, task: &str) -> bool {
+        let mut tasks = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match tasks.iter().position(|t| t == task) {
+            Some(i) => {
+                tasks.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forget every mark, e.g. when the queue is cleared.
+    pub fn clear(&self) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+/// Why the most recent run of a session stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// The prompt queue drained normally
+    Completed,
+    /// A task hit `kernel.max_turns`
+    MaxTurns,
+    /// A harness hook rejected continuation
+    HarnessRejected,
+    /// The run failed with an error
+    Error,
+    /// The user interrupted the session
+    Interrupted,
+    /// The embedding host aborted the run
+    Aborted,
+    /// Estimated spend reached `limits.max_cost_usd` / `kernel.cost_budget_usd`
+    CostBudgetExceeded,
+    /// Session tokens went over `limits.max_input_tokens` or `limits.max_output_tokens`
+    LimitExceeded,
+    /// The REPL received no input for `repl.idle_timeout` minutes
+    IdleTimeout,
+    /// More than `kernel.max_consecutive_tool_errors` tool calls failed in a row
+    ToolErrorStorm,
+}
+
 /// Holds the state of an active agent session.
 pub struct SessionState {
     pub id: String,
+    /// Human-readable name, usable in place of the id
+    pub name: Option<String>,
     pub history: Vec<InferenceMessage>,
     pub queue: Arc<Mutex<VecDeque<String>>>,
+    /// Queued tasks that didn't come from the user
+    pub injected_tasks: InjectedTasks,
     pub turn_index: u32,
     pub total_input_tokens: u64,
     pub total_output_tokens: u64,
+    /// Estimated spend in USD from the built-in pricing table (0 for unpriced models)
+    pub total_cost_usd: f64,
+    /// Why the last run stopped (set by the kernel or the embedding caller)
+    pub stop_reason: Option<StopReason>,
+    /// Tool executions that have failed in a row, reset by a successful one
+    pub consecutive_tool_errors: u32,
+    /// Most recent tool call, for `kernel.dedup_tool_calls`
+    pub last_tool_call: Option<LastToolCall>,
+    /// Session that delegated this one, for sub-agents
+    pub parent_session: Option<String>,
+    /// Tools the user approved for the rest of the session ("always")
+    pub approved_tools: std::collections::HashSet<String>,
     pub mcp_clients: Vec<Arc<McpClient<StdioTransport>>>,
     // Event channel for this session
     pub event_tx: mpsc::UnboundedSender<(String, KernelEvent)>,
@@ -28,15 +175,77 @@ impl SessionState {
         let (tx, rx) = mpsc::unbounded_channel();
         Self {
             id: uuid::Uuid::new_v4().to_string(),
+            name: None,
             history: Vec::new(),
             queue: Arc::new(Mutex::new(VecDeque::new())),
+            injected_tasks: InjectedTasks::default(),
             turn_index: 0,
             total_input_tokens: 0,
             total_output_tokens: 0,
+            total_cost_usd: 0.0,
+            stop_reason: None,
+            consecutive_tool_errors: 0,
+            last_tool_call: None,
+            parent_session: None,
+            approved_tools: Default::default(),
             mcp_clients: Vec::new(),
             event_tx: tx,
             event_rx: Some(Arc::new(Mutex::new(Some(rx)))),
             event_task: Some(Arc::new(Mutex::new(None))),
         }
     }
+
+    /// Text of the last assistant message, if there is one.
+    pub fn final_answer(&self) -> Option<String> {
+        let message = self.history.iter().rev().find(|m| m.role == InferenceRole::Assistant)?;
+        let text: Vec<&str> = message
+            .content
+            .iter()
+            .filter_map(|c| match c {
+                InferenceContent::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        Some(text.join("\n"))
+    }
+
+    /// Close the session's event channel and wait, up to `timeout`, for the
+    /// background writer to persist the events still queued.
+    pub async fn flush_events(self, timeout: std::time::Duration) {
+        let task = self.event_task.clone();
+        drop(self);
+        let handle = match task {
+            Some(task) => task.lock().await.take(),
+            None => None,
+        };
+        if let Some(handle) = handle {
+            let _ = tokio::time::timeout(timeout, handle).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_names() {
+        assert_eq!(session_slug("Fix the bug in utils.rs, please!"), "fix-the-bug-in-utils-rs");
+        assert_eq!(session_slug("  Explain   `Kernel::run` to me like I'm five"), "explain-kernel-run-to-me-like");
+        let long = session_slug(&"abcdefghijklmnopqrstuvwxyz ".repeat(3));
+        assert_eq!(long, "abcdefghijklmnopqrstuvwxyz");
+        let random = session_slug("¿¡ 日本 !?");
+        assert!(random.starts_with("session-") && random.len() == 16);
+
+        for name in ["fix-login-bug", "a", "v2"] {
+            assert!(validate_session_name(name).is_ok(), "{}", name);
+        }
+        let id = uuid::Uuid::new_v4().to_string();
+        for name in ["", "Fix", "fix--bug", "-fix", "fix_bug", &"a".repeat(49), &id] {
+            assert!(validate_session_name(name).is_err(), "{}", name);
+        }
+        // Generated names are always valid
+        assert!(validate_session_name(&session_slug("Hello, World")).is_ok());
+        assert_eq!(session_slug(&id), format!("session-{}", id));
+    }
 }
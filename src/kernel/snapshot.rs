@@ -0,0 +1,163 @@
+//! Session snapshots for fast resume.
+//!
+//! With `persistence.snapshot_interval_turns = N` the kernel serializes the
+//! live session (history, counters, turn index, model and provider) into the
+//! `session_snapshots` table every N turns. Resuming loads the snapshot and
+//! replays only the messages written after it; a missing or incompatible
+//! snapshot falls back to replaying every message.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::inference::provider::{InferenceContent, InferenceMessage, InferenceRole};
+use crate::kernel::session::SessionState;
+use crate::persistence::state::MessageRow;
+
+/// Bump when the snapshot layout changes; older snapshots are then ignored.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Serialized form of a [`SessionState`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub history: Vec<InferenceMessage>,
+    pub turn_index: u32,
+    pub total_input_tokens: u64,
+    pub total_output_tokens: u64,
+    pub total_cost_usd: f64,
+    pub model: String,
+    pub provider: String,
+}
+
+impl SessionSnapshot {
+    pub fn capture(session: &SessionState, model: &str, provider: &str) -> Self {
+        Self {
+            history: session.history.clone(),
+            turn_index: session.turn_index,
+            total_input_tokens: session.total_input_tokens,
+            total_output_tokens: session.total_output_tokens,
+            total_cost_usd: session.total_cost_usd,
+            model: model.to_string(),
+            provider: provider.to_string(),
+        }
+    }
+
+    /// Copy the snapshot's state into `session`.
+    pub fn restore(self, session: &mut SessionState) {
+        session.history = self.history;
+        session.turn_index = self.turn_index;
+        session.total_input_tokens = self.total_input_tokens;
+        session.total_output_tokens = self.total_output_tokens;
+        session.total_cost_usd = self.total_cost_usd;
+    }
+}
+
+/// Rebuild history messages from `messages` rows. Partial rows (a stream that
+/// never finished) are skipped, as are rows whose content is malformed, with a
+/// warning, so one corrupt row doesn't make the whole session unresumable.
+/// Skipping a row can unpair tool calls and results; see
+/// [`drop_unpaired_tool_blocks`].
+pub fn history_from_rows(rows: &[MessageRow]) -> Vec<InferenceMessage> {
+    rows.iter()
+        .filter(|row| !row.partial)
+        .filter_map(|row| {
+            let content: Vec<InferenceContent> = match serde_json::from_value(row.content_json()?) {
+                Ok(content) => content,
+                Err(e) => {
+                    warn!(id = row.id, error = %e, "Skipping message with unrecognized content");
+                    return None;
+                }
+            };
+            let role = if row.role == "assistant" { InferenceRole::Assistant } else { InferenceRole::User };
+            Some(InferenceMessage { role, content, tool_call_id: None })
+        })
+        .collect()
+}
+
+/// Drop tool calls without a result and results without a call, and any
+/// message left empty, so a skipped row or a snapshot boundary doesn't leave
+/// half of a `tool_use`/`tool_result` pair that providers would reject.
+/// Returns how many blocks were dropped.
+pub fn drop_unpaired_tool_blocks(history: &mut Vec<InferenceMessage>) -> usize {
+    let blocks = || history.iter().flat_map(|m| m.content.iter());
+    let calls: HashSet<String> = blocks().filter_map(|c| match c {
+        InferenceContent::ToolUse { id, .. } => Some(id.clone()),
+        _ => None,
+    }).collect();
+    let results: HashSet<String> = blocks().filter_map(|c| match c {
+        InferenceContent::ToolResult { tool_use_id, .. } => Some(tool_use_id.clone()),
+        _ => None,
+    }).collect();
+
+    let mut dropped = 0;
+    for message in history.iter_mut() {
+        let before = message.content.len();
+        message.content.retain(|c| match c {
+            InferenceContent::ToolUse { id, .. } => results.contains(id),
+            InferenceContent::ToolResult { tool_use_id, .. } => calls.contains(tool_use_id),
+            _ => true,
+        });
+        dropped += before - message.content.len();
+    }
+    history.retain(|m| !m.content.is_empty());
+    dropped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(id: i64, role: &str, content: &str, partial: bool) -> MessageRow {
+        MessageRow {
+            id,
+            session_id: "s".to_string(),
+            turn_index: 0,
+            role: role.to_string(),
+            content: content.to_string(),
+            token_count: None,
+            created_at: String::new(),
+            partial,
+            finish_reason: None,
+        }
+    }
+
+    #[test]
+    fn test_history_from_rows() {
+        let rows = vec![
+            row(1, "user", r#"[{"type":"text","text":"hi"}]"#, false),
+            row(2, "assistant", r#"[{"type":"tool_use","id":"t1","name":"read_file","input":{}}]"#, false),
+            row(3, "tool_result", r#"[{"type":"tool_result","tool_use_id":"t1","content":"ok","is_error":false}]"#, false),
+            row(4, "assistant", r#"[{"type":"text","text":"cut"}]"#, true),
+        ];
+        let history = history_from_rows(&rows);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[1].role, InferenceRole::Assistant);
+        assert_eq!(history[2].role, InferenceRole::User);
+        assert!(matches!(&history[2].content[0], InferenceContent::ToolResult { tool_use_id, .. } if tool_use_id == "t1"));
+
+        // Corrupt rows are dropped rather than failing the whole history
+        let rows = vec![row(5, "user", "not json", false), row(6, "user", r#"[{"type":"bogus"}]"#, false), rows[0].clone()];
+        assert_eq!(history_from_rows(&rows).len(), 1);
+    }
+
+    #[test]
+    fn test_drop_unpaired_tool_blocks() {
+        let rows = vec![
+            row(1, "user", r#"[{"type":"text","text":"hi"}]"#, false),
+            row(2, "assistant", r#"[{"type":"text","text":"reading"},{"type":"tool_use","id":"t1","name":"read_file","input":{}}]"#, false),
+            // The result of t1 is corrupt, so t1 must go too
+            row(3, "tool_result", "not json", false),
+            row(4, "assistant", r#"[{"type":"tool_use","id":"t2","name":"read_file","input":{}}]"#, false),
+            row(5, "tool_result", r#"[{"type":"tool_result","tool_use_id":"t2","content":"ok","is_error":false}]"#, false),
+            // Its call was never stored
+            row(6, "tool_result", r#"[{"type":"tool_result","tool_use_id":"t3","content":"ok","is_error":false}]"#, false),
+        ];
+        let mut history = history_from_rows(&rows);
+        assert_eq!(drop_unpaired_tool_blocks(&mut history), 2);
+        assert_eq!(history.len(), 4);
+        assert_eq!(history[1].content, vec![InferenceContent::Text { text: "reading".to_string() }]);
+        assert!(matches!(&history[2].content[0], InferenceContent::ToolUse { id, .. } if id == "t2"));
+        assert!(matches!(&history[3].content[0], InferenceContent::ToolResult { tool_use_id, .. } if tool_use_id == "t2"));
+    }
+}
@@ -0,0 +1,183 @@
+//! System prompt templating.
+//!
+//! `agent.system_prompt` is rendered against a [`PromptContext`] at the start
+//! of every turn. The default `simple` engine replaces `{{ path }}`
+//! placeholders such as `{{ session.id }}` or `{{ usage.total_tokens }}` and
+//! leaves anything it can't resolve untouched. With
+//! `agent.prompt_template = "jinja"` the prompt is a minijinja template
+//! instead, so it can branch and loop over tools and memories.
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::kernel::config::PromptTemplate;
+
+/// Name the template is registered under; shows up in minijinja errors.
+const TEMPLATE_NAME: &str = "system_prompt";
+
+/// Values available to the system prompt template.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PromptContext {
+    pub session: SessionInfo,
+    pub model: String,
+    pub provider: String,
+    /// Registered tools, sorted by name
+    pub tools: Vec<ToolInfo>,
+    /// Most recent memories stored for the session, oldest first
+    pub memories: Vec<String>,
+    pub usage: UsageInfo,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub turn_index: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ToolInfo {
+    pub name: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageInfo {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub total_tokens: u64,
+    pub cost_usd: f64,
+}
+
+/// Whether `template` contains anything a template engine would act on.
+pub fn has_markup(template: &str) -> bool {
+    template.contains("{{") || template.contains("{%") || template.contains("{#")
+}
+
+/// Check that `template` parses with `engine`, so syntax errors surface when
+/// the config is loaded rather than on the first turn.
+pub fn check(engine: PromptTemplate, template: &str) -> Result<()> {
+    match engine {
+        PromptTemplate::Simple => Ok(()),
+        PromptTemplate::Jinja => {
+            environment().template_from_named_str(TEMPLATE_NAME, template).map_err(template_error)?;
+            Ok(())
+        }
+    }
+}
+
+/// Render `template` against `ctx` with `engine`.
+pub fn render(engine: PromptTemplate, template: &str, ctx: &PromptContext) -> Result<String> {
+    match engine {
+        PromptTemplate::Simple => Ok(render_simple(template, &serde_json::to_value(ctx)?)),
+        PromptTemplate::Jinja => {
+            let env = environment();
+            let compiled = env.template_from_named_str(TEMPLATE_NAME, template).map_err(template_error)?;
+            compiled.render(ctx).map_err(template_error)
+        }
+    }
+}
+
+fn environment() -> minijinja::Environment<'static> {
+    let mut env = minijinja::Environment::new();
+    // Catch misspelled variables, while `{% if memories %}`-style checks still work
+    env.set_undefined_behavior(minijinja::UndefinedBehavior::SemiStrict);
+    env
+}
+
+fn template_error(err: minijinja::Error) -> anyhow::Error {
+    let detail = err.detail().map(|d| format!(": {}", d)).unwrap_or_default();
+    match err.line() {
+        Some(line) => anyhow::anyhow!("agent.system_prompt template error on line {}: {}{}", line, err.kind(), detail),
+        None => anyhow::anyhow!("agent.system_prompt template error: {}{}", err.kind(), detail),
+    }
+}
+
+/// Replace each `{{ path }}` that resolves to a value in `ctx`.
+fn render_simple(template: &str, ctx: &Value) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else { break };
+        let end = start + 2 + len + 2;
+        out.push_str(&rest[..start]);
+        let path = rest[start + 2..end - 2].trim();
+        match path.split('.').try_fold(ctx, |value, key| value.get(key)).and_then(display) {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[start..end]),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Scalars render as text; lists render as a comma-separated list of their
+/// items (or of the items' `name`, for tools).
+fn display(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Array(items) => Some(
+            items
+                .iter()
+                .filter_map(|item| display(item.get("name").unwrap_or(item)))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> PromptContext {
+        PromptContext {
+            session: SessionInfo { id: "s1".to_string(), turn_index: 3 },
+            model: "gpt-4o".to_string(),
+            provider: "openai".to_string(),
+            tools: vec![
+                ToolInfo { name: "read_file".to_string(), description: "Read a file".to_string() },
+                ToolInfo { name: "shell_exec".to_string(), description: "Run a command".to_string() },
+            ],
+            memories: vec!["prefers tabs".to_string()],
+            usage: UsageInfo { input_tokens: 100, output_tokens: 20, total_tokens: 120, cost_usd: 0.5 },
+        }
+    }
+
+    #[test]
+    fn test_simple_substitution() {
+        let out = render(
+            PromptTemplate::Simple,
+            "Session {{ session.id }} on {{model}} ({{ usage.total_tokens }} tokens). Tools: {{ tools }}. Keep {{ unknown }} and {{ open",
+            &context(),
+        )
+        .unwrap();
+        assert_eq!(out, "Session s1 on gpt-4o (120 tokens). Tools: read_file, shell_exec. Keep {{ unknown }} and {{ open");
+    }
+
+    #[test]
+    fn test_jinja_conditionals_and_loops() {
+        let template = "You have:\n{% for tool in tools %}- {{ tool.name }}: {{ tool.description }}\n{% endfor %}{% if memories %}Remember: {{ memories | join(\"; \") }}{% endif %}";
+        let out = render(PromptTemplate::Jinja, template, &context()).unwrap();
+        assert_eq!(out, "You have:\n- read_file: Read a file\n- shell_exec: Run a command\nRemember: prefers tabs");
+
+        let empty = render(PromptTemplate::Jinja, "{% if memories %}x{% endif %}ok", &PromptContext::default()).unwrap();
+        assert_eq!(empty, "ok");
+    }
+
+    #[test]
+    fn test_jinja_errors_report_line() {
+        let err = check(PromptTemplate::Jinja, "line one\nline two\n{{ model + }}").unwrap_err();
+        assert!(err.to_string().contains("line 3"), "{}", err);
+
+        let err = render(PromptTemplate::Jinja, "ok\n{{ sesion.id }}", &context()).unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{}", err);
+
+        // The simple engine has no syntax to get wrong
+        assert!(check(PromptTemplate::Simple, "{% if %}").is_ok());
+    }
+}
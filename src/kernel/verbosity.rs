@@ -0,0 +1,132 @@
+use anyhow::Result;
+use std::str::FromStr;
+
+/// Categories of diagnostic output the Kernel prints to stderr.
+///
+/// Independent of the tracing log level: these are for a human watching
+/// an interactive session, not for log files. The default is quiet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Verbosity {
+    /// Kernel events (type and payload), excluding streaming deltas
+    pub events: bool,
+    /// Tool calls with their arguments and a preview of the result
+    pub tools: bool,
+    /// Token usage per turn and running session totals
+    pub tokens: bool,
+    /// Turn and tool execution durations
+    pub timing: bool,
+}
+
+/// Valid category names for `--show`.
+pub const CATEGORIES: &[&str] = &["events", "tools", "tokens", "timing"];
+
+impl Verbosity {
+    /// Map a `-v` count to categories: `-v` shows tools, `-vv` adds tokens
+    /// and timing, `-vvv` adds every event.
+    pub fn from_level(level: u8) -> Self {
+        Self {
+            tools: level >= 1,
+            tokens: level >= 2,
+            timing: level >= 2,
+            events: level >= 3,
+        }
+    }
+
+    /// Enable every category.
+    pub fn all() -> Self {
+        Self::from_level(u8::MAX)
+    }
+
+    /// Union of two verbosity settings.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            events: self.events || other.events,
+            tools: self.tools || other.tools,
+            tokens: self.tokens || other.tokens,
+            timing: self.timing || other.timing,
+        }
+    }
+
+    pub fn is_quiet(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl FromStr for Verbosity {
+    type Err = anyhow::Error;
+
+    /// Parse a comma-separated category list, e.g. `tools,timing` or `all`.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut v = Self::default();
+        for category in s.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            match category {
+                "events" => v.events = true,
+                "tools" => v.tools = true,
+                "tokens" => v.tokens = true,
+                "timing" => v.timing = true,
+                "all" => v = Self::all(),
+                other => anyhow::bail!(
+                    "Unknown output category '{}' (expected one of: {}, all)",
+                    other,
+                    CATEGORIES.join(", ")
+                ),
+            }
+        }
+        Ok(v)
+    }
+}
+
+/// Shorten `s` to at most `max` characters for single-line display.
+pub(crate) fn preview(s: &str, max: usize) -> String {
+    let flat = s.replace('\n', "\\n");
+    match flat.char_indices().nth(max) {
+        Some((idx, _)) => format!("{}…", &flat[..idx]),
+        None => flat,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_is_quiet() {
+        assert!(Verbosity::default().is_quiet());
+        assert!(Verbosity::from_level(0).is_quiet());
+    }
+
+    #[test]
+    fn test_levels_are_cumulative() {
+        let v1 = Verbosity::from_level(1);
+        assert!(v1.tools && !v1.tokens && !v1.timing && !v1.events);
+
+        let v2 = Verbosity::from_level(2);
+        assert!(v2.tools && v2.tokens && v2.timing && !v2.events);
+
+        assert_eq!(Verbosity::from_level(3), Verbosity::all());
+    }
+
+    #[test]
+    fn test_parse_categories() {
+        let v: Verbosity = "events, timing".parse().unwrap();
+        assert!(v.events && v.timing && !v.tools && !v.tokens);
+
+        let v: Verbosity = "all".parse().unwrap();
+        assert_eq!(v, Verbosity::all());
+
+        assert!("tools,bogus".parse::<Verbosity>().is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let v = Verbosity::from_level(1).merge("events".parse().unwrap());
+        assert!(v.tools && v.events && !v.tokens);
+    }
+
+    #[test]
+    fn test_preview_truncates_on_char_boundary() {
+        assert_eq!(preview("short", 10), "short");
+        assert_eq!(preview("a\nb", 10), "a\\nb");
+        assert_eq!(preview("héllo wörld", 5), "héllo…");
+    }
+}
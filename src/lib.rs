@@ -3,3 +3,4 @@ pub mod harness;
 pub mod tools;
 pub mod inference;
 pub mod persistence;
+pub mod server;
@@ -2,10 +2,19 @@ use anyhow::{Context, Result};
 use clap::Parser;
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use bedrock::kernel::config::BedrockConfig;
+use bedrock::kernel::config::{BedrockConfig, ConfigErrors, QueueEmptyPolicy, MODEL_ENV, PROVIDER_ENV};
+use bedrock::kernel::session::StopReason;
+use bedrock::kernel::verbosity::Verbosity;
+use bedrock::inference::credentials::{self, CredentialSource};
+use bedrock::inference::provider::InferenceRole;
+use bedrock::kernel::regression::{self, Recording};
+use bedrock::kernel::render::{self, EventRenderer, ReplayOptions};
 use bedrock::kernel::Kernel;
+use bedrock::persistence::state::{StateStore, ToolStatsFilter, NEAR_DUPLICATE_SIMILARITY};
+use bedrock::persistence::transcript::{self, TranscriptFormat, TranscriptOptions};
+use bedrock::tools::mcp_resources::McpPromptMessage;
 
 /// Bedrock: A single-binary, event-driven LLM execution runtime
 #[derive(Parser, Debug)]
@@ -21,15 +30,35 @@ struct Cli {
     /// Path to log file
     #[arg(long, global = true)]
     log_file: Option<PathBuf>,
+
+    /// Load KEY=VALUE lines from this file into the environment before reading config
+    #[arg(long, global = true)]
+    env_file: Option<PathBuf>,
+
+    /// Let --env-file values replace variables already set in the environment
+    #[arg(long, global = true, requires = "env_file")]
+    env_file_override: bool,
+
+    /// Use this database instead of persistence.database_path (e.g. an older rotated file)
+    #[arg(long, global = true)]
+    db: Option<PathBuf>,
 }
 
 #[derive(clap::Subcommand, Debug)]
 enum Commands {
     /// Run the agent with a prompt
     Run {
-        /// The prompt to send to the LLM
+        /// The prompt to send to the LLM ("-" reads it from stdin)
+        #[arg(long, required_unless_present = "from_stdin", conflicts_with = "from_stdin")]
+        prompt: Option<String>,
+
+        /// Read the whole of stdin as the prompt
+        #[arg(long)]
+        from_stdin: bool,
+
+        /// Split a prompt over kernel.max_prompt_tokens into overlapping queued parts
         #[arg(long)]
-        prompt: String,
+        chunk: bool,
 
         /// Path to bedrock.toml config file
         #[arg(long, default_value = "bedrock.toml")]
@@ -43,13 +72,87 @@ enum Commands {
         #[arg(long)]
         provider: Option<String>,
 
-        /// Show verbose event-level output
-        #[arg(long)]
-        verbose: bool,
+        /// Increase stderr output (-v tools, -vv +tokens/timing, -vvv +events)
+        #[arg(short, long, action = clap::ArgAction::Count)]
+        verbose: u8,
+
+        /// Comma-separated output categories to show: events,tools,tokens,timing,all
+        #[arg(long, value_name = "CATEGORIES")]
+        show: Option<Verbosity>,
 
         /// Output events as NDJSON to stdout
         #[arg(long)]
         json: bool,
+
+        /// Maximum tool calls executed per turn (extra calls are dropped)
+        #[arg(long)]
+        max_tool_calls_per_turn: Option<u32>,
+
+        /// Chat only: register no tools and send none to the provider
+        #[arg(long)]
+        no_tools: bool,
+
+        /// Safe mode: only read-only tools run unless unlocked with --allow or by the harness
+        #[arg(long)]
+        safe: bool,
+
+        /// Unlock a tool in safe mode (repeatable)
+        #[arg(long = "allow", value_name = "TOOL", requires = "safe")]
+        allow: Vec<String>,
+
+        /// Write the final assistant message to this file when the run completes
+        #[arg(long, value_name = "PATH")]
+        answer_file: Option<PathBuf>,
+
+        /// Store nothing: no session, messages or events are written to the database
+        #[arg(long, conflicts_with = "resume")]
+        no_persist: bool,
+
+        /// Name the session (default: a slug of the prompt); usable in place of its id
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Continue a stored session (name or id) instead of starting a new one
+        #[arg(long, value_name = "SESSION")]
+        resume: Option<String>,
+    },
+
+    /// Serve the agent over HTTP: create sessions, post prompts, stream events (SSE)
+    Serve {
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: std::net::SocketAddr,
+
+        /// Override the model from config
+        #[arg(long)]
+        model: Option<String>,
+
+        /// Override the provider from config
+        #[arg(long)]
+        provider: Option<String>,
+    },
+
+    /// Serve the registered tools over MCP (stdio) for other agents and IDEs
+    McpServe {
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+
+        /// Also expose a `run_agent` tool that runs a full agent session on a prompt
+        #[arg(long)]
+        run_agent: bool,
+
+        /// Override the model from config
+        #[arg(long)]
+        model: Option<String>,
+
+        /// Override the provider from config
+        #[arg(long)]
+        provider: Option<String>,
     },
 
     /// Start an interactive REPL session
@@ -66,9 +169,37 @@ enum Commands {
         #[arg(long)]
         provider: Option<String>,
 
-        /// Show verbose event-level output
+        /// Increase stderr output (-v tools, -vv +tokens/timing, -vvv +events)
+        #[arg(short, long, action = clap::ArgAction::Count)]
+        verbose: u8,
+
+        /// Comma-separated output categories to show: events,tools,tokens,timing,all
+        #[arg(long, value_name = "CATEGORIES")]
+        show: Option<Verbosity>,
+
+        /// Maximum tool calls executed per turn (extra calls are dropped)
         #[arg(long)]
-        verbose: bool,
+        max_tool_calls_per_turn: Option<u32>,
+
+        /// Chat only: register no tools and send none to the provider
+        #[arg(long)]
+        no_tools: bool,
+
+        /// Safe mode: only read-only tools run unless unlocked with --allow or by the harness
+        #[arg(long)]
+        safe: bool,
+
+        /// Unlock a tool in safe mode (repeatable)
+        #[arg(long = "allow", value_name = "TOOL", requires = "safe")]
+        allow: Vec<String>,
+
+        /// Name the session (default: a slug of the first prompt); usable in place of its id
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Continue a stored session (name or id) instead of starting a new one
+        #[arg(long, value_name = "SESSION")]
+        resume: Option<String>,
     },
 
     /// Run a specific harness script (for testing)
@@ -88,6 +219,330 @@ enum Commands {
         #[arg(long)]
         provider: Option<String>,
     },
+
+    /// Print the transcript of a stored session
+    Cat {
+        /// Session name or id
+        #[arg(long)]
+        session: String,
+
+        /// Output format: text, markdown or json (one entry per line)
+        #[arg(long, default_value = "text")]
+        format: TranscriptFormat,
+
+        /// Omit tool call arguments
+        #[arg(long)]
+        no_tool_args: bool,
+
+        /// Omit thinking blocks
+        #[arg(long)]
+        no_thinking: bool,
+
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
+
+    /// Re-render a stored session from its event log, as it printed live
+    Replay {
+        /// Session name or id
+        session: String,
+
+        /// Pace events like the recording, this many times faster (default: no pauses)
+        #[arg(long)]
+        speed: Option<f64>,
+
+        /// Start at this turn index
+        #[arg(long)]
+        from_turn: Option<u32>,
+
+        /// Re-run the session through the current harness, with the model and tools
+        /// played back from the recording, and report where it diverges
+        #[arg(long, conflicts_with_all = ["speed", "from_turn"])]
+        deterministic: bool,
+
+        /// Increase stderr output (-v tools, -vv +tokens, -vvv +events)
+        #[arg(short, long, action = clap::ArgAction::Count)]
+        verbose: u8,
+
+        /// Comma-separated output categories to show: events,tools,tokens,timing,all
+        #[arg(long, value_name = "CATEGORIES")]
+        show: Option<Verbosity>,
+
+        /// Output events as NDJSON to stdout
+        #[arg(long)]
+        json: bool,
+
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
+
+    /// Print the raw provider response stored for a session turn
+    ShowRaw {
+        /// Session name or id
+        #[arg(long)]
+        session: String,
+
+        /// Turn index within the session
+        #[arg(long)]
+        turn: u32,
+
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
+
+    /// Import a session and its memories from another Bedrock database
+    Import {
+        /// Path to the database to import from
+        from: PathBuf,
+
+        /// Session id to import (memories only if omitted)
+        #[arg(long)]
+        session: Option<String>,
+
+        /// Re-embed imported memories with the configured embedding provider
+        #[arg(long)]
+        reembed: bool,
+
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
+
+    /// Re-embed memories excluded from search (e.g. imported with a different dimension)
+    Reindex {
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
+
+    /// Check a config file and show the effective model and provider
+    Validate {
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+
+        /// Override the model from config
+        #[arg(long)]
+        model: Option<String>,
+
+        /// Override the provider from config
+        #[arg(long)]
+        provider: Option<String>,
+    },
+
+    /// List, inspect and delete stored sessions
+    Sessions {
+        #[command(subcommand)]
+        command: SessionsCommand,
+    },
+
+    /// Inspect the memory store
+    Memory {
+        #[command(subcommand)]
+        command: MemoryCommand,
+    },
+
+    /// Inspect tools
+    Tools {
+        #[command(subcommand)]
+        command: ToolsCommand,
+    },
+
+    /// Inspect assistant turns
+    Turns {
+        #[command(subcommand)]
+        command: TurnsCommand,
+    },
+
+    /// Replay recorded sessions against the current harness and report behaviour changes
+    Test {
+        /// Directory of recordings, each the output of `bedrock cat --format json` (*.jsonl)
+        dir: PathBuf,
+
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
+
+    /// List the sources (files, URLs, memories) cited by a session's tool outputs
+    Sources {
+        /// Session name or id
+        #[arg(long)]
+        session: String,
+
+        /// Only show sources from this turn
+        #[arg(long)]
+        turn: Option<u32>,
+
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum SessionsCommand {
+    /// Recent sessions, most recently active first, with turn, message and token counts
+    List {
+        /// Maximum number of sessions to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+
+        /// Skip this many of the most recent sessions
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+
+        /// Output JSON instead of a table
+        #[arg(long)]
+        json: bool,
+
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
+
+    /// A session's token totals and counts, followed by its transcript
+    Show {
+        /// Session name or id
+        session: String,
+
+        /// Output JSON (summary and transcript entries) instead of text
+        #[arg(long)]
+        json: bool,
+
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
+
+    /// Token usage and estimated cost of each provider response in a session, with totals
+    Cost {
+        /// Session name or id
+        session: String,
+
+        /// Output JSON instead of a table
+        #[arg(long)]
+        json: bool,
+
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
+
+    /// Copy a session's history before a turn into a new session, to continue it differently
+    Fork {
+        /// Session name or id
+        session: String,
+
+        /// Turn to branch at: the fork keeps the turns before it
+        #[arg(long)]
+        at_turn: u32,
+
+        /// Name the fork
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
+
+    /// Delete a session's messages, events, tool executions and artifacts (memories are kept)
+    Delete {
+        /// Session name or id
+        session: String,
+
+        /// Don't ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
+
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum ToolsCommand {
+    /// Registered tools with their origin (builtin, harness or mcp:<server>) and effect
+    List {
+        /// Output JSON instead of a table
+        #[arg(long)]
+        json: bool,
+
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
+    /// Per-tool call counts, error rates and latency percentiles
+    Stats {
+        /// Only include executions from this session (name or id)
+        #[arg(long)]
+        session: Option<String>,
+
+        /// Only include executions at or after this time (e.g. 2026-01-31 or "2026-01-31 12:00:00", UTC)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only include executions at or before this time
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Output JSON instead of a table
+        #[arg(long)]
+        json: bool,
+
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum MemoryCommand {
+    /// Memory counts per session, content sizes, embedding dimensions, date range and duplicates
+    Stats {
+        /// Only include memories from this session (name or id)
+        #[arg(long)]
+        session: Option<String>,
+
+        /// Output JSON instead of a report
+        #[arg(long)]
+        json: bool,
+
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum TurnsCommand {
+    /// How often assistant turns ended by each finish reason (stop, length, tool_calls, ...)
+    Stats {
+        /// Only include turns from this session (name or id)
+        #[arg(long)]
+        session: Option<String>,
+
+        /// Only include turns at or after this time (e.g. 2026-01-31 or "2026-01-31 12:00:00", UTC)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only include turns at or before this time
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Output JSON instead of a table
+        #[arg(long)]
+        json: bool,
+
+        /// Path to bedrock.toml config file
+        #[arg(long, default_value = "bedrock.toml")]
+        config: PathBuf,
+    },
 }
 
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
@@ -123,68 +578,190 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
     init_tracing(&cli.log_level, cli.log_file)?;
 
+    if let Some(path) = &cli.env_file {
+        let count = bedrock::kernel::env::load_env_file(path, cli.env_file_override)?;
+        tracing::debug!(path = %path.display(), count, "Loaded env file");
+    }
+
+    let db = cli.db;
     match cli.command {
         Commands::Run {
             prompt,
+            from_stdin,
+            chunk,
             config,
             model,
             provider,
-            verbose: _,
+            verbose,
+            show,
             json,
+            max_tool_calls_per_turn,
+            no_tools,
+            safe,
+            allow,
+            answer_file,
+            no_persist,
+            name,
+            resume,
         } => {
+            let prompt = match prompt {
+                Some(p) if !from_stdin && p != "-" => p,
+                _ => read_stdin_prompt()?,
+            };
+
             // Load config
             let mut config =
-                BedrockConfig::from_file(&config).with_context(|| "Failed to load config")?;
+                load_config(&config, db.as_deref())?;
 
             // Apply CLI overrides
-            if let Some(m) = model {
-                config.agent.model = m;
+            if let Some(n) = max_tool_calls_per_turn {
+                config.kernel.max_tool_calls_per_turn = Some(n);
+            }
+            if no_tools {
+                config.tools.enabled = false;
             }
-            if let Some(p) = provider {
-                config.agent.provider = p;
-                // Re-validate after override
-                config.validate()?;
+            if safe {
+                config.tools.safe = true;
+                config.tools.allow.extend(allow);
             }
+            // CLI flag > BEDROCK_MODEL / BEDROCK_PROVIDER > config; re-validates
+            let agent = config.resolve_agent(model, provider)?;
 
             tracing::info!(
                 model = %config.agent.model,
                 provider = %config.agent.provider,
                 workspace = %config.kernel.workspace_root,
                 harness_dir = %config.harness.directory,
-                db = %config.persistence.database_path,
+                db = %config.database_path(),
                 "Config loaded"
             );
 
+            let chunk_limit = match chunk {
+                true => Some(config.kernel.max_prompt_tokens.context("--chunk needs kernel.max_prompt_tokens to be set")?),
+                false => None,
+            };
+
             // Build kernel, initialize state store, and run
-            let mut kernel = Kernel::builder(config).json_mode(json).build()?;
-            kernel.init_state().await?;
-            kernel.init_clients()?;
+            let verbosity = Verbosity::from_level(verbose).merge(show.unwrap_or_default());
+            let mut kernel = Kernel::builder(config)
+                .json_mode(json)
+                .verbosity(verbosity)
+                .pin_agent(agent.overridden())
+                .build()?;
+            if !no_persist {
+                kernel.init_state().await?;
+                kernel.start_maintenance();
+            }
+            kernel.init_clients().await?;
             kernel.init_harness().await?;
             kernel.start_watcher()?;
-            let mut session = kernel.create_session();
-            kernel.run(&mut session, Some(prompt)).await?;
-            kernel.end_session(&mut session).await?;
+            let mut session = match resume {
+                Some(id) => kernel.resume_session(&id).await?,
+                None => kernel.create_session(),
+            };
+            if let Some(name) = name {
+                kernel.name_session(&mut session, &name).await?;
+            }
+            let result = if let Some(limit) = chunk_limit {
+                let parts = bedrock::kernel::chunk::chunk_prompt(&prompt, limit);
+                if parts.len() > 1 {
+                    eprintln!("[bedrock] Prompt split into {} parts", parts.len());
+                }
+                session.queue.lock().await.extend(parts);
+                kernel.run(&mut session, None).await
+            } else {
+                kernel.run(&mut session, Some(prompt)).await
+            };
+            if result.is_err() {
+                session.stop_reason = Some(StopReason::Error);
+            }
+            // Always end the session so on_session_end cleanup runs; the
+            // original run error takes precedence over an end_session error.
+            let end_result = kernel.end_session(&mut session).await;
+            result?;
+            end_result?;
+            if session.stop_reason == Some(StopReason::CostBudgetExceeded) {
+                anyhow::bail!("Cost budget exceeded: ${:.4} spent", session.total_cost_usd);
+            }
+            if session.stop_reason == Some(StopReason::LimitExceeded) {
+                anyhow::bail!(
+                    "Token limit exceeded: {} input / {} output tokens used ([limits])",
+                    session.total_input_tokens, session.total_output_tokens
+                );
+            }
+            if session.stop_reason == Some(StopReason::ToolErrorStorm) {
+                anyhow::bail!(
+                    "Halted after {} consecutive tool errors (kernel.max_consecutive_tool_errors)",
+                    session.consecutive_tool_errors
+                );
+            }
+            if let Some(path) = answer_file {
+                // Only a completed run has an answer worth handing to a pipeline
+                if session.stop_reason != Some(StopReason::Completed) {
+                    anyhow::bail!("No answer written: the run stopped early ({:?})", session.stop_reason);
+                }
+                let answer = session.final_answer().context("No answer written: the run produced no assistant message")?;
+                write_answer_file(&path, &answer)?;
+            }
 
             Ok(())
         }
+        Commands::Serve { config, listen, model, provider } => {
+            let mut config =
+                load_config(&config, db.as_deref())?;
+            config.resolve_agent(model, provider)?;
+
+            let listener = tokio::net::TcpListener::bind(listen)
+                .await
+                .with_context(|| format!("Failed to listen on {}", listen))?;
+            eprintln!("[bedrock] Serving on http://{}", listener.local_addr()?);
+            let shutdown = async {
+                tokio::signal::ctrl_c().await.ok();
+            };
+            bedrock::server::serve(config, listener, shutdown).await
+        }
+        Commands::McpServe { config, run_agent, model, provider } => {
+            let mut config =
+                load_config(&config, db.as_deref())?;
+            config.resolve_agent(model, provider)?;
+
+            // stdin and stdout carry the protocol, so nothing else may use them
+            let mut kernel = Kernel::builder(config).quiet(true).interactive(false).build()?;
+            kernel.init_state().await?;
+            kernel.init_clients().await?;
+            kernel.init_harness().await?;
+            let input = tokio::io::BufReader::new(tokio::io::stdin());
+            bedrock::server::mcp::McpServer::new(kernel, run_agent).serve(input, tokio::io::stdout()).await
+        }
         Commands::Repl {
             config,
             model,
             provider,
             verbose,
+            show,
+            max_tool_calls_per_turn,
+            no_tools,
+            safe,
+            allow,
+            name,
+            resume,
         } => {
             // Load config
             let mut config =
-                BedrockConfig::from_file(&config).with_context(|| "Failed to load config")?;
+                load_config(&config, db.as_deref())?;
 
             // Apply CLI overrides
-            if let Some(m) = model {
-                config.agent.model = m;
+            if let Some(n) = max_tool_calls_per_turn {
+                config.kernel.max_tool_calls_per_turn = Some(n);
+            }
+            if no_tools {
+                config.tools.enabled = false;
             }
-            if let Some(p) = provider {
-                config.agent.provider = p;
-                config.validate()?;
+            if safe {
+                config.tools.safe = true;
+                config.tools.allow.extend(allow);
             }
+            let agent = config.resolve_agent(model, provider)?;
 
             tracing::info!(
                 model = %config.agent.model,
@@ -192,27 +769,49 @@ async fn main() -> Result<()> {
                 "Config loaded (REPL mode)"
             );
 
+            let idle_timeout = config.repl.idle_timeout();
+
             // Build kernel
-            let mut kernel = Kernel::builder(config).build()?; // JSON not supported in REPL yet
+            let verbosity = Verbosity::from_level(verbose).merge(show.unwrap_or_default());
+            let mut kernel = Kernel::builder(config).verbosity(verbosity).pin_agent(agent.overridden()).build()?; // JSON not supported in REPL yet
             kernel.init_state().await?;
-            kernel.init_clients()?;
+            kernel.init_clients().await?;
             kernel.init_harness().await?;
             kernel.start_watcher()?;
+            kernel.start_maintenance();
 
             // Start REPL loop
+            let terminal = TerminalState::save();
             let mut rl = DefaultEditor::new()?;
             tracing::info!("REPL started. Type 'exit' or Ctrl+D to quit.");
-            if !verbose {
+            if verbosity.is_quiet() {
                 println!("Bedrock REPL v{}", env!("CARGO_PKG_VERSION"));
                 println!("Type 'exit' or Ctrl+D to quit. Type '/reload' to reload harness.");
+                println!("Type '/model <name>' or '/provider <name>' to switch for the next turns.");
+                println!("Type '/tools' to list tools (and which are locked in safe mode).");
+                println!("Type '/prompt' to list MCP prompts and '/prompt <name> key=value ...' to send one.");
+                println!("Ctrl+C interrupts a running turn; input typed meanwhile runs once it finishes.");
             }
+            let interrupt = kernel.interrupt_handle();
 
-            // Trigger AgentStart
-            let mut session = kernel.create_session();
-            kernel.run(&mut session, None).await?;
+            // Trigger AgentStart (a resumed session has already started)
+            let mut session = match resume {
+                Some(id) => kernel.resume_session(&id).await?,
+                None => kernel.create_session(),
+            };
+            if let Some(name) = name {
+                kernel.name_session(&mut session, &name).await?;
+            }
+            kernel.run_with_policy(&mut session, None, QueueEmptyPolicy::Stop).await?;
 
             loop {
-                let readline = rl.readline(">> ");
+                let Some((editor, readline)) = read_line(rl, idle_timeout).await? else {
+                    let minutes = idle_timeout.unwrap_or_default().as_secs() / 60;
+                    eprintln!("\n[bedrock] No input for {} min; ending session.", minutes);
+                    session.stop_reason = Some(StopReason::IdleTimeout);
+                    break;
+                };
+                rl = editor;
                 match readline {
                     Ok(line) => {
                         let line = line.trim();
@@ -223,6 +822,27 @@ async fn main() -> Result<()> {
                             break;
                         }
 
+                        if let Some((command, name)) = parse_switch_command(line) {
+                            let result = match (command, name) {
+                                (_, None) => Ok(()),
+                                ("/model", Some(name)) => kernel.switch_agent(&session, Some(name.to_string()), None),
+                                (_, Some(name)) => kernel.switch_agent(&session, None, Some(name.to_string())),
+                            };
+                            match result {
+                                Ok(()) => {
+                                    let agent = &kernel.config().agent;
+                                    println!("[bedrock] Using {} via {}", agent.model, agent.provider);
+                                }
+                                Err(e) => eprintln!("[bedrock] {:#}", e),
+                            }
+                            continue;
+                        }
+
+                        if line.eq_ignore_ascii_case("/tools") {
+                            print!("{}", format_tool_list(&kernel.tools(), kernel.config().tools.safe));
+                            continue;
+                        }
+
                         if line.eq_ignore_ascii_case("/reload") {
                             tracing::info!("Reloading harness...");
                             match kernel.reload_harness().await {
@@ -233,11 +853,70 @@ async fn main() -> Result<()> {
                         }
                         let _ = rl.add_history_entry(line);
 
-                        // Push prompt to kernel queue and run until empty
-                        kernel.run(&mut session, Some(line.to_string())).await?;
+                        let mut prompt = line.to_string();
+                        if let Some(args) = line.strip_prefix("/prompt").filter(|rest| rest.is_empty() || rest.starts_with(' ')) {
+                            match expand_prompt_command(&kernel, args).await {
+                                Ok(Some(messages)) => match split_prompt_messages(messages) {
+                                    Ok((context, text)) => {
+                                        kernel.add_messages(&mut session, context).await;
+                                        prompt = text;
+                                    }
+                                    Err(e) => {
+                                        eprintln!("[bedrock] {:#}", e);
+                                        continue;
+                                    }
+                                },
+                                Ok(None) => continue,
+                                Err(e) => {
+                                    eprintln!("[bedrock] {:#}", e);
+                                    continue;
+                                }
+                            }
+                        }
+
+                        // Push prompt to kernel queue and run until empty. Ctrl+C stops the
+                        // turn, not the REPL; keystrokes are held back until the prompt returns.
+                        let result = {
+                            let _echo = EchoGuard::new();
+                            let run = kernel.run_with_policy(&mut session, Some(prompt), QueueEmptyPolicy::Stop);
+                            tokio::pin!(run);
+                            loop {
+                                tokio::select! {
+                                    result = &mut run => break result,
+                                    _ = tokio::signal::ctrl_c() => {
+                                        eprintln!("\n[bedrock] Interrupting turn...");
+                                        interrupt.interrupt();
+                                    }
+                                }
+                            }
+                        };
+                        if let Err(e) = result {
+                            session.stop_reason = Some(StopReason::Error);
+                            let _ = kernel.end_session(&mut session).await;
+                            return Err(e);
+                        }
+                        if session.stop_reason == Some(StopReason::CostBudgetExceeded) {
+                            eprintln!("[bedrock] Cost budget exceeded: ${:.4} spent. Ending session.", session.total_cost_usd);
+                            break;
+                        }
+                        if session.stop_reason == Some(StopReason::LimitExceeded) {
+                            eprintln!(
+                                "[bedrock] Token limit exceeded: {} input / {} output tokens used. Ending session.",
+                                session.total_input_tokens, session.total_output_tokens
+                            );
+                            break;
+                        }
+                        if session.stop_reason == Some(StopReason::ToolErrorStorm) {
+                            eprintln!("[bedrock] {} tool calls failed in a row; turn halted.", session.consecutive_tool_errors);
+                            session.consecutive_tool_errors = 0;
+                        }
+                        if session.stop_reason == Some(StopReason::Interrupted) {
+                            eprintln!("[bedrock] Turn interrupted.");
+                        }
                     }
                     Err(ReadlineError::Interrupted) => {
                         println!("^C");
+                        session.stop_reason = Some(StopReason::Interrupted);
                         break;
                     }
                     Err(ReadlineError::Eof) => {
@@ -251,6 +930,9 @@ async fn main() -> Result<()> {
                 }
             }
             kernel.end_session(&mut session).await?;
+            kernel.flush_events(&mut session).await;
+            // After an idle timeout the abandoned read still holds the terminal in raw mode
+            terminal.restore();
             Ok(())
         }
         Commands::Script {
@@ -261,21 +943,15 @@ async fn main() -> Result<()> {
         } => {
             // Load config
             let mut config =
-                BedrockConfig::from_file(&config).with_context(|| "Failed to load config")?;
+                load_config(&config, db.as_deref())?;
 
-            // Apply CLI overrides
-            if let Some(m) = model {
-                config.agent.model = m;
-            }
-            if let Some(p) = provider {
-                config.agent.provider = p;
-                config.validate()?;
-            }
+            // Apply CLI / environment overrides
+            config.resolve_agent(model, provider)?;
 
             // Build kernel
             let mut kernel = Kernel::builder(config).json_mode(false).build()?;
             kernel.init_state().await?;
-            kernel.init_clients()?;
+            kernel.init_clients().await?;
             kernel.init_harness().await?;
 
             // Read script
@@ -286,5 +962,763 @@ async fn main() -> Result<()> {
 
             Ok(())
         }
+        Commands::Cat {
+            session,
+            format,
+            no_tool_args,
+            no_thinking,
+            config,
+        } => {
+            let config = load_config(&config, db.as_deref())?;
+            let store = StateStore::open(&config.database_path()).await?;
+
+            let session = store.resolve_session(&session).await?;
+            if let Some(name) = store.session_name(&session).await? {
+                match format {
+                    TranscriptFormat::Text => println!("Session: {} ({})\n", name, session),
+                    TranscriptFormat::Markdown => println!("# {}\n\n`{}`\n", name, session),
+                    TranscriptFormat::Json => {}
+                }
+            }
+            let options = TranscriptOptions { hide_tool_args: no_tool_args, hide_thinking: no_thinking };
+            transcript::write(&store, &session, options, format, &mut std::io::stdout().lock()).await?;
+            Ok(())
+        }
+        Commands::Replay {
+            session,
+            speed,
+            from_turn,
+            deterministic,
+            verbose,
+            show,
+            json,
+            config,
+        } => {
+            if let Some(speed) = speed {
+                anyhow::ensure!(speed.is_finite() && speed >= 0.0, "--speed must be a non-negative number");
+            }
+            let config = load_config(&config, db.as_deref())?;
+            let store = StateStore::open(&config.database_path()).await?;
+
+            let session = store.resolve_session(&session).await?;
+            let verbosity = Verbosity::from_level(verbose).merge(show.unwrap_or_default());
+            if deterministic {
+                let recording = Recording::load(&store, &session).await?;
+                let diffs = regression::replay_with_output(&config, &recording, json, verbosity).await?;
+                // Reported on stderr so --json output stays NDJSON
+                if diffs.is_empty() {
+                    eprintln!("Replay matches session '{}'", session);
+                    return Ok(());
+                }
+                eprintln!("Replay diverges from session '{}':", session);
+                for diff in &diffs {
+                    eprintln!("  {}", diff);
+                }
+                anyhow::bail!("{} difference(s) from the recording", diffs.len());
+            }
+            let mut renderer = EventRenderer::new(json, false, verbosity);
+            let options = ReplayOptions { speed, from_turn };
+            if render::replay(&store, &session, options, &mut renderer).await? == 0 {
+                match from_turn {
+                    Some(turn) => anyhow::bail!("Session '{}' has no events from turn {}", session, turn),
+                    None => anyhow::bail!("Session '{}' has no recorded events", session),
+                }
+            }
+            Ok(())
+        }
+        Commands::ShowRaw {
+            session,
+            turn,
+            config,
+        } => {
+            let config = load_config(&config, db.as_deref())?;
+            let store = StateStore::open(&config.database_path()).await?;
+
+            let session = store.resolve_session(&session).await?;
+            let responses = store.get_raw_responses(&session, turn).await?;
+            if responses.is_empty() {
+                anyhow::bail!(
+                    "No raw response stored for session '{}' turn {} (is persistence.store_raw_responses enabled?)",
+                    session,
+                    turn
+                );
+            }
+            for row in responses {
+                let body: serde_json::Value = serde_json::from_str(&row.body)?;
+                let out = serde_json::json!({
+                    "provider": row.provider,
+                    "model": row.model,
+                    "created_at": row.created_at,
+                    "body": body,
+                });
+                println!("{}", serde_json::to_string_pretty(&out)?);
+            }
+            Ok(())
+        }
+        Commands::Import {
+            from,
+            session,
+            reembed,
+            config,
+        } => {
+            let config = load_config(&config, db.as_deref())?;
+            let source = StateStore::open(&from.to_string_lossy()).await?;
+            let mut kernel = Kernel::builder(config).build()?;
+            kernel.init_state().await?;
+            kernel.init_clients().await?;
+
+            let report = kernel.import_session(&source, session.as_deref(), reembed).await?;
+            println!(
+                "Imported {} messages and {} memories ({} re-embedded, {} excluded from search)",
+                report.messages, report.memories, report.reembedded, report.excluded
+            );
+            if report.excluded > 0 {
+                println!("Run `bedrock reindex` to re-embed excluded memories.");
+            }
+            if report.malformed > 0 {
+                println!("Skipped {} messages with malformed content.", report.malformed);
+            }
+            if report.duplicates > 0 {
+                println!("Skipped {} memories already in the target database.", report.duplicates);
+            }
+            Ok(())
+        }
+        Commands::Reindex { config } => {
+            let config = load_config(&config, db.as_deref())?;
+            let mut kernel = Kernel::builder(config).build()?;
+            kernel.init_state().await?;
+            kernel.init_clients().await?;
+
+            let count = kernel.reindex_memories().await?;
+            println!("Re-embedded {} memories", count);
+            Ok(())
+        }
+        Commands::Validate { config: path, model, provider } => {
+            let mut config =
+                load_config(&path, db.as_deref()).map_err(|e| report_config_errors(&path, e))?;
+            let sources = config.resolve_agent(model, provider).map_err(|e| report_config_errors(&path, e))?;
+            println!("{}: OK", path.display());
+            println!("  provider: {} (from {})", config.agent.provider, sources.provider.describe(PROVIDER_ENV, "--provider"));
+            println!("  model:    {} (from {})", config.agent.model, sources.model.describe(MODEL_ENV, "--model"));
+            let db_path = config.database_path();
+            println!("  database: {}", db_path);
+            if let Err(e) = bedrock::persistence::path::check_writable(Path::new(&db_path)) {
+                println!("  warning:  database is not writable: {}", e);
+            }
+            let mut providers: Vec<_> = config.providers.iter().filter(|(_, p)| p.kind != "mock" && p.kind != "ollama").collect();
+            providers.sort_by_key(|(name, _)| *name);
+            for (name, provider) in providers {
+                let source = credentials::source_for(name, provider, &config.credentials)
+                    .await
+                    .unwrap_or_else(|e| { println!("  warning:  {}", e); CredentialSource::Missing });
+                println!("  key:      {}: {}", name, source);
+            }
+
+            // Load the harness too, so its tool hooks can be checked against the
+            // registry (empty with `tools.enabled = false`; MCP servers aren't started)
+            let harness_dir = PathBuf::from(&config.harness.directory);
+            let mut kernel = Kernel::builder(config).build()?;
+            if harness_dir.is_dir() {
+                kernel.init_harness().await?;
+            }
+            if let Some(problem) = kernel.tool_registry_problem().await {
+                println!("  warning:  {}", problem);
+            }
+            Ok(())
+        }
+        Commands::Memory {
+            command: MemoryCommand::Stats { session, json, config },
+        } => {
+            let config = load_config(&config, db.as_deref())?;
+            let store = StateStore::open(&config.database_path()).await?;
+
+            let session = match session {
+                Some(s) => Some(store.resolve_session(&s).await?),
+                None => None,
+            };
+            let stats = store.memory_stats(session.as_deref()).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+                return Ok(());
+            }
+            println!("Memories:    {} ({} excluded from search)", stats.total, stats.excluded);
+            if stats.total == 0 {
+                return Ok(());
+            }
+            println!("Content:     {} chars total, {:.0} average", stats.total_chars, stats.avg_chars);
+            let dimensions: Vec<String> = stats.dimensions.iter()
+                .map(|d| match d.dimension {
+                    0 => format!("none ({})", d.count),
+                    n => format!("{} ({})", n, d.count),
+                })
+                .collect();
+            println!("Dimensions:  {}", dimensions.join(", "));
+            println!("Created:     {} to {}", stats.oldest.as_deref().unwrap_or("-"), stats.newest.as_deref().unwrap_or("-"));
+            println!(
+                "Duplicates:  {} exact, {} near (similarity >= {} among the first {} embedded)",
+                stats.exact_duplicates, stats.near_duplicates, NEAR_DUPLICATE_SIMILARITY, stats.near_duplicate_sample
+            );
+            println!();
+            println!("{:<40} {:>8}", "SESSION", "MEMORIES");
+            for s in &stats.sessions {
+                println!("{:<40} {:>8}", s.session_id, s.count);
+            }
+            Ok(())
+        }
+        Commands::Sessions {
+            command: SessionsCommand::List { limit, offset, json, config },
+        } => {
+            let config = load_config(&config, db.as_deref())?;
+            let store = StateStore::open(&config.database_path()).await?;
+
+            let mut summaries = Vec::new();
+            for listed in store.list_sessions(limit, offset).await? {
+                summaries.extend(store.session_summary(&listed.id).await?);
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&summaries)?);
+                return Ok(());
+            }
+            println!(
+                "{:<36} {:<24} {:<20} {:>6} {:>6} {:>10} {:>10} {:>10}",
+                "SESSION", "NAME", "LAST ACTIVE", "TURNS", "TOOLS", "INPUT", "OUTPUT", "COST"
+            );
+            for s in &summaries {
+                println!(
+                    "{:<36} {:<24} {:<20} {:>6} {:>6} {:>10} {:>10} {:>10}",
+                    s.session_id,
+                    s.name.as_deref().unwrap_or("-"),
+                    s.last_active_at.as_deref().unwrap_or("-"),
+                    s.turns, s.tool_executions, s.input_tokens, s.output_tokens, format!("${:.4}", s.cost_usd)
+                );
+            }
+            Ok(())
+        }
+        Commands::Sessions {
+            command: SessionsCommand::Show { session, json, config },
+        } => {
+            let config = load_config(&config, db.as_deref())?;
+            let store = StateStore::open(&config.database_path()).await?;
+
+            let session = store.resolve_session(&session).await?;
+            let summary = store.session_summary(&session).await?
+                .with_context(|| format!("No session '{}'", session))?;
+            let entries = if summary.messages > 0 {
+                transcript::load(&store, &session, TranscriptOptions::default()).await?
+            } else {
+                Vec::new()
+            };
+            if json {
+                let out = serde_json::json!({ "session": summary, "transcript": entries });
+                println!("{}", serde_json::to_string_pretty(&out)?);
+                return Ok(());
+            }
+            println!("Session:     {}", summary.session_id);
+            if let Some(name) = &summary.name {
+                println!("Name:        {}", name);
+            }
+            println!("Active:      {} to {}", summary.started_at.as_deref().unwrap_or("-"), summary.last_active_at.as_deref().unwrap_or("-"));
+            println!("Turns:       {} ({} messages, {} tool executions)", summary.turns, summary.messages, summary.tool_executions);
+            println!("Tokens:      {} input, {} output", summary.input_tokens, summary.output_tokens);
+            println!("Cost:        ${:.4} (estimated)", summary.cost_usd);
+            if !entries.is_empty() {
+                println!("\n{}", transcript::render(&entries, TranscriptFormat::Text)?);
+            }
+            Ok(())
+        }
+        Commands::Sessions {
+            command: SessionsCommand::Cost { session, json, config },
+        } => {
+            let config = load_config(&config, db.as_deref())?;
+            let store = StateStore::open(&config.database_path()).await?;
+
+            let session = store.resolve_session(&session).await?;
+            let costs = store.get_turn_costs(&session).await?;
+            let total_input: u64 = costs.iter().map(|c| c.input_tokens).sum();
+            let total_output: u64 = costs.iter().map(|c| c.output_tokens).sum();
+            let total_cost: f64 = costs.iter().filter_map(|c| c.cost_usd).sum();
+            let mut unpriced: Vec<&str> = costs.iter().filter(|c| c.cost_usd.is_none()).map(|c| c.model.as_str()).collect();
+            if json {
+                let out = serde_json::json!({
+                    "session_id": session,
+                    "turns": costs,
+                    "input_tokens": total_input,
+                    "output_tokens": total_output,
+                    "cost_usd": total_cost,
+                });
+                println!("{}", serde_json::to_string_pretty(&out)?);
+                return Ok(());
+            }
+            if costs.is_empty() {
+                println!("No usage recorded for session {}", session);
+                return Ok(());
+            }
+            println!("{:>5} {:<12} {:<32} {:>10} {:>10} {:>10}", "TURN", "PROVIDER", "MODEL", "INPUT", "OUTPUT", "COST");
+            for c in &costs {
+                println!(
+                    "{:>5} {:<12} {:<32} {:>10} {:>10} {:>10}",
+                    c.turn_index, c.provider, c.model, c.input_tokens, c.output_tokens,
+                    c.cost_usd.map_or_else(|| "-".to_string(), |cost| format!("${:.4}", cost))
+                );
+            }
+            println!("{:>5} {:<12} {:<32} {:>10} {:>10} {:>10}", "", "", "TOTAL", total_input, total_output, format!("${:.4}", total_cost));
+            if !unpriced.is_empty() {
+                unpriced.sort_unstable();
+                unpriced.dedup();
+                println!("\nNo pricing for {}; add a [pricing] entry to include it", unpriced.join(", "));
+            }
+            Ok(())
+        }
+        Commands::Sessions {
+            command: SessionsCommand::Fork { session, at_turn, name, config },
+        } => {
+            let config = load_config(&config, db.as_deref())?;
+            let mut kernel = Kernel::builder(config).quiet(true).interactive(false).build()?;
+            kernel.init_state().await?;
+
+            let fork = kernel.fork_session(&session, at_turn, name.as_deref()).await?;
+            println!("Forked session {} at turn {}: {}", session, at_turn, name.as_deref().unwrap_or(&fork));
+            println!("Continue it with: bedrock run --resume {} --prompt \"...\"", name.as_deref().unwrap_or(&fork));
+            Ok(())
+        }
+        Commands::Sessions {
+            command: SessionsCommand::Delete { session, yes, config },
+        } => {
+            let config = load_config(&config, db.as_deref())?;
+            let store = StateStore::open(&config.database_path()).await?;
+
+            let session = store.resolve_session(&session).await?;
+            let summary = store.session_summary(&session).await?
+                .with_context(|| format!("No session '{}'", session))?;
+            if !yes {
+                eprint!(
+                    "Delete session {} ({} messages, {} tool executions)? [y/N] ",
+                    summary.name.as_deref().unwrap_or(&session), summary.messages, summary.tool_executions
+                );
+                let mut answer = String::new();
+                std::io::stdin().read_line(&mut answer)?;
+                if !answer.trim().eq_ignore_ascii_case("y") {
+                    println!("Not deleted");
+                    return Ok(());
+                }
+            }
+            store.delete_session(&session).await?;
+            println!("Deleted session {}", session);
+            Ok(())
+        }
+        Commands::Tools {
+            command: ToolsCommand::List { json, config },
+        } => {
+            let config = load_config(&config, db.as_deref())?;
+            // Start MCP servers and the harness so their tools are listed too
+            let harness_dir = PathBuf::from(&config.harness.directory);
+            let mut kernel = Kernel::builder(config).quiet(true).build()?;
+            kernel.init_mcp_servers().await;
+            if harness_dir.is_dir() {
+                kernel.init_harness().await?;
+            }
+            let tools = kernel.tools();
+            if json {
+                println!("{}", serde_json::to_string_pretty(&tools)?);
+                return Ok(());
+            }
+            println!("{:<32} {:<24} EFFECT", "TOOL", "ORIGIN");
+            for tool in tools {
+                let alias = tool.alias_of.as_deref().map(|name| format!(" (alias of {})", name)).unwrap_or_default();
+                println!("{:<32} {:<24} {}{}", tool.name, tool.origin(), tool.effect, alias);
+            }
+            Ok(())
+        }
+        Commands::Tools {
+            command: ToolsCommand::Stats { session, since, until, json, config },
+        } => {
+            let config = load_config(&config, db.as_deref())?;
+            let store = StateStore::open(&config.database_path()).await?;
+
+            let session = match session {
+                Some(s) => Some(store.resolve_session(&s).await?),
+                None => None,
+            };
+            let filter = ToolStatsFilter { session_id: session, since, until };
+            let stats = store.tool_stats(&filter).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+                return Ok(());
+            }
+            let ms = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or_else(|| "-".to_string());
+            println!("{:<24} {:>8} {:>8} {:>7} {:>9} {:>9} {:>9}", "TOOL", "CALLS", "ERRORS", "ERR%", "P50_MS", "P95_MS", "MAX_MS");
+            for s in stats {
+                println!(
+                    "{:<24} {:>8} {:>8} {:>6.1}% {:>9} {:>9} {:>9}",
+                    s.tool_name, s.calls, s.errors, s.error_rate * 100.0, ms(s.p50_ms), ms(s.p95_ms), ms(s.max_ms)
+                );
+            }
+            Ok(())
+        }
+        Commands::Turns {
+            command: TurnsCommand::Stats { session, since, until, json, config },
+        } => {
+            let config = load_config(&config, db.as_deref())?;
+            let store = StateStore::open(&config.database_path()).await?;
+
+            let session = match session {
+                Some(s) => Some(store.resolve_session(&s).await?),
+                None => None,
+            };
+            let filter = ToolStatsFilter { session_id: session, since, until };
+            let counts = store.finish_reason_stats(&filter).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&counts)?);
+                return Ok(());
+            }
+            println!("{:<24} {:>8} {:>7}", "FINISH_REASON", "TURNS", "SHARE");
+            for c in counts {
+                println!(
+                    "{:<24} {:>8} {:>6.1}%",
+                    c.finish_reason.as_deref().unwrap_or("-"), c.count, c.share * 100.0
+                );
+            }
+            Ok(())
+        }
+        Commands::Test { dir, config } => {
+            let config = load_config(&config, db.as_deref())?;
+            let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+                .with_context(|| format!("Failed to read recordings directory: {}", dir.display()))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"))
+                .collect();
+            paths.sort();
+            anyhow::ensure!(!paths.is_empty(), "No recordings (*.jsonl) in {}", dir.display());
+
+            let mut failed = 0;
+            for path in &paths {
+                let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read recording: {}", path.display()))?;
+                let diffs = match Recording::parse(&text) {
+                    Ok(recording) => regression::replay(&config, &recording).await?,
+                    Err(e) => vec![format!("invalid recording: {:#}", e)],
+                };
+                if diffs.is_empty() {
+                    println!("ok   {}", name);
+                } else {
+                    failed += 1;
+                    println!("FAIL {}", name);
+                    for diff in diffs {
+                        println!("     {}", diff);
+                    }
+                }
+            }
+            println!("\n{} passed, {} failed", paths.len() - failed, failed);
+            anyhow::ensure!(failed == 0, "{} recording(s) no longer match", failed);
+            Ok(())
+        }
+        Commands::Sources {
+            session,
+            turn,
+            config,
+        } => {
+            let config = load_config(&config, db.as_deref())?;
+            let store = StateStore::open(&config.database_path()).await?;
+
+            let session = store.resolve_session(&session).await?;
+            for row in store.get_tool_sources(&session, turn).await? {
+                let source: serde_json::Value = serde_json::from_str(&row.source)?;
+                let out = serde_json::json!({
+                    "turn": row.turn_index,
+                    "tool_call_id": row.tool_call_id,
+                    "tool": row.tool_name,
+                    "source": source,
+                });
+                println!("{}", serde_json::to_string(&out)?);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Load the config file, pointing it at `--db` when given.
+fn load_config(path: &Path, db: Option<&Path>) -> Result<BedrockConfig> {
+    let mut config = BedrockConfig::from_file(path).with_context(|| "Failed to load config")?;
+    if let Some(db) = db {
+        config.persistence.database_path = db.to_string_lossy().into_owned();
+    }
+    Ok(config)
+}
+
+/// For `validate`: print each problem in a config error on its own line and
+/// return a short summary error. Other errors pass through unchanged.
+fn report_config_errors(path: &Path, e: anyhow::Error) -> anyhow::Error {
+    let Some(ConfigErrors(errors)) = e.chain().find_map(|cause| cause.downcast_ref::<ConfigErrors>()) else {
+        return e;
+    };
+    println!("{}: {} problem(s)", path.display(), errors.len());
+    for error in errors {
+        println!("  error:    {}", error);
+    }
+    anyhow::anyhow!("{} is invalid", path.display())
+}
+
+/// Parse a REPL `/model [name]` or `/provider [name]` line into the command
+/// and the optional name.
+fn parse_switch_command(line: &str) -> Option<(&str, Option<&str>)> {
+    let mut parts = line.split_whitespace();
+    let command = parts.next().filter(|c| *c == "/model" || *c == "/provider")?;
+    Some((command, parts.next()))
+}
+
+/// Handle the REPL `/prompt [<name> [key=value ...]]`: list the MCP
+/// servers' prompts, or expand one into its messages.
+async fn expand_prompt_command(kernel: &Kernel, args: &str) -> Result<Option<Vec<McpPromptMessage>>> {
+    use bedrock::tools::mcp_resources::{get_prompt, list_prompts};
+
+    let servers = kernel.mcp_servers();
+    let mut words = args.split_whitespace();
+    let Some(name) = words.next() else {
+        let prompts = list_prompts(&servers).await;
+        if prompts.is_empty() {
+            println!("No MCP prompts available.");
+        }
+        for prompt in prompts {
+            let arguments: Vec<String> = prompt
+                .arguments
+                .iter()
+                .map(|a| if a.required { format!("{}=", a.name) } else { format!("[{}=]", a.name) })
+                .collect();
+            let usage = std::iter::once(format!("{}:{}", prompt.server, prompt.name)).chain(arguments).collect::<Vec<_>>().join(" ");
+            match prompt.description {
+                Some(description) => println!("{}  {}", usage, description),
+                None => println!("{}", usage),
+            }
+        }
+        return Ok(None);
+    };
+    let arguments = words
+        .map(|word| {
+            word.split_once('=')
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .ok_or_else(|| anyhow::anyhow!("Expected key=value after the prompt name, got '{}'", word))
+        })
+        .collect::<Result<std::collections::HashMap<_, _>>>()?;
+    get_prompt(&servers, name, &arguments).await.map(Some)
+}
+
+/// Split an expanded prompt into the messages added to the history with
+/// their roles and the last one, sent as the user's message.
+fn split_prompt_messages(mut messages: Vec<McpPromptMessage>) -> Result<(Vec<(InferenceRole, String)>, String)> {
+    let last = messages.pop().filter(|m| m.role == "user");
+    let Some(last) = last else {
+        anyhow::bail!("The prompt doesn't end with a user message to send");
+    };
+    let context = messages
+        .into_iter()
+        .map(|m| {
+            let role = if m.role == "assistant" { InferenceRole::Assistant } else { InferenceRole::User };
+            (role, m.text)
+        })
+        .collect();
+    Ok((context, last.text))
+}
+
+/// Render the REPL `/tools` listing: one line per tool with its effect and
+/// source, marking locked tools when safe mode is on.
+fn format_tool_list(tools: &[bedrock::tools::registry::ToolDescriptor], safe: bool) -> String {
+    if tools.is_empty() {
+        return "No tools registered.\n".to_string();
+    }
+    let width = tools.iter().map(|t| t.name.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    if safe {
+        out.push_str("Safe mode: tools that aren't read-only are locked unless allowed.\n");
+    }
+    for tool in tools {
+        let state = match (safe, tool.locked) {
+            (false, _) => "",
+            (true, true) => "  locked",
+            (true, false) => "  unlocked",
+        };
+        out.push_str(&format!("  {:<width$}  {:<9}  {}{}\n", tool.name, tool.effect.to_string(), tool.source, state, width = width));
+    }
+    out
+}
+
+/// Read the prompt for `run --from-stdin` / `--prompt -`. Refuses to wait on
+/// an interactive terminal, where it would otherwise appear to hang.
+fn read_stdin_prompt() -> Result<String> {
+    use std::io::{IsTerminal, Read};
+
+    let mut stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        anyhow::bail!(
+            "--from-stdin expects piped input, e.g. `cat issue.md | bedrock run --from-stdin`; use --prompt \"...\" to pass the prompt directly"
+        );
+    }
+    let mut prompt = String::new();
+    stdin.read_to_string(&mut prompt).with_context(|| "Failed to read prompt from stdin (is it UTF-8 text?)")?;
+    if prompt.trim().is_empty() {
+        anyhow::bail!("No prompt received on stdin");
+    }
+    Ok(prompt)
+}
+
+/// Write the final answer for `run --answer-file`, creating parent directories.
+fn write_answer_file(path: &Path, answer: &str) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    std::fs::write(path, answer).with_context(|| format!("Failed to write answer to {}", path.display()))
+}
+
+/// Read one REPL line, bounded by `repl.idle_timeout`. Returns `None` when
+/// the timeout elapses first; the read is then left pending and the editor
+/// with it.
+async fn read_line(
+    mut rl: DefaultEditor,
+    idle_timeout: Option<std::time::Duration>,
+) -> Result<Option<(DefaultEditor, rustyline::Result<String>)>> {
+    read_with_timeout(
+        move || {
+            let line = rl.readline(">> ");
+            (rl, line)
+        },
+        idle_timeout,
+    )
+    .await
+}
+
+/// Run a blocking `read` on its own thread and wait for it up to `timeout`.
+/// A detached thread rather than `spawn_blocking`, since the runtime waits
+/// for its blocking tasks on shutdown and an abandoned read never returns.
+async fn read_with_timeout<T: Send + 'static>(
+    read: impl FnOnce() -> T + Send + 'static,
+    timeout: Option<std::time::Duration>,
+) -> Result<Option<T>> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    std::thread::Builder::new()
+        .name("repl-input".to_string())
+        .spawn(move || {
+            let _ = tx.send(read());
+        })
+        .context("Failed to start the REPL input thread")?;
+    let received = match timeout {
+        Some(limit) => match tokio::time::timeout(limit, rx).await {
+            Ok(received) => received,
+            Err(_) => return Ok(None),
+        },
+        None => rx.await,
+    };
+    Ok(Some(received.context("REPL input thread failed")?))
+}
+
+/// Terminal settings from before the line editor switched to raw mode, for
+/// restoring them when the REPL exits with a read still pending.
+#[cfg(unix)]
+struct TerminalState(Option<nix::sys::termios::Termios>);
+
+#[cfg(unix)]
+impl TerminalState {
+    fn save() -> Self {
+        Self(nix::sys::termios::tcgetattr(std::io::stdin()).ok())
+    }
+
+    fn restore(&self) {
+        if let Some(ref saved) = self.0 {
+            let _ = nix::sys::termios::tcsetattr(std::io::stdin(), nix::sys::termios::SetArg::TCSANOW, saved);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct TerminalState;
+
+#[cfg(not(unix))]
+impl TerminalState {
+    fn save() -> Self {
+        Self
+    }
+
+    fn restore(&self) {}
+}
+
+/// Turns off terminal echo while a REPL turn runs so keystrokes typed meanwhile
+/// don't interleave with streamed output. The terminal still buffers them, so
+/// they become the next prompt's input once echo is restored.
+#[cfg(unix)]
+struct EchoGuard {
+    saved: nix::sys::termios::Termios,
+}
+
+#[cfg(unix)]
+impl EchoGuard {
+    fn new() -> Option<Self> {
+        use nix::sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg};
+        use std::io::IsTerminal;
+
+        let stdin = std::io::stdin();
+        if !stdin.is_terminal() {
+            return None;
+        }
+        let saved = tcgetattr(&stdin).ok()?;
+        let mut quiet = saved.clone();
+        quiet.local_flags.remove(LocalFlags::ECHO);
+        tcsetattr(&stdin, SetArg::TCSANOW, &quiet).ok()?;
+        Some(Self { saved })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for EchoGuard {
+    fn drop(&mut self) {
+        let _ = nix::sys::termios::tcsetattr(std::io::stdin(), nix::sys::termios::SetArg::TCSANOW, &self.saved);
+    }
+}
+
+#[cfg(not(unix))]
+struct EchoGuard;
+
+#[cfg(not(unix))]
+impl EchoGuard {
+    fn new() -> Option<Self> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_answer_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("out/nested/answer.md");
+        write_answer_file(&path, "42").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "42");
+        // An existing file is replaced
+        write_answer_file(&path, "43").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "43");
+    }
+
+    #[test]
+    fn test_run_answer_file_and_no_persist_flags() {
+        let cli = Cli::try_parse_from(["bedrock", "run", "--prompt", "hi", "--answer-file", "out/a.md", "--no-persist"]).unwrap();
+        let Commands::Run { answer_file, no_persist, .. } = cli.command else {
+            panic!("expected run");
+        };
+        assert_eq!(answer_file, Some(PathBuf::from("out/a.md")));
+        assert!(no_persist);
+        // Nothing to resume from without the database
+        assert!(Cli::try_parse_from(["bedrock", "run", "--prompt", "hi", "--no-persist", "--resume", "s1"]).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_read_with_timeout() {
+        // A read that never returns gives up once the (paused) clock passes the timeout
+        let (_hold, pending) = std::sync::mpsc::channel::<()>();
+        let read = read_with_timeout(move || pending.recv().ok(), Some(std::time::Duration::from_secs(30 * 60)));
+        assert!(read.await.unwrap().is_none());
+
+        let read = read_with_timeout(|| "line", None);
+        assert_eq!(read.await.unwrap(), Some("line"));
     }
 }
@@ -0,0 +1,187 @@
+//! Importing sessions and memories from another Bedrock state database.
+//!
+//! Memories carry embeddings from whatever model produced them. An imported
+//! memory whose dimension doesn't match the local embedding provider can't be
+//! compared against local query vectors, so it is either re-embedded on import
+//! or stored with `excluded = 1` until [`reindex_memories`] re-embeds it.
+
+use std::collections::HashSet;
+
+use anyhow::{Context, Result};
+
+use crate::inference::embeddings::EmbeddingProvider;
+use crate::persistence::state::StateStore;
+
+/// What an import copied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub messages: usize,
+    pub memories: usize,
+    /// Memories re-embedded with the local provider
+    pub reembedded: usize,
+    /// Memories stored but excluded from search (dimension mismatch)
+    pub excluded: usize,
+    /// Messages skipped because their stored content is not valid JSON
+    pub malformed: usize,
+    /// Memories skipped because the target already has them
+    pub duplicates: usize,
+}
+
+/// Copy a session's messages and memories from `source` into `target`.
+///
+/// With `session_id` unset, only memories are copied (all of them). With
+/// `reembed`, every imported memory is re-embedded with `embedder`; otherwise
+/// memories whose dimension differs from the embedder's are flagged excluded.
+/// Memories the target already has (same session and content) are skipped,
+/// so re-running an import is harmless. Nothing is written unless the whole
+/// import succeeds.
+pub async fn import_session(
+    source: &StateStore,
+    target: &StateStore,
+    session_id: Option<&str>,
+    embedder: &dyn EmbeddingProvider,
+    reembed: bool,
+) -> Result<ImportReport> {
+    let mut report = ImportReport::default();
+
+    let mut messages = Vec::new();
+    if let Some(id) = session_id {
+        anyhow::ensure!(
+            target.get_messages(id).await?.is_empty(),
+            "Session '{}' already exists in the target database",
+            id
+        );
+        for msg in source.get_messages(id).await? {
+            if msg.content_json().is_none() {
+                report.malformed += 1;
+                continue;
+            }
+            messages.push(msg);
+        }
+    }
+
+    let local_dim = embedder
+        .dimensions()
+        .await
+        .context("Failed to determine local embedding dimension")?;
+
+    let mut seen: HashSet<(String, String)> = target
+        .get_memories(session_id)
+        .await?
+        .into_iter()
+        .map(|m| (m.session_id, m.content))
+        .collect();
+    let mut memories = Vec::new();
+    for mut memory in source.get_memories(session_id).await? {
+        if !seen.insert((memory.session_id.clone(), memory.content.clone())) {
+            report.duplicates += 1;
+            continue;
+        }
+        if reembed {
+            let embedding = embedder
+                .embed(&memory.content)
+                .await
+                .with_context(|| format!("Failed to re-embed memory {}", memory.id))?;
+            memory.embedding = embedding.vector;
+            memory.excluded = false;
+            report.reembedded += 1;
+        } else {
+            memory.excluded = memory.excluded || memory.embedding.len() != local_dim;
+            if memory.excluded {
+                report.excluded += 1;
+            }
+        }
+        memories.push(memory);
+    }
+
+    let inserted = target.import_records(&messages, &memories).await?;
+    report.messages = messages.len();
+    report.memories = inserted;
+    report.duplicates += memories.len() - inserted;
+    Ok(report)
+}
+
+/// Re-embed every excluded memory so it participates in search again.
+/// Returns the number of memories reindexed.
+pub async fn reindex_memories(store: &StateStore, embedder: &dyn EmbeddingProvider) -> Result<usize> {
+    let mut count = 0;
+    for memory in store.get_memories(None).await?.into_iter().filter(|m| m.excluded) {
+        let embedding = embedder
+            .embed(&memory.content)
+            .await
+            .with_context(|| format!("Failed to re-embed memory {}", memory.id))?;
+        store.update_memory_embedding(memory.id, &embedding.vector).await?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inference::embeddings::Embedding;
+    use async_trait::async_trait;
+    use serde_json::json;
+
+    /// Produces 2-dimensional embeddings.
+    struct TinyEmbedder;
+
+    #[async_trait]
+    impl EmbeddingProvider for TinyEmbedder {
+        async fn embed(&self, text: &str) -> Result<Embedding> {
+            Ok(Embedding { content: text.to_string(), vector: vec![1.0, 0.0], model: "tiny".to_string() })
+        }
+    }
+
+    async fn shared_store() -> StateStore {
+        let store = StateStore::open_memory().await.unwrap();
+        store.insert_message("shared", 0, "user", &json!([{"type": "text", "text": "hi"}]), None, None).await.unwrap();
+        store.insert_message("shared", 0, "assistant", &json!([{"type": "text", "text": "hello"}]), None, Some("stop")).await.unwrap();
+        store.insert_memory("shared", "apples are red", &[1.0, 0.0], &json!({})).await.unwrap();
+        store.insert_memory("shared", "bananas are yellow", &[0.5, 0.5, 0.5], &json!({})).await.unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn test_import_flags_mismatched_dimensions() {
+        let source = shared_store().await;
+        let target = StateStore::open_memory().await.unwrap();
+
+        let report = import_session(&source, &target, Some("shared"), &TinyEmbedder, false).await.unwrap();
+        assert_eq!(report, ImportReport { messages: 2, memories: 2, reembedded: 0, excluded: 1, malformed: 0, duplicates: 0 });
+        let messages = target.get_messages("shared").await.unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].finish_reason.as_deref(), Some("stop"));
+
+        // The 3-dim memory is stored but not searchable
+        let results = target.search_memories("shared", None, Some("bananas"), 10).await.unwrap();
+        assert!(results.is_empty());
+
+        assert_eq!(reindex_memories(&target, &TinyEmbedder).await.unwrap(), 1);
+        let results = target.search_memories("shared", None, Some("bananas"), 10).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(target.get_memories(None).await.unwrap().iter().all(|m| !m.excluded));
+    }
+
+    #[tokio::test]
+    async fn test_import_reembed() {
+        let source = shared_store().await;
+        let target = StateStore::open_memory().await.unwrap();
+
+        let report = import_session(&source, &target, Some("shared"), &TinyEmbedder, true).await.unwrap();
+        assert_eq!(report.reembedded, 2);
+        assert_eq!(report.excluded, 0);
+        let memories = target.get_memories(Some("shared")).await.unwrap();
+        assert!(memories.iter().all(|m| m.embedding == vec![1.0, 0.0]));
+
+        // Importing the same session twice is refused, and writes nothing
+        assert!(import_session(&source, &target, Some("shared"), &TinyEmbedder, true).await.is_err());
+        assert_eq!(target.get_memories(None).await.unwrap().len(), 2);
+
+        // Re-importing memories skips the ones already there
+        source.insert_memory("shared", "cherries are red", &[1.0, 0.0], &json!({})).await.unwrap();
+        let report = import_session(&source, &target, None, &TinyEmbedder, false).await.unwrap();
+        assert_eq!((report.memories, report.duplicates), (1, 2));
+        assert_eq!(target.get_memories(None).await.unwrap().len(), 3);
+    }
+}
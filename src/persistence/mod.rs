@@ -1 +1,5 @@
+pub mod import;
+pub mod path;
+pub mod redact;
 pub mod state;
+pub mod transcript;
@@ -0,0 +1,141 @@
+//! Placeholder expansion for `persistence.database_path`.
+//!
+//! The path may contain `{date}` (UTC, `YYYY-MM-DD`) and `{workspace_name}`
+//! (the last component of `kernel.workspace_root`), plus a leading `~/` for
+//! the home directory, so `~/.bedrock/{workspace_name}-{date}.db` rotates to
+//! a new file each day without a cron job. Placeholders are expanded when the
+//! database is opened.
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Placeholders understood in `persistence.database_path`.
+pub const PLACEHOLDERS: &[&str] = &["date", "workspace_name"];
+
+/// Reject placeholders other than [`PLACEHOLDERS`], so a typo doesn't create
+/// a file literally named `{dat}.db`.
+pub fn check_template(template: &str) -> Result<()> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start + 1..].find('}') else {
+            anyhow::bail!("persistence.database_path has an unclosed '{{': {}", template);
+        };
+        let name = &rest[start + 1..start + 1 + len];
+        anyhow::ensure!(
+            PLACEHOLDERS.contains(&name),
+            "Unknown placeholder '{{{}}}' in persistence.database_path (expected one of: {})",
+            name,
+            PLACEHOLDERS.iter().map(|p| format!("{{{}}}", p)).collect::<Vec<_>>().join(", ")
+        );
+        rest = &rest[start + 1 + len + 1..];
+    }
+    Ok(())
+}
+
+/// Expand `template` for the workspace at `workspace_root`, as of `now`.
+pub fn expand(template: &str, workspace_root: &str, now: SystemTime) -> String {
+    let mut path = template
+        .replace("{date}", &utc_date(now))
+        .replace("{workspace_name}", &workspace_name(workspace_root));
+    if let Some(rest) = path.strip_prefix("~/") {
+        if let Some(home) = std::env::var_os("HOME") {
+            path = PathBuf::from(home).join(rest).to_string_lossy().into_owned();
+        }
+    }
+    path
+}
+
+/// Name of the workspace directory, resolving `.` and relative roots.
+fn workspace_name(workspace_root: &str) -> String {
+    let root = std::fs::canonicalize(workspace_root).unwrap_or_else(|_| PathBuf::from(workspace_root));
+    root.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "workspace".to_string())
+}
+
+/// `YYYY-MM-DD HH:MM:SS.SSS` in UTC, the format of SQLite's `datetime()`
+/// with milliseconds.
+pub(crate) fn utc_timestamp(now: SystemTime) -> String {
+    let since_epoch = now.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() % 86_400;
+    format!(
+        "{} {:02}:{:02}:{:02}.{:03}",
+        utc_date(now),
+        secs / 3_600,
+        secs / 60 % 60,
+        secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// `YYYY-MM-DD` in UTC.
+fn utc_date(now: SystemTime) -> String {
+    let secs = now.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+    // Civil-from-days (Howard Hinnant), valid for any date after 1970
+    let z = (secs / 86_400) as i64 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Check that a database can be created or opened at `path`, by creating
+/// (and removing) a probe file in its directory.
+pub fn check_writable(path: &Path) -> Result<()> {
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    // The store creates missing directories, so the nearest existing one must be writable
+    let existing = dir.ancestors().find(|d| d.exists()).unwrap_or(Path::new("."));
+    if path.exists() {
+        let readonly = std::fs::metadata(path)?.permissions().readonly();
+        anyhow::ensure!(!readonly, "{} is read-only", path.display());
+    }
+    let probe = existing.join(format!(".bedrock-write-check-{}", std::process::id()));
+    std::fs::write(&probe, b"")
+        .map_err(|e| anyhow::anyhow!("Cannot write to {}: {}", existing.display(), e))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_expand_placeholders() {
+        // 2024-02-29T12:00:00Z
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_709_208_000);
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("my-project");
+        std::fs::create_dir(&project).unwrap();
+
+        let path = expand("dbs/{workspace_name}/{date}.db", project.to_str().unwrap(), now);
+        assert_eq!(path, "dbs/my-project/2024-02-29.db");
+        assert_eq!(expand(".bedrock/state.db", ".", now), ".bedrock/state.db");
+        assert_eq!(utc_date(SystemTime::UNIX_EPOCH), "1970-01-01");
+        assert_eq!(utc_date(SystemTime::UNIX_EPOCH + Duration::from_secs(951_782_400)), "2000-02-29");
+        assert_eq!(utc_timestamp(now + Duration::from_millis(3_723_045)), "2024-02-29 13:02:03.045");
+    }
+
+    #[test]
+    fn test_check_template() {
+        assert!(check_template("~/.bedrock/{date}.db").is_ok());
+        assert!(check_template(".bedrock/state.db").is_ok());
+        let err = check_template("{dat}.db").unwrap_err().to_string();
+        assert!(err.contains("{dat}") && err.contains("{date}"), "{}", err);
+        assert!(check_template("{date.db").is_err());
+    }
+
+    #[test]
+    fn test_check_writable() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(check_writable(&dir.path().join("new/nested/state.db")).is_ok());
+        assert!(check_writable(Path::new("/proc/bedrock/state.db")).is_err());
+    }
+}
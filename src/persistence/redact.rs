@@ -0,0 +1,111 @@
+//! Redaction of secrets from JSON payloads before they are persisted.
+
+use serde_json::Value;
+
+/// Placeholder written in place of redacted values.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Object keys whose values are always redacted (matched case-insensitively,
+/// ignoring `-` and `_`).
+const SECRET_KEYS: &[&str] = &[
+    "apikey",
+    "authorization",
+    "xapikey",
+    "accesstoken",
+    "refreshtoken",
+    "secret",
+    "password",
+];
+
+/// String prefixes of well-known credential formats.
+const SECRET_PREFIXES: &[&str] = &["sk-", "sk_", "Bearer "];
+
+/// Return a copy of `value` with secret-looking fields and strings replaced by `[REDACTED]`.
+pub fn redact(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| {
+                    let redacted = if is_secret_key(k) {
+                        Value::String(REDACTED.to_string())
+                    } else {
+                        redact(v)
+                    };
+                    (k.clone(), redacted)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        Value::String(s) if looks_like_secret(s) => Value::String(REDACTED.to_string()),
+        other => other.clone(),
+    }
+}
+
+/// Return `text` with credential-looking tokens (`sk-...`, the token after
+/// `Bearer`) replaced by `[REDACTED]`, for free-form text such as model output.
+pub fn redact_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut after_bearer = false;
+    for piece in text.split_inclusive(char::is_whitespace) {
+        let word = piece.trim_end();
+        let token = word.trim_matches(|c: char| matches!(c, '"' | '\'' | '`' | ',' | ';' | '(' | ')'));
+        let secret = (after_bearer && token.len() >= 8) || looks_like_secret(token);
+        if secret && !token.is_empty() {
+            out.push_str(&piece.replacen(token, REDACTED, 1));
+        } else {
+            out.push_str(piece);
+        }
+        after_bearer = token == "Bearer";
+    }
+    out
+}
+
+/// A bare credential: a known prefix followed by a single whitespace-free token.
+fn looks_like_secret(s: &str) -> bool {
+    SECRET_PREFIXES.iter().any(|p| {
+        s.strip_prefix(p)
+            .is_some_and(|rest| rest.len() >= 8 && !rest.contains(char::is_whitespace))
+    })
+}
+
+fn is_secret_key(key: &str) -> bool {
+    let normalized: String = key
+        .chars()
+        .filter(|c| *c != '-' && *c != '_')
+        .flat_map(char::to_lowercase)
+        .collect();
+    SECRET_KEYS.contains(&normalized.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_redacts_secret_keys() {
+        let v = json!({"API_KEY": "abc", "headers": {"Authorization": "xyz"}, "model": "gpt-4o"});
+        let r = redact(&v);
+        assert_eq!(r["API_KEY"], REDACTED);
+        assert_eq!(r["headers"]["Authorization"], REDACTED);
+        assert_eq!(r["model"], "gpt-4o");
+    }
+
+    #[test]
+    fn test_redacts_credential_strings() {
+        let v = json!(["sk-abcdef1234567890", "Bearer abcdefgh12345", "sk-is a prefix in prose"]);
+        let r = redact(&v);
+        assert_eq!(r[0], REDACTED);
+        assert_eq!(r[1], REDACTED);
+        assert_eq!(r[2], "sk-is a prefix in prose");
+    }
+
+    #[test]
+    fn test_redact_text() {
+        let text = "Use key \"sk-abcdef1234567890\" with\nAuthorization: Bearer abcdefgh12345, not sk-is.";
+        assert_eq!(
+            redact_text(text),
+            "Use key \"[REDACTED]\" with\nAuthorization: Bearer [REDACTED], not sk-is."
+        );
+    }
+}
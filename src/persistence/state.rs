@@ -5,11 +5,20 @@
 //! - Message history (per session)
 //! - Tool execution log
 //! - Harness key-value store
+//! - Cross-session tool result cache
+//! - Raw provider responses (opt-in, redacted)
+//! - Tool output sources (provenance)
+//! - Agent plans (per session)
+//! - Session snapshots (latest per session, for fast resume)
 //! - Cognitive memories (vector store)
 
 use anyhow::{Context, Result};
+use futures::{Stream, TryStreamExt};
+use turso::transaction::TransactionBehavior;
 use turso::{Connection, Database};
 
+use crate::tools::{Artifact, ArtifactRef};
+
 /// The state store manages all Bedrock persistence.
 use std::sync::Arc;
 
@@ -20,10 +29,31 @@ use std::sync::Arc;
 #[derive(Clone)]
 pub struct StateStore {
     db: Arc<Database>,
+    /// Store event payloads and tool args as indented JSON
+    pretty_payloads: bool,
+    /// Replace logged tool args larger than this many bytes with a marker
+    max_logged_arg_size: Option<usize>,
+    /// Keep the full value of truncated args as an artifact
+    spill_logged_args: bool,
 }
 
 /// Schema version — bump when changing table structure.
-const SCHEMA_VERSION: u32 = 2;
+const SCHEMA_VERSION: u32 = 15;
+
+/// How long a write waits for a competing connection's lock before failing.
+const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Rows fetched per query by [`StateStore::stream_messages`] and [`StateStore::stream_events`].
+pub const STREAM_PAGE_SIZE: usize = 500;
+
+/// Largest serialized record accepted by [`StateStore::records_put`].
+pub const MAX_RECORD_SIZE: usize = 1_048_576;
+
+/// Cosine similarity at which two memories count as near duplicates.
+pub const NEAR_DUPLICATE_SIMILARITY: f32 = 0.95;
+
+/// Memories compared pairwise for the near-duplicate estimate (quadratic cost).
+pub const NEAR_DUPLICATE_SAMPLE: usize = 2_000;
 
 
 /// SQL statements to initialize the core database schema.
@@ -45,6 +75,9 @@ CREATE TABLE IF NOT EXISTS messages (
     role        TEXT NOT NULL,
     content     TEXT NOT NULL,
     token_count INTEGER,
+    partial     INTEGER NOT NULL DEFAULT 0,
+    -- Provider stop/finish reason of an assistant message (stop, length, tool_calls, ...)
+    finish_reason TEXT,
     created_at  TEXT NOT NULL DEFAULT (datetime('now'))
 );
 
@@ -56,6 +89,18 @@ CREATE TABLE IF NOT EXISTS harness_kv (
     updated_at  TEXT NOT NULL DEFAULT (datetime('now'))
 );
 
+-- Harness document store (bedrock.records_put / records_query); session_id ''
+-- marks a record shared by all sessions
+CREATE TABLE IF NOT EXISTS harness_records (
+    collection  TEXT NOT NULL,
+    id          TEXT NOT NULL,
+    session_id  TEXT NOT NULL DEFAULT '',
+    json        TEXT NOT NULL,
+    created_at  TEXT NOT NULL DEFAULT (datetime('now')),
+    updated_at  TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (collection, session_id, id)
+);
+
 -- Tool execution log
 CREATE TABLE IF NOT EXISTS tool_executions (
     id            INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -71,6 +116,88 @@ CREATE TABLE IF NOT EXISTS tool_executions (
     created_at    TEXT NOT NULL DEFAULT (datetime('now'))
 );
 
+-- Cross-session tool result cache
+CREATE TABLE IF NOT EXISTS tool_cache (
+    tool_name   TEXT NOT NULL,
+    args_hash   TEXT NOT NULL,
+    output      TEXT NOT NULL,
+    expires_at  TEXT NOT NULL,
+    created_at  TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (tool_name, args_hash)
+);
+
+-- Raw provider responses (opt-in debugging aid)
+CREATE TABLE IF NOT EXISTS raw_responses (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id  TEXT NOT NULL,
+    turn_index  INTEGER NOT NULL,
+    provider    TEXT NOT NULL,
+    model       TEXT NOT NULL,
+    body        TEXT NOT NULL,
+    created_at  TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+-- Token usage and estimated cost of each provider response
+CREATE TABLE IF NOT EXISTS turn_costs (
+    id             INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id     TEXT NOT NULL,
+    turn_index     INTEGER NOT NULL,
+    provider       TEXT NOT NULL,
+    model          TEXT NOT NULL,
+    input_tokens   INTEGER NOT NULL,
+    output_tokens  INTEGER NOT NULL,
+    cost_usd       REAL,
+    created_at     TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+-- Provenance of tool outputs (files read, URLs fetched, memories recalled)
+CREATE TABLE IF NOT EXISTS tool_sources (
+    id            INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id    TEXT NOT NULL,
+    turn_index    INTEGER NOT NULL,
+    tool_call_id  TEXT NOT NULL,
+    tool_name     TEXT NOT NULL,
+    kind          TEXT NOT NULL,
+    source        TEXT NOT NULL,
+    created_at    TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+-- Large tool results kept out of the context, fetched by id
+CREATE TABLE IF NOT EXISTS artifacts (
+    id            TEXT PRIMARY KEY,
+    session_id    TEXT NOT NULL,
+    tool_call_id  TEXT NOT NULL,
+    kind          TEXT NOT NULL,
+    size          INTEGER NOT NULL,
+    preview       TEXT NOT NULL,
+    data          BLOB NOT NULL,
+    created_at    TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+-- Agent-maintained plan, one per session (steps as a JSON array)
+CREATE TABLE IF NOT EXISTS plans (
+    session_id  TEXT PRIMARY KEY,
+    steps       TEXT NOT NULL,
+    updated_at  TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+-- Latest serialized SessionState per session; messages after last_message_id
+-- are replayed on top of it when resuming
+CREATE TABLE IF NOT EXISTS session_snapshots (
+    session_id       TEXT PRIMARY KEY,
+    version          INTEGER NOT NULL,
+    last_message_id  INTEGER NOT NULL,
+    state            TEXT NOT NULL,
+    created_at       TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+-- Human-readable session names (unique; see the v15 migration for the index)
+CREATE TABLE IF NOT EXISTS session_names (
+    session_id  TEXT PRIMARY KEY,
+    name        TEXT NOT NULL,
+    created_at  TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
 -- Schema version tracking
 CREATE TABLE IF NOT EXISTS schema_info (
     key   TEXT PRIMARY KEY,
@@ -81,6 +208,9 @@ CREATE TABLE IF NOT EXISTS schema_info (
 CREATE INDEX IF NOT EXISTS idx_events_session ON events(session_id);
 CREATE INDEX IF NOT EXISTS idx_messages_session ON messages(session_id);
 CREATE INDEX IF NOT EXISTS idx_tool_executions_session ON tool_executions(session_id);
+CREATE INDEX IF NOT EXISTS idx_raw_responses_session ON raw_responses(session_id, turn_index);
+CREATE INDEX IF NOT EXISTS idx_tool_sources_session ON tool_sources(session_id, turn_index);
+CREATE INDEX IF NOT EXISTS idx_turn_costs_session ON turn_costs(session_id, turn_index);
 
 -- Cognitive Memory
 CREATE TABLE IF NOT EXISTS memories (
@@ -89,6 +219,7 @@ CREATE TABLE IF NOT EXISTS memories (
     content     TEXT NOT NULL,
     embedding   F32_BLOB(1536), 
     metadata    TEXT,
+    excluded    INTEGER NOT NULL DEFAULT 0,
     created_at  TEXT NOT NULL DEFAULT (datetime('now'))
 );
 "#;
@@ -133,7 +264,7 @@ impl StateStore {
             .await
             .with_context(|| format!("Failed to open database: {}", db_path))?;
 
-        let store = Self { db: Arc::new(db) };
+        let store = Self { db: Arc::new(db), pretty_payloads: false, max_logged_arg_size: None, spill_logged_args: false };
         store.init_schema().await?;
 
         Ok(store)
@@ -146,15 +277,48 @@ impl StateStore {
             .await
             .with_context(|| "Failed to open in-memory database")?;
 
-        let store = Self { db: Arc::new(db) };
+        let store = Self { db: Arc::new(db), pretty_payloads: false, max_logged_arg_size: None, spill_logged_args: false };
         store.init_schema().await?;
 
         Ok(store)
     }
 
+    /// Store event payloads and tool arguments as indented JSON (for reading
+    /// the database directly). Only affects rows written afterwards.
+    pub fn with_pretty_payloads(mut self, pretty: bool) -> Self {
+        self.pretty_payloads = pretty;
+        self
+    }
+
+    /// Cap the size of tool args stored in `tool_executions`. Larger args are
+    /// replaced by a marker carrying their size, SHA-256 and a preview; with
+    /// `spill` the full value is kept as an artifact referenced by the marker.
+    /// The tool itself always receives the full args.
+    pub fn with_max_logged_arg_size(mut self, max: Option<usize>, spill: bool) -> Self {
+        self.max_logged_arg_size = max;
+        self.spill_logged_args = spill;
+        self
+    }
+
+    fn payload_json(&self, value: &serde_json::Value) -> Result<String> {
+        Ok(if self.pretty_payloads {
+            serde_json::to_string_pretty(value)?
+        } else {
+            serde_json::to_string(value)?
+        })
+    }
+
+    /// Open a connection that waits out short write locks held by other
+    /// connections (e.g. the background event writer) instead of failing.
+    fn connect(&self) -> Result<Connection> {
+        let conn = self.db.connect()?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        Ok(conn)
+    }
+
     /// Initialize the database schema.
     async fn init_schema(&self) -> Result<()> {
-        let conn = self.db.connect()?;
+        let conn = self.connect()?;
 
         // 1. Init Core Schema
         conn
@@ -188,6 +352,48 @@ impl StateStore {
             }
         }
 
+        if version < 5 && !Self::column_exists(&conn, "messages", "partial").await? {
+            // Migration v4 -> v5: flag for in-progress streamed assistant messages
+            conn.execute("ALTER TABLE messages ADD COLUMN partial INTEGER NOT NULL DEFAULT 0", ())
+                .await
+                .context("Failed to add messages.partial column during migration")?;
+        }
+
+        if version < 7 && !Self::column_exists(&conn, "memories", "excluded").await? {
+            // Migration v6 -> v7: imported memories with foreign embeddings are kept out of search
+            conn.execute("ALTER TABLE memories ADD COLUMN excluded INTEGER NOT NULL DEFAULT 0", ())
+                .await
+                .context("Failed to add memories.excluded column during migration")?;
+        }
+
+        if version < 10 && !Self::column_exists(&conn, "messages", "finish_reason").await? {
+            // Migration v9 -> v10: provider finish reason per assistant message
+            conn.execute("ALTER TABLE messages ADD COLUMN finish_reason TEXT", ())
+                .await
+                .context("Failed to add messages.finish_reason column during migration")?;
+        }
+
+        if version < 15 {
+            // Migration v14 -> v15: session names become unique in the database,
+            // not just in set_session_name. Duplicates from a lost race keep
+            // only their first session.
+            let mut rows = conn
+                .query("SELECT name, MIN(rowid) FROM session_names GROUP BY name HAVING COUNT(*) > 1", ())
+                .await?;
+            let mut duplicates: Vec<(String, i64)> = Vec::new();
+            while let Some(row) = rows.next().await? {
+                duplicates.push((row.get(0)?, row.get(1)?));
+            }
+            drop(rows);
+            for (name, keep) in duplicates {
+                conn.execute("DELETE FROM session_names WHERE name = ?1 AND rowid != ?2", turso::params![name, keep]).await?;
+            }
+            conn.execute_batch(r#"
+                DROP INDEX IF EXISTS idx_session_names_name;
+                CREATE UNIQUE INDEX IF NOT EXISTS idx_session_names_unique ON session_names(name);
+            "#).await.context("Failed to make session names unique during migration")?;
+        }
+
         // Record schema version
         conn
             .execute(
@@ -199,6 +405,16 @@ impl StateStore {
         Ok(())
     }
 
+    async fn column_exists(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+        let mut rows = conn.query(&format!("PRAGMA table_info({})", table), ()).await?;
+        while let Some(row) = rows.next().await? {
+            if row.get::<String>(1)? == column {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
     async fn get_schema_version(&self, conn: &Connection) -> Result<Option<u32>> {
         let mut rows = conn.query("SELECT value FROM schema_info WHERE key = 'version'", ()).await?;
         if let Some(row) = rows.next().await? {
@@ -218,12 +434,14 @@ impl StateStore {
         event_type: &str,
         payload: &serde_json::Value,
     ) -> Result<()> {
-        let conn = self.db.connect()?;
-        let payload_str = serde_json::to_string(payload)?;
+        let conn = self.connect()?;
+        let payload_str = self.payload_json(payload)?;
+        // Milliseconds, unlike the column default, so replays keep the pacing
+        let created_at = super::path::utc_timestamp(std::time::SystemTime::now());
         conn
             .execute(
-                "INSERT INTO events (session_id, event_type, payload) VALUES (?1, ?2, ?3)",
-                turso::params![session_id, event_type, payload_str],
+                "INSERT INTO events (session_id, event_type, payload, created_at) VALUES (?1, ?2, ?3, ?4)",
+                turso::params![session_id, event_type, payload_str, created_at],
             )
             .await
             .with_context(|| format!("Failed to insert event for session: {}", session_id))?;
@@ -231,45 +449,163 @@ impl StateStore {
     }
 
     /// Get all events for a session, ordered by creation time.
+    ///
+    /// Loads the whole session; prefer [`Self::get_events_page`] or
+    /// [`Self::stream_events`] for sessions that may be large.
     pub async fn get_events(&self, session_id: &str) -> Result<Vec<EventRow>> {
-        let conn = self.db.connect()?;
+        self.get_events_page(session_id, 0, usize::MAX).await
+    }
+
+    /// Get up to `limit` of a session's events with ids greater than
+    /// `after_id`, oldest first. Pass the last row's id to get the next page.
+    pub async fn get_events_page(&self, session_id: &str, after_id: i64, limit: usize) -> Result<Vec<EventRow>> {
+        let conn = self.connect()?;
         let mut rows = conn
             .query(
-                "SELECT id, session_id, event_type, payload, created_at FROM events WHERE session_id = ?1 ORDER BY id",
-                [session_id],
+                "SELECT id, session_id, event_type, payload, created_at FROM events WHERE session_id = ?1 AND id > ?2 ORDER BY id LIMIT ?3",
+                turso::params![session_id, after_id, page_limit(limit)],
             )
             .await?;
 
         let mut events = Vec::new();
         while let Some(row) = rows.next().await? {
-            events.push(EventRow {
-                id: row.get::<i64>(0)?,
-                session_id: row.get::<String>(1)?,
-                event_type: row.get::<String>(2)?,
-                payload: row.get::<String>(3)?,
-                created_at: row.get::<String>(4)?,
-            });
+            events.push(EventRow::from_row(&row)?);
+        }
+        Ok(events)
+    }
+
+    /// Stream a session's events oldest first, fetching [`STREAM_PAGE_SIZE`]
+    /// rows at a time instead of loading them all.
+    pub fn stream_events<'a>(&'a self, session_id: &'a str) -> impl Stream<Item = Result<EventRow>> + 'a {
+        paginate(move |after_id| self.get_events_page(session_id, after_id, STREAM_PAGE_SIZE), |e: &EventRow| e.id)
+    }
+
+    /// Get a session's most recent events matching `filter`, oldest first.
+    pub async fn query_events(&self, session_id: &str, filter: &EventFilter) -> Result<Vec<EventRow>> {
+        let conn = self.connect()?;
+        // Types are matched against comma-delimited lists so the query stays static
+        let list = |types: &[String]| if types.is_empty() { String::new() } else { format!(",{},", types.join(",")) };
+        let mut rows = conn
+            .query(
+                "SELECT id, session_id, event_type, payload, created_at FROM events
+                 WHERE session_id = ?1
+                   AND (?2 = '' OR instr(?2, ',' || event_type || ',') > 0)
+                   AND instr(?3, ',' || event_type || ',') = 0
+                 ORDER BY id DESC LIMIT ?4",
+                turso::params![session_id, list(&filter.event_types), list(&filter.exclude_types), filter.limit as i64],
+            )
+            .await?;
+
+        let mut events = Vec::new();
+        while let Some(row) = rows.next().await? {
+            events.push(EventRow::from_row(&row)?);
         }
+        events.reverse();
         Ok(events)
     }
 
     /// List recent sessions, ordered by last activity.
-    pub async fn list_sessions(&self, limit: usize, offset: usize) -> Result<Vec<String>> {
-        let conn = self.db.connect()?;
+    pub async fn list_sessions(&self, limit: usize, offset: usize) -> Result<Vec<SessionListing>> {
+        let conn = self.connect()?;
         let mut rows = conn
             .query(
-                "SELECT session_id FROM events GROUP BY session_id ORDER BY MAX(id) DESC LIMIT ?1 OFFSET ?2",
+                "SELECT e.session_id, n.name FROM (SELECT session_id, MAX(id) AS last_id FROM events GROUP BY session_id) e \
+                 LEFT JOIN session_names n ON n.session_id = e.session_id \
+                 ORDER BY e.last_id DESC LIMIT ?1 OFFSET ?2",
                 turso::params![limit as i64, offset as i64],
             )
             .await?;
 
         let mut sessions = Vec::new();
         while let Some(row) = rows.next().await? {
-            sessions.push(row.get(0)?);
+            sessions.push(SessionListing { id: row.get(0)?, name: row.get(1)? });
         }
         Ok(sessions)
     }
 
+    /// Activity span, message and tool counts, and token and cost totals of a
+    /// session; None when nothing is stored for it.
+    pub async fn session_summary(&self, session_id: &str) -> Result<Option<SessionSummary>> {
+        let conn = self.connect()?;
+        let mut rows = conn
+            .query("SELECT MIN(created_at), MAX(created_at), COUNT(*) FROM events WHERE session_id = ?1", [session_id])
+            .await?;
+        let (started_at, last_active_at, events) = match rows.next().await? {
+            Some(row) => (row.get::<Option<String>>(0)?, row.get::<Option<String>>(1)?, row.get::<i64>(2)?),
+            None => (None, None, 0),
+        };
+        drop(rows);
+        let mut rows = conn
+            .query("SELECT COUNT(*), COALESCE(MAX(turn_index) + 1, 0) FROM messages WHERE session_id = ?1", [session_id])
+            .await?;
+        let (messages, turns) = match rows.next().await? {
+            Some(row) => (row.get::<i64>(0)?, row.get::<i64>(1)?),
+            None => (0, 0),
+        };
+        drop(rows);
+        if events == 0 && messages == 0 {
+            return Ok(None);
+        }
+        let mut rows = conn
+            .query("SELECT COUNT(*) FROM tool_executions WHERE session_id = ?1", [session_id])
+            .await?;
+        let tool_executions = match rows.next().await? {
+            Some(row) => row.get::<i64>(0)?,
+            None => 0,
+        };
+        drop(rows);
+
+        // Usage is only recorded on message_end events
+        let (mut input_tokens, mut output_tokens) = (0, 0);
+        let mut rows = conn
+            .query("SELECT payload FROM events WHERE session_id = ?1 AND event_type = 'message_end'", [session_id])
+            .await?;
+        while let Some(row) = rows.next().await? {
+            let payload: serde_json::Value = serde_json::from_str(&row.get::<String>(0)?).unwrap_or_default();
+            input_tokens += payload["input_tokens"].as_u64().unwrap_or(0);
+            output_tokens += payload["output_tokens"].as_u64().unwrap_or(0);
+        }
+        drop(rows);
+        let mut rows = conn
+            .query("SELECT COALESCE(SUM(cost_usd), 0.0) FROM turn_costs WHERE session_id = ?1", [session_id])
+            .await?;
+        let cost_usd = match rows.next().await? {
+            Some(row) => row.get::<f64>(0)?,
+            None => 0.0,
+        };
+
+        Ok(Some(SessionSummary {
+            session_id: session_id.to_string(),
+            name: self.session_name(session_id).await?,
+            started_at,
+            last_active_at,
+            turns: turns as u32,
+            messages: messages as u64,
+            tool_executions: tool_executions as u64,
+            input_tokens,
+            output_tokens,
+            cost_usd,
+        }))
+    }
+
+    /// Delete everything stored for a session in one transaction. Memories it
+    /// captured are kept, as they are shared with later sessions.
+    pub async fn delete_session(&self, session_id: &str) -> Result<()> {
+        const TABLES: &[&str] = &[
+            "events", "messages", "tool_executions", "raw_responses", "turn_costs", "tool_sources",
+            "artifacts", "plans", "session_snapshots", "session_names", "harness_records",
+        ];
+        let mut conn = self.connect()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate).await?;
+        for table in TABLES {
+            tx.execute(&format!("DELETE FROM {table} WHERE session_id = ?1"), [session_id])
+                .await
+                .with_context(|| format!("Failed to delete {} of session: {}", table, session_id))?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
     // ─── Message History ─────────────────────────────────────────
 
     /// Insert a message into the history.
@@ -280,18 +616,20 @@ impl StateStore {
         role: &str,
         content: &serde_json::Value,
         token_count: Option<u64>,
+        finish_reason: Option<&str>,
     ) -> Result<()> {
-        let conn = self.db.connect()?;
+        let conn = self.connect()?;
         let content_str = serde_json::to_string(content)?;
         conn
             .execute(
-                "INSERT INTO messages (session_id, turn_index, role, content, token_count) VALUES (?1, ?2, ?3, ?4, ?5)",
+                "INSERT INTO messages (session_id, turn_index, role, content, token_count, finish_reason) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
                 turso::params![
                     session_id,
                     turn_index as i64,
                     role,
                     content_str,
                     token_count.map(|t| t as i64),
+                    finish_reason,
                 ],
             )
             .await
@@ -299,13 +637,78 @@ impl StateStore {
         Ok(())
     }
 
+    /// Upsert the assistant message for a turn while it is streaming (`partial = true`)
+    /// and once complete (`partial = false`).
+    ///
+    /// Idempotent per `(session_id, turn_index)`: the in-progress row is updated in
+    /// place, and finalizing converts it rather than adding a second row.
+    /// `finish_reason` is the provider's stop reason, known once the stream ends.
+    pub async fn upsert_assistant_message(
+        &self,
+        session_id: &str,
+        turn_index: u32,
+        content: &serde_json::Value,
+        partial: bool,
+        finish_reason: Option<&str>,
+    ) -> Result<()> {
+        let conn = self.connect()?;
+        let content_str = serde_json::to_string(content)?;
+        let updated = conn
+            .execute(
+                "UPDATE messages SET content = ?1, partial = ?2, finish_reason = ?3 WHERE session_id = ?4 AND turn_index = ?5 AND role = 'assistant' AND partial = 1",
+                turso::params![content_str.clone(), partial as i64, finish_reason, session_id, turn_index as i64],
+            )
+            .await
+            .with_context(|| format!("Failed to update assistant message for session: {}", session_id))?;
+        if updated == 0 {
+            conn
+                .execute(
+                    "INSERT INTO messages (session_id, turn_index, role, content, partial, finish_reason) VALUES (?1, ?2, 'assistant', ?3, ?4, ?5)",
+                    turso::params![session_id, turn_index as i64, content_str, partial as i64, finish_reason],
+                )
+                .await
+                .with_context(|| format!("Failed to insert assistant message for session: {}", session_id))?;
+        }
+        Ok(())
+    }
+
+    /// Copy the messages `session_id` stored before turn `at_turn` into a new
+    /// session and return its id. The original is left as it was; the fork
+    /// has no events, tool executions or snapshot of its own.
+    pub async fn fork_session(&self, session_id: &str, at_turn: u32) -> Result<String> {
+        let fork_id = uuid::Uuid::new_v4().to_string();
+        let conn = self.connect()?;
+        let copied = conn
+            .execute(
+                "INSERT INTO messages (session_id, turn_index, role, content, token_count, partial, finish_reason) \
+                 SELECT ?1, turn_index, role, content, token_count, partial, finish_reason FROM messages \
+                 WHERE session_id = ?2 AND turn_index < ?3 ORDER BY id",
+                turso::params![fork_id.as_str(), session_id, at_turn as i64],
+            )
+            .await
+            .with_context(|| format!("Failed to fork session: {}", session_id))?;
+        anyhow::ensure!(copied > 0, "Session '{}' has no messages before turn {}", session_id, at_turn);
+        Ok(fork_id)
+    }
+
     /// Get all messages for a session.
     pub async fn get_messages(&self, session_id: &str) -> Result<Vec<MessageRow>> {
-        let conn = self.db.connect()?;
+        self.get_messages_after(session_id, 0).await
+    }
+
+    /// Get a session's messages with ids greater than `after_id`.
+    pub async fn get_messages_after(&self, session_id: &str, after_id: i64) -> Result<Vec<MessageRow>> {
+        self.get_messages_page(session_id, after_id, usize::MAX).await
+    }
+
+    /// Get up to `limit` of a session's messages with ids greater than
+    /// `after_id`, oldest first. Pass the last row's id to get the next page.
+    pub async fn get_messages_page(&self, session_id: &str, after_id: i64, limit: usize) -> Result<Vec<MessageRow>> {
+        let conn = self.connect()?;
         let mut rows = conn
             .query(
-                "SELECT id, session_id, turn_index, role, content, token_count, created_at FROM messages WHERE session_id = ?1 ORDER BY id",
-                [session_id],
+                "SELECT id, session_id, turn_index, role, content, token_count, created_at, partial, finish_reason FROM messages WHERE session_id = ?1 AND id > ?2 ORDER BY id LIMIT ?3",
+                turso::params![session_id, after_id, page_limit(limit)],
             )
             .await?;
 
@@ -319,11 +722,19 @@ impl StateStore {
                 content: row.get::<String>(4)?,
                 token_count: row.get::<Option<i64>>(5)?.map(|t| t as u64),
                 created_at: row.get::<String>(6)?,
+                partial: row.get::<i64>(7)? != 0,
+                finish_reason: row.get::<Option<String>>(8)?,
             });
         }
         Ok(messages)
     }
 
+    /// Stream a session's messages oldest first, fetching
+    /// [`STREAM_PAGE_SIZE`] rows at a time instead of loading them all.
+    pub fn stream_messages<'a>(&'a self, session_id: &'a str) -> impl Stream<Item = Result<MessageRow>> + 'a {
+        paginate(move |after_id| self.get_messages_page(session_id, after_id, STREAM_PAGE_SIZE), |m: &MessageRow| m.id)
+    }
+
     // ─── Tool Executions ─────────────────────────────────────────
 
     /// Log a tool execution.
@@ -339,8 +750,8 @@ impl StateStore {
         duration_ms: Option<u64>,
         verdict: &str,
     ) -> Result<()> {
-        let conn = self.db.connect()?;
-        let args_str = serde_json::to_string(args)?;
+        let args_str = self.logged_args(session_id, tool_call_id, args).await?;
+        let conn = self.connect()?;
         conn
             .execute(
                 "INSERT INTO tool_executions (session_id, turn_index, tool_call_id, tool_name, args, output, is_error, duration_ms, verdict) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
@@ -361,13 +772,47 @@ impl StateStore {
         Ok(())
     }
 
+    /// Serialize tool args for the log, truncating them past `max_logged_arg_size`.
+    async fn logged_args(&self, session_id: &str, tool_call_id: &str, args: &serde_json::Value) -> Result<String> {
+        let full = serde_json::to_string(args)?;
+        let max = match self.max_logged_arg_size {
+            Some(max) if full.len() > max => max,
+            _ => return self.payload_json(args),
+        };
+        let mut end = max;
+        while !full.is_char_boundary(end) {
+            end -= 1;
+        }
+        let mut marker = serde_json::json!({
+            "_truncated": true,
+            "size": full.len(),
+            "sha256": crate::tools::args_hash(args),
+            "preview": &full[..end],
+        });
+        if self.spill_logged_args {
+            let artifact = Artifact::text("application/json", full.clone());
+            let handle = self.insert_artifact(session_id, tool_call_id, &artifact).await?;
+            marker["artifact"] = serde_json::Value::String(handle.id);
+        }
+        self.payload_json(&marker)
+    }
+
     /// Get all tool executions for a session.
     pub async fn get_tool_executions(&self, session_id: &str) -> Result<Vec<ToolExecutionRow>> {
-        let conn = self.db.connect()?;
+        self.query_tool_executions(session_id, None).await
+    }
+
+    /// Get the tool executions of one turn of a session.
+    pub async fn get_turn_tool_executions(&self, session_id: &str, turn_index: u32) -> Result<Vec<ToolExecutionRow>> {
+        self.query_tool_executions(session_id, Some(turn_index)).await
+    }
+
+    async fn query_tool_executions(&self, session_id: &str, turn_index: Option<u32>) -> Result<Vec<ToolExecutionRow>> {
+        let conn = self.connect()?;
         let mut rows = conn
             .query(
-                "SELECT id, session_id, turn_index, tool_call_id, tool_name, args, output, is_error, duration_ms, verdict, created_at FROM tool_executions WHERE session_id = ?1 ORDER BY id",
-                [session_id],
+                "SELECT id, session_id, turn_index, tool_call_id, tool_name, args, output, is_error, duration_ms, verdict, created_at FROM tool_executions WHERE session_id = ?1 AND (?2 IS NULL OR turn_index = ?2) ORDER BY id",
+                turso::params![session_id, turn_index.map(i64::from)],
             )
             .await?;
 
@@ -390,79 +835,735 @@ impl StateStore {
         Ok(execs)
     }
 
-    // ─── Memories (Vector + FTS Hybrid Store) ─────────────────────
+    /// Aggregate tool executions into per-tool call counts, error rates and
+    /// latency percentiles, optionally scoped to a session or time range.
+    pub async fn tool_stats(&self, filter: &ToolStatsFilter) -> Result<Vec<ToolStats>> {
+        let conn = self.connect()?;
+        check_time_range(&conn, filter).await?;
+        let mut rows = conn
+            .query(
+                "SELECT tool_name, is_error, duration_ms FROM tool_executions
+                 WHERE (?1 IS NULL OR session_id = ?1)
+                   AND (?2 IS NULL OR created_at >= datetime(?2))
+                   AND (?3 IS NULL OR created_at <= datetime(?3))
+                 ORDER BY tool_name",
+                turso::params![filter.session_id.as_deref(), filter.since.as_deref(), filter.until.as_deref()],
+            )
+            .await
+            .context("Failed to query tool executions for stats")?;
 
-    /// Insert a memory with an embedding vector.
-    pub async fn insert_memory(
+        let mut stats: Vec<ToolStats> = Vec::new();
+        let mut durations: Vec<Vec<u64>> = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let name: String = row.get(0)?;
+            if stats.last().is_none_or(|s| s.tool_name != name) {
+                stats.push(ToolStats { tool_name: name, ..Default::default() });
+                durations.push(Vec::new());
+            }
+            let entry = stats.last_mut().expect("pushed above");
+            entry.calls += 1;
+            if row.get::<i64>(1)? != 0 {
+                entry.errors += 1;
+            }
+            if let Some(ms) = row.get::<Option<i64>>(2)? {
+                durations.last_mut().expect("pushed above").push(ms.max(0) as u64);
+            }
+        }
+
+        for (entry, mut ms) in stats.iter_mut().zip(durations) {
+            entry.error_rate = entry.errors as f64 / entry.calls as f64;
+            ms.sort_unstable();
+            entry.p50_ms = percentile(&ms, 50.0);
+            entry.p95_ms = percentile(&ms, 95.0);
+            entry.max_ms = ms.last().copied();
+        }
+        Ok(stats)
+    }
+
+    /// Count completed assistant messages by finish reason, scoped like
+    /// [`tool_stats`](Self::tool_stats). Messages without a recorded reason
+    /// (older rows, harness-appended messages) are grouped under `None`.
+    pub async fn finish_reason_stats(&self, filter: &ToolStatsFilter) -> Result<Vec<FinishReasonCount>> {
+        let conn = self.connect()?;
+        check_time_range(&conn, filter).await?;
+        let mut rows = conn
+            .query(
+                "SELECT finish_reason, COUNT(*) FROM messages
+                 WHERE role = 'assistant' AND partial = 0
+                   AND (?1 IS NULL OR session_id = ?1)
+                   AND (?2 IS NULL OR created_at >= datetime(?2))
+                   AND (?3 IS NULL OR created_at <= datetime(?3))
+                 GROUP BY finish_reason
+                 ORDER BY COUNT(*) DESC, finish_reason",
+                turso::params![filter.session_id.as_deref(), filter.since.as_deref(), filter.until.as_deref()],
+            )
+            .await
+            .context("Failed to query finish reasons")?;
+
+        let mut counts = Vec::new();
+        while let Some(row) = rows.next().await? {
+            counts.push(FinishReasonCount {
+                finish_reason: row.get::<Option<String>>(0)?,
+                count: row.get::<i64>(1)? as u64,
+                share: 0.0,
+            });
+        }
+        let total: u64 = counts.iter().map(|c| c.count).sum();
+        for c in &mut counts {
+            c.share = c.count as f64 / total as f64;
+        }
+        Ok(counts)
+    }
+
+    // ─── Raw Provider Responses ──────────────────────────────────
+
+    /// Store the raw provider response for a turn. Secrets are redacted before writing.
+    pub async fn insert_raw_response(
         &self,
         session_id: &str,
-        content: &str,
-        vector: &[f32],
-        metadata: &serde_json::Value,
+        turn_index: u32,
+        provider: &str,
+        model: &str,
+        body: &serde_json::Value,
     ) -> Result<()> {
-        // Convert vector to raw bytes (little endian)
-        let mut vector_bytes = Vec::with_capacity(vector.len() * 4);
-        for &val in vector {
-            vector_bytes.extend_from_slice(&val.to_le_bytes());
-        }
-
-        let metadata_str = serde_json::to_string(metadata)?;
-        
-        let conn = self.db.connect()?;
+        let conn = self.connect()?;
+        let body_str = serde_json::to_string(&crate::persistence::redact::redact(body))?;
         conn
             .execute(
-                "INSERT INTO memories (session_id, content, embedding, metadata) VALUES (?1, ?2, ?3, ?4)",
-                turso::params![
-                    session_id,
-                    content,
-                    vector_bytes,
-                    metadata_str,
-                ],
+                "INSERT INTO raw_responses (session_id, turn_index, provider, model, body) VALUES (?1, ?2, ?3, ?4, ?5)",
+                turso::params![session_id, turn_index as i64, provider, model, body_str],
             )
             .await
-            .with_context(|| format!("Failed to insert memory for session: {}", session_id))?;
+            .with_context(|| format!("Failed to insert raw response for session: {}", session_id))?;
         Ok(())
     }
 
-    /// Search memories using Hybrid Search (Vector + FTS5).
-    /// 
-    /// Uses Reciprocal Rank Fusion (RRF) to combine results.
-    /// - `vector`: Optional embedding for semantic search.
-    /// - `content_query`: Optional keyword string for FTS search. if None, relies only on vector.
-    pub async fn search_memories(
+    /// Get the raw provider responses recorded for a session turn.
+    pub async fn get_raw_responses(&self, session_id: &str, turn_index: u32) -> Result<Vec<RawResponseRow>> {
+        let conn = self.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT id, session_id, turn_index, provider, model, body, created_at FROM raw_responses WHERE session_id = ?1 AND turn_index = ?2 ORDER BY id",
+                turso::params![session_id, turn_index as i64],
+            )
+            .await?;
+
+        let mut responses = Vec::new();
+        while let Some(row) = rows.next().await? {
+            responses.push(RawResponseRow {
+                id: row.get::<i64>(0)?,
+                session_id: row.get::<String>(1)?,
+                turn_index: row.get::<i64>(2)? as u32,
+                provider: row.get::<String>(3)?,
+                model: row.get::<String>(4)?,
+                body: row.get::<String>(5)?,
+                created_at: row.get::<String>(6)?,
+            });
+        }
+        Ok(responses)
+    }
+
+    // ─── Turn Costs ──────────────────────────────────────────────
+
+    /// Record the usage of one provider response. `cost_usd` is `None` when the model is unpriced.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn insert_turn_cost(
         &self,
         session_id: &str,
-        vector: Option<&[f32]>,
-        content_query: Option<&str>,
-        limit: usize,
-    ) -> Result<Vec<MemoryRow>> {
-        use std::collections::HashMap;
-
-        // RRF constant k (usually 60)
-        const RRF_K: f64 = 60.0;
-        let mut scores: HashMap<i64, f64> = HashMap::new();
-        let mut rows_data: HashMap<i64, MemoryRow> = HashMap::new();
+        turn_index: u32,
+        provider: &str,
+        model: &str,
+        input_tokens: u64,
+        output_tokens: u64,
+        cost_usd: Option<f64>,
+    ) -> Result<()> {
+        let conn = self.connect()?;
+        conn
+            .execute(
+                "INSERT INTO turn_costs (session_id, turn_index, provider, model, input_tokens, output_tokens, cost_usd)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                turso::params![session_id, turn_index as i64, provider, model, input_tokens as i64, output_tokens as i64, cost_usd],
+            )
+            .await
+            .with_context(|| format!("Failed to insert turn cost for session: {}", session_id))?;
+        Ok(())
+    }
 
-        let conn = self.db.connect()?;
+    /// Get the usage recorded for a session, one row per provider response, oldest first.
+    pub async fn get_turn_costs(&self, session_id: &str) -> Result<Vec<TurnCostRow>> {
+        let conn = self.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT turn_index, provider, model, input_tokens, output_tokens, cost_usd, created_at
+                 FROM turn_costs WHERE session_id = ?1 ORDER BY id",
+                [session_id],
+            )
+            .await?;
 
-        // 1. Vector Search
-        if let Some(vec) = vector {
-            // Convert to bytes
-            let mut vector_bytes = Vec::with_capacity(vec.len() * 4);
-            for &val in vec {
-                vector_bytes.extend_from_slice(&val.to_le_bytes());
-            }
+        let mut costs = Vec::new();
+        while let Some(row) = rows.next().await? {
+            costs.push(TurnCostRow {
+                turn_index: row.get::<i64>(0)? as u32,
+                provider: row.get::<String>(1)?,
+                model: row.get::<String>(2)?,
+                input_tokens: row.get::<i64>(3)? as u64,
+                output_tokens: row.get::<i64>(4)? as u64,
+                cost_usd: row.get::<Option<f64>>(5)?,
+                created_at: row.get::<String>(6)?,
+            });
+        }
+        Ok(costs)
+    }
 
-            let mut rows = conn.query(
-                "SELECT id, session_id, content, metadata, created_at, vector_distance_cos(embedding, ?1) as distance 
-                 FROM memories 
-                 WHERE session_id = ?2 
-                 ORDER BY distance ASC 
-                 LIMIT ?3",
-                turso::params![vector_bytes, session_id, limit as i64],
-            ).await.context("Failed to search memories (vector)")?;
+    // ─── Tool Sources ────────────────────────────────────────────
 
-            let mut rank = 1;
+    /// Record a source cited by a tool call. `source` is the serialized `tools::Source`.
+    pub async fn insert_tool_source(
+        &self,
+        session_id: &str,
+        turn_index: u32,
+        tool_call_id: &str,
+        tool_name: &str,
+        kind: &str,
+        source: &serde_json::Value,
+    ) -> Result<()> {
+        let conn = self.connect()?;
+        let source_str = serde_json::to_string(source)?;
+        conn
+            .execute(
+                "INSERT INTO tool_sources (session_id, turn_index, tool_call_id, tool_name, kind, source) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                turso::params![session_id, turn_index as i64, tool_call_id, tool_name, kind, source_str],
+            )
+            .await
+            .with_context(|| format!("Failed to insert tool source for session: {}", session_id))?;
+        Ok(())
+    }
+
+    /// Get the sources cited in a session, optionally limited to one turn.
+    pub async fn get_tool_sources(&self, session_id: &str, turn_index: Option<u32>) -> Result<Vec<ToolSourceRow>> {
+        let conn = self.connect()?;
+        let turn = turn_index.map(|t| t as i64).unwrap_or(-1);
+        let mut rows = conn
+            .query(
+                "SELECT id, session_id, turn_index, tool_call_id, tool_name, kind, source, created_at FROM tool_sources WHERE session_id = ?1 AND (?2 < 0 OR turn_index = ?2) ORDER BY id",
+                turso::params![session_id, turn],
+            )
+            .await?;
+
+        let mut sources = Vec::new();
+        while let Some(row) = rows.next().await? {
+            sources.push(ToolSourceRow {
+                id: row.get::<i64>(0)?,
+                session_id: row.get::<String>(1)?,
+                turn_index: row.get::<i64>(2)? as u32,
+                tool_call_id: row.get::<String>(3)?,
+                tool_name: row.get::<String>(4)?,
+                kind: row.get::<String>(5)?,
+                source: row.get::<String>(6)?,
+                created_at: row.get::<String>(7)?,
+            });
+        }
+        Ok(sources)
+    }
+
+    // ─── Artifacts ───────────────────────────────────────────────
+
+    /// Store a tool-produced blob and return its handle.
+    pub async fn insert_artifact(&self, session_id: &str, tool_call_id: &str, artifact: &Artifact) -> Result<ArtifactRef> {
+        let conn = self.connect()?;
+        let handle = ArtifactRef {
+            id: format!("art_{}", &uuid::Uuid::new_v4().simple().to_string()[..12]),
+            kind: artifact.kind.clone(),
+            size: artifact.data.len() as u64,
+            preview: artifact.preview(),
+        };
+        conn
+            .execute(
+                "INSERT INTO artifacts (id, session_id, tool_call_id, kind, size, preview, data) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                turso::params![
+                    handle.id.as_str(),
+                    session_id,
+                    tool_call_id,
+                    handle.kind.as_str(),
+                    handle.size as i64,
+                    handle.preview.as_str(),
+                    artifact.data.clone(),
+                ],
+            )
+            .await
+            .with_context(|| format!("Failed to store artifact for session: {}", session_id))?;
+        Ok(handle)
+    }
+
+    /// Get an artifact with its data.
+    pub async fn get_artifact(&self, session_id: &str, id: &str) -> Result<Option<ArtifactRow>> {
+        let conn = self.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT id, session_id, tool_call_id, kind, size, preview, data, created_at FROM artifacts WHERE id = ?1 AND session_id = ?2",
+                [id, session_id],
+            )
+            .await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(ArtifactRow {
+                id: row.get::<String>(0)?,
+                session_id: row.get::<String>(1)?,
+                tool_call_id: row.get::<String>(2)?,
+                kind: row.get::<String>(3)?,
+                size: row.get::<i64>(4)? as u64,
+                preview: row.get::<String>(5)?,
+                data: row.get::<Vec<u8>>(6)?,
+                created_at: row.get::<String>(7)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Handles of the artifacts stored in a session, oldest first.
+    pub async fn list_artifacts(&self, session_id: &str) -> Result<Vec<ArtifactRef>> {
+        let conn = self.connect()?;
+        let mut rows = conn
+            .query("SELECT id, kind, size, preview FROM artifacts WHERE session_id = ?1 ORDER BY created_at, rowid", [session_id])
+            .await?;
+        let mut artifacts = Vec::new();
+        while let Some(row) = rows.next().await? {
+            artifacts.push(ArtifactRef {
+                id: row.get::<String>(0)?,
+                kind: row.get::<String>(1)?,
+                size: row.get::<i64>(2)? as u64,
+                preview: row.get::<String>(3)?,
+            });
+        }
+        Ok(artifacts)
+    }
+
+    // ─── Plans ───────────────────────────────────────────────────
+
+    /// Replace a session's plan.
+    pub async fn set_plan(&self, session_id: &str, steps: &[PlanStep]) -> Result<()> {
+        let conn = self.connect()?;
+        let steps_str = serde_json::to_string(steps)?;
+        conn
+            .execute(
+                "INSERT OR REPLACE INTO plans (session_id, steps, updated_at) VALUES (?1, ?2, datetime('now'))",
+                [session_id, steps_str.as_str()],
+            )
+            .await
+            .with_context(|| format!("Failed to store plan for session: {}", session_id))?;
+        Ok(())
+    }
+
+    /// Get a session's plan (empty if none has been set).
+    pub async fn get_plan(&self, session_id: &str) -> Result<Vec<PlanStep>> {
+        let conn = self.connect()?;
+        let mut rows = conn
+            .query("SELECT steps FROM plans WHERE session_id = ?1", [session_id])
+            .await?;
+        match rows.next().await? {
+            Some(row) => {
+                let steps: String = row.get(0)?;
+                serde_json::from_str(&steps).with_context(|| format!("Malformed plan for session: {}", session_id))
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // ─── Session Names ───────────────────────────────────────────
+
+    /// Name a session, replacing any name it had. Fails if another session
+    /// already uses `name`; the unique index keeps that true under races.
+    pub async fn set_session_name(&self, session_id: &str, name: &str) -> Result<()> {
+        if let Some(owner) = self.session_by_name(name).await? {
+            anyhow::ensure!(owner == session_id, "Session name '{}' is already used by session {}", name, owner);
+            return Ok(());
+        }
+        let conn = self.connect()?;
+        // Not INSERT OR REPLACE: on a name clash that would delete the other session's row
+        let renamed = conn
+            .execute("UPDATE session_names SET name = ?2, created_at = datetime('now') WHERE session_id = ?1", [session_id, name])
+            .await
+            .with_context(|| format!("Failed to name session: {}", session_id))?;
+        if renamed == 0 {
+            conn
+                .execute("INSERT INTO session_names (session_id, name) VALUES (?1, ?2)", [session_id, name])
+                .await
+                .with_context(|| format!("Failed to name session: {}", session_id))?;
+        }
+        Ok(())
+    }
+
+    /// A session's name, if it has one.
+    pub async fn session_name(&self, session_id: &str) -> Result<Option<String>> {
+        let conn = self.connect()?;
+        let mut rows = conn
+            .query("SELECT name FROM session_names WHERE session_id = ?1", [session_id])
+            .await?;
+        Ok(match rows.next().await? {
+            Some(row) => Some(row.get(0)?),
+            None => None,
+        })
+    }
+
+    /// The session named `name`, if any.
+    pub(crate) async fn session_by_name(&self, name: &str) -> Result<Option<String>> {
+        let conn = self.connect()?;
+        let mut rows = conn
+            .query("SELECT session_id FROM session_names WHERE name = ?1", [name])
+            .await?;
+        Ok(match rows.next().await? {
+            Some(row) => Some(row.get(0)?),
+            None => None,
+        })
+    }
+
+    /// Resolve a session name to its id; anything else is taken as an id.
+    pub async fn resolve_session(&self, name_or_id: &str) -> Result<String> {
+        Ok(self.session_by_name(name_or_id).await?.unwrap_or_else(|| name_or_id.to_string()))
+    }
+
+    /// `base` if no session uses it yet, otherwise the first free `base-2`,
+    /// `base-3`, ...
+    pub async fn unused_session_name(&self, base: &str) -> Result<String> {
+        let mut name = base.to_string();
+        let mut n = 1;
+        while self.session_by_name(&name).await?.is_some() {
+            n += 1;
+            name = format!("{}-{}", base, n);
+        }
+        Ok(name)
+    }
+
+    // ─── Session Snapshots ───────────────────────────────────────
+
+    /// Replace a session's snapshot. `last_message_id` is the newest message
+    /// row already reflected in `state`.
+    pub async fn put_snapshot(&self, session_id: &str, version: u32, last_message_id: i64, state: &str) -> Result<()> {
+        let conn = self.connect()?;
+        conn
+            .execute(
+                "INSERT OR REPLACE INTO session_snapshots (session_id, version, last_message_id, state, created_at) VALUES (?1, ?2, ?3, ?4, datetime('now'))",
+                turso::params![session_id, version as i64, last_message_id, state],
+            )
+            .await
+            .with_context(|| format!("Failed to store snapshot for session: {}", session_id))?;
+        Ok(())
+    }
+
+    /// Get a session's latest snapshot, if any.
+    pub async fn get_snapshot(&self, session_id: &str) -> Result<Option<SnapshotRow>> {
+        let conn = self.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT session_id, version, last_message_id, state, created_at FROM session_snapshots WHERE session_id = ?1",
+                [session_id],
+            )
+            .await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(SnapshotRow {
+                session_id: row.get::<String>(0)?,
+                version: row.get::<i64>(1)? as u32,
+                last_message_id: row.get::<i64>(2)?,
+                state: row.get::<String>(3)?,
+                created_at: row.get::<String>(4)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Id of a session's newest message row (0 if it has none).
+    pub async fn last_message_id(&self, session_id: &str) -> Result<i64> {
+        let conn = self.connect()?;
+        let mut rows = conn
+            .query("SELECT COALESCE(MAX(id), 0) FROM messages WHERE session_id = ?1", [session_id])
+            .await?;
+        match rows.next().await? {
+            Some(row) => Ok(row.get::<i64>(0)?),
+            None => Ok(0),
+        }
+    }
+
+    // ─── Tool Result Cache ───────────────────────────────────────
+
+    /// Look up a cached tool result that has not yet expired.
+    pub async fn tool_cache_get(&self, tool_name: &str, args_hash: &str) -> Result<Option<String>> {
+        let conn = self.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT output FROM tool_cache WHERE tool_name = ?1 AND args_hash = ?2 AND expires_at > datetime('now')",
+                [tool_name, args_hash],
+            )
+            .await?;
+
+        if let Some(row) = rows.next().await? {
+            Ok(Some(row.get::<String>(0)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Store a tool result in the cross-session cache for `ttl_secs` seconds.
+    pub async fn tool_cache_put(
+        &self,
+        tool_name: &str,
+        args_hash: &str,
+        output: &str,
+        ttl_secs: u64,
+    ) -> Result<()> {
+        let conn = self.connect()?;
+        let ttl_modifier = format!("+{} seconds", ttl_secs);
+        conn
+            .execute(
+                "INSERT OR REPLACE INTO tool_cache (tool_name, args_hash, output, expires_at) VALUES (?1, ?2, ?3, datetime('now', ?4))",
+                turso::params![tool_name, args_hash, output, ttl_modifier],
+            )
+            .await
+            .with_context(|| format!("Failed to cache result for tool: {}", tool_name))?;
+        Ok(())
+    }
+
+    // ─── Memories (Vector + FTS Hybrid Store) ─────────────────────
+
+    /// Insert a memory with an embedding vector.
+    pub async fn insert_memory(
+        &self,
+        session_id: &str,
+        content: &str,
+        vector: &[f32],
+        metadata: &serde_json::Value,
+    ) -> Result<()> {
+        let metadata_str = serde_json::to_string(metadata)?;
+        self.insert_memory_raw(session_id, content, vector, &metadata_str, false).await
+    }
+
+    /// Insert a memory copied from another store. `excluded` memories are kept
+    /// but skipped by search until re-embedded with `update_memory_embedding`.
+    pub async fn insert_imported_memory(
+        &self,
+        session_id: &str,
+        content: &str,
+        vector: &[f32],
+        metadata: &str,
+        excluded: bool,
+    ) -> Result<()> {
+        self.insert_memory_raw(session_id, content, vector, metadata, excluded).await
+    }
+
+    /// Write imported messages and memories in one transaction, so a failed
+    /// import leaves nothing behind. Fails if the store already has messages
+    /// for a session being imported; memories already stored with the same
+    /// session and content are skipped. Returns the number of memories inserted.
+    pub async fn import_records(&self, messages: &[MessageRow], memories: &[StoredMemory]) -> Result<usize> {
+        let mut conn = self.connect()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate).await?;
+        if let Some(session_id) = messages.first().map(|m| m.session_id.as_str()) {
+            let mut rows = tx
+                .query("SELECT 1 FROM messages WHERE session_id = ?1 LIMIT 1", [session_id])
+                .await?;
+            anyhow::ensure!(
+                rows.next().await?.is_none(),
+                "Session '{}' already exists in the target database",
+                session_id
+            );
+        }
+        for msg in messages {
+            tx.execute(
+                "INSERT INTO messages (session_id, turn_index, role, content, token_count, partial, finish_reason) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                turso::params![
+                    msg.session_id.as_str(),
+                    msg.turn_index as i64,
+                    msg.role.as_str(),
+                    msg.content.as_str(),
+                    msg.token_count.map(|t| t as i64),
+                    msg.partial as i64,
+                    msg.finish_reason.as_deref(),
+                ],
+            )
+            .await
+            .with_context(|| format!("Failed to import message {}", msg.id))?;
+        }
+        let mut inserted = 0;
+        for memory in memories {
+            let mut rows = tx
+                .query(
+                    "SELECT 1 FROM memories WHERE session_id = ?1 AND content = ?2 LIMIT 1",
+                    [memory.session_id.as_str(), memory.content.as_str()],
+                )
+                .await?;
+            if rows.next().await?.is_some() {
+                continue;
+            }
+            drop(rows);
+            tx.execute(
+                "INSERT INTO memories (session_id, content, embedding, metadata, excluded) VALUES (?1, ?2, ?3, ?4, ?5)",
+                turso::params![
+                    memory.session_id.as_str(),
+                    memory.content.as_str(),
+                    vector_to_bytes(&memory.embedding),
+                    memory.metadata.as_str(),
+                    memory.excluded as i64,
+                ],
+            )
+            .await
+            .with_context(|| format!("Failed to import memory {}", memory.id))?;
+            inserted += 1;
+        }
+        tx.commit().await?;
+        Ok(inserted)
+    }
+
+    async fn insert_memory_raw(
+        &self,
+        session_id: &str,
+        content: &str,
+        vector: &[f32],
+        metadata: &str,
+        excluded: bool,
+    ) -> Result<()> {
+        let conn = self.connect()?;
+        conn
+            .execute(
+                "INSERT INTO memories (session_id, content, embedding, metadata, excluded) VALUES (?1, ?2, ?3, ?4, ?5)",
+                turso::params![
+                    session_id,
+                    content,
+                    vector_to_bytes(vector),
+                    metadata,
+                    excluded as i64,
+                ],
+            )
+            .await
+            .with_context(|| format!("Failed to insert memory for session: {}", session_id))?;
+        Ok(())
+    }
+
+    /// Get stored memories with their embeddings, optionally for one session.
+    pub async fn get_memories(&self, session_id: Option<&str>) -> Result<Vec<StoredMemory>> {
+        let conn = self.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT id, session_id, content, metadata, embedding, excluded FROM memories WHERE ?1 IS NULL OR session_id = ?1 ORDER BY id",
+                turso::params![session_id],
+            )
+            .await?;
+
+        let mut memories = Vec::new();
+        while let Some(row) = rows.next().await? {
+            memories.push(StoredMemory {
+                id: row.get::<i64>(0)?,
+                session_id: row.get::<String>(1)?,
+                content: row.get::<String>(2)?,
+                metadata: row.get::<Option<String>>(3)?.unwrap_or_default(),
+                embedding: bytes_to_vector(&row.get::<Option<Vec<u8>>>(4)?.unwrap_or_default()),
+                excluded: row.get::<i64>(5)? != 0,
+            });
+        }
+        Ok(memories)
+    }
+
+    /// Replace a memory's embedding and make it searchable again.
+    pub async fn update_memory_embedding(&self, id: i64, vector: &[f32]) -> Result<()> {
+        let conn = self.connect()?;
+        conn
+            .execute(
+                "UPDATE memories SET embedding = ?1, excluded = 0 WHERE id = ?2",
+                turso::params![vector_to_bytes(vector), id],
+            )
+            .await
+            .with_context(|| format!("Failed to update embedding for memory: {}", id))?;
+        Ok(())
+    }
+
+    /// Summarize the memory store, optionally for one session: counts per
+    /// session, content sizes, embedding dimensions, date range and how many
+    /// memories duplicate an earlier one, exactly or by embedding similarity.
+    pub async fn memory_stats(&self, session_id: Option<&str>) -> Result<MemoryStats> {
+        use std::collections::{BTreeMap, HashMap, HashSet};
+
+        let conn = self.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT session_id, content, embedding, excluded, created_at FROM memories WHERE ?1 IS NULL OR session_id = ?1 ORDER BY id",
+                turso::params![session_id],
+            )
+            .await
+            .context("Failed to query memories for stats")?;
+
+        let mut stats = MemoryStats::default();
+        let mut sessions: HashMap<String, u64> = HashMap::new();
+        let mut dimensions: BTreeMap<usize, u64> = BTreeMap::new();
+        let mut seen_content: HashSet<String> = HashSet::new();
+        let mut sample: Vec<Vec<f32>> = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let content: String = row.get(1)?;
+            let embedding = bytes_to_vector(&row.get::<Option<Vec<u8>>>(2)?.unwrap_or_default());
+            let created_at: String = row.get(4)?;
+
+            stats.total += 1;
+            *sessions.entry(row.get(0)?).or_default() += 1;
+            stats.total_chars += content.chars().count() as u64;
+            if row.get::<i64>(3)? != 0 {
+                stats.excluded += 1;
+            }
+            *dimensions.entry(embedding.len()).or_default() += 1;
+            if stats.oldest.as_ref().is_none_or(|oldest| created_at < *oldest) {
+                stats.oldest = Some(created_at.clone());
+            }
+            if stats.newest.as_ref().is_none_or(|newest| created_at > *newest) {
+                stats.newest = Some(created_at);
+            }
+            if !seen_content.insert(content) {
+                stats.exact_duplicates += 1;
+            } else if !embedding.is_empty() && sample.len() < NEAR_DUPLICATE_SAMPLE {
+                if sample.iter().any(|other| cosine_similarity(other, &embedding) >= NEAR_DUPLICATE_SIMILARITY) {
+                    stats.near_duplicates += 1;
+                }
+                sample.push(embedding);
+            }
+        }
+
+        stats.avg_chars = if stats.total > 0 { stats.total_chars as f64 / stats.total as f64 } else { 0.0 };
+        stats.near_duplicate_sample = sample.len() as u64;
+        stats.dimensions = dimensions.into_iter().map(|(dimension, count)| DimensionCount { dimension, count }).collect();
+        stats.sessions = sessions.into_iter().map(|(session_id, count)| SessionMemoryCount { session_id, count }).collect();
+        stats.sessions.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.session_id.cmp(&b.session_id)));
+        Ok(stats)
+    }
+
+    /// Search memories using Hybrid Search (Vector + FTS5).
+    /// 
+    /// Uses Reciprocal Rank Fusion (RRF) to combine results.
+    /// - `vector`: Optional embedding for semantic search.
+    /// - `content_query`: Optional keyword string for FTS search. if None, relies only on vector.
+    pub async fn search_memories(
+        &self,
+        session_id: &str,
+        vector: Option<&[f32]>,
+        content_query: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<MemoryRow>> {
+        use std::collections::HashMap;
+
+        // RRF constant k (usually 60)
+        const RRF_K: f64 = 60.0;
+        let mut scores: HashMap<i64, f64> = HashMap::new();
+        let mut rows_data: HashMap<i64, MemoryRow> = HashMap::new();
+
+        let conn = self.connect()?;
+
+        // 1. Vector Search
+        if let Some(vec) = vector {
+            let vector_bytes = vector_to_bytes(vec);
+
+            let mut rows = conn.query(
+                "SELECT id, session_id, content, metadata, created_at, vector_distance_cos(embedding, ?1) as distance 
+                 FROM memories 
+                 WHERE session_id = ?2 AND excluded = 0 
+                 ORDER BY distance ASC 
+                 LIMIT ?3",
+                turso::params![vector_bytes, session_id, limit as i64],
+            ).await.context("Failed to search memories (vector)")?;
+
+            let mut rank = 1;
             while let Some(row) = rows.next().await? {
                 let id: i64 = row.get(0)?;
                 
@@ -506,7 +1607,7 @@ impl StateStore {
                             if !rows_data.contains_key(&id) {
                                     // Fetch full row data
                                 let mut full_row_q = conn.query(
-                                    "SELECT session_id, content, metadata, created_at FROM memories WHERE id = ?1", 
+                                    "SELECT session_id, content, metadata, created_at FROM memories WHERE id = ?1 AND excluded = 0", 
                                     [id]
                                 ).await?;
                                 if let Some(full_row) = full_row_q.next().await? {
@@ -521,6 +1622,11 @@ impl StateStore {
                                 }
                             }
 
+                            // Excluded (or deleted) memories have no row data
+                            if !rows_data.contains_key(&id) {
+                                continue;
+                            }
+
                             // RRF score addition
                             // Note: If ID was found in Vector search, it gets a score boost here.
                             let rrf = 1.0 / (RRF_K + rank as f64);
@@ -559,7 +1665,7 @@ impl StateStore {
                 let terms: Vec<&str> = query.split_whitespace().collect();
                 if !terms.is_empty() {
                     // "SELECT ... FROM memories WHERE session_id = ? AND (content LIKE ? OR content LIKE ? ...) ORDER BY created_at DESC LIMIT ?"
-                    let mut sql = "SELECT id, session_id, content, metadata, created_at FROM memories WHERE session_id = ?1 AND (".to_string();
+                    let mut sql = "SELECT id, session_id, content, metadata, created_at FROM memories WHERE session_id = ?1 AND excluded = 0 AND (".to_string();
                     let mut params = vec![turso::Value::from(session_id.to_string())];
                     
                     for (i, term) in terms.iter().enumerate() {
@@ -623,7 +1729,7 @@ impl StateStore {
             );
         }
 
-        let conn = self.db.connect()?;
+        let conn = self.connect()?;
         conn
             .execute(
                 "INSERT OR REPLACE INTO harness_kv (key, value, updated_at) VALUES (?1, ?2, datetime('now'))",
@@ -636,28 +1742,150 @@ impl StateStore {
 
     /// Get a value from the harness store.
     pub async fn kv_get(&self, key: &str) -> Result<Option<String>> {
-        let conn = self.db.connect()?;
+        let conn = self.connect()?;
+        let mut rows = conn
+            .query(
+                "SELECT value FROM harness_kv WHERE key = ?1 AND (expires_at IS NULL OR expires_at > datetime('now'))",
+                [key],
+            )
+            .await?;
+
+        if let Some(row) = rows.next().await? {
+            Ok(Some(row.get::<String>(0)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Delete a key from the harness store.
+    pub async fn kv_delete(&self, key: &str) -> Result<()> {
+        let conn = self.connect()?;
+        conn
+            .execute("DELETE FROM harness_kv WHERE key = ?1", [key])
+            .await?;
+        Ok(())
+    }
+
+    // ─── Harness Records ─────────────────────────────────────────
+
+    /// Store `data` as record `id` of `collection`, replacing any record with
+    /// that id in the same scope. `session_id` of `None` stores a global record.
+    pub async fn records_put(&self, collection: &str, id: &str, session_id: Option<&str>, data: &serde_json::Value) -> Result<()> {
+        let json = serde_json::to_string(data)?;
+        anyhow::ensure!(
+            json.len() <= MAX_RECORD_SIZE,
+            "Record exceeds maximum size of {} bytes (got {})",
+            MAX_RECORD_SIZE,
+            json.len()
+        );
+        let scope = session_id.unwrap_or("");
+        let conn = self.connect()?;
+        // Update in place so the record keeps its created_at and position in query results
+        let updated = conn
+            .execute(
+                "UPDATE harness_records SET json = ?1, updated_at = datetime('now') WHERE collection = ?2 AND session_id = ?3 AND id = ?4",
+                [json.as_str(), collection, scope, id],
+            )
+            .await
+            .with_context(|| format!("Failed to update record '{}' in '{}'", id, collection))?;
+        if updated == 0 {
+            conn.execute(
+                "INSERT INTO harness_records (collection, id, session_id, json) VALUES (?1, ?2, ?3, ?4)",
+                [collection, id, scope, json.as_str()],
+            )
+            .await
+            .with_context(|| format!("Failed to insert record '{}' into '{}'", id, collection))?;
+        }
+        Ok(())
+    }
+
+    /// Up to `limit` records of `collection` whose fields match `filter`, oldest
+    /// first. Each filter key is a dotted path into the record (`owner.name`,
+    /// `tags.0`) whose value must equal the filter value. `session_id` of
+    /// `None` queries global records.
+    pub async fn records_query(
+        &self,
+        collection: &str,
+        session_id: Option<&str>,
+        filter: &serde_json::Map<String, serde_json::Value>,
+        limit: usize,
+    ) -> Result<Vec<HarnessRecord>> {
+        let conn = self.connect()?;
         let mut rows = conn
             .query(
-                "SELECT value FROM harness_kv WHERE key = ?1 AND (expires_at IS NULL OR expires_at > datetime('now'))",
-                [key],
+                "SELECT rowid, id, session_id, json, created_at, updated_at FROM harness_records \
+                 WHERE collection = ?1 AND session_id = ?2 ORDER BY rowid",
+                [collection, session_id.unwrap_or("")],
             )
             .await?;
-
-        if let Some(row) = rows.next().await? {
-            Ok(Some(row.get::<String>(0)?))
-        } else {
-            Ok(None)
+        let mut records = Vec::new();
+        while records.len() < limit {
+            let Some(row) = rows.next().await? else { break };
+            let Some(data) = parse_column("harness_records", row.get(0)?, &row.get::<String>(3)?) else { continue };
+            if !filter.iter().all(|(path, expected)| json_path(&data, path) == Some(expected)) {
+                continue;
+            }
+            let scope: String = row.get(2)?;
+            records.push(HarnessRecord {
+                collection: collection.to_string(),
+                id: row.get(1)?,
+                session_id: (!scope.is_empty()).then_some(scope),
+                data,
+                created_at: row.get(4)?,
+                updated_at: row.get(5)?,
+            });
         }
+        Ok(records)
     }
 
-    /// Delete a key from the harness store.
-    pub async fn kv_delete(&self, key: &str) -> Result<()> {
-        let conn = self.db.connect()?;
-        conn
-            .execute("DELETE FROM harness_kv WHERE key = ?1", [key])
-            .await?;
-        Ok(())
+    // ─── Maintenance ─────────────────────────────────────────────
+
+    /// Delete up to `limit` expired harness KV entries and tool cache rows.
+    pub async fn delete_expired(&self, limit: usize) -> Result<u64> {
+        let kv = self.delete_batch("harness_kv", "key", "expires_at IS NOT NULL AND expires_at <= datetime('now')", limit).await?;
+        let cache = self.delete_batch("tool_cache", "rowid", "expires_at <= datetime('now')", limit).await?;
+        Ok(kv + cache)
+    }
+
+    /// Delete up to `limit` events and raw responses older than `days`.
+    pub async fn delete_log_rows_older_than(&self, days: u32, limit: usize) -> Result<u64> {
+        let cutoff = format!("created_at < datetime('now', '-{} days')", days);
+        let events = self.delete_batch("events", "id", &cutoff, limit).await?;
+        let raw = self.delete_batch("raw_responses", "id", &cutoff, limit).await?;
+        Ok(events + raw)
+    }
+
+    /// Delete up to `limit` memories older than `days`.
+    pub async fn delete_memories_older_than(&self, days: u32, limit: usize) -> Result<u64> {
+        let cutoff = format!("created_at < datetime('now', '-{} days')", days);
+        self.delete_batch("memories", "id", &cutoff, limit).await
+    }
+
+    /// Delete up to `limit` rows of `table` matching `condition` in one
+    /// immediate transaction, so the write lock is taken up front and held
+    /// only for a single small batch.
+    async fn delete_batch(&self, table: &str, key: &str, condition: &str, limit: usize) -> Result<u64> {
+        let mut conn = self.connect()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate).await?;
+        let mut keys = Vec::new();
+        let mut rows = tx.query(&format!("SELECT {key} FROM {table} WHERE {condition} LIMIT {limit}"), ()).await?;
+        while let Some(row) = rows.next().await? {
+            keys.push(row.get_value(0)?);
+        }
+        drop(rows);
+        if keys.is_empty() {
+            tx.commit().await?;
+            return Ok(0);
+        }
+        // IN (subquery) isn't supported, so the keys are bound one by one.
+        // The transaction holds the write lock, so exactly these rows go.
+        let deleted = keys.len() as u64;
+        let placeholders = vec!["?"; keys.len()].join(", ");
+        tx.execute(&format!("DELETE FROM {table} WHERE {key} IN ({placeholders})"), turso::params_from_iter(keys))
+            .await
+            .with_context(|| format!("Failed to prune {}", table))?;
+        tx.commit().await?;
+        Ok(deleted)
     }
 
     /// Get a new database connection (for advanced operations).
@@ -674,6 +1902,30 @@ impl StateStore {
 
 // ─── Row Types ───────────────────────────────────────────────
 
+/// A stored session as listed by [`StateStore::list_sessions`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SessionListing {
+    pub id: String,
+    pub name: Option<String>,
+}
+
+/// Overview of a stored session, from [`StateStore::session_summary`].
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SessionSummary {
+    pub session_id: String,
+    pub name: Option<String>,
+    /// Time of the first and last stored event (UTC)
+    pub started_at: Option<String>,
+    pub last_active_at: Option<String>,
+    pub turns: u32,
+    pub messages: u64,
+    pub tool_executions: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Estimated spend, from the `turn_costs` table
+    pub cost_usd: f64,
+}
+
 /// A row from the `events` table.
 #[derive(Debug, Clone)]
 pub struct EventRow {
@@ -694,6 +1946,99 @@ pub struct MessageRow {
     pub content: String,
     pub token_count: Option<u64>,
     pub created_at: String,
+    /// Assistant message still streaming (or interrupted mid-stream)
+    pub partial: bool,
+    /// Provider stop/finish reason, for completed assistant messages
+    pub finish_reason: Option<String>,
+}
+
+impl EventRow {
+    /// Read a row selected as `id, session_id, event_type, payload, created_at`.
+    fn from_row(row: &turso::Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get::<i64>(0)?,
+            session_id: row.get::<String>(1)?,
+            event_type: row.get::<String>(2)?,
+            payload: row.get::<String>(3)?,
+            created_at: row.get::<String>(4)?,
+        })
+    }
+
+    /// The parsed payload, or `None` (with a warning) if the stored JSON is
+    /// malformed, e.g. after a crash mid-write or a manual edit.
+    pub fn payload_json(&self) -> Option<serde_json::Value> {
+        parse_column("events", self.id, &self.payload)
+    }
+}
+
+impl MessageRow {
+    /// The parsed content, or `None` (with a warning) if the stored JSON is
+    /// malformed. Readers skip such rows or show the raw `content` instead of
+    /// failing the whole session.
+    pub fn content_json(&self) -> Option<serde_json::Value> {
+        parse_column("messages", self.id, &self.content)
+    }
+}
+
+/// The value at a dotted `path` (`a.b.0`; a leading `$.` is ignored) in `value`.
+fn json_path<'a>(value: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    let path = path.strip_prefix("$.").unwrap_or(path);
+    path.split('.').try_fold(value, |value, key| match value {
+        serde_json::Value::Object(map) => map.get(key),
+        serde_json::Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+fn parse_column(table: &str, id: i64, raw: &str) -> Option<serde_json::Value> {
+    match serde_json::from_str(raw) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            tracing::warn!(table, id, error = %e, "Malformed JSON in stored row");
+            None
+        }
+    }
+}
+
+/// A page size as an SQL `LIMIT` (SQLite treats a negative limit as none).
+fn page_limit(limit: usize) -> i64 {
+    i64::try_from(limit).unwrap_or(-1)
+}
+
+/// Keyset pagination as a stream: `fetch(after_id)` returns the rows after
+/// `after_id` (at most [`STREAM_PAGE_SIZE`]), and `id` gives a row's cursor.
+/// Only one page is held at a time.
+fn paginate<'a, T, F, Fut>(fetch: F, id: fn(&T) -> i64) -> impl Stream<Item = Result<T>> + 'a
+where
+    T: 'a,
+    F: Fn(i64) -> Fut + 'a,
+    Fut: std::future::Future<Output = Result<Vec<T>>> + 'a,
+{
+    futures::stream::try_unfold((fetch, Some(0)), move |(fetch, cursor)| async move {
+        let Some(after_id) = cursor else {
+            return anyhow::Ok(None);
+        };
+        let page = fetch(after_id).await?;
+        // A short page is the last one
+        let next = match page.last() {
+            Some(last) if page.len() >= STREAM_PAGE_SIZE => Some(id(last)),
+            _ => None,
+        };
+        anyhow::Ok(Some((futures::stream::iter(page.into_iter().map(anyhow::Ok)), (fetch, next))))
+    })
+    .try_flatten()
+}
+
+/// A row from the `session_snapshots` table.
+#[derive(Debug, Clone)]
+pub struct SnapshotRow {
+    pub session_id: String,
+    /// Snapshot format version the state was written with
+    pub version: u32,
+    pub last_message_id: i64,
+    /// Serialized session state (JSON)
+    pub state: String,
+    pub created_at: String,
 }
 
 /// A row from the `tool_executions` table.
@@ -712,6 +2057,208 @@ pub struct ToolExecutionRow {
     pub created_at: String,
 }
 
+/// Selection for `StateStore::query_events`.
+#[derive(Debug, Clone)]
+pub struct EventFilter {
+    /// Event types to include (all when empty)
+    pub event_types: Vec<String>,
+    /// Event types to leave out
+    pub exclude_types: Vec<String>,
+    /// Most recent events to return
+    pub limit: usize,
+}
+
+/// Scope for `StateStore::tool_stats` and `StateStore::finish_reason_stats`. Times are SQLite datetime strings
+/// (e.g. `2026-01-31` or `2026-01-31 12:00:00`, UTC).
+#[derive(Debug, Clone, Default)]
+pub struct ToolStatsFilter {
+    pub session_id: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+}
+
+/// Reject `since`/`until` values that don't parse as a datetime. `datetime()`
+/// yields NULL or an empty string for those, which would silently filter
+/// everything or nothing.
+async fn check_time_range(conn: &Connection, filter: &ToolStatsFilter) -> Result<()> {
+    for (flag, value) in [("since", &filter.since), ("until", &filter.until)] {
+        let Some(value) = value else { continue };
+        let mut rows = conn.query("SELECT datetime(?1)", [value.as_str()]).await?;
+        let parsed = match rows.next().await? {
+            Some(row) => row.get::<Option<String>>(0)?,
+            None => None,
+        };
+        anyhow::ensure!(
+            parsed.is_some_and(|p| !p.is_empty()),
+            "Invalid --{} time '{}': expected e.g. 2026-01-31 or \"2026-01-31 12:00:00\"",
+            flag,
+            value
+        );
+    }
+    Ok(())
+}
+
+/// Aggregated execution metrics for one tool.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct ToolStats {
+    pub tool_name: String,
+    pub calls: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+    /// Latency percentiles over executions with a recorded duration
+    pub p50_ms: Option<u64>,
+    pub p95_ms: Option<u64>,
+    pub max_ms: Option<u64>,
+}
+
+/// Number of assistant messages that ended with one finish reason.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct FinishReasonCount {
+    pub finish_reason: Option<String>,
+    pub count: u64,
+    /// Fraction of all counted messages
+    pub share: f64,
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[u64], pct: f64) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// A row from the `raw_responses` table.
+#[derive(Debug, Clone)]
+pub struct RawResponseRow {
+    pub id: i64,
+    pub session_id: String,
+    pub turn_index: u32,
+    pub provider: String,
+    pub model: String,
+    pub body: String,
+    pub created_at: String,
+}
+
+/// A row from the `turn_costs` table.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TurnCostRow {
+    pub turn_index: u32,
+    pub provider: String,
+    pub model: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// `None` when the model had no pricing
+    pub cost_usd: Option<f64>,
+    pub created_at: String,
+}
+
+/// A record from the `harness_records` table.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct HarnessRecord {
+    pub collection: String,
+    pub id: String,
+    /// The owning session, or `None` for a global record
+    pub session_id: Option<String>,
+    pub data: serde_json::Value,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// One step of an agent plan (see `StateStore::set_plan`).
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PlanStep {
+    pub title: String,
+    #[serde(default)]
+    pub status: PlanStatus,
+}
+
+/// Progress of a plan step.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanStatus {
+    #[default]
+    Pending,
+    InProgress,
+    Done,
+}
+
+/// A row from the `tool_sources` table.
+#[derive(Debug, Clone)]
+pub struct ToolSourceRow {
+    pub id: i64,
+    pub session_id: String,
+    pub turn_index: u32,
+    pub tool_call_id: String,
+    pub tool_name: String,
+    pub kind: String,
+    /// JSON-serialized `tools::Source`
+    pub source: String,
+    pub created_at: String,
+}
+
+/// A row from the `artifacts` table.
+#[derive(Debug, Clone)]
+pub struct ArtifactRow {
+    pub id: String,
+    pub session_id: String,
+    pub tool_call_id: String,
+    pub kind: String,
+    pub size: u64,
+    pub preview: String,
+    pub data: Vec<u8>,
+    pub created_at: String,
+}
+
+/// A memory with its embedding, as stored (see `StateStore::get_memories`).
+#[derive(Debug, Clone)]
+pub struct StoredMemory {
+    pub id: i64,
+    pub session_id: String,
+    pub content: String,
+    pub metadata: String,
+    pub embedding: Vec<f32>,
+    /// Excluded from search until re-embedded
+    pub excluded: bool,
+}
+
+/// Summary of the memory store (see `StateStore::memory_stats`).
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct MemoryStats {
+    pub total: u64,
+    /// Excluded from search until re-embedded (`bedrock reindex`)
+    pub excluded: u64,
+    /// Memory counts per session, largest first
+    pub sessions: Vec<SessionMemoryCount>,
+    pub total_chars: u64,
+    pub avg_chars: f64,
+    /// Embedding lengths found, with how many memories have each (0 = no embedding)
+    pub dimensions: Vec<DimensionCount>,
+    pub oldest: Option<String>,
+    pub newest: Option<String>,
+    /// Memories whose content repeats an earlier memory's exactly
+    pub exact_duplicates: u64,
+    /// Memories at least `NEAR_DUPLICATE_SIMILARITY` similar to an earlier one,
+    /// among the first `near_duplicate_sample` distinct embedded memories
+    pub near_duplicates: u64,
+    pub near_duplicate_sample: u64,
+}
+
+/// Number of memories stored by one session.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SessionMemoryCount {
+    pub session_id: String,
+    pub count: u64,
+}
+
+/// Number of memories with embeddings of one length.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct DimensionCount {
+    pub dimension: usize,
+    pub count: u64,
+}
+
 /// A row from the `memories` table.
 #[derive(Debug, Clone)]
 pub struct MemoryRow {
@@ -723,6 +2270,29 @@ pub struct MemoryRow {
     pub score: f64,
 }
 
+/// Encode an embedding as a little-endian F32 blob.
+fn vector_to_bytes(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// Cosine similarity of two embeddings; 0 for mismatched lengths or zero vectors.
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denom = norm(a) * norm(b);
+    if denom == 0.0 { 0.0 } else { dot / denom }
+}
+
+fn bytes_to_vector(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
 // ─── Tests ───────────────────────────────────────────────────
 
 #[cfg(test)]
@@ -790,11 +2360,11 @@ mod tests {
         let session = "test-session";
 
         store
-            .insert_message(session, 0, "user", &json!([{"type": "text", "text": "hello"}]), None)
+            .insert_message(session, 0, "user", &json!([{"type": "text", "text": "hello"}]), None, None)
             .await
             .unwrap();
         store
-            .insert_message(session, 0, "assistant", &json!([{"type": "text", "text": "hi!"}]), Some(10))
+            .insert_message(session, 0, "assistant", &json!([{"type": "text", "text": "hi!"}]), Some(10), None)
             .await
             .unwrap();
 
@@ -805,6 +2375,54 @@ mod tests {
         assert_eq!(msgs[1].token_count, Some(10));
     }
 
+    #[tokio::test]
+    async fn test_fork_session() {
+        let store = StateStore::open_memory().await.unwrap();
+        for turn in 0..3 {
+            let text = format!("turn {}", turn);
+            store.insert_message("orig", turn, "user", &json!([{"type": "text", "text": text}]), None, None).await.unwrap();
+            store.insert_message("orig", turn, "assistant", &json!([{"type": "text", "text": text}]), Some(5), None).await.unwrap();
+        }
+
+        let fork = store.fork_session("orig", 2).await.unwrap();
+        let msgs = store.get_messages(&fork).await.unwrap();
+        assert_eq!(msgs.len(), 4);
+        assert_eq!(msgs.iter().map(|m| m.turn_index).max(), Some(1));
+        assert_eq!(msgs[3].token_count, Some(5));
+        assert_eq!(store.get_messages("orig").await.unwrap().len(), 6);
+
+        assert!(store.fork_session("orig", 0).await.is_err());
+        assert!(store.fork_session("missing", 2).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_paged_and_streamed_reads() {
+        let store = StateStore::open_memory().await.unwrap();
+        for i in 0..STREAM_PAGE_SIZE * 2 + 3 {
+            store.insert_message("big", i as u32, "user", &json!([{"type": "text", "text": i.to_string()}]), None, None).await.unwrap();
+            if i % 100 == 0 {
+                store.insert_message("other", 0, "user", &json!([]), None, None).await.unwrap();
+            }
+        }
+        // Exactly one full page: the stream must stop on the empty page after it
+        for i in 0..STREAM_PAGE_SIZE {
+            store.insert_event("big", "turn_start", &json!({ "turn_index": i })).await.unwrap();
+        }
+
+        let first = store.get_messages_page("big", 0, 2).await.unwrap();
+        assert_eq!(first.iter().map(|m| m.turn_index).collect::<Vec<_>>(), vec![0, 1]);
+        let next = store.get_messages_page("big", first[1].id, 2).await.unwrap();
+        assert_eq!(next.iter().map(|m| m.turn_index).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(store.get_events_page("big", 0, 3).await.unwrap().len(), 3);
+
+        let streamed: Vec<MessageRow> = store.stream_messages("big").try_collect().await.unwrap();
+        assert_eq!(streamed.len(), STREAM_PAGE_SIZE * 2 + 3);
+        assert!(streamed.iter().enumerate().all(|(i, m)| m.turn_index == i as u32 && m.session_id == "big"));
+        let events: Vec<EventRow> = store.stream_events("big").try_collect().await.unwrap();
+        assert_eq!(events.len(), STREAM_PAGE_SIZE);
+        assert_eq!(store.stream_events("missing").try_collect::<Vec<_>>().await.unwrap().len(), 0);
+    }
+
     #[tokio::test]
     async fn test_insert_and_get_tool_executions() {
         let store = StateStore::open_memory().await.unwrap();
@@ -860,6 +2478,201 @@ mod tests {
         assert_eq!(execs[0].verdict, "reject");
     }
 
+    #[tokio::test]
+    async fn test_upsert_partial_assistant_message() {
+        let store = StateStore::open_memory().await.unwrap();
+        let session = "stream-session";
+
+        store.upsert_assistant_message(session, 0, &json!([{"type": "text", "text": "Hel"}]), true, None).await.unwrap();
+        store.upsert_assistant_message(session, 0, &json!([{"type": "text", "text": "Hello wor"}]), true, None).await.unwrap();
+
+        let msgs = store.get_messages(session).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert!(msgs[0].partial);
+        assert!(msgs[0].content.contains("Hello wor"));
+
+        store.upsert_assistant_message(session, 0, &json!([{"type": "text", "text": "Hello world"}]), false, Some("stop")).await.unwrap();
+        let msgs = store.get_messages(session).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert!(!msgs[0].partial);
+        assert!(msgs[0].content.contains("Hello world"));
+        assert_eq!(msgs[0].finish_reason.as_deref(), Some("stop"));
+
+        // A finalized message is never overwritten by a later turn's partial
+        store.upsert_assistant_message(session, 1, &json!([{"type": "text", "text": "Next"}]), true, None).await.unwrap();
+        let msgs = store.get_messages(session).await.unwrap();
+        assert_eq!(msgs.len(), 2);
+        assert!(msgs[1].partial);
+    }
+
+    #[tokio::test]
+    async fn test_migration_adds_partial_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.db");
+        let path = path.to_str().unwrap();
+        {
+            let db = turso::Builder::new_local(path).build().await.unwrap();
+            let conn = db.connect().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE messages (id INTEGER PRIMARY KEY AUTOINCREMENT, session_id TEXT NOT NULL, turn_index INTEGER NOT NULL, role TEXT NOT NULL, content TEXT NOT NULL, token_count INTEGER, created_at TEXT NOT NULL DEFAULT (datetime('now')));
+                 INSERT INTO messages (session_id, turn_index, role, content) VALUES ('old', 0, 'user', '[]');",
+            ).await.unwrap();
+        }
+
+        let store = StateStore::open(path).await.unwrap();
+        let msgs = store.get_messages("old").await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert!(!msgs[0].partial);
+        assert!(msgs[0].finish_reason.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_raw_responses_redacted_and_keyed_by_turn() {
+        let store = StateStore::open_memory().await.unwrap();
+        let session = "raw-session";
+
+        store
+            .insert_raw_response(session, 0, "anthropic", "claude", &json!({"events": [], "api_key": "sk-abcdef1234567890"}))
+            .await
+            .unwrap();
+        store
+            .insert_raw_response(session, 1, "anthropic", "claude", &json!({"events": [{"type": "message_end"}]}))
+            .await
+            .unwrap();
+
+        let turn0 = store.get_raw_responses(session, 0).await.unwrap();
+        assert_eq!(turn0.len(), 1);
+        assert!(!turn0[0].body.contains("sk-abcdef"));
+        assert!(turn0[0].body.contains("[REDACTED]"));
+
+        let turn1 = store.get_raw_responses(session, 1).await.unwrap();
+        assert_eq!(turn1.len(), 1);
+        assert!(turn1[0].body.contains("message_end"));
+        assert!(store.get_raw_responses(session, 2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_memory_stats() {
+        let store = StateStore::open_memory().await.unwrap();
+        let empty = store.memory_stats(None).await.unwrap();
+        assert_eq!((empty.total, empty.avg_chars, empty.oldest.clone()), (0, 0.0, None));
+
+        let meta = json!({});
+        store.insert_memory("s1", "cats purr", &[1.0, 0.0], &meta).await.unwrap();
+        store.insert_memory("s1", "cats purr", &[1.0, 0.0], &meta).await.unwrap();
+        store.insert_memory("s1", "kittens purr", &[0.99, 0.05], &meta).await.unwrap();
+        store.insert_memory("s2", "dogs bark", &[0.0, 1.0], &meta).await.unwrap();
+        store.insert_imported_memory("s2", "foreign", &[0.5, 0.5, 0.5], "{}", true).await.unwrap();
+
+        let stats = store.memory_stats(None).await.unwrap();
+        assert_eq!((stats.total, stats.excluded), (5, 1));
+        assert_eq!(stats.sessions[0], SessionMemoryCount { session_id: "s1".to_string(), count: 3 });
+        assert_eq!(stats.total_chars, 9 + 9 + 12 + 9 + 7);
+        assert_eq!(stats.dimensions, vec![DimensionCount { dimension: 2, count: 4 }, DimensionCount { dimension: 3, count: 1 }]);
+        assert!(stats.oldest.is_some() && stats.oldest <= stats.newest);
+        // The repeated "cats purr" is an exact duplicate; "kittens purr" is a near one
+        assert_eq!((stats.exact_duplicates, stats.near_duplicates, stats.near_duplicate_sample), (1, 1, 4));
+
+        let scoped = store.memory_stats(Some("s2")).await.unwrap();
+        assert_eq!((scoped.total, scoped.sessions.len(), scoped.exact_duplicates), (2, 1, 0));
+    }
+
+    #[tokio::test]
+    async fn test_tool_stats() {
+        let store = StateStore::open_memory().await.unwrap();
+        for (i, ms) in (1..=20).enumerate() {
+            let session = if i % 2 == 0 { "s1" } else { "s2" };
+            store.insert_tool_execution(session, 0, &format!("r{}", i), "read_file", &json!({}), None, i == 0, Some(ms), "allow").await.unwrap();
+        }
+        store.insert_tool_execution("s1", 0, "w0", "write_file", &json!({}), None, false, None, "allow").await.unwrap();
+
+        let stats = store.tool_stats(&ToolStatsFilter::default()).await.unwrap();
+        assert_eq!(stats.len(), 2);
+        let read = &stats[0];
+        assert_eq!(read.tool_name, "read_file");
+        assert_eq!((read.calls, read.errors), (20, 1));
+        assert!((read.error_rate - 0.05).abs() < 1e-9);
+        assert_eq!((read.p50_ms, read.p95_ms, read.max_ms), (Some(10), Some(19), Some(20)));
+        assert_eq!(stats[1].p95_ms, None);
+
+        let scoped = store.tool_stats(&ToolStatsFilter { session_id: Some("s2".into()), ..Default::default() }).await.unwrap();
+        assert_eq!(scoped.len(), 1);
+        assert_eq!(scoped[0].calls, 10);
+
+        let future = store.tool_stats(&ToolStatsFilter { since: Some("2999-01-01".into()), ..Default::default() }).await.unwrap();
+        assert!(future.is_empty());
+
+        let invalid = ToolStatsFilter { since: Some("last tuesday".into()), ..Default::default() };
+        assert!(store.tool_stats(&invalid).await.unwrap_err().to_string().contains("--since"));
+        assert!(store.finish_reason_stats(&invalid).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_finish_reason_stats() {
+        let store = StateStore::open_memory().await.unwrap();
+        let text = json!([{"type": "text", "text": "x"}]);
+        for (turn, reason) in [(0, Some("stop")), (1, Some("length")), (2, Some("stop")), (3, None)] {
+            store.upsert_assistant_message("s1", turn, &text, false, reason).await.unwrap();
+        }
+        // Partial and non-assistant rows are not counted
+        store.upsert_assistant_message("s1", 4, &text, true, None).await.unwrap();
+        store.insert_message("s1", 0, "user", &text, None, None).await.unwrap();
+        store.upsert_assistant_message("s2", 0, &text, false, Some("tool_calls")).await.unwrap();
+
+        let counts = store.finish_reason_stats(&ToolStatsFilter { session_id: Some("s1".into()), ..Default::default() }).await.unwrap();
+        let pairs: Vec<_> = counts.iter().map(|c| (c.finish_reason.as_deref(), c.count)).collect();
+        assert_eq!(pairs, vec![(Some("stop"), 2), (None, 1), (Some("length"), 1)]);
+        assert!((counts[0].share - 0.5).abs() < 1e-9);
+
+        let all = store.finish_reason_stats(&ToolStatsFilter::default()).await.unwrap();
+        assert_eq!(all.iter().map(|c| c.count).sum::<u64>(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_tool_sources() {
+        let store = StateStore::open_memory().await.unwrap();
+        let session = "test-session";
+
+        store.insert_tool_source(session, 0, "call-1", "read_file", "file", &json!({"kind": "file", "path": "a.rs"})).await.unwrap();
+        store.insert_tool_source(session, 1, "call-2", "recall", "memory", &json!({"kind": "memory", "session_id": "s0", "memory_id": 7})).await.unwrap();
+
+        let all = store.get_tool_sources(session, None).await.unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].tool_call_id, "call-1");
+        assert_eq!(all[1].kind, "memory");
+
+        let turn1 = store.get_tool_sources(session, Some(1)).await.unwrap();
+        assert_eq!(turn1.len(), 1);
+        assert!(turn1[0].source.contains("\"memory_id\":7"));
+        assert!(store.get_tool_sources("other", None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tool_cache_put_get() {
+        let store = StateStore::open_memory().await.unwrap();
+
+        assert_eq!(store.tool_cache_get("fetch_doc", "abc").await.unwrap(), None);
+
+        store.tool_cache_put("fetch_doc", "abc", "cached body", 3600).await.unwrap();
+        let val = store.tool_cache_get("fetch_doc", "abc").await.unwrap();
+        assert_eq!(val, Some("cached body".to_string()));
+
+        // Same key overwrites
+        store.tool_cache_put("fetch_doc", "abc", "fresh body", 3600).await.unwrap();
+        let val = store.tool_cache_get("fetch_doc", "abc").await.unwrap();
+        assert_eq!(val, Some("fresh body".to_string()));
+
+        // Different tool, same hash is a miss
+        assert_eq!(store.tool_cache_get("other_tool", "abc").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_tool_cache_expiry() {
+        let store = StateStore::open_memory().await.unwrap();
+        store.tool_cache_put("fetch_doc", "abc", "stale", 0).await.unwrap();
+        assert_eq!(store.tool_cache_get("fetch_doc", "abc").await.unwrap(), None);
+    }
+
     #[tokio::test]
     async fn test_kv_set_get_delete() {
         let store = StateStore::open_memory().await.unwrap();
@@ -1011,4 +2824,241 @@ mod tests {
         assert!(!results_multi.is_empty(), "Fallback/FTS multi-term search failed");
         assert!(results_multi[0].content.contains("secret code"));
     }
+
+    #[tokio::test]
+    async fn test_pretty_payloads() {
+        let store = StateStore::open_memory().await.unwrap().with_pretty_payloads(true);
+        let payload = serde_json::json!({"turn_index": 0, "tool": "read_file"});
+        store.insert_event("s", "turn_start", &payload).await.unwrap();
+
+        let events = store.get_events("s").await.unwrap();
+        assert!(events[0].payload.contains("\n  "));
+        let parsed: serde_json::Value = serde_json::from_str(&events[0].payload).unwrap();
+        assert_eq!(parsed, payload);
+    }
+
+    #[tokio::test]
+    async fn test_max_logged_arg_size() {
+        let store = StateStore::open_memory().await.unwrap().with_max_logged_arg_size(Some(64), true);
+        let small = json!({"path": "a.txt"});
+        let large = json!({"path": "b.txt", "content": "é".repeat(200)});
+        store.insert_tool_execution("s", 0, "c1", "write_file", &small, Some("ok"), false, None, "allow").await.unwrap();
+        store.insert_tool_execution("s", 0, "c2", "write_file", &large, Some("ok"), false, None, "allow").await.unwrap();
+
+        let rows = store.get_tool_executions("s").await.unwrap();
+        let logged: serde_json::Value = serde_json::from_str(&rows[0].args).unwrap();
+        assert_eq!(logged, small);
+
+        let marker: serde_json::Value = serde_json::from_str(&rows[1].args).unwrap();
+        let full = serde_json::to_string(&large).unwrap();
+        assert_eq!(marker["_truncated"], true);
+        assert_eq!(marker["size"], full.len());
+        assert_eq!(marker["sha256"], crate::tools::args_hash(&large));
+        assert!(marker["preview"].as_str().unwrap().len() <= 64);
+
+        let artifact = store.get_artifact("s", marker["artifact"].as_str().unwrap()).await.unwrap().unwrap();
+        assert_eq!(artifact.tool_call_id, "c2");
+        let spilled: serde_json::Value = serde_json::from_slice(&artifact.data).unwrap();
+        assert_eq!(spilled, large);
+    }
+
+    #[tokio::test]
+    async fn test_artifact_roundtrip() {
+        let store = StateStore::open_memory().await.unwrap();
+        let data = "x".repeat(500);
+        let handle = store.insert_artifact("s", "call-1", &Artifact::text("text/plain", data.clone())).await.unwrap();
+        assert!(handle.id.starts_with("art_"));
+        assert_eq!(handle.size, 500);
+        assert_eq!(handle.preview, format!("{}...", "x".repeat(200)));
+
+        let row = store.get_artifact("s", &handle.id).await.unwrap().unwrap();
+        assert_eq!(row.data, data.as_bytes());
+        assert_eq!(row.tool_call_id, "call-1");
+        assert!(store.get_artifact("s", "art_missing").await.unwrap().is_none());
+        // Another session can't read it
+        assert!(store.get_artifact("other", &handle.id).await.unwrap().is_none());
+
+        let binary = Artifact { kind: "image/png".to_string(), data: vec![0, 159, 146, 150] };
+        let second = store.insert_artifact("s", "call-2", &binary).await.unwrap();
+        assert_eq!(second.preview, "(binary, 4 bytes)");
+        assert_eq!(store.list_artifacts("s").await.unwrap(), vec![handle, second]);
+    }
+
+    #[tokio::test]
+    async fn test_plan_roundtrip() {
+        let store = StateStore::open_memory().await.unwrap();
+        assert!(store.get_plan("s").await.unwrap().is_empty());
+
+        let mut steps = vec![
+            PlanStep { title: "Read the code".to_string(), status: PlanStatus::Done },
+            PlanStep { title: "Write the fix".to_string(), status: PlanStatus::InProgress },
+        ];
+        store.set_plan("s", &steps).await.unwrap();
+        assert_eq!(store.get_plan("s").await.unwrap(), steps);
+
+        steps[1].status = PlanStatus::Done;
+        store.set_plan("s", &steps).await.unwrap();
+        assert_eq!(store.get_plan("s").await.unwrap(), steps);
+        assert!(store.get_plan("other").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_query_events_filters_and_limits() {
+        let store = StateStore::open_memory().await.unwrap();
+        for i in 0..5 {
+            store.insert_event("s", "tool_call", &serde_json::json!({ "n": i })).await.unwrap();
+            store.insert_event("s", "turn_start", &serde_json::json!({ "turn_index": i })).await.unwrap();
+        }
+        store.insert_event("other", "tool_call", &serde_json::json!({})).await.unwrap();
+
+        let filter = EventFilter { event_types: vec!["tool_call".to_string()], exclude_types: Vec::new(), limit: 2 };
+        let events = store.query_events("s", &filter).await.unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| e.event_type == "tool_call"));
+        assert!(events[0].payload.contains("3") && events[1].payload.contains("4"));
+
+        let all = store.query_events("s", &EventFilter { event_types: Vec::new(), exclude_types: Vec::new(), limit: 100 }).await.unwrap();
+        assert_eq!(all.len(), 10);
+
+        let filter = EventFilter { event_types: Vec::new(), exclude_types: vec!["turn_start".to_string()], limit: 100 };
+        let events = store.query_events("s", &filter).await.unwrap();
+        assert_eq!(events.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_roundtrip() {
+        let store = StateStore::open_memory().await.unwrap();
+        assert!(store.get_snapshot("s").await.unwrap().is_none());
+        assert_eq!(store.last_message_id("s").await.unwrap(), 0);
+
+        store.insert_message("s", 0, "user", &serde_json::json!([]), None, None).await.unwrap();
+        let last = store.last_message_id("s").await.unwrap();
+        store.put_snapshot("s", 1, last, "{}").await.unwrap();
+        store.insert_message("s", 1, "user", &serde_json::json!([]), None, None).await.unwrap();
+        store.put_snapshot("s", 1, last, "{\"v\":2}").await.unwrap();
+
+        let snap = store.get_snapshot("s").await.unwrap().unwrap();
+        assert_eq!(snap.last_message_id, last);
+        assert_eq!(snap.state, "{\"v\":2}");
+        let after = store.get_messages_after("s", snap.last_message_id).await.unwrap();
+        assert_eq!(after.len(), 1);
+        assert_eq!(after[0].turn_index, 1);
+    }
+
+    #[tokio::test]
+    async fn test_harness_records() {
+        let store = StateStore::open_memory().await.unwrap();
+        let open = |title: &str, owner: &str| serde_json::json!({ "title": title, "status": "open", "owner": { "name": owner }, "tags": ["a", "b"] });
+        store.records_put("tasks", "t1", Some("s1"), &open("first", "ann")).await.unwrap();
+        store.records_put("tasks", "t2", Some("s1"), &open("second", "bob")).await.unwrap();
+        store.records_put("tasks", "t3", Some("s1"), &open("third", "ann")).await.unwrap();
+        store.records_put("tasks", "t1", Some("s2"), &open("other session", "ann")).await.unwrap();
+        store.records_put("tasks", "g1", None, &open("global", "ann")).await.unwrap();
+        // Replacing keeps the record's position
+        store.records_put("tasks", "t1", Some("s1"), &serde_json::json!({ "title": "first", "status": "done" })).await.unwrap();
+
+        let filter = |value: serde_json::Value| value.as_object().unwrap().clone();
+        let ids = |records: Vec<HarnessRecord>| records.into_iter().map(|r| r.id).collect::<Vec<_>>();
+        let all = store.records_query("tasks", Some("s1"), &filter(serde_json::json!({})), 10).await.unwrap();
+        assert_eq!(all[0].data["status"], "done");
+        assert_eq!(all[0].session_id.as_deref(), Some("s1"));
+        assert_eq!(ids(all), vec!["t1", "t2", "t3"]);
+
+        let ann = filter(serde_json::json!({ "status": "open", "owner.name": "ann" }));
+        assert_eq!(ids(store.records_query("tasks", Some("s1"), &ann, 10).await.unwrap()), vec!["t3"]);
+        assert_eq!(ids(store.records_query("tasks", Some("s1"), &filter(serde_json::json!({ "$.tags.1": "b" })), 1).await.unwrap()), vec!["t2"]);
+        let global = store.records_query("tasks", None, &ann, 10).await.unwrap();
+        assert_eq!(ids(global.clone()), vec!["g1"]);
+        assert_eq!(global[0].session_id, None);
+        assert!(store.records_query("notes", Some("s1"), &filter(serde_json::json!({})), 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_session_names() {
+        let store = StateStore::open_memory().await.unwrap();
+        assert!(store.session_name("s1").await.unwrap().is_none());
+        assert_eq!(store.resolve_session("s1").await.unwrap(), "s1");
+
+        store.set_session_name("s1", "fix-bug").await.unwrap();
+        store.set_session_name("s1", "fix-bug").await.unwrap();
+        assert_eq!(store.session_name("s1").await.unwrap().as_deref(), Some("fix-bug"));
+        assert_eq!(store.resolve_session("fix-bug").await.unwrap(), "s1");
+
+        let err = store.set_session_name("s2", "fix-bug").await.unwrap_err();
+        assert!(err.to_string().contains("already used by session s1"));
+        assert_eq!(store.unused_session_name("fix-bug").await.unwrap(), "fix-bug-2");
+        store.set_session_name("s2", "fix-bug-2").await.unwrap();
+        assert_eq!(store.unused_session_name("fix-bug").await.unwrap(), "fix-bug-3");
+
+        // Renaming frees the old name
+        store.set_session_name("s1", "other").await.unwrap();
+        assert_eq!(store.resolve_session("fix-bug").await.unwrap(), "fix-bug");
+        assert_eq!(store.resolve_session("other").await.unwrap(), "s1");
+
+        // The database itself refuses a duplicate, e.g. from a racing writer
+        let conn = store.connect().unwrap();
+        assert!(conn.execute("INSERT INTO session_names (session_id, name) VALUES ('s3', 'other')", ()).await.is_err());
+
+        for (session, event) in [("s1", "turn_start"), ("s4", "turn_start")] {
+            store.insert_event(session, event, &json!({"type": event, "turn_index": 0})).await.unwrap();
+        }
+        let listed = store.list_sessions(10, 0).await.unwrap();
+        assert_eq!(listed, vec![
+            SessionListing { id: "s4".to_string(), name: None },
+            SessionListing { id: "s1".to_string(), name: Some("other".to_string()) },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_session_summary_and_delete() {
+        let store = StateStore::open_memory().await.unwrap();
+        assert!(store.session_summary("s1").await.unwrap().is_none());
+
+        store.set_session_name("s1", "fix-bug").await.unwrap();
+        store.insert_event("s1", "turn_start", &json!({"type": "turn_start", "turn_index": 0})).await.unwrap();
+        for (turn, tokens) in [(0, 100), (1, 50)] {
+            store.insert_event("s1", "message_end", &json!({"type": "message_end", "role": "assistant", "input_tokens": tokens, "output_tokens": 10, "stop_reason": null})).await.unwrap();
+            store.insert_message("s1", turn, "assistant", &json!([{"type": "text", "text": "hi"}]), None, None).await.unwrap();
+        }
+        store.insert_tool_execution("s1", 0, "c1", "read_file", &json!({}), Some("ok"), false, Some(3), "allow").await.unwrap();
+        store.insert_turn_cost("s1", 0, "openai", "gpt-4o", 100, 10, Some(0.00035)).await.unwrap();
+        store.insert_turn_cost("s1", 1, "mock", "mock-model", 50, 10, None).await.unwrap();
+        store.insert_message("s2", 0, "user", &json!([{"type": "text", "text": "other"}]), None, None).await.unwrap();
+
+        let summary = store.session_summary("s1").await.unwrap().unwrap();
+        assert_eq!(summary.name.as_deref(), Some("fix-bug"));
+        assert_eq!((summary.turns, summary.messages, summary.tool_executions), (2, 2, 1));
+        assert_eq!((summary.input_tokens, summary.output_tokens), (150, 20));
+        assert!((summary.cost_usd - 0.00035).abs() < 1e-12);
+        assert!(summary.started_at.is_some());
+        let costs = store.get_turn_costs("s1").await.unwrap();
+        assert_eq!(costs.len(), 2);
+        assert_eq!((costs[1].turn_index, costs[1].model.as_str(), costs[1].cost_usd), (1, "mock-model", None));
+
+        store.delete_session("s1").await.unwrap();
+        assert!(store.session_summary("s1").await.unwrap().is_none());
+        assert!(store.get_turn_costs("s1").await.unwrap().is_empty());
+        assert!(store.get_tool_executions("s1").await.unwrap().is_empty());
+        assert_eq!(store.resolve_session("fix-bug").await.unwrap(), "fix-bug");
+        assert_eq!(store.session_summary("s2").await.unwrap().unwrap().messages, 1);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_rows_are_readable() {
+        let store = StateStore::open_memory().await.unwrap();
+        store.insert_message("s", 0, "user", &serde_json::json!([{"type": "text", "text": "hi"}]), None, None).await.unwrap();
+        store.insert_message("s", 0, "assistant", &serde_json::json!([]), None, None).await.unwrap();
+        store.insert_event("s", "turn_start", &serde_json::json!({})).await.unwrap();
+        // Simulate a torn write
+        let conn = store.get_connection().unwrap();
+        conn.execute("UPDATE messages SET content = '[{\"type\": \"te' WHERE role = 'assistant'", ()).await.unwrap();
+        conn.execute("UPDATE events SET payload = '{' WHERE session_id = 's'", ()).await.unwrap();
+
+        let messages = store.get_messages("s").await.unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].content_json().is_some());
+        assert!(messages[1].content_json().is_none());
+        let events = store.get_events("s").await.unwrap();
+        assert!(events[0].payload_json().is_none());
+    }
 }
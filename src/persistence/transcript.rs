@@ -0,0 +1,382 @@
+//! Readable transcripts of stored sessions, for `bedrock cat`.
+//!
+//! A transcript is rebuilt from the `messages` table, with each tool call
+//! joined to its result and to its `tool_executions` row (duration and
+//! harness verdict), so a past session can be reviewed without querying the
+//! database by hand.
+
+use anyhow::{Context, Result};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::persistence::state::{MessageRow, StateStore, ToolExecutionRow};
+
+/// Output format for a transcript.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    #[default]
+    Text,
+    Markdown,
+    /// One JSON object per entry, one per line
+    Json,
+}
+
+impl FromStr for TranscriptFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "markdown" | "md" => Ok(Self::Markdown),
+            "json" => Ok(Self::Json),
+            other => anyhow::bail!("Unknown transcript format '{}' (expected text, markdown or json)", other),
+        }
+    }
+}
+
+/// What to leave out of a transcript.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TranscriptOptions {
+    pub hide_tool_args: bool,
+    pub hide_thinking: bool,
+}
+
+/// One step of a session, in the order it happened.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TranscriptEntry {
+    User {
+        turn_index: u32,
+        text: String,
+        /// Queued by the harness or kernel rather than sent by the user
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        injected: bool,
+    },
+    Assistant { turn_index: u32, text: String },
+    Thinking { turn_index: u32, text: String },
+    ToolCall {
+        turn_index: u32,
+        id: String,
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        args: Option<serde_json::Value>,
+        output: Option<String>,
+        is_error: bool,
+        duration_ms: Option<u64>,
+        /// Harness verdict, when it was anything other than a plain allow
+        #[serde(skip_serializing_if = "Option::is_none")]
+        verdict: Option<String>,
+    },
+    /// A message whose stored content is not valid JSON, shown as stored
+    Malformed { turn_index: u32, role: String, raw: String },
+}
+
+impl TranscriptEntry {
+    pub fn turn_index(&self) -> u32 {
+        match self {
+            Self::User { turn_index, .. }
+            | Self::Assistant { turn_index, .. }
+            | Self::Thinking { turn_index, .. }
+            | Self::ToolCall { turn_index, .. }
+            | Self::Malformed { turn_index, .. } => *turn_index,
+        }
+    }
+}
+
+/// Rebuild the transcript of `session_id` from `store`.
+///
+/// Loads the whole session; [`write`] streams it instead.
+pub async fn load(store: &StateStore, session_id: &str, options: TranscriptOptions) -> Result<Vec<TranscriptEntry>> {
+    let messages = store.get_messages(session_id).await?;
+    anyhow::ensure!(!messages.is_empty(), "No messages stored for session '{}'", session_id);
+    let executions = store.get_tool_executions(session_id).await?;
+    build(&messages, &executions, options)
+}
+
+/// Write the transcript of `session_id` to `out` in `format` as it is read,
+/// one turn at a time, so a huge session is never held in memory at once.
+/// The output is the same as printing [`render`] of [`load`].
+pub async fn write(
+    store: &StateStore,
+    session_id: &str,
+    options: TranscriptOptions,
+    format: TranscriptFormat,
+    out: &mut dyn std::io::Write,
+) -> Result<()> {
+    let mut messages = std::pin::pin!(store.stream_messages(session_id));
+    let mut turn: Vec<MessageRow> = Vec::new();
+    // Each turn is written once the next one renders, so the last can be trimmed like `render`'s output
+    let mut rendered: Option<String> = None;
+    loop {
+        let next = messages.try_next().await?;
+        let turn_ended = match (&next, turn.first()) {
+            (Some(msg), Some(first)) => msg.turn_index != first.turn_index,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+        if turn_ended {
+            let executions = store.get_turn_tool_executions(session_id, turn[0].turn_index).await?;
+            let mut chunk = String::new();
+            render_into(&mut chunk, &build(&turn, &executions, options)?, format)?;
+            turn.clear();
+            if let Some(previous) = rendered.replace(chunk) {
+                out.write_all(previous.as_bytes())?;
+            }
+        }
+        match next {
+            Some(msg) => turn.push(msg),
+            None => break,
+        }
+    }
+    let last = rendered.with_context(|| format!("No messages stored for session '{}'", session_id))?;
+    writeln!(out, "{}", last.trim_end())?;
+    Ok(())
+}
+
+/// Assemble transcript entries from stored rows.
+pub fn build(messages: &[MessageRow], executions: &[ToolExecutionRow], options: TranscriptOptions) -> Result<Vec<TranscriptEntry>> {
+    let executions: HashMap<&str, &ToolExecutionRow> =
+        executions.iter().map(|e| (e.tool_call_id.as_str(), e)).collect();
+    let mut entries = Vec::new();
+    // Index of each tool call entry, so its result can be filled in later
+    let mut calls: HashMap<String, usize> = HashMap::new();
+
+    for msg in messages {
+        let turn_index = msg.turn_index;
+        let Some(serde_json::Value::Array(parts)) = msg.content_json() else {
+            entries.push(TranscriptEntry::Malformed { turn_index, role: msg.role.clone(), raw: msg.content.clone() });
+            continue;
+        };
+        for part in parts {
+            let text = || part.get("text").and_then(|t| t.as_str()).unwrap_or_default().to_string();
+            match part.get("type").and_then(|t| t.as_str()) {
+                Some("text") if msg.role == "user" => entries.push(TranscriptEntry::User {
+                    turn_index,
+                    text: text(),
+                    injected: part.get("injected").and_then(|i| i.as_bool()).unwrap_or(false),
+                }),
+                Some("text") => entries.push(TranscriptEntry::Assistant { turn_index, text: text() }),
+                Some("thinking") if !options.hide_thinking => {
+                    let thinking = ["thinking", "content"]
+                        .iter()
+                        .find_map(|key| part.get(*key).and_then(|t| t.as_str()))
+                        .map(str::to_string)
+                        .unwrap_or_else(text);
+                    entries.push(TranscriptEntry::Thinking { turn_index, text: thinking });
+                }
+                Some("tool_use") => {
+                    let id = part.get("id").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+                    let exec = executions.get(id.as_str());
+                    calls.insert(id.clone(), entries.len());
+                    entries.push(TranscriptEntry::ToolCall {
+                        turn_index,
+                        name: part.get("name").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+                        args: (!options.hide_tool_args).then(|| part.get("input").cloned().unwrap_or_default()),
+                        output: exec.and_then(|e| e.output.clone()),
+                        is_error: exec.is_some_and(|e| e.is_error),
+                        duration_ms: exec.and_then(|e| e.duration_ms),
+                        verdict: exec.map(|e| e.verdict.clone()).filter(|v| v != "allow"),
+                        id,
+                    });
+                }
+                Some("tool_result") => {
+                    let id = part.get("tool_use_id").and_then(|v| v.as_str()).unwrap_or_default();
+                    // The result the model saw takes precedence over the logged output
+                    if let Some(TranscriptEntry::ToolCall { output, is_error, .. }) = calls.get(id).map(|&i| &mut entries[i]) {
+                        *output = part.get("content").and_then(|c| c.as_str()).map(str::to_string).or(output.take());
+                        *is_error = part.get("is_error").and_then(|e| e.as_bool()).unwrap_or(*is_error);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Render `entries` in `format`.
+pub fn render(entries: &[TranscriptEntry], format: TranscriptFormat) -> Result<String> {
+    let mut out = String::new();
+    render_into(&mut out, entries, format)?;
+    Ok(out.trim_end().to_string())
+}
+
+/// Append `entries` rendered in `format` to `out`, untrimmed.
+fn render_into(out: &mut String, entries: &[TranscriptEntry], format: TranscriptFormat) -> Result<()> {
+    match format {
+        TranscriptFormat::Json => {
+            for entry in entries {
+                writeln!(out, "{}", serde_json::to_string(entry)?)?;
+            }
+        }
+        TranscriptFormat::Text => {
+            for entry in entries {
+                match entry {
+                    TranscriptEntry::User { turn_index, text, injected: false } => writeln!(out, "[{}] user:\n{}\n", turn_index, text)?,
+                    TranscriptEntry::User { turn_index, text, injected: true } => writeln!(out, "[{}] user (queued):\n{}\n", turn_index, text)?,
+                    TranscriptEntry::Assistant { turn_index, text } => writeln!(out, "[{}] assistant:\n{}\n", turn_index, text)?,
+                    TranscriptEntry::Thinking { turn_index, text } => writeln!(out, "[{}] thinking:\n{}\n", turn_index, text)?,
+                    TranscriptEntry::Malformed { turn_index, role, raw } => writeln!(out, "[{}] {} (malformed):\n{}\n", turn_index, role, raw)?,
+                    TranscriptEntry::ToolCall { turn_index, name, args, output, is_error, duration_ms, verdict, .. } => {
+                        write!(out, "[{}] tool {}", turn_index, name)?;
+                        if let Some(args) = args {
+                            write!(out, " {}", args)?;
+                        }
+                        writeln!(out, "{}", call_status(*is_error, *duration_ms, verdict.as_deref()))?;
+                        writeln!(out, "{}\n", output.as_deref().unwrap_or("(no output)"))?;
+                    }
+                }
+            }
+        }
+        TranscriptFormat::Markdown => {
+            let mut turn = None;
+            for entry in entries {
+                let turn_index = entry.turn_index();
+                if turn != Some(turn_index) {
+                    writeln!(out, "## Turn {}\n", turn_index)?;
+                    turn = Some(turn_index);
+                }
+                match entry {
+                    TranscriptEntry::User { text, injected: false, .. } => writeln!(out, "**User**\n\n{}\n", text)?,
+                    TranscriptEntry::User { text, injected: true, .. } => writeln!(out, "**User** (queued)\n\n{}\n", text)?,
+                    TranscriptEntry::Assistant { text, .. } => writeln!(out, "**Assistant**\n\n{}\n", text)?,
+                    TranscriptEntry::Thinking { text, .. } => {
+                        let quoted: Vec<String> = text.lines().map(|l| format!("> {}", l)).collect();
+                        writeln!(out, "**Thinking**\n\n{}\n", quoted.join("\n"))?;
+                    }
+                    TranscriptEntry::Malformed { role, raw, .. } => writeln!(out, "**{} (malformed)**\n\n```\n{}\n```\n", role, raw)?,
+                    TranscriptEntry::ToolCall { name, args, output, is_error, duration_ms, verdict, .. } => {
+                        writeln!(out, "**Tool `{}`**{}\n", name, call_status(*is_error, *duration_ms, verdict.as_deref()))?;
+                        if let Some(args) = args {
+                            writeln!(out, "```json\n{}\n```\n", serde_json::to_string_pretty(args)?)?;
+                        }
+                        writeln!(out, "```\n{}\n```\n", output.as_deref().unwrap_or("(no output)"))?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// e.g. ` (error, 120ms, reject)`; empty for a plain successful call.
+fn call_status(is_error: bool, duration_ms: Option<u64>, verdict: Option<&str>) -> String {
+    let mut parts = Vec::new();
+    if is_error {
+        parts.push("error".to_string());
+    }
+    if let Some(ms) = duration_ms {
+        parts.push(format!("{}ms", ms));
+    }
+    if let Some(verdict) = verdict {
+        parts.push(verdict.to_string());
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", parts.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn session() -> StateStore {
+        let store = StateStore::open_memory().await.unwrap();
+        store.insert_message("s", 0, "user", &json!([{"type": "text", "text": "read a.txt"}]), None, None).await.unwrap();
+        store.insert_message("s", 0, "assistant", &json!([
+            {"type": "thinking", "thinking": "I should read it"},
+            {"type": "tool_use", "id": "t1", "name": "read_file", "input": {"path": "a.txt"}},
+        ]), None, None).await.unwrap();
+        store.insert_tool_execution("s", 0, "t1", "read_file", &json!({"path": "a.txt"}), Some("hello"), false, Some(3), "allow").await.unwrap();
+        store.insert_message("s", 0, "tool_result", &json!([
+            {"type": "tool_result", "tool_use_id": "t1", "content": "hello", "is_error": false},
+        ]), None, None).await.unwrap();
+        store.insert_message("s", 1, "assistant", &json!([{"type": "text", "text": "It says hello."}]), None, Some("stop")).await.unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn test_transcript_joins_tool_results() {
+        let store = session().await;
+        let entries = load(&store, "s", TranscriptOptions::default()).await.unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0], TranscriptEntry::User { turn_index: 0, text: "read a.txt".to_string(), injected: false });
+        assert_eq!(entries[1], TranscriptEntry::Thinking { turn_index: 0, text: "I should read it".to_string() });
+        assert_eq!(entries[2], TranscriptEntry::ToolCall {
+            turn_index: 0,
+            id: "t1".to_string(),
+            name: "read_file".to_string(),
+            args: Some(json!({"path": "a.txt"})),
+            output: Some("hello".to_string()),
+            is_error: false,
+            duration_ms: Some(3),
+            verdict: None,
+        });
+
+        let text = render(&entries, TranscriptFormat::Text).unwrap();
+        assert!(text.contains("[0] tool read_file {\"path\":\"a.txt\"} (3ms)\nhello"), "{}", text);
+        assert!(text.ends_with("[1] assistant:\nIt says hello."));
+
+        let markdown = render(&entries, TranscriptFormat::Markdown).unwrap();
+        assert!(markdown.starts_with("## Turn 0\n\n**User**\n\nread a.txt"));
+        assert!(markdown.contains("## Turn 1\n\n**Assistant**"));
+
+        assert!(load(&store, "missing", TranscriptOptions::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transcript_options_hide_parts() {
+        let store = session().await;
+        let options = TranscriptOptions { hide_tool_args: true, hide_thinking: true };
+        let entries = load(&store, "s", options).await.unwrap();
+        assert!(!entries.iter().any(|e| matches!(e, TranscriptEntry::Thinking { .. })));
+
+        let json = render(&entries, TranscriptFormat::Json).unwrap();
+        let lines: Vec<serde_json::Value> = json.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1]["type"], "tool_call");
+        assert!(lines[1].get("args").is_none());
+        assert_eq!(lines[1]["output"], "hello");
+    }
+
+    #[test]
+    fn test_malformed_message_is_shown_raw() {
+        let row = |id, role: &str, content: &str| MessageRow {
+            id,
+            session_id: "s".to_string(),
+            turn_index: 0,
+            role: role.to_string(),
+            content: content.to_string(),
+            token_count: None,
+            created_at: String::new(),
+            partial: false,
+            finish_reason: None,
+        };
+        let rows = [row(1, "user", r#"[{"type":"text","text":"hi"}]"#), row(2, "assistant", r#"[{"type":"te"#)];
+        let entries = build(&rows, &[], TranscriptOptions::default()).unwrap();
+        assert_eq!(entries[1], TranscriptEntry::Malformed {
+            turn_index: 0,
+            role: "assistant".to_string(),
+            raw: r#"[{"type":"te"#.to_string(),
+        });
+        assert!(render(&entries, TranscriptFormat::Text).unwrap().ends_with("[0] assistant (malformed):\n[{\"type\":\"te"));
+    }
+
+    #[tokio::test]
+    async fn test_streamed_write_matches_render() {
+        let store = session().await;
+        for format in [TranscriptFormat::Text, TranscriptFormat::Markdown, TranscriptFormat::Json] {
+            let entries = load(&store, "s", TranscriptOptions::default()).await.unwrap();
+            let mut out = Vec::new();
+            write(&store, "s", TranscriptOptions::default(), format, &mut out).await.unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), format!("{}\n", render(&entries, format).unwrap()));
+        }
+        assert!(write(&store, "missing", TranscriptOptions::default(), TranscriptFormat::Text, &mut Vec::new()).await.is_err());
+    }
+}
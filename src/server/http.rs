@@ -0,0 +1,421 @@
+//! `bedrock serve`: the kernel behind an HTTP API, for embedding Bedrock in
+//! a web UI or another service. Escalated tool calls are denied, as there
+//! is no terminal to ask at. Every request must carry the bearer token from
+//! `server.token_env`.
+//!
+//! | Route | |
+//! |---|---|
+//! | `POST /sessions` | Start a session; optional body `{"name": ..., "resume": ...}`. Returns `{"session_id"}` |
+//! | `POST /sessions/{id}/messages` | Queue a prompt, `{"prompt": ...}` |
+//! | `GET /sessions/{id}/events` | The session's events as server-sent events |
+//!
+//! Each session gets its own [`Kernel`], running in a background task under
+//! [`QueueEmptyPolicy::AwaitMore`], so posted prompts join the session queue
+//! just like tasks queued by a harness. All kernels share one [`StateStore`],
+//! and the provider clients and MCP servers started with the server. A
+//! session with nothing queued that emits no event for
+//! `server.idle_timeout_secs` is ended.
+//!
+//! Event streams tail the session's rows in the `events` table: each SSE
+//! event carries the row id, the event type and the JSON payload, and a
+//! client reconnecting with `Last-Event-ID` (or `?after=<id>`) resumes where
+//! it left off.
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::stream::{self, Stream};
+use serde::Deserialize;
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::inference::embeddings::EmbeddingProvider;
+use crate::inference::provider::ProviderClient;
+use crate::kernel::config::{BedrockConfig, QueueEmptyPolicy};
+use crate::kernel::interrupt::InterruptHandle;
+use crate::kernel::session::{SessionState, StopReason};
+use crate::kernel::Kernel;
+use crate::persistence::state::{EventRow, StateStore, STREAM_PAGE_SIZE};
+use crate::tools::mcp::McpServers;
+use crate::tools::registry::ToolRegistry;
+
+/// How often an event stream checks the database for new events.
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often a stopped session checks its queue for the next prompt.
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often sessions are checked against `server.idle_timeout_secs`.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shared by every request handler.
+struct ServerState {
+    config: BedrockConfig,
+    store: StateStore,
+    token: String,
+    runtime: SharedRuntime,
+    sessions: Mutex<HashMap<String, SessionHandle>>,
+}
+
+/// What every session's kernel shares: the provider clients, and the MCP
+/// servers with the tools they registered.
+struct SharedRuntime {
+    clients: HashMap<String, ProviderClient>,
+    embedding_provider: Option<Arc<dyn EmbeddingProvider>>,
+    mcp_servers: McpServers,
+    tool_registry: ToolRegistry,
+}
+
+impl SharedRuntime {
+    async fn start(config: &BedrockConfig, store: &StateStore) -> Result<Self> {
+        let mut kernel = Kernel::builder(config.clone()).with_state_store(store.clone()).build()?;
+        kernel.init_clients().await?;
+        Ok(Self {
+            clients: kernel.clients.clone(),
+            embedding_provider: kernel.embedding_provider.clone(),
+            mcp_servers: kernel.mcp_servers.clone(),
+            tool_registry: kernel.tool_registry.clone(),
+        })
+    }
+
+    /// A kernel for one session, with its own harness.
+    async fn kernel(&self, config: &BedrockConfig, store: &StateStore) -> Result<Kernel> {
+        let mut kernel = Kernel::builder(config.clone())
+            .with_state_store(store.clone())
+            .with_tool_registry(self.tool_registry.clone())
+            .quiet(true)
+            .interactive(false)
+            .build()?;
+        kernel.clients = self.clients.clone();
+        kernel.embedding_provider = self.embedding_provider.clone();
+        kernel.mcp_servers = self.mcp_servers.clone();
+        kernel.init_harness().await?;
+        Ok(kernel)
+    }
+}
+
+/// A running session: its prompt queue and the means to stop its task.
+struct SessionHandle {
+    queue: Arc<Mutex<VecDeque<String>>>,
+    interrupt: InterruptHandle,
+    /// Set once the server ends the session. Unlike the interrupt, which the
+    /// kernel resets when a run ends, it stays set between runs.
+    stopping: Arc<AtomicBool>,
+    /// When the session last emitted an event or was sent a prompt
+    last_active: Arc<std::sync::Mutex<Instant>>,
+    task: JoinHandle<()>,
+}
+
+impl SessionHandle {
+    /// Abort the session's run and end the session.
+    fn stop(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        self.interrupt.abort();
+    }
+
+    fn touch(&self) {
+        *self.last_active.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+
+    async fn is_idle(&self, timeout: Duration) -> bool {
+        self.queue.lock().await.is_empty() && self.last_active.lock().unwrap_or_else(|e| e.into_inner()).elapsed() >= timeout
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CreateSession {
+    /// Name the session (default: a slug of its first prompt)
+    name: Option<String>,
+    /// Continue a stored session (name or id)
+    resume: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PostMessage {
+    prompt: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventsQuery {
+    /// Only send events with ids greater than this
+    after: Option<i64>,
+}
+
+/// An error response: the status and a `{"error": ...}` body.
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e))
+    }
+}
+
+/// Serve the API on `listener` until `shutdown` resolves, then abort every
+/// session's run and wait for its `end_session`. Fails without a token in
+/// `server.token_env`.
+pub async fn serve(config: BedrockConfig, listener: TcpListener, shutdown: impl Future<Output = ()> + Send + 'static) -> Result<()> {
+    let token = std::env::var(&config.server.token_env).unwrap_or_default();
+    anyhow::ensure!(
+        !token.trim().is_empty(),
+        "Set {} to the bearer token clients must send (server.token_env)",
+        config.server.token_env
+    );
+    let db_path = config.database_path();
+    let store = StateStore::open(&db_path)
+        .await
+        .with_context(|| format!("Failed to initialize state store at '{}'", db_path))?
+        .with_pretty_payloads(config.persistence.pretty_payloads)
+        .with_max_logged_arg_size(config.persistence.max_logged_arg_size, config.persistence.spill_logged_args);
+    let runtime = SharedRuntime::start(&config, &store).await?;
+    let state = Arc::new(ServerState { config, store, token, runtime, sessions: Mutex::new(HashMap::new()) });
+
+    let app = Router::new()
+        .route("/sessions", post(create_session))
+        .route("/sessions/{id}/messages", post(post_message))
+        .route("/sessions/{id}/events", get(session_events))
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state.clone());
+
+    let idle_timeout = state.config.server.idle_timeout_secs;
+    let evictor = (idle_timeout > 0).then(|| tokio::spawn(evict_idle_sessions(state.clone(), Duration::from_secs(idle_timeout))));
+
+    info!(addr = %listener.local_addr()?, "Serving HTTP API");
+    axum::serve(listener, app).with_graceful_shutdown(shutdown).await?;
+
+    if let Some(evictor) = evictor {
+        evictor.abort();
+    }
+    let sessions: Vec<SessionHandle> = state.sessions.lock().await.drain().map(|(_, handle)| handle).collect();
+    for handle in &sessions {
+        handle.stop();
+    }
+    for handle in sessions {
+        if let Err(e) = handle.task.await {
+            warn!(error = %e, "Session task failed");
+        }
+    }
+    Ok(())
+}
+
+/// Reject requests without `Authorization: Bearer <token>`.
+async fn require_token(State(state): State<Arc<ServerState>>, request: Request, next: Next) -> Response {
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match presented {
+        Some(token) if tokens_match(token.trim(), &state.token) => next.run(request).await,
+        _ => {
+            let mut response = ApiError(StatusCode::UNAUTHORIZED, "Missing or invalid bearer token".to_string()).into_response();
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, header::HeaderValue::from_static("Bearer"));
+            response
+        }
+    }
+}
+
+/// Compare tokens in time independent of where they differ.
+fn tokens_match(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len() && presented.bytes().zip(expected.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// End sessions idle for `timeout`. Each session's task removes it from
+/// `sessions` once it has ended.
+async fn evict_idle_sessions(state: Arc<ServerState>, timeout: Duration) {
+    loop {
+        tokio::time::sleep(IDLE_CHECK_INTERVAL).await;
+        for (session_id, handle) in state.sessions.lock().await.iter() {
+            if !handle.stopping.load(Ordering::SeqCst) && handle.is_idle(timeout).await {
+                info!(session_id = %session_id, idle_secs = timeout.as_secs(), "Ending idle session");
+                handle.stop();
+            }
+        }
+    }
+}
+
+async fn create_session(
+    State(state): State<Arc<ServerState>>,
+    body: Option<Json<CreateSession>>,
+) -> Result<(StatusCode, Json<serde_json::Value>), ApiError> {
+    let request = body.map(|Json(body)| body).unwrap_or_default();
+    if let Some(resume) = &request.resume {
+        let session_id = state.store.resolve_session(resume).await?;
+        if state.sessions.lock().await.contains_key(&session_id) {
+            return Err(ApiError(StatusCode::CONFLICT, format!("Session '{}' is already running", resume)));
+        }
+    }
+    let mut kernel = state.runtime.kernel(&state.config, &state.store).await?;
+
+    let mut session = match &request.resume {
+        Some(id) => kernel
+            .resume_session(id)
+            .await
+            .map_err(|e| ApiError(StatusCode::NOT_FOUND, format!("{:#}", e)))?,
+        None => kernel.create_session(),
+    };
+    if let Some(name) = &request.name {
+        kernel
+            .name_session(&mut session, name)
+            .await
+            .map_err(|e| ApiError(StatusCode::CONFLICT, format!("{:#}", e)))?;
+    }
+
+    let session_id = session.id.clone();
+    match state.sessions.lock().await.entry(session_id.clone()) {
+        Entry::Occupied(_) => {
+            return Err(ApiError(StatusCode::CONFLICT, format!("Session '{}' is already running", session_id)));
+        }
+        Entry::Vacant(slot) => {
+            let stopping = Arc::new(AtomicBool::new(false));
+            let last_active = Arc::new(std::sync::Mutex::new(Instant::now()));
+            slot.insert(SessionHandle {
+                queue: session.queue.clone(),
+                interrupt: kernel.interrupt_handle(),
+                stopping: stopping.clone(),
+                last_active: last_active.clone(),
+                task: tokio::spawn(run_session(state.clone(), kernel, session, stopping, last_active)),
+            });
+        }
+    }
+    info!(session_id = %session_id, "Session started");
+    Ok((StatusCode::CREATED, Json(serde_json::json!({ "session_id": session_id }))))
+}
+
+async fn post_message(
+    State(state): State<Arc<ServerState>>,
+    Path(id): Path<String>,
+    Json(message): Json<PostMessage>,
+) -> Result<(StatusCode, Json<serde_json::Value>), ApiError> {
+    if message.prompt.trim().is_empty() {
+        return Err(ApiError(StatusCode::BAD_REQUEST, "prompt must not be empty".to_string()));
+    }
+    let session_id = state.store.resolve_session(&id).await?;
+    let sessions = state.sessions.lock().await;
+    let handle = sessions
+        .get(&session_id)
+        .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, format!("No running session '{}'", id)))?;
+    if handle.stopping.load(Ordering::SeqCst) {
+        return Err(ApiError(StatusCode::CONFLICT, format!("Session '{}' is ending", id)));
+    }
+    handle.touch();
+    let mut queue = handle.queue.lock().await;
+    queue.push_back(message.prompt);
+    Ok((StatusCode::ACCEPTED, Json(serde_json::json!({ "session_id": session_id, "queued": queue.len() }))))
+}
+
+async fn session_events(
+    State(state): State<Arc<ServerState>>,
+    Path(id): Path<String>,
+    Query(query): Query<EventsQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let session_id = state.store.resolve_session(&id).await?;
+    let running = state.sessions.lock().await.contains_key(&session_id);
+    if !running && state.store.session_summary(&session_id).await?.is_none() {
+        return Err(ApiError(StatusCode::NOT_FOUND, format!("No session '{}'", id)));
+    }
+    let after = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok()?.parse().ok())
+        .or(query.after)
+        .unwrap_or(0);
+    Ok(Sse::new(tail_events(state.store.clone(), session_id, after)).keep_alive(KeepAlive::default()))
+}
+
+/// A session's stored events with ids after `after`, followed by each new
+/// one as it is written. Ends only if the database fails.
+fn tail_events(store: StateStore, session_id: String, after: i64) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold((store, session_id, after, VecDeque::<EventRow>::new()), |(store, session_id, mut after, mut pending)| async move {
+        loop {
+            if let Some(row) = pending.pop_front() {
+                let event = Event::default().id(row.id.to_string()).event(row.event_type).data(row.payload);
+                return Some((Ok(event), (store, session_id, after, pending)));
+            }
+            match store.get_events_page(&session_id, after, STREAM_PAGE_SIZE).await {
+                Ok(rows) if rows.is_empty() => tokio::time::sleep(EVENT_POLL_INTERVAL).await,
+                Ok(rows) => {
+                    after = rows.last().map_or(after, |row| row.id);
+                    pending.extend(rows);
+                }
+                Err(e) => {
+                    warn!(session_id = %session_id, error = %e, "Event stream failed");
+                    return None;
+                }
+            }
+        }
+    })
+}
+
+/// Run a session until the server stops it. A run that stops early (an error
+/// or a limit) is restarted once another prompt is queued. Every event the
+/// session emits refreshes `last_active`.
+async fn run_session(
+    state: Arc<ServerState>,
+    mut kernel: Kernel,
+    mut session: SessionState,
+    stopping: Arc<AtomicBool>,
+    last_active: Arc<std::sync::Mutex<Instant>>,
+) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let mut events = kernel.subscribe();
+    let session_id = session.id.clone();
+    let activity = tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok((id, _)) if id != session_id => continue,
+                // Skipped events may have been this session's
+                Ok(_) | Err(RecvError::Lagged(_)) => {
+                    *last_active.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+    let stopped = || stopping.load(Ordering::SeqCst);
+    loop {
+        if let Err(e) = kernel.run_with_policy(&mut session, None, QueueEmptyPolicy::AwaitMore).await {
+            warn!(session_id = %session.id, error = %e, "Session run failed");
+            session.stop_reason = Some(StopReason::Error);
+        }
+        // The run resets the interrupt as it ends, so a stop that arrived
+        // as it finished is only seen here
+        if session.stop_reason == Some(StopReason::Aborted) || stopped() {
+            break;
+        }
+        while session.queue.lock().await.is_empty() && !stopped() {
+            tokio::time::sleep(QUEUE_POLL_INTERVAL).await;
+        }
+        if stopped() {
+            break;
+        }
+    }
+    if let Err(e) = kernel.end_session(&mut session).await {
+        warn!(session_id = %session.id, error = %e, "Failed to end session");
+    }
+    kernel.flush_events(&mut session).await;
+    activity.abort();
+    state.sessions.lock().await.remove(&session.id);
+    info!(session_id = %session.id, stop_reason = ?session.stop_reason, "Session ended");
+}
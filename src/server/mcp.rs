@@ -0,0 +1,239 @@
+//! `bedrock mcp-serve`: Bedrock as a Model Context Protocol server, so other
+//! agents and IDEs can call into it.
+//!
+//! Speaks newline-delimited JSON-RPC 2.0 (MCP's stdio transport) and
+//! implements the tools part of the protocol: `initialize`, `ping`,
+//! `tools/list` and `tools/call`. Every registered tool is listed (builtins
+//! and the tools of `[mcp]` servers alike), and calls pass the harness
+//! `on_tool_call` and `on_tool_result` hooks just as a model's would. With
+//! `run_agent` enabled, a `run_agent` tool also runs a whole agent session
+//! on a prompt and returns its final answer.
+//!
+//! Requests are handled one at a time, in the order they arrive.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::{info, warn};
+
+use crate::kernel::session::{SessionState, StopReason};
+use crate::kernel::Kernel;
+use crate::tools::mcp::PROTOCOL_VERSIONS;
+use crate::tools::ToolEffect;
+
+/// Name of the optional tool that runs a full agent session.
+pub const RUN_AGENT_TOOL: &str = "run_agent";
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+pub struct McpServer {
+    kernel: Kernel,
+    /// Session that direct tool calls are recorded under
+    session: SessionState,
+    run_agent: bool,
+    /// Whether any tool was called, so the session is worth ending
+    used: bool,
+}
+
+impl McpServer {
+    /// Serve `kernel`'s tools, plus `run_agent` if enabled. The kernel should
+    /// be built `quiet` and non-`interactive`, as stdin and stdout carry the protocol.
+    pub fn new(kernel: Kernel, run_agent: bool) -> Self {
+        let session = kernel.create_session();
+        Self { kernel, session, run_agent, used: false }
+    }
+
+    /// Answer requests read from `input` until it closes, writing responses to `output`.
+    pub async fn serve<R, W>(mut self, input: R, mut output: W) -> Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut lines = input.lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle_message(&line).await {
+                let mut bytes = serde_json::to_vec(&response)?;
+                bytes.push(b'\n');
+                output.write_all(&bytes).await?;
+                output.flush().await?;
+            }
+        }
+        if self.used {
+            self.kernel.end_session(&mut self.session).await?;
+        }
+        Ok(())
+    }
+
+    /// The response to one JSON-RPC message, or `None` for notifications.
+    async fn handle_message(&mut self, line: &str) -> Option<Value> {
+        let message: Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(e) => return Some(error_response(Value::Null, PARSE_ERROR, format!("Parse error: {}", e))),
+        };
+        // Notifications carry no id and get no response
+        let id = message.get("id")?.clone();
+        let method = message["method"].as_str().unwrap_or_default();
+        let result = match method {
+            "initialize" => Ok(initialize_result(&message["params"])),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": self.list_tools() })),
+            "tools/call" => self.call_tool(&message["params"]).await,
+            _ => Err((METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+        };
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error_response(id, code, message),
+        })
+    }
+
+    fn list_tools(&self) -> Vec<Value> {
+        let mut tools: Vec<Value> = self
+            .kernel
+            .tools()
+            .into_iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "inputSchema": tool.parameters,
+                    "annotations": { "readOnlyHint": tool.effect == ToolEffect::ReadOnly },
+                })
+            })
+            .collect();
+        if self.run_agent {
+            tools.push(json!({
+                "name": RUN_AGENT_TOOL,
+                "description": "Run the Bedrock agent on a prompt, with its own tools, harness and model, and return its final answer. The answer ends with the session id; pass it as `session` to continue that conversation.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "prompt": { "type": "string", "description": "The task for the agent" },
+                        "session": { "type": "string", "description": "Continue this stored session (name or id)" }
+                    },
+                    "required": ["prompt"]
+                },
+            }));
+        }
+        tools
+    }
+
+    async fn call_tool(&mut self, params: &Value) -> Result<Value, (i64, String)> {
+        let name = params["name"].as_str().ok_or((INVALID_PARAMS, "Missing tool name".to_string()))?;
+        let args = match &params["arguments"] {
+            Value::Null => json!({}),
+            args => args.clone(),
+        };
+        let (text, is_error) = if self.run_agent && name == RUN_AGENT_TOOL {
+            self.run_agent(&args).await
+        } else if self.kernel.tools().iter().any(|tool| tool.name == name) {
+            self.used = true;
+            self.kernel.call_tool(&mut self.session, name, args).await
+        } else {
+            return Err((INVALID_PARAMS, format!("Unknown tool: {}", name)));
+        };
+        Ok(json!({ "content": [{ "type": "text", "text": text }], "isError": is_error }))
+    }
+
+    /// Run an agent session on `args.prompt`, continuing `args.session` if
+    /// given, and return its final answer followed by the session id.
+    async fn run_agent(&mut self, args: &Value) -> (String, bool) {
+        let Some(prompt) = args["prompt"].as_str().filter(|p| !p.trim().is_empty()) else {
+            return ("Missing 'prompt'".to_string(), true);
+        };
+        let mut session = match args["session"].as_str() {
+            Some(id) => match self.kernel.resume_session(id).await {
+                Ok(session) => session,
+                Err(e) => return (format!("{:#}", e), true),
+            },
+            None => self.kernel.create_session(),
+        };
+        info!(session_id = %session.id, "run_agent started");
+        let result = self.kernel.run(&mut session, Some(prompt.to_string())).await;
+        if result.is_err() {
+            session.stop_reason = Some(StopReason::Error);
+        }
+        if let Err(e) = self.kernel.end_session(&mut session).await {
+            warn!(session_id = %session.id, error = %e, "Failed to end session");
+        }
+
+        let footer = format!("[session: {}]", session.id);
+        match (result, session.stop_reason, session.final_answer()) {
+            (Err(e), _, _) => (format!("Agent run failed: {:#}\n\n{}", e, footer), true),
+            (Ok(()), Some(StopReason::Completed), Some(answer)) => (format!("{}\n\n{}", answer, footer), false),
+            (Ok(()), reason, answer) => {
+                let answer = answer.map(|a| format!("\n\nLast answer:\n{}", a)).unwrap_or_default();
+                (format!("The run stopped early ({:?}){}\n\n{}", reason, answer, footer), true)
+            }
+        }
+    }
+}
+
+/// The `initialize` result: the client's protocol version if supported, else our newest.
+fn initialize_result(params: &Value) -> Value {
+    let requested = params["protocolVersion"].as_str().unwrap_or_default();
+    let version = PROTOCOL_VERSIONS.iter().find(|v| **v == requested).unwrap_or(&PROTOCOL_VERSIONS[0]);
+    json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": { "listChanged": false } },
+        "serverInfo": { "name": "bedrock", "version": env!("CARGO_PKG_VERSION") },
+    })
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::config::BedrockConfig;
+    use tokio::io::BufReader;
+
+    async fn exchange(server: McpServer, requests: &[Value]) -> Vec<Value> {
+        let input: String = requests.iter().map(|r| format!("{}\n", r)).collect();
+        let mut output = Vec::new();
+        server.serve(BufReader::new(input.as_bytes()), &mut output).await.unwrap();
+        String::from_utf8(output).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_tools_over_mcp() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("notes.txt"), "hello from bedrock").unwrap();
+        let mut config = BedrockConfig::default();
+        config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+        let kernel = Kernel::builder(config).quiet(true).interactive(false).build().unwrap();
+
+        let responses = exchange(McpServer::new(kernel, false), &[
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": { "protocolVersion": "2024-11-05" } }),
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/list" }),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "tools/call", "params": { "name": "read_file", "arguments": { "path": "notes.txt" } } }),
+            json!({ "jsonrpc": "2.0", "id": 4, "method": "tools/call", "params": { "name": "nope" } }),
+            json!({ "jsonrpc": "2.0", "id": 5, "method": "resources/list" }),
+        ])
+        .await;
+
+        // The notification got no response
+        assert_eq!(responses.len(), 5);
+        assert_eq!(responses[0]["result"]["protocolVersion"], "2024-11-05");
+        assert_eq!(responses[0]["result"]["serverInfo"]["name"], "bedrock");
+
+        let tools = responses[1]["result"]["tools"].as_array().unwrap();
+        let read_file = tools.iter().find(|t| t["name"] == "read_file").unwrap();
+        assert_eq!(read_file["inputSchema"]["type"], "object");
+        assert_eq!(read_file["annotations"]["readOnlyHint"], true);
+        assert!(!tools.iter().any(|t| t["name"] == RUN_AGENT_TOOL));
+
+        assert_eq!(responses[2]["result"]["isError"], false);
+        assert!(responses[2]["result"]["content"][0]["text"].as_str().unwrap().contains("hello from bedrock"));
+        assert_eq!(responses[3]["error"]["code"], INVALID_PARAMS);
+        assert_eq!(responses[4]["error"]["code"], METHOD_NOT_FOUND);
+    }
+}
@@ -0,0 +1,9 @@
+//! Ways to drive the kernel from another program.
+//!
+//! - [`http`]: `bedrock serve`, sessions over HTTP with SSE event streams
+//! - [`mcp`]: `bedrock mcp-serve`, the registered tools as an MCP server over stdio
+
+pub mod http;
+pub mod mcp;
+
+pub use http::serve;
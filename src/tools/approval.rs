@@ -0,0 +1,171 @@
+//! Human approval for tool calls matching `tools.requires_approval`.
+//!
+//! A rule is a tool name, optionally followed by an argument pattern in
+//! parentheses: `shell_exec` matches every shell command, `shell_exec(git push*)`
+//! only those whose command starts with `git push`, and `write_file(/etc/*)`
+//! writes under `/etc`. `*` matches any run of characters, in tool names too.
+//! An argument pattern matches if any string argument of the call matches it.
+//!
+//! Matching calls, like calls a harness escalates, pause the turn until the
+//! user answers. An "always" answer approves the tool for the rest of the
+//! session.
+
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+
+/// One parsed `tools.requires_approval` entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApprovalRule {
+    tool: String,
+    arg: Option<String>,
+}
+
+impl ApprovalRule {
+    pub fn parse(rule: &str) -> Result<Self> {
+        let rule = rule.trim();
+        let (tool, arg) = match rule.split_once('(') {
+            Some((tool, rest)) => match rest.strip_suffix(')') {
+                Some(arg) => (tool.trim(), Some(arg.to_string())),
+                None => bail!("'{}' is missing its closing ')'", rule),
+            },
+            None => (rule, None),
+        };
+        if tool.is_empty() {
+            bail!("'{}' has no tool name", rule);
+        }
+        Ok(Self { tool: tool.to_string(), arg })
+    }
+
+    /// Whether a call of `name` with `args` needs approval under this rule.
+    pub fn matches(&self, name: &str, args: &Value) -> bool {
+        if !wildcard_match(&self.tool, name) {
+            return false;
+        }
+        match &self.arg {
+            None => true,
+            Some(pattern) => string_args(args).any(|value| wildcard_match(pattern, value)),
+        }
+    }
+}
+
+/// The first of `rules` that a call of `name` with `args` matches.
+pub fn matching_rule<'a>(rules: &'a [String], name: &str, args: &Value) -> Option<&'a str> {
+    rules
+        .iter()
+        .find(|rule| ApprovalRule::parse(rule).is_ok_and(|r| r.matches(name, args)))
+        .map(String::as_str)
+}
+
+/// The user's answer to an approval prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalDecision {
+    /// Run this call
+    Approve,
+    /// Run this call and further calls of the same tool this session
+    Always,
+    Deny,
+}
+
+impl ApprovalDecision {
+    /// Parse a prompt answer: `y`/`yes`, `a`/`always`; anything else denies.
+    pub fn from_answer(answer: &str) -> Self {
+        match answer.trim().to_ascii_lowercase().as_str() {
+            "y" | "yes" => Self::Approve,
+            "a" | "always" => Self::Always,
+            _ => Self::Deny,
+        }
+    }
+
+    /// The verdict recorded in `tool_executions`.
+    pub fn verdict(self) -> &'static str {
+        match self {
+            Self::Approve => "approved",
+            Self::Always => "approved_always",
+            Self::Deny => "escalate_denied",
+        }
+    }
+}
+
+/// A tool call waiting for approval.
+#[derive(Debug, Clone)]
+pub struct ApprovalRequest<'a> {
+    pub tool: &'a str,
+    /// The arguments the model called the tool with
+    pub args: &'a Value,
+    /// The arguments the call runs with, if a harness rewrote them
+    pub modified_args: Option<&'a Value>,
+    /// The harness's escalation reason or the matching `requires_approval` rule
+    pub reason: &'a str,
+}
+
+/// Decides approval requests in place of the stdin prompt (e.g. a GUI host).
+pub type ApprovalHandler = Arc<dyn Fn(&ApprovalRequest<'_>) -> ApprovalDecision + Send + Sync>;
+
+/// `*` matches any run of characters; everything else matches itself.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*`: the whole text must match
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Every string value in `args`, however deeply nested.
+fn string_args(args: &Value) -> Box<dyn Iterator<Item = &str> + '_> {
+    match args {
+        Value::String(s) => Box::new(std::iter::once(s.as_str())),
+        Value::Array(items) => Box::new(items.iter().flat_map(string_args)),
+        Value::Object(map) => Box::new(map.values().flat_map(string_args)),
+        _ => Box::new(std::iter::empty()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("shell_exec", "shell_exec"));
+        assert!(!wildcard_match("shell_exec", "shell_exec2"));
+        assert!(wildcard_match("git push*", "git push --force"));
+        assert!(wildcard_match("*.env", "config/.env"));
+        assert!(wildcard_match("*rm*-rf*", "sudo rm -rf /"));
+        assert!(!wildcard_match("*rm*-rf*", "rm -r"));
+        assert!(wildcard_match("*", ""));
+    }
+
+    #[test]
+    fn test_rules() {
+        let rules = vec!["shell_exec(git push*)".to_string(), "write_*(/etc/*)".to_string(), "delete_file".to_string()];
+        assert_eq!(matching_rule(&rules, "shell_exec", &json!({ "command": "git push origin" })), Some("shell_exec(git push*)"));
+        assert_eq!(matching_rule(&rules, "shell_exec", &json!({ "command": "git status" })), None);
+        assert_eq!(matching_rule(&rules, "write_file", &json!({ "path": "/etc/hosts", "content": "" })), Some("write_*(/etc/*)"));
+        assert_eq!(matching_rule(&rules, "delete_file", &json!({})), Some("delete_file"));
+        assert!(ApprovalRule::parse("shell_exec(git").is_err());
+        assert!(ApprovalRule::parse("(x)").is_err());
+    }
+
+    #[test]
+    fn test_decisions() {
+        assert_eq!(ApprovalDecision::from_answer(" Y\n"), ApprovalDecision::Approve);
+        assert_eq!(ApprovalDecision::from_answer("always"), ApprovalDecision::Always);
+        assert_eq!(ApprovalDecision::from_answer(""), ApprovalDecision::Deny);
+        assert_eq!(ApprovalDecision::Always.verdict(), "approved_always");
+    }
+}
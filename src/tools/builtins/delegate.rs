@@ -0,0 +1,110 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::tools::{parse_args, Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
+
+pub struct DelegateTool;
+
+#[derive(Deserialize)]
+struct DelegateArgs {
+    /// What the sub-agent should accomplish
+    goal: String,
+    /// Tools the sub-agent may use (all but `delegate` if omitted)
+    #[serde(default)]
+    tools: Option<Vec<String>>,
+    /// Turn limit for the sub-agent, capped at the parent's `kernel.max_turns`
+    #[serde(default)]
+    max_turns: Option<u32>,
+    /// Spend limit for the sub-agent in USD
+    #[serde(default)]
+    cost_budget_usd: Option<f64>,
+}
+
+#[async_trait]
+impl Tool for DelegateTool {
+    fn name(&self) -> &str {
+        "delegate"
+    }
+
+    fn description(&self) -> &str {
+        "Hand a focused sub-goal to a sub-agent that runs in its own session, optionally with a restricted set of tools and a turn or cost limit. Blocks until the sub-agent finishes and returns its final answer. Use it for self-contained work whose intermediate steps you don't need to see."
+    }
+
+    fn parameters_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "goal": {
+                    "type": "string",
+                    "description": "The sub-goal, stated so it can be completed without further context"
+                },
+                "tools": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Names of the tools the sub-agent may use. Defaults to all of yours except delegate."
+                },
+                "max_turns": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "Most LLM turns the sub-agent may take"
+                },
+                "cost_budget_usd": {
+                    "type": "number",
+                    "description": "Stop the sub-agent once its estimated spend crosses this"
+                }
+            },
+            "required": ["goal"]
+        })
+    }
+
+    /// The sub-agent may use any tool it is given.
+    fn effect(&self) -> ToolEffect {
+        ToolEffect::Execute
+    }
+
+    #[tracing::instrument(skip(self, params, _ctx))]
+    async fn execute(&self, params: Value, _ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
+        let args: DelegateArgs = parse_args(params)?;
+        if args.goal.trim().is_empty() {
+            return Err(ToolError::InvalidParams("goal must not be empty".to_string()));
+        }
+        if args.max_turns == Some(0) {
+            return Err(ToolError::InvalidParams("max_turns must be at least 1".to_string()));
+        }
+
+        // The kernel runs the sub-agent from the metadata and replaces the content with its answer.
+        Ok(ToolOutput {
+            content: format!("Delegating to a sub-agent: {}", args.goal),
+            metadata: serde_json::json!({
+                "action": "delegate",
+                "goal": args.goal,
+                "tools": args.tools,
+                "max_turns": args.max_turns,
+                "cost_budget_usd": args.cost_budget_usd,
+            }),
+            sources: Vec::new(),
+            artifacts: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_delegate_validates_args() {
+        let ctx = ToolContext { workspace_root: std::path::PathBuf::from("."), session_id: "s".to_string(), ..Default::default() };
+        let out = DelegateTool
+            .execute(serde_json::json!({ "goal": "Count the TODOs", "tools": ["read_file"], "max_turns": 3 }), &ctx)
+            .await
+            .unwrap();
+        assert_eq!(out.metadata["action"], "delegate");
+        assert_eq!(out.metadata["tools"][0], "read_file");
+        assert!(out.metadata["cost_budget_usd"].is_null());
+
+        assert!(DelegateTool.execute(serde_json::json!({ "goal": " " }), &ctx).await.is_err());
+        assert!(DelegateTool.execute(serde_json::json!({ "goal": "x", "max_turns": 0 }), &ctx).await.is_err());
+    }
+}
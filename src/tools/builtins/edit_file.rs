@@ -1,8 +1,28 @@
+//! `edit_file`: change part of a file without resending all of it.
+//!
+//! An edit is either one search/replace (`old_text` → `new_text`, which must
+//! match exactly once) or a unified diff (`diff`) with any number of hunks.
+//! Hunks are located by their context and removed lines, so a hunk whose
+//! `@@` line numbers are off still applies; when its lines occur more than
+//! once, the occurrence nearest the stated line wins. Either every hunk
+//! applies or the file is left untouched.
+//!
+//! The new contents go to a temporary file that is renamed over the
+//! original, and only if the file still holds what the edit was computed
+//! from, so a concurrent change is reported rather than overwritten. The
+//! result shows each changed region with its line numbers.
+
+use std::path::Path;
+
 use async_trait::async_trait;
 use serde::Deserialize;
 use serde_json::Value;
 
-use crate::tools::{parse_args, Tool, ToolContext, ToolError, ToolOutput};
+use super::read_file::render_numbered;
+use crate::tools::{parse_args, Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
+
+/// Unchanged lines shown around each edited region in the result
+const RESULT_CONTEXT: usize = 3;
 
 pub struct EditFileTool;
 
@@ -11,9 +31,219 @@ struct EditFileArgs {
     /// Path to edit (relative to workspace root, or absolute)
     path: String,
     /// Exact string to search for in the file
-    old_text: String,
+    old_text: Option<String>,
     /// Replacement string
-    new_text: String,
+    new_text: Option<String>,
+    /// Unified diff to apply instead of a search/replace
+    diff: Option<String>,
+}
+
+/// One `@@` hunk of a unified diff.
+#[derive(Debug)]
+struct Hunk {
+    /// 1-based line the hunk starts at in the original, per its header (0 if absent)
+    old_start: usize,
+    /// Context and removed lines: what the hunk expects to find
+    old: Vec<String>,
+    /// Context and added lines, with whether each was added
+    new: Vec<(String, bool)>,
+}
+
+/// An edit applied in memory: the new contents, plus the lines of each
+/// changed region and which lines are new, for the result.
+#[derive(Debug)]
+struct Applied {
+    content: String,
+    /// `(start, end)` line indices of each region in `content`, end exclusive
+    regions: Vec<(usize, usize)>,
+    added: Vec<usize>,
+}
+
+fn parse_hunks(diff: &str) -> Result<Vec<Hunk>, String> {
+    let mut hunks = Vec::new();
+    let mut current: Option<Hunk> = None;
+    for line in diff.lines() {
+        if let Some(header) = line.strip_prefix("@@") {
+            hunks.extend(current.take());
+            // `@@ -12,5 +12,6 @@`; the count is optional and a bare `@@` has no position
+            let old_start = header
+                .split_whitespace()
+                .next()
+                .and_then(|range| range.strip_prefix('-'))
+                .and_then(|range| range.split(',').next()?.parse().ok())
+                .unwrap_or(0);
+            current = Some(Hunk { old_start, old: Vec::new(), new: Vec::new() });
+            continue;
+        }
+        let Some(hunk) = current.as_mut() else {
+            // File headers (`diff --git`, `---`, `+++`, `index`) before the first hunk
+            continue;
+        };
+        if line.starts_with("diff ") {
+            return Err("the diff changes more than one file; send one edit_file call per file".to_string());
+        }
+        match line.chars().next() {
+            // Editors strip the trailing space of blank context lines
+            None => {
+                hunk.old.push(String::new());
+                hunk.new.push((String::new(), false));
+            }
+            Some(' ') => {
+                hunk.old.push(line[1..].to_string());
+                hunk.new.push((line[1..].to_string(), false));
+            }
+            Some('-') => hunk.old.push(line[1..].to_string()),
+            Some('+') => hunk.new.push((line[1..].to_string(), true)),
+            // `\ No newline at end of file`
+            Some('\\') => {}
+            _ => return Err(format!("unexpected line in hunk {}: '{}' (lines must start with ' ', '-' or '+')", hunks.len() + 1, line)),
+        }
+    }
+    hunks.extend(current);
+    if hunks.is_empty() {
+        return Err("no @@ hunks found in diff".to_string());
+    }
+    Ok(hunks)
+}
+
+/// The lines of `content`, without line terminators.
+fn split_lines(content: &str) -> Vec<&str> {
+    if content.is_empty() {
+        return Vec::new();
+    }
+    content
+        .strip_suffix('\n')
+        .unwrap_or(content)
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect()
+}
+
+/// Where `old` occurs in `lines` at or after `from`, nearest to `hint`.
+/// Falls back to ignoring trailing whitespace.
+fn find_hunk(lines: &[&str], old: &[String], from: usize, hint: usize) -> Option<usize> {
+    if old.is_empty() {
+        return Some(hint.clamp(from, lines.len()));
+    }
+    let nearest = |eq: &dyn Fn(&str, &str) -> bool| {
+        (from..=lines.len().saturating_sub(old.len()))
+            .filter(|&at| at + old.len() <= lines.len())
+            .filter(|&at| lines[at..at + old.len()].iter().zip(old).all(|(a, b)| eq(a, b)))
+            .min_by_key(|&at| at.abs_diff(hint))
+    };
+    nearest(&|a, b| a == b).or_else(|| nearest(&|a, b| a.trim_end() == b.trim_end()))
+}
+
+/// Apply every hunk of `diff` to `content`, or explain why one doesn't apply.
+fn apply_diff(content: &str, diff: &str) -> Result<Applied, String> {
+    let hunks = parse_hunks(diff)?;
+    let lines = split_lines(content);
+    let mut out: Vec<String> = Vec::with_capacity(lines.len());
+    let mut regions = Vec::new();
+    let mut added = Vec::new();
+    let mut pos = 0;
+    for (i, hunk) in hunks.iter().enumerate() {
+        let at = find_hunk(&lines, &hunk.old, pos, hunk.old_start.saturating_sub(1)).ok_or_else(|| {
+            format!(
+                "hunk {} (@@ -{}) does not match the file; its context and '-' lines must match the current contents exactly. Use read_file to check them.",
+                i + 1,
+                hunk.old_start
+            )
+        })?;
+        out.extend(lines[pos..at].iter().map(|line| line.to_string()));
+        let start = out.len();
+        for (line, is_added) in &hunk.new {
+            if *is_added {
+                added.push(out.len());
+            }
+            out.push(line.clone());
+        }
+        regions.push((start, out.len()));
+        pos = at + hunk.old.len();
+    }
+    out.extend(lines[pos..].iter().map(|line| line.to_string()));
+
+    let eol = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut new_content = out.join(eol);
+    if !out.is_empty() && (content.ends_with('\n') || content.is_empty()) {
+        new_content.push_str(eol);
+    }
+    Ok(Applied { content: new_content, regions, added })
+}
+
+/// Replace the single occurrence of `old_text` in `content`.
+fn apply_replace(content: &str, old_text: &str, new_text: &str, path: &Path) -> Result<Applied, ToolError> {
+    // Count occurrences
+    let count = content.matches(old_text).count();
+    if count == 0 {
+        return Err(ToolError::ExecutionError(format!(
+            "old_text not found in {}. Use read_file to verify the current contents.",
+            path.display()
+        )));
+    }
+    if count > 1 {
+        return Err(ToolError::ExecutionError(format!(
+            "old_text found {} times in {} — it must appear exactly once. Use a more specific match.",
+            count,
+            path.display()
+        )));
+    }
+
+    let offset = content.find(old_text).unwrap_or_default();
+    let start = content[..offset].matches('\n').count();
+    let end = if new_text.is_empty() { start } else { start + new_text.trim_end_matches('\n').matches('\n').count() + 1 };
+    Ok(Applied {
+        content: content.replacen(old_text, new_text, 1),
+        regions: vec![(start, end)],
+        added: (start..end).collect(),
+    })
+}
+
+/// Each changed region with `RESULT_CONTEXT` lines around it, numbered as
+/// `read_file` numbers lines (`:` marks new lines).
+fn render_regions(applied: &Applied) -> String {
+    let lines = split_lines(&applied.content);
+    let mut shown = vec![false; lines.len()];
+    for &(start, end) in &applied.regions {
+        let from = start.saturating_sub(RESULT_CONTEXT);
+        let to = (end + RESULT_CONTEXT).min(lines.len());
+        for flag in shown.iter_mut().take(to).skip(from) {
+            *flag = true;
+        }
+    }
+    let mut matched = vec![false; lines.len()];
+    for &i in applied.added.iter().filter(|&&i| i < lines.len()) {
+        matched[i] = true;
+    }
+    render_numbered(&lines, &shown, &matched)
+}
+
+/// Replace `path` with `new` through a temporary file and a rename, unless
+/// the file no longer holds `old` (it was changed since it was read).
+async fn replace_atomically(path: &Path, old: &str, new: &str) -> Result<(), ToolError> {
+    // Write through symlinks rather than replacing them
+    let path = tokio::fs::canonicalize(path).await.unwrap_or_else(|_| path.to_path_buf());
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", name, uuid::Uuid::new_v4().simple()));
+    let write_error = |e: std::io::Error| ToolError::ExecutionError(format!("Failed to write {}: {}", path.display(), e));
+
+    tokio::fs::write(&tmp, new).await.map_err(write_error)?;
+    if let Ok(metadata) = tokio::fs::metadata(&path).await {
+        let _ = tokio::fs::set_permissions(&tmp, metadata.permissions()).await;
+    }
+    let current = tokio::fs::read_to_string(&path).await.unwrap_or_default();
+    if current != old {
+        let _ = tokio::fs::remove_file(&tmp).await;
+        return Err(ToolError::ExecutionError(format!(
+            "{} changed while it was being edited. Use read_file to see the current contents and redo the edit.",
+            path.display()
+        )));
+    }
+    if let Err(e) = tokio::fs::rename(&tmp, &path).await {
+        let _ = tokio::fs::remove_file(&tmp).await;
+        return Err(write_error(e));
+    }
+    Ok(())
 }
 
 #[async_trait]
@@ -23,7 +253,7 @@ impl Tool for EditFileTool {
     }
 
     fn description(&self) -> &str {
-        "Edit a file by replacing an exact string match. The old_text must appear exactly once in the file. Use read_file first to see the current contents."
+        "Edit part of a file, either by replacing an exact string (old_text must appear exactly once) or by applying a unified diff (diff) with one or more @@ hunks. The edit is applied atomically: all of it or nothing. Returns the edited regions with line numbers. Use read_file first to see the current contents."
     }
 
     fn parameters_schema(&self) -> Value {
@@ -41,58 +271,62 @@ impl Tool for EditFileTool {
                 "new_text": {
                     "type": "string",
                     "description": "Replacement text"
+                },
+                "diff": {
+                    "type": "string",
+                    "description": "Unified diff of this file (@@ hunks with ' ' context, '-' removed and '+' added lines), instead of old_text/new_text"
                 }
             },
-            "required": ["path", "old_text", "new_text"]
+            "required": ["path"]
         })
     }
 
+    fn effect(&self) -> ToolEffect {
+        ToolEffect::Write
+    }
+
     #[tracing::instrument(skip(self, params, ctx), fields(path = %params["path"].as_str().unwrap_or("unknown")))]
     async fn execute(&self, params: Value, ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
         let args: EditFileArgs = parse_args(params)?;
         tracing::info!(path = %args.path, "Editing file");
-        
+
         // Security: validate path is within workspace using centralized logic
-        let path = crate::tools::is_safe_path(&ctx.workspace_root, std::path::Path::new(&args.path))?;
+        let path = ctx.resolve_path(&args.path)?;
 
         let content = tokio::fs::read_to_string(&path)
             .await
             .map_err(|e| ToolError::ExecutionError(format!("Failed to read {}: {}", path.display(), e)))?;
 
-        // Count occurrences
-        let count = content.matches(&args.old_text).count();
-        if count == 0 {
-            return Err(ToolError::ExecutionError(format!(
-                "old_text not found in {}. Use read_file to verify the current contents.",
-                path.display()
-            )));
-        }
-        if count > 1 {
-            return Err(ToolError::ExecutionError(format!(
-                "old_text found {} times in {} — it must appear exactly once. Use a more specific match.",
-                count,
-                path.display()
-            )));
-        }
-
-        let new_content = content.replacen(&args.old_text, &args.new_text, 1);
+        let (mode, applied) = match (&args.diff, &args.old_text, &args.new_text) {
+            (Some(diff), None, None) => ("diff", apply_diff(&content, diff).map_err(ToolError::ExecutionError)?),
+            (None, Some(old_text), Some(new_text)) => ("replace", apply_replace(&content, old_text, new_text, &path)?),
+            _ => {
+                return Err(ToolError::InvalidParams(
+                    "pass either diff, or old_text and new_text".to_string(),
+                ))
+            }
+        };
 
-        tokio::fs::write(&path, &new_content).await.map_err(|e| {
-            ToolError::ExecutionError(format!("Failed to write {}: {}", path.display(), e))
-        })?;
+        replace_atomically(&path, &content, &applied.content).await?;
 
+        let regions = applied.regions.len();
         Ok(ToolOutput {
             content: format!(
-                "Successfully edited {}. Replaced {} bytes with {} bytes.",
+                "Successfully edited {} ({} {}):\n{}",
                 path.display(),
-                args.old_text.len(),
-                args.new_text.len()
+                regions,
+                if regions == 1 { "region" } else { "regions" },
+                render_regions(&applied)
             ),
             metadata: serde_json::json!({
                 "path": path.display().to_string(),
-                "old_len": args.old_text.len(),
-                "new_len": args.new_text.len(),
+                "mode": mode,
+                "regions": regions,
+                "old_len": content.len(),
+                "new_len": applied.content.len(),
             }),
+            sources: Vec::new(),
+            artifacts: Vec::new(),
         })
     }
 }
@@ -112,6 +346,7 @@ mod tests {
         let ctx = ToolContext {
             workspace_root: dir.path().to_path_buf(),
             session_id: "test".to_string(),
+            ..Default::default()
         };
 
         let result = tool
@@ -141,6 +376,7 @@ mod tests {
         let ctx = ToolContext {
             workspace_root: dir.path().to_path_buf(),
             session_id: "test".to_string(),
+            ..Default::default()
         };
 
         let result = tool
@@ -167,6 +403,7 @@ mod tests {
         let ctx = ToolContext {
             workspace_root: dir.path().to_path_buf(),
             session_id: "test".to_string(),
+            ..Default::default()
         };
 
         let result = tool
@@ -185,4 +422,70 @@ mod tests {
         let content = std::fs::read_to_string(&file_path).unwrap();
         assert_eq!(content, "aaa aaa aaa");
     }
+
+    const SOURCE: &str = "fn main() {\n    let a = 1;\n    let b = 2;\n    println!(\"{}\", a + b);\n}\n\nfn helper() {\n    todo!()\n}\n";
+
+    #[test]
+    fn test_apply_diff() {
+        // Line numbers are off by two; the hunks are found by their content
+        let diff = "--- a/main.rs\n+++ b/main.rs\n@@ -4,3 +4,3 @@\n     let a = 1;\n-    let b = 2;\n+    let b = 40;\n     println!(\"{}\", a + b);\n@@ -9,3 +9,3 @@ fn helper\n fn helper() {\n-    todo!()\n+    println!(\"help\");\n }\n";
+        let applied = apply_diff(SOURCE, diff).unwrap();
+        assert_eq!(applied.content, SOURCE.replace("b = 2", "b = 40").replace("todo!()", "println!(\"help\");"));
+        assert_eq!(applied.regions, vec![(1, 4), (6, 9)]);
+        assert_eq!(applied.added, vec![2, 7]);
+        let rendered = render_regions(&applied);
+        assert!(rendered.contains("\n3:     let b = 40;"));
+        assert!(rendered.starts_with("1- fn main() {\n2-     let a = 1;"));
+
+        // CRLF line endings are kept
+        let crlf = apply_diff("a\r\nb\r\n", "@@ -1,2 +1,2 @@\n a\n-b\n+c\n").unwrap();
+        assert_eq!(crlf.content, "a\r\nc\r\n");
+
+        let err = apply_diff(SOURCE, "@@ -2,1 +2,1 @@\n-    let c = 3;\n+    let c = 4;\n").unwrap_err();
+        assert!(err.contains("hunk 1"));
+        assert!(apply_diff(SOURCE, "just some text").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_edit_file_diff() {
+        let dir = TempDir::new().unwrap();
+        let file_path = dir.path().join("main.rs");
+        std::fs::write(&file_path, SOURCE).unwrap();
+        let ctx = ToolContext {
+            workspace_root: dir.path().to_path_buf(),
+            session_id: "test".to_string(),
+            ..Default::default()
+        };
+
+        // One bad hunk leaves the file untouched
+        let bad = "@@ -2 +2 @@\n-    let a = 1;\n+    let a = 5;\n@@ -8 +8 @@\n-    unimplemented!()\n+    ()\n";
+        let result = EditFileTool.execute(serde_json::json!({ "path": "main.rs", "diff": bad }), &ctx).await;
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_to_string(&file_path).unwrap(), SOURCE);
+
+        let good = bad.replace("unimplemented!()", "todo!()");
+        let result = EditFileTool.execute(serde_json::json!({ "path": "main.rs", "diff": good }), &ctx).await.unwrap();
+        assert!(result.content.contains("(2 regions)"));
+        assert!(result.content.contains("2:     let a = 5;"));
+        assert_eq!(std::fs::read_to_string(&file_path).unwrap(), SOURCE.replace("a = 1", "a = 5").replace("todo!()", "()"));
+        // No temporary files are left behind
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let both = EditFileTool
+            .execute(serde_json::json!({ "path": "main.rs", "diff": good, "old_text": "a", "new_text": "b" }), &ctx)
+            .await;
+        assert!(matches!(both, Err(ToolError::InvalidParams(_))));
+    }
+
+    #[tokio::test]
+    async fn test_replace_atomically_detects_concurrent_change() {
+        let dir = TempDir::new().unwrap();
+        let file_path = dir.path().join("test.txt");
+        std::fs::write(&file_path, "edited elsewhere").unwrap();
+
+        let result = replace_atomically(&file_path, "original", "mine").await;
+        assert!(result.unwrap_err().to_string().contains("changed while it was being edited"));
+        assert_eq!(std::fs::read_to_string(&file_path).unwrap(), "edited elsewhere");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
 }
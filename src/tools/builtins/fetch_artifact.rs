@@ -0,0 +1,159 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::persistence::state::ArtifactRow;
+use crate::tools::{is_text_kind, parse_args, Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
+
+/// Characters returned when the model doesn't ask for a length
+const DEFAULT_FETCH_CHARS: usize = 4_000;
+
+/// Hard cap on characters returned by a single fetch
+const MAX_FETCH_CHARS: usize = 20_000;
+
+pub struct FetchArtifactTool;
+
+#[derive(Deserialize)]
+struct FetchArtifactArgs {
+    /// Artifact id from a tool result's handle
+    id: String,
+    /// Character offset to start reading at
+    #[serde(default)]
+    offset: usize,
+    /// Characters to read
+    length: Option<usize>,
+    /// Workspace path to write the whole artifact to instead of reading it
+    save_to: Option<String>,
+}
+
+/// Render `length` characters of a text artifact starting at `offset`, with a
+/// header giving the range and a hint for reading on.
+pub fn render_slice(artifact: &ArtifactRow, offset: usize, length: usize) -> String {
+    if !is_text_kind(&artifact.kind) {
+        return format!(
+            "Artifact {} is binary ({}, {} bytes); use save_to to write it to a file.",
+            artifact.id, artifact.kind, artifact.size
+        );
+    }
+    let text = String::from_utf8_lossy(&artifact.data);
+    let total = text.chars().count();
+    let start = offset.min(total);
+    let end = (start + length).min(total);
+    let slice: String = text.chars().skip(start).take(end - start).collect();
+    let mut out = format!("[artifact {}: chars {}-{} of {}]\n{}", artifact.id, start, end, total, slice);
+    if end < total {
+        out.push_str(&format!("\n[... {} more chars; continue with offset={}]", total - end, end));
+    }
+    out
+}
+
+#[async_trait]
+impl Tool for FetchArtifactTool {
+    fn name(&self) -> &str {
+        "fetch_artifact"
+    }
+
+    fn description(&self) -> &str {
+        "Read part of a stored artifact (a large tool result shown only as a handle), or save the whole artifact to a workspace file with save_to. Read in slices with offset and length rather than pulling everything into context."
+    }
+
+    fn parameters_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "id": {
+                    "type": "string",
+                    "description": "Artifact id, e.g. art_1a2b3c4d5e6f"
+                },
+                "offset": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "Character offset to start reading at (default 0)"
+                },
+                "length": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": MAX_FETCH_CHARS,
+                    "description": "Characters to read (default 4000)"
+                },
+                "save_to": {
+                    "type": "string",
+                    "description": "Write the whole artifact to this workspace path instead of returning it"
+                }
+            },
+            "required": ["id"]
+        })
+    }
+
+    /// `save_to` writes into the workspace.
+    fn effect(&self) -> ToolEffect {
+        ToolEffect::Write
+    }
+
+    #[tracing::instrument(skip(self, params, ctx))]
+    async fn execute(&self, params: Value, ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
+        let args: FetchArtifactArgs = parse_args(params)?;
+        if args.length == Some(0) {
+            return Err(ToolError::InvalidParams("length must be at least 1".to_string()));
+        }
+        let save_to = match &args.save_to {
+            Some(path) => Some(ctx.resolve_path(path)?.display().to_string()),
+            None => None,
+        };
+
+        // The kernel reads the artifact from the state store and replaces the content.
+        Ok(ToolOutput {
+            content: format!("Fetching artifact {}", args.id),
+            metadata: serde_json::json!({
+                "action": "fetch_artifact",
+                "id": args.id,
+                "offset": args.offset,
+                "length": args.length.unwrap_or(DEFAULT_FETCH_CHARS).min(MAX_FETCH_CHARS),
+                "save_to": save_to,
+            }),
+            sources: Vec::new(),
+            artifacts: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(kind: &str, data: &str) -> ArtifactRow {
+        ArtifactRow {
+            id: "art_1".to_string(),
+            session_id: "s".to_string(),
+            tool_call_id: "call".to_string(),
+            kind: kind.to_string(),
+            size: data.len() as u64,
+            preview: String::new(),
+            data: data.as_bytes().to_vec(),
+            created_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_render_slice() {
+        let artifact = row("text/plain", "héllo world");
+        assert_eq!(render_slice(&artifact, 0, 5), "[artifact art_1: chars 0-5 of 11]\nhéllo\n[... 6 more chars; continue with offset=5]");
+        assert_eq!(render_slice(&artifact, 6, 100), "[artifact art_1: chars 6-11 of 11]\nworld");
+        assert!(render_slice(&artifact, 50, 5).starts_with("[artifact art_1: chars 11-11 of 11]"));
+        assert!(render_slice(&row("image/png", "\u{1}"), 0, 5).contains("binary"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_artifact_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = ToolContext { workspace_root: dir.path().to_path_buf(), session_id: "s".to_string(), ..Default::default() };
+        let out = FetchArtifactTool.execute(serde_json::json!({ "id": "art_1", "length": 1_000_000 }), &ctx).await.unwrap();
+        assert_eq!(out.metadata["action"], "fetch_artifact");
+        assert_eq!(out.metadata["length"], MAX_FETCH_CHARS);
+        assert!(out.metadata["save_to"].is_null());
+
+        let out = FetchArtifactTool.execute(serde_json::json!({ "id": "art_1", "save_to": "out/log.txt" }), &ctx).await.unwrap();
+        assert!(out.metadata["save_to"].as_str().unwrap().ends_with("out/log.txt"));
+        assert!(FetchArtifactTool.execute(serde_json::json!({ "id": "art_1", "save_to": "../x" }), &ctx).await.is_err());
+    }
+}
@@ -0,0 +1,329 @@
+//! `fetch_url`: HTTP GET and POST for the model, within `[tools.network]`.
+//!
+//! Every request, including each redirect hop, is checked against the
+//! session's [`NetworkPolicy`](crate::tools::network::NetworkPolicy) before
+//! it is sent. Headers configured under `[tools.fetch.headers."<domain>"]`
+//! are added to requests for that domain only, so secrets such as API
+//! tokens (usually written as `${VAR}`) never pass through the model; they
+//! are also masked if a response echoes them. Bodies are read up to
+//! `max_response_bytes` and cut there.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::tools::network::{domain_matches, NetworkPolicy, PolicyResolver};
+use crate::tools::{parse_args, Source, Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
+
+/// Redirects followed before giving up
+const MAX_REDIRECTS: usize = 5;
+
+/// Limits and secret headers for `fetch_url` (`[tools.fetch]`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct FetchConfig {
+    /// Response body bytes kept; the rest is not read
+    #[serde(default = "default_max_response_bytes")]
+    pub max_response_bytes: usize,
+    /// Seconds a request may take, redirects included
+    #[serde(default = "default_fetch_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Headers added to requests by domain pattern (as in `tools.network`),
+    /// e.g. `Authorization = "Bearer ${GITHUB_TOKEN}"` for `api.github.com`
+    #[serde(default)]
+    pub headers: HashMap<String, HashMap<String, String>>,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            max_response_bytes: default_max_response_bytes(),
+            timeout_secs: default_fetch_timeout_secs(),
+            headers: HashMap::new(),
+        }
+    }
+}
+
+fn default_max_response_bytes() -> usize {
+    100_000
+}
+
+fn default_fetch_timeout_secs() -> u64 {
+    30
+}
+
+pub struct FetchUrlTool {
+    config: FetchConfig,
+}
+
+impl FetchUrlTool {
+    pub fn new(config: FetchConfig) -> Self {
+        Self { config }
+    }
+
+    /// A client whose DNS lookups are checked against `policy`.
+    fn client(&self, policy: Arc<NetworkPolicy>) -> Result<reqwest::Client, ToolError> {
+        reqwest::Client::builder()
+            // Redirects are followed by hand so each hop passes the network policy
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(std::time::Duration::from_secs(self.config.timeout_secs))
+            .dns_resolver(Arc::new(PolicyResolver::new(policy)))
+            .build()
+            .map_err(|e| ToolError::ExecutionError(format!("Could not build the HTTP client: {}", e)))
+    }
+
+    /// Configured headers for requests to `host`.
+    fn secret_headers(&self, host: &str) -> impl Iterator<Item = (&String, &String)> {
+        let host = host.to_ascii_lowercase();
+        self.config
+            .headers
+            .iter()
+            .filter(move |(pattern, _)| domain_matches(pattern, &host))
+            .flat_map(|(_, headers)| headers.iter())
+    }
+}
+
+impl Default for FetchUrlTool {
+    fn default() -> Self {
+        Self::new(FetchConfig::default())
+    }
+}
+
+#[derive(Deserialize)]
+struct FetchUrlArgs {
+    url: String,
+    /// `GET` (default) or `POST`
+    method: Option<String>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    /// Request body, for POST
+    body: Option<String>,
+}
+
+#[async_trait]
+impl Tool for FetchUrlTool {
+    fn name(&self) -> &str {
+        "fetch_url"
+    }
+
+    fn description(&self) -> &str {
+        "Fetch a URL over HTTP(S) with GET or POST and return the status, content type and body as text. Redirects are followed. Only hosts allowed by the configured network policy can be reached, and long bodies are cut."
+    }
+
+    fn parameters_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "url": {
+                    "type": "string",
+                    "description": "http:// or https:// URL to fetch"
+                },
+                "method": {
+                    "type": "string",
+                    "enum": ["GET", "POST"],
+                    "description": "HTTP method (default GET)"
+                },
+                "headers": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                    "description": "Extra request headers"
+                },
+                "body": {
+                    "type": "string",
+                    "description": "Request body for POST; set a Content-Type header to match"
+                }
+            },
+            "required": ["url"]
+        })
+    }
+
+    fn effect(&self) -> ToolEffect {
+        // POST can change state elsewhere
+        ToolEffect::Write
+    }
+
+    #[tracing::instrument(skip(self, params, ctx), fields(url = %params["url"].as_str().unwrap_or("unknown")))]
+    async fn execute(&self, params: Value, ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
+        let args: FetchUrlArgs = parse_args(params)?;
+        let mut method = match args.method.as_deref().map(str::to_ascii_uppercase).as_deref() {
+            None | Some("GET") => reqwest::Method::GET,
+            Some("POST") => reqwest::Method::POST,
+            Some(other) => return Err(ToolError::InvalidParams(format!("unsupported method '{}': use GET or POST", other))),
+        };
+        let mut body = args.body;
+        let mut url = reqwest::Url::parse(&args.url).map_err(|e| ToolError::InvalidParams(format!("invalid url '{}': {}", args.url, e)))?;
+        let mut secrets: Vec<&str> = Vec::new();
+        let client = self.client(ctx.network.clone())?;
+
+        for _ in 0..=MAX_REDIRECTS {
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(ToolError::InvalidParams(format!("only http and https URLs can be fetched, not {}", url.scheme())));
+            }
+            let host = url.host_str().ok_or_else(|| ToolError::InvalidParams(format!("{} has no host", url)))?.to_string();
+            ctx.network.check_host(&host)?;
+
+            let mut request = client.request(method.clone(), url.clone());
+            for (name, value) in &args.headers {
+                request = request.header(name.as_str(), value.as_str());
+            }
+            for (name, value) in self.secret_headers(&host) {
+                request = request.header(name.as_str(), value.as_str());
+                secrets.push(value);
+            }
+            if let Some(body) = &body {
+                request = request.body(body.clone());
+            }
+            let mut response = request.send().await.map_err(|e| request_error(&url, e))?;
+
+            let status = response.status();
+            if status.is_redirection() {
+                if let Some(location) = response.headers().get(reqwest::header::LOCATION).and_then(|l| l.to_str().ok()) {
+                    url = url
+                        .join(location)
+                        .map_err(|e| ToolError::ExecutionError(format!("Bad redirect from {} to '{}': {}", url, location, e)))?;
+                    // As browsers do: 301, 302 and 303 turn a POST into a GET
+                    if status != reqwest::StatusCode::TEMPORARY_REDIRECT && status != reqwest::StatusCode::PERMANENT_REDIRECT {
+                        method = reqwest::Method::GET;
+                        body = None;
+                    }
+                    continue;
+                }
+            }
+
+            let content_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|t| t.to_str().ok())
+                .unwrap_or("unknown")
+                .to_string();
+            let limit = self.config.max_response_bytes;
+            let mut bytes = Vec::new();
+            let mut truncated = false;
+            while let Some(chunk) = response
+                .chunk()
+                .await
+                .map_err(|e| ToolError::ExecutionError(format!("Reading the response from {} failed: {}", url, e)))?
+            {
+                bytes.extend_from_slice(&chunk);
+                if bytes.len() > limit {
+                    bytes.truncate(limit);
+                    truncated = true;
+                    break;
+                }
+            }
+
+            let mut text = String::from_utf8_lossy(&bytes).into_owned();
+            for secret in secrets.iter().filter(|s| !s.is_empty()) {
+                text = text.replace(secret, "[REDACTED]");
+            }
+            let mut content = format!("HTTP {}\nURL: {}\nContent-Type: {}\n\n{}", status, url, content_type, text);
+            if truncated {
+                content.push_str(&format!("\n\n[response truncated at {} bytes]", limit));
+            }
+            return Ok(ToolOutput {
+                content,
+                metadata: serde_json::json!({
+                    "url": args.url,
+                    "final_url": url.to_string(),
+                    "status": status.as_u16(),
+                    "content_type": content_type,
+                    "bytes": bytes.len(),
+                    "truncated": truncated,
+                }),
+                sources: vec![Source::Url { url: url.to_string(), title: None }],
+                artifacts: Vec::new(),
+            });
+        }
+        Err(ToolError::ExecutionError(format!("Too many redirects fetching {} (more than {})", args.url, MAX_REDIRECTS)))
+    }
+}
+
+/// A failed request as a tool error; a policy refusal from the resolver
+/// keeps its `PermissionDenied`.
+fn request_error(url: &reqwest::Url, error: reqwest::Error) -> ToolError {
+    let mut source = std::error::Error::source(&error);
+    while let Some(cause) = source {
+        if let Some(ToolError::PermissionDenied(reason)) = cause.downcast_ref::<ToolError>() {
+            return ToolError::PermissionDenied(reason.clone());
+        }
+        source = cause.source();
+    }
+    ToolError::ExecutionError(format!("Request to {} failed: {}", url, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderMap;
+    use axum::routing::{get, post};
+    use axum::Router;
+
+    /// A local server; the context allows it explicitly, as loopback is refused by default.
+    async fn serve() -> (String, ToolContext) {
+        let app = Router::new()
+            .route("/hello", get(|| async { "hello from the web" }))
+            .route("/echo", post(|headers: HeaderMap, body: String| async move {
+                let token = headers.get("x-token").and_then(|t| t.to_str().ok()).unwrap_or("none").to_string();
+                format!("token={} body={}", token, body)
+            }))
+            .route("/moved", post(|| async { axum::response::Redirect::to("/hello") }))
+            .route("/away", get(|| async { axum::response::Redirect::to("http://169.254.169.254/latest/meta-data") }))
+            .route("/big", get(|| async { "x".repeat(5000) }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let ctx = ToolContext {
+            network: Arc::new(NetworkPolicy { allow_domains: vec!["127.0.0.1".into()], ..Default::default() }),
+            ..Default::default()
+        };
+        (format!("http://{}", addr), ctx)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_url() {
+        let (base, ctx) = serve().await;
+        let mut config = FetchConfig { max_response_bytes: 1000, ..Default::default() };
+        config.headers.insert("127.0.0.1".into(), HashMap::from([("X-Token".to_string(), "s3cret".to_string())]));
+        let tool = FetchUrlTool::new(config);
+
+        let result = tool.execute(serde_json::json!({ "url": format!("{}/hello", base) }), &ctx).await.unwrap();
+        assert!(result.content.starts_with("HTTP 200 OK"));
+        assert!(result.content.ends_with("hello from the web"));
+
+        // The configured header is sent, and masked where the response echoes it
+        let result = tool
+            .execute(serde_json::json!({ "url": format!("{}/echo", base), "method": "post", "body": "ping" }), &ctx)
+            .await
+            .unwrap();
+        assert!(result.content.ends_with("token=[REDACTED] body=ping"));
+
+        // 303 turns the POST into a GET
+        let result = tool.execute(serde_json::json!({ "url": format!("{}/moved", base), "method": "POST" }), &ctx).await.unwrap();
+        assert_eq!(result.metadata["final_url"], format!("{}/hello", base));
+
+        let result = tool.execute(serde_json::json!({ "url": format!("{}/big", base) }), &ctx).await.unwrap();
+        assert_eq!(result.metadata["bytes"], 1000);
+        assert!(result.content.ends_with("[response truncated at 1000 bytes]"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_url_policy() {
+        let (base, ctx) = serve().await;
+        let tool = FetchUrlTool::default();
+
+        // Redirects are checked hop by hop
+        let away = tool.execute(serde_json::json!({ "url": format!("{}/away", base) }), &ctx).await;
+        assert!(matches!(away, Err(ToolError::PermissionDenied(_))));
+
+        // Loopback is refused unless allowed
+        let local = tool.execute(serde_json::json!({ "url": format!("{}/hello", base) }), &ToolContext::default()).await;
+        assert!(matches!(local, Err(ToolError::PermissionDenied(_))));
+
+        let file = tool.execute(serde_json::json!({ "url": "file:///etc/passwd" }), &ctx).await;
+        assert!(matches!(file, Err(ToolError::InvalidParams(_))));
+        let delete = tool.execute(serde_json::json!({ "url": base, "method": "DELETE" }), &ctx).await;
+        assert!(matches!(delete, Err(ToolError::InvalidParams(_))));
+    }
+}
@@ -0,0 +1,184 @@
+//! `glob`: find workspace files by path pattern.
+//!
+//! Walks like `grep` does (skipping `.gitignore`d and hidden files) and
+//! lists the files whose workspace-relative path matches the pattern,
+//! sorted by path, up to `max_results` paths or `MAX_OUTPUT_BYTES` of output.
+
+use std::path::Path;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::grep::{display_path, path_matcher, workspace_walker, MAX_OUTPUT_BYTES};
+use crate::tools::{parse_args, Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
+
+/// Paths returned when the model doesn't ask for a number
+const DEFAULT_MAX_RESULTS: usize = 200;
+
+/// Most paths one call may ask for
+const MAX_RESULTS: usize = 1000;
+
+pub struct GlobTool;
+
+#[derive(Deserialize)]
+struct GlobArgs {
+    /// Glob over workspace-relative paths
+    pattern: String,
+    /// Directory to search (default: the workspace root)
+    path: Option<String>,
+    max_results: Option<usize>,
+}
+
+#[async_trait]
+impl Tool for GlobTool {
+    fn name(&self) -> &str {
+        "glob"
+    }
+
+    fn description(&self) -> &str {
+        "Find files in the workspace whose path matches a glob, such as \"**/*.rs\" or \"src/**/mod.rs\". A pattern without a '/' matches file names in any directory. Skips files ignored by .gitignore and hidden files. Returns workspace-relative paths, one per line."
+    }
+
+    fn parameters_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "pattern": {
+                    "type": "string",
+                    "description": "Glob over workspace-relative paths: * and ? within a path segment, ** across directories, {a,b} alternatives"
+                },
+                "path": {
+                    "type": "string",
+                    "description": "Directory to search (default: the whole workspace)"
+                },
+                "max_results": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": MAX_RESULTS,
+                    "description": "Most paths to return (default 200)"
+                }
+            },
+            "required": ["pattern"]
+        })
+    }
+
+    fn effect(&self) -> ToolEffect {
+        ToolEffect::ReadOnly
+    }
+
+    #[tracing::instrument(skip(self, params, ctx), fields(pattern = %params["pattern"].as_str().unwrap_or("unknown")))]
+    async fn execute(&self, params: Value, ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
+        let args: GlobArgs = parse_args(params)?;
+        let matches = path_matcher(&args.pattern)?;
+        let max_results = args.max_results.unwrap_or(DEFAULT_MAX_RESULTS).clamp(1, MAX_RESULTS);
+
+        // Security: validate path is within workspace using centralized logic
+        let dir = ctx.resolve_path(args.path.as_deref().unwrap_or("."))?;
+        if !dir.is_dir() {
+            return Err(ToolError::ExecutionError(format!("{} is not a directory", dir.display())));
+        }
+
+        // Canonical, like `dir`, so results are listed relative to it
+        let root = ctx.resolve_path(".")?;
+        let (paths, total, full) = tokio::task::spawn_blocking(move || {
+            let mut paths = Vec::new();
+            let mut total = 0;
+            let mut bytes = 0;
+            // Whether the output limit, rather than max_results, cut the listing
+            let mut full = false;
+            for entry in workspace_walker(&dir).build().flatten() {
+                if !entry.file_type().is_some_and(|t| t.is_file()) {
+                    continue;
+                }
+                let relative = display_path(&root, entry.path());
+                if matches(Path::new(&relative)) {
+                    total += 1;
+                    full |= paths.len() < max_results && bytes + relative.len() + 1 > MAX_OUTPUT_BYTES;
+                    if paths.len() < max_results && !full {
+                        bytes += relative.len() + 1;
+                        paths.push(relative);
+                    }
+                }
+            }
+            (paths, total, full)
+        })
+        .await
+        .map_err(|e| ToolError::ExecutionError(format!("Search failed: {}", e)))?;
+
+        let mut content = if paths.is_empty() {
+            format!("No files match '{}'", args.pattern)
+        } else {
+            paths.join("\n")
+        };
+        if total > paths.len() {
+            let limit = if full { format!("the {} byte output limit", MAX_OUTPUT_BYTES) } else { format!("max_results ({})", max_results) };
+            content.push_str(&format!(
+                "\n\n[{} more files not shown, past {}; use a more specific pattern or path]",
+                total - paths.len(),
+                limit
+            ));
+        }
+        Ok(ToolOutput {
+            content,
+            metadata: serde_json::json!({
+                "pattern": args.pattern,
+                "files": total,
+                "truncated": total > paths.len(),
+            }),
+            sources: Vec::new(),
+            artifacts: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_glob() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("src/tools")).unwrap();
+        std::fs::create_dir_all(dir.path().join("target")).unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+        for file in ["src/main.rs", "src/tools/mod.rs", "src/tools/grep.rs", "README.md", "target/build.rs"] {
+            std::fs::write(dir.path().join(file), "").unwrap();
+        }
+        let ctx = ToolContext {
+            workspace_root: dir.path().to_path_buf(),
+            session_id: "test".to_string(),
+            ..Default::default()
+        };
+
+        let result = GlobTool.execute(serde_json::json!({ "pattern": "*.rs" }), &ctx).await.unwrap();
+        assert_eq!(result.content, "src/main.rs\nsrc/tools/grep.rs\nsrc/tools/mod.rs");
+
+        let result = GlobTool.execute(serde_json::json!({ "pattern": "src/*.rs" }), &ctx).await.unwrap();
+        assert_eq!(result.content, "src/main.rs");
+
+        let result = GlobTool
+            .execute(serde_json::json!({ "pattern": "**/{mod,main}.rs", "path": "src/tools" }), &ctx)
+            .await
+            .unwrap();
+        assert_eq!(result.content, "src/tools/mod.rs");
+
+        let result = GlobTool.execute(serde_json::json!({ "pattern": "**/*", "max_results": 2 }), &ctx).await.unwrap();
+        assert_eq!(result.metadata["files"], 4);
+        assert!(result.content.ends_with("[2 more files not shown, past max_results (2); use a more specific pattern or path]"));
+
+        let long = dir.path().join("x".repeat(200));
+        std::fs::create_dir_all(&long).unwrap();
+        for i in 0..300 {
+            std::fs::write(long.join(format!("{}_{}.txt", "y".repeat(40), i)), "").unwrap();
+        }
+        let result = GlobTool.execute(serde_json::json!({ "pattern": "*.txt", "max_results": 1000 }), &ctx).await.unwrap();
+        assert_eq!(result.metadata["files"], 300);
+        assert!(result.content.len() < MAX_OUTPUT_BYTES + 200);
+        assert!(result.content.contains("past the 50000 byte output limit"));
+
+        let invalid = GlobTool.execute(serde_json::json!({ "pattern": "src/[" }), &ctx).await;
+        assert!(matches!(invalid, Err(ToolError::InvalidParams(_))));
+    }
+}
@@ -0,0 +1,345 @@
+//! `grep`: regex search across the workspace, ripgrep-style.
+//!
+//! Walks the workspace (or `path` within it) the way ripgrep does: files
+//! matched by `.gitignore`, `.ignore` and `.git/info/exclude` are skipped,
+//! as are hidden files and binary files. Matches are listed per file with
+//! line numbers, as `read_file` numbers them. Results stop at `max_results`
+//! matches or `MAX_OUTPUT_BYTES` of output, whichever comes first.
+
+use std::path::Path;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::read_file::numbered_lines;
+use crate::tools::{parse_args, Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
+
+/// Matches returned when the model doesn't ask for a number
+const DEFAULT_MAX_RESULTS: usize = 100;
+
+/// Most matches one call may ask for
+const MAX_RESULTS: usize = 500;
+
+/// Most context lines allowed around a match
+const MAX_CONTEXT: usize = 10;
+
+/// Most output listed; lines past it are left out
+pub(super) const MAX_OUTPUT_BYTES: usize = 50_000;
+
+/// Longest line shown; longer lines (minified code) are cut
+const MAX_LINE_BYTES: usize = 300;
+
+/// Files larger than this are not searched
+const MAX_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+pub struct GrepTool;
+
+#[derive(Deserialize)]
+struct GrepArgs {
+    /// Regex to search for
+    pattern: String,
+    /// File or directory to search (default: the workspace root)
+    path: Option<String>,
+    /// Only search files whose path matches this glob
+    glob: Option<String>,
+    #[serde(default)]
+    case_insensitive: bool,
+    /// Lines of context around each match
+    context: Option<usize>,
+    max_results: Option<usize>,
+}
+
+/// A `.gitignore`-aware walk of `dir`, as ripgrep does it. Ignore files are
+/// honoured even outside a git repository.
+pub(super) fn workspace_walker(dir: &Path) -> ignore::WalkBuilder {
+    let mut builder = ignore::WalkBuilder::new(dir);
+    builder.require_git(false).sort_by_file_path(|a, b| a.cmp(b));
+    builder
+}
+
+/// `path` relative to the workspace root, for display.
+pub(super) fn display_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root).unwrap_or(path).display().to_string()
+}
+
+/// A glob matched against workspace-relative paths; one without a `/`
+/// matches file names in any directory, as in `.gitignore`.
+pub(super) fn path_matcher(pattern: &str) -> Result<impl Fn(&Path) -> bool, ToolError> {
+    let matcher = globset::GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| ToolError::InvalidParams(format!("invalid glob '{}': {}", pattern, e)))?
+        .compile_matcher();
+    let by_name = !pattern.contains('/');
+    Ok(move |path: &Path| {
+        matcher.is_match(path) || (by_name && path.file_name().is_some_and(|name| matcher.is_match(name)))
+    })
+}
+
+/// Why a search listed fewer matches than there are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Truncation {
+    /// `max_results` matches were found
+    MaxResults,
+    /// The listing reached `MAX_OUTPUT_BYTES`
+    OutputBytes,
+}
+
+/// What a search found.
+struct Found {
+    output: String,
+    matches: usize,
+    files: usize,
+    truncated: Option<Truncation>,
+}
+
+fn search(root: &Path, dir: &Path, pattern: &regex::Regex, glob: Option<&dyn Fn(&Path) -> bool>, context: usize, max_results: usize) -> Found {
+    let mut found = Found { output: String::new(), matches: 0, files: 0, truncated: None };
+    for entry in workspace_walker(dir).build().flatten() {
+        if !entry.file_type().is_some_and(|t| t.is_file()) || entry.metadata().is_ok_and(|m| m.len() > MAX_FILE_BYTES) {
+            continue;
+        }
+        let relative = display_path(root, entry.path());
+        if glob.is_some_and(|matches| !matches(Path::new(&relative))) {
+            continue;
+        }
+        // Unreadable, binary and non-UTF-8 files are skipped
+        let Ok(content) = std::fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        if content.contains('\0') {
+            continue;
+        }
+
+        let lines: Vec<&str> = content.lines().map(|line| &line[..line.floor_char_boundary(MAX_LINE_BYTES)]).collect();
+        let mut shown = vec![false; lines.len()];
+        let mut matched = vec![false; lines.len()];
+        let mut hits = 0;
+        for (i, line) in content.lines().enumerate() {
+            if !pattern.is_match(line) {
+                continue;
+            }
+            if found.matches + hits == max_results {
+                found.truncated = Some(Truncation::MaxResults);
+                break;
+            }
+            hits += 1;
+            matched[i] = true;
+            shown[i.saturating_sub(context)..(i + context + 1).min(lines.len())].fill(true);
+        }
+        if hits == 0 {
+            continue;
+        }
+        // Lines are added while they fit, so one file can't overrun the limit
+        let start = found.output.len();
+        found.output.push_str(&relative);
+        found.output.push('\n');
+        let mut listed = 0;
+        for (line, is_match) in numbered_lines(&lines, &shown, &matched) {
+            if found.output.len() + line.len() + 2 > MAX_OUTPUT_BYTES {
+                found.truncated = Some(Truncation::OutputBytes);
+                break;
+            }
+            found.output.push_str(&line);
+            found.output.push('\n');
+            listed += usize::from(is_match);
+        }
+        if listed == 0 {
+            found.output.truncate(start);
+            break;
+        }
+        found.output.push('\n');
+        found.matches += listed;
+        found.files += 1;
+        if found.truncated.is_some() {
+            break;
+        }
+    }
+    found
+}
+
+#[async_trait]
+impl Tool for GrepTool {
+    fn name(&self) -> &str {
+        "grep"
+    }
+
+    fn description(&self) -> &str {
+        "Search file contents in the workspace with a regex, like ripgrep. Skips files ignored by .gitignore, hidden files and binary files. Returns matching lines with their line numbers, grouped by file. Narrow the search with path (a file or directory) and glob (e.g. \"*.rs\")."
+    }
+
+    fn parameters_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "pattern": {
+                    "type": "string",
+                    "description": "Regex to search for (Rust regex syntax)"
+                },
+                "path": {
+                    "type": "string",
+                    "description": "File or directory to search (default: the whole workspace)"
+                },
+                "glob": {
+                    "type": "string",
+                    "description": "Only search files matching this glob, e.g. \"*.rs\" or \"src/**/*.ts\""
+                },
+                "case_insensitive": {
+                    "type": "boolean",
+                    "description": "Ignore case (default false)"
+                },
+                "context": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "maximum": MAX_CONTEXT,
+                    "description": "Lines of context around each match (default 0)"
+                },
+                "max_results": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": MAX_RESULTS,
+                    "description": "Most matches to return (default 100)"
+                }
+            },
+            "required": ["pattern"]
+        })
+    }
+
+    fn effect(&self) -> ToolEffect {
+        ToolEffect::ReadOnly
+    }
+
+    #[tracing::instrument(skip(self, params, ctx), fields(pattern = %params["pattern"].as_str().unwrap_or("unknown")))]
+    async fn execute(&self, params: Value, ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
+        let args: GrepArgs = parse_args(params)?;
+        let pattern = regex::RegexBuilder::new(&args.pattern)
+            .case_insensitive(args.case_insensitive)
+            .build()
+            .map_err(|e| ToolError::InvalidParams(format!("invalid pattern: {}", e)))?;
+        let glob = args.glob.as_deref().map(path_matcher).transpose()?;
+        let context = args.context.unwrap_or(0).min(MAX_CONTEXT);
+        let max_results = args.max_results.unwrap_or(DEFAULT_MAX_RESULTS).clamp(1, MAX_RESULTS);
+
+        // Security: validate path is within workspace using centralized logic
+        let dir = ctx.resolve_path(args.path.as_deref().unwrap_or("."))?;
+        if !dir.exists() {
+            return Err(ToolError::ExecutionError(format!("{} does not exist", dir.display())));
+        }
+
+        // Canonical, like `dir`, so results are listed relative to it
+        let root = ctx.resolve_path(".")?;
+        let found = tokio::task::spawn_blocking(move || {
+            search(&root, &dir, &pattern, glob.as_ref().map(|g| g as &dyn Fn(&Path) -> bool), context, max_results)
+        })
+        .await
+        .map_err(|e| ToolError::ExecutionError(format!("Search failed: {}", e)))?;
+
+        let mut content = if found.matches == 0 {
+            format!("No matches for /{}/", args.pattern)
+        } else {
+            format!("{} matches in {} files:\n\n{}", found.matches, found.files, found.output.trim_end())
+        };
+        let limit = match found.truncated {
+            Some(Truncation::MaxResults) => Some(format!("max_results ({})", max_results)),
+            Some(Truncation::OutputBytes) => Some(format!("the {} byte output limit", MAX_OUTPUT_BYTES)),
+            None => None,
+        };
+        if let Some(limit) = limit {
+            content.push_str(&format!(
+                "\n\n[results truncated at {} matches by {}; narrow the search with path or glob, or a more specific pattern]",
+                found.matches, limit
+            ));
+        }
+        Ok(ToolOutput {
+            content,
+            metadata: serde_json::json!({
+                "pattern": args.pattern,
+                "matches": found.matches,
+                "files": found.files,
+                "truncated": found.truncated.is_some(),
+            }),
+            sources: Vec::new(),
+            artifacts: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn workspace() -> (TempDir, ToolContext) {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("src/nested")).unwrap();
+        std::fs::create_dir_all(dir.path().join("target")).unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() {\n    helper();\n}\n").unwrap();
+        std::fs::write(dir.path().join("src/nested/lib.rs"), "pub fn helper() {}\n// TODO: more helpers\n").unwrap();
+        std::fs::write(dir.path().join("notes.md"), "call Helper() first\n").unwrap();
+        std::fs::write(dir.path().join("target/out.rs"), "fn helper() {}\n").unwrap();
+        std::fs::write(dir.path().join("blob.bin"), b"helper\0\x01").unwrap();
+        let ctx = ToolContext {
+            workspace_root: dir.path().to_path_buf(),
+            session_id: "test".to_string(),
+            ..Default::default()
+        };
+        (dir, ctx)
+    }
+
+    #[tokio::test]
+    async fn test_grep() {
+        let (_dir, ctx) = workspace();
+
+        let result = GrepTool.execute(serde_json::json!({ "pattern": "helper\\(" }), &ctx).await.unwrap();
+        assert_eq!(result.metadata["matches"], 2);
+        assert!(result.content.contains("src/main.rs\n2:     helper();"));
+        assert!(result.content.contains("src/nested/lib.rs\n1: pub fn helper() {}"));
+        // Ignored by .gitignore, binary, and different case
+        assert!(!result.content.contains("target/out.rs"));
+        assert!(!result.content.contains("blob.bin"));
+        assert!(!result.content.contains("notes.md"));
+
+        let result = GrepTool
+            .execute(serde_json::json!({ "pattern": "helper\\(", "case_insensitive": true, "glob": "*.md" }), &ctx)
+            .await
+            .unwrap();
+        assert_eq!(result.content, "1 matches in 1 files:\n\nnotes.md\n1: call Helper() first");
+
+        let result = GrepTool
+            .execute(serde_json::json!({ "pattern": "pub fn", "path": "src/nested", "context": 1 }), &ctx)
+            .await
+            .unwrap();
+        assert!(result.content.contains("1: pub fn helper() {}\n2- // TODO: more helpers"));
+    }
+
+    #[tokio::test]
+    async fn test_grep_limits() {
+        let (dir, ctx) = workspace();
+        let many: String = (0..50).map(|i| format!("match {}\n", i)).collect();
+        std::fs::write(dir.path().join("many.txt"), many).unwrap();
+
+        let result = GrepTool.execute(serde_json::json!({ "pattern": "^match", "max_results": 10 }), &ctx).await.unwrap();
+        assert_eq!(result.metadata["matches"], 10);
+        assert_eq!(result.metadata["truncated"], true);
+        assert!(result.content.ends_with("[results truncated at 10 matches by max_results (10); narrow the search with path or glob, or a more specific pattern]"));
+
+        // One file's matches alone would pass the output limit
+        let long: String = (0..400).map(|i| format!("wide {} {}\n", i, "x".repeat(250))).collect();
+        std::fs::write(dir.path().join("wide.txt"), long).unwrap();
+        let result = GrepTool.execute(serde_json::json!({ "pattern": "^wide", "max_results": 500 }), &ctx).await.unwrap();
+        assert!(result.content.len() < MAX_OUTPUT_BYTES + 300);
+        assert_eq!(result.metadata["truncated"], true);
+        let listed = result.metadata["matches"].as_u64().unwrap();
+        assert!(listed > 0 && listed < 400);
+        assert!(result.content.contains("by the 50000 byte output limit"));
+
+        let none = GrepTool.execute(serde_json::json!({ "pattern": "nothing here" }), &ctx).await.unwrap();
+        assert_eq!(none.metadata["matches"], 0);
+        let invalid = GrepTool.execute(serde_json::json!({ "pattern": "(" }), &ctx).await;
+        assert!(matches!(invalid, Err(ToolError::InvalidParams(_))));
+        let outside = GrepTool.execute(serde_json::json!({ "pattern": "x", "path": "../" }), &ctx).await;
+        assert!(matches!(outside, Err(ToolError::PermissionDenied(_))));
+    }
+}
@@ -3,32 +3,67 @@
 //! These are the core tools available out of the box:
 //! - `read_file` — Read file contents
 //! - `write_file` — Create or overwrite a file
-//! - `edit_file` — Search-and-replace within a file
+//! - `edit_file` — Search-and-replace or unified-diff edits within a file
 //! - `shell_exec` — Execute a shell command
+//! - `grep` — Regex search of file contents across the workspace
+//! - `glob` — Find workspace files by path pattern
+//! - `fetch_url` — HTTP GET/POST within the `[tools.network]` policy
+//! - `update_plan` — Maintain the session's step-by-step plan
+//! - `query_events` — Inspect the session's own event log
+//! - `delegate` — Hand a sub-goal to a sub-agent in its own session
+//! - `fetch_artifact` — Read or save a stored artifact by id
 
 mod read_file;
 mod write_file;
 mod edit_file;
 mod shell_exec;
+mod grep;
+mod glob;
+mod fetch_url;
 mod submit_task;
+mod update_plan;
+mod query_events;
+mod delegate;
+mod fetch_artifact;
 
 pub use read_file::ReadFileTool;
 pub use write_file::WriteFileTool;
 pub use edit_file::EditFileTool;
-pub use shell_exec::ShellExecTool;
+pub use shell_exec::{ShellExecTool, ShellPolicy};
+pub use grep::GrepTool;
+pub use glob::GlobTool;
+pub use fetch_url::{FetchConfig, FetchUrlTool};
 pub use submit_task::SubmitTaskTool;
+pub use update_plan::{render_plan, UpdatePlanTool};
+pub use query_events::{event_filter, summarize_events, QueryEventsTool};
+pub use delegate::DelegateTool;
+pub use fetch_artifact::{render_slice, FetchArtifactTool};
 use crate::tools::mcp::BridgeMcp;
 
 use super::registry::ToolRegistry;
+use crate::kernel::config::ToolsConfig;
 
 /// Create a ToolRegistry with all built-in tools registered.
 pub fn create_default_registry() -> ToolRegistry {
+    create_registry(&ToolsConfig::default())
+}
+
+/// Like [`create_default_registry`], with the tools configured by `config`
+/// (`[tools.shell]`, `[tools.fetch]`).
+pub fn create_registry(config: &ToolsConfig) -> ToolRegistry {
     let mut registry = ToolRegistry::new();
     registry.register(Box::new(ReadFileTool)).expect("Failed to register ReadFileTool");
     registry.register(Box::new(WriteFileTool)).expect("Failed to register WriteFileTool");
     registry.register(Box::new(EditFileTool)).expect("Failed to register EditFileTool");
-    registry.register(Box::new(ShellExecTool)).expect("Failed to register ShellExecTool");
+    registry.register(Box::new(ShellExecTool::new(config.shell.clone()))).expect("Failed to register ShellExecTool");
+    registry.register(Box::new(GrepTool)).expect("Failed to register GrepTool");
+    registry.register(Box::new(GlobTool)).expect("Failed to register GlobTool");
+    registry.register(Box::new(FetchUrlTool::new(config.fetch.clone()))).expect("Failed to register FetchUrlTool");
     registry.register(Box::new(SubmitTaskTool)).expect("Failed to register SubmitTaskTool");
+    registry.register(Box::new(UpdatePlanTool)).expect("Failed to register UpdatePlanTool");
+    registry.register(Box::new(QueryEventsTool)).expect("Failed to register QueryEventsTool");
+    registry.register(Box::new(DelegateTool)).expect("Failed to register DelegateTool");
+    registry.register(Box::new(FetchArtifactTool)).expect("Failed to register FetchArtifactTool");
     registry.register(Box::new(BridgeMcp)).expect("Failed to register BridgeMcp");
     registry
 }
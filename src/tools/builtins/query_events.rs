@@ -0,0 +1,170 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::kernel::verbosity::preview;
+use crate::persistence::redact::redact;
+use crate::persistence::state::{EventFilter, EventRow};
+use crate::tools::{parse_args, Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
+
+/// Events returned when the model doesn't ask for a count
+const DEFAULT_EVENT_LIMIT: usize = 20;
+
+/// Hard cap on events returned by a single query
+const MAX_EVENT_ROWS: usize = 50;
+
+/// Payload characters shown per event
+const PAYLOAD_PREVIEW_CHARS: usize = 200;
+
+/// Streaming deltas are excluded unless explicitly requested
+const DELTA_EVENT_TYPES: &[&str] = &["message_delta", "thinking_delta"];
+
+pub struct QueryEventsTool;
+
+#[derive(Deserialize)]
+struct QueryEventsArgs {
+    /// Only events of these types (all non-delta types when omitted)
+    #[serde(default)]
+    event_types: Vec<String>,
+    /// How many of the most recent matching events to return
+    limit: Option<usize>,
+}
+
+/// Build the state-store filter from this tool's metadata.
+pub fn event_filter(metadata: &Value) -> EventFilter {
+    let event_types: Vec<String> = metadata
+        .get("event_types")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default();
+    let exclude_types = if event_types.is_empty() {
+        DELTA_EVENT_TYPES.iter().map(|t| t.to_string()).collect()
+    } else {
+        Vec::new()
+    };
+    let limit = metadata
+        .get("limit")
+        .and_then(|v| v.as_u64())
+        .map_or(DEFAULT_EVENT_LIMIT, |n| n as usize)
+        .min(MAX_EVENT_ROWS);
+    EventFilter { event_types, exclude_types, limit }
+}
+
+/// Render events as one compact line each, with secrets redacted.
+pub fn summarize_events(events: &[EventRow]) -> String {
+    if events.is_empty() {
+        return "No matching events.".to_string();
+    }
+    events
+        .iter()
+        .map(|e| {
+            let payload = match e.payload_json() {
+                Some(mut v) => {
+                    // The type is already on the line
+                    if let Some(obj) = v.as_object_mut() {
+                        obj.remove("type");
+                    }
+                    redact(&v).to_string()
+                }
+                None => "[unparseable payload]".to_string(),
+            };
+            format!("#{} {} {}: {}", e.id, e.created_at, e.event_type, preview(&payload, PAYLOAD_PREVIEW_CHARS))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[async_trait]
+impl Tool for QueryEventsTool {
+    fn name(&self) -> &str {
+        "query_events"
+    }
+
+    fn description(&self) -> &str {
+        "Look up recent events from this session's own event log (tool calls, turn boundaries, plan updates, errors, ...). Returns one summary line per event, oldest first, with secrets redacted. Events are recorded asynchronously, so the last few moments may be missing."
+    }
+
+    fn parameters_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "event_types": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Only return these event types, e.g. [\"tool_call\", \"turn_end\"]. Streaming deltas are excluded unless listed."
+                },
+                "limit": {
+                    "type": "integer",
+                    "description": format!("Number of most recent events to return (default {}, max {})", DEFAULT_EVENT_LIMIT, MAX_EVENT_ROWS)
+                }
+            }
+        })
+    }
+
+    fn effect(&self) -> ToolEffect {
+        ToolEffect::ReadOnly
+    }
+
+    #[tracing::instrument(skip(self, params, _ctx))]
+    async fn execute(&self, params: Value, _ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
+        let args: QueryEventsArgs = parse_args(params)?;
+        if args.limit == Some(0) {
+            return Err(ToolError::InvalidParams("limit must be at least 1".to_string()));
+        }
+        let limit = args.limit.unwrap_or(DEFAULT_EVENT_LIMIT).min(MAX_EVENT_ROWS);
+
+        // The kernel runs the query against the state store from the metadata.
+        Ok(ToolOutput {
+            content: "Event log is unavailable: persistence is disabled.".to_string(),
+            metadata: serde_json::json!({
+                "action": "query_events",
+                "event_types": args.event_types,
+                "limit": limit,
+            }),
+            sources: Vec::new(),
+            artifacts: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_query_events_caps_limit() {
+        let ctx = ToolContext { workspace_root: std::path::PathBuf::from("."), session_id: "s".to_string(), ..Default::default() };
+        let out = QueryEventsTool.execute(serde_json::json!({ "limit": 10_000 }), &ctx).await.unwrap();
+        assert_eq!(out.metadata["action"], "query_events");
+        assert_eq!(out.metadata["limit"], MAX_EVENT_ROWS);
+
+        let out = QueryEventsTool.execute(serde_json::json!({}), &ctx).await.unwrap();
+        assert_eq!(out.metadata["limit"], DEFAULT_EVENT_LIMIT);
+
+        let err = QueryEventsTool.execute(serde_json::json!({ "limit": 0 }), &ctx).await;
+        assert!(matches!(err, Err(ToolError::InvalidParams(_))));
+    }
+
+    #[test]
+    fn test_summarize_events_redacts_and_filter_skips_deltas() {
+        let row = |id, event_type: &str, payload: &str| EventRow {
+            id,
+            session_id: "s".to_string(),
+            event_type: event_type.to_string(),
+            payload: payload.to_string(),
+            created_at: "2026-01-01 00:00:00".to_string(),
+        };
+        let events = vec![row(1, "tool_call", r#"{"type":"tool_call","name":"shell_exec","args":{"api_key":"sk-secret"}}"#)];
+        let summary = summarize_events(&events);
+        assert!(summary.starts_with("#1 2026-01-01 00:00:00 tool_call: "));
+        assert!(summary.contains("shell_exec"));
+        assert!(!summary.contains("sk-secret"));
+
+        let filter = event_filter(&serde_json::json!({ "limit": 5 }));
+        assert_eq!(filter.limit, 5);
+        assert!(filter.exclude_types.contains(&"message_delta".to_string()));
+        let filter = event_filter(&serde_json::json!({ "event_types": ["message_delta"], "limit": 500 }));
+        assert!(filter.exclude_types.is_empty());
+        assert_eq!(filter.limit, MAX_EVENT_ROWS);
+        assert_eq!(summarize_events(&[]), "No matching events.");
+    }
+}
@@ -2,7 +2,16 @@ use async_trait::async_trait;
 use serde::Deserialize;
 use serde_json::Value;
 
-use crate::tools::{parse_args, Tool, ToolContext, ToolError, ToolOutput};
+use crate::tools::{parse_args, Source, Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
+
+/// Context lines shown around each grep match when the model doesn't ask
+const DEFAULT_GREP_CONTEXT: usize = 2;
+
+/// Most context lines allowed around a grep match
+const MAX_GREP_CONTEXT: usize = 20;
+
+/// Most grep matches returned by one call
+const MAX_GREP_MATCHES: usize = 200;
 
 pub struct ReadFileTool;
 
@@ -10,6 +19,38 @@ pub struct ReadFileTool;
 struct ReadFileArgs {
     /// Path to read (relative to workspace root, or absolute)
     path: String,
+    /// First line to return (1-based)
+    start_line: Option<usize>,
+    /// Last line to return (inclusive)
+    end_line: Option<usize>,
+    /// Regex; only matching lines (with context) are returned
+    grep: Option<String>,
+    /// Lines of context around each match
+    context: Option<usize>,
+}
+
+/// Render the `shown` lines with right-aligned 1-based line numbers, `:`
+/// after matched lines and `-` after context lines (as `grep -n` does), and
+/// `--` between groups that aren't adjacent.
+pub(super) fn render_numbered(lines: &[&str], shown: &[bool], matched: &[bool]) -> String {
+    numbered_lines(lines, shown, matched).into_iter().map(|(line, _)| line + "\n").collect()
+}
+
+/// The lines `render_numbered` writes, each paired with whether it is a
+/// matched line.
+pub(super) fn numbered_lines(lines: &[&str], shown: &[bool], matched: &[bool]) -> Vec<(String, bool)> {
+    let width = lines.len().to_string().len();
+    let mut out = Vec::new();
+    let mut previous: Option<usize> = None;
+    for (i, line) in lines.iter().enumerate().filter(|(i, _)| shown[*i]) {
+        if previous.is_some_and(|p| p + 1 != i) {
+            out.push(("--".to_string(), false));
+        }
+        let sep = if matched[i] { ':' } else { '-' };
+        out.push((format!("{:>width$}{} {}", i + 1, sep, line, width = width), matched[i]));
+        previous = Some(i);
+    }
+    out
 }
 
 #[async_trait]
@@ -19,7 +60,7 @@ impl Tool for ReadFileTool {
     }
 
     fn description(&self) -> &str {
-        "Read the contents of a file. Returns the full file content as text."
+        "Read the contents of a file. Returns the full file content as text. For large files, read a line range with start_line/end_line or search with grep (a regex); both return lines prefixed with their line numbers."
     }
 
     fn parameters_schema(&self) -> Value {
@@ -29,30 +70,109 @@ impl Tool for ReadFileTool {
                 "path": {
                     "type": "string",
                     "description": "Path to the file to read (relative to workspace root, or absolute)"
+                },
+                "start_line": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "First line to return, 1-based (default 1)"
+                },
+                "end_line": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "Last line to return, inclusive (default: end of file)"
+                },
+                "grep": {
+                    "type": "string",
+                    "description": "Regex; return only matching lines (within the line range, if given) with surrounding context"
+                },
+                "context": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "maximum": MAX_GREP_CONTEXT,
+                    "description": "Lines of context around each grep match (default 2)"
                 }
             },
             "required": ["path"]
         })
     }
 
+    fn effect(&self) -> ToolEffect {
+        ToolEffect::ReadOnly
+    }
+
     #[tracing::instrument(skip(self, params, ctx), fields(path = %params["path"].as_str().unwrap_or("unknown")))]
     async fn execute(&self, params: Value, ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
         let args: ReadFileArgs = parse_args(params)?;
         tracing::info!(path = %args.path, "Reading file");
+
+        if args.start_line == Some(0) || args.end_line == Some(0) {
+            return Err(ToolError::InvalidParams("line numbers start at 1".to_string()));
+        }
+        if let (Some(start), Some(end)) = (args.start_line, args.end_line) {
+            if end < start {
+                return Err(ToolError::InvalidParams(format!("end_line {} is before start_line {}", end, start)));
+            }
+        }
+        let pattern = match &args.grep {
+            Some(grep) => Some(regex::Regex::new(grep).map_err(|e| ToolError::InvalidParams(format!("invalid grep pattern: {}", e)))?),
+            None => None,
+        };
         
         // Security: validate path is within workspace using centralized logic
-        let path = crate::tools::is_safe_path(&ctx.workspace_root, std::path::Path::new(&args.path))?;
+        let path = ctx.resolve_path(&args.path)?;
 
         let content = tokio::fs::read_to_string(&path)
             .await
             .map_err(|e| ToolError::ExecutionError(format!("Failed to read {}: {}", path.display(), e)))?;
+        let mut metadata = serde_json::json!({
+            "path": path.display().to_string(),
+            "bytes": tokio::fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0),
+        });
+
+        let ranged = args.start_line.is_some() || args.end_line.is_some();
+        let content = if !ranged && pattern.is_none() {
+            content
+        } else {
+            let lines: Vec<&str> = content.lines().collect();
+            if args.start_line.is_some_and(|start| start > lines.len()) {
+                return Err(ToolError::InvalidParams(format!("start_line is past the end of the file ({} lines)", lines.len())));
+            }
+            let start = args.start_line.unwrap_or(1) - 1;
+            let end = args.end_line.unwrap_or(lines.len()).min(lines.len());
+            let mut shown = vec![false; lines.len()];
+            let mut matched = vec![false; lines.len()];
+            let mut header = format!("[{}: lines {}-{} of {}", args.path, start + 1, end, lines.len());
+            match &pattern {
+                None => {
+                    shown[start..end].fill(true);
+                    matched[start..end].fill(true);
+                }
+                Some(pattern) => {
+                    let context = args.context.unwrap_or(DEFAULT_GREP_CONTEXT).min(MAX_GREP_CONTEXT);
+                    let hits: Vec<usize> = (start..end).filter(|&i| pattern.is_match(lines[i])).collect();
+                    for &i in hits.iter().take(MAX_GREP_MATCHES) {
+                        matched[i] = true;
+                        shown[i.saturating_sub(context).max(start)..(i + context + 1).min(end)].fill(true);
+                    }
+                    header.push_str(&format!(", {} matches for /{}/", hits.len(), pattern.as_str()));
+                    if hits.len() > MAX_GREP_MATCHES {
+                        header.push_str(&format!(", showing the first {}", MAX_GREP_MATCHES));
+                    }
+                    metadata["matches"] = hits.len().into();
+                }
+            }
+            metadata["total_lines"] = lines.len().into();
+            metadata["start_line"] = (start + 1).into();
+            metadata["end_line"] = end.into();
+            header.push_str("]\n");
+            header + &render_numbered(&lines, &shown, &matched)
+        };
 
         Ok(ToolOutput {
             content,
-            metadata: serde_json::json!({
-                "path": path.display().to_string(),
-                "bytes": tokio::fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0),
-            }),
+            metadata,
+            sources: vec![Source::File { path: args.path }],
+            artifacts: Vec::new(),
         })
     }
 }
@@ -73,6 +193,7 @@ mod tests {
         let ctx = ToolContext {
             workspace_root: dir.path().to_path_buf(),
             session_id: "test".to_string(),
+            ..Default::default()
         };
 
         let result = tool
@@ -89,6 +210,7 @@ mod tests {
         let ctx = ToolContext {
             workspace_root: dir.path().to_path_buf(),
             session_id: "test".to_string(),
+            ..Default::default()
         };
 
         let result = tool
@@ -96,4 +218,57 @@ mod tests {
             .await;
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_read_file_range_and_grep() {
+        let dir = TempDir::new().unwrap();
+        let text: String = (1..=12).map(|n| if n % 5 == 0 { format!("fn f{}()\n", n) } else { format!("line {}\n", n) }).collect();
+        std::fs::write(dir.path().join("big.rs"), text).unwrap();
+        let ctx = ToolContext { workspace_root: dir.path().to_path_buf(), session_id: "test".to_string(), ..Default::default() };
+        let read = |args: Value| {
+            let ctx = ctx.clone();
+            async move { ReadFileTool.execute(args, &ctx).await }
+        };
+
+        let out = read(serde_json::json!({ "path": "big.rs", "start_line": 9, "end_line": 10 })).await.unwrap();
+        assert_eq!(out.content, "[big.rs: lines 9-10 of 12]\n 9: line 9\n10: fn f10()\n");
+        assert_eq!(out.metadata["total_lines"], 12);
+
+        let out = read(serde_json::json!({ "path": "big.rs", "grep": "^fn", "context": 1 })).await.unwrap();
+        assert_eq!(
+            out.content,
+            "[big.rs: lines 1-12 of 12, 2 matches for /^fn/]\n 4- line 4\n 5: fn f5()\n 6- line 6\n--\n 9- line 9\n10: fn f10()\n11- line 11\n"
+        );
+        assert_eq!(out.metadata["matches"], 2);
+
+        // Grep within a range; context stops at the range edge
+        let out = read(serde_json::json!({ "path": "big.rs", "grep": "f10", "start_line": 10 })).await.unwrap();
+        assert!(out.content.starts_with("[big.rs: lines 10-12 of 12, 1 matches"));
+        assert!(out.content.contains("10: fn f10()\n11- line 11\n12- line 12\n") && !out.content.contains(" 9-"));
+
+        assert!(read(serde_json::json!({ "path": "big.rs", "start_line": 0 })).await.is_err());
+        assert!(read(serde_json::json!({ "path": "big.rs", "start_line": 13 })).await.is_err());
+        assert!(read(serde_json::json!({ "path": "big.rs", "start_line": 5, "end_line": 4 })).await.is_err());
+        assert!(read(serde_json::json!({ "path": "big.rs", "grep": "(" })).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_file_allowed_paths() {
+        let dir = TempDir::new().unwrap();
+        let shared = TempDir::new().unwrap();
+        std::fs::write(shared.path().join("notes.txt"), "shared notes").unwrap();
+        let path = shared.path().join("notes.txt");
+        let mut ctx = ToolContext {
+            workspace_root: dir.path().to_path_buf(),
+            session_id: "test".to_string(),
+            ..Default::default()
+        };
+
+        let outside = ReadFileTool.execute(serde_json::json!({ "path": path }), &ctx).await;
+        assert!(matches!(outside, Err(ToolError::PermissionDenied(_))));
+
+        ctx.allowed_paths = std::sync::Arc::new(vec![shared.path().to_path_buf()]);
+        let result = ReadFileTool.execute(serde_json::json!({ "path": path }), &ctx).await.unwrap();
+        assert_eq!(result.content, "shared notes");
+    }
 }
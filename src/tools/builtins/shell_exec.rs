@@ -3,9 +3,216 @@ use serde::Deserialize;
 use serde_json::Value;
 use std::process::Stdio;
 
-use crate::tools::{parse_args, Tool, ToolContext, ToolError, ToolOutput, is_safe_path};
+use crate::tools::approval::wildcard_match;
+use crate::tools::{parse_args, Artifact, Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
 
-pub struct ShellExecTool;
+/// Output kept in the result by default; longer output goes to an artifact.
+pub const DEFAULT_MAX_OUTPUT_BYTES: usize = 100_000;
+
+/// End of the output shown in the result when the whole is stored as an
+/// artifact (whose handle already shows the start).
+const OUTPUT_TAIL_BYTES: usize = 2_000;
+
+/// A command run in its own process group. Unless it ran to completion, the
+/// whole group is killed on drop, so background jobs and children that hold
+/// its output open don't outlive a cancelled or timed-out call.
+struct ProcessGroup {
+    child: tokio::process::Child,
+    finished: bool,
+}
+
+impl ProcessGroup {
+    fn new(child: tokio::process::Child) -> Self {
+        Self { child, finished: false }
+    }
+
+    /// Like `Child::wait_with_output`, keeping the child for the drop.
+    async fn wait_with_output(&mut self) -> std::io::Result<std::process::Output> {
+        use tokio::io::AsyncReadExt;
+
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        let mut stdout_pipe = self.child.stdout.take();
+        let mut stderr_pipe = self.child.stderr.take();
+        let read_stdout = async {
+            match &mut stdout_pipe {
+                Some(pipe) => pipe.read_to_end(&mut stdout).await.map(|_| ()),
+                None => Ok(()),
+            }
+        };
+        let read_stderr = async {
+            match &mut stderr_pipe {
+                Some(pipe) => pipe.read_to_end(&mut stderr).await.map(|_| ()),
+                None => Ok(()),
+            }
+        };
+        let (status, read_stdout, read_stderr) = tokio::join!(self.child.wait(), read_stdout, read_stderr);
+        let status = status?;
+        read_stdout?;
+        read_stderr?;
+        self.finished = true;
+        Ok(std::process::Output { status, stdout, stderr })
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        #[cfg(unix)]
+        if let Some(pid) = self.child.id() {
+            use nix::sys::signal::{killpg, Signal};
+            let _ = killpg(nix::unistd::Pid::from_raw(pid as i32), Signal::SIGKILL);
+        }
+        // The shell itself is also killed by kill_on_drop
+    }
+}
+
+/// What `shell_exec` may run and how (`[tools.shell]`).
+///
+/// Command patterns use `*` wildcards and are checked against every simple
+/// command in the line (split at `;`, `&`, `|`, newlines, parentheses and
+/// backticks, quoted or not), with quotes and backslashes removed. A pattern
+/// matches a command if it matches the whole command or just its program
+/// name, looking past `env`, `nice`, `command` and `exec`, so `rm` covers
+/// `rm -rf build` and `env rm x`, and `git push*` covers only pushes. With
+/// either list set, command substitution is refused.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShellPolicy {
+    /// Commands that may run; every command in the line must match one (empty = any)
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Commands that never run, checked before `allow`
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Always run in the workspace root; the `cwd` argument is refused
+    #[serde(default)]
+    pub pin_cwd: bool,
+    /// Environment variables passed to commands (wildcards; empty = all)
+    #[serde(default)]
+    pub env_allow: Vec<String>,
+    /// Environment variables withheld from commands (wildcards), e.g. `*_API_KEY`
+    #[serde(default)]
+    pub env_deny: Vec<String>,
+    /// Longest output kept in the result; longer output is stored as an
+    /// artifact and only its end is shown
+    #[serde(default = "default_max_output_bytes")]
+    pub max_output_bytes: usize,
+    /// Check commands against the policy and report them without running them
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl Default for ShellPolicy {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            deny: Vec::new(),
+            pin_cwd: false,
+            env_allow: Vec::new(),
+            env_deny: Vec::new(),
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+            dry_run: false,
+        }
+    }
+}
+
+fn default_max_output_bytes() -> usize {
+    DEFAULT_MAX_OUTPUT_BYTES
+}
+
+impl ShellPolicy {
+    /// Why `command` may not run, if it may not.
+    pub fn check(&self, command: &str) -> Option<String> {
+        let commands = simple_commands(command);
+        for cmd in &commands {
+            if let Some(pattern) = self.deny.iter().find(|p| command_matches(p, cmd)) {
+                return Some(format!("'{}' is denied by tools.shell.deny ('{}')", cmd, pattern));
+            }
+        }
+        if self.allow.is_empty() && self.deny.is_empty() {
+            return None;
+        }
+        // The substituted command is only known once the shell runs it
+        if ["$(", "`", "<(", ">("].iter().any(|s| command.contains(s)) {
+            return Some("command substitution is not allowed with tools.shell.allow or tools.shell.deny".to_string());
+        }
+        if self.allow.is_empty() {
+            return None;
+        }
+        commands
+            .iter()
+            .find(|cmd| !self.allow.iter().any(|p| command_matches(p, cmd)))
+            .map(|cmd| format!("'{}' is not in tools.shell.allow", cmd))
+    }
+
+    /// Whether the environment variable `name` is passed to commands.
+    pub fn passes_env(&self, name: &str) -> bool {
+        (self.env_allow.is_empty() || self.env_allow.iter().any(|p| wildcard_match(p, name)))
+            && !self.env_deny.iter().any(|p| wildcard_match(p, name))
+    }
+
+    fn filters_env(&self) -> bool {
+        !self.env_allow.is_empty() || !self.env_deny.is_empty()
+    }
+}
+
+/// Commands that run the rest of their arguments as another command.
+const WRAPPERS: &[&str] = &["command", "env", "exec", "nice"];
+
+/// The simple commands of a command line, split conservatively, with quotes
+/// and backslashes removed. `&` in the redirections `>&`, `<&` and `&>` does
+/// not split.
+fn simple_commands(line: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut current = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let redirect = c == '&' && (matches!(current.chars().last(), Some('>' | '<')) || chars.peek() == Some(&'>'));
+        match c {
+            ';' | '|' | '\n' | '(' | ')' | '`' => commands.push(std::mem::take(&mut current)),
+            '&' if !redirect => commands.push(std::mem::take(&mut current)),
+            '\'' | '"' | '\\' => {}
+            _ => current.push(c),
+        }
+    }
+    commands.push(current);
+    commands
+        .iter()
+        .map(|cmd| cmd.trim().trim_start_matches('$').trim())
+        .filter(|cmd| !cmd.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether `pattern` matches the command or its program, with or without its
+/// directory. `VAR=value` prefixes and wrappers like `env` or `nice` (with
+/// their options) are skipped.
+fn command_matches(pattern: &str, command: &str) -> bool {
+    let mut rest = command;
+    while let Some(word) = rest.split_whitespace().next() {
+        let wrapper = WRAPPERS.contains(&word.rsplit('/').next().unwrap_or(word));
+        let option = rest.len() < command.len() && (word.starts_with('-') || word.parse::<i32>().is_ok());
+        if !(word.contains('=') || wrapper || option) {
+            break;
+        }
+        rest = rest.trim_start()[word.len()..].trim_start();
+    }
+    let program = rest.split_whitespace().next().unwrap_or_default();
+    let base = program.rsplit('/').next().unwrap_or(program);
+    [command, rest, program, base].iter().any(|candidate| wildcard_match(pattern, candidate))
+}
+
+#[derive(Default)]
+pub struct ShellExecTool {
+    policy: ShellPolicy,
+}
+
+impl ShellExecTool {
+    pub fn new(policy: ShellPolicy) -> Self {
+        Self { policy }
+    }
+}
 
 #[derive(Deserialize)]
 struct ShellExecArgs {
@@ -34,7 +241,7 @@ impl Tool for ShellExecTool {
     }
 
     fn parameters_schema(&self) -> Value {
-        serde_json::json!({
+        let mut schema = serde_json::json!({
             "type": "object",
             "properties": {
                 "command": {
@@ -52,7 +259,15 @@ impl Tool for ShellExecTool {
                 }
             },
             "required": ["command"]
-        })
+        });
+        if self.policy.pin_cwd {
+            schema["properties"].as_object_mut().map(|props| props.remove("cwd"));
+        }
+        schema
+    }
+
+    fn effect(&self) -> ToolEffect {
+        ToolEffect::Execute
     }
 
     #[tracing::instrument(skip(self, params, ctx), fields(command = %params["command"].as_str().unwrap_or("unknown")))]
@@ -60,25 +275,48 @@ impl Tool for ShellExecTool {
         let args: ShellExecArgs = parse_args(params)?;
         tracing::info!(command = %args.command, "Executing shell command");
 
+        if let Some(reason) = self.policy.check(&args.command) {
+            return Err(ToolError::PermissionDenied(reason));
+        }
+
         // Resolve working directory
-        let cwd = if let Some(ref dir) = args.cwd {
-            is_safe_path(&ctx.workspace_root, std::path::Path::new(dir))?
-        } else {
-            ctx.workspace_root.clone()
+        let cwd = match args.cwd {
+            Some(_) if self.policy.pin_cwd => {
+                return Err(ToolError::PermissionDenied("commands run in the workspace root (tools.shell.pin_cwd)".to_string()));
+            }
+            Some(ref dir) => ctx.resolve_path(dir)?,
+            None => ctx.workspace_root.clone(),
         };
 
+        if self.policy.dry_run {
+            return Ok(ToolOutput {
+                content: format!("[dry run] Would run in {}: {}", cwd.display(), args.command),
+                metadata: serde_json::json!({ "command": args.command, "cwd": cwd.display().to_string(), "dry_run": true }),
+                sources: Vec::new(),
+                artifacts: Vec::new(),
+            });
+        }
+
         // Execute command with timeout
-        let child = tokio::process::Command::new("/bin/sh")
-            .arg("-c")
-            .arg(&args.command)
-            .current_dir(&cwd)
+        let mut command = tokio::process::Command::new("/bin/sh");
+        command.arg("-c").arg(&args.command).current_dir(&cwd);
+        if self.policy.filters_env() {
+            command.env_clear().envs(std::env::vars_os().filter(|(name, _)| self.policy.passes_env(&name.to_string_lossy())));
+        }
+        #[cfg(unix)]
+        command.process_group(0);
+        let child = command
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
+            .kill_on_drop(true)
             .spawn()
             .map_err(|e| ToolError::ExecutionError(format!("Failed to spawn command: {}", e)))?;
+        // Killed, with everything it started, if the kernel cancels the call
+        // (tools.timeout_ms) or the command times out
+        let mut group = ProcessGroup::new(child);
 
         let timeout = std::time::Duration::from_secs(args.timeout_secs);
-        let output = tokio::time::timeout(timeout, child.wait_with_output())
+        let output = tokio::time::timeout(timeout, group.wait_with_output())
             .await
             .map_err(|_| {
                 ToolError::ExecutionError(format!(
@@ -108,11 +346,16 @@ impl Tool for ShellExecTool {
             content = format!("Command exited with code {}", exit_code);
         }
 
-        // Truncate very long output
-        const MAX_OUTPUT: usize = 100_000;
-        if content.len() > MAX_OUTPUT {
-            content.truncate(MAX_OUTPUT);
-            content.push_str("\n... [output truncated]");
+        // Very long output goes to an artifact; only its end stays in the result
+        let max_output = self.policy.max_output_bytes;
+        let mut artifacts = Vec::new();
+        if content.len() > max_output {
+            let tail_start = content.ceil_char_boundary(content.len() - OUTPUT_TAIL_BYTES.min(max_output));
+            let inline = format!(
+                "... [{} of {} bytes omitted; the full output is in the artifact below]\n{}",
+                tail_start, content.len(), &content[tail_start..]
+            );
+            artifacts.push(Artifact::text("text/plain", std::mem::replace(&mut content, inline)));
         }
 
         Ok(ToolOutput {
@@ -124,6 +367,8 @@ impl Tool for ShellExecTool {
                 "stderr_bytes": stderr.len(),
                 "cwd": cwd.display().to_string(),
             }),
+            sources: Vec::new(),
+            artifacts,
         })
     }
 }
@@ -136,10 +381,11 @@ mod tests {
     #[tokio::test]
     async fn test_shell_exec_echo() {
         let dir = TempDir::new().unwrap();
-        let tool = ShellExecTool;
+        let tool = ShellExecTool::default();
         let ctx = ToolContext {
             workspace_root: dir.path().to_path_buf(),
             session_id: "test".to_string(),
+            ..Default::default()
         };
 
         let result = tool
@@ -151,13 +397,37 @@ mod tests {
         assert_eq!(result.metadata["exit_code"], 0);
     }
 
+    #[tokio::test]
+    async fn test_shell_exec_long_output_kept_as_artifact() {
+        let dir = TempDir::new().unwrap();
+        let ctx = ToolContext {
+            workspace_root: dir.path().to_path_buf(),
+            session_id: "test".to_string(),
+            ..Default::default()
+        };
+
+        let result = ShellExecTool::default()
+            .execute(serde_json::json!({ "command": "head -c 150000 /dev/zero | tr '\\0' x" }), &ctx)
+            .await
+            .unwrap();
+        // Only the end of the output is inlined
+        assert!(result.content.len() < 2_100, "{}", result.content.len());
+        assert!(result.content.starts_with("... [148000 of 150000 bytes omitted; the full output is in the artifact below]\nxxx"));
+        assert_eq!(result.artifacts.len(), 1);
+        assert_eq!(result.artifacts[0].data.len(), 150_000);
+
+        let short = ShellExecTool::default().execute(serde_json::json!({ "command": "echo hi" }), &ctx).await.unwrap();
+        assert!(short.artifacts.is_empty());
+    }
+
     #[tokio::test]
     async fn test_shell_exec_exit_code() {
         let dir = TempDir::new().unwrap();
-        let tool = ShellExecTool;
+        let tool = ShellExecTool::default();
         let ctx = ToolContext {
             workspace_root: dir.path().to_path_buf(),
             session_id: "test".to_string(),
+            ..Default::default()
         };
 
         let result = tool
@@ -171,10 +441,11 @@ mod tests {
     #[tokio::test]
     async fn test_shell_exec_stderr() {
         let dir = TempDir::new().unwrap();
-        let tool = ShellExecTool;
+        let tool = ShellExecTool::default();
         let ctx = ToolContext {
             workspace_root: dir.path().to_path_buf(),
             session_id: "test".to_string(),
+            ..Default::default()
         };
 
         let result = tool
@@ -192,10 +463,11 @@ mod tests {
     #[tokio::test]
     async fn test_shell_exec_timeout() {
         let dir = TempDir::new().unwrap();
-        let tool = ShellExecTool;
+        let tool = ShellExecTool::default();
         let ctx = ToolContext {
             workspace_root: dir.path().to_path_buf(),
             session_id: "test".to_string(),
+            ..Default::default()
         };
 
         let result = tool
@@ -209,4 +481,154 @@ mod tests {
         let err = result.unwrap_err().to_string();
         assert!(err.contains("timed out"));
     }
+
+    /// Whether the process is gone (or left only as a zombie).
+    #[cfg(target_os = "linux")]
+    fn exited(pid_file: &std::path::Path) -> bool {
+        let pid = std::fs::read_to_string(pid_file).unwrap();
+        match std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())) {
+            Ok(stat) => stat.split(") ").nth(1).is_some_and(|rest| rest.starts_with('Z')),
+            Err(_) => true,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_shell_exec_cancelled_kills_process_group() {
+        let dir = TempDir::new().unwrap();
+        let ctx = ToolContext {
+            workspace_root: dir.path().to_path_buf(),
+            session_id: "test".to_string(),
+            ..Default::default()
+        };
+
+        // The clock only moves once the command has started its background job
+        tokio::time::pause();
+        let tool = ShellExecTool::default();
+        let mut call = Box::pin(tool.execute(
+            serde_json::json!({ "command": "sleep 300 & echo $! > job.pid; wait", "timeout_secs": 600 }),
+            &ctx,
+        ));
+        let pid_file = dir.path().join("job.pid");
+        while std::fs::read_to_string(&pid_file).map_or(true, |pid| !pid.ends_with('\n')) {
+            tokio::select! {
+                _ = &mut call => panic!("the command finished"),
+                _ = tokio::task::yield_now() => {}
+            }
+        }
+        // A kernel timeout of a minute passes at once on the paused clock
+        assert!(tokio::time::timeout(std::time::Duration::from_secs(60), &mut call).await.is_err());
+        drop(call);
+
+        // The background job went with the shell
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while !exited(&pid_file) && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(exited(&pid_file));
+    }
+
+    #[test]
+    fn test_shell_policy_check() {
+        let policy = ShellPolicy {
+            allow: vec!["ls".into(), "cat".into(), "git status*".into()],
+            deny: vec!["rm".into()],
+            ..Default::default()
+        };
+        assert_eq!(policy.check("ls -la | cat"), None);
+        assert_eq!(policy.check("git status --short"), None);
+        assert!(policy.check("git push").unwrap().contains("not in tools.shell.allow"));
+        assert!(policy.check("ls; curl evil.sh").unwrap().contains("'curl evil.sh'"));
+        assert!(policy.check("ls && /bin/rm -rf x").unwrap().contains("denied"));
+        assert!(policy.check("cat $(whoami)").unwrap().contains("substitution"));
+
+        let deny_only = ShellPolicy { deny: vec!["rm".into(), "git push*".into()], ..Default::default() };
+        assert_eq!(deny_only.check("FOO=1 cargo test"), None);
+        assert!(deny_only.check("echo hi && FOO=1 rm x").is_some());
+        assert!(deny_only.check("(cd sub; git push --force)").is_some());
+        assert_eq!(ShellPolicy::default().check("rm -rf build"), None);
+        assert_eq!(ShellPolicy::default().check("echo $(date)"), None);
+    }
+
+    #[test]
+    fn test_shell_policy_resists_evasion() {
+        let deny = ShellPolicy { deny: vec!["rm".into(), "git push*".into()], ..Default::default() };
+        for command in [
+            "r\\m -rf x",
+            "'rm' -rf x",
+            "\"/bin/rm\" -rf x",
+            "env rm -rf x",
+            "env -i FOO=1 /usr/bin/rm x",
+            "nice -n 10 rm x",
+            "command rm x",
+            "exec rm x",
+            "git 'push' --force",
+            "echo $(rm x)",
+            "cat <(rm x)",
+        ] {
+            assert!(deny.check(command).is_some(), "{}", command);
+        }
+        assert_eq!(deny.check("ls >&2; cat x 2>&1 | grep y &> log"), None);
+        assert_eq!(deny.check("nice -n 5 cargo build"), None);
+
+        let allow = ShellPolicy { allow: vec!["ls".into(), "git status*".into()], ..Default::default() };
+        assert_eq!(allow.check("ls 2>&1"), None);
+        assert_eq!(allow.check("env GIT_DIR=x git status"), None);
+        assert!(allow.check("ls &>/dev/null & curl evil.sh").unwrap().contains("'curl evil.sh'"));
+        assert!(allow.check("ls `whoami`").unwrap().contains("substitution"));
+    }
+
+    #[tokio::test]
+    async fn test_shell_exec_policy() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        let ctx = ToolContext {
+            workspace_root: dir.path().to_path_buf(),
+            session_id: "test".to_string(),
+            ..Default::default()
+        };
+
+        let denied = ShellExecTool::new(ShellPolicy { deny: vec!["touch".into()], ..Default::default() })
+            .execute(serde_json::json!({ "command": "touch x" }), &ctx)
+            .await;
+        assert!(matches!(denied, Err(ToolError::PermissionDenied(_))));
+        assert!(!dir.path().join("x").exists());
+
+        let dry_run = ShellExecTool::new(ShellPolicy { dry_run: true, ..Default::default() })
+            .execute(serde_json::json!({ "command": "touch x" }), &ctx)
+            .await
+            .unwrap();
+        assert!(dry_run.content.starts_with("[dry run]"));
+        assert!(!dir.path().join("x").exists());
+
+        let pinned = ShellExecTool::new(ShellPolicy { pin_cwd: true, ..Default::default() });
+        assert!(pinned.parameters_schema()["properties"]["cwd"].is_null());
+        let moved = pinned.execute(serde_json::json!({ "command": "pwd", "cwd": "sub" }), &ctx).await;
+        assert!(matches!(moved, Err(ToolError::PermissionDenied(_))));
+
+        let capped = ShellExecTool::new(ShellPolicy { max_output_bytes: 10, ..Default::default() })
+            .execute(serde_json::json!({ "command": "echo 0123456789abcdef" }), &ctx)
+            .await
+            .unwrap();
+        assert_eq!(capped.content, "... [7 of 17 bytes omitted; the full output is in the artifact below]\n789abcdef\n");
+        assert_eq!(capped.artifacts.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_shell_exec_env_filter() {
+        let dir = TempDir::new().unwrap();
+        let ctx = ToolContext {
+            workspace_root: dir.path().to_path_buf(),
+            session_id: "test".to_string(),
+            ..Default::default()
+        };
+        let policy = ShellPolicy { env_deny: vec!["*_API_KEY".into()], ..Default::default() };
+        assert!(policy.passes_env("PATH") && !policy.passes_env("OPENAI_API_KEY"));
+
+        let only_path = ShellExecTool::new(ShellPolicy { env_allow: vec!["PATH".into()], ..Default::default() })
+            .execute(serde_json::json!({ "command": "echo \"home=${HOME:-none}\"" }), &ctx)
+            .await
+            .unwrap();
+        assert_eq!(only_path.content.trim(), "home=none");
+    }
 }
@@ -2,7 +2,7 @@ use async_trait::async_trait;
 use serde::Deserialize;
 use serde_json::Value;
 
-use crate::tools::{parse_args, Tool, ToolContext, ToolError, ToolOutput};
+use crate::tools::{parse_args, Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
 
 pub struct SubmitTaskTool;
 
@@ -49,6 +49,10 @@ impl Tool for SubmitTaskTool {
         })
     }
 
+    fn effect(&self) -> ToolEffect {
+        ToolEffect::ReadOnly
+    }
+
     #[tracing::instrument(skip(self, params, _ctx), fields(title = %params["title"].as_str().unwrap_or("unknown")))]
     async fn execute(&self, params: Value, _ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
         let args: SubmitTaskArgs = parse_args(params)?;
@@ -64,6 +68,8 @@ impl Tool for SubmitTaskTool {
                 "subtasks": args.subtasks,
                 "clear_existing": args.clear_existing
             }),
+            sources: Vec::new(),
+            artifacts: Vec::new(),
         })
     }
 }
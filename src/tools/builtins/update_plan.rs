@@ -0,0 +1,117 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::persistence::state::{PlanStatus, PlanStep};
+use crate::tools::{parse_args, Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
+
+pub struct UpdatePlanTool;
+
+#[derive(Deserialize)]
+struct UpdatePlanArgs {
+    /// The full plan, in order
+    steps: Vec<PlanStep>,
+}
+
+/// Render a plan as a checklist, e.g. for injection into the system prompt.
+pub fn render_plan(steps: &[PlanStep]) -> String {
+    steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| {
+            let mark = match step.status {
+                PlanStatus::Pending => "[ ]",
+                PlanStatus::InProgress => "[~]",
+                PlanStatus::Done => "[x]",
+            };
+            format!("{}. {} {}", i + 1, mark, step.title)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[async_trait]
+impl Tool for UpdatePlanTool {
+    fn name(&self) -> &str {
+        "update_plan"
+    }
+
+    fn description(&self) -> &str {
+        "Replace your working plan for this session with an ordered list of steps and their statuses. Keep at most one step in_progress and update the plan as you make progress. Returns the current plan."
+    }
+
+    fn parameters_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "steps": {
+                    "type": "array",
+                    "description": "The complete plan, in order",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "title": { "type": "string", "description": "What the step accomplishes" },
+                            "status": {
+                                "type": "string",
+                                "enum": ["pending", "in_progress", "done"],
+                                "description": "Defaults to pending"
+                            }
+                        },
+                        "required": ["title"]
+                    }
+                }
+            },
+            "required": ["steps"]
+        })
+    }
+
+    fn effect(&self) -> ToolEffect {
+        ToolEffect::ReadOnly
+    }
+
+    #[tracing::instrument(skip(self, params, _ctx))]
+    async fn execute(&self, params: Value, _ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
+        let args: UpdatePlanArgs = parse_args(params)?;
+        if args.steps.iter().any(|s| s.title.trim().is_empty()) {
+            return Err(ToolError::InvalidParams("plan steps must have a title".to_string()));
+        }
+        tracing::info!(steps = args.steps.len(), "Updating plan");
+
+        // The kernel persists the plan and emits PlanUpdated from the metadata.
+        Ok(ToolOutput {
+            content: format!("Plan updated:\n{}", render_plan(&args.steps)),
+            metadata: serde_json::json!({
+                "action": "update_plan",
+                "steps": args.steps,
+            }),
+            sources: Vec::new(),
+            artifacts: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_update_plan_renders_checklist() {
+        let ctx = ToolContext { workspace_root: std::path::PathBuf::from("."), session_id: "s".to_string(), ..Default::default() };
+        let out = UpdatePlanTool
+            .execute(serde_json::json!({ "steps": [
+                { "title": "Explore", "status": "done" },
+                { "title": "Implement", "status": "in_progress" },
+                { "title": "Test" }
+            ]}), &ctx)
+            .await
+            .unwrap();
+        assert_eq!(out.content, "Plan updated:\n1. [x] Explore\n2. [~] Implement\n3. [ ] Test");
+        assert_eq!(out.metadata["action"], "update_plan");
+        assert_eq!(out.metadata["steps"][2]["status"], "pending");
+
+        let err = UpdatePlanTool
+            .execute(serde_json::json!({ "steps": [{ "title": "Ship", "status": "blocked" }] }), &ctx)
+            .await;
+        assert!(matches!(err, Err(ToolError::InvalidParams(_))));
+    }
+}
@@ -2,7 +2,7 @@ use async_trait::async_trait;
 use serde::Deserialize;
 use serde_json::Value;
 
-use crate::tools::{parse_args, Tool, ToolContext, ToolError, ToolOutput};
+use crate::tools::{parse_args, Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
 
 pub struct WriteFileTool;
 
@@ -41,13 +41,17 @@ impl Tool for WriteFileTool {
         })
     }
 
+    fn effect(&self) -> ToolEffect {
+        ToolEffect::Write
+    }
+
     #[tracing::instrument(skip(self, params, ctx), fields(path = %params["path"].as_str().unwrap_or("unknown")))]
     async fn execute(&self, params: Value, ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
         let args: WriteFileArgs = parse_args(params)?;
         tracing::info!(path = %args.path, "Writing file");
         
         // Security: validate path is within workspace using centralized logic
-        let path = crate::tools::is_safe_path(&ctx.workspace_root, std::path::Path::new(&args.path))?;
+        let path = ctx.resolve_path(&args.path)?;
 
         // Create parent directories if needed
         if let Some(parent) = path.parent() {
@@ -71,6 +75,8 @@ impl Tool for WriteFileTool {
                 "path": path.display().to_string(),
                 "bytes": bytes,
             }),
+            sources: Vec::new(),
+            artifacts: Vec::new(),
         })
     }
 }
@@ -87,6 +93,7 @@ mod tests {
         let ctx = ToolContext {
             workspace_root: dir.path().to_path_buf(),
             session_id: "test".to_string(),
+            ..Default::default()
         };
 
         let result = tool
@@ -109,6 +116,7 @@ mod tests {
         let ctx = ToolContext {
             workspace_root: dir.path().to_path_buf(),
             session_id: "test".to_string(),
+            ..Default::default()
         };
 
         tool.execute(
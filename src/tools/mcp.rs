@@ -1,13 +1,24 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
 
-use crate::tools::{Tool, ToolContext, ToolError, ToolOutput};
-use mcp_sdk::client::McpClient;
-use mcp_sdk::transport::StdioTransport;
+use crate::persistence::state::StateStore;
+use crate::tools::mcp_http::HttpClient;
+use crate::tools::mcp_stdio::StdioClient;
+use crate::tools::{Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
 use mcp_sdk::types::ToolDefinition;
 
+/// MCP protocol revisions spoken, newest first: offered by the clients at
+/// `initialize` and accepted by `bedrock mcp-serve`.
+pub const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
 /// The builtin tool that allows agents to request an MCP server connection.
 pub struct BridgeMcp;
 
@@ -18,7 +29,7 @@ impl Tool for BridgeMcp {
     }
 
     fn description(&self) -> &str {
-        "Connect to a Model Context Protocol (MCP) server to dynamically load its tools. Provide the command and arguments to spawn the server (e.g., `['npx', '-y', '@modelcontextprotocol/server-filesystem', '/path']`)."
+        "Connect to a Model Context Protocol (MCP) server to dynamically load its tools. Provide the command and arguments to spawn the server (e.g., `['npx', '-y', '@modelcontextprotocol/server-filesystem', '/path']`) and optionally a short server name used to namespace its tools."
     }
 
     fn parameters_schema(&self) -> Value {
@@ -33,12 +44,21 @@ impl Tool for BridgeMcp {
                     "type": "array",
                     "items": { "type": "string" },
                     "description": "Arguments for the command"
+                },
+                "name": {
+                    "type": "string",
+                    "description": "Short server name (e.g. 'filesystem'); defaults to the command name"
                 }
             },
             "required": ["command", "args"]
         })
     }
 
+    /// Starts an MCP server process.
+    fn effect(&self) -> ToolEffect {
+        ToolEffect::Execute
+    }
+
     async fn execute(&self, params: Value, _ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
         let command = params["command"].as_str()
             .ok_or_else(|| ToolError::InvalidParams("Missing 'command'".to_string()))?
@@ -56,6 +76,9 @@ impl Tool for BridgeMcp {
         output.metadata["action"] = json!("spawn_mcp");
         output.metadata["command"] = json!(command);
         output.metadata["args"] = json!(args);
+        if let Some(name) = params["name"].as_str() {
+            output.metadata["name"] = json!(name);
+        }
 
         Ok(output)
     }
@@ -63,20 +86,468 @@ impl Tool for BridgeMcp {
 
 /// A proxy tool that forwards calls to a remote MCP server.
 pub struct McpToolProxy {
-    client: Arc<McpClient<StdioTransport>>,
+    server: Arc<McpServerHandle>,
     def: ToolDefinition,
+    /// Name exposed to the model (may be namespaced; `def.name` is sent to the server)
+    name: String,
 }
 
 impl McpToolProxy {
-    pub fn new(client: Arc<McpClient<StdioTransport>>, def: ToolDefinition) -> Self {
-        Self { client, def }
+    pub fn new(server: Arc<McpServerHandle>, def: ToolDefinition) -> Self {
+        let name = def.name.clone();
+        Self { server, def, name }
+    }
+
+    /// Expose the tool as `<server>__<tool>` to avoid clashes with other tools.
+    pub fn namespaced(mut self) -> Self {
+        self.name = namespaced_tool_name(self.server.name(), &self.def.name);
+        self
+    }
+}
+
+/// How long a health check (`tools/list`) may take before the server counts as dead.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Cap on the wait between restarts.
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+type Client = Arc<McpClient>;
+
+/// When a crashed server may be restarted (`[mcp]` restart settings).
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    /// Consecutive failed starts after which the server stays down
+    pub max_restarts: u32,
+    /// Wait before the first restart, doubled for each failure after it
+    pub base_delay: Duration,
+    /// How long starting may take
+    pub startup_timeout: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self { max_restarts: 5, base_delay: Duration::from_secs(1), startup_timeout: Duration::from_secs(30) }
+    }
+}
+
+/// Consecutive failures of a server and when it may next be started.
+#[derive(Debug, Default)]
+struct Backoff {
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Backoff {
+    fn fail(&mut self, policy: &RestartPolicy, now: Instant) {
+        self.failures += 1;
+        let delay = policy.base_delay.saturating_mul(1 << (self.failures - 1).min(16)).min(MAX_RESTART_DELAY);
+        self.retry_at = Some(now + delay);
+    }
+
+    /// Why the server can't be started at `now`, if it can't.
+    fn blocked(&self, policy: &RestartPolicy, now: Instant) -> Option<String> {
+        if self.failures > policy.max_restarts {
+            return Some(format!("gave up after {} failed restarts", policy.max_restarts));
+        }
+        match self.retry_at {
+            Some(at) if at > now => Some(format!("restarting in {:.0}s", (at - now).as_secs_f64().ceil())),
+            _ => None,
+        }
+    }
+}
+
+/// Where an MCP server is in its lifecycle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "status")]
+pub enum McpServerStatus {
+    /// Lazy, and none of its tools called yet
+    NotStarted,
+    Running,
+    /// Crashed or failed to start; restarted with backoff
+    Down { failures: u32, error: String },
+}
+
+#[derive(Default)]
+struct ServerState {
+    client: Option<Client>,
+    backoff: Backoff,
+    last_error: String,
+}
+
+/// A configured MCP server, started on first use if lazy and restarted with
+/// backoff when it dies.
+///
+/// A server counts as dead when a health check (`tools/list`, run by
+/// [`Self::supervise`] and after any failed call) fails or times out. Its
+/// client is dropped, and the next call or health check past the backoff
+/// starts it again. Successful calls and checks reset the backoff; after
+/// `max_restarts` failures in a row the server stays down.
+pub struct McpServerHandle {
+    name: String,
+    transport: McpTransport,
+    policy: RestartPolicy,
+    /// Where the tool list is cached for lazy starts
+    cache: Option<StateStore>,
+    state: tokio::sync::Mutex<ServerState>,
+}
+
+impl McpServerHandle {
+    pub fn new(name: &str, transport: McpTransport, policy: RestartPolicy) -> Self {
+        Self {
+            name: name.to_string(),
+            transport,
+            policy,
+            cache: None,
+            state: Default::default(),
+        }
+    }
+
+    /// Cache the server's tool list in `store` whenever it starts, for
+    /// [`Self::cached_tools`].
+    pub fn with_tool_cache(mut self, store: StateStore) -> Self {
+        self.cache = Some(store);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub async fn status(&self) -> McpServerStatus {
+        let state = self.state.lock().await;
+        match (&state.client, state.backoff.failures) {
+            (Some(_), _) => McpServerStatus::Running,
+            (None, 0) => McpServerStatus::NotStarted,
+            (None, failures) => McpServerStatus::Down { failures, error: state.last_error.clone() },
+        }
+    }
+
+    /// Start the server now and list its tools.
+    pub async fn start(&self) -> Result<Vec<ToolDefinition>> {
+        let mut state = self.state.lock().await;
+        self.connect(&mut state).await.map(|(_, tools)| tools)
+    }
+
+    /// The running client, starting the server first if it isn't running
+    /// and its backoff allows.
+    pub async fn client(&self) -> Result<Client> {
+        let mut state = self.state.lock().await;
+        if let Some(client) = &state.client {
+            return Ok(client.clone());
+        }
+        if let Some(reason) = state.backoff.blocked(&self.policy, Instant::now()) {
+            anyhow::bail!("MCP server '{}' is down ({}): {}", self.name, reason, state.last_error);
+        }
+        self.connect(&mut state).await.map(|(client, _)| client)
+    }
+
+    async fn connect(&self, state: &mut ServerState) -> Result<(Client, Vec<ToolDefinition>)> {
+        let restart = state.backoff.failures > 0;
+        let connected = tokio::time::timeout(self.policy.startup_timeout, self.transport.connect())
+            .await
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("Timed out after {}s", self.policy.startup_timeout.as_secs_f64())));
+        let McpConnection { client, tools } = match connected {
+            Ok(connection) => connection,
+            Err(e) => {
+                state.backoff.fail(&self.policy, Instant::now());
+                state.last_error = format!("{:#}", e);
+                return Err(e.context(format!("Failed to start MCP server '{}'", self.name)));
+            }
+        };
+        if restart {
+            info!(server = %self.name, failures = state.backoff.failures, "MCP server restarted");
+        }
+        state.client = Some(client.clone());
+        let names = |tools: &[ToolDefinition]| tools.iter().map(|t| t.name.clone()).collect::<Vec<_>>();
+        if self.cached_tools().await.is_some_and(|cached| names(&cached) != names(&tools)) {
+            warn!(server = %self.name, "MCP server's tools changed since they were cached; restart to pick up the new list");
+        }
+        self.cache_tools(&tools).await;
+        Ok((client, tools))
+    }
+
+    /// Send a JSON-RPC request (resources, prompts) to the server, starting
+    /// it first if needed.
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let client = self.client().await?;
+        let result = client.request(method, params).await;
+        match &result {
+            Ok(_) => self.succeeded(&client).await,
+            Err(_) => {
+                self.check(&client).await;
+            }
+        }
+        result
+    }
+
+    /// Reset the backoff after `client` answered, if it is still the
+    /// running client.
+    async fn succeeded(&self, client: &Client) {
+        let mut state = self.state.lock().await;
+        if state.backoff.failures > 0 && state.client.as_ref().is_some_and(|current| Arc::ptr_eq(current, client)) {
+            state.backoff = Backoff::default();
+        }
+    }
+
+    /// One health-check round: check a running server, and restart a dead
+    /// one whose backoff has passed. A lazy server not yet started is left alone.
+    pub async fn supervise(&self) {
+        let client = {
+            let mut state = self.state.lock().await;
+            match state.client.clone() {
+                Some(client) => client,
+                None => {
+                    if state.backoff.failures > 0 && state.backoff.blocked(&self.policy, Instant::now()).is_none() {
+                        let _ = self.connect(&mut state).await;
+                    }
+                    return;
+                }
+            }
+        };
+        self.check(&client).await;
+    }
+
+    /// Check `client` after a failed call, marking the server dead if it
+    /// doesn't answer. Returns whether it is still alive.
+    pub async fn check(&self, client: &Client) -> bool {
+        let alive = match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, client.list_tools()).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(_) => Err(format!("no answer within {}s", HEALTH_CHECK_TIMEOUT.as_secs())),
+        };
+        let mut state = self.state.lock().await;
+        // Parallel calls can fail together; only the first marks the client dead
+        if !state.client.as_ref().is_some_and(|current| Arc::ptr_eq(current, client)) {
+            return alive.is_ok();
+        }
+        match alive {
+            Ok(()) => {
+                state.backoff = Backoff::default();
+                true
+            }
+            Err(error) => {
+                state.client = None;
+                state.backoff.fail(&self.policy, Instant::now());
+                warn!(server = %self.name, error = %error, failures = state.backoff.failures, "MCP server failed its health check");
+                state.last_error = error;
+                false
+            }
+        }
+    }
+
+    /// The tool list cached the last time this server (same command line
+    /// or URL) started, if any.
+    pub async fn cached_tools(&self) -> Option<Vec<ToolDefinition>> {
+        let raw = self.cache.as_ref()?.kv_get(&tool_cache_key(&self.name)).await.ok()??;
+        let cached: CachedTools = serde_json::from_str(&raw).ok()?;
+        (cached.source == self.transport.source()).then_some(cached.tools)
     }
+
+    async fn cache_tools(&self, tools: &[ToolDefinition]) {
+        let Some(store) = &self.cache else {
+            return;
+        };
+        let cached = CachedTools { source: self.transport.source(), tools: tools.to_vec() };
+        let json = serde_json::to_string(&cached).unwrap_or_default();
+        if let Err(e) = store.kv_set(&tool_cache_key(&self.name), &json).await {
+            warn!(server = %self.name, error = %e, "Failed to cache MCP tool list");
+        }
+    }
+}
+
+/// The kernel's MCP servers, shared with the tools and harness APIs that use them.
+pub type McpServers = Arc<std::sync::RwLock<Vec<Arc<McpServerHandle>>>>;
+
+/// Key of a server's cached tool list in the state store's kv table.
+pub fn tool_cache_key(server: &str) -> String {
+    format!("bedrock.mcp_tools.{}", server)
+}
+
+/// A server's tool list as cached for lazy starts.
+#[derive(Serialize, Deserialize)]
+pub struct CachedTools {
+    /// The command line or URL the tools were listed by
+    pub source: String,
+    pub tools: Vec<ToolDefinition>,
+}
+
+/// Run [`McpServerHandle::supervise`] on every server in `servers` each
+/// `interval`, until `servers` is dropped.
+pub fn spawn_health_checks(servers: std::sync::Weak<std::sync::RwLock<Vec<Arc<McpServerHandle>>>>, interval: Duration) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            let Some(servers) = servers.upgrade() else {
+                break;
+            };
+            let snapshot = servers.read().unwrap_or_else(|e| e.into_inner()).clone();
+            drop(servers);
+            futures::future::join_all(snapshot.iter().map(|server| server.supervise())).await;
+        }
+    });
+}
+
+/// Build `<server>__<tool>`, replacing characters providers reject in tool names.
+pub fn namespaced_tool_name(server: &str, tool: &str) -> String {
+    let server: String = server
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .collect();
+    format!("{}__{}", server, tool)
+}
+
+/// Default server name for namespacing: the command's file stem.
+pub fn default_server_name(command: &str) -> String {
+    std::path::Path::new(command)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| command.to_string())
+}
+
+/// How to reach an MCP server.
+#[derive(Debug, Clone, PartialEq)]
+pub enum McpTransport {
+    /// Spawn `command` and speak over its stdin and stdout
+    Stdio { command: String, args: Vec<String> },
+    /// A remote server at `url` over streamable HTTP, or HTTP+SSE if `sse`
+    Http { url: String, headers: HashMap<String, String>, sse: bool },
+}
+
+impl McpTransport {
+    /// Start or reach the server, initialize it and list its tools.
+    pub async fn connect(&self) -> Result<McpConnection> {
+        let client = match self {
+            McpTransport::Stdio { command, args } => McpClient::Stdio(Box::new(StdioClient::connect(command, args).await?)),
+            McpTransport::Http { url, headers, sse } => McpClient::Http(Box::new(HttpClient::connect(url, headers, *sse).await?)),
+        };
+        let tools = client.list_tools().await.with_context(|| "Failed to list MCP tools")?;
+        Ok(McpConnection { client: Arc::new(client), tools })
+    }
+
+    /// The command line or URL, which a cached tool list must match.
+    fn source(&self) -> String {
+        match self {
+            McpTransport::Stdio { command, args } => std::iter::once(command).chain(args).cloned().collect::<Vec<_>>().join(" "),
+            McpTransport::Http { url, .. } => url.clone(),
+        }
+    }
+
+    /// Default server name for namespacing: the command's file stem, or the URL's host.
+    pub fn default_name(&self) -> String {
+        match self {
+            McpTransport::Stdio { command, .. } => default_server_name(command),
+            McpTransport::Http { url, .. } => reqwest::Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .unwrap_or_else(|| url.clone()),
+        }
+    }
+}
+
+/// A client of one MCP server, over its transport.
+pub enum McpClient {
+    Stdio(Box<StdioClient>),
+    Http(Box<HttpClient>),
+}
+
+/// A tool call's text output.
+pub struct McpCallResult {
+    pub text: String,
+    /// Whether the server reported the call as failed
+    pub is_error: bool,
+}
+
+impl McpClient {
+    /// List the server's tools, following `nextCursor` through every page.
+    pub async fn list_tools(&self) -> Result<Vec<ToolDefinition>> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let result = self.request("tools/list", params).await?;
+            for tool in result["tools"].as_array().into_iter().flatten() {
+                tools.push(ToolDefinition {
+                    name: tool["name"].as_str().context("MCP server listed a tool without a name")?.to_string(),
+                    description: tool["description"].as_str().map(str::to_string),
+                    input_schema: tool.get("inputSchema").cloned().unwrap_or_else(|| json!({ "type": "object" })),
+                });
+            }
+            match result["nextCursor"].as_str() {
+                Some(next) => cursor = Some(next.to_string()),
+                None => return Ok(tools),
+            }
+        }
+    }
+
+    /// Call a tool; text content is joined by newlines, and other content
+    /// is named by its type.
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<McpCallResult> {
+        let result = self.request("tools/call", json!({ "name": name, "arguments": arguments })).await?;
+        let text: Vec<String> = result["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|item| match item["text"].as_str() {
+                Some(text) => text.to_string(),
+                None => format!("[{} content]", item["type"].as_str().unwrap_or("unknown")),
+            })
+            .collect();
+        Ok(McpCallResult { text: text.join("\n"), is_error: result["isError"].as_bool().unwrap_or(false) })
+    }
+
+    /// Send any JSON-RPC request and return its `result`.
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        match self {
+            McpClient::Stdio(client) => client.request(method, params).await,
+            McpClient::Http(client) => client.request(method, params).await,
+        }
+    }
+}
+
+/// A started and initialized MCP server with the tools it listed.
+pub struct McpConnection {
+    pub client: Arc<McpClient>,
+    pub tools: Vec<ToolDefinition>,
+}
+
+/// Run `start` for every server, at most `concurrency` at a time, giving each
+/// `timeout` to finish. Results come back in the order of `servers`.
+pub async fn start_all<'a, S, T, F, Fut>(servers: &'a [S], concurrency: usize, timeout: Duration, start: F) -> Vec<Result<T>>
+where
+    F: Fn(&'a S) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    // Collected up front so no closure over borrowed servers is held across
+    // an await, which would keep the caller's future from being `Send`
+    let pending: Vec<_> = servers
+        .iter()
+        .enumerate()
+        .map(|(i, server)| {
+            let started = start(server);
+            async move {
+                let result = tokio::time::timeout(timeout, started)
+                    .await
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("Timed out after {}s", timeout.as_secs_f64())));
+                (i, result)
+            }
+        })
+        .collect();
+    let mut results: Vec<(usize, Result<T>)> = futures::stream::iter(pending)
+        // Unordered so a slow server doesn't keep finished slots from being reused
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
 }
 
 #[async_trait]
 impl Tool for McpToolProxy {
     fn name(&self) -> &str {
-        &self.def.name
+        &self.name
     }
 
     fn description(&self) -> &str {
@@ -87,17 +558,141 @@ impl Tool for McpToolProxy {
         self.def.input_schema.clone()
     }
 
-    async fn execute(&self, params: Value, _ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
-        let result = self.client.call_tool(&self.def.name, params).await
-            .map_err(|e| ToolError::ExecutionError(format!("MCP Call Failed: {}", e)))?;
+    fn server(&self) -> Option<&str> {
+        Some(self.server.name())
+    }
 
-        // Convert MCP content to ToolOutput text
-        let text_output = result.as_text();
+    async fn execute(&self, params: Value, _ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
+        let client = self.server.client().await.map_err(|e| ToolError::ExecutionError(format!("{:#}", e)))?;
+        let result = match client.call_tool(&self.def.name, params).await {
+            Ok(result) => {
+                self.server.succeeded(&client).await;
+                result
+            }
+            Err(e) => {
+                let restart = if self.server.check(&client).await { "" } else { " (server stopped responding; it will be restarted)" };
+                return Err(ToolError::ExecutionError(format!("MCP Call Failed: {}{}", e, restart)));
+            }
+        };
 
         if result.is_error {
-            return Err(ToolError::ExecutionError(text_output));
+            return Err(ToolError::ExecutionError(result.text));
         }
 
-        Ok(ToolOutput::new(text_output.trim().to_string()))
+        Ok(ToolOutput::new(result.text.trim().to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespaced_tool_name() {
+        assert_eq!(namespaced_tool_name("filesystem", "read_file"), "filesystem__read_file");
+        assert_eq!(namespaced_tool_name("@scope/server.fs", "list"), "_scope_server_fs__list");
+    }
+
+    #[test]
+    fn test_default_server_name() {
+        assert_eq!(default_server_name("/usr/local/bin/my-server"), "my-server");
+        assert_eq!(default_server_name("server.py"), "server");
+    }
+
+    #[test]
+    fn test_restart_backoff() {
+        let policy = RestartPolicy { max_restarts: 3, base_delay: Duration::from_secs(1), ..Default::default() };
+        let now = Instant::now();
+        let mut backoff = Backoff::default();
+        assert!(backoff.blocked(&policy, now).is_none());
+
+        backoff.fail(&policy, now);
+        assert_eq!(backoff.blocked(&policy, now).as_deref(), Some("restarting in 1s"));
+        assert!(backoff.blocked(&policy, now + Duration::from_secs(1)).is_none());
+        backoff.fail(&policy, now);
+        backoff.fail(&policy, now);
+        assert_eq!(backoff.blocked(&policy, now).as_deref(), Some("restarting in 4s"));
+        backoff.fail(&policy, now);
+        let later = now + MAX_RESTART_DELAY;
+        assert_eq!(backoff.blocked(&policy, later).as_deref(), Some("gave up after 3 failed restarts"));
+
+        // Doubling stops at the cap
+        let mut long = Backoff { failures: 30, retry_at: None };
+        long.fail(&RestartPolicy::default(), now);
+        assert_eq!(long.retry_at, Some(now + MAX_RESTART_DELAY));
+    }
+
+    #[tokio::test]
+    async fn test_start_all_bounded_with_timeout() {
+        let active = std::sync::atomic::AtomicUsize::new(0);
+        let peak = std::sync::atomic::AtomicUsize::new(0);
+        // Delay in ms per server; 0 fails immediately, 500 outlives the timeout
+        let servers = [20u64, 500, 0, 20, 20];
+        let started = std::time::Instant::now();
+        let results = start_all(&servers, 2, Duration::from_millis(200), |&delay| {
+            let (active, peak) = (&active, &peak);
+            async move {
+                let now = active.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                peak.fetch_max(now, std::sync::atomic::Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                active.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                anyhow::ensure!(delay > 0, "exited");
+                Ok(delay)
+            }
+        })
+        .await;
+
+        let outcome: Vec<_> = results.iter().map(|r| r.as_ref().ok().copied()).collect();
+        assert_eq!(outcome, vec![Some(20), None, None, Some(20), Some(20)]);
+        assert!(results[1].as_ref().unwrap_err().to_string().contains("Timed out"));
+        assert_eq!(peak.load(std::sync::atomic::Ordering::SeqCst), 2);
+        // The slow server didn't hold up the others
+        assert!(started.elapsed() < Duration::from_millis(450));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_killed_server_is_restarted() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pid_file = tmp.path().join("server.pid");
+        // Answers every request with the same tool list, recording its pid on start
+        let script = format!(
+            r#"echo $$ > {}
+while read line; do
+  id=$(echo "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  [ -n "$id" ] && echo "{{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{{\"tools\":[{{\"name\":\"t\"}}]}}}}"
+done"#,
+            pid_file.display()
+        );
+        let transport = McpTransport::Stdio { command: "sh".to_string(), args: vec!["-c".to_string(), script] };
+        let policy = RestartPolicy { base_delay: Duration::ZERO, ..Default::default() };
+        let server = Arc::new(McpServerHandle::new("sh", transport, policy));
+        let tools = server.start().await.unwrap();
+        let proxy = McpToolProxy::new(server.clone(), tools[0].clone());
+        let ctx = ToolContext::default();
+        assert!(proxy.execute(json!({}), &ctx).await.is_ok());
+
+        let pid = || std::fs::read_to_string(&pid_file).unwrap().trim().to_string();
+        let kill = |pid: &str| assert!(std::process::Command::new("kill").args(["-9", pid]).status().unwrap().success());
+
+        // The next call fails and finds the server dead; the one after restarts it
+        let first = pid();
+        kill(&first);
+        let failed = proxy.execute(json!({}), &ctx).await.unwrap_err().to_string();
+        assert!(failed.contains("it will be restarted"), "{}", failed);
+        assert!(matches!(server.status().await, McpServerStatus::Down { failures: 1, .. }));
+        assert!(proxy.execute(json!({}), &ctx).await.is_ok());
+        assert_eq!(server.status().await, McpServerStatus::Running);
+        let second = pid();
+        assert_ne!(first, second);
+
+        // A health check finds it dead, and the next one restarts it
+        kill(&second);
+        server.supervise().await;
+        assert!(matches!(server.status().await, McpServerStatus::Down { failures: 1, .. }));
+        server.supervise().await;
+        assert_eq!(server.status().await, McpServerStatus::Running);
+        assert_ne!(pid(), second);
+        assert!(proxy.execute(json!({}), &ctx).await.is_ok());
     }
 }
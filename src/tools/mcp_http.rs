@@ -0,0 +1,349 @@
+//! Clients for MCP servers reached over HTTP (`url` in `[[mcp.servers]]`).
+//!
+//! Two transports are spoken:
+//!
+//! - **Streamable HTTP** (`transport = "http"`, the default for a `url`): each
+//!   JSON-RPC message is POSTed to the URL, and the server answers with a
+//!   JSON body or an SSE stream carrying the response. The session id a
+//!   server may assign at `initialize` (`Mcp-Session-Id`) is sent back with
+//!   every later request.
+//! - **HTTP+SSE** (`transport = "sse"`, protocol 2024-11-05): a GET to the URL
+//!   opens an event stream whose `endpoint` event names where messages are
+//!   POSTed; responses arrive on the stream. The endpoint must be on the
+//!   URL's origin, so the configured headers never go to another host.
+//!
+//! The configured `headers` (e.g. `Authorization`) go with every request.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, CONTENT_TYPE};
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+
+use super::mcp::PROTOCOL_VERSIONS;
+
+/// How long connecting to the server may take (calls themselves are not limited)
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const SESSION_HEADER: &str = "mcp-session-id";
+const PROTOCOL_HEADER: &str = "mcp-protocol-version";
+
+/// Requests awaiting a response by id; `None` once the server's stream or
+/// output has closed.
+pub(super) type Pending = Arc<Mutex<Option<HashMap<u64, oneshot::Sender<Value>>>>>;
+
+pub(super) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// An initialized client of a remote MCP server.
+pub struct HttpClient {
+    http: reqwest::Client,
+    url: reqwest::Url,
+    headers: HeaderMap,
+    next_id: AtomicU64,
+    /// Assigned by a streamable HTTP server at `initialize`
+    session_id: Mutex<Option<String>>,
+    /// Agreed at `initialize`
+    protocol_version: Mutex<Option<String>>,
+    /// The event stream of an HTTP+SSE server
+    sse: Option<SseChannel>,
+}
+
+impl HttpClient {
+    /// Connect to `url` over streamable HTTP, or HTTP+SSE if `sse`, and
+    /// initialize the session.
+    pub async fn connect(url: &str, headers: &HashMap<String, String>, sse: bool) -> Result<Self> {
+        let url = reqwest::Url::parse(url).with_context(|| format!("Invalid MCP server URL: {}", url))?;
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).with_context(|| format!("Invalid header name: {}", name))?;
+            let value = HeaderValue::from_str(value).with_context(|| format!("Invalid value for header {}", name))?;
+            header_map.insert(name, value);
+        }
+        let http = reqwest::Client::builder().connect_timeout(CONNECT_TIMEOUT).build()?;
+        let sse = match sse {
+            true => Some(SseChannel::open(&http, &url, &header_map).await?),
+            false => None,
+        };
+        let client = Self {
+            http,
+            url,
+            headers: header_map,
+            next_id: AtomicU64::new(1),
+            session_id: Default::default(),
+            protocol_version: Default::default(),
+            sse,
+        };
+        client.initialize().await.context("Failed to initialize MCP client")?;
+        Ok(client)
+    }
+
+    async fn initialize(&self) -> Result<()> {
+        let result = self
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSIONS[0],
+                    "capabilities": {},
+                    "clientInfo": { "name": "bedrock", "version": env!("CARGO_PKG_VERSION") },
+                }),
+            )
+            .await?;
+        let version = result["protocolVersion"].as_str().unwrap_or(PROTOCOL_VERSIONS[0]);
+        *lock(&self.protocol_version) = Some(version.to_string());
+        let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        self.post(self.endpoint(), &initialized).await.map(drop)
+    }
+
+    /// Send a JSON-RPC request and return its `result`.
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let response = match &self.sse {
+            Some(sse) => {
+                let (tx, rx) = oneshot::channel();
+                lock(&sse.pending)
+                    .as_mut()
+                    .context("MCP server closed its event stream")?
+                    .insert(id, tx);
+                if let Err(e) = self.post(&sse.endpoint, &message).await {
+                    lock(&sse.pending).as_mut().map(|pending| pending.remove(&id));
+                    return Err(e);
+                }
+                rx.await.map_err(|_| anyhow::anyhow!("MCP server closed its event stream"))?
+            }
+            None => read_response(self.post(&self.url, &message).await?, id).await?,
+        };
+        if let Some(error) = response.get("error") {
+            anyhow::bail!("MCP error {}: {}", error["code"], error["message"].as_str().unwrap_or("unknown error"));
+        }
+        Ok(response["result"].clone())
+    }
+
+    /// Where messages are POSTed.
+    fn endpoint(&self) -> &reqwest::Url {
+        self.sse.as_ref().map_or(&self.url, |sse| &sse.endpoint)
+    }
+
+    async fn post(&self, url: &reqwest::Url, message: &Value) -> Result<reqwest::Response> {
+        let mut request = self
+            .http
+            .post(url.clone())
+            .headers(self.headers.clone())
+            .header(ACCEPT, "application/json, text/event-stream")
+            .json(message);
+        if let Some(session_id) = lock(&self.session_id).clone() {
+            request = request.header(SESSION_HEADER, session_id);
+        }
+        if let Some(version) = lock(&self.protocol_version).clone() {
+            request = request.header(PROTOCOL_HEADER, version);
+        }
+        let response = request.send().await.with_context(|| format!("Failed to reach MCP server at {}", url))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("MCP server at {} answered {}: {}", url, status, body.trim());
+        }
+        if let Some(session_id) = response.headers().get(SESSION_HEADER).and_then(|v| v.to_str().ok()) {
+            *lock(&self.session_id) = Some(session_id.to_string());
+        }
+        Ok(response)
+    }
+}
+
+/// The response with `id` in a streamable HTTP reply: a JSON body or an SSE stream.
+async fn read_response(response: reqwest::Response, id: u64) -> Result<Value> {
+    let is_stream = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|t| t.starts_with("text/event-stream"));
+    if !is_stream {
+        return response.json().await.context("Invalid JSON-RPC response from MCP server");
+    }
+    let mut parser = SseParser::default();
+    let mut body = response.bytes_stream();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.context("MCP server's event stream failed")?;
+        // Server requests and notifications sent before the response are skipped
+        let found = parser
+            .push(&chunk)
+            .into_iter()
+            .filter_map(|event| serde_json::from_str::<Value>(&event.data).ok())
+            .find(|message| message["id"] == json!(id) && message.get("method").is_none());
+        if let Some(message) = found {
+            return Ok(message);
+        }
+    }
+    anyhow::bail!("MCP server ended its event stream without answering")
+}
+
+/// The open event stream of an HTTP+SSE server.
+struct SseChannel {
+    /// Where messages are POSTed, from the `endpoint` event
+    endpoint: reqwest::Url,
+    pending: Pending,
+    _reader: AbortOnDrop,
+}
+
+/// Stops the stream reader with the channel, or if connecting is abandoned.
+pub(super) struct AbortOnDrop(pub(super) tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl SseChannel {
+    async fn open(http: &reqwest::Client, url: &reqwest::Url, headers: &HeaderMap) -> Result<Self> {
+        let response = http
+            .get(url.clone())
+            .headers(headers.clone())
+            .header(ACCEPT, "text/event-stream")
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach MCP server at {}", url))?;
+        anyhow::ensure!(response.status().is_success(), "MCP server at {} answered {}", url, response.status());
+
+        let pending: Pending = Arc::new(Mutex::new(Some(HashMap::new())));
+        let (endpoint_tx, endpoint_rx) = oneshot::channel();
+        let reader = AbortOnDrop(tokio::spawn(read_events(response, endpoint_tx, pending.clone())));
+        let endpoint = endpoint_rx
+            .await
+            .map_err(|_| anyhow::anyhow!("MCP server at {} closed its event stream before naming an endpoint", url))?;
+        let endpoint = url.join(endpoint.trim()).with_context(|| format!("Invalid MCP endpoint: {}", endpoint))?;
+        anyhow::ensure!(
+            endpoint.origin() == url.origin(),
+            "MCP server at {} named an endpoint on another origin: {}",
+            url,
+            endpoint
+        );
+        Ok(Self { endpoint, pending, _reader: reader })
+    }
+}
+
+/// Hand the `endpoint` event and each response on an HTTP+SSE stream to
+/// whoever is waiting for it, failing every waiting request once it closes.
+async fn read_events(response: reqwest::Response, endpoint: oneshot::Sender<String>, pending: Pending) {
+    let mut endpoint = Some(endpoint);
+    let mut parser = SseParser::default();
+    let mut body = response.bytes_stream();
+    while let Some(Ok(chunk)) = body.next().await {
+        for event in parser.push(&chunk) {
+            if event.event == "endpoint" {
+                if let Some(endpoint) = endpoint.take() {
+                    let _ = endpoint.send(event.data);
+                }
+                continue;
+            }
+            let Ok(message) = serde_json::from_str::<Value>(&event.data) else {
+                continue;
+            };
+            let waiting = match message.get("method") {
+                Some(_) => None,
+                None => message["id"].as_u64().and_then(|id| lock(&pending).as_mut()?.remove(&id)),
+            };
+            if let Some(waiting) = waiting {
+                let _ = waiting.send(message);
+            }
+        }
+    }
+    // Dropping the senders fails the requests still waiting
+    lock(&pending).take();
+}
+
+#[derive(Debug, PartialEq)]
+struct SseEvent {
+    event: String,
+    data: String,
+}
+
+/// Splits a `text/event-stream` body into events as chunks arrive.
+#[derive(Default)]
+struct SseParser {
+    /// Bytes of a line not yet complete
+    line: Vec<u8>,
+    event: String,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.line.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(end) = self.line.iter().position(|&b| b == b'\n') {
+            let raw: Vec<u8> = self.line.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&raw);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    let event = std::mem::take(&mut self.event);
+                    events.push(SseEvent {
+                        event: if event.is_empty() { "message".to_string() } else { event },
+                        data: self.data.join("\n"),
+                    });
+                }
+                self.event.clear();
+                self.data.clear();
+                continue;
+            }
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "event" => self.event = value.to_string(),
+                "data" => self.data.push(value.to_string()),
+                // Comments (":") and `id`/`retry` fields
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_parser() {
+        let mut parser = SseParser::default();
+        assert!(parser.push(b": keep-alive\n\nevent: endpoint\r\ndata: /messages?session=1").is_empty());
+        let events = parser.push(b"\r\n\r\ndata: {\"id\":1,\ndata: \"result\":{}}\n\n");
+        assert_eq!(
+            events,
+            vec![
+                SseEvent { event: "endpoint".to_string(), data: "/messages?session=1".to_string() },
+                SseEvent { event: "message".to_string(), data: "{\"id\":1,\n\"result\":{}}".to_string() },
+            ]
+        );
+        // A multi-byte character split across chunks survives
+        let first = "data: é\n\n".as_bytes();
+        assert!(parser.push(&first[..7]).is_empty());
+        assert_eq!(parser.push(&first[7..])[0].data, "é");
+    }
+
+    #[tokio::test]
+    async fn test_sse_endpoint_must_share_origin() {
+        use axum::response::sse::{Event, Sse};
+        let app = axum::Router::new().route(
+            "/sse",
+            axum::routing::get(|| async {
+                let endpoint = Event::default().event("endpoint").data("http://evil.example/messages");
+                Sse::new(futures::stream::iter([Ok::<_, std::convert::Infallible>(endpoint)]).chain(futures::stream::pending()))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = reqwest::Url::parse(&format!("http://{}/sse", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let err = SseChannel::open(&reqwest::Client::new(), &url, &HeaderMap::new()).await.err().unwrap();
+        assert!(err.to_string().contains("named an endpoint on another origin: http://evil.example/messages"), "{}", err);
+    }
+}
@@ -0,0 +1,275 @@
+//! Resources and prompts of MCP servers.
+//!
+//! Resources (`resources/list`, `resources/read`) are offered to the model
+//! through the `read_resource` tool and to harnesses through
+//! `bedrock.mcp_resources()` and `bedrock.read_mcp_resource(uri)`. Prompts
+//! (`prompts/list`, `prompts/get`) are templates the user expands from the
+//! REPL with `/prompt <name> key=value ...`.
+//!
+//! Servers are asked over any transport. A server that doesn't support
+//! resources or prompts is skipped when listing them.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::debug;
+
+use super::mcp::{McpServerHandle, McpServers};
+use crate::tools::{Tool, ToolContext, ToolEffect, ToolError, ToolOutput};
+
+/// A resource listed by a server.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct McpResource {
+    pub server: String,
+    pub uri: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+/// A prompt template listed by a server.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct McpPrompt {
+    pub server: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub arguments: Vec<McpPromptArgument>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct McpPromptArgument {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// Every item of a paginated `*/list` method, following `nextCursor`.
+async fn list_all(server: &McpServerHandle, method: &str, field: &str) -> Result<Vec<Value>> {
+    let mut items = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let params = match &cursor {
+            Some(cursor) => json!({ "cursor": cursor }),
+            None => json!({}),
+        };
+        let result = server.request(method, params).await?;
+        items.extend(result[field].as_array().into_iter().flatten().cloned());
+        match result["nextCursor"].as_str() {
+            Some(next) => cursor = Some(next.to_string()),
+            None => return Ok(items),
+        }
+    }
+}
+
+/// Resources of every server that offers them.
+pub async fn list_resources(servers: &[Arc<McpServerHandle>]) -> Vec<McpResource> {
+    let mut resources = Vec::new();
+    for server in servers {
+        let listed = match list_all(server, "resources/list", "resources").await {
+            Ok(listed) => listed,
+            Err(e) => {
+                debug!(server = %server.name(), error = %format!("{:#}", e), "MCP server lists no resources");
+                continue;
+            }
+        };
+        resources.extend(listed.iter().filter_map(|resource| {
+            Some(McpResource {
+                server: server.name().to_string(),
+                uri: resource["uri"].as_str()?.to_string(),
+                name: resource["name"].as_str().unwrap_or_default().to_string(),
+                description: resource["description"].as_str().map(str::to_string),
+                mime_type: resource["mimeType"].as_str().map(str::to_string),
+            })
+        }));
+    }
+    resources
+}
+
+/// Read `uri` from `server`, or from the server that lists it. Text
+/// contents are joined by newlines; binary contents are described.
+pub async fn read_resource(servers: &[Arc<McpServerHandle>], uri: &str, server: Option<&str>) -> Result<String> {
+    let owner = match server {
+        Some(name) => servers
+            .iter()
+            .find(|s| s.name() == name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No MCP server named '{}'", name))?,
+        None => {
+            let listed = list_resources(servers).await;
+            let owner = listed
+                .iter()
+                .find(|r| r.uri == uri)
+                .ok_or_else(|| anyhow::anyhow!("No MCP server lists the resource '{}'", uri))?;
+            servers.iter().find(|s| s.name() == owner.server).cloned().expect("listed by a known server")
+        }
+    };
+    let result = owner.request("resources/read", json!({ "uri": uri })).await?;
+    let contents: Vec<String> = result["contents"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|content| match (content["text"].as_str(), content["blob"].as_str()) {
+            (Some(text), _) => text.to_string(),
+            (None, Some(blob)) => format!(
+                "[binary {}, {} bytes]",
+                content["mimeType"].as_str().unwrap_or("content"),
+                blob.len() / 4 * 3
+            ),
+            (None, None) => String::new(),
+        })
+        .collect();
+    Ok(contents.join("\n"))
+}
+
+/// Prompts of every server that offers them.
+pub async fn list_prompts(servers: &[Arc<McpServerHandle>]) -> Vec<McpPrompt> {
+    let mut prompts = Vec::new();
+    for server in servers {
+        let listed = match list_all(server, "prompts/list", "prompts").await {
+            Ok(listed) => listed,
+            Err(e) => {
+                debug!(server = %server.name(), error = %format!("{:#}", e), "MCP server lists no prompts");
+                continue;
+            }
+        };
+        prompts.extend(listed.iter().filter_map(|prompt| {
+            Some(McpPrompt {
+                server: server.name().to_string(),
+                name: prompt["name"].as_str()?.to_string(),
+                description: prompt["description"].as_str().map(str::to_string),
+                arguments: serde_json::from_value(prompt["arguments"].clone()).unwrap_or_default(),
+            })
+        }));
+    }
+    prompts
+}
+
+/// A text message of an expanded prompt, `role` being `user` or `assistant`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct McpPromptMessage {
+    pub role: String,
+    pub text: String,
+}
+
+/// Expand prompt `name` (`<server>:<name>` when several servers offer it)
+/// with `arguments`, returning its text messages in order.
+pub async fn get_prompt(
+    servers: &[Arc<McpServerHandle>],
+    name: &str,
+    arguments: &HashMap<String, String>,
+) -> Result<Vec<McpPromptMessage>> {
+    let prompts = list_prompts(servers).await;
+    let (server_name, prompt_name) = match name.split_once(':') {
+        Some((server, prompt)) => (Some(server), prompt),
+        None => (None, name),
+    };
+    let matching: Vec<&McpPrompt> = prompts
+        .iter()
+        .filter(|p| p.name == prompt_name && server_name.is_none_or(|s| p.server == s))
+        .collect();
+    let prompt = match matching.as_slice() {
+        [] => anyhow::bail!("No MCP prompt named '{}'", name),
+        [prompt] => *prompt,
+        several => anyhow::bail!(
+            "Several MCP servers offer the prompt '{}'; use one of {}",
+            name,
+            several.iter().map(|p| format!("{}:{}", p.server, p.name)).collect::<Vec<_>>().join(", ")
+        ),
+    };
+    let missing: Vec<&str> = prompt
+        .arguments
+        .iter()
+        .filter(|a| a.required && !arguments.contains_key(&a.name))
+        .map(|a| a.name.as_str())
+        .collect();
+    anyhow::ensure!(missing.is_empty(), "Prompt '{}' needs {}", name, missing.join(", "));
+
+    let server = servers.iter().find(|s| s.name() == prompt.server).expect("listed by a known server");
+    let result = server.request("prompts/get", json!({ "name": prompt.name, "arguments": arguments })).await?;
+    let messages = result["messages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|message| {
+            Some(McpPromptMessage {
+                role: message["role"].as_str().unwrap_or("user").to_string(),
+                text: message["content"]["text"].as_str()?.to_string(),
+            })
+        })
+        .collect();
+    Ok(messages)
+}
+
+/// `read_resource`: list the resources of the connected MCP servers, or read one.
+pub struct ReadResourceTool {
+    servers: McpServers,
+}
+
+impl ReadResourceTool {
+    pub fn new(servers: McpServers) -> Self {
+        Self { servers }
+    }
+}
+
+#[async_trait]
+impl Tool for ReadResourceTool {
+    fn name(&self) -> &str {
+        "read_resource"
+    }
+
+    fn description(&self) -> &str {
+        "Read a resource (a file, document or record) from a connected MCP server by its URI. Call without a uri to list the available resources."
+    }
+
+    fn parameters_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "uri": {
+                    "type": "string",
+                    "description": "URI of the resource to read; omit to list resources"
+                },
+                "server": {
+                    "type": "string",
+                    "description": "MCP server to read from (default: the server listing the uri)"
+                }
+            }
+        })
+    }
+
+    fn effect(&self) -> ToolEffect {
+        ToolEffect::ReadOnly
+    }
+
+    #[tracing::instrument(skip(self, params, _ctx), fields(uri = %params["uri"].as_str().unwrap_or("")))]
+    async fn execute(&self, params: Value, _ctx: &ToolContext) -> Result<ToolOutput, ToolError> {
+        let servers = self.servers.read().unwrap_or_else(|e| e.into_inner()).clone();
+        let Some(uri) = params["uri"].as_str() else {
+            let resources = list_resources(&servers).await;
+            if resources.is_empty() {
+                return Ok(ToolOutput::new("No MCP resources available".to_string()));
+            }
+            let lines: Vec<String> = resources
+                .iter()
+                .map(|r| match &r.description {
+                    Some(description) => format!("{} ({}, {}): {}", r.uri, r.name, r.server, description),
+                    None => format!("{} ({}, {})", r.uri, r.name, r.server),
+                })
+                .collect();
+            return Ok(ToolOutput::new(lines.join("\n")));
+        };
+        read_resource(&servers, uri, params["server"].as_str())
+            .await
+            .map(ToolOutput::new)
+            .map_err(|e| ToolError::ExecutionError(format!("{:#}", e)))
+    }
+}
@@ -0,0 +1,196 @@
+//! Client for MCP servers run as a local process (`command` in
+//! `[[mcp.servers]]`).
+//!
+//! Newline-delimited JSON-RPC messages are written to the process's stdin
+//! and read from its stdout; its stderr is logged at debug level. The client
+//! owns the process: dropping it, including when a start is abandoned on
+//! `mcp.startup_timeout_secs`, kills the process.
+
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::oneshot;
+use tracing::debug;
+
+use super::mcp::PROTOCOL_VERSIONS;
+use super::mcp_http::{lock, AbortOnDrop, Pending};
+
+/// An initialized client of a local MCP server process.
+pub struct StdioClient {
+    stdin: tokio::sync::Mutex<ChildStdin>,
+    next_id: AtomicU64,
+    pending: Pending,
+    _reader: AbortOnDrop,
+    /// Spawned with `kill_on_drop`
+    _child: Child,
+}
+
+impl StdioClient {
+    /// Spawn `command` and initialize the session. The process is killed if
+    /// it can't be initialized.
+    pub async fn connect(command: &str, args: &[String]) -> Result<Self> {
+        let mut child = Command::new(command)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to spawn MCP process: {}", command))?;
+        let stdin = child.stdin.take().context("MCP process has no stdin")?;
+        let stdout = child.stdout.take().context("MCP process has no stdout")?;
+        if let Some(stderr) = child.stderr.take() {
+            let command = command.to_string();
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    debug!(command = %command, "MCP server: {}", line);
+                }
+            });
+        }
+
+        let pending: Pending = Arc::new(Mutex::new(Some(HashMap::new())));
+        let reader = AbortOnDrop(tokio::spawn(read_messages(stdout, pending.clone())));
+        let client = Self {
+            stdin: tokio::sync::Mutex::new(stdin),
+            next_id: AtomicU64::new(1),
+            pending,
+            _reader: reader,
+            _child: child,
+        };
+        // On failure the client, and with it the process, is dropped
+        client.initialize().await.context("Failed to initialize MCP client")?;
+        Ok(client)
+    }
+
+    async fn initialize(&self) -> Result<()> {
+        self.request(
+            "initialize",
+            json!({
+                "protocolVersion": PROTOCOL_VERSIONS[0],
+                "capabilities": {},
+                "clientInfo": { "name": "bedrock", "version": env!("CARGO_PKG_VERSION") },
+            }),
+        )
+        .await?;
+        self.send(&json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await
+    }
+
+    /// Send a JSON-RPC request and return its `result`.
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        lock(&self.pending)
+            .as_mut()
+            .context("MCP server closed its output")?
+            .insert(id, tx);
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if let Err(e) = self.send(&message).await {
+            lock(&self.pending).as_mut().map(|pending| pending.remove(&id));
+            return Err(e);
+        }
+        let response = rx.await.map_err(|_| anyhow::anyhow!("MCP server closed its output"))?;
+        if let Some(error) = response.get("error") {
+            anyhow::bail!("MCP error {}: {}", error["code"], error["message"].as_str().unwrap_or("unknown error"));
+        }
+        Ok(response["result"].clone())
+    }
+
+    async fn send(&self, message: &Value) -> Result<()> {
+        let mut line = message.to_string();
+        line.push('\n');
+        let mut stdin = self.stdin.lock().await;
+        stdin.write_all(line.as_bytes()).await.context("Failed to write to MCP server")?;
+        stdin.flush().await.context("Failed to write to MCP server")
+    }
+}
+
+/// Hand each response on the server's stdout to whoever is waiting for it,
+/// failing every waiting request once the output closes.
+async fn read_messages(stdout: ChildStdout, pending: Pending) {
+    let mut lines = BufReader::new(stdout).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        // Server requests and notifications are skipped
+        let waiting = match message.get("method") {
+            Some(_) => None,
+            None => message["id"].as_u64().and_then(|id| lock(&pending).as_mut()?.remove(&id)),
+        };
+        if let Some(waiting) = waiting {
+            let _ = waiting.send(message);
+        }
+    }
+    // Dropping the senders fails the requests still waiting
+    lock(&pending).take();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn sh(script: &str) -> Vec<String> {
+        vec!["-c".to_string(), script.to_string()]
+    }
+
+    /// Whether the process is gone (or left only as a zombie).
+    fn exited(pid_file: &std::path::Path) -> bool {
+        let pid = std::fs::read_to_string(pid_file).unwrap();
+        match std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())) {
+            Ok(stat) => stat.split(") ").nth(1).is_some_and(|rest| rest.starts_with('Z')),
+            Err(_) => true,
+        }
+    }
+
+    async fn wait_exited(pid_file: &std::path::Path) -> bool {
+        for _ in 0..50 {
+            if exited(pid_file) {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        false
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_stdio_client_kills_process_that_fails_to_start() {
+        let tmp = tempfile::tempdir().unwrap();
+
+        // A server that answers initialize with an error
+        let pid_file = tmp.path().join("refuses.pid");
+        let script = format!(
+            "echo $$ > {}; read line; echo '{{\"jsonrpc\":\"2.0\",\"id\":1,\"error\":{{\"code\":-32600,\"message\":\"no\"}}}}'; exec sleep 30",
+            pid_file.display()
+        );
+        let err = StdioClient::connect("sh", &sh(&script)).await.err().unwrap();
+        assert!(format!("{:#}", err).contains("MCP error -32600: no"), "{:#}", err);
+        assert!(wait_exited(&pid_file).await);
+
+        // A server that never answers, abandoned on a start timeout
+        let pid_file = tmp.path().join("silent.pid");
+        let script = format!("echo $$ > {}; exec sleep 30", pid_file.display());
+        let started = tokio::time::timeout(Duration::from_millis(300), StdioClient::connect("sh", &sh(&script))).await;
+        assert!(started.is_err());
+        assert!(wait_exited(&pid_file).await);
+    }
+
+    #[tokio::test]
+    async fn test_stdio_client_request() {
+        // Answers initialize, skips the initialized notification, then answers one request
+        let script = r#"read line; echo '{"jsonrpc":"2.0","id":1,"result":{}}'; read line; read line
+echo '{"jsonrpc":"2.0","method":"notifications/message","params":{}}'
+echo '{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"t"}]}}'; read line"#;
+        let client = StdioClient::connect("sh", &sh(script)).await.unwrap();
+        let result = client.request("tools/list", json!({})).await.unwrap();
+        assert_eq!(result["tools"][0]["name"], "t");
+    }
+}
@@ -2,9 +2,17 @@ pub mod provider;
 pub mod registry;
 pub mod builtins;
 pub mod mcp;
+pub mod mcp_http;
+pub mod mcp_stdio;
+pub mod mcp_resources;
+pub mod resource;
+pub mod safe_mode;
+pub mod approval;
+pub mod network;
 
 use async_trait::async_trait;
 use std::path::{Path, PathBuf, Component};
+use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 
 /// Output from a tool execution.
@@ -14,6 +22,10 @@ pub struct ToolOutput {
     pub content: String,
     /// Structured metadata for logging and harness inspection
     pub metadata: Value,
+    /// Where the content came from, for citation and verification
+    pub sources: Vec<Source>,
+    /// Large results kept out of the context; the model sees only a handle
+    pub artifacts: Vec<Artifact>,
 }
 
 impl ToolOutput {
@@ -21,6 +33,141 @@ impl ToolOutput {
         Self {
             content,
             metadata: json!({}),
+            sources: Vec::new(),
+            artifacts: Vec::new(),
+        }
+    }
+
+    /// Attach a provenance record to this output.
+    pub fn with_source(mut self, source: Source) -> Self {
+        self.sources.push(source);
+        self
+    }
+
+    /// Attach a blob to be stored as an artifact instead of entering the context.
+    pub fn with_artifact(mut self, artifact: Artifact) -> Self {
+        self.artifacts.push(artifact);
+        self
+    }
+}
+
+/// Characters of a text artifact shown in its handle
+pub const ARTIFACT_PREVIEW_CHARS: usize = 200;
+
+/// A blob produced by a tool. The kernel stores it in the `artifacts` table
+/// and appends an [`ArtifactRef`] to the tool result in its place; the model
+/// reads it back piecewise with `fetch_artifact`.
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    /// Media type, e.g. `text/plain` or `application/json`
+    pub kind: String,
+    pub data: Vec<u8>,
+}
+
+impl Artifact {
+    pub fn text(kind: &str, text: impl Into<String>) -> Self {
+        Self { kind: kind.to_string(), data: text.into().into_bytes() }
+    }
+
+    /// Whether the data can be shown as text.
+    pub fn is_text(&self) -> bool {
+        is_text_kind(&self.kind)
+    }
+
+    /// The start of a text artifact, or a size note for binary data.
+    pub fn preview(&self) -> String {
+        if !self.is_text() {
+            return format!("(binary, {} bytes)", self.data.len());
+        }
+        let text = String::from_utf8_lossy(&self.data);
+        let mut preview: String = text.chars().take(ARTIFACT_PREVIEW_CHARS).collect();
+        if preview.len() < text.len() {
+            preview.push_str("...");
+        }
+        preview
+    }
+}
+
+/// Whether an artifact of this media type can be shown as text.
+pub fn is_text_kind(kind: &str) -> bool {
+    kind.starts_with("text/") || kind.ends_with("json") || kind.ends_with("xml")
+}
+
+/// Lightweight handle to a stored artifact, as shown to the model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArtifactRef {
+    pub id: String,
+    pub kind: String,
+    pub size: u64,
+    pub preview: String,
+}
+
+impl std::fmt::Display for ArtifactRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[artifact {}: {}, {} bytes; read it with fetch_artifact]\npreview: {}",
+            self.id, self.kind, self.size, self.preview
+        )
+    }
+}
+
+/// Provenance of information returned by a tool.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Source {
+    /// A web resource
+    Url {
+        url: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+    },
+    /// A file, as a path within the workspace
+    File { path: String },
+    /// A recalled memory and the session that stored it
+    Memory { session_id: String, memory_id: i64 },
+}
+
+impl Source {
+    /// Short kind label (`url`, `file`, `memory`), matching the serialized tag.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Source::Url { .. } => "url",
+            Source::File { .. } => "file",
+            Source::Memory { .. } => "memory",
+        }
+    }
+}
+
+/// A source attributed to the tool call that produced it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CitedSource {
+    pub tool_call_id: String,
+    pub tool_name: String,
+    pub source: Source,
+}
+
+/// What a tool can do to the world, for harnesses deciding what to allow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolEffect {
+    /// Reads the workspace or session state; changes nothing outside the session
+    ReadOnly,
+    /// Creates or modifies files in the workspace
+    Write,
+    /// Runs arbitrary commands or processes
+    Execute,
+    /// Not declared (e.g. MCP tools)
+    Unknown,
+}
+
+impl std::fmt::Display for ToolEffect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToolEffect::ReadOnly => write!(f, "read_only"),
+            ToolEffect::Write => write!(f, "write"),
+            ToolEffect::Execute => write!(f, "execute"),
+            ToolEffect::Unknown => write!(f, "unknown"),
         }
     }
 }
@@ -37,12 +184,33 @@ pub enum ToolError {
 }
 
 /// Context available to tools during execution.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ToolContext {
     /// Root directory for workspace-relative paths
     pub workspace_root: std::path::PathBuf,
     /// Current session ID
     pub session_id: String,
+    /// Hosts network tools may reach (`[tools.network]`)
+    pub network: std::sync::Arc<network::NetworkPolicy>,
+    /// Directories besides the workspace root that file tools may use (`tools.allowed_paths`)
+    pub allowed_paths: std::sync::Arc<Vec<PathBuf>>,
+    /// The call's arguments as the model sent them, before a harness
+    /// `MODIFY` (`None` outside a model's tool call)
+    pub original_args: Option<Value>,
+    /// When the call's timeout (`tools.timeout_ms`) runs out. Tools that
+    /// block instead of awaiting can't be cancelled, so they stop themselves
+    /// here
+    pub deadline: Option<std::time::Instant>,
+}
+
+impl ToolContext {
+    /// Resolve a path argument for a file tool: relative paths are taken
+    /// from the workspace root, symlinks are followed, and the result must
+    /// lie inside the workspace root or one of `allowed_paths`. Every file
+    /// builtin goes through here.
+    pub fn resolve_path(&self, path: impl AsRef<Path>) -> Result<PathBuf, ToolError> {
+        contain_path(&self.workspace_root, &self.allowed_paths, path.as_ref())
+    }
 }
 
 /// The Tool trait — every tool in Bedrock implements this.
@@ -66,6 +234,33 @@ pub trait Tool: Send + Sync {
         params: Value,
         ctx: &ToolContext,
     ) -> Result<ToolOutput, ToolError>;
+
+    /// Whether successful results may be cached across sessions.
+    ///
+    /// Only deterministic, side-effect-free tools should opt in. Cached
+    /// results are keyed by tool name and a hash of the arguments. Results
+    /// with artifacts are never cached, since their handles are per session.
+    fn cacheable_globally(&self) -> bool {
+        false
+    }
+
+    /// External resource this tool uses, e.g. `"github-api"` or `"main-db"`.
+    ///
+    /// Tools with the same key share a concurrency limit (see
+    /// `tools.resource_limits`), so they never exceed it together even when
+    /// called in parallel under different names.
+    fn resource_key(&self) -> Option<&str> {
+        None
+    }
+    /// What the tool can do, as reported to harnesses by `bedrock.list_tools()`.
+    fn effect(&self) -> ToolEffect {
+        ToolEffect::Unknown
+    }
+
+    /// The MCP server this tool is proxied from, if any.
+    fn server(&self) -> Option<&str> {
+        None
+    }
 }
 
 /// Helper to deserialize tool arguments from a JSON Value.
@@ -73,8 +268,28 @@ pub fn parse_args<T: serde::de::DeserializeOwned>(args: Value) -> Result<T, Tool
     serde_json::from_value(args).map_err(|e| ToolError::InvalidParams(e.to_string()))
 }
 
+/// Stable hash of tool arguments, used as a cache key.
+///
+/// `serde_json` keeps object keys sorted, so equal arguments always
+/// serialize (and therefore hash) identically.
+pub fn args_hash(args: &Value) -> String {
+    use sha2::{Digest, Sha256};
+    let canonical = serde_json::to_string(args).unwrap_or_default();
+    format!("{:x}", Sha256::digest(canonical.as_bytes()))
+}
+
 /// Centralized path validation to prevent traversal attacks.
+///
+/// Returns where `path` (absolute, or relative to `root`) really points,
+/// with symlinks resolved, if that is inside `root`.
 pub fn is_safe_path(root: &Path, path: &Path) -> Result<PathBuf, ToolError> {
+    contain_path(root, &[], path)
+}
+
+/// Like [`is_safe_path`], also accepting paths under any of the `allowed`
+/// directories (absolute, or relative to `root`). Directories that don't
+/// exist are ignored.
+pub fn contain_path(root: &Path, allowed: &[PathBuf], path: &Path) -> Result<PathBuf, ToolError> {
     // 1. Resolve to absolute-ish path within root
     let resolved = if path.is_absolute() {
         path.to_path_buf()
@@ -95,27 +310,49 @@ pub fn is_safe_path(root: &Path, path: &Path) -> Result<PathBuf, ToolError> {
         ToolError::ExecutionError(format!("Failed to canonicalize workspace root: {}", e))
     })?;
 
-    // 4. Resolve the target path's existing ancestor to check against root
-    let mut current = resolved.clone();
-    while !current.exists() {
-        if let Some(parent) = current.parent() {
-            current = parent.to_path_buf();
-        } else {
-            break;
-        }
+    // 4. Check where the path really leads, through any symlinks
+    let target = real_path(&resolved)?;
+    let inside = target.starts_with(&canonical_root)
+        || allowed
+            .iter()
+            .filter_map(|dir| root.join(dir).canonicalize().ok())
+            .any(|dir| target.starts_with(dir));
+    if !inside {
+        return Err(ToolError::PermissionDenied(format!(
+            "Path '{}' is outside workspace root{}",
+            path.display(),
+            if allowed.is_empty() { "" } else { " and tools.allowed_paths" }
+        )));
     }
 
-    let canonical_current = current.canonicalize().map_err(|e| {
-        ToolError::ExecutionError(format!("Failed to canonicalize path: {}", e))
-    })?;
+    Ok(target)
+}
 
-    if !canonical_current.starts_with(&canonical_root) {
-        return Err(ToolError::PermissionDenied(format!(
-            "Path '{}' is outside workspace root",
-            path.display()
-        )));
+/// `path` with every symlink resolved, including when the last components
+/// don't exist yet (a file about to be created).
+fn real_path(path: &Path) -> Result<PathBuf, ToolError> {
+    // The longest prefix that exists; `symlink_metadata` counts a dangling
+    // symlink as existing, so writes can't follow one out of the workspace
+    let mut existing = path.to_path_buf();
+    let mut missing = Vec::new();
+    while std::fs::symlink_metadata(&existing).is_err() {
+        match (existing.file_name(), existing.parent()) {
+            (Some(name), Some(parent)) => {
+                missing.push(name.to_os_string());
+                existing = parent.to_path_buf();
+            }
+            _ => break,
+        }
     }
 
-    Ok(resolved)
+    let mut real = existing.canonicalize().map_err(|e| {
+        if existing.is_symlink() {
+            ToolError::PermissionDenied(format!("'{}' is a symlink to a missing target", existing.display()))
+        } else {
+            ToolError::ExecutionError(format!("Failed to canonicalize path: {}", e))
+        }
+    })?;
+    real.extend(missing.iter().rev());
+    Ok(real)
 }
 
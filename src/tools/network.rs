@@ -0,0 +1,161 @@
+//! Which hosts network tools may reach (`[tools.network]`).
+//!
+//! A domain pattern matches the domain itself and its subdomains, so
+//! `github.com` also covers `api.github.com`; `*` wildcards match any run of
+//! characters. `deny_domains` wins over `allow_domains`, and an empty
+//! `allow_domains` allows every host not denied. Loopback, private and
+//! link-local addresses (including `localhost` and cloud metadata endpoints)
+//! are refused unless `allow_domains` names them explicitly.
+//!
+//! The policy travels with every call in [`ToolContext::network`](super::ToolContext),
+//! so any tool that opens connections checks the same lists. Names are also
+//! checked after DNS resolution, by [`PolicyResolver`], so a public name
+//! pointing at a private address is refused too.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use super::approval::wildcard_match;
+use super::ToolError;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NetworkPolicy {
+    /// Hosts network tools may reach (empty = any host not denied)
+    #[serde(default)]
+    pub allow_domains: Vec<String>,
+    /// Hosts network tools never reach, checked before `allow_domains`
+    #[serde(default)]
+    pub deny_domains: Vec<String>,
+}
+
+impl NetworkPolicy {
+    /// Refuse `host` unless the policy lets network tools reach it.
+    pub fn check_host(&self, host: &str) -> Result<(), ToolError> {
+        let host = host.trim_start_matches('[').trim_end_matches(']').trim_end_matches('.').to_ascii_lowercase();
+        if let Some(pattern) = self.deny_domains.iter().find(|p| domain_matches(p, &host)) {
+            return Err(ToolError::PermissionDenied(format!("{} is denied by tools.network.deny_domains ('{}')", host, pattern)));
+        }
+        let allowed = self.allow_domains.iter().any(|p| domain_matches(p, &host));
+        if !allowed && !self.allow_domains.is_empty() {
+            return Err(ToolError::PermissionDenied(format!("{} is not in tools.network.allow_domains", host)));
+        }
+        if !allowed && is_local(&host) {
+            return Err(ToolError::PermissionDenied(format!(
+                "{} is a local or private address; list it in tools.network.allow_domains to reach it",
+                host
+            )));
+        }
+        Ok(())
+    }
+
+    /// Refuse `addr`, an address `host` resolved to, if it is local or
+    /// private and `allow_domains` doesn't name `host`.
+    pub fn check_resolved(&self, host: &str, addr: IpAddr) -> Result<(), ToolError> {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        if is_local_ip(addr) && !self.allow_domains.iter().any(|p| domain_matches(p, &host)) {
+            return Err(ToolError::PermissionDenied(format!(
+                "{} resolves to the local or private address {}; list it in tools.network.allow_domains to reach it",
+                host, addr
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A `reqwest` DNS resolver that checks every address a name resolves to
+/// against the policy.
+pub struct PolicyResolver {
+    policy: Arc<NetworkPolicy>,
+}
+
+impl PolicyResolver {
+    pub fn new(policy: Arc<NetworkPolicy>) -> Self {
+        Self { policy }
+    }
+}
+
+impl reqwest::dns::Resolve for PolicyResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let policy = self.policy.clone();
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0)).await?.collect();
+            for addr in &addrs {
+                policy.check_resolved(host, addr.ip())?;
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// Whether `pattern` covers `host` or a domain `host` is under.
+pub fn domain_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.trim().trim_end_matches('.').to_ascii_lowercase();
+    wildcard_match(&pattern, host) || host.strip_suffix(pattern.as_str()).is_some_and(|sub| sub.ends_with('.'))
+}
+
+fn is_local(host: &str) -> bool {
+    host == "localhost" || host.ends_with(".localhost") || host.parse().is_ok_and(is_local_ip)
+}
+
+fn is_local_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified(),
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // Unique local (fc00::/7) and link-local (fe80::/10)
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80
+                || ip.to_ipv4_mapped().is_some_and(|v4| is_local_ip(IpAddr::V4(v4)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_host() {
+        let open = NetworkPolicy::default();
+        assert!(open.check_host("example.com").is_ok());
+        for local in ["localhost", "127.0.0.1", "10.1.2.3", "169.254.169.254", "[::1]", "::ffff:192.168.0.1"] {
+            assert!(open.check_host(local).is_err(), "{} should be refused", local);
+        }
+
+        let policy = NetworkPolicy {
+            allow_domains: vec!["github.com".into(), "*.rust-lang.org".into(), "127.0.0.1".into()],
+            deny_domains: vec!["gist.github.com".into()],
+        };
+        assert!(policy.check_host("github.com").is_ok());
+        assert!(policy.check_host("API.GitHub.com").is_ok());
+        assert!(policy.check_host("docs.rust-lang.org").is_ok());
+        assert!(policy.check_host("127.0.0.1").is_ok());
+        assert!(policy.check_host("notgithub.com").is_err());
+        assert!(policy.check_host("example.com").is_err());
+        let denied = policy.check_host("gist.github.com").unwrap_err();
+        assert!(denied.to_string().contains("deny_domains"));
+    }
+
+    #[tokio::test]
+    async fn test_resolver_checks_addresses() {
+        use reqwest::dns::Resolve;
+        let name = || "localhost".parse::<reqwest::dns::Name>().unwrap();
+
+        let open = PolicyResolver::new(Arc::new(NetworkPolicy::default()));
+        let refused = open.resolve(name()).await.err().unwrap();
+        assert!(refused.to_string().contains("resolves to the local or private address"));
+
+        let allowed = NetworkPolicy { allow_domains: vec!["localhost".into()], ..Default::default() };
+        let addrs: Vec<_> = PolicyResolver::new(Arc::new(allowed)).resolve(name()).await.unwrap().collect();
+        assert!(addrs.iter().all(|addr| addr.ip().is_loopback()));
+
+        let public = NetworkPolicy::default();
+        assert!(public.check_resolved("example.com", "93.184.215.14".parse().unwrap()).is_ok());
+        assert!(public.check_resolved("evil.example", "10.0.0.5".parse().unwrap()).is_err());
+    }
+}
@@ -1,6 +1,123 @@
-use std::collections::BTreeMap;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 
-use super::{Tool, ToolContext};
+use super::safe_mode::SharedSafeMode;
+use super::{Tool, ToolContext, ToolEffect};
+
+/// Where a tool was registered from. Used to resolve name clashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolSource {
+    /// Registered by a harness script
+    Harness,
+    /// Compiled into bedrock
+    Builtin,
+    /// Proxied from an MCP server
+    Mcp,
+}
+
+impl std::fmt::Display for ToolSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToolSource::Harness => write!(f, "harness"),
+            ToolSource::Builtin => write!(f, "builtin"),
+            ToolSource::Mcp => write!(f, "mcp"),
+        }
+    }
+}
+
+/// Default clash precedence, highest first.
+pub const DEFAULT_PRECEDENCE: [ToolSource; 3] = [ToolSource::Harness, ToolSource::Builtin, ToolSource::Mcp];
+
+/// What to do when same-named tools from different sources take different
+/// parameters, so the schema the model sees may not match the tool that runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaConflictPolicy {
+    /// Refuse to register the tool
+    #[default]
+    Error,
+    /// Resolve by precedence, as for identical schemas
+    Precedence,
+}
+
+/// A name clash resolved at registration: `winner` stays active, `loser` is dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadowedTool {
+    pub name: String,
+    pub winner: ToolSource,
+    pub loser: ToolSource,
+}
+
+/// Read-only description of a registered tool, as exposed to harnesses.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolDescriptor {
+    pub name: String,
+    pub description: String,
+    /// JSON Schema for the tool's parameters
+    pub parameters: serde_json::Value,
+    pub effect: ToolEffect,
+    pub source: ToolSource,
+    /// MCP server the tool is proxied from
+    pub server: Option<String>,
+    /// The tool's own name, when `name` is an alias from `tools.aliases`
+    pub alias_of: Option<String>,
+    /// Refused until unlocked (safe mode only)
+    pub locked: bool,
+}
+
+impl ToolDescriptor {
+    /// Where the tool comes from: `builtin`, `harness`, or `mcp:<server>`.
+    pub fn origin(&self) -> String {
+        match &self.server {
+            Some(server) => format!("{}:{}", self.source, server),
+            None => self.source.to_string(),
+        }
+    }
+}
+
+/// A tool offered under an alias; calls go to the tool itself.
+struct AliasedTool {
+    alias: String,
+    tool: Arc<dyn Tool>,
+}
+
+#[async_trait]
+impl Tool for AliasedTool {
+    fn name(&self) -> &str {
+        &self.alias
+    }
+
+    fn description(&self) -> &str {
+        self.tool.description()
+    }
+
+    fn parameters_schema(&self) -> serde_json::Value {
+        self.tool.parameters_schema()
+    }
+
+    async fn execute(&self, params: serde_json::Value, ctx: &ToolContext) -> Result<super::ToolOutput, super::ToolError> {
+        self.tool.execute(params, ctx).await
+    }
+
+    fn cacheable_globally(&self) -> bool {
+        self.tool.cacheable_globally()
+    }
+
+    fn resource_key(&self) -> Option<&str> {
+        self.tool.resource_key()
+    }
+
+    fn effect(&self) -> ToolEffect {
+        self.tool.effect()
+    }
+
+    fn server(&self) -> Option<&str> {
+        self.tool.server()
+    }
+}
 
 /// Central registry of available tools.
 ///
@@ -8,30 +125,182 @@ use super::{Tool, ToolContext};
 /// - Tool lookup by name
 /// - JSON schema generation for LLM tool definitions
 /// - Tool execution dispatch
+///
+/// When tools from different sources share a name, the one whose source comes
+/// first in the precedence order wins (harness > builtin > mcp by default).
+/// If their parameter schemas differ, registration fails instead, unless the
+/// schema conflict policy or an override for that name allows it.
+///
+/// Tools named in the aliases (see [`Self::set_aliases`]) are offered under
+/// their alias instead of their own name, whenever they are registered.
+///
+/// Cloning is cheap: tool instances are shared, so a clone is a snapshot of
+/// the current tool set.
+#[derive(Clone)]
 pub struct ToolRegistry {
-    tools: BTreeMap<String, Box<dyn Tool>>,
+    tools: BTreeMap<String, (ToolSource, Arc<dyn Tool>)>,
+    precedence: Vec<ToolSource>,
+    schema_conflicts: SchemaConflictPolicy,
+    /// Names resolved by precedence even when their schemas differ
+    schema_overrides: Vec<String>,
+    /// Alias by tool name
+    aliases: BTreeMap<String, String>,
+    /// Set in safe mode; shared with clones so runtime unlocks apply to snapshots
+    safe_mode: Option<SharedSafeMode>,
 }
 
 impl ToolRegistry {
     pub fn new() -> Self {
         Self {
             tools: BTreeMap::new(),
+            precedence: DEFAULT_PRECEDENCE.to_vec(),
+            schema_conflicts: SchemaConflictPolicy::default(),
+            schema_overrides: Vec::new(),
+            aliases: BTreeMap::new(),
+            safe_mode: None,
+        }
+    }
+
+    /// Set the clash precedence, highest first. Sources not listed rank lowest.
+    pub fn set_precedence(&mut self, precedence: Vec<ToolSource>) {
+        self.precedence = precedence;
+    }
+
+    /// Set how clashes with differing schemas are handled, and the tool names
+    /// exempt from [`SchemaConflictPolicy::Error`].
+    pub fn set_schema_conflicts(&mut self, policy: SchemaConflictPolicy, overrides: Vec<String>) {
+        self.schema_conflicts = policy;
+        self.schema_overrides = overrides;
+    }
+
+    /// Offer tools under other names, given as alias -> tool name (as in
+    /// `tools.aliases`). Tools already registered are renamed; the rest are
+    /// renamed as they register. An alias clashing with another tool is
+    /// resolved like any other name clash.
+    pub fn set_aliases(&mut self, aliases: &HashMap<String, String>) -> anyhow::Result<()> {
+        self.aliases = aliases.iter().map(|(alias, name)| (name.clone(), alias.clone())).collect();
+        let renamed: Vec<String> = self.tools.keys().filter(|name| self.aliases.contains_key(*name)).cloned().collect();
+        for name in renamed {
+            if let Some((source, tool)) = self.tools.remove(&name) {
+                self.insert(source, self.aliased(tool))?;
+            }
         }
+        Ok(())
+    }
+
+    /// The name a tool called `name` is offered under: its alias, if it has one.
+    pub fn exposed_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.aliases.get(name).map_or(name, String::as_str)
     }
 
-    /// Register a tool. Returns error if a tool with the same name is already registered.
+    /// The tool's own name for a name it is offered under: `name` itself
+    /// unless `name` is an alias.
+    pub fn canonical_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.aliases.iter().find(|(_, alias)| *alias == name).map_or(name, |(tool, _)| tool.as_str())
+    }
+
+    fn aliased(&self, tool: Arc<dyn Tool>) -> Arc<dyn Tool> {
+        match self.aliases.get(tool.name()) {
+            Some(alias) => Arc::new(AliasedTool { alias: alias.clone(), tool }),
+            None => tool,
+        }
+    }
+
+    /// Enable safe mode: tools that aren't read-only fail with
+    /// `PermissionDenied` unless `safe_mode` has them unlocked.
+    pub fn set_safe_mode(&mut self, safe_mode: SharedSafeMode) {
+        self.safe_mode = Some(safe_mode);
+    }
+
+    /// The safe-mode state, if safe mode is on.
+    pub fn safe_mode(&self) -> Option<&SharedSafeMode> {
+        self.safe_mode.as_ref()
+    }
+
+    /// Whether safe mode currently refuses the tool with this name.
+    pub fn is_locked(&self, name: &str) -> bool {
+        match (&self.safe_mode, self.get(name)) {
+            (Some(safe_mode), Some(tool)) => safe_mode.read().unwrap_or_else(|e| e.into_inner()).is_locked(name, tool.effect()),
+            _ => false,
+        }
+    }
+
+    /// Check whether registering `tool` from `source` would clash with a
+    /// same-named tool whose parameter schema differs. The error names both
+    /// sources and shows both schemas.
+    pub fn check_schema_conflict(&self, source: ToolSource, tool: &dyn Tool) -> anyhow::Result<()> {
+        let name = self.exposed_name(tool.name());
+        let Some((existing_source, existing)) = self.tools.get(name) else {
+            return Ok(());
+        };
+        if *existing_source == source
+            || self.schema_conflicts == SchemaConflictPolicy::Precedence
+            || self.schema_overrides.iter().any(|n| n == name)
+        {
+            return Ok(());
+        }
+        let (ours, theirs) = (existing.parameters_schema(), tool.parameters_schema());
+        if ours == theirs {
+            return Ok(());
+        }
+        anyhow::bail!(
+            "Tool '{name}' from {source} has a different schema than the {existing_source} tool of the same name\n  \
+             {existing_source}: {ours}\n  \
+             {source}: {theirs}\n\
+             Disambiguate with tools.namespace_mcp or tools.aliases, list '{name}' in tools.schema_overrides, \
+             or set tools.on_schema_conflict = \"precedence\"",
+        )
+    }
+
+    fn rank(&self, source: ToolSource) -> usize {
+        self.precedence.iter().position(|s| *s == source).unwrap_or(self.precedence.len())
+    }
+
+    /// Register a builtin tool. Returns error if a tool with the same name is already registered.
     pub fn register(&mut self, tool: Box<dyn Tool>) -> anyhow::Result<()> {
+        self.register_from(ToolSource::Builtin, tool).map(|_| ())
+    }
+
+    /// Register a tool from the given source.
+    ///
+    /// A clash with a tool from the *same* source is an error. A clash across
+    /// sources is resolved by precedence and reported in the return value so the
+    /// caller can warn about the shadowed tool, unless the schemas differ (see
+    /// [`Self::check_schema_conflict`]).
+    pub fn register_from(&mut self, source: ToolSource, tool: Box<dyn Tool>) -> anyhow::Result<Option<ShadowedTool>> {
+        let tool = self.aliased(Arc::from(tool));
+        self.insert(source, tool)
+    }
+
+    fn insert(&mut self, source: ToolSource, tool: Arc<dyn Tool>) -> anyhow::Result<Option<ShadowedTool>> {
+        self.check_schema_conflict(source, tool.as_ref())?;
         let name = tool.name().to_string();
-        if self.tools.contains_key(&name) {
+        let existing = match self.tools.get(&name) {
+            None => {
+                self.tools.insert(name, (source, tool));
+                return Ok(None);
+            }
+            Some((existing, _)) => *existing,
+        };
+        if existing == source {
             anyhow::bail!("Tool '{}' already registered", name);
         }
-        self.tools.insert(name, tool);
-        Ok(())
+        if self.rank(source) < self.rank(existing) {
+            self.tools.insert(name.clone(), (source, tool));
+            Ok(Some(ShadowedTool { name, winner: source, loser: existing }))
+        } else {
+            Ok(Some(ShadowedTool { name, winner: existing, loser: source }))
+        }
+    }
+
+    /// The source of the active tool with this name.
+    pub fn source(&self, name: &str) -> Option<ToolSource> {
+        self.tools.get(name).map(|(source, _)| *source)
     }
 
     /// Get a tool by name.
     pub fn get(&self, name: &str) -> Option<&dyn Tool> {
-        self.tools.get(name).map(|t| t.as_ref())
+        self.tools.get(name).map(|(_, t)| t.as_ref())
     }
 
     /// Generate JSON tool definitions for the LLM API.
@@ -43,7 +312,7 @@ impl ToolRegistry {
     pub fn tool_definitions(&self) -> Vec<serde_json::Value> {
         self.tools
             .values()
-            .map(|tool| {
+            .map(|(_, tool)| {
                 serde_json::json!({
                     "name": tool.name(),
                     "description": tool.description(),
@@ -53,6 +322,23 @@ impl ToolRegistry {
             .collect()
     }
 
+    /// Describe every active tool, sorted by name.
+    pub fn descriptors(&self) -> Vec<ToolDescriptor> {
+        self.tools
+            .values()
+            .map(|(source, tool)| ToolDescriptor {
+                name: tool.name().to_string(),
+                description: tool.description().to_string(),
+                parameters: tool.parameters_schema(),
+                effect: tool.effect(),
+                source: *source,
+                server: tool.server().map(str::to_string),
+                alias_of: Some(self.canonical_name(tool.name())).filter(|name| *name != tool.name()).map(str::to_string),
+                locked: self.is_locked(tool.name()),
+            })
+            .collect()
+    }
+
     /// Execute a tool by name with the given arguments.
     pub async fn execute(
         &self,
@@ -63,9 +349,36 @@ impl ToolRegistry {
         let tool = self
             .get(name)
             .ok_or_else(|| super::ToolError::ExecutionError(format!("Unknown tool: {}", name)))?;
+        if self.is_locked(name) {
+            return Err(super::ToolError::PermissionDenied(format!(
+                "'{}' ({} tool) is locked in safe mode; it runs only if unlocked with --allow {} or bedrock.unlock_tool",
+                name,
+                tool.effect(),
+                name
+            )));
+        }
         tool.execute(args, ctx).await
     }
 
+    /// A snapshot holding only the named tools. Unknown names are an error.
+    pub fn subset(&self, names: &[String]) -> anyhow::Result<Self> {
+        let unknown: Vec<&str> = names.iter().filter(|n| !self.tools.contains_key(*n)).map(String::as_str).collect();
+        anyhow::ensure!(unknown.is_empty(), "Unknown tools: {}", unknown.join(", "));
+        Ok(Self {
+            tools: self.tools.iter().filter(|(name, _)| names.contains(name)).map(|(k, v)| (k.clone(), v.clone())).collect(),
+            precedence: self.precedence.clone(),
+            schema_conflicts: self.schema_conflicts,
+            schema_overrides: self.schema_overrides.clone(),
+            aliases: self.aliases.clone(),
+            safe_mode: self.safe_mode.clone(),
+        })
+    }
+
+    /// Unregister a tool. Returns whether it was registered.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.tools.remove(name).is_some()
+    }
+
     /// Get the number of registered tools.
     pub fn len(&self) -> usize {
         self.tools.len()
@@ -108,6 +421,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_subset_and_remove() {
+        let mut registry = builtins::create_default_registry();
+        let subset = registry.subset(&["read_file".to_string(), "shell_exec".to_string()]).unwrap();
+        assert_eq!(subset.len(), 2);
+        assert!(subset.get("write_file").is_none());
+        assert!(registry.subset(&["nope".to_string()]).is_err());
+
+        assert!(registry.remove("delegate"));
+        assert!(!registry.remove("delegate"));
+        assert!(registry.get("delegate").is_none());
+    }
+
+    #[test]
+    fn test_descriptors() {
+        let registry = builtins::create_default_registry();
+        let descriptors = registry.descriptors();
+        assert_eq!(descriptors.len(), registry.len());
+        let shell = descriptors.iter().find(|d| d.name == "shell_exec").unwrap();
+        assert_eq!(shell.effect, ToolEffect::Execute);
+        assert_eq!(shell.source, ToolSource::Builtin);
+        assert_eq!(shell.parameters, registry.get("shell_exec").unwrap().parameters_schema());
+        assert_eq!(descriptors.iter().find(|d| d.name == "read_file").unwrap().effect, ToolEffect::ReadOnly);
+    }
+
     #[test]
     #[should_panic(expected = "already registered")]
     fn test_duplicate_registration_panics() {
@@ -115,4 +453,124 @@ mod tests {
         registry.register(Box::new(builtins::ReadFileTool)).unwrap();
         registry.register(Box::new(builtins::ReadFileTool)).unwrap(); // should panic
     }
+
+    #[test]
+    fn test_clash_resolved_by_default_precedence() {
+        let mut registry = ToolRegistry::new();
+        registry.register(Box::new(builtins::ReadFileTool)).unwrap();
+
+        // MCP loses to builtin
+        let shadowed = registry.register_from(ToolSource::Mcp, Box::new(builtins::ReadFileTool)).unwrap();
+        assert_eq!(shadowed, Some(ShadowedTool { name: "read_file".into(), winner: ToolSource::Builtin, loser: ToolSource::Mcp }));
+        assert_eq!(registry.source("read_file"), Some(ToolSource::Builtin));
+
+        // Harness beats builtin
+        let shadowed = registry.register_from(ToolSource::Harness, Box::new(builtins::ReadFileTool)).unwrap();
+        assert_eq!(shadowed.unwrap().loser, ToolSource::Builtin);
+        assert_eq!(registry.source("read_file"), Some(ToolSource::Harness));
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn test_clash_resolved_by_custom_precedence() {
+        let mut registry = ToolRegistry::new();
+        registry.set_precedence(vec![ToolSource::Mcp, ToolSource::Builtin]);
+        registry.register(Box::new(builtins::ReadFileTool)).unwrap();
+
+        let shadowed = registry.register_from(ToolSource::Mcp, Box::new(builtins::ReadFileTool)).unwrap();
+        assert_eq!(shadowed.unwrap().winner, ToolSource::Mcp);
+        assert_eq!(registry.source("read_file"), Some(ToolSource::Mcp));
+
+        // Unlisted sources rank lowest
+        let shadowed = registry.register_from(ToolSource::Harness, Box::new(builtins::ReadFileTool)).unwrap();
+        assert_eq!(shadowed.unwrap().loser, ToolSource::Harness);
+    }
+
+    /// A `read_file` that takes different parameters than the builtin.
+    struct OtherReadFile;
+
+    #[async_trait::async_trait]
+    impl Tool for OtherReadFile {
+        fn name(&self) -> &str {
+            "read_file"
+        }
+
+        fn description(&self) -> &str {
+            "Read a file by URI"
+        }
+
+        fn parameters_schema(&self) -> serde_json::Value {
+            serde_json::json!({ "type": "object", "properties": { "uri": { "type": "string" } } })
+        }
+
+        async fn execute(&self, _params: serde_json::Value, _ctx: &ToolContext) -> Result<super::super::ToolOutput, super::super::ToolError> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_schema_conflict() {
+        let mut registry = ToolRegistry::new();
+        registry.register(Box::new(builtins::ReadFileTool)).unwrap();
+
+        let err = registry.register_from(ToolSource::Mcp, Box::new(OtherReadFile)).unwrap_err().to_string();
+        assert!(err.contains("'read_file' from mcp") && err.contains("builtin: {"), "{}", err);
+        assert!(err.contains("\"uri\"") && err.contains("\"path\""), "{}", err);
+        assert_eq!(registry.source("read_file"), Some(ToolSource::Builtin));
+
+        // An override for the name falls back to precedence
+        registry.set_schema_conflicts(SchemaConflictPolicy::Error, vec!["read_file".to_string()]);
+        assert!(registry.register_from(ToolSource::Mcp, Box::new(OtherReadFile)).unwrap().is_some());
+
+        let mut registry = ToolRegistry::new();
+        registry.set_schema_conflicts(SchemaConflictPolicy::Precedence, Vec::new());
+        registry.register(Box::new(builtins::ReadFileTool)).unwrap();
+        let shadowed = registry.register_from(ToolSource::Harness, Box::new(OtherReadFile)).unwrap();
+        assert_eq!(shadowed.unwrap().winner, ToolSource::Harness);
+    }
+
+    #[tokio::test]
+    async fn test_aliases() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "hello").unwrap();
+        let ctx = ToolContext { workspace_root: dir.path().to_path_buf(), session_id: "s".to_string(), ..Default::default() };
+        let mut registry = ToolRegistry::new();
+        registry.register(Box::new(builtins::ReadFileTool)).unwrap();
+        registry.set_aliases(&HashMap::from([("cat".to_string(), "read_file".to_string())])).unwrap();
+
+        // Renamed when aliases are set, and called by the alias
+        assert!(registry.get("read_file").is_none());
+        assert_eq!(registry.exposed_name("read_file"), "cat");
+        let output = registry.execute("cat", serde_json::json!({ "path": "a.txt" }), &ctx).await.unwrap();
+        assert!(output.content.contains("hello"));
+        let descriptor = registry.descriptors().into_iter().find(|d| d.name == "cat").unwrap();
+        assert_eq!(descriptor.alias_of.as_deref(), Some("read_file"));
+        assert_eq!(descriptor.origin(), "builtin");
+
+        // A later tool under the aliased name clashes with the alias
+        let err = registry.register_from(ToolSource::Mcp, Box::new(OtherReadFile)).unwrap_err().to_string();
+        assert!(err.contains("'cat' from mcp"), "{}", err);
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_safe_mode_locks_until_unlocked() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = ToolContext { workspace_root: dir.path().to_path_buf(), session_id: "s".to_string(), ..Default::default() };
+        let mut registry = builtins::create_default_registry();
+        let safe_mode = super::super::safe_mode::SafeMode::new(Vec::new()).shared();
+        registry.set_safe_mode(safe_mode.clone());
+        let snapshot = registry.subset(&["write_file".to_string(), "read_file".to_string()]).unwrap();
+
+        let args = serde_json::json!({ "path": "a.txt", "content": "hi" });
+        let err = snapshot.execute("write_file", args.clone(), &ctx).await.unwrap_err();
+        assert!(matches!(err, super::super::ToolError::PermissionDenied(_)), "{}", err);
+        assert!(registry.descriptors().iter().find(|d| d.name == "write_file").unwrap().locked);
+        assert!(!registry.descriptors().iter().find(|d| d.name == "read_file").unwrap().locked);
+
+        // Unlocking through the shared state applies to existing snapshots
+        safe_mode.write().unwrap().unlock("write_file");
+        snapshot.execute("write_file", args, &ctx).await.unwrap();
+        assert!(dir.path().join("a.txt").exists());
+    }
 }
@@ -0,0 +1,68 @@
+//! Concurrency limits for tools that share an external resource.
+//!
+//! A tool that declares a [`Tool::resource_key`](super::Tool::resource_key)
+//! holds a permit for that key while it executes, so tools touching the same
+//! database or rate-limited API serialize even when their names differ. The
+//! number of permits per key comes from `tools.resource_limits` and defaults
+//! to 1.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Permits for a key missing from `tools.resource_limits`.
+pub const DEFAULT_RESOURCE_LIMIT: usize = 1;
+
+/// One semaphore per resource key, created on first use.
+#[derive(Debug, Default)]
+pub struct ResourceLimiter {
+    limits: HashMap<String, usize>,
+    semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl ResourceLimiter {
+    pub fn new(limits: HashMap<String, usize>) -> Self {
+        Self { limits, semaphores: Mutex::new(HashMap::new()) }
+    }
+
+    /// Wait for a permit on `key`. Tools without a resource key run
+    /// unrestricted and get `None`.
+    pub async fn acquire(&self, key: Option<&str>) -> Option<OwnedSemaphorePermit> {
+        let key = key?;
+        let semaphore = {
+            let mut semaphores = self.semaphores.lock().unwrap_or_else(|e| e.into_inner());
+            semaphores
+                .entry(key.to_string())
+                .or_insert_with(|| {
+                    let limit = self.limits.get(key).copied().unwrap_or(DEFAULT_RESOURCE_LIMIT);
+                    Arc::new(Semaphore::new(limit))
+                })
+                .clone()
+        };
+        // The semaphore is never closed, so acquiring only fails if that changes
+        semaphore.acquire_owned().await.ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_permits_per_key() {
+        let limiter = ResourceLimiter::new(HashMap::from([("wide".to_string(), 2)]));
+        assert!(limiter.acquire(None).await.is_none());
+
+        let held = limiter.acquire(Some("db")).await.unwrap();
+        // A declared key defaults to one permit
+        let blocked = tokio::time::timeout(std::time::Duration::from_millis(20), limiter.acquire(Some("db"))).await;
+        assert!(blocked.is_err());
+        drop(held);
+        assert!(limiter.acquire(Some("db")).await.is_some());
+
+        let _a = limiter.acquire(Some("wide")).await.unwrap();
+        let _b = limiter.acquire(Some("wide")).await.unwrap();
+        let blocked = tokio::time::timeout(std::time::Duration::from_millis(20), limiter.acquire(Some("wide"))).await;
+        assert!(blocked.is_err());
+    }
+}
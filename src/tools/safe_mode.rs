@@ -0,0 +1,74 @@
+//! Deny-by-default tool gating for `--safe` runs.
+//!
+//! In safe mode only [`ToolEffect::ReadOnly`] tools run. Everything else
+//! (writes, command execution, and undeclared effects such as MCP tools) is
+//! locked and fails with `PermissionDenied` until it is unlocked by name,
+//! either up front with `tools.allow` / `--allow <tool>` or at runtime by a
+//! harness calling `bedrock.unlock_tool(name)`.
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, RwLock};
+
+use super::ToolEffect;
+
+/// Safe-mode state shared by a registry, its snapshots and the harness.
+pub type SharedSafeMode = Arc<RwLock<SafeMode>>;
+
+/// Tools unlocked in safe mode.
+#[derive(Debug, Default)]
+pub struct SafeMode {
+    unlocked: BTreeSet<String>,
+    /// Unlocked since the kernel last reported them
+    pending: Vec<String>,
+}
+
+impl SafeMode {
+    /// Safe mode with `allow` unlocked up front.
+    pub fn new(allow: impl IntoIterator<Item = String>) -> Self {
+        Self { unlocked: allow.into_iter().collect(), pending: Vec::new() }
+    }
+
+    pub fn shared(self) -> SharedSafeMode {
+        Arc::new(RwLock::new(self))
+    }
+
+    /// Whether a tool named `name` with `effect` may not run.
+    pub fn is_locked(&self, name: &str, effect: ToolEffect) -> bool {
+        effect != ToolEffect::ReadOnly && !self.unlocked.contains(name)
+    }
+
+    /// Unlock `name`. Returns false if it already was.
+    pub fn unlock(&mut self, name: &str) -> bool {
+        let added = self.unlocked.insert(name.to_string());
+        if added {
+            self.pending.push(name.to_string());
+        }
+        added
+    }
+
+    /// Tools unlocked at runtime since the last call, in unlock order.
+    pub fn take_unlocked(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locks_all_but_read_only() {
+        let mut safe = SafeMode::new(["write_file".to_string()]);
+        assert!(!safe.is_locked("read_file", ToolEffect::ReadOnly));
+        assert!(!safe.is_locked("write_file", ToolEffect::Write));
+        assert!(safe.is_locked("shell_exec", ToolEffect::Execute));
+        assert!(safe.is_locked("github__create_issue", ToolEffect::Unknown));
+
+        assert!(safe.unlock("shell_exec"));
+        assert!(!safe.unlock("shell_exec"));
+        assert!(!safe.is_locked("shell_exec", ToolEffect::Execute));
+        // Only runtime unlocks are reported, once
+        assert_eq!(safe.take_unlocked(), vec!["shell_exec"]);
+        assert!(safe.take_unlocked().is_empty());
+    }
+}
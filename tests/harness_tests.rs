@@ -65,6 +65,10 @@ async fn test_harness_rejection() -> Result<()> {
         kind: "mock".to_string(),
         api_key_env: None,
         base_url: None,
+        auth: None,
+        tool_results: Default::default(),
+        extra_body: None,
+        strip_thinking: true,
     });
 
     let config = BedrockConfig {
@@ -73,14 +77,20 @@ async fn test_harness_rejection() -> Result<()> {
             provider: "mock".to_string(),
             system_prompt: "You are a test assistant.".to_string(),
             thinking: None,
+            inject_plan: true,
+            prompt_template: Default::default(),
+            postprocessors: Vec::new(),
+            fallbacks: Vec::new(),
         },
         kernel: bedrock::kernel::config::KernelConfig {
             workspace_root: tmp.path().to_str().unwrap().to_string(),
             max_turns: 5,
             heartbeat_interval_secs: 30,
+            ..Default::default()
         },
         persistence: PersistenceConfig {
             database_path: db_path.to_str().unwrap().to_string(),
+            ..Default::default()
         },
         harness: HarnessConfig {
             directory: harness_dir.to_str().unwrap().to_string(),
@@ -88,6 +98,17 @@ async fn test_harness_rejection() -> Result<()> {
         },
         providers,
         embeddings: Some(EmbeddingConfig::NoOp),
+        tools: Default::default(),
+        repl: Default::default(),
+        memory: Default::default(),
+        maintenance: Default::default(),
+        credentials: Default::default(),
+        mcp: Default::default(),
+        inference: Default::default(),
+        compaction: Default::default(),
+        limits: Default::default(),
+        server: Default::default(),
+        pricing: Default::default(),
     };
 
     let mut kernel = Kernel::builder(config).build()?;
@@ -1,10 +1,170 @@
 use anyhow::Result;
-use bedrock::kernel::config::{BedrockConfig, ProviderConfig, AgentConfig, PersistenceConfig, HarnessConfig, EmbeddingConfig};
+use bedrock::kernel::config::{BedrockConfig, ProviderConfig, AgentConfig, PersistenceConfig, HarnessConfig, EmbeddingConfig, EagerDispatch, FallbackTarget, LoopMode, Postprocessor, QueueEmptyPolicy};
 use bedrock::kernel::Kernel;
-use bedrock::kernel::session::SessionState;
+use bedrock::kernel::event::KernelEvent;
+use bedrock::persistence::state::{EventRow, PlanStatus, StateStore, TurnCostRow};
+use bedrock::kernel::session::{SessionState, StopReason};
+use bedrock::inference::pricing::ModelPricing;
+use bedrock::inference::provider::{
+    InferenceEvent, InferenceProvider, InferenceRequest, InferenceContent, InferenceMessage, InferenceRole, SdkError,
+    InferenceStream, RequestOptions, ProviderClient, ProviderKind,
+};
+use bedrock::tools::{Artifact, Tool, ToolContext, ToolError, ToolOutput};
+use bedrock::tools::registry::ToolRegistry;
+use futures::future::BoxFuture;
+use futures::stream;
 use std::collections::HashMap;
+use std::sync::Arc;
 use tempfile::tempdir;
 
+/// One response of a [`ScriptedProvider`].
+#[derive(Debug, Clone, Default)]
+struct Reply {
+    thinking: Vec<String>,
+    text: String,
+    /// (id, name, args)
+    tool_calls: Vec<(String, String, serde_json::Value)>,
+    input_tokens: u32,
+    output_tokens: u32,
+    stop_reason: Option<String>,
+    /// Fail the request with this error instead of streaming
+    error: Option<String>,
+    /// Stream only this error event
+    stream_error: Option<String>,
+    /// Never end the message after the text
+    stall: bool,
+    /// Wait this long before ending the message
+    end_delay_ms: u64,
+}
+
+impl Reply {
+    fn text(text: &str) -> Self {
+        Self { text: text.to_string(), input_tokens: 10, output_tokens: 5, ..Default::default() }
+    }
+
+    /// Calls each tool in one response, with ids `call-0`, `call-1`, ...
+    fn calls<'a>(calls: impl IntoIterator<Item = (&'a str, serde_json::Value)>) -> Self {
+        let tool_calls = calls.into_iter().enumerate().map(|(i, (name, args))| (format!("call-{}", i), name.to_string(), args)).collect();
+        Self { tool_calls, ..Self::text("") }
+    }
+
+    fn call(name: &str, args: serde_json::Value) -> Self {
+        Self::calls([(name, args)])
+    }
+
+    fn error(message: &str) -> Self {
+        Self { error: Some(message.to_string()), ..Default::default() }
+    }
+
+    fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.tool_calls[0].0 = id.into();
+        self
+    }
+
+    fn with_usage(mut self, input_tokens: u32, output_tokens: u32) -> Self {
+        (self.input_tokens, self.output_tokens) = (input_tokens, output_tokens);
+        self
+    }
+
+    fn with_stop_reason(mut self, stop_reason: &str) -> Self {
+        self.stop_reason = Some(stop_reason.to_string());
+        self
+    }
+
+    fn events(self, model: String) -> InferenceStream {
+        if let Some(message) = self.stream_error {
+            return Box::pin(stream::iter(vec![Ok(InferenceEvent::Error { message })]));
+        }
+        let mut events = vec![Ok(InferenceEvent::MessageStart {
+            role: "assistant".to_string(),
+            model,
+            provider_id: "mock".to_string(),
+        })];
+        events.extend(self.thinking.into_iter().map(|content| Ok(InferenceEvent::ThinkingDelta { content })));
+        if !self.text.is_empty() {
+            events.push(Ok(InferenceEvent::MessageDelta { content: self.text }));
+        }
+        for (id, name, args) in self.tool_calls {
+            events.push(Ok(InferenceEvent::ToolCall { id, name, args }));
+        }
+        if self.stall {
+            return Box::pin(futures::StreamExt::chain(stream::iter(events), stream::pending()));
+        }
+        let end = InferenceEvent::MessageEnd {
+            input_tokens: self.input_tokens,
+            output_tokens: self.output_tokens,
+            stop_reason: self.stop_reason,
+        };
+        let delay = std::time::Duration::from_millis(self.end_delay_ms);
+        let end = stream::once(async move {
+            tokio::time::sleep(delay).await;
+            Ok(end)
+        });
+        Box::pin(futures::StreamExt::chain(stream::iter(events), end))
+    }
+}
+
+type Script = dyn Fn(usize, &InferenceRequest) -> Reply + Send + Sync;
+
+/// Mock provider that answers each request from a script and records the
+/// requests it was sent.
+struct ScriptedProvider {
+    script: Box<Script>,
+    calls: std::sync::atomic::AtomicUsize,
+    requests: std::sync::Mutex<Vec<InferenceRequest>>,
+}
+
+impl ScriptedProvider {
+    /// Answers with `replies` in order, repeating the last once they run out.
+    fn replies(replies: Vec<Reply>) -> Arc<Self> {
+        Self::new(move |call, _| replies[call.min(replies.len() - 1)].clone())
+    }
+
+    /// Answers from `script(call_index, request)`.
+    fn new(script: impl Fn(usize, &InferenceRequest) -> Reply + Send + Sync + 'static) -> Arc<Self> {
+        Arc::new(Self { script: Box::new(script), calls: Default::default(), requests: Default::default() })
+    }
+
+    fn client(self: &Arc<Self>) -> ProviderClient {
+        ProviderClient::new(ProviderKind::Mock, self.clone())
+    }
+
+    fn requests(&self) -> Vec<InferenceRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl InferenceProvider for ScriptedProvider {
+    fn stream<'a>(&'a self, request: InferenceRequest, _options: Option<RequestOptions>) -> BoxFuture<'a, std::result::Result<InferenceStream, SdkError>> {
+        let call = self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let reply = (self.script)(call, &request);
+        let model = request.model.clone();
+        self.requests.lock().unwrap().push(request);
+        Box::pin(async move {
+            match reply.error.clone() {
+                Some(message) => Err(SdkError::Api(message)),
+                None => Ok(reply.events(model)),
+            }
+        })
+    }
+}
+
+/// Reads `notes.txt`, then answers "Done".
+fn read_notes_provider() -> Arc<ScriptedProvider> {
+    ScriptedProvider::replies(vec![Reply::call("read_file", serde_json::json!({ "path": "notes.txt" })), Reply::text("Done")])
+}
+
+/// Always answers with the same text.
+fn text_provider(text: &str) -> Arc<ScriptedProvider> {
+    ScriptedProvider::replies(vec![Reply::text(text)])
+}
+
+/// Calls each of `tools` at once, then answers "done".
+fn parallel_tools_provider(tools: &[&str]) -> Arc<ScriptedProvider> {
+    let first = Reply::calls(tools.iter().map(|name| (*name, serde_json::json!({}))));
+    ScriptedProvider::replies(vec![first, Reply::text("done")])
+}
+
 #[tokio::test]
 async fn test_agent_loop_basic_flow() -> Result<()> {
     let tmp = tempdir()?;
@@ -17,6 +177,10 @@ async fn test_agent_loop_basic_flow() -> Result<()> {
         kind: "mock".to_string(),
         api_key_env: None,
         base_url: Some("Mock response content".to_string()),
+        auth: None,
+        tool_results: Default::default(),
+        extra_body: None,
+        strip_thinking: true,
     });
 
     let config = BedrockConfig {
@@ -25,14 +189,20 @@ async fn test_agent_loop_basic_flow() -> Result<()> {
             provider: "mock".to_string(),
             system_prompt: "You are a test assistant.".to_string(),
             thinking: None,
+            inject_plan: true,
+            prompt_template: Default::default(),
+            postprocessors: Vec::new(),
+            fallbacks: Vec::new(),
         },
         kernel: bedrock::kernel::config::KernelConfig {
             workspace_root: tmp.path().to_str().unwrap().to_string(),
             max_turns: 5,
             heartbeat_interval_secs: 30,
+            ..Default::default()
         },
         persistence: PersistenceConfig {
             database_path: db_path.to_str().unwrap().to_string(),
+            ..Default::default()
         },
         harness: HarnessConfig {
             directory: harness_dir.to_str().unwrap().to_string(),
@@ -40,11 +210,22 @@ async fn test_agent_loop_basic_flow() -> Result<()> {
         },
         providers,
         embeddings: Some(EmbeddingConfig::NoOp),
+        tools: Default::default(),
+        repl: Default::default(),
+        memory: Default::default(),
+        maintenance: Default::default(),
+        credentials: Default::default(),
+        mcp: Default::default(),
+        inference: Default::default(),
+        compaction: Default::default(),
+        limits: Default::default(),
+        server: Default::default(),
+        pricing: Default::default(),
     };
 
     let mut kernel = Kernel::builder(config).build()?;
     kernel.init_state().await?;
-    kernel.init_clients()?;
+    kernel.init_clients().await?;
     kernel.init_harness().await?;
 
     let mut session = kernel.create_session();
@@ -69,3 +250,3277 @@ async fn test_agent_loop_basic_flow() -> Result<()> {
     
     Ok(())
 }
+
+/// Reads `missing-0.txt` .. `missing-{calls - 1}.txt` in every response.
+fn missing_files_provider(calls: usize) -> Arc<ScriptedProvider> {
+    let paths: Vec<_> = (0..calls).map(|i| format!("missing-{}.txt", i)).collect();
+    ScriptedProvider::replies(vec![Reply::calls(paths.iter().map(|path| ("read_file", serde_json::json!({ "path": path }))))])
+}
+
+#[tokio::test]
+async fn test_max_tool_calls_per_turn_truncates() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.kernel.max_turns = 1;
+    config.kernel.max_tool_calls_per_turn = Some(1);
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), missing_files_provider(3).client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Read everything".to_string())).await?;
+
+    let results: Vec<(String, String)> = session.history.iter()
+        .flat_map(|m| m.content.iter())
+        .filter_map(|c| match c {
+            InferenceContent::ToolResult { tool_use_id, content, .. } => Some((tool_use_id.clone(), content.clone())),
+            _ => None,
+        })
+        .collect();
+
+    // Every tool call gets a result, but only the first one was executed
+    assert_eq!(results.len(), 3);
+    let truncated: Vec<_> = results.iter().filter(|(_, c)| c.contains("[TOOL CALL LIMIT]")).collect();
+    assert_eq!(truncated.len(), 2);
+    assert!(truncated.iter().all(|(id, _)| id != "call-0"));
+
+    kernel.end_session(&mut session).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_subscribe_receives_session_events() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.kernel.max_turns = 1;
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    // No state store: subscribers still get every event
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.add_client("mock".to_string(), missing_files_provider(1).client());
+    kernel.init_harness().await?;
+
+    let mut events = kernel.subscribe();
+    let mut session = kernel.create_session();
+    let id = session.id.clone();
+    kernel.run(&mut session, Some("Read a file".to_string())).await?;
+    kernel.end_session(&mut session).await?;
+    session.flush_events(std::time::Duration::from_secs(5)).await;
+
+    let mut received = Vec::new();
+    while let Ok((session_id, event)) = events.try_recv() {
+        assert_eq!(session_id, id);
+        received.push(event.event_type().to_string());
+    }
+    let position = |name: &str| received.iter().position(|t| t == name).unwrap_or_else(|| panic!("no {} in {:?}", name, received));
+    assert!(position("turn_start") < position("tool_call"));
+    assert!(position("tool_call") < position("tool_exec_start"));
+    assert!(position("tool_exec_start") < position("tool_exec_end"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_on_session_end_hook_runs_and_errors_are_contained() -> Result<()> {
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(
+        harness_dir.join("a_cleanup.lua"),
+        r#"
+        function on_session_end(stats)
+            fs.write("session_end.txt", stats.stop_reason .. ":" .. tostring(stats.turns))
+            return ALLOW
+        end
+        "#,
+    )?;
+    std::fs::write(
+        harness_dir.join("b_broken.lua"),
+        r#"
+        function on_session_end(stats)
+            error("cleanup failed")
+        end
+        "#,
+    )?;
+
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), missing_files_provider(0).client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Hello".to_string())).await?;
+    assert_eq!(session.stop_reason, Some(StopReason::Completed));
+
+    // A failing on_session_end hook must not fail end_session
+    kernel.end_session(&mut session).await?;
+
+    let written = std::fs::read_to_string(tmp.path().join("session_end.txt"))?;
+    assert_eq!(written, format!("completed:{}", session.turn_index));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_deterministic_tool_ids() -> Result<()> {
+    let tmp = tempdir()?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.kernel.max_turns = 1;
+    config.kernel.deterministic_tool_ids = true;
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), missing_files_provider(3).client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Read everything".to_string())).await?;
+    kernel.end_session(&mut session).await?;
+    let session_id = session.id.clone();
+    drop(kernel);
+
+    let expected = vec!["call_0_0", "call_0_1", "call_0_2"];
+    let result_ids: Vec<String> = session.history.iter()
+        .flat_map(|m| m.content.iter())
+        .filter_map(|c| match c {
+            InferenceContent::ToolResult { tool_use_id, .. } => Some(tool_use_id.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(result_ids, expected);
+
+    // Executions are recorded in call order with the synthetic ids
+    let store = StateStore::open(&db_path).await?;
+    let exec_ids: Vec<String> = store.get_tool_executions(&session_id).await?
+        .into_iter()
+        .map(|e| e.tool_call_id)
+        .collect();
+    assert_eq!(exec_ids, expected);
+    Ok(())
+}
+
+async fn run_flaky(empty_responses: usize, max_turn_retries: u32) -> Result<(SessionState, Vec<String>)> {
+    let tmp = tempdir()?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.kernel.max_turn_retries = max_turn_retries;
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    // Empty responses, then text
+    let provider = ScriptedProvider::new(move |call, _| Reply::text(if call < empty_responses { "" } else { "Recovered" }));
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), provider.client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Hello".to_string())).await?;
+    kernel.end_session(&mut session).await?;
+    kernel.flush_events(&mut session).await;
+    drop(kernel);
+
+    let store = StateStore::open(&db_path).await?;
+    let event_types = store.get_events(&session.id).await?.into_iter().map(|e| e.event_type).collect();
+    Ok((session, event_types))
+}
+
+#[tokio::test]
+async fn test_turn_retried_on_empty_response() -> Result<()> {
+    let (session, events) = run_flaky(2, 3).await?;
+    assert_eq!(events.iter().filter(|e| *e == "turn_retried").count(), 2);
+    assert!(!events.iter().any(|e| e == "turn_failed"));
+
+    let last = session.history.last().unwrap();
+    assert!(matches!(&last.content[0], InferenceContent::Text { text } if text == "Recovered"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_turn_failed_when_retries_exhausted() -> Result<()> {
+    let (_, events) = run_flaky(5, 1).await?;
+    assert_eq!(events.iter().filter(|e| *e == "turn_retried").count(), 1);
+    assert_eq!(events.iter().filter(|e| *e == "turn_failed").count(), 1);
+    Ok(())
+}
+
+/// Requests a tool call and reports a million input tokens per response.
+fn costly_provider() -> Arc<ScriptedProvider> {
+    let reply = Reply { text: "Partial answer".to_string(), ..Reply::call("read_file", serde_json::json!({ "path": "missing.txt" })) };
+    ScriptedProvider::replies(vec![reply.with_usage(1_000_000, 0)])
+}
+
+async fn run_with_cost_budget(budget: f64, prompts: &[&str]) -> Result<SessionState> {
+    let mut config = BedrockConfig::default();
+    config.kernel.cost_budget_usd = Some(budget);
+    Ok(run_costly(config, prompts).await?.0)
+}
+
+/// Runs `prompts` against `costly_provider`, returning the session, its
+/// events and its recorded turn costs.
+async fn run_costly(mut config: BedrockConfig, prompts: &[&str]) -> Result<(SessionState, Vec<EventRow>, Vec<TurnCostRow>)> {
+    let tmp = tempdir()?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.agent.model = "claude-sonnet-4-20250514".to_string();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), costly_provider().client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    for prompt in prompts {
+        kernel.queue_prompt(&session, prompt.to_string()).await;
+    }
+    kernel.run(&mut session, None).await?;
+    kernel.flush_events(&mut session).await;
+    drop(kernel);
+    let store = StateStore::open(&db_path).await?;
+    let events = store.get_events(&session.id).await?;
+    let costs = store.get_turn_costs(&session.id).await?;
+    Ok((session, events, costs))
+}
+
+/// Asserts every tool call in `history` is answered by a result in the next message.
+fn assert_tool_calls_answered(history: &[InferenceMessage]) {
+    for (i, message) in history.iter().enumerate() {
+        for content in &message.content {
+            let InferenceContent::ToolUse { id, .. } = content else { continue };
+            let answered = history.get(i + 1).is_some_and(|next| next.content.iter().any(|c| matches!(c, InferenceContent::ToolResult { tool_use_id, .. } if tool_use_id == id)));
+            assert!(answered, "tool call {} has no result", id);
+        }
+    }
+}
+
+/// Sends one more prompt on `session` from a kernel without budgets or
+/// limits, returning the request the provider received.
+async fn continue_session(session: &mut SessionState) -> Result<InferenceRequest> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    let provider = text_provider("Carried on");
+    kernel.add_client("mock".to_string(), provider.client());
+    kernel.init_harness().await?;
+    kernel.flush_events(session).await;
+    kernel.run(session, Some("Carry on".to_string())).await?;
+    Ok(provider.requests().pop().unwrap())
+}
+
+#[tokio::test]
+async fn test_token_limits() -> Result<()> {
+    // Each response reports a million input tokens and calls a tool, so the run keeps going
+    let mut config = BedrockConfig::default();
+    config.limits.max_input_tokens = Some(2_500_000);
+    config.limits.warn_at = vec![0.5, 0.75];
+    let (session, events, _) = run_costly(config, &["First", "Second"]).await?;
+    assert_eq!(session.stop_reason, Some(StopReason::LimitExceeded));
+    assert_eq!(session.total_input_tokens, 3_000_000);
+    assert_eq!(session.queue.lock().await.len(), 1);
+
+    let payloads = |kind: &str| -> Vec<serde_json::Value> {
+        events.iter().filter(|e| e.event_type == kind).map(|e| serde_json::from_str(&e.payload).unwrap()).collect()
+    };
+    let warnings = payloads("budget_warning");
+    assert_eq!(warnings.len(), 2);
+    assert_eq!((warnings[0]["fraction"].as_f64(), warnings[0]["used"].as_f64()), (Some(0.5), Some(2_000_000.0)));
+    assert_eq!(warnings[1]["fraction"], 0.75);
+    let exceeded = payloads("limit_exceeded");
+    assert_eq!(exceeded.len(), 1);
+    assert_eq!((&exceeded[0]["limit"], &exceeded[0]["used"], &exceeded[0]["max"]), (&serde_json::json!("max_input_tokens"), &serde_json::json!(3_000_000), &serde_json::json!(2_500_000)));
+
+    // The cost cap under [limits] behaves like kernel.cost_budget_usd and warns first
+    let mut config = BedrockConfig::default();
+    config.limits.max_cost_usd = Some(4.0);
+    let (session, events, _) = run_costly(config, &["First"]).await?;
+    assert_eq!(session.stop_reason, Some(StopReason::CostBudgetExceeded));
+    assert!(events.iter().any(|e| e.event_type == "budget_warning" && e.payload.contains("max_cost_usd")));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_turn_costs_with_pricing_override() -> Result<()> {
+    // $1 per response instead of the built-in $3, so the $2.50 cap is crossed by the third
+    let mut config = BedrockConfig::default();
+    config.limits.max_cost_usd = Some(2.5);
+    config.pricing.insert(
+        "mock/claude-sonnet-4".to_string(),
+        ModelPricing { input_per_mtok: 1.0, output_per_mtok: 0.0 },
+    );
+    let (session, _, costs) = run_costly(config, &["First"]).await?;
+    assert_eq!(session.stop_reason, Some(StopReason::CostBudgetExceeded));
+    assert!((session.total_cost_usd - 3.0).abs() < 1e-9);
+
+    assert_eq!(costs.len(), 3);
+    assert_eq!(costs.iter().map(|c| c.turn_index).collect::<Vec<_>>(), vec![0, 1, 2]);
+    assert!(costs.iter().all(|c| c.provider == "mock" && c.model == "claude-sonnet-4-20250514"));
+    assert!(costs.iter().all(|c| c.input_tokens == 1_000_000 && c.cost_usd == Some(1.0)));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cost_budget_aborts_after_response() -> Result<()> {
+    // The pre-check estimate is well under $1; the response itself costs $3
+    let session = run_with_cost_budget(1.0, &["First", "Second"]).await?;
+    assert_eq!(session.stop_reason, Some(StopReason::CostBudgetExceeded));
+    assert!((session.total_cost_usd - 3.0).abs() < 1e-9);
+
+    // The partial response is kept, its tool call is answered without running,
+    // and the queue is not drained
+    assert_eq!(session.history.len(), 3);
+    assert!(matches!(&session.history[1].content[0], InferenceContent::Text { text } if text == "Partial answer"));
+    assert!(matches!(
+        &session.history[2].content[0],
+        InferenceContent::ToolResult { content, is_error: true, .. } if content.starts_with("[BUDGET EXCEEDED] Tool 'read_file' was not executed")
+    ));
+    assert_tool_calls_answered(&session.history);
+    assert_eq!(session.queue.lock().await.len(), 1);
+
+    // The history is still one a provider accepts
+    let mut session = session;
+    let request = continue_session(&mut session).await?;
+    assert_tool_calls_answered(&request.messages);
+    assert_eq!(session.final_answer().as_deref(), Some("Carried on"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_output_token_limit_answers_pending_tool_calls() -> Result<()> {
+    // The response calling a tool crosses the output cap
+    let tmp = tempdir()?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.limits.max_output_tokens = Some(100);
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    let calls = Reply::calls([("read_file", serde_json::json!({ "path": "a.txt" })), ("read_file", serde_json::json!({ "path": "b.txt" }))]);
+    kernel.add_client("mock".to_string(), ScriptedProvider::replies(vec![calls.with_usage(10, 500)]).client());
+    kernel.init_harness().await?;
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Read both".to_string())).await?;
+    kernel.flush_events(&mut session).await;
+    drop(kernel);
+
+    assert_eq!(session.stop_reason, Some(StopReason::LimitExceeded));
+    assert_eq!(session.history.len(), 3);
+    assert_tool_calls_answered(&session.history);
+    assert!(session.history[2].content.iter().all(|c| matches!(
+        c, InferenceContent::ToolResult { content, is_error: true, .. } if content.starts_with("[LIMIT EXCEEDED]")
+    )));
+    let executions = StateStore::open(&db_path).await?.get_tool_executions(&session.id).await?;
+    assert_eq!(executions.len(), 2);
+    assert!(executions.iter().all(|e| e.verdict == "limit_exceeded"));
+
+    let request = continue_session(&mut session).await?;
+    assert_tool_calls_answered(&request.messages);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cost_budget_refuses_request_estimated_over_budget() -> Result<()> {
+    let session = run_with_cost_budget(0.001, &["Hello"]).await?;
+    assert_eq!(session.stop_reason, Some(StopReason::CostBudgetExceeded));
+    assert_eq!(session.total_cost_usd, 0.0);
+    // Only the user message: nothing was sent
+    assert_eq!(session.history.len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tool_sources_recorded() -> Result<()> {
+    let tmp = tempdir()?;
+    std::fs::write(tmp.path().join("notes.txt"), "remember the milk")?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), read_notes_provider().client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Read my notes".to_string())).await?;
+    kernel.flush_events(&mut session).await;
+    drop(kernel);
+
+    let store = StateStore::open(&db_path).await?;
+    let sources = store.get_tool_sources(&session.id, Some(0)).await?;
+    assert_eq!(sources.len(), 1);
+    assert_eq!(sources[0].tool_call_id, "call-0");
+    assert_eq!(sources[0].kind, "file");
+    let source: serde_json::Value = serde_json::from_str(&sources[0].source)?;
+    assert_eq!(source, serde_json::json!({ "kind": "file", "path": "notes.txt" }));
+
+    let events = store.get_events(&session.id).await?;
+    assert!(events.iter().any(|e| e.event_type == "sources_collected"));
+    Ok(())
+}
+
+// Orchestration primitives block on async work via block_in_place
+#[tokio::test(flavor = "multi_thread")]
+async fn test_orchestrator_mode_harness_drives_loop() -> Result<()> {
+    let tmp = tempdir()?;
+    std::fs::write(tmp.path().join("notes.txt"), "remember the milk")?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(harness_dir.join("loop.lua"), r#"
+function on_orchestrate(event)
+    bedrock.append_message({ role = "user", content = {{ type = "text", text = event.prompt }} })
+    for _ = 1, 4 do
+        local reply = bedrock.call_model()
+        local content = {}
+        if reply.text ~= "" then
+            table.insert(content, { type = "text", text = reply.text })
+        end
+        for _, call in reply.tool_calls do
+            table.insert(content, { type = "tool_use", id = call.id, name = call.name, input = call.args })
+        end
+        bedrock.append_message({ role = "assistant", content = content })
+        if #reply.tool_calls == 0 then
+            return ALLOW
+        end
+
+        local results = {}
+        for _, call in reply.tool_calls do
+            local out = bedrock.run_tool(call.name, call.args, call.id)
+            table.insert(results, { type = "tool_result", tool_use_id = call.id, content = "[orchestrated] " .. out.content, is_error = out.is_error })
+        end
+        bedrock.append_message({ role = "user", content = results })
+    end
+    return REJECT, "too many steps"
+end
+"#)?;
+
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.kernel.mode = LoopMode::Orchestrator;
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), read_notes_provider().client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Read my notes".to_string())).await?;
+    assert_ne!(session.stop_reason, Some(StopReason::HarnessRejected));
+
+    // user, assistant (tool call), user (tool result), assistant (text)
+    assert_eq!(session.history.len(), 4);
+    match &session.history[2].content[0] {
+        InferenceContent::ToolResult { content, is_error, .. } => {
+            assert!(content.starts_with("[orchestrated] "));
+            assert!(content.contains("remember the milk"));
+            assert!(!is_error);
+        }
+        other => panic!("expected tool result, got {:?}", other),
+    }
+    assert_eq!(session.total_input_tokens, 20);
+    assert_eq!(session.turn_index, 1);
+    drop(kernel);
+
+    let store = StateStore::open(&db_path).await?;
+    let roles: Vec<String> = store.get_messages(&session.id).await?.into_iter().map(|m| m.role).collect();
+    assert_eq!(roles, vec!["user", "assistant", "tool_result", "assistant"]);
+    let stats = store.tool_stats(&Default::default()).await?;
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].tool_name, "read_file");
+    Ok(())
+}
+
+/// Runs an orchestrator script that calls the model until refused, against `costly_provider`.
+async fn run_orchestrated_costly(mut config: BedrockConfig) -> Result<(SessionState, Vec<EventRow>)> {
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(harness_dir.join("loop.lua"), r#"
+function on_orchestrate(event)
+    for _ = 1, 10 do
+        bedrock.call_model(nil, { tools = false })
+    end
+    return ALLOW
+end
+"#)?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.agent.model = "claude-sonnet-4-20250514".to_string();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.kernel.mode = LoopMode::Orchestrator;
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), costly_provider().client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Go".to_string())).await?;
+    kernel.flush_events(&mut session).await;
+    drop(kernel);
+    let events = StateStore::open(&db_path).await?.get_events(&session.id).await?;
+    Ok((session, events))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_orchestrator_mode_enforces_run_limits() -> Result<()> {
+    let mut config = BedrockConfig::default();
+    config.kernel.max_turns = 2;
+    let (session, _) = run_orchestrated_costly(config).await?;
+    assert_eq!(session.stop_reason, Some(StopReason::MaxTurns));
+    assert_eq!(session.total_input_tokens, 2_000_000);
+
+    // $3 per call: the second crosses $4, so the third is refused
+    let mut config = BedrockConfig::default();
+    config.kernel.cost_budget_usd = Some(4.0);
+    let (session, events) = run_orchestrated_costly(config).await?;
+    assert_eq!(session.stop_reason, Some(StopReason::CostBudgetExceeded));
+    assert!((session.total_cost_usd - 6.0).abs() < 1e-9);
+    assert!(events.iter().any(|e| e.event_type == "cost_budget_exceeded"));
+    Ok(())
+}
+
+/// Streams one delta and then stalls on its first call; answers normally afterwards.
+fn stalling_provider() -> Arc<ScriptedProvider> {
+    ScriptedProvider::replies(vec![Reply { stall: true, ..Reply::text("Working on it") }, Reply::text("Done")])
+}
+
+#[tokio::test]
+async fn test_interrupt_stops_turn_and_keeps_session() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    let provider = stalling_provider();
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.add_client("mock".to_string(), provider.client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    session.queue.lock().await.push_back("Queued while busy".to_string());
+    let interrupt = kernel.interrupt_handle();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        interrupt.interrupt();
+    });
+    kernel.run(&mut session, Some("Long task".to_string())).await?;
+
+    assert_eq!(session.stop_reason, Some(StopReason::Interrupted));
+    assert_eq!(session.history.len(), 2);
+    match &session.history[1].content[0] {
+        InferenceContent::Text { text } => assert_eq!(text, "Working on it"),
+        other => panic!("expected partial text, got {:?}", other),
+    }
+    // Input queued behind the interrupted task is kept for the next run
+    assert_eq!(session.queue.lock().await.len(), 1);
+
+    kernel.run(&mut session, None).await?;
+    assert_ne!(session.stop_reason, Some(StopReason::Interrupted));
+    assert_eq!(session.history.len(), 4);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_abort_ends_run_with_aborted_event() -> Result<()> {
+    let tmp = tempdir()?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = db_path.clone();
+    config.persistence.snapshot_interval_turns = 10;
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    let provider = stalling_provider();
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), provider.client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    let handle = kernel.interrupt_handle();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        handle.abort();
+    });
+    kernel.run(&mut session, Some("Long task".to_string())).await?;
+    assert_eq!(session.stop_reason, Some(StopReason::Aborted));
+    kernel.end_session(&mut session).await?;
+    let session_id = session.id.clone();
+    session.flush_events(std::time::Duration::from_secs(5)).await;
+
+    let store = StateStore::open(&db_path).await?;
+    let types: Vec<String> = store.get_events(&session_id).await?.into_iter().map(|e| e.event_type).collect();
+    let interrupted = types.iter().position(|t| t == "turn_interrupted").unwrap();
+    let aborted = types.iter().position(|t| t == "aborted").unwrap();
+    assert!(interrupted < aborted, "{:?}", types);
+    // The aborted session was snapshotted even though no interval was reached
+    assert!(store.get_snapshot(&session_id).await?.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_abort_before_run_stops_it() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    let provider = text_provider("Done");
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.add_client("mock".to_string(), provider.client());
+    kernel.init_harness().await?;
+
+    // E.g. the client went away while the run was being set up
+    let mut session = kernel.create_session();
+    kernel.interrupt_handle().abort();
+    kernel.run(&mut session, Some("Task".to_string())).await?;
+    assert_eq!(session.stop_reason, Some(StopReason::Aborted));
+    assert!(provider.requests().is_empty());
+
+    // The abort ended with that run
+    kernel.run(&mut session, Some("Task".to_string())).await?;
+    assert_eq!(session.stop_reason, Some(StopReason::Completed));
+    assert_eq!(provider.requests().len(), 1);
+    Ok(())
+}
+
+// The harness records the prompt via db.kv_set, which blocks via block_in_place
+#[tokio::test(flavor = "multi_thread")]
+async fn test_update_plan_persists_and_is_injected() -> Result<()> {
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(harness_dir.join("spy.lua"), r#"
+function on_before_inference(ctx)
+    db.kv_set("last_system_prompt", ctx:get_system_prompt())
+    return ALLOW
+end
+"#)?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+
+    // Sets a two-step plan, then answers with text
+    let plan = serde_json::json!({ "steps": [
+        { "title": "Investigate", "status": "in_progress" },
+        { "title": "Fix" }
+    ]});
+    let provider = ScriptedProvider::replies(vec![Reply::call("update_plan", plan), Reply::text("Investigating")]);
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), provider.client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Fix the bug".to_string())).await?;
+    kernel.flush_events(&mut session).await;
+    drop(kernel);
+
+    let store = StateStore::open(&db_path).await?;
+    let plan = store.get_plan(&session.id).await?;
+    assert_eq!(plan.len(), 2);
+    assert_eq!(plan[0].status, PlanStatus::InProgress);
+    assert_eq!(plan[1].status, PlanStatus::Pending);
+
+    let prompt = store.kv_get("last_system_prompt").await?.unwrap();
+    assert!(prompt.contains("## Current plan\n1. [~] Investigate\n2. [ ] Fix"), "{}", prompt);
+
+    let events = store.get_events(&session.id).await?;
+    assert!(events.iter().any(|e| e.event_type == "plan_updated"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_query_events_returns_session_log() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+
+    // Answers with text, except on its second call where it queries the event log
+    let query = Reply::call("query_events", serde_json::json!({ "event_types": ["turn_start"], "limit": 5 }));
+    let provider = ScriptedProvider::replies(vec![Reply::text("Done"), query, Reply::text("Done")]);
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), provider.client());
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Hello".to_string())).await?;
+    // Events are written in the background; let the first run's land
+    kernel.flush_events(&mut session).await;
+    kernel.run(&mut session, Some("What happened so far?".to_string())).await?;
+
+    let result = session.history.iter()
+        .flat_map(|m| m.content.iter())
+        .find_map(|c| match c {
+            InferenceContent::ToolResult { tool_use_id, content, is_error } if tool_use_id == "call-0" => Some((content.clone(), *is_error)),
+            _ => None,
+        })
+        .expect("query_events result");
+    assert!(!result.1, "{}", result.0);
+    assert!(result.0.contains("turn_start: {\"turn_index\":0"), "{}", result.0);
+    assert!(!result.0.contains("tool_call"), "{}", result.0);
+    Ok(())
+}
+
+async fn run_overflowing(max_messages: usize) -> Result<(SessionState, Result<()>)> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+
+    let mut kernel = Kernel::builder(config).build()?;
+    // Rejects requests with more than `max_messages` messages as too long
+    let provider = ScriptedProvider::new(move |_, request| match request.messages.len() > max_messages {
+        true => Reply::error("prompt is too long: 210000 tokens > 200000 maximum"),
+        false => Reply::text("Fits now"),
+    });
+    kernel.add_client("mock".to_string(), provider.client());
+
+    let mut session = kernel.create_session();
+    for i in 0..3 {
+        session.history.push(InferenceMessage {
+            role: InferenceRole::User,
+            content: vec![InferenceContent::Text { text: format!("question {}", i) }],
+            tool_call_id: None,
+        });
+        session.history.push(InferenceMessage {
+            role: InferenceRole::Assistant,
+            content: vec![InferenceContent::Text { text: format!("answer {}", i) }],
+            tool_call_id: None,
+        });
+    }
+    let result = kernel.run(&mut session, Some("Next".to_string())).await;
+    Ok((session, result))
+}
+
+#[tokio::test]
+async fn test_context_overflow_trims_and_retries() -> Result<()> {
+    // 7 messages are too many; dropping the two oldest turns leaves 3
+    let (session, result) = run_overflowing(3).await?;
+    result?;
+    match &session.history[0].content[0] {
+        InferenceContent::Text { text } => assert_eq!(text, "question 2"),
+        other => panic!("expected the newest earlier turn, got {:?}", other),
+    }
+    assert!(matches!(&session.history.last().unwrap().content[0], InferenceContent::Text { text } if text == "Fits now"));
+
+    // Only one retry: a request that still overflows fails clearly
+    let (_, result) = run_overflowing(1).await?;
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains("still exceeds the provider's context window"), "{}", err);
+    Ok(())
+}
+
+/// Cuts its first answer off at the output limit; later calls finish it.
+fn truncating_provider() -> Arc<ScriptedProvider> {
+    ScriptedProvider::replies(vec![
+        Reply::text("Hello, wor").with_stop_reason("length"),
+        Reply::text("ld!").with_stop_reason("end_turn"),
+    ])
+}
+
+/// Runs one prompt against `truncating_provider`. Returns the session, the
+/// message count of each request and the persisted assistant finish reason.
+async fn run_truncating(max_continuations: u32) -> Result<(SessionState, Vec<usize>, Option<String>)> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.kernel.max_continuations = max_continuations;
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+
+    let provider = truncating_provider();
+    let mut kernel = Kernel::builder(config.clone()).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), provider.client());
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Greet me".to_string())).await?;
+    let sizes = provider.requests().iter().map(|r| r.messages.len()).collect();
+    drop(kernel);
+
+    let store = StateStore::open(&config.persistence.database_path).await?;
+    let finish_reason = store
+        .get_messages(&session.id)
+        .await?
+        .into_iter()
+        .find(|m| m.role == "assistant")
+        .and_then(|m| m.finish_reason);
+    Ok((session, sizes, finish_reason))
+}
+
+#[tokio::test]
+async fn test_truncated_response_is_continued_and_stitched() -> Result<()> {
+    let (session, sizes, finish_reason) = run_truncating(2).await?;
+    // The continuation request carries the partial answer and a follow-up prompt
+    assert_eq!(sizes, vec![1, 3]);
+    assert_eq!(session.history.len(), 2);
+    assert!(matches!(&session.history[1].content[0], InferenceContent::Text { text } if text == "Hello, world!"));
+    // The stitched message records how the final part ended, normalized
+    // from the provider's "end_turn"
+    assert_eq!(finish_reason.as_deref(), Some("stop"));
+
+    // Continuations are off by default: the truncated text is kept as is
+    let (session, sizes, finish_reason) = run_truncating(0).await?;
+    assert_eq!(sizes, vec![1]);
+    assert!(matches!(&session.history[1].content[0], InferenceContent::Text { text } if text == "Hello, wor"));
+    assert_eq!(finish_reason.as_deref(), Some("length"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_resume_from_snapshot_and_replay() -> Result<()> {
+    let tmp = tempdir()?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = db_path.clone();
+    config.persistence.snapshot_interval_turns = 1;
+
+    let mut kernel = Kernel::builder(config.clone()).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), read_notes_provider().client());
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("First".to_string())).await?;
+    drop(kernel);
+
+    let store = StateStore::open(&db_path).await?;
+    let snapshot = store.get_snapshot(&session.id).await?.expect("snapshot written at turn boundary");
+    // A message written after the snapshot is replayed on top of it
+    store.insert_message(&session.id, session.turn_index, "user", &serde_json::json!([{"type": "text", "text": "Later"}]), None, None).await?;
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    let resumed = kernel.resume_session(&session.id).await?;
+    assert_eq!(resumed.history.len(), session.history.len() + 1);
+    assert_eq!(serde_json::to_value(&resumed.history[..session.history.len()])?, serde_json::to_value(&session.history)?);
+    assert_eq!(resumed.total_input_tokens, session.total_input_tokens);
+    assert_eq!(resumed.turn_index, session.turn_index + 1);
+
+    // An incompatible snapshot falls back to replaying every message
+    store.put_snapshot(&session.id, 999, snapshot.last_message_id, &snapshot.state).await?;
+    let replayed = kernel.resume_session(&session.id).await?;
+    assert_eq!(serde_json::to_value(&replayed.history)?, serde_json::to_value(&resumed.history)?);
+    assert_eq!(replayed.total_input_tokens, 0);
+
+    assert!(kernel.resume_session("missing").await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sessions_named_from_prompt() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), read_notes_provider().client());
+
+    let mut first = kernel.create_session();
+    kernel.run(&mut first, Some("Fix the login bug!".to_string())).await?;
+    assert_eq!(first.name.as_deref(), Some("fix-the-login-bug"));
+    // The same prompt again gets a suffix
+    let mut second = kernel.create_session();
+    kernel.run(&mut second, Some("Fix the login bug".to_string())).await?;
+    assert_eq!(second.name.as_deref(), Some("fix-the-login-bug-2"));
+
+    // An explicit name is kept, and must be valid and unused
+    let mut named = kernel.create_session();
+    assert!(kernel.name_session(&mut named, "fix-the-login-bug").await.is_err());
+    assert!(kernel.name_session(&mut named, "Not A Slug").await.is_err());
+    kernel.name_session(&mut named, "login").await?;
+    kernel.run(&mut named, Some("Fix it".to_string())).await?;
+    assert_eq!(named.name.as_deref(), Some("login"));
+
+    // Names work wherever ids do
+    let resumed = kernel.resume_session("login").await?;
+    assert_eq!(resumed.id, named.id);
+    assert_eq!(resumed.name.as_deref(), Some("login"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_background_maintenance_sweeps_when_idle() -> Result<()> {
+    let tmp = tempdir()?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+    config.maintenance.enabled = true;
+    config.maintenance.interval_secs = 1;
+    config.maintenance.idle_secs = 0;
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    let store = StateStore::open(&db_path).await?;
+    store.get_connection()?
+        .execute("INSERT INTO harness_kv (key, value, expires_at) VALUES ('stale', 'v', datetime('now', '-1 minute'))", ())
+        .await?;
+    let count = || async {
+        let mut rows = store.get_connection().unwrap().query("SELECT COUNT(*) FROM harness_kv", ()).await.unwrap();
+        rows.next().await.unwrap().unwrap().get::<i64>(0).unwrap()
+    };
+    assert_eq!(count().await, 1);
+
+    kernel.start_maintenance();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while count().await > 0 && std::time::Instant::now() < deadline {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(count().await, 0);
+    kernel.stop_maintenance().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_eager_tool_dispatch_starts_before_response_ends() -> Result<()> {
+    for (mode, eager) in [(EagerDispatch::Off, false), (EagerDispatch::ReadOnly, true), (EagerDispatch::All, true)] {
+        let tmp = tempdir()?;
+        std::fs::write(tmp.path().join("notes.txt"), "remember the milk")?;
+        let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+        let mut config = BedrockConfig::default();
+        config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+        config.kernel.eager_tool_dispatch = mode;
+        config.persistence.database_path = db_path.clone();
+        config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+        // Streams a `read_file` call, then takes a while to finish the response
+        let read = Reply { end_delay_ms: 300, ..Reply::call("read_file", serde_json::json!({ "path": "notes.txt" })) };
+        let provider = ScriptedProvider::replies(vec![read, Reply { end_delay_ms: 300, ..Reply::text("Done") }]);
+        let mut kernel = Kernel::builder(config).build()?;
+        kernel.init_state().await?;
+        kernel.add_client("mock".to_string(), provider.client());
+
+        let mut session = kernel.create_session();
+        kernel.run(&mut session, Some("Read my notes".to_string())).await?;
+        kernel.flush_events(&mut session).await;
+        drop(kernel);
+
+        let store = StateStore::open(&db_path).await?;
+        let events = store.get_events(&session.id).await?;
+        let position = |kind: &str| events.iter().position(|e| e.event_type == kind).unwrap();
+        assert_eq!(position("tool_exec_start") < position("message_end"), eager, "{:?}", mode);
+        // Either way the result is recorded once and answers the call
+        let executions = store.get_tool_executions(&session.id).await?;
+        assert_eq!(executions.len(), 1, "{:?}", mode);
+        assert!(!executions[0].is_error);
+        assert!(session.history.iter().any(|m| m.content.iter().any(|c| matches!(
+            c, InferenceContent::ToolResult { content, .. } if content.contains("remember the milk")
+        ))));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_oversized_prompt_rejected_before_inference() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.kernel.max_prompt_tokens = Some(10);
+
+    let provider = truncating_provider();
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.add_client("mock".to_string(), provider.client());
+
+    let mut session = kernel.create_session();
+    let err = kernel.run(&mut session, Some("word ".repeat(100))).await.unwrap_err().to_string();
+    assert!(err.contains("~125 tokens, over the kernel.max_prompt_tokens limit of 10"), "{}", err);
+    assert!(session.history.is_empty());
+    assert!(provider.requests().is_empty());
+
+    kernel.run(&mut session, Some("short".to_string())).await?;
+    assert_eq!(session.history.len(), 2);
+
+    // Every queued prompt runs, as each part of `run --chunk` must
+    session.queue.lock().await.extend(["[Part 1 of 2] one".to_string(), "[Part 2 of 2] two".to_string()]);
+    kernel.run(&mut session, None).await?;
+    assert_eq!(session.history.len(), 6);
+    Ok(())
+}
+
+/// Runs one prompt under a harness that clears the messages. Returns the
+/// session, the run's result and the types of the events it emitted.
+async fn run_with_clearing_harness(min_retained_turns: u32, context_window_tokens: u32) -> Result<(SessionState, Result<()>, Vec<String>)> {
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(harness_dir.join("compact.lua"), r#"
+function on_before_inference(ctx)
+    ctx:clear_messages()
+    return ALLOW
+end
+"#)?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.kernel.min_retained_turns = min_retained_turns;
+    config.kernel.context_window_tokens = context_window_tokens;
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.add_client("mock".to_string(), read_notes_provider().client());
+    kernel.init_harness().await?;
+
+    let mut events = kernel.subscribe();
+    let mut session = kernel.create_session();
+    let result = kernel.run(&mut session, Some("Keep me".to_string())).await;
+    kernel.flush_events(&mut session).await;
+    let mut event_types = Vec::new();
+    while let Ok((_, event)) = events.try_recv() {
+        event_types.push(event.event_type().to_string());
+    }
+    Ok((session, result, event_types))
+}
+
+#[tokio::test]
+async fn test_min_retained_turns_overrides_harness_trimming() -> Result<()> {
+    // Without a floor the harness may drop everything
+    let (session, result, events) = run_with_clearing_harness(0, 128_000).await?;
+    result?;
+    assert!(!session.history.iter().any(|m| matches!(&m.content[..], [InferenceContent::Text { text }] if text == "Keep me")));
+    assert!(!events.iter().any(|e| e == "harness_edit_discarded"));
+
+    // With a floor of one turn, the current turn's messages survive
+    let (session, result, events) = run_with_clearing_harness(1, 128_000).await?;
+    result?;
+    match &session.history[0].content[0] {
+        InferenceContent::Text { text } => assert_eq!(text, "Keep me"),
+        other => panic!("expected the prompt, got {:?}", other),
+    }
+    assert!(events.iter().any(|e| e == "harness_edit_discarded"), "{:?}", events);
+
+    // A floor that doesn't fit the configured context window fails the turn
+    let (_, result, _) = run_with_clearing_harness(1, 1).await?;
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains("exceed the 1-token context window"), "{}", err);
+    Ok(())
+}
+
+/// Records how many executions of tools sharing `peak` overlap.
+struct ProbeTool {
+    name: &'static str,
+    resource: &'static str,
+    active: Arc<std::sync::atomic::AtomicUsize>,
+    peak: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+#[async_trait::async_trait]
+impl Tool for ProbeTool {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        "Probe"
+    }
+
+    fn parameters_schema(&self) -> serde_json::Value {
+        serde_json::json!({ "type": "object" })
+    }
+
+    fn resource_key(&self) -> Option<&str> {
+        Some(self.resource)
+    }
+
+    async fn execute(&self, _params: serde_json::Value, _ctx: &ToolContext) -> std::result::Result<ToolOutput, ToolError> {
+        use std::sync::atomic::Ordering;
+        let now = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        self.active.fetch_sub(1, Ordering::SeqCst);
+        Ok(ToolOutput::new("ok".to_string()))
+    }
+}
+
+/// Runs two differently named tools that share a resource key; returns their peak overlap.
+async fn shared_resource_peak(limit: Option<usize>) -> Result<usize> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    if let Some(limit) = limit {
+        config.tools.resource_limits.insert("shared-api".to_string(), limit);
+    }
+
+    let active = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let peak = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let mut registry = ToolRegistry::new();
+    for name in ["list_issues", "create_issue"] {
+        registry.register(Box::new(ProbeTool { name, resource: "shared-api", active: active.clone(), peak: peak.clone() }))?;
+    }
+
+    let mut kernel = Kernel::builder(config).with_tool_registry(registry).build()?;
+    let provider = parallel_tools_provider(&["list_issues", "create_issue"]);
+    kernel.add_client("mock".to_string(), provider.client());
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Sync issues".to_string())).await?;
+    assert_eq!(session.stop_reason, Some(StopReason::Completed));
+    Ok(peak.load(std::sync::atomic::Ordering::SeqCst))
+}
+
+/// Runs four tools with separate resource keys in one turn; returns their peak
+/// overlap and the order their results reached the history in.
+async fn parallel_tools_peak(max_parallel: Option<u32>) -> Result<(usize, Vec<String>)> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.kernel.max_parallel_tool_calls = max_parallel;
+
+    let active = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let peak = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let names = ["probe_a", "probe_b", "probe_c", "probe_d"];
+    let mut registry = ToolRegistry::new();
+    for name in names {
+        registry.register(Box::new(ProbeTool { name, resource: name, active: active.clone(), peak: peak.clone() }))?;
+    }
+
+    let mut kernel = Kernel::builder(config).with_tool_registry(registry).build()?;
+    let provider = parallel_tools_provider(&names);
+    kernel.add_client("mock".to_string(), provider.client());
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Probe everything".to_string())).await?;
+    assert_eq!(session.stop_reason, Some(StopReason::Completed));
+    let order = session.history.iter()
+        .flat_map(|m| &m.content)
+        .filter_map(|c| match c {
+            InferenceContent::ToolResult { tool_use_id, .. } => Some(tool_use_id.clone()),
+            _ => None,
+        })
+        .collect();
+    Ok((peak.load(std::sync::atomic::Ordering::SeqCst), order))
+}
+
+#[tokio::test]
+async fn test_max_parallel_tool_calls() -> Result<()> {
+    let in_call_order: Vec<String> = (0..4).map(|i| format!("call-{}", i)).collect();
+    let (peak, order) = parallel_tools_peak(None).await?;
+    assert_eq!((peak, order), (4, in_call_order.clone()));
+    let (peak, order) = parallel_tools_peak(Some(2)).await?;
+    assert_eq!((peak, order), (2, in_call_order.clone()));
+    let (peak, order) = parallel_tools_peak(Some(1)).await?;
+    assert_eq!((peak, order), (1, in_call_order));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tools_sharing_resource_key_serialize() -> Result<()> {
+    // A declared resource defaults to one execution at a time
+    assert_eq!(shared_resource_peak(None).await?, 1);
+    // tools.resource_limits raises it
+    assert_eq!(shared_resource_peak(Some(2)).await?, 2);
+    Ok(())
+}
+
+/// Sleeps for `ms` before answering.
+struct SleepTool {
+    name: &'static str,
+    ms: u64,
+}
+
+#[async_trait::async_trait]
+impl Tool for SleepTool {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        "Sleep"
+    }
+
+    fn parameters_schema(&self) -> serde_json::Value {
+        serde_json::json!({ "type": "object" })
+    }
+
+    async fn execute(&self, _params: serde_json::Value, _ctx: &ToolContext) -> std::result::Result<ToolOutput, ToolError> {
+        tokio::time::sleep(std::time::Duration::from_millis(self.ms)).await;
+        Ok(ToolOutput::new("woke up".to_string()))
+    }
+}
+
+/// Counts its runs; opts in to the cross-session cache.
+struct CountingTool {
+    runs: Arc<std::sync::atomic::AtomicUsize>,
+    artifact: bool,
+}
+
+#[async_trait::async_trait]
+impl Tool for CountingTool {
+    fn name(&self) -> &str {
+        "lookup"
+    }
+
+    fn description(&self) -> &str {
+        "Lookup"
+    }
+
+    fn parameters_schema(&self) -> serde_json::Value {
+        serde_json::json!({ "type": "object" })
+    }
+
+    fn cacheable_globally(&self) -> bool {
+        true
+    }
+
+    async fn execute(&self, _params: serde_json::Value, _ctx: &ToolContext) -> std::result::Result<ToolOutput, ToolError> {
+        let run = self.runs.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+        let output = ToolOutput::new(format!("answer {}", run));
+        Ok(if self.artifact { output.with_artifact(Artifact::text("text/plain", "details".to_string())) } else { output })
+    }
+}
+
+/// Runs the same `lookup` call in two sessions sharing a database; returns
+/// how often the tool ran and the verdict and output recorded in each session.
+async fn global_cache_runs(ttl_secs: u64, artifact: bool) -> Result<(usize, Vec<(String, Option<String>)>)> {
+    let tmp = tempdir()?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.kernel.tool_cache_ttl_secs = ttl_secs;
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    let runs = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let mut registry = ToolRegistry::new();
+    registry.register(Box::new(CountingTool { runs: runs.clone(), artifact }))?;
+    let mut kernel = Kernel::builder(config).with_tool_registry(registry).build()?;
+    kernel.init_state().await?;
+    let provider = ScriptedProvider::new(|_, request| match request.messages.len() {
+        1 => Reply::call("lookup", serde_json::json!({ "q": "x" })),
+        _ => Reply::text("done"),
+    });
+    kernel.add_client("mock".to_string(), provider.client());
+    kernel.init_harness().await?;
+
+    let mut sessions = Vec::new();
+    for _ in 0..2 {
+        let mut session = kernel.create_session();
+        kernel.run(&mut session, Some("Look it up".to_string())).await?;
+        kernel.flush_events(&mut session).await;
+        sessions.push(session.id);
+    }
+    drop(kernel);
+
+    let store = StateStore::open(&db_path).await?;
+    let mut executions = Vec::new();
+    for id in &sessions {
+        let rows = store.get_tool_executions(id).await?;
+        assert_eq!(rows.len(), 1);
+        executions.push((rows[0].verdict.clone(), rows[0].output.clone()));
+    }
+    Ok((runs.load(std::sync::atomic::Ordering::SeqCst), executions))
+}
+
+#[tokio::test]
+async fn test_global_tool_cache_across_sessions() -> Result<()> {
+    // The second session is answered from the first one's result
+    let (runs, executions) = global_cache_runs(60, false).await?;
+    assert_eq!(runs, 1);
+    assert_eq!(executions[0].0, "ALLOW");
+    assert_eq!(executions[1].0, "global_cache_hit");
+    assert_eq!(executions[1].1.as_deref(), Some("answer 1"));
+
+    // A zero TTL expires the entry as soon as it is written
+    let (runs, executions) = global_cache_runs(0, false).await?;
+    assert_eq!(runs, 2);
+    assert!(executions.iter().all(|(verdict, _)| verdict == "ALLOW"));
+
+    // Artifact handles are per session, so such results are not cached
+    let (runs, executions) = global_cache_runs(60, true).await?;
+    assert_eq!(runs, 2);
+    assert!(executions.iter().all(|(verdict, _)| verdict == "ALLOW"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tool_timeout_cancels_call() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.tools.timeout_ms = 5_000;
+    config.tools.timeouts.insert("stuck".to_string(), 100);
+
+    let mut registry = ToolRegistry::new();
+    registry.register(Box::new(SleepTool { name: "stuck", ms: 60_000 }))?;
+    registry.register(Box::new(SleepTool { name: "quick", ms: 10 }))?;
+    let mut kernel = Kernel::builder(config.clone()).with_tool_registry(registry).build()?;
+    kernel.init_state().await?;
+    let provider = parallel_tools_provider(&["stuck", "quick"]);
+    kernel.add_client("mock".to_string(), provider.client());
+
+    let mut session = kernel.create_session();
+    let started = std::time::Instant::now();
+    kernel.run(&mut session, Some("Wait for it".to_string())).await?;
+    // The turn went on without the stuck call
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    assert_eq!(session.stop_reason, Some(StopReason::Completed));
+    let results: Vec<_> = session.history.iter().flat_map(|m| &m.content).filter_map(|c| match c {
+        InferenceContent::ToolResult { content, is_error, .. } => Some((content.clone(), *is_error)),
+        _ => None,
+    }).collect();
+    assert!(results[0].1 && results[0].0.starts_with("[TOOL TIMEOUT] Tool 'stuck' did not finish within 100ms"), "{:?}", results);
+    assert_eq!(results[1], ("woke up".to_string(), false));
+
+    kernel.flush_events(&mut session).await;
+    let store = StateStore::open(&config.persistence.database_path).await?;
+    let verdicts: Vec<_> = store.get_tool_executions(&session.id).await?.into_iter().map(|e| e.verdict).collect();
+    assert_eq!(verdicts, vec!["timeout", "ALLOW"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tool_timeout_stops_harness_tool() -> Result<()> {
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(harness_dir.join("tools.lua"), r#"
+bedrock.register_tool({
+    name = "spin",
+    description = "Never returns",
+    handler = function() while true do end end,
+})
+"#)?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+    config.tools.timeout_ms = 200;
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_harness().await?;
+    let provider = parallel_tools_provider(&["spin"]);
+    kernel.add_client("mock".to_string(), provider.client());
+    let mut session = kernel.create_session();
+    let run = kernel.run(&mut session, Some("Spin".to_string()));
+    tokio::time::timeout(std::time::Duration::from_secs(10), run).await.expect("the handler was stopped")?;
+    let result = session.history.iter().flat_map(|m| &m.content).find_map(|c| match c {
+        InferenceContent::ToolResult { content, is_error, .. } => Some((content.clone(), *is_error)),
+        _ => None,
+    });
+    let (content, is_error) = result.expect("spin answered");
+    assert!(is_error && content.starts_with("[TOOL TIMEOUT] Tool 'spin' did not finish within 200ms"), "{}", content);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_on_tool_result_rewrites_output() -> Result<()> {
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(harness_dir.join("a_trim.lua"), r#"
+function on_tool_result(result)
+    if result.name == "secret" then
+        return reject("it may contain credentials")
+    end
+    return string.sub(result.output, 1, 4) .. "... [truncated]"
+end
+"#)?;
+    std::fs::write(harness_dir.join("b_note.lua"), r#"
+function on_tool_result(result)
+    return MODIFY, result.output .. " (reviewed)"
+end
+"#)?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+
+    let mut registry = ToolRegistry::new();
+    registry.register(Box::new(SleepTool { name: "noisy", ms: 0 }))?;
+    registry.register(Box::new(SleepTool { name: "secret", ms: 0 }))?;
+    let mut kernel = Kernel::builder(config.clone()).with_tool_registry(registry).build()?;
+    kernel.init_state().await?;
+    kernel.init_harness().await?;
+    let provider = parallel_tools_provider(&["noisy", "secret"]);
+    kernel.add_client("mock".to_string(), provider.client());
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Look around".to_string())).await?;
+    let results: Vec<_> = session.history.iter().flat_map(|m| &m.content).filter_map(|c| match c {
+        InferenceContent::ToolResult { content, is_error, .. } => Some((content.clone(), *is_error)),
+        _ => None,
+    }).collect();
+    // Each script sees the output as left by the one before it
+    assert_eq!(results[0], ("woke... [truncated] (reviewed)".to_string(), false));
+    assert_eq!(results[1], ("[HARNESS WITHHELD] Output of tool 'secret' withheld: it may contain credentials".to_string(), true));
+
+    // The raw output is still on record
+    kernel.flush_events(&mut session).await;
+    let store = StateStore::open(&config.persistence.database_path).await?;
+    let outputs: Vec<_> = store.get_tool_executions(&session.id).await?.into_iter().map(|e| e.output).collect();
+    assert_eq!(outputs, vec![Some("woke up".to_string()), Some("woke up".to_string())]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_harness_defined_tools() -> Result<()> {
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(harness_dir.join("tools.lua"), r#"
+bedrock.register_tool({
+    name = "whoami",
+    description = "Name the session",
+    effect = "read_only",
+    handler = function(args, ctx) return "session " .. ctx.session_id end,
+})
+bedrock.register_tool({
+    name = "broken",
+    description = "Always fails",
+    handler = function() error("out of order") end,
+})
+function on_tool_result(result)
+    if result.name == "whoami" then return result.output .. " (checked)" end
+end
+"#)?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.init_harness().await?;
+    let whoami = kernel.tools().into_iter().find(|t| t.name == "whoami").expect("harness tool registered");
+    assert_eq!(whoami.origin(), "harness");
+    assert_eq!(whoami.effect, bedrock::tools::ToolEffect::ReadOnly);
+    assert!(kernel.tools().iter().any(|t| t.name == "read_file"));
+
+    let provider = parallel_tools_provider(&["whoami", "broken"]);
+    kernel.add_client("mock".to_string(), provider.client());
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Who am I?".to_string())).await?;
+    let results: Vec<_> = session.history.iter().flat_map(|m| &m.content).filter_map(|c| match c {
+        InferenceContent::ToolResult { content, is_error, .. } => Some((content.clone(), *is_error)),
+        _ => None,
+    }).collect();
+    // Harness tools pass the same hooks as any other tool
+    assert_eq!(results[0], (format!("session {} (checked)", session.id), false));
+    assert!(results[1].1 && results[1].0.contains("out of order"), "{:?}", results[1]);
+
+    // Reloading without the script drops its tools
+    std::fs::remove_file(harness_dir.join("tools.lua"))?;
+    kernel.reload_harness().await?;
+    assert!(!kernel.tools().iter().any(|t| t.name == "whoami"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lazy_mcp_server_registered_from_cache() -> Result<()> {
+    use bedrock::kernel::config::McpServerConfig;
+    use bedrock::tools::mcp::{tool_cache_key, CachedTools, McpServerStatus};
+
+    let tmp = tempdir()?;
+    let db_path = tmp.path().join("test.db");
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = db_path.to_str().unwrap().to_string();
+    config.mcp.lazy = true;
+    config.mcp.servers = vec![McpServerConfig {
+        name: Some("docs".to_string()),
+        command: "docs-mcp".to_string(),
+        args: vec!["--stdio".to_string()],
+        ..Default::default()
+    }];
+
+    let store = StateStore::open(db_path.to_str().unwrap()).await?;
+    let tool = mcp_sdk::types::ToolDefinition {
+        name: "search_docs".to_string(),
+        description: Some("Search the docs".to_string()),
+        input_schema: serde_json::json!({ "type": "object", "properties": {} }),
+    };
+    let cached = CachedTools { source: "docs-mcp --stdio".to_string(), tools: vec![tool] };
+    store.kv_set(&tool_cache_key("docs"), &serde_json::to_string(&cached)?).await?;
+
+    let mut kernel = Kernel::builder(config.clone()).build()?;
+    kernel.init_state().await?;
+    assert_eq!(kernel.init_mcp_servers().await, 1);
+    let search = kernel.tools().into_iter().find(|t| t.name == "search_docs").expect("cached tool registered");
+    assert_eq!(search.origin(), "mcp:docs");
+    // Registered from the cache without starting the process
+    assert_eq!(kernel.mcp_servers()[0].status().await, McpServerStatus::NotStarted);
+
+    // With tools disabled the server is neither registered nor started
+    let mut disabled = config.clone();
+    disabled.tools.enabled = false;
+    let mut kernel = Kernel::builder(disabled).build()?;
+    kernel.init_state().await?;
+    assert_eq!(kernel.init_mcp_servers().await, 0);
+    assert!(kernel.mcp_servers().is_empty());
+
+    // A cache for other arguments is ignored, so the server is started instead
+    config.mcp.servers[0].args = vec!["--verbose".to_string()];
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.init_mcp_servers().await;
+    assert!(!kernel.tools().iter().any(|t| t.name == "search_docs"));
+    Ok(())
+}
+
+/// A remote MCP server with one `echo` tool, a `notes://todo` resource and
+/// a `review` prompt, over streamable HTTP at `/mcp` (requiring a bearer
+/// token) and over HTTP+SSE at `/sse`.
+async fn spawn_remote_mcp_server() -> Result<String> {
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::sse::{Event, Sse};
+    use axum::response::IntoResponse;
+    use axum::routing::{get, post};
+    use serde_json::{json, Value};
+
+    type Streams = Arc<std::sync::Mutex<Vec<tokio::sync::mpsc::UnboundedSender<Event>>>>;
+
+    fn answer(message: &Value) -> Option<Value> {
+        let result = match message["method"].as_str()? {
+            "initialize" => json!({ "protocolVersion": "2025-06-18", "capabilities": { "tools": {} }, "serverInfo": { "name": "remote" } }),
+            "tools/list" => json!({ "tools": [{ "name": "echo", "description": "Echo text", "inputSchema": { "type": "object" } }] }),
+            "tools/call" => json!({ "content": [{ "type": "text", "text": format!("echo: {}", message["params"]["arguments"]["text"]) }] }),
+            "resources/list" => json!({ "resources": [{ "uri": "notes://todo", "name": "todo", "mimeType": "text/plain" }] }),
+            "resources/read" => json!({ "contents": [{ "uri": message["params"]["uri"], "text": "- ship it" }] }),
+            "prompts/list" => json!({ "prompts": [{ "name": "review", "arguments": [{ "name": "file", "required": true }] }] }),
+            "prompts/get" => json!({ "messages": [{ "role": "user", "content": { "type": "text", "text": format!("Review {}", message["params"]["arguments"]["file"].as_str().unwrap_or_default()) } }] }),
+            _ => return None,
+        };
+        Some(json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }))
+    }
+
+    let streams: Streams = Default::default();
+    let sse_streams = streams.clone();
+    let app = axum::Router::new()
+        .route("/mcp", post(|headers: HeaderMap, axum::Json(message): axum::Json<Value>| async move {
+            if headers.get("authorization").and_then(|v| v.to_str().ok()) != Some("Bearer secret") {
+                return StatusCode::UNAUTHORIZED.into_response();
+            }
+            let initialize = message["method"] == "initialize";
+            if !initialize && headers.get("mcp-session-id").is_none() {
+                return StatusCode::BAD_REQUEST.into_response();
+            }
+            let Some(response) = answer(&message) else {
+                return StatusCode::ACCEPTED.into_response();
+            };
+            if initialize {
+                return ([("mcp-session-id", "session-1")], axum::Json(response)).into_response();
+            }
+            // Tool calls answer on an event stream, after a progress notification
+            let body = format!(
+                "event: message\ndata: {}\n\nevent: message\ndata: {}\n\n",
+                json!({ "jsonrpc": "2.0", "method": "notifications/progress", "params": {} }),
+                response
+            );
+            ([("content-type", "text/event-stream")], body).into_response()
+        }))
+        .route("/sse", get(move || async move {
+            let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+            tx.send(Event::default().event("endpoint").data("/messages")).unwrap();
+            sse_streams.lock().unwrap().push(tx);
+            let events = futures::stream::unfold(rx, |mut rx| async move {
+                rx.recv().await.map(|event| (Ok::<_, std::convert::Infallible>(event), rx))
+            });
+            Sse::new(events)
+        }))
+        .route("/messages", post(move |axum::Json(message): axum::Json<Value>| async move {
+            if let Some(response) = answer(&message) {
+                for stream in streams.lock().unwrap().iter() {
+                    let _ = stream.send(Event::default().event("message").data(response.to_string()));
+                }
+            }
+            StatusCode::ACCEPTED
+        }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    Ok(base)
+}
+
+#[tokio::test]
+async fn test_remote_mcp_servers() -> Result<()> {
+    use bedrock::kernel::config::{McpServerConfig, McpTransportKind};
+
+    let base = spawn_remote_mcp_server().await?;
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let streamable = McpServerConfig {
+        name: Some("remote".to_string()),
+        url: Some(format!("{}/mcp", base)),
+        headers: HashMap::from([("Authorization".to_string(), "Bearer secret".to_string())]),
+        ..Default::default()
+    };
+    let legacy = McpServerConfig {
+        url: Some(format!("{}/sse", base)),
+        transport: Some(McpTransportKind::Sse),
+        ..Default::default()
+    };
+    let unauthorized = McpServerConfig { name: Some("anonymous".to_string()), headers: HashMap::new(), ..streamable.clone() };
+    config.mcp.servers = vec![streamable.clone(), legacy.clone(), unauthorized];
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    assert_eq!(kernel.init_mcp_servers().await, 2);
+    let tools: Vec<_> = kernel.tools().into_iter().filter(|t| t.origin().starts_with("mcp:")).map(|t| (t.origin(), t.name)).collect();
+    assert_eq!(tools, vec![
+        ("mcp:127.0.0.1".to_string(), "127_0_0_1__echo".to_string()),
+        ("mcp:remote".to_string(), "echo".to_string()),
+    ]);
+
+    // A cached tool list doesn't change which server keeps the plain name
+    let mut lazy_config = kernel.config().clone();
+    lazy_config.mcp.servers = vec![streamable.clone(), McpServerConfig { lazy: Some(true), ..legacy.clone() }];
+    let mut lazy_kernel = Kernel::builder(lazy_config).build()?;
+    lazy_kernel.init_state().await?;
+    assert_eq!(lazy_kernel.init_mcp_servers().await, 2);
+    let lazy_tools: Vec<_> = lazy_kernel.tools().into_iter().filter(|t| t.origin().starts_with("mcp:")).map(|t| (t.origin(), t.name)).collect();
+    assert_eq!(lazy_tools, tools);
+
+    for server in [streamable, legacy] {
+        let connection = server.to_transport().connect().await?;
+        let result = connection.client.call_tool("echo", serde_json::json!({ "text": "hi" })).await?;
+        assert_eq!((result.text.as_str(), result.is_error), ("echo: \"hi\"", false));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_remote_mcp_resources_and_prompts() -> Result<()> {
+    use bedrock::kernel::config::McpServerConfig;
+    use bedrock::tools::mcp_resources::{get_prompt, list_prompts, list_resources, read_resource};
+
+    let base = spawn_remote_mcp_server().await?;
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.mcp.servers = vec![McpServerConfig {
+        name: Some("notes".to_string()),
+        url: Some(format!("{}/mcp", base)),
+        headers: HashMap::from([("Authorization".to_string(), "Bearer secret".to_string())]),
+        ..Default::default()
+    }];
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.init_mcp_servers().await;
+    let servers = kernel.mcp_servers();
+
+    let resources = list_resources(&servers).await;
+    assert_eq!((resources.len(), resources[0].server.as_str(), resources[0].uri.as_str()), (1, "notes", "notes://todo"));
+    assert_eq!(read_resource(&servers, "notes://todo", None).await?, "- ship it");
+    assert!(read_resource(&servers, "notes://missing", None).await.is_err());
+
+    let prompts = list_prompts(&servers).await;
+    assert_eq!(prompts[0].name, "review");
+    assert!(prompts[0].arguments[0].required);
+    let file = HashMap::from([("file".to_string(), "main.rs".to_string())]);
+    let messages = get_prompt(&servers, "notes:review", &file).await?;
+    assert_eq!((messages.len(), messages[0].role.as_str(), messages[0].text.as_str()), (1, "user", "Review main.rs"));
+    let err = get_prompt(&servers, "review", &HashMap::new()).await.unwrap_err();
+    assert!(err.to_string().contains("needs file"), "{}", err);
+
+    // The model reaches resources through read_resource
+    let provider = parallel_tools_provider(&["read_resource"]);
+    kernel.add_client("mock".to_string(), provider.client());
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("What resources are there?".to_string())).await?;
+    let listed = session.history.iter().flat_map(|m| &m.content).find_map(|c| match c {
+        InferenceContent::ToolResult { content, .. } => Some(content.clone()),
+        _ => None,
+    });
+    assert_eq!(listed.as_deref(), Some("notes://todo (todo, notes)"));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_stdio_mcp_resources_and_prompts() -> Result<()> {
+    use bedrock::kernel::config::McpServerConfig;
+    use bedrock::tools::mcp_resources::get_prompt;
+
+    // Answers by method, listing no tools
+    let script = r#"while read line; do
+  id=$(echo "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  [ -z "$id" ] && continue
+  case "$line" in
+    *'"method":"resources/list"'*) result='{"resources":[{"uri":"notes://todo","name":"todo"}]}' ;;
+    *'"method":"resources/read"'*) result='{"contents":[{"uri":"notes://todo","text":"- ship it"}]}' ;;
+    *'"method":"prompts/list"'*) result='{"prompts":[{"name":"review"}]}' ;;
+    *'"method":"prompts/get"'*) result='{"messages":[{"role":"assistant","content":{"type":"text","text":"I read main.rs."}},{"role":"user","content":{"type":"text","text":"Review it"}}]}' ;;
+    *) result='{"tools":[]}' ;;
+  esac
+  echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":$result}"
+done"#;
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+    config.mcp.servers = vec![McpServerConfig {
+        name: Some("notes".to_string()),
+        command: "sh".to_string(),
+        args: vec!["-c".to_string(), script.to_string()],
+        ..Default::default()
+    }];
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.init_mcp_servers().await;
+    kernel.init_harness().await?;
+    assert!(kernel.tools().iter().any(|t| t.name == "read_resource"));
+
+    // Harnesses list and read resources
+    kernel.run_script(r#"
+        local resources = bedrock.mcp_resources()
+        assert(#resources == 1 and resources[1].server == "notes" and resources[1].uri == "notes://todo", "listed")
+        assert(bedrock.read_mcp_resource("notes://todo") == "- ship it", "read")
+        assert(bedrock.read_mcp_resource("notes://todo", "notes") == "- ship it", "read from server")
+        assert(not pcall(bedrock.read_mcp_resource, "notes://todo", "missing"), "unknown server")
+    "#).await?;
+
+    // Prompt messages keep their roles
+    let messages = get_prompt(&kernel.mcp_servers(), "review", &HashMap::new()).await?;
+    let roles: Vec<&str> = messages.iter().map(|m| m.role.as_str()).collect();
+    assert_eq!(roles, ["assistant", "user"]);
+    let mut session = kernel.create_session();
+    kernel.add_messages(&mut session, vec![(InferenceRole::Assistant, messages[0].text.clone())]).await;
+    assert_eq!(session.history.len(), 1);
+    assert_eq!(session.history[0].role, InferenceRole::Assistant);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_requires_approval() -> Result<()> {
+    use bedrock::tools::approval::{ApprovalDecision, ApprovalRequest};
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.tools.requires_approval = vec!["noisy".to_string(), "sec*".to_string()];
+
+    let asked = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let log = asked.clone();
+    let handler = Arc::new(move |request: &ApprovalRequest<'_>| {
+        log.lock().unwrap().push((request.tool.to_string(), request.reason.to_string()));
+        if request.tool == "noisy" { ApprovalDecision::Always } else { ApprovalDecision::Deny }
+    });
+    let mut registry = ToolRegistry::new();
+    registry.register(Box::new(SleepTool { name: "noisy", ms: 0 }))?;
+    registry.register(Box::new(SleepTool { name: "secret", ms: 0 }))?;
+    let mut kernel = Kernel::builder(config.clone()).with_tool_registry(registry).approval_handler(handler).build()?;
+    kernel.init_state().await?;
+    let provider = parallel_tools_provider(&["noisy", "secret", "noisy"]);
+    kernel.add_client("mock".to_string(), provider.client());
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Go".to_string())).await?;
+    // "always" covers the second noisy call without asking again
+    assert_eq!(*asked.lock().unwrap(), vec![
+        ("noisy".to_string(), "matches requires_approval rule 'noisy'".to_string()),
+        ("secret".to_string(), "matches requires_approval rule 'sec*'".to_string()),
+    ]);
+    assert!(session.approved_tools.contains("noisy"));
+
+    kernel.flush_events(&mut session).await;
+    let store = StateStore::open(&config.persistence.database_path).await?;
+    let mut executions = store.get_tool_executions(&session.id).await?;
+    executions.sort_by(|a, b| a.tool_call_id.cmp(&b.tool_call_id));
+    let verdicts: Vec<_> = executions.iter().map(|e| (e.tool_call_id.as_str(), e.verdict.as_str(), e.is_error)).collect();
+    assert_eq!(verdicts, vec![
+        ("call-0", "approved_always", false),
+        ("call-1", "escalate_denied", true),
+        ("call-2", "approved_session", false),
+    ]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_eager_tool_dispatch_waits_for_approval() -> Result<()> {
+    use bedrock::tools::approval::{ApprovalDecision, ApprovalRequest};
+    let tmp = tempdir()?;
+    std::fs::write(tmp.path().join("notes.txt"), "remember the milk")?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.kernel.eager_tool_dispatch = EagerDispatch::All;
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+    config.tools.requires_approval = vec!["read_file".to_string()];
+
+    let asked = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let count = asked.clone();
+    let handler = Arc::new(move |_: &ApprovalRequest<'_>| {
+        count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        ApprovalDecision::Approve
+    });
+    let read = Reply { end_delay_ms: 300, ..Reply::call("read_file", serde_json::json!({ "path": "notes.txt" })) };
+    let provider = ScriptedProvider::replies(vec![read, Reply::text("Done")]);
+    let mut kernel = Kernel::builder(config).approval_handler(handler).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), provider.client());
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Read my notes".to_string())).await?;
+    kernel.flush_events(&mut session).await;
+    drop(kernel);
+
+    assert_eq!(asked.load(std::sync::atomic::Ordering::SeqCst), 1);
+    let store = StateStore::open(&db_path).await?;
+    let events = store.get_events(&session.id).await?;
+    let position = |kind: &str| events.iter().position(|e| e.event_type == kind).unwrap();
+    assert!(position("tool_exec_start") > position("message_end"));
+    let executions = store.get_tool_executions(&session.id).await?;
+    assert_eq!(executions.len(), 1);
+    assert_eq!(executions[0].verdict, "approved");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_requires_approval_covers_aliases() -> Result<()> {
+    use bedrock::tools::approval::{ApprovalDecision, ApprovalRequest};
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.tools.requires_approval = vec!["noisy".to_string()];
+    config.tools.aliases = HashMap::from([("quiet".to_string(), "noisy".to_string())]);
+
+    let asked = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let log = asked.clone();
+    let handler = Arc::new(move |request: &ApprovalRequest<'_>| {
+        log.lock().unwrap().push((request.tool.to_string(), request.reason.to_string()));
+        ApprovalDecision::Deny
+    });
+    let mut registry = ToolRegistry::new();
+    registry.register(Box::new(SleepTool { name: "noisy", ms: 0 }))?;
+    let mut kernel = Kernel::builder(config).with_tool_registry(registry).approval_handler(handler).build()?;
+    let provider = parallel_tools_provider(&["quiet"]);
+    kernel.add_client("mock".to_string(), provider.client());
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Go".to_string())).await?;
+    assert_eq!(*asked.lock().unwrap(), vec![("quiet".to_string(), "matches requires_approval rule 'noisy'".to_string())]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_approval_shows_model_args() -> Result<()> {
+    use bedrock::tools::approval::{ApprovalDecision, ApprovalRequest};
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(harness_dir.join("rewrite.lua"), r#"
+function on_tool_call(call)
+    return MODIFY, { target = "staging" }
+end
+"#)?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+    config.tools.requires_approval = vec!["deploy".to_string()];
+
+    let asked = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let log = asked.clone();
+    let handler = Arc::new(move |request: &ApprovalRequest<'_>| {
+        log.lock().unwrap().push((request.args.clone(), request.modified_args.cloned()));
+        ApprovalDecision::Approve
+    });
+    let mut registry = ToolRegistry::new();
+    registry.register(Box::new(SleepTool { name: "deploy", ms: 0 }))?;
+    let mut kernel = Kernel::builder(config).with_tool_registry(registry).approval_handler(handler).build()?;
+    let provider = ScriptedProvider::replies(vec![Reply::call("deploy", serde_json::json!({ "target": "prod" })), Reply::text("done")]);
+    kernel.add_client("mock".to_string(), provider.client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Ship it".to_string())).await?;
+    assert_eq!(*asked.lock().unwrap(), vec![(serde_json::json!({ "target": "prod" }), Some(serde_json::json!({ "target": "staging" })))]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_postprocessor_chain_runs_builtin_and_harness_steps() -> Result<()> {
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(harness_dir.join("shout.lua"), r#"
+function on_assistant_message(msg)
+    return MODIFY, string.upper(msg.text)
+end
+"#)?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.agent.postprocessors = vec![Postprocessor::StripCodeFences, Postprocessor::Harness, Postprocessor::Truncate(8)];
+
+    let mut kernel = Kernel::builder(config.clone()).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), text_provider("```\nhello world\n```").client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Greet me".to_string())).await?;
+    assert!(matches!(&session.history[1].content[0], InferenceContent::Text { text } if text == "HELLO WO"));
+    kernel.flush_events(&mut session).await;
+    drop(kernel);
+
+    // The persisted message is the processed one, and each step left an event
+    let store = StateStore::open(&config.persistence.database_path).await?;
+    let messages = store.get_messages(&session.id).await?;
+    assert!(messages.iter().any(|m| m.role == "assistant" && m.content.contains("HELLO WO") && !m.content.contains("```")));
+    let events = store.get_events(&session.id).await?;
+    let steps: Vec<_> = events.iter().filter(|e| e.event_type == "message_postprocessed").collect();
+    assert_eq!(steps.len(), 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_redact_secrets_applies_to_streamed_deltas() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.agent.postprocessors = vec![Postprocessor::RedactSecrets];
+
+    let mut kernel = Kernel::builder(config.clone()).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), text_provider("Your key is sk-abcdef1234567890").client());
+    let mut deltas = kernel.subscribe();
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Show the key".to_string())).await?;
+    kernel.flush_events(&mut session).await;
+    drop(kernel);
+
+    // Neither the live event stream nor the stored events carry the secret
+    let mut streamed = String::new();
+    while let Ok((_, event)) = deltas.try_recv() {
+        if let KernelEvent::MessageDelta { content_delta } = event {
+            streamed.push_str(&content_delta);
+        }
+    }
+    assert_eq!(streamed, "Your key is [REDACTED]");
+    let store = StateStore::open(&config.persistence.database_path).await?;
+    let events = store.get_events(&session.id).await?;
+    assert!(events.iter().any(|e| e.event_type == "message_delta"));
+    assert!(events.iter().all(|e| !e.payload.contains("sk-abcdef")));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_empty_tool_registry_is_reported() -> Result<()> {
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+
+    let kernel = Kernel::builder(config.clone()).build()?;
+    assert!(kernel.tool_registry_problem().await.is_none());
+
+    let mut kernel = Kernel::builder(config.clone()).with_tool_registry(ToolRegistry::new()).build()?;
+    kernel.init_harness().await?;
+    assert!(kernel.tool_registry_problem().await.unwrap().contains("called without tools"));
+
+    // A harness that governs tools expects some to exist
+    std::fs::write(harness_dir.join("guard.lua"), "function on_tool_call(call) return ALLOW end")?;
+    kernel.init_harness().await?;
+    let problem = kernel.tool_registry_problem().await.unwrap();
+    assert!(problem.contains("on_tool_call"), "{}", problem);
+
+    // The run still goes ahead
+    kernel.add_client("mock".to_string(), text_provider("hi").client());
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Hello".to_string())).await?;
+    assert_eq!(session.history.len(), 2);
+
+    // What `bedrock validate` builds: tools disabled in config, with the same harness
+    config.tools.enabled = false;
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_harness().await?;
+    let problem = kernel.tool_registry_problem().await.unwrap();
+    assert!(problem.contains("Tools are disabled") && problem.contains("on_tool_call"), "{}", problem);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_delegate_runs_sub_agent_in_child_session() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    // Delegates on its first call, answers as the sub-agent on its second and
+    // wraps up on the rest
+    let provider = ScriptedProvider::new(|call, request| match call {
+        0 => Reply::call("delegate", serde_json::json!({ "goal": "Count the TODOs", "tools": ["read_file"], "max_turns": 2 })),
+        1 => {
+            let tool_names: Vec<String> = request.tools.iter().flatten().map(|t| t.name.clone()).collect();
+            Reply::text(&format!("Found 3 TODOs with {:?}", tool_names))
+        }
+        _ => Reply::text("Done"),
+    });
+    let mut kernel = Kernel::builder(config.clone()).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), provider.client());
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("How many TODOs?".to_string())).await?;
+    kernel.end_session(&mut session).await?;
+
+    let result = session.history.iter()
+        .flat_map(|m| m.content.iter())
+        .find_map(|c| match c {
+            InferenceContent::ToolResult { tool_use_id, content, is_error } if tool_use_id == "call-0" => Some((content.clone(), *is_error)),
+            _ => None,
+        })
+        .expect("delegate result");
+    assert!(!result.1, "{}", result.0);
+    // The sub-agent only saw the tools it was given
+    assert!(result.0.contains("Found 3 TODOs with [\"read_file\"]"), "{}", result.0);
+    // Its usage counts toward the parent
+    assert_eq!(session.total_output_tokens, 15);
+
+    kernel.flush_events(&mut session).await;
+    let store = StateStore::open(&config.persistence.database_path).await?;
+    // The recorded execution holds the sub-agent's answer, not the tool's placeholder
+    let executions = store.get_tool_executions(&session.id).await?;
+    assert_eq!(executions[0].output.as_deref(), Some(result.0.as_str()));
+    let events = store.get_events(&session.id).await?;
+    let finished = events.iter().find(|e| e.event_type == "subagent_finished").expect("subagent_finished event");
+    let child_id = finished.payload_json().unwrap()["child_session"].as_str().unwrap().to_string();
+    assert_ne!(child_id, session.id);
+    let child_events = store.get_events(&child_id).await?;
+    let start = child_events.iter().find(|e| e.event_type == "agent_start").expect("child agent_start");
+    assert_eq!(start.payload_json().unwrap()["parent_session"], session.id.as_str());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_delegate_budget_is_capped_by_parent_remaining() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.agent.model = "claude-sonnet-4-20250514".to_string();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+    config.kernel.cost_budget_usd = Some(10.0);
+
+    // Every call costs $3; the sub-agent asks for far more than the parent's $10
+    let provider = ScriptedProvider::new(|call, _| match call {
+        0 => Reply::call("delegate", serde_json::json!({ "goal": "Dig", "max_turns": 10, "cost_budget_usd": 100.0 })),
+        _ => Reply::call("read_file", serde_json::json!({ "path": "missing.txt" })),
+    }.with_usage(1_000_000, 0));
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.add_client("mock".to_string(), provider.client());
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Dig deep".to_string())).await?;
+
+    let result = session.history.iter()
+        .flat_map(|m| m.content.iter())
+        .find_map(|c| match c {
+            InferenceContent::ToolResult { tool_use_id, content, .. } if tool_use_id == "call-0" => Some(content.clone()),
+            _ => None,
+        })
+        .expect("delegate result");
+    // The sub-agent stopped at the $7 the parent had left, not after 10 turns
+    assert!(result.contains("CostBudgetExceeded"), "{}", result);
+    assert!(provider.requests().len() < 10);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_consecutive_tool_errors_halt_run() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.kernel.max_turns = 10;
+    config.kernel.max_consecutive_tool_errors = Some(2);
+
+    // Calls a tool that doesn't exist on every turn
+    let thrash = Reply::call("no_such_tool", serde_json::json!({})).with_id("call");
+    let mut kernel = Kernel::builder(config.clone()).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), ScriptedProvider::replies(vec![thrash]).client());
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Go".to_string())).await?;
+    assert_eq!(session.stop_reason, Some(StopReason::ToolErrorStorm));
+    assert_eq!(session.turn_index, 3);
+    assert_eq!(session.consecutive_tool_errors, 3);
+
+    kernel.flush_events(&mut session).await;
+    let store = StateStore::open(&config.persistence.database_path).await?;
+    let events = store.get_events(&session.id).await?;
+    let storm = events.iter().find(|e| e.event_type == "tool_error_storm").expect("tool_error_storm event");
+    let payload = storm.payload_json().unwrap();
+    assert_eq!(payload["limit"], 2);
+    assert!(payload["last_error"].as_str().unwrap().contains("no_such_tool"), "{}", payload);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_identical_tool_calls_deduplicated() -> Result<()> {
+    let tmp = tempdir()?;
+    std::fs::write(tmp.path().join("notes.txt"), "remember the milk")?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.kernel.max_turns = 10;
+    config.kernel.max_consecutive_tool_errors = Some(2);
+    config.kernel.dedup_tool_calls = true;
+
+    let mut kernel = Kernel::builder(config.clone()).build()?;
+    kernel.init_state().await?;
+    // Reads the same file with the same arguments on every turn
+    let provider = ScriptedProvider::new(|call, _| Reply::call("read_file", serde_json::json!({ "path": "notes.txt" })).with_id(format!("call-{}", call)));
+    kernel.add_client("mock".to_string(), provider.client());
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Go".to_string())).await?;
+    // One real read, then three repeats trip the error storm
+    assert_eq!(session.stop_reason, Some(StopReason::ToolErrorStorm));
+    assert_eq!(session.turn_index, 4);
+
+    let repeat = session.history.iter().flat_map(|m| m.content.iter()).find_map(|c| match c {
+        InferenceContent::ToolResult { tool_use_id, content, is_error } if tool_use_id == "call-3" => Some((content.clone(), *is_error)),
+        _ => None,
+    }).expect("answer to call-3");
+    assert!(repeat.1);
+    assert!(repeat.0.starts_with("[IDENTICAL CALL]") && repeat.0.ends_with("remember the milk"), "{}", repeat.0);
+    assert_eq!(repeat.0.matches("[IDENTICAL CALL]").count(), 1);
+
+    kernel.flush_events(&mut session).await;
+    let store = StateStore::open(&config.persistence.database_path).await?;
+    let verdicts: Vec<_> = store.get_tool_executions(&session.id).await?.into_iter().map(|e| e.verdict).collect();
+    assert_eq!(verdicts, vec!["ALLOW", "deduplicated", "deduplicated", "deduplicated"]);
+    let events = store.get_events(&session.id).await?;
+    let deduped: Vec<_> = events.iter().filter(|e| e.event_type == "tool_call_deduplicated").collect();
+    assert_eq!(deduped.len(), 3);
+    assert_eq!(deduped[2].payload_json().unwrap()["repeat_of"], "call-0");
+    Ok(())
+}
+
+/// Produces a 10,000-character report as an artifact.
+struct ReportTool;
+
+#[async_trait::async_trait]
+impl Tool for ReportTool {
+    fn name(&self) -> &str {
+        "build_report"
+    }
+
+    fn description(&self) -> &str {
+        "Build a report"
+    }
+
+    fn parameters_schema(&self) -> serde_json::Value {
+        serde_json::json!({ "type": "object" })
+    }
+
+    async fn execute(&self, _params: serde_json::Value, _ctx: &ToolContext) -> std::result::Result<ToolOutput, ToolError> {
+        let report: String = (0..1000).map(|i| format!("row {:05}\n", i)).collect();
+        Ok(ToolOutput::new("Report built".to_string()).with_artifact(Artifact::text("text/plain", report)))
+    }
+}
+
+#[tokio::test]
+async fn test_tool_artifacts_stored_and_fetched() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+
+    let mut registry = bedrock::tools::builtins::create_default_registry();
+    registry.register(Box::new(ReportTool))?;
+    let mut kernel = Kernel::builder(config).with_tool_registry(registry).build()?;
+    kernel.init_state().await?;
+    // Builds a report, then fetches a slice of it by the id in the handle
+    let provider = ScriptedProvider::new(|call, request| {
+        let artifact_id = request.messages.iter().flat_map(|m| m.content.iter()).find_map(|c| match c {
+            InferenceContent::ToolResult { content, .. } => content.split("[artifact ").nth(1).and_then(|rest| rest.split(':').next()).map(str::to_string),
+            _ => None,
+        });
+        match call {
+            0 => Reply::call("build_report", serde_json::json!({})),
+            1 => Reply::call("fetch_artifact", serde_json::json!({ "id": artifact_id.unwrap_or_default(), "offset": 20, "length": 10 })).with_id("call-1"),
+            _ => Reply::text("Done"),
+        }
+    });
+    kernel.add_client("mock".to_string(), provider.client());
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Build the report".to_string())).await?;
+
+    let results: HashMap<String, String> = session.history.iter()
+        .flat_map(|m| m.content.iter())
+        .filter_map(|c| match c {
+            InferenceContent::ToolResult { tool_use_id, content, .. } => Some((tool_use_id.clone(), content.clone())),
+            _ => None,
+        })
+        .collect();
+    // The report itself never entered the context, only its handle and preview
+    let handle = &results["call-0"];
+    assert!(handle.starts_with("Report built\n\n[artifact art_"), "{}", handle);
+    assert!(handle.contains("text/plain, 10000 bytes") && !handle.contains("row 00999"), "{}", handle);
+    assert!(results["call-1"].contains("chars 20-30 of 10000]\nrow 00002\n"), "{}", results["call-1"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tools_disabled_for_pure_chat() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.tools.enabled = false;
+
+    let mut kernel = Kernel::builder(config.clone()).build()?;
+    kernel.init_state().await?;
+    // Disabled on purpose, so nothing to warn about
+    assert!(kernel.tool_registry_problem().await.is_none());
+    let provider = text_provider("Hi there");
+    kernel.add_client("mock".to_string(), provider.client());
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Hello".to_string())).await?;
+    assert_eq!(provider.requests().iter().map(|r| r.tools.as_ref().map(Vec::len)).collect::<Vec<_>>(), vec![None]);
+
+    // Conversation is still persisted
+    let store = StateStore::open(&config.persistence.database_path).await?;
+    let roles: Vec<String> = store.get_messages(&session.id).await?.into_iter().map(|m| m.role).collect();
+    assert_eq!(roles, vec!["user", "assistant"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_switch_model_and_provider_mid_session() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.persistence.snapshot_interval_turns = 1;
+    for name in ["mock", "big"] {
+        config.providers.insert(name.to_string(), ProviderConfig {
+            kind: "mock".to_string(),
+            api_key_env: None,
+            base_url: None,
+            auth: None,
+            tool_results: Default::default(),
+            extra_body: None,
+            strip_thinking: true,
+        });
+    }
+
+    let mut kernel = Kernel::builder(config.clone()).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), text_provider("small answer").client());
+    kernel.add_client("big".to_string(), text_provider("big answer").client());
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Easy question".to_string())).await?;
+
+    // Invalid switches leave the current agent alone
+    assert!(kernel.switch_agent(&session, None, Some("nope".to_string())).is_err());
+    assert!(kernel.switch_agent(&session, Some(" ".to_string()), None).is_err());
+    assert_eq!((kernel.config().agent.provider.as_str(), kernel.config().agent.model.as_str()), ("mock", "test-model"));
+
+    kernel.switch_agent(&session, Some("big-model".to_string()), Some("big".to_string()))?;
+    kernel.run(&mut session, Some("Hard question".to_string())).await?;
+    assert_eq!(session.final_answer().as_deref(), Some("big answer"));
+    // History carried over
+    assert_eq!(session.history.len(), 4);
+
+    kernel.flush_events(&mut session).await;
+    let store = StateStore::open(&config.persistence.database_path).await?;
+    let switches: Vec<serde_json::Value> = store.get_events(&session.id).await?.iter()
+        .filter(|e| e.event_type == "model_switched")
+        .filter_map(|e| e.payload_json())
+        .collect();
+    assert_eq!(switches.len(), 1);
+    assert_eq!(switches[0]["previous_provider"], "mock");
+    assert_eq!(switches[0]["model"], "big-model");
+
+    // Resuming restores the snapshot's agent, unless one was given explicitly
+    drop(store);
+    drop(kernel);
+    let mut kernel = Kernel::builder(config.clone()).build()?;
+    kernel.init_state().await?;
+    kernel.resume_session(&session.id).await?;
+    assert_eq!(kernel.config().agent.model, "big-model");
+    let mut kernel = Kernel::builder(config).pin_agent(true).build()?;
+    kernel.init_state().await?;
+    kernel.resume_session(&session.id).await?;
+    assert_eq!(kernel.config().agent.model, "test-model");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tool_results_captured_as_memories() -> Result<()> {
+    let tmp = tempdir()?;
+    std::fs::write(tmp.path().join("build.log"), "error[E0308]: mismatched types in src/main.rs:12, expected u32")?;
+    std::fs::write(tmp.path().join("key.txt"), "see .env")?;
+    std::fs::write(tmp.path().join("noise.txt"), "lorem ipsum dolor sit amet, consectetur adipiscing elit")?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(
+        harness_dir.join("capture.lua"),
+        r#"
+        function on_memory_capture(result)
+            if result.args.path == "key.txt" then
+                return MODIFY, { worth_remembering = true, content = "The API key lives in .env" }
+            elseif result.args.path == "noise.txt" then
+                return MODIFY, { worth_remembering = false }
+            end
+            return ALLOW
+        end
+        "#,
+    )?;
+
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+    config.embeddings = Some(EmbeddingConfig::NoOp);
+    config.memory.capture_tool_results = true;
+    config.memory.capture_min_chars = 40;
+
+    let paths = ["build.log", "key.txt", "noise.txt", "missing.txt"];
+    let read = Reply::calls(paths.iter().map(|path| ("read_file", serde_json::json!({ "path": path }))));
+    let provider = ScriptedProvider::replies(vec![read, Reply::text("Done")]);
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), provider.client());
+    kernel.init_clients().await?;
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Why does the build fail?".to_string())).await?;
+    kernel.flush_events(&mut session).await;
+    drop(kernel);
+
+    // Long enough by default; short but flagged by the harness. Not the
+    // result the harness declined, nor the failed read.
+    let store = StateStore::open(&db_path).await?;
+    let memories = store.get_memories(Some(&session.id)).await?;
+    assert_eq!(memories.len(), 2, "{:?}", memories.iter().map(|m| &m.content).collect::<Vec<_>>());
+    let build = memories.iter().find(|m| m.content.contains("E0308")).expect("build log captured");
+    let metadata: serde_json::Value = serde_json::from_str(&build.metadata)?;
+    assert_eq!(metadata["source"], "tool_result");
+    assert_eq!(metadata["tool"], "read_file");
+    assert_eq!(metadata["args"], serde_json::json!({ "path": "build.log" }));
+    assert_eq!(metadata["tool_call_id"], "call-0");
+    assert!(memories.iter().any(|m| m.content == "The API key lives in .env"), "{:?}", memories.iter().map(|m| &m.content).collect::<Vec<_>>());
+
+    let events = store.get_events(&session.id).await?;
+    assert_eq!(events.iter().filter(|e| e.event_type == "memory_captured").count(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_safe_mode_denies_until_unlocked() -> Result<()> {
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    // Scratch files may be written; everything else stays locked
+    std::fs::write(
+        harness_dir.join("unlock.lua"),
+        r#"
+        function on_tool_call(call)
+            if call.name == "write_file" and call.args.path == "scratch.txt" then
+                bedrock.unlock_tool("write_file")
+            end
+            return ALLOW
+        end
+        "#,
+    )?;
+
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+    config.tools.safe = true;
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    // Writes two files and runs a command in one turn, then answers
+    let calls = Reply::calls([
+        ("write_file", serde_json::json!({ "path": "notes.md", "content": "draft" })),
+        ("write_file", serde_json::json!({ "path": "scratch.txt", "content": "tmp" })),
+        ("shell_exec", serde_json::json!({ "command": "touch pwned" })),
+    ]);
+    kernel.add_client("mock".to_string(), ScriptedProvider::replies(vec![calls, Reply::text("Done")]).client());
+    kernel.init_harness().await?;
+    assert!(kernel.tools().iter().any(|t| t.name == "shell_exec" && t.locked));
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Take notes".to_string())).await?;
+    kernel.flush_events(&mut session).await;
+
+    let results: HashMap<String, (String, bool)> = session.history.iter()
+        .flat_map(|m| m.content.iter())
+        .filter_map(|c| match c {
+            InferenceContent::ToolResult { tool_use_id, content, is_error } => Some((tool_use_id.clone(), (content.clone(), *is_error))),
+            _ => None,
+        })
+        .collect();
+    // The unlock happened while the turn's calls were vetted, so it covers both writes
+    assert!(!results["call-0"].1 && !results["call-1"].1, "{:?}", results);
+    assert!(results["call-2"].1 && results["call-2"].0.contains("locked in safe mode"), "{:?}", results["call-2"]);
+    assert!(!tmp.path().join("pwned").exists());
+    assert!(!kernel.tools().iter().find(|t| t.name == "write_file").unwrap().locked);
+    drop(kernel);
+
+    let store = StateStore::open(&db_path).await?;
+    let events = store.get_events(&session.id).await?;
+    let unlocked: Vec<_> = events.iter().filter(|e| e.event_type == "tool_unlocked").collect();
+    assert_eq!(unlocked.len(), 1);
+    assert_eq!(unlocked[0].payload_json().unwrap()["tool"], "write_file");
+    let locked: Vec<_> = events.iter().filter(|e| e.event_type == "tool_locked").collect();
+    assert_eq!(locked.len(), 1);
+    assert_eq!(locked[0].payload_json().unwrap()["tool_call_id"], "call-2");
+    Ok(())
+}
+
+async fn run_with_request_hook(persist: bool) -> Result<(SessionState, Vec<String>, Vec<EventRow>, SessionState)> {
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(harness_dir.join("reminder.lua"), format!(r#"
+function on_before_request(req)
+    req:prepend({{ role = "user", content = {{{{ type = "text", text = "Reply in French." }}}} }})
+    if {persist} then req:persist() end
+end
+"#))?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+
+    let provider = text_provider("Bonjour");
+    let mut kernel = Kernel::builder(config.clone()).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), provider.client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Hello".to_string())).await?;
+    kernel.run(&mut session, Some("Thanks".to_string())).await?;
+    kernel.flush_events(&mut session).await;
+    drop(kernel);
+
+    let store = StateStore::open(&config.persistence.database_path).await?;
+    let events = store.get_events(&session.id).await?;
+    // The text of the first message of each request
+    let first_messages = provider.requests().iter().map(|r| match r.messages.first().and_then(|m| m.content.first()) {
+        Some(InferenceContent::Text { text }) => text.clone(),
+        _ => String::new(),
+    }).collect();
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    let resumed = kernel.resume_session(&session.id).await?;
+    Ok((session, first_messages, events, resumed))
+}
+
+#[tokio::test]
+async fn test_before_request_hook_edits_sent_messages_only() -> Result<()> {
+    let (session, first_messages, events, resumed) = run_with_request_hook(false).await?;
+    // Every request carried the reminder, but the history never did
+    assert_eq!(first_messages, vec!["Reply in French.", "Reply in French."]);
+    assert_eq!(session.history.len(), 4);
+    assert!(matches!(&session.history[0].content[0], InferenceContent::Text { text } if text == "Hello"));
+
+    let rewritten: Vec<_> = events.iter().filter(|e| e.event_type == "request_rewritten").collect();
+    assert_eq!(rewritten.len(), 2);
+    let payload = rewritten[1].payload_json().unwrap();
+    assert_eq!(payload["messages_before"], 3);
+    assert_eq!(payload["messages_sent"], 4);
+    assert_eq!(payload["persisted"], false);
+    assert_eq!(resumed.history.len(), 4);
+
+    // Persisted edits stay in the history, so the second request adds another reminder
+    let (session, _, _, resumed) = run_with_request_hook(true).await?;
+    assert_eq!(session.history.len(), 6);
+    assert!(matches!(&session.history[0].content[0], InferenceContent::Text { text } if text == "Reply in French."));
+    assert!(matches!(&session.history[1].content[0], InferenceContent::Text { text } if text == "Reply in French."));
+    // ...and in the stored session
+    assert_eq!(resumed.history.len(), 6);
+    assert!(matches!(&resumed.history[1].content[0], InferenceContent::Text { text } if text == "Reply in French."));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_before_request_hook_runs_once_per_request_across_retries() -> Result<()> {
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(harness_dir.join("reminder.lua"), r#"
+function on_before_request(req)
+    req:prepend({ role = "user", content = {{ type = "text", text = "Reply in French." }} })
+end
+"#)?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+    config.inference.retry.base_delay_ms = 1;
+    config.inference.retry.max_delay_ms = 5;
+
+    let provider = overloaded_provider(2);
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.add_client("mock".to_string(), provider.client());
+    kernel.init_harness().await?;
+    let mut rewrites = kernel.subscribe();
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Hello".to_string())).await?;
+    kernel.flush_events(&mut session).await;
+
+    // Three attempts, each with the reminder, from a single hook run
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 3);
+    assert!(requests.iter().all(|r| r.messages.len() == 2));
+    let mut count = 0;
+    while let Ok((_, event)) = rewrites.try_recv() {
+        count += matches!(event, KernelEvent::RequestRewritten { .. }) as usize;
+    }
+    assert_eq!(count, 1);
+    Ok(())
+}
+
+async fn queue_policy_kernel(policy: QueueEmptyPolicy, harness: Option<&str>) -> Result<(Kernel, tempfile::TempDir)> {
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    if let Some(code) = harness {
+        std::fs::write(harness_dir.join("policy.lua"), code)?;
+    }
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.kernel.on_queue_empty = policy;
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), text_provider("Done").client());
+    kernel.init_harness().await?;
+    Ok((kernel, tmp))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_queue_empty_policies() -> Result<()> {
+    // Stop: every queued task runs, then the run ends
+    let (mut kernel, _tmp) = queue_policy_kernel(QueueEmptyPolicy::Stop, None).await?;
+    let mut session = kernel.create_session();
+    session.queue.lock().await.push_back("First".to_string());
+    kernel.run(&mut session, Some("Second".to_string())).await?;
+    assert_eq!(session.turn_index, 2);
+    assert_eq!(session.stop_reason, Some(StopReason::Completed));
+
+    // CompletionCheck: the harness queues one follow-up, then lets the run end
+    let (mut kernel, _tmp) = queue_policy_kernel(QueueEmptyPolicy::CompletionCheck, Some(r#"
+checks = 0
+function should_continue(state)
+    checks = checks + 1
+    if checks == 1 then
+        return MODIFY, { "Double-check your answer to turn " .. state.turn_count }
+    end
+    return ALLOW
+end
+"#)).await?;
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Solve it".to_string())).await?;
+    assert_eq!(session.turn_index, 2);
+    assert!(matches!(&session.history[2].content[0], InferenceContent::Text { text } if text == "Double-check your answer to turn 1"));
+
+    // AwaitMore: tasks queued from outside are picked up until an interrupt
+    let (mut kernel, _tmp) = queue_policy_kernel(QueueEmptyPolicy::AwaitMore, None).await?;
+    let mut session = kernel.create_session();
+    let queue = session.queue.clone();
+    let interrupt = kernel.interrupt_handle();
+    let feeder = tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        queue.lock().await.push_back("Later".to_string());
+        while !queue.lock().await.is_empty() {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        interrupt.interrupt();
+    });
+    kernel.run(&mut session, Some("Now".to_string())).await?;
+    feeder.await?;
+    assert_eq!(session.turn_index, 2);
+    assert_eq!(session.stop_reason, Some(StopReason::Interrupted));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_replay_recorded_session() -> Result<()> {
+    use bedrock::kernel::regression::{self, Recording};
+    use bedrock::persistence::transcript::{self, TranscriptFormat, TranscriptOptions};
+
+    let tmp = tempdir()?;
+    std::fs::write(tmp.path().join("notes.txt"), "remember the milk")?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+
+    let mut kernel = Kernel::builder(config.clone()).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), read_notes_provider().client());
+    kernel.init_harness().await?;
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Read my notes".to_string())).await?;
+    kernel.end_session(&mut session).await?;
+    drop(kernel);
+
+    // Record the session as `bedrock cat --format json` would print it
+    let store = StateStore::open(&db_path).await?;
+    let entries = transcript::load(&store, &session.id, TranscriptOptions::default()).await?;
+    let recording = Recording::parse(&transcript::render(&entries, TranscriptFormat::Json)?)?;
+    assert_eq!(recording.calls.len(), 1);
+    assert_eq!(recording.answer, "Done");
+    // Loading straight from the store gives the same recording
+    assert!(recording.diff(&Recording::load(&store, &session.id).await?).is_empty());
+
+    // Unchanged harness and code: the replay matches
+    let diffs = regression::replay(&config, &recording).await?;
+    assert!(diffs.is_empty(), "{:?}", diffs);
+
+    // A harness that now blocks the read shows up as a diff
+    std::fs::write(
+        harness_dir.join("guard.lua"),
+        r#"function on_tool_call(call) return REJECT, "no reading" end"#,
+    )?;
+    let diffs = regression::replay(&config, &recording).await?;
+    assert_eq!(diffs.len(), 1, "{:?}", diffs);
+    assert!(diffs[0].starts_with("tool call 1:") && diffs[0].contains("no reading"), "{}", diffs[0]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fork_session_at_turn() -> Result<()> {
+    let tmp = tempdir()?;
+    std::fs::write(tmp.path().join("notes.txt"), "remember the milk")?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), read_notes_provider().client());
+    kernel.init_harness().await?;
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Read my notes".to_string())).await?;
+    kernel.end_session(&mut session).await?;
+    assert_eq!(session.turn_index, 2);
+
+    // Branch before the final answer: the read and its result are kept
+    let fork_id = kernel.fork_session(&session.id, 1, None).await?;
+    assert_ne!(fork_id, session.id);
+    let fork = kernel.resume_session(&fork_id).await?;
+    assert_eq!(fork.turn_index, 1);
+    assert_eq!(fork.history.len(), 3);
+    assert!(fork.final_answer().is_none_or(|answer| answer != "Done"));
+
+    // The original is untouched
+    let original = kernel.resume_session(&session.id).await?;
+    assert_eq!(original.history.len(), session.history.len());
+    assert!(kernel.fork_session(&session.id, 0, None).await.is_err());
+
+    // A bad or taken name is refused before anything is copied
+    let conn = StateStore::open(tmp.path().join("test.db").to_str().unwrap()).await?.get_connection()?;
+    let count_sessions = || async {
+        let mut rows = conn.query("SELECT COUNT(DISTINCT session_id) FROM messages", ()).await?;
+        anyhow::Ok(rows.next().await?.unwrap().get::<i64>(0)?)
+    };
+    let before = count_sessions().await?;
+    assert!(kernel.fork_session(&session.id, 1, Some("not a name!")).await.is_err());
+    let named = kernel.fork_session(&session.id, 1, Some("milk-alt")).await?;
+    assert_eq!(kernel.resume_session("milk-alt").await?.id, named);
+    assert!(kernel.fork_session(&session.id, 1, Some("milk-alt")).await.is_err());
+    assert_eq!(count_sessions().await?, before + 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_replay_sends_follow_up_prompts() -> Result<()> {
+    use bedrock::kernel::regression::{self, Recording};
+
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+
+    // A REPL session: the second prompt was sent after the first run ended
+    let recording = Recording::parse(r#"
+{"type":"user","turn_index":0,"text":"Read the notes"}
+{"type":"tool_call","turn_index":0,"id":"call_0_0","name":"read_file","args":{"path":"notes.txt"},"output":"milk","is_error":false,"duration_ms":1,"verdict":"ALLOW"}
+{"type":"assistant","turn_index":1,"text":"Buy milk."}
+{"type":"user","turn_index":2,"text":"Anything else?"}
+{"type":"assistant","turn_index":2,"text":"No."}
+"#)?;
+    assert_eq!(recording.prompts.len(), 2);
+    let diffs = regression::replay(&config, &recording).await?;
+    assert!(diffs.is_empty(), "{:?}", diffs);
+
+    // A harness change in the first run still shows up, and the second prompt is still sent
+    std::fs::write(
+        harness_dir.join("guard.lua"),
+        r#"function on_tool_call(call) return REJECT, "no reading" end"#,
+    )?;
+    let diffs = regression::replay(&config, &recording).await?;
+    assert!(diffs[0].starts_with("tool call 1:"), "{:?}", diffs);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_replay_leaves_queued_tasks_to_the_harness() -> Result<()> {
+    use bedrock::kernel::regression::{self, Recording};
+
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(
+        harness_dir.join("check.lua"),
+        r#"
+        local checked = false
+        return {
+            on_task_complete = function(info)
+                if not checked then
+                    checked = true
+                    return MODIFY, { "Double-check" }
+                end
+            end,
+        }
+        "#,
+    )?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+
+    // The harness's task is stored as queued, not as a prompt
+    let mut kernel = Kernel::builder(config.clone()).build()?;
+    kernel.init_state().await?;
+    let provider = ScriptedProvider::replies(vec![Reply::text("Buy milk."), Reply::text("Still milk."), Reply::text("No.")]);
+    kernel.add_client("mock".to_string(), provider.client());
+    kernel.init_harness().await?;
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Read the notes".to_string())).await?;
+    kernel.run(&mut session, Some("Anything else?".to_string())).await?;
+    kernel.end_session(&mut session).await?;
+    drop(kernel);
+    let store = StateStore::open(&db_path).await?;
+    let recording = Recording::load(&store, &session.id).await?;
+    assert_eq!(recording.prompts, ["Read the notes", "Anything else?"]);
+    let queued: Vec<bool> = recording.user_messages.iter().map(|(_, injected)| *injected).collect();
+    assert_eq!(queued, [false, true, false]);
+
+    // Replayed with the harness, its task is queued again rather than sent
+    let diffs = regression::replay(&config, &recording).await?;
+    assert!(diffs.is_empty(), "{:?}", diffs);
+
+    // A harness that stops queueing it shows up in the user messages
+    std::fs::remove_file(harness_dir.join("check.lua"))?;
+    let diffs = regression::replay(&config, &recording).await?;
+    assert!(diffs[0].starts_with(r#"user message 2: expected queued "Double-check", got prompt"#), "{:?}", diffs);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_replay_modified_call_and_awaiting_run() -> Result<()> {
+    use bedrock::kernel::config::QueueEmptyPolicy;
+    use bedrock::kernel::regression::{self, Recording};
+
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(
+        harness_dir.join("redirect.lua"),
+        r#"function on_tool_call(call) return MODIFY, { path = "other.txt" } end"#,
+    )?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+    // Would wait forever for another task in a live run
+    config.kernel.on_queue_empty = QueueEmptyPolicy::AwaitMore;
+
+    // Recorded with the same harness: the args are the model's, before the MODIFY
+    let recording = Recording::parse(r#"
+{"type":"user","turn_index":0,"text":"Read the notes"}
+{"type":"tool_call","turn_index":0,"id":"call_0_0","name":"read_file","args":{"path":"notes.txt"},"output":"milk","is_error":false,"duration_ms":1,"verdict":"MODIFY: {\"path\":\"other.txt\"}"}
+{"type":"assistant","turn_index":1,"text":"Buy milk."}
+"#)?;
+    let diffs = tokio::time::timeout(std::time::Duration::from_secs(10), regression::replay(&config, &recording)).await??;
+    assert!(diffs.is_empty(), "{:?}", diffs);
+    Ok(())
+}
+
+async fn run_with_thinking(strip: bool) -> Result<(SessionState, String, bool)> {
+    let tmp = tempdir()?;
+    std::fs::write(tmp.path().join("notes.txt"), "remember the milk")?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+
+    // Reasons before reading `notes.txt`, then answers
+    let read = Reply {
+        thinking: vec!["The notes ".to_string(), "should help".to_string()],
+        ..Reply::call("read_file", serde_json::json!({ "path": "notes.txt" }))
+    };
+    let provider = ScriptedProvider::replies(vec![read, Reply::text("Done")]);
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client(
+        "mock".to_string(),
+        provider.client().with_strip_thinking(strip),
+    );
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Read my notes".to_string())).await?;
+    kernel.flush_events(&mut session).await;
+    drop(kernel);
+
+    let store = StateStore::open(&db_path).await?;
+    let events = store.get_events(&session.id).await?;
+    let reasoning = events.iter().find(|e| e.event_type == "reasoning").expect("reasoning event");
+    let payload: serde_json::Value = serde_json::from_str(&reasoning.payload)?;
+    assert_eq!(payload["content"], "The notes should help");
+    let messages = store.get_messages(&session.id).await?;
+    let first_reply = messages.iter().find(|m| m.role == "assistant").expect("assistant message");
+    // Whether any request carried reasoning back
+    let saw_thinking = provider.requests().iter().flat_map(|r| r.messages.iter()).flat_map(|m| m.content.iter())
+        .any(|c| matches!(c, InferenceContent::Thinking { .. }));
+    Ok((session, first_reply.content.clone(), saw_thinking))
+}
+
+#[tokio::test]
+async fn test_thinking_kept_in_session_and_stripped_from_requests() -> Result<()> {
+    let (session, stored, saw_thinking) = run_with_thinking(true).await?;
+    assert!(!saw_thinking);
+    assert!(matches!(
+        &session.history[1].content[0],
+        InferenceContent::Thinking { content } if content == "The notes should help"
+    ));
+    let parts: serde_json::Value = serde_json::from_str(&stored)?;
+    assert_eq!(parts[0], serde_json::json!({ "type": "thinking", "content": "The notes should help" }));
+
+    let (_, _, saw_thinking) = run_with_thinking(false).await?;
+    assert!(saw_thinking);
+    Ok(())
+}
+
+async fn run_with_fallbacks(fallbacks: &[(&str, Option<&str>)]) -> Result<(SessionState, Result<()>, Vec<EventRow>)> {
+    let tmp = tempdir()?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+    config.inference.retry.max_attempts = 1;
+    config.agent.fallbacks = fallbacks
+        .iter()
+        .map(|(provider, model)| FallbackTarget { provider: provider.to_string(), model: model.map(str::to_string) })
+        .collect();
+    config.providers.insert("local".to_string(), ProviderConfig {
+        kind: "mock".to_string(),
+        api_key_env: None,
+        base_url: Some("Served locally".to_string()),
+        auth: None,
+        tool_results: Default::default(),
+        extra_body: None,
+        strip_thinking: true,
+    });
+
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    // Both always fail with a rate limit, one before streaming and one in the stream
+    let in_stream = Reply { stream_error: Some("overloaded_error: Overloaded".to_string()), ..Default::default() };
+    kernel.add_client("mock".to_string(), ScriptedProvider::replies(vec![Reply::error("429 Too Many Requests: rate limit exceeded")]).client());
+    kernel.add_client("busy".to_string(), ScriptedProvider::replies(vec![in_stream]).client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    let result = kernel.run(&mut session, Some("Hello".to_string())).await;
+    kernel.flush_events(&mut session).await;
+    drop(kernel);
+    let events = StateStore::open(&db_path).await?.get_events(&session.id).await?;
+    Ok((session, result, events))
+}
+
+#[tokio::test]
+async fn test_provider_failover() -> Result<()> {
+    let (session, result, events) = run_with_fallbacks(&[("busy", None), ("local", Some("llama3.1"))]).await?;
+    result?;
+    assert!(matches!(&session.history[1].content[0], InferenceContent::Text { text } if text == "Served locally"));
+    let failovers: Vec<serde_json::Value> = events
+        .iter()
+        .filter(|e| e.event_type == "provider_failover")
+        .map(|e| serde_json::from_str(&e.payload).unwrap())
+        .collect();
+    assert_eq!(failovers.len(), 2);
+    assert_eq!((failovers[0]["from_provider"].as_str(), failovers[0]["to_provider"].as_str()), (Some("mock"), Some("busy")));
+    assert_eq!(failovers[0]["to_model"], "test-model");
+    assert_eq!((failovers[1]["to_provider"].as_str(), failovers[1]["to_model"].as_str()), (Some("local"), Some("llama3.1")));
+
+    // Without a chain, or once it is exhausted, the error surfaces
+    let (_, result, _) = run_with_fallbacks(&[]).await?;
+    assert!(result.unwrap_err().to_string().contains("rate limit"));
+    let (_, result, _) = run_with_fallbacks(&[("busy", None)]).await?;
+    assert!(result.unwrap_err().to_string().contains("Overloaded"));
+    Ok(())
+}
+
+/// Fails with an overloaded error `failures` times, then answers.
+fn overloaded_provider(failures: usize) -> Arc<ScriptedProvider> {
+    ScriptedProvider::new(move |call, _| match call < failures {
+        true => Reply::error("529 overloaded_error"),
+        false => Reply::text("Recovered"),
+    })
+}
+
+#[tokio::test]
+async fn test_retry_with_backoff() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.inference.retry.base_delay_ms = 1;
+    config.inference.retry.max_delay_ms = 5;
+
+    let mut kernel = Kernel::builder(config).build()?;
+    let provider = overloaded_provider(2);
+    kernel.add_client("mock".to_string(), provider.client());
+    let mut session = kernel.create_session();
+    kernel.run(&mut session, Some("Hello".to_string())).await?;
+    assert!(matches!(&session.history[1].content[0], InferenceContent::Text { text } if text == "Recovered"));
+    assert_eq!(provider.requests().len(), 3);
+
+    // A third failure uses up the default three attempts
+    kernel.add_client("mock".to_string(), overloaded_provider(3).client());
+    let mut session = kernel.create_session();
+    let err = kernel.run(&mut session, Some("Hello".to_string())).await.unwrap_err();
+    assert!(err.to_string().contains("overloaded"), "{}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_history_compaction() -> Result<()> {
+    let tmp = tempdir()?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = db_path.clone();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+    config.compaction.enabled = true;
+    // Crossed only by the third request
+    config.compaction.threshold_tokens = 300;
+    config.compaction.keep_turns = 1;
+    config.compaction.provider = Some("cheap".to_string());
+    config.compaction.model = Some("cheap-model".to_string());
+    config.providers.insert("cheap".to_string(), ProviderConfig {
+        kind: "mock".to_string(),
+        api_key_env: None,
+        base_url: Some("The user asked three long questions.".to_string()),
+        auth: None,
+        tool_results: Default::default(),
+        extra_body: None,
+        strip_thinking: true,
+    });
+
+    let provider = text_provider("Answer");
+    let mut kernel = Kernel::builder(config).build()?;
+    kernel.init_state().await?;
+    kernel.add_client("mock".to_string(), provider.client());
+    kernel.init_harness().await?;
+
+    let mut session = kernel.create_session();
+    for i in 0..3 {
+        kernel.run(&mut session, Some(format!("Question {}: {}", i, "x".repeat(300)))).await?;
+    }
+    kernel.flush_events(&mut session).await;
+
+    // The third request no longer carries the first two turns
+    let requests: Vec<_> = provider.requests().into_iter().map(|r| r.messages).collect();
+    assert_eq!(requests[1].len(), 3);
+    assert_eq!(requests[2].len(), 1);
+    match &requests[2][0].content[..] {
+        [InferenceContent::Text { text: summary }, InferenceContent::Text { text: question }] => {
+            assert!(summary.contains("The user asked three long questions."), "{}", summary);
+            assert!(question.starts_with("Question 2"));
+        }
+        other => panic!("expected a summary and the question, got {:?}", other),
+    }
+    drop(kernel);
+
+    let store = StateStore::open(&db_path).await?;
+    let events = store.get_events(&session.id).await?;
+    let compactions: Vec<&EventRow> = events.iter().filter(|e| e.event_type == "history_compacted").collect();
+    assert_eq!(compactions.len(), 1);
+    let payload: serde_json::Value = serde_json::from_str(&compactions[0].payload)?;
+    assert_eq!(payload["removed_messages"], 4);
+    assert_eq!(payload["model"], "cheap-model");
+    assert!(payload["tokens_after"].as_u64() < payload["tokens_before"].as_u64());
+    // The full conversation stays in the database
+    assert_eq!(store.get_messages(&session.id).await?.len(), 6);
+    // The summary call is billed to the session
+    let costs = store.get_turn_costs(&session.id).await?;
+    assert!(costs.iter().any(|c| c.provider == "cheap" && c.model == "cheap-model"), "{:?}", costs);
+    Ok(())
+}
+
+/// Config for `bedrock serve` on a mock provider, with its token set in the
+/// environment, and a client that sends it.
+fn serve_config(tmp: &std::path::Path, reply: &str) -> (BedrockConfig, reqwest::Client) {
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.join("test.db").to_str().unwrap().to_string();
+    config.harness.directory = tmp.join("harnesses").to_str().unwrap().to_string();
+    config.providers.insert("mock".to_string(), ProviderConfig {
+        kind: "mock".to_string(),
+        api_key_env: None,
+        base_url: Some(reply.to_string()),
+        auth: None,
+        tool_results: Default::default(),
+        extra_body: None,
+        strip_thinking: true,
+    });
+    config.server.token_env = "BEDROCK_TEST_SERVER_TOKEN".to_string();
+    std::env::set_var("BEDROCK_TEST_SERVER_TOKEN", "s3cret");
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(reqwest::header::AUTHORIZATION, "Bearer s3cret".parse().unwrap());
+    (config, reqwest::Client::builder().default_headers(headers).build().unwrap())
+}
+
+#[tokio::test]
+async fn test_serve_http_api() -> Result<()> {
+    let tmp = tempdir()?;
+    let db_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    let (config, http) = serve_config(tmp.path(), "Served over HTTP");
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(bedrock::server::serve(config, listener, async {
+        stop_rx.await.ok();
+    }));
+
+    // Requests without the token are refused
+    let anonymous = reqwest::Client::new().post(format!("{}/sessions", base)).send().await?;
+    assert_eq!(anonymous.status(), reqwest::StatusCode::UNAUTHORIZED);
+    let wrong = reqwest::Client::new().get(format!("{}/sessions/web/events", base)).bearer_auth("guess").send().await?;
+    assert_eq!(wrong.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let created: serde_json::Value = http
+        .post(format!("{}/sessions", base))
+        .json(&serde_json::json!({ "name": "web" }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let session_id = created["session_id"].as_str().unwrap().to_string();
+
+    // Sessions can be addressed by name
+    let queued = http.post(format!("{}/sessions/web/messages", base)).json(&serde_json::json!({ "prompt": "Hello" })).send().await?;
+    assert_eq!(queued.status(), reqwest::StatusCode::ACCEPTED);
+    let missing = http.post(format!("{}/sessions/nope/messages", base)).json(&serde_json::json!({ "prompt": "Hi" })).send().await?;
+    assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+
+    let mut events = http.get(format!("{}/sessions/{}/events", base, session_id)).send().await?.error_for_status()?;
+    assert!(events.headers()["content-type"].to_str()?.starts_with("text/event-stream"));
+    let mut body = String::new();
+    tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        while let Some(chunk) = events.chunk().await? {
+            body.push_str(&String::from_utf8_lossy(&chunk));
+            if body.contains("event: turn_end") {
+                break;
+            }
+        }
+        anyhow::Ok(())
+    })
+    .await??;
+    assert!(body.contains("event: agent_start"), "{}", body);
+    assert!(body.contains("Served over HTTP"), "{}", body);
+    assert!(body.contains("event: message_end"), "{}", body);
+
+    // Reconnecting after the last seen id skips what was already sent
+    let last_id: i64 = body.lines().rev().find_map(|l| l.strip_prefix("id: ")).unwrap().parse()?;
+    let mut resumed = http
+        .get(format!("{}/sessions/{}/events", base, session_id))
+        .header("Last-Event-ID", (last_id - 1).to_string())
+        .send()
+        .await?;
+    let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), resumed.chunk()).await??.unwrap();
+    assert!(String::from_utf8_lossy(&chunk).contains("event: turn_end"));
+
+    // Shutting down aborts and ends the session
+    stop_tx.send(()).unwrap();
+    drop((events, resumed));
+    server.await??;
+    let stored = StateStore::open(&db_path).await?.get_events(&session_id).await?;
+    assert!(stored.iter().any(|e| e.event_type == "aborted"));
+    assert_eq!(stored.last().unwrap().event_type, "agent_end");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_serve_ends_idle_sessions() -> Result<()> {
+    let tmp = tempdir()?;
+    let (mut config, http) = serve_config(tmp.path(), "Soon idle");
+    config.server.idle_timeout_secs = 1;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(bedrock::server::serve(config, listener, async {
+        stop_rx.await.ok();
+    }));
+
+    let created: serde_json::Value = http.post(format!("{}/sessions", base)).send().await?.error_for_status()?.json().await?;
+    let session_id = created["session_id"].as_str().unwrap().to_string();
+    http.post(format!("{}/sessions/{}/messages", base, session_id))
+        .json(&serde_json::json!({ "prompt": "Hello" }))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    // Once answered and idle, the session is ended and no longer takes prompts
+    let store = StateStore::open(&tmp.path().join("test.db").to_string_lossy()).await?;
+    tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        while !store.get_events(&session_id).await?.iter().any(|e| e.event_type == "agent_end") {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        anyhow::Ok(())
+    })
+    .await??;
+    let events = store.get_events(&session_id).await?;
+    assert!(events.iter().any(|e| e.event_type == "message_end"));
+    let late = http.post(format!("{}/sessions/{}/messages", base, session_id)).json(&serde_json::json!({ "prompt": "Hi" })).send().await?;
+    assert!(matches!(late.status(), reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::CONFLICT), "{}", late.status());
+
+    stop_tx.send(()).unwrap();
+    server.await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_call_tool_applies_actions() -> Result<()> {
+    let tmp = tempdir()?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.harness.directory = tmp.path().join("harnesses").to_str().unwrap().to_string();
+    let mut kernel = Kernel::builder(config).quiet(true).interactive(false).build()?;
+    kernel.init_state().await?;
+    kernel.init_harness().await?;
+
+    // A direct call's action metadata is carried out as in a turn
+    let mut session = kernel.create_session();
+    let steps = serde_json::json!({ "steps": [{ "title": "Read the code", "status": "in_progress" }] });
+    let (_, is_error) = kernel.call_tool(&mut session, "update_plan", steps).await;
+    assert!(!is_error);
+    let plan = StateStore::open(&tmp.path().join("test.db").to_string_lossy()).await?.get_plan(&session.id).await?;
+    assert_eq!(plan.len(), 1);
+    assert_eq!(plan[0].title, "Read the code");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mcp_serve_run_agent() -> Result<()> {
+    let tmp = tempdir()?;
+    let harness_dir = tmp.path().join("harnesses");
+    std::fs::create_dir(&harness_dir)?;
+    std::fs::write(harness_dir.join("guard.lua"), r#"
+function on_tool_call(call)
+    if call.name == "shell_exec" then
+        return REJECT, "No shell over MCP"
+    end
+end
+"#)?;
+    let mut config = BedrockConfig::default();
+    config.kernel.workspace_root = tmp.path().to_str().unwrap().to_string();
+    config.persistence.database_path = tmp.path().join("test.db").to_str().unwrap().to_string();
+    config.harness.directory = harness_dir.to_str().unwrap().to_string();
+    config.providers.insert("mock".to_string(), ProviderConfig {
+        kind: "mock".to_string(),
+        api_key_env: None,
+        base_url: Some("Answered over MCP".to_string()),
+        auth: None,
+        tool_results: Default::default(),
+        extra_body: None,
+        strip_thinking: true,
+    });
+
+    let mut kernel = Kernel::builder(config).quiet(true).interactive(false).build()?;
+    kernel.init_state().await?;
+    kernel.init_clients().await?;
+    kernel.init_harness().await?;
+
+    let requests = [
+        serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" }),
+        serde_json::json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/call", "params": { "name": "run_agent", "arguments": { "prompt": "Hello" } } }),
+        serde_json::json!({ "jsonrpc": "2.0", "id": 3, "method": "tools/call", "params": { "name": "shell_exec", "arguments": { "command": "ls" } } }),
+    ];
+    let input: String = requests.iter().map(|r| format!("{}\n", r)).collect();
+    let mut output = Vec::new();
+    bedrock::server::mcp::McpServer::new(kernel, true)
+        .serve(tokio::io::BufReader::new(input.as_bytes()), &mut output)
+        .await?;
+    let responses: Vec<serde_json::Value> =
+        String::from_utf8(output)?.lines().map(serde_json::from_str).collect::<Result<_, _>>()?;
+
+    assert!(responses[0]["result"]["tools"].as_array().unwrap().iter().any(|t| t["name"] == "run_agent"));
+    let answer = responses[1]["result"]["content"][0]["text"].as_str().unwrap();
+    assert_eq!(responses[1]["result"]["isError"], false, "{}", answer);
+    assert!(answer.contains("Answered over MCP"), "{}", answer);
+    assert!(answer.contains("[session: "), "{}", answer);
+
+    // Direct calls go through the harness like a model's would
+    assert_eq!(responses[2]["result"]["isError"], true);
+    assert!(responses[2]["result"]["content"][0]["text"].as_str().unwrap().contains("No shell over MCP"));
+    Ok(())
+}
@@ -1,5 +1,8 @@
 use anyhow::Result;
-use bedrock::tools::{is_safe_path, ToolError};
+use bedrock::kernel::event::KernelEvent;
+use bedrock::kernel::session::StopReason;
+use bedrock::persistence::state::{EventRow, PlanStatus, PlanStep};
+use bedrock::tools::{contain_path, is_safe_path, ArtifactRef, CitedSource, Source, ToolError};
 use proptest::prelude::*;
 use std::path::{Path, PathBuf};
 use tempfile::tempdir;
@@ -64,4 +67,184 @@ fn test_is_safe_path_known_attacks() {
     
     // Path with dots but not traversal
     assert!(is_safe_path(root, Path::new(".hidden")).is_ok());
+
+    // Symlinks out of the root, whether or not their target exists
+    #[cfg(unix)]
+    {
+        let outside = tempdir().unwrap();
+        std::fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.join("escape")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("new.txt"), root.join("dangling")).unwrap();
+        assert!(matches!(is_safe_path(root, Path::new("escape/secret.txt")), Err(ToolError::PermissionDenied(_))));
+        assert!(matches!(is_safe_path(root, Path::new("escape/new/file.txt")), Err(ToolError::PermissionDenied(_))));
+        assert!(matches!(is_safe_path(root, Path::new("dangling")), Err(ToolError::PermissionDenied(_))));
+
+        // ...unless the target directory is allowed
+        let allowed = vec![outside.path().to_path_buf()];
+        let resolved = contain_path(root, &allowed, Path::new("escape/secret.txt")).unwrap();
+        assert_eq!(resolved, outside.path().canonicalize().unwrap().join("secret.txt"));
+        assert!(contain_path(root, &allowed, &outside.path().join("other.txt")).is_ok());
+    }
+}
+
+/// Finite floats; JSON has no NaN or infinity.
+fn finite_f64() -> impl Strategy<Value = f64> {
+    prop::num::f64::POSITIVE | prop::num::f64::NEGATIVE | prop::num::f64::NORMAL | prop::num::f64::SUBNORMAL | prop::num::f64::ZERO
+}
+
+/// One event of every variant, filled from the generated values.
+fn every_event(text: String, other: String, n: u32, big: u64, flag: bool, cost: f64) -> Vec<KernelEvent> {
+    let opt = flag.then(|| other.clone());
+    let args = serde_json::json!({ text.clone(): n, "nested": [other.clone(), cost, flag, null] });
+    vec![
+        KernelEvent::AgentStart { session_id: text.clone(), parent_session: opt.clone() },
+        KernelEvent::AgentEnd { message_count: n, total_input_tokens: big, total_output_tokens: big / 2, total_cost_usd: cost },
+        KernelEvent::TurnStart { turn_index: n },
+        KernelEvent::TurnEnd { turn_index: n, has_tool_calls: flag },
+        KernelEvent::TurnRetried { turn_index: n, attempt: n / 2, reason: text.clone() },
+        KernelEvent::TurnFailed { turn_index: n, reason: text.clone() },
+        KernelEvent::TurnInterrupted { turn_index: n },
+        KernelEvent::Aborted { turn_index: n },
+        KernelEvent::ContextTrimmed { turn_index: n, removed_messages: n / 3, remaining_messages: n / 2 },
+        KernelEvent::HarnessEditDiscarded { turn_index: n, hook: other.clone(), reason: text.clone() },
+        KernelEvent::HistoryCompacted {
+            turn_index: n, removed_messages: n / 2, tokens_before: big, tokens_after: big / 3, model: other.clone(), summary: text.clone(),
+        },
+        KernelEvent::ProviderFailover {
+            turn_index: n, from_provider: text.clone(), from_model: other.clone(), to_provider: other.clone(), to_model: text.clone(), error: text.clone(),
+        },
+        KernelEvent::ResponseTruncated { turn_index: n, stop_reason: text.clone(), continuations: n, continuing: flag },
+        KernelEvent::MessagePostprocessed {
+            turn_index: n, step: text.clone(), changed: flag, chars_before: n as usize, chars_after: big as usize, error: opt.clone(),
+        },
+        KernelEvent::MessageStart { role: text.clone(), model: other.clone() },
+        KernelEvent::MessageDelta { content_delta: text.clone() },
+        KernelEvent::ThinkingDelta { thinking: other.clone() },
+        KernelEvent::Reasoning { turn_index: n, content: other.clone() },
+        KernelEvent::MessageEnd { role: text.clone(), input_tokens: big, output_tokens: n as u64, stop_reason: opt.clone() },
+        KernelEvent::ToolCall { id: text.clone(), name: other.clone(), args: args.clone() },
+        KernelEvent::ToolResult { id: text.clone(), output: other.clone(), is_error: flag },
+        KernelEvent::ToolErrorStorm { consecutive_errors: n, limit: n / 2, last_error: text.clone() },
+        KernelEvent::ToolCallDeduplicated { tool_call_id: text.clone(), tool: other.clone(), repeat_of: text.clone() },
+        KernelEvent::SubagentFinished {
+            child_session: text.clone(), goal: other.clone(), turns: n, stop_reason: flag.then_some(StopReason::CostBudgetExceeded),
+        },
+        KernelEvent::ArtifactStored {
+            tool_call_id: text.clone(),
+            artifact: ArtifactRef { id: other.clone(), kind: text.clone(), size: big, preview: other.clone() },
+        },
+        KernelEvent::RequestRewritten { turn_index: n, messages_before: n as usize, messages_sent: big as usize, persisted: flag },
+        KernelEvent::ToolLocked { tool_call_id: text.clone(), tool: other.clone() },
+        KernelEvent::ToolUnlocked { tool: text.clone() },
+        KernelEvent::MemoryCaptured { tool_call_id: text.clone(), tool: other.clone(), chars: n as usize },
+        KernelEvent::ModelSwitched {
+            previous_provider: text.clone(), previous_model: other.clone(), provider: other.clone(), model: text.clone(),
+        },
+        KernelEvent::SourcesCollected {
+            turn_index: n,
+            sources: vec![
+                CitedSource { tool_call_id: text.clone(), tool_name: other.clone(), source: Source::Url { url: text.clone(), title: opt.clone() } },
+                CitedSource { tool_call_id: text.clone(), tool_name: other.clone(), source: Source::File { path: other.clone() } },
+                CitedSource {
+                    tool_call_id: other.clone(), tool_name: text.clone(), source: Source::Memory { session_id: text.clone(), memory_id: big as i64 },
+                },
+            ],
+        },
+        KernelEvent::PlanUpdated {
+            steps: vec![
+                PlanStep { title: text.clone(), status: PlanStatus::InProgress },
+                PlanStep { title: other.clone(), status: PlanStatus::Pending },
+            ],
+        },
+        KernelEvent::BudgetWarning { limit: text.clone(), used: cost, max: cost, fraction: cost },
+        KernelEvent::ToolCallsTruncated { requested: n, executed: n / 2 },
+        KernelEvent::ToolExecStart { id: text.clone(), name: other.clone() },
+        KernelEvent::ToolExecEnd { id: text.clone(), success: flag },
+        KernelEvent::TokenUsage { input_tokens: big, output_tokens: n as u64, cost_usd: cost },
+        KernelEvent::LimitExceeded { limit: other.clone(), used: big, max: n as u64 },
+        KernelEvent::CostBudgetExceeded { spent_usd: cost, budget_usd: cost / 2.0, next_request_estimate_usd: flag.then_some(cost * 3.0) },
+        KernelEvent::HarnessRejection { event: text, reason: other },
+    ]
+}
+
+/// Number of `KernelEvent` variants, i.e. of arms in `variant_index`.
+const VARIANT_COUNT: usize = 40;
+
+/// A distinct index below `VARIANT_COUNT` for each variant. There is no
+/// wildcard arm, so a new variant fails to compile here; once it has an arm
+/// (and `VARIANT_COUNT` is bumped), the round-trip test fails until
+/// `every_event` builds it.
+fn variant_index(event: &KernelEvent) -> usize {
+    match event {
+        KernelEvent::AgentStart { .. } => 0,
+        KernelEvent::AgentEnd { .. } => 1,
+        KernelEvent::TurnStart { .. } => 2,
+        KernelEvent::TurnEnd { .. } => 3,
+        KernelEvent::TurnRetried { .. } => 4,
+        KernelEvent::TurnFailed { .. } => 5,
+        KernelEvent::TurnInterrupted { .. } => 6,
+        KernelEvent::Aborted { .. } => 7,
+        KernelEvent::ContextTrimmed { .. } => 8,
+        KernelEvent::HarnessEditDiscarded { .. } => 9,
+        KernelEvent::ProviderFailover { .. } => 10,
+        KernelEvent::HistoryCompacted { .. } => 11,
+        KernelEvent::ResponseTruncated { .. } => 12,
+        KernelEvent::MessagePostprocessed { .. } => 13,
+        KernelEvent::MessageStart { .. } => 14,
+        KernelEvent::MessageDelta { .. } => 15,
+        KernelEvent::ThinkingDelta { .. } => 16,
+        KernelEvent::Reasoning { .. } => 17,
+        KernelEvent::MessageEnd { .. } => 18,
+        KernelEvent::ToolCall { .. } => 19,
+        KernelEvent::ToolResult { .. } => 20,
+        KernelEvent::ToolErrorStorm { .. } => 21,
+        KernelEvent::ToolCallDeduplicated { .. } => 22,
+        KernelEvent::SubagentFinished { .. } => 23,
+        KernelEvent::ArtifactStored { .. } => 24,
+        KernelEvent::RequestRewritten { .. } => 25,
+        KernelEvent::ToolLocked { .. } => 26,
+        KernelEvent::ToolUnlocked { .. } => 27,
+        KernelEvent::MemoryCaptured { .. } => 28,
+        KernelEvent::ModelSwitched { .. } => 29,
+        KernelEvent::SourcesCollected { .. } => 30,
+        KernelEvent::PlanUpdated { .. } => 31,
+        KernelEvent::BudgetWarning { .. } => 32,
+        KernelEvent::ToolCallsTruncated { .. } => 33,
+        KernelEvent::ToolExecStart { .. } => 34,
+        KernelEvent::ToolExecEnd { .. } => 35,
+        KernelEvent::TokenUsage { .. } => 36,
+        KernelEvent::CostBudgetExceeded { .. } => 37,
+        KernelEvent::LimitExceeded { .. } => 38,
+        KernelEvent::HarnessRejection { .. } => 39,
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_kernel_events_round_trip(
+        text in ".*",
+        other in ".*",
+        n in any::<u32>(),
+        big in any::<u64>(),
+        flag in any::<bool>(),
+        cost in finite_f64(),
+    ) {
+        let events = every_event(text, other, n, big, flag, cost);
+        let mut indices: Vec<usize> = events.iter().map(variant_index).collect();
+        indices.sort_unstable();
+        prop_assert_eq!(indices, (0..VARIANT_COUNT).collect::<Vec<_>>(), "every_event must build each variant exactly once");
+        for (id, event) in events.into_iter().enumerate() {
+            // As stored by the persistence task
+            let row = EventRow {
+                id: id as i64,
+                session_id: "s".to_string(),
+                event_type: event.event_type().to_string(),
+                payload: serde_json::to_value(&event).unwrap().to_string(),
+                created_at: String::new(),
+            };
+            let restored = KernelEvent::from_row(&row);
+            prop_assert!(restored.is_ok(), "{}: {:?}", row.payload, restored.err());
+            prop_assert_eq!(restored.unwrap(), event);
+        }
+    }
 }